@@ -6,3 +6,11 @@ void R_init_helloextendr_extendr(void *dll);
 void R_init_helloextendr(void *dll) {
     R_init_helloextendr_extendr(dll);
 }
+
+// Stop the threads and handlers of the Rust library before R unloads it.
+
+void helloextendr_teardown(void);
+
+void R_unload_helloextendr(void *dll) {
+    helloextendr_teardown();
+}
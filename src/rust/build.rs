@@ -0,0 +1,449 @@
+//! Records what the build found out about R in `probe-manifest.json`.
+//!
+//! extendr's build script probes for R and generates or picks the
+//! bindings, and passes the R home and version on to this crate as
+//! `DEP_R_*` metadata, which is otherwise only visible in cargo's output.
+//! This script writes them, with the target, profile, features, link mode
+//! and the settings bindgen reads, to `probe-manifest.json` in `OUT_DIR`,
+//! where `cargo xtask doctor` finds the one of the last build. With
+//! `LIBRSYS_BINDINGS_DIR` set, it also writes it there as
+//! `probe-manifest-<target>.json`, for tools that cache bindings by target.
+//!
+//! extendr's build script runs first and builds against `R_HOME`, or the
+//! `R` on the `PATH`, so `LIBRSYS_R_VERSION` cannot change which R that is.
+//! When it is set, this script fails the build if that R is not the version
+//! requested, rather than letting it go on against another one;
+//! `cargo xtask with-r` sets `R_HOME` to a matching installation.
+//!
+//! For the same reason, test and other executables always link the shared
+//! `libR` unless built through `cargo xtask with-r --link`, but the static
+//! library `R CMD INSTALL` links only gets what `native-libs.txt` lists.
+//! `LIBRSYS_LINK` selects what goes there, with the modes of `with-r`:
+//! `static` adds `libR.a` and the libraries it needs, failing when R was
+//! built without it, `host` leaves R's symbols to the R process loading the
+//! package, adding only the flag macOS needs to allow that, and `webr`
+//! checks that the target is webR's.
+//!
+//! It also links the BLAS and LAPACK selected by the `link-rblas` or
+//! `link-external-blas` feature. R loads one BLAS into the process, which
+//! packages calling BLAS through `$(BLAS_LIBS)` share; Rust code linking
+//! another one gets two sets of the same symbols, resolved differently on
+//! each platform, and results that depend on which one a call ends up in.
+//! So by default nothing is linked and R's symbols are used, `link-rblas`
+//! links R's reference shims `Rblas` and `Rlapack` explicitly, for code
+//! that needs them at link time, and `link-external-blas` links a BLAS
+//! found by `helloextendr-sysdeps`, for an R linked against that same one.
+//! The choice is passed on as `cargo:blas` and `cargo:blas_link_flags`
+//! metadata, recorded in the manifest and written to `native-libs.txt` for
+//! `Makevars`.
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use helloextendr_sysdeps::{r_static_dependencies, write_link_flags, Found, Library, Source};
+
+/// Increases when fields are renamed or removed.
+const SCHEMA: u32 = 1;
+
+/// The environment variables that change what the probe finds.
+const VARIABLES: &[&str] = &[
+    "R_HOME",
+    "LIBRSYS_R_VERSION",
+    "LIBRSYS_LINK",
+    "LIBRSYS_BINDINGS_DIR",
+    "LIBCLANG_PATH",
+    "BINDGEN_EXTRA_CLANG_ARGS",
+    "BLAS_PKG_CONFIG",
+    "BLAS_LIB_NAMES",
+];
+
+/// How R is linked, from `LIBRSYS_LINK`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LinkMode {
+    /// The shared `libR`, which extendr's build script links.
+    Dylib,
+    /// `libR.a`.
+    Static,
+    /// Not at all: the symbols are resolved from the hosting R process.
+    Host,
+    /// Built for webR, whose main module provides the symbols.
+    Webr,
+}
+
+impl LinkMode {
+    fn name(self) -> &'static str {
+        match self {
+            LinkMode::Dylib => "dylib",
+            LinkMode::Static => "static",
+            LinkMode::Host => "host",
+            LinkMode::Webr => "webr",
+        }
+    }
+}
+
+/// The target webR packages are built for.
+const WEBR_TARGET: &str = "wasm32-unknown-emscripten";
+
+/// The BLAS and LAPACK linked, from the features.
+enum Blas {
+    /// None: R's symbols are resolved when the package is loaded.
+    FromR,
+    Rblas(Found),
+    External(Found),
+}
+
+impl Blas {
+    fn name(&self) -> &'static str {
+        match self {
+            Blas::FromR => "r",
+            Blas::Rblas(_) => "rblas",
+            Blas::External(_) => "external",
+        }
+    }
+
+    fn found(&self) -> Option<&Found> {
+        match self {
+            Blas::FromR => None,
+            Blas::Rblas(found) | Blas::External(found) => Some(found),
+        }
+    }
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    for name in VARIABLES {
+        println!("cargo:rerun-if-env-changed={name}");
+    }
+
+    check_version();
+    let link_mode = link_mode();
+    let r_libraries = r_libraries(link_mode);
+    let blas = blas();
+    if let Some(found) = blas.found() {
+        found.emit();
+    }
+    let link_flags = blas.found().map(Found::link_flags).unwrap_or_default();
+    println!("cargo:blas={}", blas.name());
+    println!("cargo:blas_link_flags={link_flags}");
+    let written = write_link_flags(
+        r_libraries
+            .iter()
+            .chain(blas.found())
+            .cloned()
+            .collect::<Vec<_>>()
+            .as_slice(),
+    )
+    .and_then(|path| match r_link_args(link_mode) {
+        [] => Ok(()),
+        args => writeln!(
+            fs::OpenOptions::new().append(true).open(path)?,
+            "{}",
+            args.join(" ")
+        ),
+    });
+    if let Err(e) = written {
+        println!("cargo:warning=cannot write native-libs.txt: {e}");
+    }
+
+    let manifest = manifest(link_mode, &blas, &link_flags);
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").expect("OUT_DIR is set by cargo"));
+    write(&out_dir.join("probe-manifest.json"), &manifest);
+    if let Some(dir) = var("LIBRSYS_BINDINGS_DIR") {
+        let target = env::var("TARGET").unwrap_or_default();
+        write(
+            &Path::new(&dir).join(format!("probe-manifest-{target}.json")),
+            &manifest,
+        );
+    }
+}
+
+/// A manifest that cannot be written must not fail the build.
+fn write(path: &Path, manifest: &str) {
+    if let Err(e) = fs::write(path, manifest) {
+        println!(
+            "cargo:warning=cannot write the probe manifest to {}: {e}",
+            path.display()
+        );
+    }
+}
+
+fn var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// Without metadata, as when extendr's build script is overridden without
+/// it, the R_HOME the build runs with.
+fn r_home() -> Option<String> {
+    var("DEP_R_R_HOME").or_else(|| var("R_HOME"))
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("error: {message}");
+    std::process::exit(1)
+}
+
+/// The version of the R built against, from extendr's metadata.
+fn r_version() -> Option<String> {
+    ["MAJOR", "MINOR", "PATCH"]
+        .iter()
+        .map(|part| var(&format!("DEP_R_R_VERSION_{part}")))
+        .collect::<Option<Vec<_>>>()
+        .map(|parts| parts.join("."))
+}
+
+/// Fail unless the R built against is the `LIBRSYS_R_VERSION` requested,
+/// such as `4.3` or `4.3.2`, compared component by component.
+fn check_version() {
+    let requested = match var("LIBRSYS_R_VERSION") {
+        Some(requested) => requested,
+        None => return,
+    };
+    let version = match r_version() {
+        Some(version) => version,
+        None => {
+            println!("cargo:warning=LIBRSYS_R_VERSION is set, but the version of R is unknown");
+            return;
+        }
+    };
+    let requested_parts: Vec<&str> = requested.trim().split('.').collect();
+    let parts: Vec<&str> = version.split('.').collect();
+    let matches = requested_parts.len() <= parts.len()
+        && requested_parts.iter().zip(&parts).all(|(r, v)| r == v);
+    if !matches {
+        fail(&format!(
+            "LIBRSYS_R_VERSION={requested}, but the R in {} is {version}: build \
+             through `cargo xtask with-r`, which sets R_HOME to the newest \
+             matching installation, or set R_HOME to one",
+            r_home().as_deref().unwrap_or("R_HOME")
+        ));
+    }
+}
+
+fn link_mode() -> LinkMode {
+    match var("LIBRSYS_LINK").as_deref().map(str::trim) {
+        None | Some("") | Some("dylib") => LinkMode::Dylib,
+        Some("static") => LinkMode::Static,
+        Some("host") => LinkMode::Host,
+        Some("webr") => LinkMode::Webr,
+        Some(other) => fail(&format!(
+            "unknown LIBRSYS_LINK `{other}`: use `dylib`, `static`, `host` or `webr`"
+        )),
+    }
+}
+
+/// What `native-libs.txt` lists for R in `mode`, failing when the mode is
+/// not available for the R or the target.
+fn r_libraries(mode: LinkMode) -> Vec<Found> {
+    let target = env::var("TARGET").unwrap_or_default();
+    match mode {
+        LinkMode::Dylib => Vec::new(),
+        LinkMode::Static => {
+            let home = r_home().unwrap_or_else(|| fail("LIBRSYS_LINK=static needs R_HOME"));
+            let home = Path::new(&home);
+            let lib = home.join("lib");
+            if !lib.join("libR.a").is_file() {
+                fail(&format!(
+                    "LIBRSYS_LINK=static needs {}, which R only installs when \
+                     configured with --enable-R-static-lib",
+                    lib.join("libR.a").display()
+                ));
+            }
+            let r = Found {
+                name: "R".into(),
+                source: Source::Env,
+                version: r_version(),
+                include_paths: vec![home.join("include")],
+                link_paths: vec![lib],
+                libs: vec!["R".into()],
+                statik: true,
+            };
+            vec![r, r_static_dependencies(home)]
+        }
+        LinkMode::Host => {
+            if target.contains("windows") {
+                fail(
+                    "LIBRSYS_LINK=host is not available on Windows, where DLLs must \
+                     link against R.dll",
+                );
+            }
+            Vec::new()
+        }
+        LinkMode::Webr => {
+            if target != WEBR_TARGET {
+                fail(&format!(
+                    "LIBRSYS_LINK=webr builds for {WEBR_TARGET}, not {target}"
+                ));
+            }
+            Vec::new()
+        }
+    }
+}
+
+/// The linker flags `native-libs.txt` adds for R in `mode`, after those of
+/// the libraries.
+fn r_link_args(mode: LinkMode) -> &'static [&'static str] {
+    let target = env::var("TARGET").unwrap_or_default();
+    // Linux allows undefined symbols in shared libraries, macOS has to be
+    // told to look them up at load time. The package's shared library is
+    // linked by `R CMD INSTALL`, so the flag goes to `PKG_LIBS` rather than
+    // to cargo, which only links the static library.
+    if mode == LinkMode::Host && target.contains("apple") {
+        &["-Wl,-undefined,dynamic_lookup"]
+    } else {
+        &[]
+    }
+}
+
+fn blas() -> Blas {
+    let rblas = env::var_os("CARGO_FEATURE_LINK_RBLAS").is_some();
+    let external = env::var_os("CARGO_FEATURE_LINK_EXTERNAL_BLAS").is_some();
+    match (rblas, external) {
+        (false, false) => Blas::FromR,
+        (true, true) => {
+            fail("the `link-rblas` and `link-external-blas` features exclude each other")
+        }
+        (true, false) => Blas::Rblas(rblas_library()),
+        (false, true) => {
+            let module = var("BLAS_PKG_CONFIG").unwrap_or_else(|| "blas".into());
+            let mut library = Library::new("blas")
+                .pkg_config(&module)
+                .vcpkg("openblas")
+                .system_package("deb", "libopenblas-dev")
+                .system_package("rpm", "openblas-devel")
+                .system_package("brew", "openblas");
+            // Only used when found through BLAS_LIB_DIR or conda.
+            for lib in var("BLAS_LIB_NAMES").unwrap_or_default().split_whitespace() {
+                library = library.lib(lib);
+            }
+            Blas::External(library.probe_or_exit())
+        }
+    }
+}
+
+/// R's `Rblas` and `Rlapack`, which it only has when built with its own
+/// BLAS as a shared library, the default.
+fn rblas_library() -> Found {
+    let home = r_home().unwrap_or_else(|| fail("`link-rblas` needs R_HOME"));
+    let target = env::var("TARGET").unwrap_or_default();
+    let dir = if target.contains("windows") {
+        Path::new(&home).join("bin").join("x64")
+    } else {
+        Path::new(&home).join("lib")
+    };
+    let has = |lib: &str| {
+        ["so", "dylib", "dll"].iter().any(|ext| {
+            dir.join(format!("lib{lib}.{ext}")).is_file()
+                || dir.join(format!("{lib}.{ext}")).is_file()
+        })
+    };
+    if !has("Rblas") {
+        fail(&format!(
+            "{} has no Rblas: R was built with an external BLAS, so use \
+             `link-external-blas` with that BLAS instead of `link-rblas`",
+            dir.display()
+        ));
+    }
+    Found {
+        name: "Rblas".into(),
+        source: Source::Env,
+        version: None,
+        include_paths: vec![Path::new(&home).join("include")],
+        link_paths: vec![dir],
+        libs: vec!["Rlapack".into(), "Rblas".into()],
+        statik: false,
+    }
+}
+
+fn manifest(link_mode: LinkMode, blas: &Blas, link_flags: &str) -> String {
+    let home = r_home();
+    let version = r_version();
+    let dir = |name: &str| {
+        let dir = Path::new(home.as_deref()?).join(name);
+        dir.is_dir().then(|| dir.display().to_string())
+    };
+
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(name, _)| {
+            let feature = name.strip_prefix("CARGO_FEATURE_")?;
+            Some(feature.to_ascii_lowercase().replace('_', "-"))
+        })
+        .collect();
+    features.sort();
+    let clang_args: Vec<String> = var("BINDGEN_EXTRA_CLANG_ARGS")
+        .map(|args| args.split_whitespace().map(str::to_string).collect())
+        .unwrap_or_default();
+    let environment: Vec<(&str, String)> = VARIABLES
+        .iter()
+        .map(|name| (*name, json_option(var(name).as_deref())))
+        .collect();
+
+    let r = object(&[
+        ("home", json_option(home.as_deref())),
+        ("version", json_option(version.as_deref())),
+        ("include_dir", json_option(dir("include").as_deref())),
+        ("library_dir", json_option(dir("lib").as_deref())),
+    ]);
+    let bindgen = object(&[
+        (
+            "libclang_path",
+            json_option(var("LIBCLANG_PATH").as_deref()),
+        ),
+        ("extra_clang_args", array(&clang_args)),
+    ]);
+    let cargo = |name: &str| string(&env::var(name).unwrap_or_default());
+    object(&[
+        ("schema", SCHEMA.to_string()),
+        ("package", cargo("CARGO_PKG_NAME")),
+        ("version", cargo("CARGO_PKG_VERSION")),
+        ("target", cargo("TARGET")),
+        ("host", cargo("HOST")),
+        ("profile", cargo("PROFILE")),
+        ("features", array(&features)),
+        ("r", r),
+        ("link_mode", string(link_mode.name())),
+        (
+            "blas",
+            object(&[
+                ("linked", string(blas.name())),
+                ("link_flags", string(link_flags)),
+            ]),
+        ),
+        ("bindgen", bindgen),
+        ("environment", object(&environment)),
+    ]) + "\n"
+}
+
+fn string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_option(s: Option<&str>) -> String {
+    s.map_or_else(|| "null".into(), string)
+}
+
+fn array(values: &[String]) -> String {
+    let values: Vec<String> = values.iter().map(|v| string(v)).collect();
+    format!("[{}]", values.join(", "))
+}
+
+/// `fields`, whose values are already JSON, as an object.
+fn object(fields: &[(&str, String)]) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|(name, value)| format!("{}: {value}", string(name)))
+        .collect();
+    format!("{{{}}}", fields.join(", "))
+}
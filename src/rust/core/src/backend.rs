@@ -0,0 +1,149 @@
+//! The operations on R values that need an R engine.
+//!
+//! Code that builds R objects through a [`Backend`] rather than the R API
+//! runs on any implementation of it: the main crate implements it with
+//! libR, and [`Mock`] without R, for tests here and under Miri. Other
+//! runtimes, such as webR or an R session in another process, only need
+//! another implementation.
+
+use std::cell::{Ref, RefCell};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::na::NA_INTEGER;
+
+/// The contents of a vector to allocate, with `None` for `NA`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VectorData<V> {
+    Logical(Vec<Option<bool>>),
+    /// [`NA_INTEGER`] for `NA`, as in R.
+    Integer(Vec<i32>),
+    /// `NA_real_` for `NA`, as in R.
+    Double(Vec<f64>),
+    Character(Vec<Option<String>>),
+    Raw(Vec<u8>),
+    List(Vec<V>),
+}
+
+/// An R engine.
+pub trait Backend {
+    /// A reference to an R value, which keeps it alive.
+    type Value: Clone;
+    type Error: fmt::Display;
+
+    /// A new vector with `data`.
+    fn alloc_vector(&self, data: VectorData<Self::Value>) -> Result<Self::Value, Self::Error>;
+
+    /// Set the attribute `name` of `x` in place.
+    fn set_attr(&self, x: &Self::Value, name: &str, value: Self::Value) -> Result<(), Self::Error>;
+
+    /// Parse `code` and evaluate it in the global environment.
+    fn eval(&self, code: &str) -> Result<Self::Value, Self::Error>;
+
+    /// The error the engine reports with `message`.
+    fn error(&self, message: String) -> Self::Error;
+}
+
+/// A data frame of `columns`, each of `nrow` elements, with their names.
+pub fn data_frame<B: Backend>(
+    backend: &B,
+    columns: Vec<(&str, B::Value)>,
+    nrow: usize,
+) -> Result<B::Value, B::Error> {
+    let nrow = i32::try_from(nrow)
+        .map_err(|_| backend.error(format!("{nrow} rows are too many for a data frame")))?;
+    let (names, columns): (Vec<_>, Vec<_>) = columns.into_iter().unzip();
+    let names = VectorData::Character(names.into_iter().map(|n| Some(n.into())).collect());
+    let df = backend.alloc_vector(VectorData::List(columns))?;
+    backend.set_attr(&df, "names", backend.alloc_vector(names)?)?;
+    // The compact form of `1:nrow`.
+    let row_names = VectorData::Integer(vec![NA_INTEGER, -nrow]);
+    backend.set_attr(&df, "row.names", backend.alloc_vector(row_names)?)?;
+    let class = VectorData::Character(vec![Some("data.frame".into())]);
+    backend.set_attr(&df, "class", backend.alloc_vector(class)?)?;
+    Ok(df)
+}
+
+/// A value of [`Mock`]: a vector and its attributes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockValue(Rc<RefCell<MockObject>>);
+
+#[derive(Debug, Clone, PartialEq)]
+struct MockObject {
+    data: VectorData<MockValue>,
+    attrs: Vec<(String, MockValue)>,
+}
+
+impl MockValue {
+    pub fn new(data: VectorData<MockValue>) -> Self {
+        Self(Rc::new(RefCell::new(MockObject {
+            data,
+            attrs: Vec::new(),
+        })))
+    }
+
+    pub fn data(&self) -> Ref<'_, VectorData<MockValue>> {
+        Ref::map(self.0.borrow(), |object| &object.data)
+    }
+
+    pub fn attr(&self, name: &str) -> Option<MockValue> {
+        let object = self.0.borrow();
+        let (_, value) = object.attrs.iter().find(|(n, _)| n == name)?;
+        Some(value.clone())
+    }
+}
+
+/// A backend without R. Code evaluates to the values given for it with
+/// [`Mock::on_eval()`], and other code fails.
+#[derive(Debug, Default)]
+pub struct Mock {
+    results: HashMap<String, MockValue>,
+    evaluated: RefCell<Vec<String>>,
+}
+
+impl Mock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Evaluate `code` to `value`.
+    pub fn on_eval(mut self, code: &str, value: MockValue) -> Self {
+        self.results.insert(code.to_string(), value);
+        self
+    }
+
+    /// The code evaluated so far, in order.
+    pub fn evaluated(&self) -> Vec<String> {
+        self.evaluated.borrow().clone()
+    }
+}
+
+impl Backend for Mock {
+    type Value = MockValue;
+    type Error = crate::Error;
+
+    fn alloc_vector(&self, data: VectorData<MockValue>) -> crate::Result<MockValue> {
+        Ok(MockValue::new(data))
+    }
+
+    fn set_attr(&self, x: &MockValue, name: &str, value: MockValue) -> crate::Result<()> {
+        let mut object = x.0.borrow_mut();
+        object.attrs.retain(|(n, _)| n != name);
+        object.attrs.push((name.to_string(), value));
+        Ok(())
+    }
+
+    fn eval(&self, code: &str) -> crate::Result<MockValue> {
+        self.evaluated.borrow_mut().push(code.to_string());
+        self.results
+            .get(code)
+            .cloned()
+            .ok_or_else(|| self.error(format!("no result for `{code}`")))
+    }
+
+    fn error(&self, message: String) -> crate::Error {
+        crate::Error(message)
+    }
+}
@@ -0,0 +1,115 @@
+//! Fixed-size sets of bits.
+
+use std::convert::TryInto;
+
+use crate::{Error, Result};
+
+/// A fixed number of bits, all unset at first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitSet {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl BitSet {
+    pub fn new(len: usize) -> Self {
+        Self {
+            words: vec![0; len.div_ceil(64)],
+            len,
+        }
+    }
+
+    /// The number of bits.
+    pub fn len(&self) -> usize {
+        no_panic!(len, { self.len })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        no_panic!(is_empty, { self.len == 0 })
+    }
+
+    /// Whether bit `i` is set. Panics if `i` is out of bounds.
+    pub fn get(&self, i: usize) -> bool {
+        assert!(i < self.len, "bit {i} of {}", self.len);
+        self.words[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    /// Set bit `i` to `value`. Panics if `i` is out of bounds.
+    pub fn set(&mut self, i: usize, value: bool) {
+        assert!(i < self.len, "bit {i} of {}", self.len);
+        if value {
+            self.words[i / 64] |= 1 << (i % 64);
+        } else {
+            self.words[i / 64] &= !(1 << (i % 64));
+        }
+    }
+
+    /// The number of bits set.
+    pub fn count_ones(&self) -> usize {
+        no_panic!(count_ones, {
+            self.words.iter().map(|w| w.count_ones() as usize).sum()
+        })
+    }
+
+    /// The positions of the bits set, in increasing order.
+    pub fn ones(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, &word)| {
+            let mut word = word;
+            std::iter::from_fn(move || {
+                if word == 0 {
+                    return None;
+                }
+                let bit = word.trailing_zeros() as usize;
+                word &= word - 1;
+                Some(i * 64 + bit)
+            })
+        })
+    }
+
+    /// The bits, 64 to a word from the least significant bit, with the
+    /// bits past the length unset.
+    pub fn words(&self) -> &[u64] {
+        no_panic!(words, { &self.words })
+    }
+
+    /// The set of `len` bits of `words`, as [`words()`](Self::words) gives
+    /// them.
+    pub fn from_words(words: Vec<u64>, len: usize) -> Result<Self> {
+        if words.len() != len.div_ceil(64) {
+            return Err(Error(format!(
+                "{} words for a bit set of {len}",
+                words.len()
+            )));
+        }
+        if !len.is_multiple_of(64) && words[len / 64] >> (len % 64) != 0 {
+            return Err(Error("bits set past the end of the bit set".into()));
+        }
+        Ok(Self { words, len })
+    }
+
+    /// The number of bits, then the words, as little-endian `u64`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(8 * (1 + self.words.len()));
+        bytes.extend_from_slice(&(self.len as u64).to_le_bytes());
+        for word in &self.words {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// The bit set encoded by [`to_bytes()`](Self::to_bytes).
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let truncated = || Error("truncated bit set".into());
+        let mut words = bytes
+            .chunks(8)
+            .map(|word| word.try_into().map(u64::from_le_bytes));
+        let len = match words.next() {
+            Some(Ok(len)) => len as usize,
+            _ => return Err(truncated()),
+        };
+        let words = words
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|_| truncated())?;
+        Self::from_words(words, len)
+    }
+}
@@ -0,0 +1,123 @@
+//! Bloom filters over hashed values.
+//!
+//! A [`BloomFilter`] answers whether a value may have been added in a fixed
+//! number of bits, with false positives at a chosen rate but never false
+//! negatives. Values are [`Key`]s, hashed from their bytes, and whole
+//! numbers hash the same whether they come as integers or doubles.
+
+use crate::{BitSet, Error, Result};
+
+/// A set of hashed values that may report values it does not contain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BloomFilter {
+    bits: BitSet,
+    hashes: u32,
+}
+
+impl BloomFilter {
+    /// A filter sized for `capacity` values with a false positive rate of
+    /// `fp_rate` once they are added.
+    pub fn new(capacity: usize, fp_rate: f64) -> Result<Self> {
+        if !(fp_rate > 0.0 && fp_rate < 1.0) {
+            return Err(Error(
+                "the false positive rate must be between 0 and 1".into(),
+            ));
+        }
+        let n = capacity.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-n * fp_rate.ln() / (ln2 * ln2)).ceil().max(64.0);
+        let hashes = (bits / n * ln2).round().clamp(1.0, 32.0);
+        Ok(Self {
+            bits: BitSet::new(bits as usize),
+            hashes: hashes as u32,
+        })
+    }
+
+    /// The number of bits.
+    pub fn bits(&self) -> usize {
+        self.bits.len()
+    }
+
+    /// The number of hash functions.
+    pub fn hashes(&self) -> u32 {
+        self.hashes
+    }
+
+    /// The number of hash functions as a little-endian `u32`, then the bits.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.hashes.to_le_bytes().to_vec();
+        bytes.extend(self.bits.to_bytes());
+        bytes
+    }
+
+    /// The filter encoded by [`to_bytes()`](Self::to_bytes).
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let (hashes, bits) = bytes
+            .split_first_chunk::<4>()
+            .ok_or_else(|| Error("truncated Bloom filter".into()))?;
+        let hashes = u32::from_le_bytes(*hashes);
+        let bits = BitSet::from_bytes(bits)?;
+        if hashes == 0 || bits.is_empty() {
+            return Err(Error("invalid Bloom filter".into()));
+        }
+        Ok(Self { bits, hashes })
+    }
+
+    pub fn insert(&mut self, key: &Key) {
+        for i in self.positions(key) {
+            self.bits.set(i, true);
+        }
+    }
+
+    /// Whether `key` may have been inserted.
+    pub fn contains(&self, key: &Key) -> bool {
+        self.positions(key).all(|i| self.bits.get(i))
+    }
+
+    /// The bits of `key`, from two halves of one hash combined as in Kirsch
+    /// and Mitzenmacher (2006).
+    fn positions(&self, key: &Key) -> impl Iterator<Item = usize> {
+        let hash = key.hash();
+        let (h1, h2) = (hash as u64, (hash >> 64) as u64 | 1);
+        let m = self.bits.len() as u64;
+        (0..u64::from(self.hashes)).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % m) as usize)
+    }
+}
+
+/// A value added to or looked up in a Bloom filter.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Key {
+    /// A whole number, from an integer or a double.
+    Whole(i64),
+    Double(f64),
+    String(String),
+}
+
+impl Key {
+    /// A 128-bit FNV-1a hash of the value, with a tag so that a number and
+    /// a string with the same bytes differ.
+    fn hash(&self) -> u128 {
+        let (tag, bytes) = match self {
+            Key::Whole(x) => (0, x.to_le_bytes().to_vec()),
+            Key::Double(x) => (1, x.to_bits().to_le_bytes().to_vec()),
+            Key::String(s) => (2, s.as_bytes().to_vec()),
+        };
+        let mut hash: u128 = 0x6c62_272e_07bb_0142_62b8_2175_6295_c58d;
+        for &byte in std::iter::once(&tag).chain(&bytes) {
+            hash ^= u128::from(byte);
+            hash = hash.wrapping_mul(0x0000_0000_0100_0000_0000_0000_0000_013b);
+        }
+        hash
+    }
+
+    /// The key of a double, which is whole if it fits in an `i64`.
+    pub fn from_double(x: f64) -> Self {
+        // 2^63 itself does not fit.
+        if x.fract() == 0.0 && x.abs() < 9.223_372_036_854_776e18 {
+            Key::Whole(x as i64)
+        } else {
+            // `0.0 +` turns -0 into 0.
+            Key::Double(0.0 + x)
+        }
+    }
+}
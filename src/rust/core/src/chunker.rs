@@ -0,0 +1,111 @@
+//! Content-defined chunking.
+//!
+//! A [`Chunker`] splits data at positions chosen by the bytes around them,
+//! so an insertion or a change moves only the nearby boundaries and the
+//! chunks elsewhere stay the same, which lets stores of versions of a value
+//! keep each chunk once.
+
+/// The random values the rolling hash adds per byte. Chunk boundaries, and
+/// so deduplication between saves, depend on them: never change them.
+const GEAR: [u64; 256] = gear_table(0x6865_6c6c_6f65_7874);
+
+const fn gear_table(seed: u64) -> [u64; 256] {
+    // SplitMix64.
+    let mut table = [0; 256];
+    let mut state = seed;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+/// Content-defined chunking with FastCDC and normalized chunk sizes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chunker {
+    min: usize,
+    avg: usize,
+    max: usize,
+    /// Boundaries before `avg` need more zero bits than after it.
+    mask_small: u64,
+    mask_large: u64,
+}
+
+impl Default for Chunker {
+    /// Chunks of 16 KiB to 256 KiB, 64 KiB on average.
+    fn default() -> Self {
+        Self::new(16 << 10, 64 << 10, 256 << 10)
+    }
+}
+
+impl Chunker {
+    /// Chunks of `min` to `max` bytes, `avg` on average. `avg` is rounded
+    /// down to a power of two.
+    pub fn new(min: usize, avg: usize, max: usize) -> Self {
+        let avg = avg.max(4);
+        let bits = usize::BITS - 1 - avg.leading_zeros();
+        let max = max.max(avg);
+        Self {
+            min: min.min(avg),
+            avg: 1 << bits,
+            max,
+            mask_small: top_bits(bits + 1),
+            mask_large: top_bits(bits - 1),
+        }
+    }
+
+    /// The length of the chunk at the start of `data`.
+    pub fn cut(&self, data: &[u8]) -> usize {
+        no_panic!(cut, {
+            if data.len() <= self.min {
+                return data.len();
+            }
+            let max = self.max.min(data.len());
+            let normal = self.avg.min(max);
+            let mut hash: u64 = 0;
+            let mut i = self.min;
+            while i < normal {
+                hash = (hash << 1).wrapping_add(GEAR[data[i] as usize]);
+                if hash & self.mask_small == 0 {
+                    return i + 1;
+                }
+                i += 1;
+            }
+            while i < max {
+                hash = (hash << 1).wrapping_add(GEAR[data[i] as usize]);
+                if hash & self.mask_large == 0 {
+                    return i + 1;
+                }
+                i += 1;
+            }
+            max
+        })
+    }
+
+    /// Split `data` into chunks.
+    pub fn chunks<'a>(&self, data: &'a [u8]) -> Vec<&'a [u8]> {
+        let mut chunks = Vec::new();
+        let mut rest = data;
+        while !rest.is_empty() {
+            let (chunk, tail) = rest.split_at(self.cut(rest));
+            chunks.push(chunk);
+            rest = tail;
+        }
+        chunks
+    }
+}
+
+/// A mask of the `n` most significant bits, which depend on the most
+/// recent bytes hashed.
+fn top_bits(n: u32) -> u64 {
+    match n {
+        0 => 0,
+        n if n >= 64 => u64::MAX,
+        n => !(u64::MAX >> n),
+    }
+}
@@ -0,0 +1,57 @@
+//! The header of `fast_save()` files.
+//!
+//! A file is [`MAGIC`], the length of the serialization and the number of
+//! blocks as little-endian `u64` and `u32`, then the compressed and
+//! uncompressed length of each block as `u32` pairs, then the compressed
+//! blocks.
+
+use std::convert::TryInto;
+
+use crate::{Error, Result};
+
+/// The first bytes of a `fast_save()` file.
+pub const MAGIC: &[u8; 8] = b"HXFST001";
+
+fn corrupt() -> Error {
+    Error("not a fast_save() file, or a truncated one".into())
+}
+
+/// Compressed blocks, each with its uncompressed length.
+pub type Blocks<'a> = Vec<(&'a [u8], usize)>;
+
+/// The length of the serialization in `data`, the contents of a
+/// `fast_save()` file, and its compressed blocks with their uncompressed
+/// lengths. Nothing is allocated for a header that the file is too short
+/// for.
+pub fn parse_blocks(data: &[u8]) -> Result<(usize, Blocks<'_>)> {
+    if data.get(..8) != Some(&MAGIC[..]) {
+        return Err(corrupt());
+    }
+    let u32_at = |at: usize| -> Option<usize> {
+        let bytes = data.get(at..at + 4)?;
+        Some(u32::from_le_bytes(bytes.try_into().ok()?) as usize)
+    };
+    let len = data
+        .get(8..16)
+        .and_then(|bytes| bytes.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or_else(corrupt)? as usize;
+    let count = u32_at(16).ok_or_else(corrupt)?;
+    let mut offset = count
+        .checked_mul(8)
+        .and_then(|size| size.checked_add(20))
+        .filter(|&offset| offset <= data.len())
+        .ok_or_else(corrupt)?;
+    let mut blocks = Vec::with_capacity(count);
+    for i in 0..count {
+        let packed = u32_at(20 + 8 * i).ok_or_else(corrupt)?;
+        let raw = u32_at(24 + 8 * i).ok_or_else(corrupt)?;
+        let block = data.get(offset..offset + packed).ok_or_else(corrupt)?;
+        blocks.push((block, raw));
+        offset += packed;
+    }
+    if blocks.iter().map(|&(_, raw)| raw).sum::<usize>() != len {
+        return Err(corrupt());
+    }
+    Ok((len, blocks))
+}
@@ -0,0 +1,318 @@
+//! Binning of numbers, as R's `findInterval()` and `cut()`.
+//!
+//! Histogram and discretization code must put values on a boundary in
+//! the same bin as R does, and label the bins the same way, or results
+//! computed in Rust and R disagree at the edges. [`find_interval()`]
+//! follows `findInterval()` including its `rightmost.closed`,
+//! `all.inside` and `left.open` options, and [`cut()`] follows
+//! `cut.default()`: breaks given as a number of intervals are spread over
+//! the range of the values and widened by a thousandth of it, bins are
+//! `(a,b]` or `[a,b)`, and labels use as few significant digits as keep
+//! them distinct. Missing values are in no interval.
+
+use crate::{Error, Result};
+
+/// The options of `findInterval()`, all `FALSE` by default.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FindInterval {
+    /// Close the last interval on the right, or the first on the left with
+    /// `left_open`.
+    pub rightmost_closed: bool,
+    /// Count values below the first break as in the first interval and
+    /// values above the last as in the last.
+    pub all_inside: bool,
+    /// Use intervals open on the left and closed on the right.
+    pub left_open: bool,
+}
+
+/// For each of `x`, the number of the interval of `breaks` it is in: `0`
+/// below the first break, `i` from `breaks[i - 1]` up to `breaks[i]` and
+/// `breaks.len()` above the last, or `None` for `NA` and `NaN`. `breaks`
+/// must be sorted and free of missing values.
+pub fn find_interval(
+    x: &[f64],
+    breaks: &[f64],
+    options: FindInterval,
+) -> Result<Vec<Option<usize>>> {
+    if breaks.iter().any(|b| b.is_nan()) || breaks.windows(2).any(|w| w[0] > w[1]) {
+        return Err(Error(
+            "'vec' must be sorted non-decreasingly and not contain NAs".into(),
+        ));
+    }
+    let n = breaks.len();
+    Ok(x.iter()
+        .map(|&x| {
+            if x.is_nan() {
+                return None;
+            }
+            let mut i = if options.left_open {
+                breaks.partition_point(|&b| b < x)
+            } else {
+                breaks.partition_point(|&b| b <= x)
+            };
+            if options.rightmost_closed && n > 0 {
+                if !options.left_open && x == breaks[n - 1] {
+                    i = n - 1;
+                } else if options.left_open && x == breaks[0] {
+                    i = 1;
+                }
+            }
+            // As in R, a single break puts the values above it in interval
+            // 0 rather than 1.
+            if options.all_inside && n > 0 {
+                if i == 0 {
+                    i = 1;
+                } else if i == n {
+                    i = n - 1;
+                }
+            }
+            Some(i)
+        })
+        .collect())
+}
+
+/// The breaks of [`cut()`].
+#[derive(Debug, Clone, Copy)]
+pub enum Breaks<'a> {
+    /// A number of equally wide intervals covering the values.
+    Count(usize),
+    /// The boundaries of the intervals, in any order.
+    At(&'a [f64]),
+}
+
+/// The options of `cut()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cut {
+    /// Close the intervals on the right, `(a,b]`, rather than on the left.
+    pub right: bool,
+    /// Also close the lowest interval on the left, or the highest on the
+    /// right if `right` is false.
+    pub include_lowest: bool,
+    /// The fewest significant digits in labels.
+    pub dig_lab: usize,
+}
+
+impl Default for Cut {
+    fn default() -> Self {
+        Cut {
+            right: true,
+            include_lowest: false,
+            dig_lab: 3,
+        }
+    }
+}
+
+/// Values divided into intervals.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binned {
+    /// The zero-based interval of each value, or `None` for values outside
+    /// all of them and missing values.
+    pub codes: Vec<Option<usize>>,
+    /// The sorted breaks.
+    pub breaks: Vec<f64>,
+    /// The label of each interval.
+    pub labels: Vec<String>,
+}
+
+impl Binned {
+    /// Replace the labels, of which there must be one per interval.
+    pub fn with_labels(mut self, labels: Vec<String>) -> Result<Self> {
+        if labels.len() != self.labels.len() {
+            return Err(Error(
+                "number of intervals and length of 'labels' differ".into(),
+            ));
+        }
+        self.labels = labels;
+        Ok(self)
+    }
+}
+
+/// Divide `x` into intervals as `cut(x, breaks)` does.
+pub fn cut(x: &[f64], breaks: Breaks<'_>, options: &Cut) -> Result<Binned> {
+    let breaks = match breaks {
+        Breaks::Count(count) => count_breaks(x, count)?,
+        Breaks::At(at) => {
+            // sort.int() drops missing breaks.
+            let mut breaks: Vec<f64> = at.iter().copied().filter(|b| !b.is_nan()).collect();
+            breaks.sort_by(f64::total_cmp);
+            if breaks.windows(2).any(|w| w[0] == w[1]) {
+                return Err(Error("'breaks' are not unique".into()));
+            }
+            breaks
+        }
+    };
+    Ok(Binned {
+        codes: bin_codes(x, &breaks, options.right, options.include_lowest),
+        labels: labels(&breaks, options),
+        breaks,
+    })
+}
+
+/// `count` intervals over the range of `x`, widened by a thousandth of it so
+/// that the extremes are inside.
+fn count_breaks(x: &[f64], count: usize) -> Result<Vec<f64>> {
+    if count < 2 {
+        return Err(Error("invalid number of intervals".into()));
+    }
+    let (min, max) = x
+        .iter()
+        .filter(|x| !x.is_nan())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &x| {
+            (min.min(x), max.max(x))
+        });
+    if !min.is_finite() || !max.is_finite() {
+        return Err(Error("'from' must be a finite number".into()));
+    }
+    let nb = count + 1;
+    let dx = max - min;
+    if dx == 0.0 {
+        let dx = if min != 0.0 { min.abs() } else { 1.0 };
+        Ok(seq(min - dx / 1000.0, max + dx / 1000.0, nb))
+    } else {
+        let mut breaks = seq(min, max, nb);
+        breaks[0] = min - dx / 1000.0;
+        breaks[nb - 1] = max + dx / 1000.0;
+        Ok(breaks)
+    }
+}
+
+/// `seq.int(from, to, length.out = n)`, which fills the halves from either
+/// end so that the result is symmetric.
+fn seq(from: f64, to: f64, n: usize) -> Vec<f64> {
+    let by = (to - from) / (n - 1) as f64;
+    (0..n)
+        .map(|i| match i {
+            0 => from,
+            _ if i == n - 1 => to,
+            _ if i < n / 2 => from + i as f64 * by,
+            _ => to - (n - 1 - i) as f64 * by,
+        })
+        .collect()
+}
+
+/// `.bincode()`: the zero-based interval of each value.
+fn bin_codes(x: &[f64], breaks: &[f64], right: bool, include_lowest: bool) -> Vec<Option<usize>> {
+    let n = breaks.len();
+    x.iter()
+        .map(|&x| {
+            if x.is_nan() || n < 2 {
+                return None;
+            }
+            if include_lowest {
+                if right && x == breaks[0] {
+                    return Some(0);
+                }
+                if !right && x == breaks[n - 1] {
+                    return Some(n - 2);
+                }
+            }
+            let above = if right {
+                breaks.partition_point(|&b| b < x)
+            } else {
+                breaks.partition_point(|&b| b <= x)
+            };
+            (1..n).contains(&above).then(|| above - 1)
+        })
+        .collect()
+}
+
+/// The default labels, `(a,b]` or `[a,b)`, with the breaks formatted like
+/// `formatC(breaks, digits = dig)` for the smallest `dig` from `dig_lab` to
+/// 12 that keeps adjacent breaks distinct.
+fn labels(breaks: &[f64], options: &Cut) -> Vec<String> {
+    if breaks.len() < 2 {
+        return Vec::new();
+    }
+    let mut formatted = Vec::new();
+    for digits in options.dig_lab..=options.dig_lab.max(12) {
+        // `0 +` turns -0 into 0.
+        formatted = breaks.iter().map(|&b| format_g(0.0 + b, digits)).collect();
+        if formatted.windows(2).all(|w| w[0] != w[1]) {
+            break;
+        }
+    }
+    let (open, close) = if options.right {
+        ("(", "]")
+    } else {
+        ("[", ")")
+    };
+    let mut labels: Vec<String> = formatted
+        .windows(2)
+        .map(|w| format!("{open}{},{}{close}", w[0], w[1]))
+        .collect();
+    if options.include_lowest {
+        if options.right {
+            labels[0].replace_range(..1, "[");
+        } else {
+            let last = labels.len() - 1;
+            let end = labels[last].len();
+            labels[last].replace_range(end - 1.., "]");
+        }
+    }
+    labels
+}
+
+/// C's `%.*g`, as `formatC()` uses it.
+pub(crate) fn format_g(x: f64, digits: usize) -> String {
+    if x.is_nan() {
+        return "NaN".into();
+    }
+    if x.is_infinite() {
+        return if x > 0.0 { "Inf" } else { "-Inf" }.into();
+    }
+    let precision = digits.max(1);
+    // The exponent after rounding to `precision` significant digits.
+    let scientific = format!("{:.*e}", precision - 1, x);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    if exponent < -4 || exponent >= precision as i32 {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!(
+            "{}e{sign}{:02}",
+            trim_zeros(mantissa),
+            exponent.unsigned_abs()
+        )
+    } else {
+        let decimals = (precision as i32 - 1 - exponent) as usize;
+        trim_zeros(&format!("{x:.decimals$}")).to_string()
+    }
+}
+
+/// `formatC(x, format = "fg", digits = digits)`: fixed notation with
+/// `digits` significant digits, and all digits before the point.
+pub(crate) fn format_fg(x: f64, digits: usize) -> String {
+    if x == 0.0 {
+        return "0".into();
+    }
+    if !x.is_finite() {
+        return format_g(x, digits);
+    }
+    let digits = digits.max(1);
+    let abs = x.abs();
+    // As in R's str_signif(), which corrects log10() for rounding.
+    let mut exponent = (abs.log10() + 1e-12).floor() as i32;
+    let scale = 10f64.powi(digits as i32 - 1);
+    let mantissa = ((abs / 10f64.powi(exponent) + 1e-12) * scale).round() / scale;
+    if exponent > 0 && mantissa >= 10.0 {
+        exponent += 1;
+    }
+    if exponent == -4 && abs < 1e-4 {
+        exponent = -5;
+    }
+    if exponent < -4 {
+        let decimals = (digits as i32 - 1 - exponent) as usize;
+        trim_zeros(&format!("{x:.decimals$}")).to_string()
+    } else if exponent >= digits as i32 {
+        format_g(x, exponent as usize + 1)
+    } else {
+        format_g(x, digits)
+    }
+}
+
+fn trim_zeros(s: &str) -> &str {
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        s
+    }
+}
@@ -0,0 +1,61 @@
+//! The parts of helloextendr that do not call R.
+//!
+//! NA handling, recycling rules, data structures such as [`BitSet`],
+//! [`BloomFilter`](bloom::BloomFilter) and [`Chunker`], the header of
+//! [`fastio`] files, and statistics such as
+//! [`quantile`](quantile::quantile), run-length encoding, binning and
+//! [`Moments`](moments::Moments) are plain Rust, working on slices that R
+//! vectors lend without copying. Keeping them out of the main crate, which
+//! links R, lets their tests run on machines without R and under Miri,
+//! which cannot call into C:
+//!
+//! ```sh
+//! cargo +nightly miri test -p helloextendr-core
+//! ```
+//!
+//! The fuzz targets in `fuzz/` depend on this crate alone, so they build
+//! without R too.
+//!
+//! The main crate converts R objects to and from the types here and turns
+//! [`Error`] into R errors. Code that builds R objects without depending
+//! on libR does so through a [`Backend`](backend::Backend), which
+//! [`remote`] also implements for an R session in another process.
+//!
+//! The scalar and slice helpers called once per element are audited not to
+//! panic, which the `no-panic` feature checks in release builds; see
+//! `no_panic.rs`.
+
+use std::fmt;
+
+#[macro_use]
+mod no_panic;
+
+pub mod backend;
+pub mod bitset;
+pub mod bloom;
+pub mod chunker;
+pub mod fastio;
+pub mod interval;
+pub mod moments;
+pub mod na;
+pub mod quantile;
+pub mod recycle;
+pub mod remote;
+pub mod rle;
+
+pub use bitset::BitSet;
+pub use chunker::Chunker;
+
+/// An invalid input, with a message for the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error(pub String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
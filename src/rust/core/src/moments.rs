@@ -0,0 +1,151 @@
+//! Single-pass summary statistics with bounded memory.
+//!
+//! A [`Moments`] accumulator keeps the total weight, mean and second and
+//! third central moments of the values pushed so far, updated with the
+//! numerically stable formulas of Welford and Pébay instead of sums of
+//! powers, which lose all precision when the mean is large compared to the
+//! spread. Accumulators of separate chunks or threads can be merged.
+//!
+//! Weights count as frequencies: pushing `x` with weight 2 is the same as
+//! pushing it twice, so the variance is that of `rep(x, w)`.
+
+use crate::na::na_real;
+
+/// The running moments of a stream of values.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Moments {
+    na_rm: bool,
+    missing: bool,
+    count: usize,
+    weight: f64,
+    mean: f64,
+    m2: f64,
+    m3: f64,
+}
+
+impl Moments {
+    /// An empty accumulator. Unless `na_rm` is true, pushing a missing
+    /// value makes every statistic `NA`, as in R.
+    pub fn new(na_rm: bool) -> Self {
+        Moments {
+            na_rm,
+            ..Default::default()
+        }
+    }
+
+    pub fn push(&mut self, x: f64) {
+        self.push_weighted(x, 1.0);
+    }
+
+    /// Push `x` with a non-negative weight. A missing weight counts as a
+    /// missing value.
+    pub fn push_weighted(&mut self, x: f64, weight: f64) {
+        if x.is_nan() || weight.is_nan() {
+            self.missing |= !self.na_rm;
+            return;
+        }
+        if weight == 0.0 {
+            return;
+        }
+        self.merge(&Moments {
+            na_rm: self.na_rm,
+            missing: false,
+            count: 1,
+            weight,
+            mean: x,
+            m2: 0.0,
+            m3: 0.0,
+        });
+    }
+
+    /// Add the values pushed to `other`.
+    pub fn merge(&mut self, other: &Moments) {
+        self.missing |= other.missing;
+        if other.weight == 0.0 {
+            return;
+        }
+        if self.weight == 0.0 {
+            let (na_rm, missing) = (self.na_rm, self.missing);
+            *self = *other;
+            self.na_rm = na_rm;
+            self.missing = missing;
+            return;
+        }
+        let (wa, wb) = (self.weight, other.weight);
+        let w = wa + wb;
+        if !self.mean.is_finite() || !other.mean.is_finite() {
+            // The update below turns `Inf` into `NaN` through `Inf - Inf`,
+            // where R's mean stays infinite. The variance is `NaN` anyway.
+            self.mean = (wa * self.mean + wb * other.mean) / w;
+            self.weight = w;
+            self.count += other.count;
+            return;
+        }
+        let delta = other.mean - self.mean;
+        let delta_w = delta / w;
+        self.m3 += other.m3
+            + delta * delta_w * delta_w * wa * wb * (wa - wb)
+            + 3.0 * delta_w * (wa * other.m2 - wb * self.m2);
+        self.m2 += other.m2 + delta * delta_w * wa * wb;
+        self.mean += delta_w * wb;
+        self.weight = w;
+        self.count += other.count;
+    }
+
+    /// The number of values pushed, not counting missing ones or those of
+    /// zero weight.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// The sum of the weights.
+    pub fn weight(&self) -> f64 {
+        self.weight
+    }
+
+    fn or_na(&self, value: f64) -> f64 {
+        if self.missing {
+            na_real()
+        } else {
+            value
+        }
+    }
+
+    /// The (weighted) mean, `NaN` without values.
+    pub fn mean(&self) -> f64 {
+        self.or_na(if self.weight > 0.0 {
+            self.mean
+        } else {
+            f64::NAN
+        })
+    }
+
+    /// The sample variance, as `var()`: `NA` for a single value, and `NaN`
+    /// if any value is infinite, whose deviation from the mean is `NaN`.
+    pub fn variance(&self) -> f64 {
+        self.or_na(if self.weight <= 1.0 {
+            na_real()
+        } else if !self.mean.is_finite() {
+            f64::NAN
+        } else {
+            self.m2 / (self.weight - 1.0)
+        })
+    }
+
+    /// The sample standard deviation, as `sd()`.
+    pub fn sd(&self) -> f64 {
+        self.variance().sqrt()
+    }
+
+    /// The skewness `m3 / m2^(3/2)` of the population moments, as
+    /// `moments::skewness()`: `NaN` for constant and infinite values.
+    pub fn skewness(&self) -> f64 {
+        self.or_na(if self.weight > 0.0 && self.mean.is_finite() {
+            let m2 = self.m2 / self.weight;
+            let m3 = self.m3 / self.weight;
+            m3 / m2.powf(1.5)
+        } else {
+            f64::NAN
+        })
+    }
+}
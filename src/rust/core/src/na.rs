@@ -0,0 +1,34 @@
+//! R's missing values.
+//!
+//! Logical and integer vectors mark `NA` with the smallest `i32`, which
+//! therefore is not a valid integer in R. Double vectors mark it with a NaN
+//! whose low word is 1954, so that R can tell `NA_real_` from other NaNs,
+//! which arithmetic on `NA` may produce as well.
+
+/// `NA_integer_`, and `NA` in logical vectors.
+pub const NA_INTEGER: i32 = i32::MIN;
+
+/// The bits of `NA_real_`.
+pub const NA_REAL_BITS: u64 = 0x7ff0_0000_0000_07a2;
+
+/// `NA_real_`.
+pub fn na_real() -> f64 {
+    no_panic!(na_real, { f64::from_bits(NA_REAL_BITS) })
+}
+
+pub fn is_na_integer(x: i32) -> bool {
+    no_panic!(is_na_integer, { x == NA_INTEGER })
+}
+
+/// Whether `x` is `NA_real_`, as `is.na(x) && !is.nan(x)` in R. Like R, only
+/// the low word of the NaN is compared, which survives arithmetic.
+pub fn is_na_real(x: f64) -> bool {
+    no_panic!(is_na_real, {
+        x.is_nan() && x.to_bits() as u32 == NA_REAL_BITS as u32
+    })
+}
+
+/// Whether `x` is `NA_real_` or another NaN, as `is.na(x)` in R.
+pub fn is_missing_real(x: f64) -> bool {
+    no_panic!(is_missing_real, { x.is_nan() })
+}
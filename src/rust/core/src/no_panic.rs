@@ -0,0 +1,61 @@
+//! Functions that cannot panic, checked by the linker.
+//!
+//! A panic unwinding out of Rust code that R called without a guard, such
+//! as a finalizer, an input handler or a callback from C, aborts the R
+//! session, and the guard `extendr` puts around `.Call()` costs too much for
+//! the scalar and slice helpers called once per element. The functions
+//! whose body is wrapped in [`no_panic!`] are audited not to panic: no
+//! indexing the optimizer cannot prove in bounds, no division by a value
+//! that may be zero, no allocation, no `unwrap()`.
+//!
+//! With the `no-panic` feature, release builds check it: the body runs with
+//! a guard whose destructor, only reached when the body unwinds, calls a
+//! function that does not exist. If the optimizer removes every path that
+//! panics, it removes the call too; otherwise linking fails with an error
+//! naming the function. Debug builds keep the unwinding paths, so the check
+//! is off there.
+//!
+//! ```sh
+//! cargo test --release --features no-panic -p helloextendr-core
+//! ```
+
+/// Runs `$body`, a function body that must not panic, as the body of
+/// `$name`.
+macro_rules! no_panic {
+    ($name:ident, $body:block) => {{
+        struct Guard;
+
+        impl Drop for Guard {
+            fn drop(&mut self) {
+                #[cfg(all(feature = "no-panic", not(debug_assertions)))]
+                {
+                    extern "C" {
+                        #[link_name = no_panic_symbol!($name)]
+                        fn can_panic() -> !;
+                    }
+                    unsafe { can_panic() }
+                }
+            }
+        }
+
+        let guard = Guard;
+        #[allow(clippy::redundant_closure_call)]
+        let result = (|| $body)();
+        std::mem::forget(guard);
+        result
+    }};
+}
+
+/// The name of the missing function, which the linker prints.
+#[cfg(all(feature = "no-panic", not(debug_assertions)))]
+macro_rules! no_panic_symbol {
+    ($name:ident) => {
+        concat!(
+            "\n\nerror: `",
+            module_path!(),
+            "::",
+            stringify!($name),
+            "` can panic, but is marked with `no_panic!`\n\n"
+        )
+    };
+}
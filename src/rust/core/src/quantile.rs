@@ -0,0 +1,130 @@
+//! Sample quantiles, as R's `quantile()`.
+//!
+//! [`quantile()`] implements the nine types of Hyndman and Fan (1996) with
+//! the same floating-point operations in the same order as
+//! `quantile.default()`, including its tolerances for rounding errors, so
+//! that results agree with R's to the last bit rather than approximately.
+//! Type 7 is R's default.
+
+use crate::interval::format_fg;
+use crate::na::na_real;
+use crate::{Error, Result};
+
+/// The tolerance `quantile()` allows for positions that are whole numbers
+/// but for rounding errors.
+const FUZZ: f64 = 4.0 * f64::EPSILON;
+
+/// The quantiles of type `kind` (1 to 9) of `x` at `probs`.
+///
+/// Missing values in `x` are an error unless `na_rm` is true, in which case
+/// they are dropped. Missing probabilities and an empty `x` give `NA`.
+pub fn quantile(x: &[f64], probs: &[f64], kind: u8, na_rm: bool) -> Result<Vec<f64>> {
+    if !(1..=9).contains(&kind) {
+        return Err(Error(format!(
+            "`type` must be a whole number from 1 to 9, not {kind}"
+        )));
+    }
+    if !na_rm && x.iter().any(|v| v.is_nan()) {
+        return Err(Error(
+            "missing values and NaN's not allowed if 'na.rm' is FALSE".into(),
+        ));
+    }
+    let eps = 100.0 * f64::EPSILON;
+    if probs
+        .iter()
+        .any(|&p| !p.is_nan() && (p < -eps || p > 1.0 + eps))
+    {
+        return Err(Error("'probs' outside [0,1]".into()));
+    }
+    let mut sorted: Vec<f64> = x.iter().copied().filter(|v| !v.is_nan()).collect();
+    sorted.sort_by(f64::total_cmp);
+    Ok(probs
+        .iter()
+        .map(|&p| {
+            if p.is_nan() || sorted.is_empty() {
+                na_real()
+            } else {
+                let p = p.clamp(0.0, 1.0);
+                if kind == 7 {
+                    type7(&sorted, p)
+                } else {
+                    hyndman_fan(&sorted, p, kind)
+                }
+            }
+        })
+        .collect())
+}
+
+/// Type 7, which `quantile()` computes separately for backward
+/// compatibility.
+fn type7(x: &[f64], p: f64) -> f64 {
+    let index = 1.0 + (x.len() - 1) as f64 * p;
+    let lo = index.floor();
+    let hi = index.ceil();
+    let qs = x[lo as usize - 1];
+    let x_hi = x[hi as usize - 1];
+    if index > lo && x_hi != qs {
+        let h = index - lo;
+        (1.0 - h) * qs + h * x_hi
+    } else {
+        qs
+    }
+}
+
+/// Types 1 to 6, 8 and 9: the sample quantile at position `j + h`, where
+/// the discontinuous types 1 to 3 choose `h` from 0, 1/2 and 1.
+fn hyndman_fan(x: &[f64], p: f64, kind: u8) -> f64 {
+    let n = x.len() as f64;
+    let (j, h) = if kind <= 3 {
+        let nppm = if kind == 3 { n * p - 0.5 } else { n * p };
+        let j = (nppm + FUZZ).floor();
+        let h = match kind {
+            1 => f64::from(u8::from(nppm > j)),
+            2 => (f64::from(u8::from(nppm > j)) + 1.0) / 2.0,
+            _ => f64::from(u8::from(nppm != j || j.rem_euclid(2.0) == 1.0)),
+        };
+        (j, h)
+    } else {
+        let (a, b) = match kind {
+            4 => (0.0, 1.0),
+            5 => (0.5, 0.5),
+            6 => (0.0, 0.0),
+            8 => (1.0 / 3.0, 1.0 / 3.0),
+            _ => (3.0 / 8.0, 3.0 / 8.0),
+        };
+        let nppm = a + p * (n + 1.0 - a - b);
+        let j = (nppm + FUZZ).floor();
+        let h = nppm - j;
+        (j, if h.abs() < FUZZ { 0.0 } else { h })
+    };
+    // R pads the sorted values to `c(x[1], x[1], x, x[n], x[n])` and reads
+    // positions `j + 2` and `j + 3` of that.
+    let at = |position: f64| {
+        let i = position as isize - 3;
+        x[i.clamp(0, x.len() as isize - 1) as usize]
+    };
+    let lo = at(j + 2.0);
+    let hi = at(j + 3.0);
+    if h == 1.0 {
+        hi
+    } else if 0.0 < h && h < 1.0 && lo != hi {
+        (1.0 - h) * lo + h * hi
+    } else {
+        lo
+    }
+}
+
+/// The names `quantile()` gives its results, such as `"25%"`, as it does
+/// for fewer than 100 probabilities; missing probabilities are unnamed.
+pub fn quantile_names(probs: &[f64]) -> Vec<String> {
+    probs
+        .iter()
+        .map(|&p| {
+            if p.is_nan() {
+                String::new()
+            } else {
+                format!("{}%", format_fg(100.0 * p, 7))
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,40 @@
+//! The lengths of results computed element-wise from several vectors.
+//!
+//! Base R recycles shorter vectors to the length of the longest one, and
+//! gives an empty result if any vector is empty. The tidyverse only
+//! recycles vectors of length one, since other mismatches are usually
+//! mistakes: [`common_length()`] follows base R and [`strict_length()`] the
+//! tidyverse.
+
+use crate::{Error, Result};
+
+/// The length of an element-wise result over vectors of `lengths`, as base
+/// R computes it: 0 if any is empty, and otherwise the longest. `None` for
+/// no vectors.
+pub fn common_length(lengths: &[usize]) -> Option<usize> {
+    no_panic!(common_length, {
+        let longest = *lengths.iter().max()?;
+        Some(if lengths.contains(&0) { 0 } else { longest })
+    })
+}
+
+/// [`common_length()`], failing if a vector is neither of length one nor of
+/// that length, unless one is empty. Zero vectors give one element, as a
+/// template of only literal text gives one string.
+pub fn strict_length(lengths: &[usize]) -> Result<usize> {
+    let len = match common_length(lengths) {
+        Some(0) => return Ok(0),
+        Some(len) => len,
+        None => return Ok(1),
+    };
+    match lengths.iter().find(|&&n| n != 1 && n != len) {
+        Some(bad) => Err(Error(format!("lengths {bad} and {len} cannot be recycled"))),
+        None => Ok(len),
+    }
+}
+
+/// The element of a vector of `len` elements used at position `i` of a
+/// recycled result. Panics if `len` is 0.
+pub fn recycled_index(i: usize, len: usize) -> usize {
+    i % len
+}
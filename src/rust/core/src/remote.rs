@@ -0,0 +1,687 @@
+//! A [`Backend`] driving an R session in another process.
+//!
+//! [`RemoteR`] sends each operation to an R server over TCP and gets the
+//! result back, both as `serialize()` output, so code written against
+//! [`Backend`] runs unchanged against R in a sandboxed process, on another
+//! machine, or from a program that does not link R at all. Values stay in
+//! the server, and a [`RemoteValue`] refers to one until its last clone is
+//! dropped; [`RemoteR::fetch()`] copies one back.
+//!
+//! [`RemoteR::spawn()`] starts a server with `Rscript` on this machine. On
+//! another machine, listen with [`RemoteR::accept()`] and run
+//! [`SERVER`] there, followed by `serve("<this host>", <port>, "<token>")`.
+//! The server sends the token when it connects, and connections without it
+//! are refused, so that other processes cannot take the session over.
+//!
+//! Each operation is a round trip, so batch work into code evaluated with
+//! [`Backend::eval()`] rather than building large objects element by
+//! element.
+
+use std::cell::RefCell;
+use std::convert::{TryFrom, TryInto};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Child, Command, Stdio};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::backend::{Backend, VectorData};
+use crate::na::NA_INTEGER;
+use crate::{Error, Result};
+
+/// The R code of the server: `serve(host, port, token)` connects to a
+/// client listening at `host` and `port`, sends it `token` and runs its
+/// requests until it closes the connection.
+pub const SERVER: &str = r#"
+serve <- function(host, port, token) {
+  con <- socketConnection(host, port, blocking = TRUE, open = "r+b", timeout = 1e7)
+  on.exit(close(con))
+  token <- charToRaw(token)
+  writeBin(length(token), con, endian = "big")
+  writeBin(token, con)
+  objects <- new.env()
+  next_id <- 0
+  read_exactly <- function(n) {
+    out <- raw()
+    while (length(out) < n) {
+      chunk <- readBin(con, "raw", n - length(out))
+      if (!length(chunk)) stop("the connection was closed")
+      out <- c(out, chunk)
+    }
+    out
+  }
+  store <- function(x) {
+    next_id <<- next_id + 1
+    assign(as.character(next_id), x, envir = objects)
+    next_id
+  }
+  get_object <- function(id) get(as.character(id), envir = objects)
+  repeat {
+    n <- readBin(con, "integer", 1, endian = "big")
+    if (!length(n)) break
+    msg <- unserialize(read_exactly(n))
+    reply <- tryCatch(
+      list("ok", switch(msg[[1]],
+        alloc = store(msg[[2]]),
+        list = store(lapply(msg[[2]], get_object)),
+        set_attr = {
+          x <- get_object(msg[[2]])
+          attr(x, msg[[3]]) <- get_object(msg[[4]])
+          assign(as.character(msg[[2]]), x, envir = objects)
+          NULL
+        },
+        eval = store(eval(parse(text = msg[[2]]), globalenv())),
+        fetch = get_object(msg[[2]]),
+        release = {
+          rm(list = as.character(msg[[2]]), envir = objects)
+          NULL
+        },
+        stop("unknown request `", msg[[1]], "`")
+      )),
+      error = function(e) list("error", conditionMessage(e))
+    )
+    bytes <- serialize(reply, NULL, xdr = TRUE, version = 2)
+    writeBin(length(bytes), con, endian = "big")
+    writeBin(bytes, con)
+  }
+}
+"#;
+
+/// How long [`RemoteR::spawn()`] waits for the server to connect.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a connection has to send its token.
+const TOKEN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 128 random bits, in hex. The keys of each `RandomState` come from the
+/// operating system's random numbers, so other processes cannot guess them.
+fn random_token() -> String {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+    (0..2)
+        .map(|i| {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u32(std::process::id());
+            hasher.write_u128(i);
+            format!("{:016x}", hasher.finish())
+        })
+        .collect()
+}
+
+/// Whether `stream` sends `token` first, as the server does.
+fn presents_token(mut stream: &TcpStream, token: &str) -> bool {
+    let mut read = || -> io::Result<bool> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(TOKEN_TIMEOUT))?;
+        let mut len = [0; 4];
+        stream.read_exact(&mut len)?;
+        if u32::from_be_bytes(len) as usize != token.len() {
+            return Ok(false);
+        }
+        let mut sent = vec![0; token.len()];
+        stream.read_exact(&mut sent)?;
+        stream.set_read_timeout(None)?;
+        Ok(sent == token.as_bytes())
+    };
+    read().unwrap_or(false)
+}
+
+fn io_error(e: io::Error) -> Error {
+    Error(format!("remote R: {e}"))
+}
+
+struct Session {
+    stream: RefCell<TcpStream>,
+    /// Ids of values dropped since the last request, released with the
+    /// next one.
+    released: RefCell<Vec<f64>>,
+    child: RefCell<Option<Child>>,
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let _ = self.stream.get_mut().shutdown(std::net::Shutdown::Both);
+        if let Some(mut child) = self.child.get_mut().take() {
+            let _ = child.wait();
+        }
+    }
+}
+
+struct Handle {
+    id: f64,
+    session: Rc<Session>,
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        self.session.released.borrow_mut().push(self.id);
+    }
+}
+
+/// A value in the server.
+#[derive(Clone)]
+pub struct RemoteValue(Rc<Handle>);
+
+impl std::fmt::Debug for RemoteValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("RemoteValue").field(&self.0.id).finish()
+    }
+}
+
+/// A copy of an R value, as [`RemoteR::fetch()`] returns it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RObject {
+    /// `None` for `NULL`.
+    pub data: Option<VectorData<RObject>>,
+    pub attrs: Vec<(String, RObject)>,
+}
+
+impl RObject {
+    /// A vector without attributes.
+    pub fn new(data: VectorData<RObject>) -> Self {
+        Self {
+            data: Some(data),
+            attrs: Vec::new(),
+        }
+    }
+}
+
+/// A connection to an R server.
+#[derive(Clone)]
+pub struct RemoteR {
+    session: Rc<Session>,
+}
+
+impl RemoteR {
+    /// Start a server with `rscript`, the path of `Rscript`, and connect to
+    /// it. The server exits with the last clone of the connection.
+    pub fn spawn(rscript: &str) -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").map_err(io_error)?;
+        let port = listener.local_addr().map_err(io_error)?.port();
+        let token = random_token();
+        let mut child = Command::new(rscript)
+            .arg("-e")
+            .arg(format!(
+                "{SERVER}\nserve(\"127.0.0.1\", {port}, \"{token}\")"
+            ))
+            .stdin(Stdio::null())
+            .spawn()
+            .map_err(|e| Error(format!("cannot start {rscript}: {e}")))?;
+        listener.set_nonblocking(true).map_err(io_error)?;
+        let start = Instant::now();
+        let stream = loop {
+            match listener.accept() {
+                Ok((stream, _)) if presents_token(&stream, &token) => break stream,
+                // Another process connected first; wait for the server.
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(io_error(e)),
+            }
+            if let Some(status) = child.try_wait().map_err(io_error)? {
+                return Err(Error(format!("the R server exited with {status}")));
+            }
+            if start.elapsed() > CONNECT_TIMEOUT {
+                let _ = child.kill();
+                return Err(Error("the R server did not connect".into()));
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        };
+        let remote = Self::from_stream(stream)?;
+        *remote.session.child.borrow_mut() = Some(child);
+        Ok(remote)
+    }
+
+    /// Wait for a server started elsewhere to connect to `listener` with
+    /// `token`, refusing connections without it.
+    pub fn accept(listener: &TcpListener, token: &str) -> Result<Self> {
+        loop {
+            let (stream, _) = listener.accept().map_err(io_error)?;
+            if presents_token(&stream, token) {
+                return Self::from_stream(stream);
+            }
+        }
+    }
+
+    fn from_stream(stream: TcpStream) -> Result<Self> {
+        stream.set_nonblocking(false).map_err(io_error)?;
+        stream.set_nodelay(true).map_err(io_error)?;
+        Ok(Self {
+            session: Rc::new(Session {
+                stream: RefCell::new(stream),
+                released: RefCell::new(Vec::new()),
+                child: RefCell::new(None),
+            }),
+        })
+    }
+
+    /// Send `request`, a list, and return the value of the reply.
+    fn call(&self, request: Vec<RObject>) -> Result<RObject> {
+        let released = std::mem::take(&mut *self.session.released.borrow_mut());
+        if !released.is_empty() {
+            self.round_trip(vec![
+                op("release"),
+                RObject::new(VectorData::Double(released)),
+            ])?;
+        }
+        self.round_trip(request)
+    }
+
+    fn round_trip(&self, request: Vec<RObject>) -> Result<RObject> {
+        let bytes = serialize(&RObject::new(VectorData::List(request)))?;
+        let mut stream = self.session.stream.borrow_mut();
+        let len: u32 = bytes
+            .len()
+            .try_into()
+            .map_err(|_| Error("the request is too large".into()))?;
+        stream.write_all(&len.to_be_bytes()).map_err(io_error)?;
+        stream.write_all(&bytes).map_err(io_error)?;
+        let mut len = [0; 4];
+        stream.read_exact(&mut len).map_err(io_error)?;
+        let mut reply = vec![0; u32::from_be_bytes(len) as usize];
+        stream.read_exact(&mut reply).map_err(io_error)?;
+        drop(stream);
+
+        let mut reply = match unserialize(&reply)?.data {
+            Some(VectorData::List(reply)) if reply.len() == 2 => reply,
+            _ => return Err(Error("remote R: malformed reply".into())),
+        };
+        let value = reply.pop().unwrap();
+        match reply[0].data {
+            Some(VectorData::Character(ref status)) if status[..] == [Some("ok".into())] => {
+                Ok(value)
+            }
+            _ => match value.data {
+                Some(VectorData::Character(message)) => Err(Error(
+                    message.into_iter().flatten().collect::<Vec<_>>().join("\n"),
+                )),
+                _ => Err(Error("remote R: malformed reply".into())),
+            },
+        }
+    }
+
+    /// A request storing a value and returning its id.
+    fn store(&self, request: Vec<RObject>) -> Result<RemoteValue> {
+        match self.call(request)?.data {
+            Some(VectorData::Double(id)) if id.len() == 1 => Ok(RemoteValue(Rc::new(Handle {
+                id: id[0],
+                session: self.session.clone(),
+            }))),
+            _ => Err(Error("remote R: malformed reply".into())),
+        }
+    }
+
+    fn check_session(&self, value: &RemoteValue) -> Result<()> {
+        if Rc::ptr_eq(&value.0.session, &self.session) {
+            Ok(())
+        } else {
+            Err(Error("the value belongs to another R server".into()))
+        }
+    }
+
+    /// A copy of `value`. Fails for values other than vectors and lists,
+    /// such as functions and environments.
+    pub fn fetch(&self, value: &RemoteValue) -> Result<RObject> {
+        self.check_session(value)?;
+        self.call(vec![op("fetch"), id(value)])
+    }
+}
+
+fn op(name: &str) -> RObject {
+    RObject::new(VectorData::Character(vec![Some(name.into())]))
+}
+
+fn id(value: &RemoteValue) -> RObject {
+    RObject::new(VectorData::Double(vec![value.0.id]))
+}
+
+impl Backend for RemoteR {
+    type Value = RemoteValue;
+    type Error = Error;
+
+    fn alloc_vector(&self, data: VectorData<RemoteValue>) -> Result<RemoteValue> {
+        let request = match data {
+            VectorData::List(values) => {
+                let ids = values
+                    .iter()
+                    .map(|value| self.check_session(value).map(|()| value.0.id))
+                    .collect::<Result<_>>()?;
+                vec![op("list"), RObject::new(VectorData::Double(ids))]
+            }
+            VectorData::Logical(x) => vec![op("alloc"), RObject::new(VectorData::Logical(x))],
+            VectorData::Integer(x) => vec![op("alloc"), RObject::new(VectorData::Integer(x))],
+            VectorData::Double(x) => vec![op("alloc"), RObject::new(VectorData::Double(x))],
+            VectorData::Character(x) => {
+                vec![op("alloc"), RObject::new(VectorData::Character(x))]
+            }
+            VectorData::Raw(x) => vec![op("alloc"), RObject::new(VectorData::Raw(x))],
+        };
+        self.store(request)
+    }
+
+    fn set_attr(&self, x: &RemoteValue, name: &str, value: RemoteValue) -> Result<()> {
+        self.check_session(x)?;
+        self.check_session(&value)?;
+        let name = RObject::new(VectorData::Character(vec![Some(name.into())]));
+        self.call(vec![op("set_attr"), id(x), name, id(&value)])?;
+        Ok(())
+    }
+
+    fn eval(&self, code: &str) -> Result<RemoteValue> {
+        let code = RObject::new(VectorData::Character(vec![Some(code.into())]));
+        self.store(vec![op("eval"), code])
+    }
+
+    fn error(&self, message: String) -> Error {
+        Error(message)
+    }
+}
+
+// Type codes and special values of R's serialization format.
+const SYMSXP: u32 = 1;
+const LISTSXP: u32 = 2;
+const LGLSXP: u32 = 10;
+const CHARSXP: u32 = 9;
+const INTSXP: u32 = 13;
+const REALSXP: u32 = 14;
+const STRSXP: u32 = 16;
+const VECSXP: u32 = 19;
+const RAWSXP: u32 = 24;
+const REFSXP: u32 = 255;
+const NILVALUE_SXP: u32 = 254;
+const IS_OBJECT: u32 = 1 << 8;
+const HAS_ATTR: u32 = 1 << 9;
+const HAS_TAG: u32 = 1 << 10;
+/// The encoding flags of a `CHARSXP`, shifted as in its header.
+const UTF8: u32 = 1 << 3 << 12;
+const LATIN1: u32 = 1 << 2 << 12;
+
+/// `x` as `serialize(x, NULL, xdr = TRUE, version = 2)` writes it. Fails
+/// for long vectors and strings, of 2^31 elements or bytes or more.
+pub fn serialize(x: &RObject) -> Result<Vec<u8>> {
+    let mut out = b"X\n".to_vec();
+    // Format 2, written by R 4.0.0, readable by R 2.3.0 and later.
+    for int in [2i32, 0x0004_0000, 0x0002_0300] {
+        out.extend_from_slice(&int.to_be_bytes());
+    }
+    write_item(&mut out, x)?;
+    Ok(out)
+}
+
+fn write_item(out: &mut Vec<u8>, x: &RObject) -> Result<()> {
+    let int = |out: &mut Vec<u8>, i: i32| out.extend_from_slice(&i.to_be_bytes());
+    let header = |out: &mut Vec<u8>, flags: u32, len: usize| {
+        let len = i32::try_from(len)
+            .map_err(|_| Error(format!("cannot serialize {len} elements or bytes")))?;
+        out.extend_from_slice(&flags.to_be_bytes());
+        out.extend_from_slice(&len.to_be_bytes());
+        Ok::<_, Error>(())
+    };
+    let data = match &x.data {
+        Some(data) => data,
+        None => {
+            out.extend_from_slice(&NILVALUE_SXP.to_be_bytes());
+            return Ok(());
+        }
+    };
+    let mut flags = 0;
+    if !x.attrs.is_empty() {
+        flags |= HAS_ATTR;
+    }
+    // R does not recompute the bit from the attributes it reads.
+    if x.attrs.iter().any(|(name, _)| name == "class") {
+        flags |= IS_OBJECT;
+    }
+    match data {
+        VectorData::Logical(x) => {
+            header(out, flags | LGLSXP, x.len())?;
+            for &v in x {
+                int(out, v.map_or(NA_INTEGER, i32::from));
+            }
+        }
+        VectorData::Integer(x) => {
+            header(out, flags | INTSXP, x.len())?;
+            for &v in x {
+                int(out, v);
+            }
+        }
+        VectorData::Double(x) => {
+            header(out, flags | REALSXP, x.len())?;
+            for &v in x {
+                out.extend_from_slice(&v.to_bits().to_be_bytes());
+            }
+        }
+        VectorData::Character(x) => {
+            header(out, flags | STRSXP, x.len())?;
+            for v in x {
+                match v {
+                    Some(s) => {
+                        header(out, CHARSXP | UTF8, s.len())?;
+                        out.extend_from_slice(s.as_bytes());
+                    }
+                    None => {
+                        out.extend_from_slice(&CHARSXP.to_be_bytes());
+                        int(out, -1);
+                    }
+                }
+            }
+        }
+        VectorData::Raw(x) => {
+            header(out, flags | RAWSXP, x.len())?;
+            out.extend_from_slice(x);
+        }
+        VectorData::List(x) => {
+            header(out, flags | VECSXP, x.len())?;
+            for item in x {
+                write_item(out, item)?;
+            }
+        }
+    }
+    // The attributes follow the data, as a pairlist tagged with symbols.
+    for (name, value) in &x.attrs {
+        out.extend_from_slice(&(LISTSXP | HAS_TAG).to_be_bytes());
+        out.extend_from_slice(&SYMSXP.to_be_bytes());
+        header(out, CHARSXP | UTF8, name.len())?;
+        out.extend_from_slice(name.as_bytes());
+        write_item(out, value)?;
+    }
+    if !x.attrs.is_empty() {
+        out.extend_from_slice(&NILVALUE_SXP.to_be_bytes());
+    }
+    Ok(())
+}
+
+/// The value serialized in `bytes` by `serialize(x, NULL, xdr = TRUE,
+/// version = 2)`, if it is made of vectors, lists and attributes only.
+pub fn unserialize(bytes: &[u8]) -> Result<RObject> {
+    if bytes.get(..2) != Some(b"X\n") {
+        return Err(Error("not an XDR serialization".into()));
+    }
+    let mut reader = Reader {
+        bytes,
+        pos: 2,
+        refs: Vec::new(),
+    };
+    if reader.int()? != 2 {
+        return Err(Error("only version 2 serializations are supported".into()));
+    }
+    reader.int()?;
+    reader.int()?;
+    reader.item(0)
+}
+
+/// How deeply lists and attributes may nest in [`unserialize()`]'s input,
+/// far beyond what R code builds, so that a malicious peer cannot exhaust
+/// the stack.
+const MAX_DEPTH: usize = 512;
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    /// Symbols read so far, referred to by later `REFSXP`s.
+    refs: Vec<String>,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8]> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|&end| end <= self.bytes.len());
+        let end = end.ok_or_else(|| Error("truncated serialization".into()))?;
+        let bytes = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn int(&mut self) -> Result<i32> {
+        Ok(i32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn len(&mut self) -> Result<usize> {
+        let len = self.int()?;
+        // Long vectors are marked by -1; they do not fit in a reply anyway.
+        usize::try_from(len).map_err(|_| Error("unsupported vector length".into()))
+    }
+
+    /// A `CHARSXP` after its flags, `None` for `NA`.
+    fn chars(&mut self, flags: u32) -> Result<Option<String>> {
+        let len = self.int()?;
+        if len == -1 {
+            return Ok(None);
+        }
+        let len = usize::try_from(len).map_err(|_| Error("invalid string length".into()))?;
+        let bytes = self.take(len)?;
+        Ok(Some(if flags & LATIN1 != 0 {
+            bytes.iter().map(|&b| char::from(b)).collect()
+        } else {
+            String::from_utf8_lossy(bytes).into_owned()
+        }))
+    }
+
+    /// A symbol, as the tag of a pairlist element.
+    fn symbol(&mut self) -> Result<String> {
+        let flags = self.int()? as u32;
+        match flags & 0xff {
+            SYMSXP => {
+                let flags = self.int()? as u32;
+                let name = self.chars(flags)?.unwrap_or_default();
+                self.refs.push(name.clone());
+                Ok(name)
+            }
+            REFSXP => {
+                let index = match flags >> 8 {
+                    0 => self.int()? as usize,
+                    index => index as usize,
+                };
+                index
+                    .checked_sub(1)
+                    .and_then(|i| self.refs.get(i))
+                    .cloned()
+                    .ok_or_else(|| Error("invalid reference".into()))
+            }
+            ty => Err(Error(format!("unsupported tag of type {ty}"))),
+        }
+    }
+
+    /// Attributes, as a pairlist after the flags of its first element, of
+    /// an object nested `depth` levels deep.
+    fn attributes(&mut self, depth: usize) -> Result<Vec<(String, RObject)>> {
+        let depth = nested(depth)?;
+        let mut attrs = Vec::new();
+        let mut flags = self.int()? as u32;
+        while flags & 0xff == LISTSXP {
+            if flags & HAS_ATTR != 0 {
+                self.attributes(depth)?;
+            }
+            let name = if flags & HAS_TAG != 0 {
+                self.symbol()?
+            } else {
+                String::new()
+            };
+            attrs.push((name, self.item(depth)?));
+            flags = self.int()? as u32;
+        }
+        if flags != NILVALUE_SXP {
+            return Err(Error("malformed attributes".into()));
+        }
+        Ok(attrs)
+    }
+
+    /// An object nested `depth` levels deep in lists and attributes.
+    fn item(&mut self, depth: usize) -> Result<RObject> {
+        let flags = self.int()? as u32;
+        let data = match flags & 0xff {
+            NILVALUE_SXP => {
+                return Ok(RObject {
+                    data: None,
+                    attrs: Vec::new(),
+                })
+            }
+            LGLSXP => {
+                let len = self.len()?;
+                let values = (0..len)
+                    .map(|_| self.int().map(|v| (v != NA_INTEGER).then_some(v != 0)))
+                    .collect::<Result<_>>()?;
+                VectorData::Logical(values)
+            }
+            INTSXP => {
+                let len = self.len()?;
+                VectorData::Integer((0..len).map(|_| self.int()).collect::<Result<_>>()?)
+            }
+            REALSXP => {
+                let len = self.len()?;
+                let bytes = self.take(len.saturating_mul(8))?;
+                VectorData::Double(
+                    bytes
+                        .chunks(8)
+                        .map(|b| f64::from_bits(u64::from_be_bytes(b.try_into().unwrap())))
+                        .collect(),
+                )
+            }
+            STRSXP => {
+                let len = self.len()?;
+                let mut values = Vec::with_capacity(len.min(self.bytes.len()));
+                for _ in 0..len {
+                    let flags = self.int()? as u32;
+                    if flags & 0xff != CHARSXP {
+                        return Err(Error("malformed character vector".into()));
+                    }
+                    values.push(self.chars(flags)?);
+                }
+                VectorData::Character(values)
+            }
+            RAWSXP => {
+                let len = self.len()?;
+                VectorData::Raw(self.take(len)?.to_vec())
+            }
+            VECSXP => {
+                let len = self.len()?;
+                let mut values = Vec::with_capacity(len.min(self.bytes.len()));
+                let depth = nested(depth)?;
+                for _ in 0..len {
+                    values.push(self.item(depth)?);
+                }
+                VectorData::List(values)
+            }
+            ty => return Err(Error(format!("cannot copy R objects of type {ty}"))),
+        };
+        let attrs = if flags & HAS_ATTR != 0 {
+            self.attributes(depth)?
+        } else {
+            Vec::new()
+        };
+        Ok(RObject {
+            data: Some(data),
+            attrs,
+        })
+    }
+}
+
+/// The depth of the contents of an object nested `depth` levels deep.
+fn nested(depth: usize) -> Result<usize> {
+    if depth < MAX_DEPTH {
+        Ok(depth + 1)
+    } else {
+        Err(Error("the serialization is nested too deeply".into()))
+    }
+}
@@ -0,0 +1,115 @@
+//! Run-length encoding, as R's `rle()` and `inverse.rle()`.
+//!
+//! A run is a maximal sequence of equal consecutive values. As in R, a
+//! missing value never equals anything, so every `NA` (and every `NaN`) is
+//! a run of its own, and each run is represented by its last value, which
+//! matters for `0` and `-0`. [`run_starts()`] and [`run_ids()`] give the
+//! same boundaries for grouping consecutive values, like
+//! `data.table::rleid()`.
+
+use crate::na::is_na_integer;
+
+/// Values whose runs are found with R's `==`.
+pub trait RunValue {
+    /// Whether `next` continues a run of `self`; never for missing values.
+    fn continues(&self, next: &Self) -> bool;
+}
+
+impl RunValue for f64 {
+    fn continues(&self, next: &Self) -> bool {
+        // NA and NaN already compare unequal to everything.
+        self == next
+    }
+}
+
+impl RunValue for i32 {
+    fn continues(&self, next: &Self) -> bool {
+        self == next && !is_na_integer(*self)
+    }
+}
+
+impl RunValue for u8 {
+    fn continues(&self, next: &Self) -> bool {
+        self == next
+    }
+}
+
+impl RunValue for bool {
+    fn continues(&self, next: &Self) -> bool {
+        self == next
+    }
+}
+
+/// Strings, with `None` for `NA`.
+impl RunValue for Option<&str> {
+    fn continues(&self, next: &Self) -> bool {
+        self.is_some() && self == next
+    }
+}
+
+/// The zero-based positions at which runs start.
+pub fn run_starts<T: RunValue>(values: &[T]) -> Vec<usize> {
+    (0..values.len())
+        .filter(|&i| i == 0 || !values[i - 1].continues(&values[i]))
+        .collect()
+}
+
+/// The zero-based positions at which runs end, which R takes their values
+/// from.
+pub fn run_ends<T: RunValue>(values: &[T]) -> Vec<usize> {
+    (0..values.len())
+        .filter(|&i| i + 1 == values.len() || !values[i].continues(&values[i + 1]))
+        .collect()
+}
+
+/// The zero-based index of the run of every value.
+pub fn run_ids<T: RunValue>(values: &[T]) -> Vec<usize> {
+    let mut id = 0;
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            if i > 0 && !values[i - 1].continues(value) {
+                id += 1;
+            }
+            id
+        })
+        .collect()
+}
+
+/// The lengths and values of the runs of a vector.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rle<T> {
+    pub lengths: Vec<usize>,
+    pub values: Vec<T>,
+}
+
+/// The runs of `values`.
+pub fn rle<T: RunValue + Clone>(values: &[T]) -> Rle<T> {
+    let ends = run_ends(values);
+    Rle {
+        lengths: run_lengths(&ends),
+        values: ends.iter().map(|&i| values[i].clone()).collect(),
+    }
+}
+
+/// The vector `rle` encodes.
+pub fn inverse_rle<T: Clone>(rle: &Rle<T>) -> Vec<T> {
+    rle.lengths
+        .iter()
+        .zip(&rle.values)
+        .flat_map(|(&n, value)| std::iter::repeat_n(value.clone(), n))
+        .collect()
+}
+
+/// The lengths of the runs ending at `ends`.
+pub fn run_lengths(ends: &[usize]) -> Vec<usize> {
+    let mut start = 0;
+    ends.iter()
+        .map(|&end| {
+            let n = end + 1 - start;
+            start = end + 1;
+            n
+        })
+        .collect()
+}
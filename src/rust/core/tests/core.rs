@@ -0,0 +1,272 @@
+//! Tests of the R-free core. They need no R, and run under Miri with
+//! `cargo +nightly miri test -p helloextendr-core`.
+
+use helloextendr_core::backend::{data_frame, Backend, Mock, MockValue, VectorData};
+use helloextendr_core::bloom::{BloomFilter, Key};
+use helloextendr_core::fastio::{parse_blocks, MAGIC};
+use helloextendr_core::interval::{cut, find_interval, Breaks, Cut, FindInterval};
+use helloextendr_core::moments::Moments;
+use helloextendr_core::na::{is_missing_real, is_na_integer, is_na_real, na_real, NA_INTEGER};
+use helloextendr_core::quantile::{quantile, quantile_names};
+use helloextendr_core::recycle::{common_length, strict_length};
+use helloextendr_core::remote::{serialize, unserialize, RObject, RemoteR};
+use helloextendr_core::rle::{inverse_rle, rle, run_ids};
+use helloextendr_core::{BitSet, Chunker};
+
+#[test]
+fn na_real_differs_from_other_nans() {
+    assert!(is_na_real(na_real()));
+    // At run time: constant folding may drop the payload of the NaN.
+    assert!(is_na_real(std::hint::black_box(na_real()) + 1.0));
+    assert!(!is_na_real(f64::NAN));
+    assert!(!is_na_real(1954.0));
+    assert!(is_na_integer(NA_INTEGER));
+    assert!(!is_na_integer(0));
+}
+
+#[test]
+fn recycling_follows_base_r_or_the_tidyverse() {
+    assert_eq!(common_length(&[3, 1, 2]), Some(3));
+    assert_eq!(common_length(&[3, 0]), Some(0));
+    assert_eq!(common_length(&[]), None);
+    assert_eq!(strict_length(&[3, 1, 3]), Ok(3));
+    assert_eq!(strict_length(&[3, 0]), Ok(0));
+    assert_eq!(strict_length(&[]), Ok(1));
+    assert!(strict_length(&[3, 2]).is_err());
+}
+
+#[test]
+fn bit_sets_round_trip_through_bytes() {
+    let mut bits = BitSet::new(130);
+    bits.set(0, true);
+    bits.set(129, true);
+    assert_eq!(bits.ones().collect::<Vec<_>>(), [0, 129]);
+    let bytes = bits.to_bytes();
+    assert_eq!(BitSet::from_bytes(&bytes), Ok(bits));
+    assert!(BitSet::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    assert!(BitSet::from_words(vec![u64::MAX], 10).is_err());
+}
+
+#[test]
+fn chunks_cover_the_data_and_survive_edits() {
+    let mut state = 1u64;
+    let data: Vec<u8> = (0..20_000)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect();
+    let chunker = Chunker::new(256, 1024, 4096);
+    let chunks = chunker.chunks(&data);
+    assert_eq!(chunks.concat(), data);
+    assert!(chunks.iter().all(|chunk| chunk.len() <= 4096));
+
+    let mut edited = data.clone();
+    edited.insert(10_000, 0);
+    let edited_chunks = chunker.chunks(&edited);
+    let shared = edited_chunks.iter().filter(|c| chunks.contains(c)).count();
+    assert!(shared + 2 >= chunks.len());
+}
+
+#[test]
+fn data_frames_build_on_any_backend() {
+    let mock = Mock::new().on_eval("1 + 1", MockValue::new(VectorData::Double(vec![2.0])));
+    let x = mock.alloc_vector(VectorData::Integer(vec![1, 2])).unwrap();
+    let df = data_frame(&mock, vec![("x", x)], 2).unwrap();
+    let names = df.attr("names").unwrap();
+    assert_eq!(*names.data(), VectorData::Character(vec![Some("x".into())]));
+    let row_names = df.attr("row.names").unwrap();
+    assert_eq!(*row_names.data(), VectorData::Integer(vec![NA_INTEGER, -2]));
+
+    assert_eq!(
+        *mock.eval("1 + 1").unwrap().data(),
+        VectorData::Double(vec![2.0])
+    );
+    assert!(mock.eval("q()").is_err());
+    assert_eq!(mock.evaluated(), ["1 + 1", "q()"]);
+}
+
+#[test]
+fn values_round_trip_through_r_serialization() {
+    // `serialize(1L, NULL, version = 2)`, after the version of R.
+    let one = serialize(&RObject::new(VectorData::Integer(vec![1]))).unwrap();
+    assert_eq!(&one[..6], b"X\n\0\0\0\x02");
+    assert_eq!(
+        &one[10..],
+        [0, 2, 3, 0, 0, 0, 0, 13, 0, 0, 0, 1, 0, 0, 0, 1]
+    );
+
+    let mut column = RObject::new(VectorData::Character(vec![Some("é".into()), None]));
+    let names = RObject::new(VectorData::Character(vec![Some("a".into())]));
+    column.attrs.push(("names".into(), names.clone()));
+    let mut x = RObject::new(VectorData::List(vec![
+        column,
+        RObject::new(VectorData::Logical(vec![Some(true), None])),
+        RObject::new(VectorData::Raw(vec![0, 255])),
+        RObject {
+            data: None,
+            attrs: Vec::new(),
+        },
+    ]));
+    x.attrs.push(("names".into(), names));
+    x.attrs.push((
+        "class".into(),
+        RObject::new(VectorData::Character(vec![Some("data.frame".into())])),
+    ));
+    let bytes = serialize(&x).unwrap();
+    assert_eq!(unserialize(&bytes), Ok(x));
+    assert!(unserialize(&bytes[..bytes.len() - 1]).is_err());
+}
+
+#[test]
+fn connections_without_the_token_are_refused() {
+    use std::io::Write;
+    use std::net::{TcpListener, TcpStream};
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let connect = |token: &str| {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream
+            .write_all(&(token.len() as u32).to_be_bytes())
+            .unwrap();
+        stream.write_all(token.as_bytes()).unwrap();
+        stream
+    };
+    let _intruder = connect("guess");
+    let _server = connect("secret");
+    assert!(RemoteR::accept(&listener, "secret").is_ok());
+}
+
+#[test]
+fn deeply_nested_serializations_are_rejected() {
+    let nest = |depth: usize| {
+        let mut x = RObject::new(VectorData::Integer(vec![1]));
+        for _ in 0..depth {
+            x = RObject::new(VectorData::List(vec![x]));
+        }
+        serialize(&x).unwrap()
+    };
+    assert!(unserialize(&nest(100)).is_ok());
+    assert!(unserialize(&nest(1000)).is_err());
+
+    // A million lists of length one, each holding the next, with no end.
+    let mut bytes = serialize(&RObject::new(VectorData::List(Vec::new()))).unwrap();
+    bytes.truncate(bytes.len() - 8);
+    for _ in 0..1_000_000 {
+        bytes.extend_from_slice(&[0, 0, 0, 19, 0, 0, 0, 1]);
+    }
+    assert!(unserialize(&bytes).is_err());
+}
+
+#[test]
+fn bloom_filters_find_what_was_inserted() {
+    let mut filter = BloomFilter::new(100, 0.01).unwrap();
+    for i in 0..100 {
+        filter.insert(&Key::Whole(i));
+    }
+    assert!((0..100).all(|i| filter.contains(&Key::from_double(i as f64))));
+    assert!(!filter.contains(&Key::String("0".into())));
+    assert_eq!(BloomFilter::from_bytes(&filter.to_bytes()), Ok(filter));
+    assert!(BloomFilter::from_bytes(&[0; 4]).is_err());
+    assert!(BloomFilter::new(100, 1.0).is_err());
+}
+
+#[test]
+fn fast_save_headers_are_checked() {
+    let mut file = MAGIC.to_vec();
+    file.extend_from_slice(&5u64.to_le_bytes());
+    file.extend_from_slice(&1u32.to_le_bytes());
+    file.extend_from_slice(&3u32.to_le_bytes());
+    file.extend_from_slice(&5u32.to_le_bytes());
+    file.extend_from_slice(b"abc");
+    assert_eq!(parse_blocks(&file), Ok((5, vec![(&b"abc"[..], 5)])));
+    assert!(parse_blocks(&file[..file.len() - 1]).is_err());
+    // A block count the file is far too short for.
+    file[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(parse_blocks(&file).is_err());
+}
+
+#[test]
+fn quantiles_match_r() {
+    let x: Vec<f64> = (1..=10).map(f64::from).collect();
+    // `quantile(1:10, 0.1, type = t)` for types 1, 6 and 7.
+    assert_eq!(quantile(&x, &[0.1], 1, false), Ok(vec![1.0]));
+    assert_eq!(quantile(&x, &[0.1], 6, false).unwrap()[0], 1.1);
+    assert_eq!(quantile(&x, &[0.1], 7, false).unwrap()[0], 1.9);
+    assert!(is_na_real(quantile(&[], &[0.5], 7, false).unwrap()[0]));
+    assert_eq!(quantile(&[na_real(), 2.0], &[1.0], 7, true), Ok(vec![2.0]));
+    assert!(quantile(&[na_real()], &[0.5], 7, false).is_err());
+    assert!(quantile(&x, &[1.5], 7, false).is_err());
+    assert!(quantile(&x, &[0.5], 10, false).is_err());
+    assert_eq!(
+        quantile_names(&[0.1, 1.0 / 3.0, na_real()]),
+        ["10%", "33.33333%", ""]
+    );
+}
+
+#[test]
+fn runs_match_r() {
+    let runs = rle(&[1, 1, NA_INTEGER, NA_INTEGER, 2]);
+    assert_eq!(runs.lengths, [2, 1, 1, 1]);
+    assert_eq!(inverse_rle(&runs), [1, 1, NA_INTEGER, NA_INTEGER, 2]);
+    assert_eq!(run_ids(&[Some("a"), Some("a"), None, None]), [0, 0, 1, 2]);
+}
+
+#[test]
+fn bins_match_r() {
+    let options = FindInterval::default();
+    assert_eq!(
+        find_interval(&[0.0, 1.0, 1.5, 3.0, f64::NAN], &[1.0, 2.0, 3.0], options),
+        Ok(vec![Some(0), Some(1), Some(1), Some(3), None])
+    );
+    assert!(find_interval(&[1.0], &[2.0, 1.0], options).is_err());
+
+    // `cut(1:10, 3)`.
+    let x: Vec<f64> = (1..=10).map(f64::from).collect();
+    let binned = cut(&x, Breaks::Count(3), &Cut::default()).unwrap();
+    assert_eq!(binned.labels, ["(0.991,4]", "(4,7]", "(7,10]"]);
+    assert_eq!(binned.codes[..4], [Some(0), Some(0), Some(0), Some(0)]);
+    assert!(binned.with_labels(vec!["a".into()]).is_err());
+}
+
+#[test]
+fn merged_moments_equal_pushed_ones() {
+    let x = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+    let mut all = Moments::new(false);
+    x.iter().for_each(|&v| all.push(v));
+    let (mut a, mut b) = (Moments::new(false), Moments::new(false));
+    x[..3].iter().for_each(|&v| a.push(v));
+    x[3..].iter().for_each(|&v| b.push(v));
+    a.merge(&b);
+    assert_eq!(all.mean(), 5.0);
+    assert!((all.variance() - 32.0 / 7.0).abs() < 1e-12);
+    assert!((a.variance() - all.variance()).abs() < 1e-12);
+    assert_eq!(a.count(), 8);
+
+    let mut missing = Moments::new(false);
+    missing.push(na_real());
+    missing.push(1.0);
+    assert!(is_na_real(missing.mean()));
+    assert!(is_na_real(Moments::new(true).variance()));
+}
+
+/// Calls every function marked with `no_panic!`, so that
+/// `cargo test --release --features no-panic` links each of them and fails
+/// if one can panic.
+#[test]
+fn audited_functions_do_not_panic() {
+    assert!(is_missing_real(f64::NAN));
+    assert!(!is_missing_real(0.0));
+    assert_eq!(common_length(&[2, 1]), Some(2));
+    let bits = BitSet::new(65);
+    assert_eq!(bits.len(), 65);
+    assert!(!bits.is_empty());
+    assert_eq!(bits.count_ones(), 0);
+    assert_eq!(bits.words(), [0, 0]);
+    let chunker = Chunker::new(4, 8, 16);
+    assert_eq!(chunker.cut(&[1, 2, 3]), 3);
+    assert!(chunker.cut(&[7; 100]) <= 16);
+}
@@ -0,0 +1,123 @@
+//! R objects of any shape, built through the mock backend, go through the
+//! conversions written against `Backend`: building a data frame of them
+//! and copying them into the serialization format of the remote backend.
+
+#![no_main]
+
+use helloextendr_core::backend::{data_frame, Backend, Mock, MockValue, VectorData};
+use helloextendr_core::remote::{serialize, unserialize, RObject};
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Debug, Arbitrary)]
+enum Data {
+    Logical(Vec<Option<bool>>),
+    Integer(Vec<i32>),
+    Double(Vec<f64>),
+    Character(Vec<Option<String>>),
+    Raw(Vec<u8>),
+    List(Vec<Object>),
+}
+
+#[derive(Debug, Arbitrary)]
+struct Object {
+    data: Data,
+    attrs: Vec<(String, Object)>,
+}
+
+fn build(mock: &Mock, object: Object) -> MockValue {
+    let data = match object.data {
+        Data::Logical(x) => VectorData::Logical(x),
+        Data::Integer(x) => VectorData::Integer(x),
+        Data::Double(x) => VectorData::Double(x),
+        Data::Character(x) => VectorData::Character(x),
+        Data::Raw(x) => VectorData::Raw(x),
+        Data::List(x) => VectorData::List(x.into_iter().map(|x| build(mock, x)).collect()),
+    };
+    let value = mock.alloc_vector(data).unwrap();
+    for (name, attr) in object.attrs {
+        mock.set_attr(&value, &name, build(mock, attr)).unwrap();
+    }
+    value
+}
+
+/// `value` as the remote backend sends it, with the attributes in `names`.
+fn to_robject(value: &MockValue, names: &[String]) -> RObject {
+    let data = match &*value.data() {
+        VectorData::Logical(x) => VectorData::Logical(x.clone()),
+        VectorData::Integer(x) => VectorData::Integer(x.clone()),
+        VectorData::Double(x) => VectorData::Double(x.clone()),
+        VectorData::Character(x) => VectorData::Character(x.clone()),
+        VectorData::Raw(x) => VectorData::Raw(x.clone()),
+        VectorData::List(x) => {
+            VectorData::List(x.iter().map(|x| to_robject(x, names)).collect())
+        }
+    };
+    let mut object = RObject::new(data);
+    for name in names {
+        if let Some(attr) = value.attr(name) {
+            object.attrs.push((name.clone(), to_robject(&attr, names)));
+        }
+    }
+    object
+}
+
+/// How deeply lists and attributes nest in `x`.
+fn depth(x: &RObject) -> usize {
+    let items = match &x.data {
+        Some(VectorData::List(items)) => &items[..],
+        _ => &[],
+    };
+    let attrs = x.attrs.iter().map(|(_, value)| value);
+    items.iter().chain(attrs).map(|x| 1 + depth(x)).max().unwrap_or(0)
+}
+
+fuzz_target!(|input: (Vec<(String, Object)>, u32)| {
+    let (columns, nrow) = input;
+    let mock = Mock::new();
+    let mut names: Vec<String> = vec!["names".into(), "row.names".into(), "class".into()];
+    let columns: Vec<(String, MockValue)> = columns
+        .into_iter()
+        .map(|(name, object)| {
+            names.extend(object.attrs.iter().map(|(name, _)| name.clone()));
+            (name, build(&mock, object))
+        })
+        .collect();
+    names.sort();
+    names.dedup();
+
+    let nrow = nrow as usize;
+    let df = data_frame(
+        &mock,
+        columns.iter().map(|(n, v)| (n.as_str(), v.clone())).collect(),
+        nrow,
+    );
+    let df = match df {
+        Ok(df) => df,
+        Err(_) => {
+            assert!(nrow > i32::MAX as usize);
+            return;
+        }
+    };
+    match &*df.data() {
+        VectorData::List(x) => assert_eq!(x.len(), columns.len()),
+        _ => panic!("a data frame is a list"),
+    }
+    let column_names: Vec<Option<String>> =
+        columns.iter().map(|(n, _)| Some(n.clone())).collect();
+    assert_eq!(
+        *df.attr("names").unwrap().data(),
+        VectorData::Character(column_names)
+    );
+
+    // Compared as bytes: doubles may be NaN. Objects nested more deeply
+    // than R code builds are refused by `unserialize()`.
+    let object = to_robject(&df, &names);
+    if depth(&object) > 256 {
+        return;
+    }
+    if let Ok(bytes) = serialize(&object) {
+        let again = unserialize(&bytes).expect("a serialized value parses");
+        assert_eq!(serialize(&again).unwrap(), bytes);
+    }
+});
@@ -0,0 +1,24 @@
+//! Chunking with any sizes covers the data with chunks within the bounds.
+
+#![no_main]
+
+use helloextendr_core::Chunker;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (u16, u16, u16, &[u8])| {
+    let (min, avg, max, data) = input;
+    let chunks = Chunker::new(min.into(), avg.into(), max.into()).chunks(data);
+    assert_eq!(chunks.concat(), data);
+    // As `Chunker::new()` adjusts them.
+    let avg = usize::from(avg).max(4);
+    let max = usize::from(max).max(avg);
+    let min = usize::from(min).min(avg);
+    for (i, chunk) in chunks.iter().enumerate() {
+        assert!(!chunk.is_empty() && chunk.len() <= max);
+        // Only the last chunk is cut short, and a chunk ends at `min` only
+        // when `max` is that small too.
+        if i + 1 < chunks.len() {
+            assert!(chunk.len() > min || chunk.len() == max);
+        }
+    }
+});
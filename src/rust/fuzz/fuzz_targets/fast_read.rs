@@ -0,0 +1,24 @@
+//! The header of a `fast_save()` file is checked before anything is
+//! allocated or decompressed from it.
+
+#![no_main]
+
+use helloextendr_core::fastio::parse_blocks;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let (len, blocks) = match parse_blocks(data) {
+        Ok(parsed) => parsed,
+        Err(_) => return,
+    };
+    assert_eq!(blocks.iter().map(|&(_, raw)| raw).sum::<usize>(), len);
+    for (block, raw) in blocks {
+        // zstd allocates the claimed length up front.
+        if raw > 1 << 20 {
+            continue;
+        }
+        if let Ok(bytes) = zstd::bulk::decompress(block, raw) {
+            assert!(bytes.len() <= raw);
+        }
+    }
+});
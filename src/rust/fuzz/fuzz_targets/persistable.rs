@@ -0,0 +1,18 @@
+//! The snapshots of persistable handles come back from files that may be
+//! damaged or crafted: decoding one fails cleanly or gives a value that
+//! encodes to the same bytes.
+
+#![no_main]
+
+use helloextendr_core::bloom::BloomFilter;
+use helloextendr_core::BitSet;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(bits) = BitSet::from_bytes(data) {
+        assert_eq!(bits.to_bytes(), data);
+    }
+    if let Ok(filter) = BloomFilter::from_bytes(data) {
+        assert_eq!(filter.to_bytes(), data);
+    }
+});
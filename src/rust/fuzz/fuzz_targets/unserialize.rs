@@ -0,0 +1,19 @@
+//! The replies of a remote R server are parsed from bytes it may have
+//! crafted: parsing fails cleanly, without overflowing the stack on deep
+//! nesting, or gives a value that serializes and parses back to itself.
+
+#![no_main]
+
+use helloextendr_core::remote::{serialize, unserialize};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let value = match unserialize(data) {
+        Ok(value) => value,
+        Err(_) => return,
+    };
+    // Compared as bytes: doubles may be NaN.
+    let bytes = serialize(&value).expect("a parsed value fits the format");
+    let again = unserialize(&bytes).expect("a serialized value parses");
+    assert_eq!(serialize(&again).unwrap(), bytes);
+});
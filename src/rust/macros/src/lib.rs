@@ -0,0 +1,192 @@
+//! Procedural macros for helloextendr.
+
+mod literal;
+mod module;
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Ident, ItemImpl, Type};
+
+/// Turn an `#[extendr]` impl block into an R class.
+///
+/// Put it above `#[extendr]`:
+///
+/// ```ignore
+/// #[extendr]
+/// struct Counter(i32);
+///
+/// #[r_class(S4)]
+/// #[extendr]
+/// impl Counter {
+///     fn new(start: i32) -> Self { ... }
+///     fn increment(&mut self) { ... }
+/// }
+/// ```
+///
+/// With `S4`, the package defines an S4 class `Counter` with a slot `ptr`
+/// holding the external pointer, methods for `$`, `[[` and `show()` that
+/// dispatch to the Rust methods, and a constructor `new_Counter()`.
+///
+/// With `R6` (the default), `new_Counter()` instead returns an environment
+/// of class `c("CounterR6", "RustR6Like")` whose members are the Rust methods
+/// bound to the object, like an R6 class. It is not an R6 object, so it does
+/// not take the class `R6` whose methods, such as `print()` and `clone()`,
+/// expect one.
+///
+/// The definition is written after the wrappers of the impl block into
+/// `R/extendr-wrappers.R` when the wrappers are generated, by the
+/// [`r_module!`] registering the impl. Export the constructor (and for S4
+/// the class) from `NAMESPACE` as usual.
+#[proc_macro_attribute]
+pub fn r_class(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let style = if attr.is_empty() {
+        Ident::new("R6", proc_macro2::Span::call_site())
+    } else {
+        parse_macro_input!(attr as Ident)
+    };
+    let item = parse_macro_input!(item as ItemImpl);
+
+    let class = match item.self_ty.as_ref() {
+        Type::Path(path) if path.qself.is_none() => match path.path.segments.last() {
+            Some(segment) => segment.ident.to_string(),
+            None => return error(&item.self_ty, "expected a type name"),
+        },
+        other => return error(other, "`#[r_class]` needs an impl of a named type"),
+    };
+    let definition = match style.to_string().as_str() {
+        "S4" => s4_definition(&class),
+        "R6" => r6_definition(&class),
+        _ => return error(&style, "expected `S4` or `R6`"),
+    };
+
+    let self_ty = &item.self_ty;
+    quote! {
+        #item
+
+        impl #self_ty {
+            /// R code defining the class, written into the wrappers.
+            #[doc(hidden)]
+            pub const R_CLASS_DEFINITION: Option<&'static str> = Some(#definition);
+        }
+    }
+    .into()
+}
+
+/// An R symbol, like `quote(name)`: `sym!(x)`, `sym!(na.rm)` or, for
+/// names that are not Rust identifiers, `sym!("my var")`.
+///
+/// Empty names and names containing NUL are compile errors.
+#[proc_macro]
+pub fn sym(input: TokenStream) -> TokenStream {
+    let name = parse_macro_input!(input as literal::Name);
+    literal::sym(name)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// A character vector, optionally named: `strings!["a", "b"]` or
+/// `strings!(first = "a", "b")`. Values can be anything that converts to
+/// `Rstr`, such as `&str` and `String`.
+///
+/// Names are checked as in [`list!`].
+#[proc_macro]
+pub fn strings(input: TokenStream) -> TokenStream {
+    literal::strings(parse_macro_input!(input as literal::Entries)).into()
+}
+
+/// A list, like `list()` in R: `list!(x = 1, na.rm = true, "my var" = v, w)`.
+/// Values are converted with `Robj::from`.
+///
+/// Names are identifiers, identifiers joined by dots or string literals.
+/// Empty names, names containing NUL and names given twice are compile
+/// errors; leave a value unnamed by omitting its name.
+#[proc_macro]
+pub fn list(input: TokenStream) -> TokenStream {
+    literal::list(parse_macro_input!(input as literal::Entries)).into()
+}
+
+/// A pairlist, as used for the arguments of a call, with names checked as
+/// in [`list!`]: `f.call(pairlist!(x, na.rm = true))`.
+#[proc_macro]
+pub fn pairlist(input: TokenStream) -> TokenStream {
+    literal::pairlist(parse_macro_input!(input as literal::Entries)).into()
+}
+
+/// Register exported functions with R, like `extendr_module!`, checking the
+/// registrations at compile time:
+///
+/// ```ignore
+/// r_module! {
+///     mod archive;
+///     fn archive_list;
+///     impl Archive;
+///     use tar;
+/// }
+/// ```
+///
+/// Functions, classes or modules registered twice in one invocation are
+/// errors pointing at the second registration. The expansion also defines
+/// the module's `__REGISTRY` and checks in a constant that no two of the
+/// modules it uses, directly or not, register the same name, and that
+/// there are not more routines than `R_registerRoutines()` can count; see
+/// `registry.rs`. Every module used must be registered with `r_module!`.
+///
+/// Its wrapper generator, which `rextendr::document()` calls, follows the
+/// wrappers with the definitions of the [`macro@r_class`] classes of the
+/// module and of the modules it uses.
+#[proc_macro]
+pub fn r_module(input: TokenStream) -> TokenStream {
+    module::module(parse_macro_input!(input as module::Module)).into()
+}
+
+fn error(tokens: impl quote::ToTokens, message: &str) -> TokenStream {
+    syn::Error::new_spanned(tokens, message)
+        .to_compile_error()
+        .into()
+}
+
+fn s4_definition(class: &str) -> String {
+    format!(
+        r#"methods::setClass("{class}", representation(ptr = "externalptr"), where = topenv())
+methods::setMethod("$", "{class}", function(x, name) {{
+  func <- {class}[[name]]
+  self <- x@ptr
+  environment(func) <- environment()
+  func
+}}, where = topenv())
+methods::setMethod("[[", "{class}", function(x, i, ...) {{
+  func <- {class}[[i]]
+  self <- x@ptr
+  environment(func) <- environment()
+  func
+}}, where = topenv())
+methods::setMethod("show", "{class}", function(object) {{
+  methods <- setdiff(ls({class}), "new")
+  cat("<{class}>\n", paste0("  $", methods, "()\n"), sep = "")
+}}, where = topenv())
+new_{class} <- function(...) methods::new("{class}", ptr = {class}$new(...))
+"#
+    )
+}
+
+fn r6_definition(class: &str) -> String {
+    format!(
+        r#"new_{class} <- function(...) {{
+  ptr <- {class}$new(...)
+  obj <- new.env(parent = emptyenv())
+  obj$.ptr <- ptr
+  for (name in setdiff(ls({class}), "new")) {{
+    obj[[name]] <- local({{
+      func <- {class}[[name]]
+      self <- ptr
+      environment(func) <- environment()
+      func
+    }})
+  }}
+  lockEnvironment(obj, bindings = TRUE)
+  class(obj) <- c("{class}R6", "RustR6Like")
+  obj
+}}
+"#
+    )
+}
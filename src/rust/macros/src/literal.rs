@@ -0,0 +1,177 @@
+//! Parsing and expansion of the literal macros: `sym!`, `strings!`,
+//! `list!` and `pairlist!`.
+
+use std::collections::HashSet;
+
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::ext::IdentExt;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{Expr, Ident, LitStr, Token};
+
+/// An R name: a string literal, or identifiers joined by dots as in
+/// `na.rm` or `.data`.
+pub struct Name {
+    pub value: String,
+    pub span: Span,
+}
+
+impl Parse for Name {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let span = input.span();
+        if input.peek(LitStr) {
+            let lit: LitStr = input.parse()?;
+            return Ok(Name {
+                value: lit.value(),
+                span,
+            });
+        }
+        let mut value = String::new();
+        if input.peek(Token![.]) {
+            input.parse::<Token![.]>()?;
+            value.push('.');
+        }
+        value.push_str(&Ident::parse_any(input)?.unraw().to_string());
+        while input.peek(Token![.]) {
+            input.parse::<Token![.]>()?;
+            value.push('.');
+            value.push_str(&Ident::parse_any(input)?.unraw().to_string());
+        }
+        Ok(Name { value, span })
+    }
+}
+
+impl Name {
+    /// R does not allow empty symbols, nor NUL in any string.
+    fn check(&self) -> syn::Result<()> {
+        if self.value.is_empty() {
+            Err(syn::Error::new(self.span, "R names cannot be empty"))
+        } else if self.value.contains('\0') {
+            Err(syn::Error::new(self.span, "R names cannot contain NUL"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// A value, optionally named: `name = value` or `value`.
+pub struct Entry {
+    pub name: Option<Name>,
+    pub value: Expr,
+}
+
+impl Parse for Entry {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let fork = input.fork();
+        let named = fork.parse::<Name>().is_ok() && fork.peek(Token![=]) && !fork.peek(Token![==]);
+        let name = if named {
+            let name: Name = input.parse()?;
+            input.parse::<Token![=]>()?;
+            Some(name)
+        } else {
+            None
+        };
+        Ok(Entry {
+            name,
+            value: input.parse()?,
+        })
+    }
+}
+
+/// The entries of `list!`, `pairlist!` and `strings!`, with their names
+/// checked and free of duplicates.
+pub struct Entries(pub Vec<Entry>);
+
+impl Parse for Entries {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let entries: Vec<Entry> = Punctuated::<Entry, Token![,]>::parse_terminated(input)?
+            .into_iter()
+            .collect();
+        let mut seen = HashSet::new();
+        for name in entries.iter().filter_map(|e| e.name.as_ref()) {
+            name.check()?;
+            if !seen.insert(name.value.clone()) {
+                return Err(syn::Error::new(
+                    name.span,
+                    format!("duplicate name `{}`", name.value),
+                ));
+            }
+        }
+        Ok(Entries(entries))
+    }
+}
+
+impl Entries {
+    fn names(&self) -> Vec<String> {
+        self.0
+            .iter()
+            .map(|e| e.name.as_ref().map(|n| n.value.clone()).unwrap_or_default())
+            .collect()
+    }
+
+    fn has_names(&self) -> bool {
+        self.0.iter().any(|e| e.name.is_some())
+    }
+
+    fn values(&self) -> impl Iterator<Item = &Expr> {
+        self.0.iter().map(|e| &e.value)
+    }
+}
+
+pub fn sym(name: Name) -> syn::Result<TokenStream> {
+    name.check()?;
+    let value = name.value;
+    Ok(quote!(::extendr_api::Symbol::from_string(#value)))
+}
+
+pub fn strings(entries: Entries) -> TokenStream {
+    if entries.0.is_empty() {
+        return quote!(::extendr_api::Strings::new(0));
+    }
+    let values = entries.values();
+    let strings = quote! {
+        ::extendr_api::Strings::from_values([#(::extendr_api::Rstr::from(#values)),*])
+    };
+    if !entries.has_names() {
+        return strings;
+    }
+    let names = entries.names();
+    quote! {{
+        let mut strings = #strings;
+        ::extendr_api::prelude::Attributes::set_names(&mut strings, [#(#names),*])
+            .expect("as many names as strings");
+        strings
+    }}
+}
+
+pub fn list(entries: Entries) -> TokenStream {
+    if entries.0.is_empty() {
+        return quote!(::extendr_api::List::new(0));
+    }
+    let values = entries.values();
+    let values = quote!([#(::extendr_api::Robj::from(#values)),*]);
+    if !entries.has_names() {
+        return quote!(::extendr_api::List::from_values(#values));
+    }
+    let names = entries.names();
+    quote! {
+        ::extendr_api::List::from_names_and_values([#(#names),*], #values)
+            .expect("as many names as values")
+    }
+}
+
+pub fn pairlist(entries: Entries) -> TokenStream {
+    if entries.0.is_empty() {
+        return quote! {
+            ::extendr_api::Pairlist::from_pairs(
+                ::std::vec::Vec::<(&str, ::extendr_api::Robj)>::new(),
+            )
+        };
+    }
+    let names = entries.names();
+    let values = entries.values();
+    quote! {
+        ::extendr_api::Pairlist::from_pairs([#((#names, ::extendr_api::Robj::from(#values))),*])
+    }
+}
@@ -0,0 +1,231 @@
+//! Parsing and expansion of `r_module!`.
+
+use std::collections::HashMap;
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote, quote_spanned};
+use syn::parse::{Parse, ParseStream};
+use syn::{Ident, Token, Type};
+
+/// An item of the module: `fn name;`, `impl Type;` or `use module;`.
+pub enum Item {
+    Fn(Ident),
+    Impl(Box<Type>, Ident),
+    Use(Ident),
+}
+
+impl Parse for Item {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let lookahead = input.lookahead1();
+        let item = if lookahead.peek(Token![fn]) {
+            input.parse::<Token![fn]>()?;
+            Item::Fn(input.parse()?)
+        } else if lookahead.peek(Token![impl]) {
+            input.parse::<Token![impl]>()?;
+            let ty: Type = input.parse()?;
+            let name = match &ty {
+                Type::Path(path) if path.qself.is_none() => path
+                    .path
+                    .segments
+                    .last()
+                    .map(|segment| segment.ident.clone()),
+                _ => None,
+            };
+            match name {
+                Some(name) => Item::Impl(Box::new(ty), name),
+                None => return Err(syn::Error::new_spanned(ty, "expected a type name")),
+            }
+        } else if lookahead.peek(Token![use]) {
+            input.parse::<Token![use]>()?;
+            Item::Use(input.parse()?)
+        } else {
+            return Err(lookahead.error());
+        };
+        input.parse::<Token![;]>()?;
+        Ok(item)
+    }
+}
+
+/// The input of `extendr_module!`, with no name registered twice.
+pub struct Module {
+    pub name: Ident,
+    pub items: Vec<Item>,
+}
+
+impl Parse for Module {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        input.parse::<Token![mod]>()?;
+        let name: Ident = input.parse()?;
+        input.parse::<Token![;]>()?;
+        let mut items = Vec::new();
+        while !input.is_empty() {
+            items.push(input.parse::<Item>()?);
+        }
+
+        // Functions and classes share the namespace, modules are only
+        // compared with each other.
+        let mut names: HashMap<String, &Ident> = HashMap::new();
+        let mut modules: HashMap<String, &Ident> = HashMap::new();
+        for item in &items {
+            let (ident, seen, what) = match item {
+                Item::Fn(ident) => (ident, &mut names, "a function"),
+                Item::Impl(_, ident) => (ident, &mut names, "a class"),
+                Item::Use(ident) => (ident, &mut modules, "a module"),
+            };
+            if let Some(first) = seen.insert(ident.to_string(), ident) {
+                let mut error = syn::Error::new(
+                    ident.span(),
+                    format!("`{ident}` is already registered as {what} of this module"),
+                );
+                error.combine(syn::Error::new(first.span(), "first registered here"));
+                return Err(error);
+            }
+        }
+        Ok(Module { name, items })
+    }
+}
+
+/// What `extendr_module!` expands to, with a wrapper generator that also
+/// writes the definitions of the `#[r_class]` classes, and the module's
+/// registry.
+pub fn module(module: Module) -> TokenStream {
+    let name = &module.name;
+    let module_name = name.to_string();
+    let metadata = format_ident!("get_{}_metadata", name);
+    let metadata_name = metadata.to_string();
+    let wrap_metadata = format_ident!("wrap__get_{}_metadata", name);
+    let wrap_metadata_name = wrap_metadata.to_string();
+    let make_wrappers_name = format!("make_{name}_wrappers");
+    let wrap_make_wrappers = format_ident!("wrap__make_{}_wrappers", name);
+    let wrap_make_wrappers_name = wrap_make_wrappers.to_string();
+    let init = format_ident!("R_init_{}_extendr", name);
+
+    let mut entries = Vec::new();
+    let mut uses = Vec::new();
+    let mut functions = Vec::new();
+    let mut impls = Vec::new();
+    let mut classes = Vec::new();
+    let mut modules = Vec::new();
+    for item in &module.items {
+        match item {
+            Item::Fn(ident) => {
+                let clash = format!(
+                    "the R function `{ident}` of module `{module_name}` is registered by another module"
+                );
+                let ident_name = ident.to_string();
+                entries.push(
+                    quote!(crate::registry::Registration { name: #ident_name, clash: #clash }),
+                );
+                functions.push(format_ident!("meta__{}", ident));
+            }
+            Item::Impl(ty, ident) => {
+                let clash = format!(
+                    "the class `{ident}` of module `{module_name}`, with its `$` and `[[` methods, is registered by another module"
+                );
+                let ident_name = ident.to_string();
+                entries.push(
+                    quote!(crate::registry::Registration { name: #ident_name, clash: #clash }),
+                );
+                impls.push(format_ident!("meta__{}", ident));
+                classes.push(ty);
+            }
+            Item::Use(ident) => {
+                uses.push(quote_spanned!(ident.span()=> &#ident::__REGISTRY));
+                modules.push((ident, format_ident!("get_{}_metadata", ident)));
+            }
+        }
+    }
+    let (used, used_metadata): (Vec<_>, Vec<_>) = modules.into_iter().unzip();
+    let check = quote_spanned!(name.span()=>
+        const _: () = crate::registry::check(&__REGISTRY);
+    );
+    quote! {
+        #[no_mangle]
+        #[allow(non_snake_case)]
+        pub fn #metadata() -> ::extendr_api::metadata::Metadata {
+            let mut functions = Vec::new();
+            let mut impls = Vec::new();
+            #(#functions(&mut functions);)*
+            #(#impls(&mut impls);)*
+            #(
+                let used = #used::#used_metadata();
+                functions.extend(used.functions);
+                impls.extend(used.impls);
+            )*
+            functions.push(::extendr_api::metadata::Func {
+                doc: "Metadata access function.",
+                rust_name: #metadata_name,
+                mod_name: #metadata_name,
+                r_name: #metadata_name,
+                c_name: #wrap_metadata_name,
+                args: Vec::new(),
+                return_type: "Metadata",
+                func_ptr: #wrap_metadata as *const u8,
+                hidden: true,
+                invisible: None,
+            });
+            functions.push(::extendr_api::metadata::Func {
+                doc: "Wrapper generator.",
+                rust_name: #make_wrappers_name,
+                mod_name: #make_wrappers_name,
+                r_name: #make_wrappers_name,
+                c_name: #wrap_make_wrappers_name,
+                args: vec![
+                    ::extendr_api::metadata::Arg { name: "use_symbols", arg_type: "bool", default: None },
+                    ::extendr_api::metadata::Arg { name: "package_name", arg_type: "&str", default: None },
+                ],
+                return_type: "String",
+                func_ptr: #wrap_make_wrappers as *const u8,
+                hidden: true,
+                invisible: None,
+            });
+            ::extendr_api::metadata::Metadata {
+                name: #module_name,
+                functions,
+                impls,
+            }
+        }
+
+        /// Push the R definitions of the `#[r_class]` classes of the module
+        /// and of the modules it uses.
+        #[doc(hidden)]
+        #[allow(clippy::ptr_arg)]
+        pub fn __r_class_definitions(definitions: &mut Vec<&'static str>) {
+            #[allow(unused_imports)]
+            use crate::registry::RClassDefinition as _;
+            #(definitions.extend(<#classes>::R_CLASS_DEFINITION);)*
+            #(#used::__r_class_definitions(definitions);)*
+        }
+
+        #[no_mangle]
+        #[allow(non_snake_case)]
+        pub extern "C" fn #wrap_metadata() -> ::extendr_api::SEXP {
+            use ::extendr_api::GetSexp;
+            unsafe { ::extendr_api::Robj::from(#metadata()).get() }
+        }
+
+        #[no_mangle]
+        #[allow(non_snake_case, clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn #wrap_make_wrappers(
+            use_symbols: ::extendr_api::SEXP,
+            package_name: ::extendr_api::SEXP,
+        ) -> ::extendr_api::SEXP {
+            unsafe { crate::registry::make_wrappers(#metadata(), __r_class_definitions, use_symbols, package_name) }
+        }
+
+        #[no_mangle]
+        #[allow(non_snake_case, clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn #init(info: *mut ::extendr_api::DllInfo) {
+            unsafe { ::extendr_api::register_call_methods(info, #metadata()) };
+        }
+
+        #[doc(hidden)]
+        pub const __REGISTRY: crate::registry::Registry = crate::registry::Registry {
+            module: #module_name,
+            entries: &[#(#entries),*],
+            uses: &[#(#uses),*],
+        };
+
+        #check
+    }
+}
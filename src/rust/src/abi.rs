@@ -0,0 +1,106 @@
+//! A handshake between the R wrappers and the compiled library.
+//!
+//! The wrappers call routines by position: `.Call(wrap__f, a, b)` passes
+//! `a` and `b` as the first and second arguments of `f`, whatever their
+//! names are in the Rust code. Wrappers from one build paired with the
+//! library of another, as when an install leaves stale R code behind or a
+//! signature changes without regenerating `extendr-wrappers.R`, silently
+//! pass arguments to the wrong parameters.
+//!
+//! The API signature lists every routine with the arguments it takes, one
+//! `wrap__f(a,b)` line each, sorted. [`signature()`] reads it from the
+//! module metadata compiled into the library and `.onLoad()` derives it
+//! from the `.Call()`s of the wrappers; [`api_check()`] compares their
+//! hashes and fails the load with a message to reinstall if they differ.
+
+use extendr_api::metadata::Func;
+use extendr_api::prelude::*;
+
+use crate::r_module;
+
+/// The API signature of the library: its routines and their arguments.
+pub fn signature() -> Vec<String> {
+    let metadata = crate::get_helloextendr_metadata();
+    let functions = metadata
+        .functions
+        .iter()
+        .filter(|func| !func.hidden)
+        .map(|func| line(&format!("wrap__{}", func.mod_name), func));
+    let methods = metadata.impls.iter().flat_map(|imp| {
+        imp.methods
+            .iter()
+            .map(move |func| line(&format!("wrap__{}__{}", imp.name, func.mod_name), func))
+    });
+    let mut lines: Vec<String> = functions.chain(methods).collect();
+    lines.sort();
+    lines.dedup();
+    lines
+}
+
+fn line(routine: &str, func: &Func) -> String {
+    let args: Vec<&str> = func.args.iter().map(|arg| arg.name).collect();
+    format!("{routine}({})", args.join(","))
+}
+
+/// The 64-bit FNV-1a hash of the lines of a signature, in hex.
+pub fn hash(lines: &[String]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for (i, line) in lines.iter().enumerate() {
+        let separator = if i == 0 { &b""[..] } else { &b"\n"[..] };
+        for &byte in separator.iter().chain(line.as_bytes()) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    format!("{hash:016x}")
+}
+
+/// API hash
+///
+/// The hash of the routines of the compiled library and of the arguments
+/// they take. Loading the package checks that the R wrappers were generated
+/// for the same routines, and asks to reinstall the package if not.
+/// @return A string of 16 hexadecimal digits.
+/// @export
+#[extendr]
+fn api_hash() -> String {
+    hash(&signature())
+}
+
+/// `NULL` if `wrappers`, the signature of the R wrappers, matches the
+/// library, or else the error message to fail the load with.
+/// @noRd
+#[extendr]
+fn api_check(mut wrappers: Vec<String>) -> Robj {
+    wrappers.sort();
+    wrappers.dedup();
+    let library = signature();
+    if hash(&wrappers) == hash(&library) {
+        return r!(NULL);
+    }
+    let differences: Vec<String> = wrappers
+        .iter()
+        .filter(|line| !library.contains(line))
+        .map(|line| format!("\n  wrappers: {line}"))
+        .chain(
+            library
+                .iter()
+                .filter(|line| !wrappers.contains(line))
+                .map(|line| format!("\n  library:  {line}")),
+        )
+        .take(10)
+        .collect();
+    r!(format!(
+        "the R code of helloextendr (API {}) does not match its compiled library (API {}):{}\n\
+         Please reinstall helloextendr.",
+        hash(&wrappers),
+        hash(&library),
+        differences.concat()
+    ))
+}
+
+r_module! {
+    mod abi;
+    fn api_hash;
+    fn api_check;
+}
@@ -0,0 +1,598 @@
+//! Zip and tar archives without external utilities.
+//!
+//! [`list()`], [`extract()`] and [`create()`] read and write `.zip`,
+//! `.tar`, `.tar.gz` and `.tar.zst` archives with the `zip`, `tar`,
+//! `flate2` and `zstd` crates, from any reader and to any writer, so R code
+//! can work on files or raw vectors alike and does not depend on the
+//! `zip`, `unzip` and `tar` programs that `utils::zip()` and friends need
+//! on some platforms.
+//!
+//! Extraction never writes outside the target directory: entries with
+//! absolute paths or `..` components are refused. Both directions check
+//! for user interrupts between entries and can report progress on the
+//! console, so they must run on the main thread.
+//!
+//! `.zip` archives need the `zip` feature and `.tar.zst` archives the
+//! `zstd` feature. Without them, those formats fail.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, Cursor, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+#[cfg(feature = "zip")]
+use zip::write::SimpleFileOptions;
+#[cfg(feature = "zip")]
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::attrib::{set_compact_row_names, AttribExt};
+use crate::encoding::Utf8Strings;
+use crate::parallel::interrupt_pending;
+use crate::r_module;
+use crate::raw_io::{IntoRaw, RawReader};
+
+/// An archive format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Zip,
+    Tar,
+    TarGz,
+    TarZst,
+}
+
+impl Format {
+    /// The format of an archive named `path`, from its extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        [
+            (".zip", Format::Zip),
+            (".tar", Format::Tar),
+            (".tar.gz", Format::TarGz),
+            (".tgz", Format::TarGz),
+            (".tar.zst", Format::TarZst),
+            (".tzst", Format::TarZst),
+        ]
+        .iter()
+        .find(|(extension, _)| name.ends_with(extension))
+        .map(|&(_, format)| format)
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "zip" => Ok(Format::Zip),
+            "tar" => Ok(Format::Tar),
+            "tar.gz" | "tgz" => Ok(Format::TarGz),
+            "tar.zst" | "tzst" => Ok(Format::TarZst),
+            other => Err(Error::Other(format!(
+                "unknown archive format \"{other}\"; expected \"zip\", \"tar\", \"tar.gz\" or \"tar.zst\""
+            ))),
+        }
+    }
+}
+
+/// An entry of an archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// The path in the archive, with `/` separators.
+    pub path: String,
+    /// The uncompressed size in bytes.
+    pub size: u64,
+    pub is_dir: bool,
+}
+
+/// Counts processed entries on one console line and checks for interrupts.
+#[derive(Debug)]
+pub struct Progress {
+    verb: &'static str,
+    show: bool,
+    done: usize,
+}
+
+impl Progress {
+    pub fn new(verb: &'static str, show: bool) -> Self {
+        Self {
+            verb,
+            show,
+            done: 0,
+        }
+    }
+
+    /// Called before each entry; fails if the user interrupted.
+    fn step(&mut self, path: &str) -> Result<()> {
+        if interrupt_pending() {
+            self.finish();
+            return Err(Error::Other("interrupted by the user".into()));
+        }
+        self.done += 1;
+        if self.show {
+            rprint!("\r\x1b[K{} {}: {path}", self.verb, self.done);
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) {
+        if self.show && self.done > 0 {
+            rprintln!("\r\x1b[K{} {} entries", self.verb, self.done);
+        }
+        self.show = false;
+    }
+}
+
+/// Errors of the archive crates, which all convert to `io::Error`.
+fn archive_error(e: impl Into<io::Error>) -> Error {
+    Error::Other(format!("invalid archive: {}", e.into()))
+}
+
+fn io_error(path: &Path, e: io::Error) -> Error {
+    Error::Other(format!("{}: {e}", path.display()))
+}
+
+/// A tar stream over `reader`, decompressed as `format` requires.
+fn tar_reader<'a, R: Read + 'a>(
+    reader: R,
+    format: Format,
+) -> Result<tar::Archive<Box<dyn Read + 'a>>> {
+    let reader: Box<dyn Read + 'a> = match format {
+        Format::Tar => Box::new(reader),
+        Format::TarGz => Box::new(GzDecoder::new(reader)),
+        #[cfg(feature = "zstd")]
+        Format::TarZst => Box::new(zstd::Decoder::new(reader).map_err(archive_error)?),
+        #[cfg(not(feature = "zstd"))]
+        Format::TarZst => return Err(crate::missing_feature("zstd")),
+        Format::Zip => unreachable!("zip archives are not tar streams"),
+    };
+    Ok(tar::Archive::new(reader))
+}
+
+/// The entries of the archive read from `reader`.
+pub fn list<R: Read + Seek>(reader: R, format: Format) -> Result<Vec<Entry>> {
+    if format == Format::Zip {
+        return list_zip(reader);
+    }
+    let mut archive = tar_reader(reader, format)?;
+    archive
+        .entries()
+        .map_err(archive_error)?
+        .map(|entry| {
+            let entry = entry.map_err(archive_error)?;
+            let path = entry.path().map_err(archive_error)?;
+            Ok(Entry {
+                path: path.to_string_lossy().replace('\\', "/"),
+                size: entry.header().size().map_err(archive_error)?,
+                is_dir: entry.header().entry_type().is_dir(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(feature = "zip")]
+fn list_zip<R: Read + Seek>(reader: R) -> Result<Vec<Entry>> {
+    let mut zip = ZipArchive::new(reader).map_err(archive_error)?;
+    (0..zip.len())
+        .map(|i| {
+            let file = zip.by_index_raw(i).map_err(archive_error)?;
+            Ok(Entry {
+                path: file.name().to_string(),
+                size: file.size(),
+                is_dir: file.is_dir(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(not(feature = "zip"))]
+fn list_zip<R: Read + Seek>(_reader: R) -> Result<Vec<Entry>> {
+    Err(crate::missing_feature("zip"))
+}
+
+/// Whether the entry `path` is selected by `files`, `None` selecting all.
+fn selected(files: Option<&[String]>, path: &str) -> bool {
+    let path = path.trim_end_matches('/');
+    files.is_none_or(|files| files.iter().any(|file| file.trim_end_matches('/') == path))
+}
+
+/// Extract the archive read from `reader` into `exdir`, only the entries
+/// named in `files` if given. Returns the paths written, directories
+/// included.
+pub fn extract<R: Read + Seek>(
+    reader: R,
+    format: Format,
+    exdir: &Path,
+    files: Option<&[String]>,
+    progress: &mut Progress,
+) -> Result<Vec<PathBuf>> {
+    fs::create_dir_all(exdir).map_err(|e| io_error(exdir, e))?;
+    let result = if format == Format::Zip {
+        extract_zip(reader, exdir, files, progress)
+    } else {
+        extract_tar(reader, format, exdir, files, progress)
+    };
+    progress.finish();
+    result
+}
+
+#[cfg(feature = "zip")]
+fn extract_zip<R: Read + Seek>(
+    reader: R,
+    exdir: &Path,
+    files: Option<&[String]>,
+    progress: &mut Progress,
+) -> Result<Vec<PathBuf>> {
+    let mut zip = ZipArchive::new(reader).map_err(archive_error)?;
+    let mut written = Vec::new();
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i).map_err(archive_error)?;
+        if !selected(files, file.name()) {
+            continue;
+        }
+        progress.step(file.name())?;
+        let relative = file.enclosed_name().ok_or_else(|| {
+            Error::Other(format!(
+                "refusing to extract \"{}\" outside the directory",
+                file.name()
+            ))
+        })?;
+        let path = exdir.join(relative);
+        if file.is_dir() {
+            fs::create_dir_all(&path).map_err(|e| io_error(&path, e))?;
+        } else {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(|e| io_error(parent, e))?;
+            }
+            let mut out = File::create(&path).map_err(|e| io_error(&path, e))?;
+            io::copy(&mut file, &mut out).map_err(|e| io_error(&path, e))?;
+            #[cfg(unix)]
+            if let Some(mode) = file.unix_mode() {
+                use std::os::unix::fs::PermissionsExt;
+                fs::set_permissions(&path, fs::Permissions::from_mode(mode & 0o777))
+                    .map_err(|e| io_error(&path, e))?;
+            }
+        }
+        written.push(path);
+    }
+    Ok(written)
+}
+
+#[cfg(not(feature = "zip"))]
+fn extract_zip<R: Read + Seek>(
+    _reader: R,
+    _exdir: &Path,
+    _files: Option<&[String]>,
+    _progress: &mut Progress,
+) -> Result<Vec<PathBuf>> {
+    Err(crate::missing_feature("zip"))
+}
+
+fn extract_tar<R: Read>(
+    reader: R,
+    format: Format,
+    exdir: &Path,
+    files: Option<&[String]>,
+    progress: &mut Progress,
+) -> Result<Vec<PathBuf>> {
+    let mut archive = tar_reader(reader, format)?;
+    let mut written = Vec::new();
+    for entry in archive.entries().map_err(archive_error)? {
+        let mut entry = entry.map_err(archive_error)?;
+        let name = entry
+            .path()
+            .map_err(archive_error)?
+            .to_string_lossy()
+            .replace('\\', "/");
+        if !selected(files, &name) {
+            continue;
+        }
+        progress.step(&name)?;
+        // `unpack_in()` refuses paths outside `exdir` by returning false.
+        if !entry.unpack_in(exdir).map_err(|e| io_error(exdir, e))? {
+            return Err(Error::Other(format!(
+                "refusing to extract \"{name}\" outside the directory"
+            )));
+        }
+        written.push(exdir.join(name.trim_end_matches('/')));
+    }
+    Ok(written)
+}
+
+/// The files under `root` named by `paths`, relative to `root`, with the
+/// contents of directories, sorted within each directory.
+fn collect(root: &Path, paths: &[String]) -> Result<Vec<(PathBuf, String, bool)>> {
+    fn walk(root: &Path, name: String, out: &mut Vec<(PathBuf, String, bool)>) -> Result<()> {
+        let path = root.join(&name);
+        let metadata = fs::metadata(&path).map_err(|e| io_error(&path, e))?;
+        if !metadata.is_dir() {
+            out.push((path, name, false));
+            return Ok(());
+        }
+        let mut children = fs::read_dir(&path)
+            .and_then(|entries| {
+                entries
+                    .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+                    .collect::<io::Result<Vec<_>>>()
+            })
+            .map_err(|e| io_error(&path, e))?;
+        children.sort();
+        out.push((path, format!("{name}/"), true));
+        for child in children {
+            walk(root, format!("{name}/{child}"), out)?;
+        }
+        Ok(())
+    }
+    let mut out = Vec::new();
+    for path in paths {
+        let name = path.replace('\\', "/").trim_end_matches('/').to_string();
+        if name.is_empty() || name.starts_with('/') || name.split('/').any(|part| part == "..") {
+            return Err(Error::Other(format!(
+                "\"{path}\" is not a relative path inside the root directory"
+            )));
+        }
+        walk(root, name, &mut out)?;
+    }
+    Ok(out)
+}
+
+/// Write an archive of the files under `root` named by `paths`, relative
+/// to `root`, to `writer`, and return the writer.
+pub fn create<W: Write + Seek>(
+    writer: W,
+    format: Format,
+    root: &Path,
+    paths: &[String],
+    progress: &mut Progress,
+) -> Result<W> {
+    let entries = collect(root, paths)?;
+    let result = match format {
+        Format::Zip => create_zip(writer, &entries, progress),
+        Format::Tar => create_tar(writer, &entries, progress),
+        Format::TarGz => {
+            let encoder = GzEncoder::new(writer, flate2::Compression::default());
+            create_tar(encoder, &entries, progress)
+                .and_then(|encoder| encoder.finish().map_err(archive_error))
+        }
+        #[cfg(feature = "zstd")]
+        Format::TarZst => zstd::Encoder::new(writer, 0)
+            .map_err(archive_error)
+            .and_then(|encoder| create_tar(encoder, &entries, progress))
+            .and_then(|encoder| encoder.finish().map_err(archive_error)),
+        #[cfg(not(feature = "zstd"))]
+        Format::TarZst => Err(crate::missing_feature("zstd")),
+    };
+    progress.finish();
+    result
+}
+
+#[cfg(feature = "zip")]
+fn create_zip<W: Write + Seek>(
+    writer: W,
+    entries: &[(PathBuf, String, bool)],
+    progress: &mut Progress,
+) -> Result<W> {
+    let mut zip = ZipWriter::new(writer);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (path, name, is_dir) in entries {
+        progress.step(name)?;
+        if *is_dir {
+            zip.add_directory(name.as_str(), options)
+                .map_err(archive_error)?;
+            continue;
+        }
+        #[cfg(unix)]
+        let options = {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(path)
+                .map_err(|e| io_error(path, e))?
+                .permissions()
+                .mode();
+            options.unix_permissions(mode & 0o777)
+        };
+        zip.start_file(name.as_str(), options)
+            .map_err(archive_error)?;
+        let mut file = BufReader::new(File::open(path).map_err(|e| io_error(path, e))?);
+        io::copy(&mut file, &mut zip).map_err(|e| io_error(path, e))?;
+    }
+    zip.finish().map_err(archive_error)
+}
+
+#[cfg(not(feature = "zip"))]
+fn create_zip<W: Write + Seek>(
+    _writer: W,
+    _entries: &[(PathBuf, String, bool)],
+    _progress: &mut Progress,
+) -> Result<W> {
+    Err(crate::missing_feature("zip"))
+}
+
+fn create_tar<W: Write>(
+    writer: W,
+    entries: &[(PathBuf, String, bool)],
+    progress: &mut Progress,
+) -> Result<W> {
+    let mut tar = tar::Builder::new(writer);
+    for (path, name, is_dir) in entries {
+        progress.step(name)?;
+        if *is_dir {
+            tar.append_dir(name, path).map_err(|e| io_error(path, e))?;
+        } else {
+            tar.append_path_with_name(path, name)
+                .map_err(|e| io_error(path, e))?;
+        }
+    }
+    tar.into_inner().map_err(archive_error)
+}
+
+/// The format from the `format` argument, or else from the name of the
+/// archive.
+fn format_arg(format: &Robj, path: Option<&str>) -> Result<Format> {
+    if let Some(format) = format.as_str() {
+        return format.parse();
+    }
+    path.and_then(|path| Format::from_path(Path::new(path)))
+        .ok_or_else(|| {
+            Error::Other("cannot tell the archive format from the file name; set `format`".into())
+        })
+}
+
+/// Calls `f` with a reader of `archive`, a path or a raw vector.
+fn with_reader<T>(
+    archive: &Robj,
+    f: impl FnOnce(&mut dyn ReadSeek, Option<&str>) -> Result<T>,
+) -> Result<T> {
+    if let Some(path) = archive.as_str() {
+        let file = File::open(path).map_err(|e| io_error(Path::new(path), e))?;
+        return f(&mut BufReader::new(file), Some(path));
+    }
+    let raw = Raw::try_from(archive.clone())
+        .map_err(|_| Error::Other("`archive` must be a path or a raw vector".into()))?;
+    f(&mut RawReader::new(raw), None)
+}
+
+trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// A character vector argument without `NA`s.
+fn paths_arg(x: &Robj, arg: &str) -> Result<Vec<String>> {
+    let invalid = || Error::Other(format!("`{arg}` must be a character vector without NA"));
+    Utf8Strings::try_from(x.clone())
+        .map_err(|_| invalid())?
+        .to_vec()?
+        .into_iter()
+        .map(|path| path.ok_or_else(invalid))
+        .collect()
+}
+
+/// Zip and tar archives
+///
+/// `archive_list()` lists the entries of an archive, `archive_extract()`
+/// extracts them and `archive_create()` creates an archive, all without the
+/// external `zip`, `unzip` and `tar` programs. Archives can be `.zip`,
+/// `.tar`, `.tar.gz` or `.tar.zst` files, or raw vectors with their
+/// contents. Extraction refuses entries that would land outside `exdir`.
+/// Long operations can be interrupted; the entries written until then
+/// remain.
+/// @param archive The path of the archive, or a raw vector. For
+///   `archive_create()`, `NULL` returns the archive as a raw vector.
+/// @param format `NULL` to tell the format from the file name, or one of
+///   `"zip"`, `"tar"`, `"tar.gz"` and `"tar.zst"`.
+/// @return `archive_list()` returns a data frame with columns `path`,
+///   `size` and `directory`. `archive_extract()` returns the paths of the
+///   extracted files and directories, invisibly. `archive_create()` returns
+///   the path of the archive, or a raw vector if `archive` is `NULL`.
+/// @export
+#[extendr]
+fn archive_list(archive: Robj, #[extendr(default = "NULL")] format: Robj) -> Result<Robj> {
+    let entries = with_reader(&archive, |reader, path| {
+        list(reader, format_arg(&format, path)?)
+    })?;
+    let columns: [Robj; 3] = [
+        Strings::from_values(entries.iter().map(|e| e.path.as_str())).into(),
+        Doubles::from_values(entries.iter().map(|e| e.size as f64)).into(),
+        Logicals::from_values(entries.iter().map(|e| e.is_dir)).into(),
+    ];
+    let mut df: Robj = List::from_names_and_values(["path", "size", "directory"], columns)?.into();
+    set_compact_row_names(&mut df, entries.len())?;
+    df.set_attr("class", "data.frame")?;
+    Ok(df)
+}
+
+/// @rdname archive_list
+/// @param exdir The directory to extract into, created if needed.
+/// @param files `NULL` to extract all entries, or the paths of the entries
+///   to extract.
+/// @param progress Whether to show the number of entries processed.
+/// @export
+#[extendr(invisible)]
+fn archive_extract(
+    archive: Robj,
+    exdir: &str,
+    #[extendr(default = "NULL")] files: Robj,
+    #[extendr(default = "NULL")] format: Robj,
+    #[extendr(default = "FALSE")] progress: bool,
+) -> Result<Vec<String>> {
+    let files = match files.is_null() {
+        true => None,
+        false => Some(paths_arg(&files, "files")?),
+    };
+    let written = with_reader(&archive, |reader, path| {
+        let format = format_arg(&format, path)?;
+        let mut progress = Progress::new("Extracted", progress);
+        extract(
+            reader,
+            format,
+            Path::new(exdir),
+            files.as_deref(),
+            &mut progress,
+        )
+    })?;
+    Ok(written
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect())
+}
+
+/// @rdname archive_list
+/// @param paths The files and directories to add, relative to `root`.
+///   Directories are added with their contents.
+/// @param root The directory `paths` are relative to.
+/// @export
+#[extendr]
+fn archive_create(
+    archive: Robj,
+    paths: Robj,
+    #[extendr(default = "\".\"")] root: &str,
+    #[extendr(default = "NULL")] format: Robj,
+    #[extendr(default = "FALSE")] progress: bool,
+) -> Result<Robj> {
+    let paths = paths_arg(&paths, "paths")?;
+    let mut progress = Progress::new("Added", progress);
+    let root = Path::new(root);
+    match archive.as_str() {
+        Some(path) => {
+            let format = format_arg(&format, Some(path))?;
+            // Written next to the target and renamed once complete, so an
+            // interrupted call leaves no truncated archive behind.
+            let partial = PathBuf::from(format!("{path}.partial"));
+            let file = File::create(&partial).map_err(|e| io_error(&partial, e))?;
+            let written = create(
+                io::BufWriter::new(file),
+                format,
+                root,
+                &paths,
+                &mut progress,
+            )
+            .and_then(|writer| {
+                writer
+                    .into_inner()
+                    .map_err(|e| io_error(&partial, e.into_error()))
+            })
+            .and_then(|file| file.sync_all().map_err(|e| io_error(&partial, e)));
+            if let Err(e) = written {
+                let _ = fs::remove_file(&partial);
+                return Err(e);
+            }
+            fs::rename(&partial, path).map_err(|e| io_error(Path::new(path), e))?;
+            Ok(archive)
+        }
+        None if archive.is_null() => {
+            let format = format_arg(&format, None)
+                .map_err(|_| Error::Other("`format` is needed to create a raw archive".into()))?;
+            let cursor = create(Cursor::new(Vec::new()), format, root, &paths, &mut progress)?;
+            Ok(cursor.into_inner().into_raw().into())
+        }
+        None => Err(Error::Other("`archive` must be a path or NULL".into())),
+    }
+}
+
+r_module! {
+    mod archive;
+    fn archive_list;
+    fn archive_extract;
+    fn archive_create;
+}
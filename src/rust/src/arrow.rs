@@ -0,0 +1,97 @@
+//! Zero-copy exchange of columnar data through the Arrow C Data Interface.
+//!
+//! Available with the `arrow` feature. On the R side data travels as
+//! `nanoarrow_array` external pointers from the {nanoarrow} package, which
+//! also accepts and produces {arrow} tables, so a `data.frame`, an
+//! `arrow::RecordBatch` or a `nanoarrow_array` can all be imported. Buffers
+//! are shared rather than copied: the release callbacks of the C interface
+//! keep the producer's memory alive for as long as the consumer needs it.
+
+use arrow_array::ffi::{from_ffi, to_ffi, FFI_ArrowArray, FFI_ArrowSchema};
+use arrow_array::{make_array, Array, ArrayRef, RecordBatch, StructArray};
+use arrow_schema::{ArrowError, DataType};
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+fn arrow_error(e: ArrowError) -> Error {
+    Error::Other(format!("arrow: {e}"))
+}
+
+/// Look up an exported function of {nanoarrow}, loading it if needed.
+fn nanoarrow(fun: &str) -> Result<Function> {
+    let ns = lang!("loadNamespace", "nanoarrow")
+        .eval()
+        .map_err(|_| Error::Other("the {nanoarrow} package is required for Arrow data".into()))?;
+    let ns: Environment = ns.try_into()?;
+    ns.local(Symbol::from_string(fun))?.try_into()
+}
+
+/// Decimal address of a C struct, the form {nanoarrow} accepts for pointers.
+fn address<T>(ptr: *mut T) -> String {
+    (ptr as usize).to_string()
+}
+
+fn pointer_of(xptr: &Robj) -> Result<usize> {
+    nanoarrow("nanoarrow_pointer_addr_chr")?
+        .call(pairlist!(xptr.clone()))?
+        .as_str()
+        .and_then(|addr| addr.parse().ok())
+        .ok_or_else(|| Error::Other("invalid nanoarrow pointer address".into()))
+}
+
+/// Import any object {nanoarrow} can convert (`data.frame`, atomic vector,
+/// {arrow} array or table, `nanoarrow_array`) as an Arrow array.
+pub fn import_array(x: &Robj) -> Result<ArrayRef> {
+    let array = nanoarrow("as_nanoarrow_array")?.call(pairlist!(x.clone()))?;
+    let schema = nanoarrow("infer_nanoarrow_schema")?.call(pairlist!(array.clone()))?;
+
+    let mut ffi_array = Box::new(FFI_ArrowArray::empty());
+    let mut ffi_schema = Box::new(FFI_ArrowSchema::empty());
+    // Both exports are independent of the R objects' lifetimes; the array
+    // only holds a reference that keeps the R-owned buffers alive.
+    let export = nanoarrow("nanoarrow_pointer_export")?;
+    export.call(pairlist!(array, address(&mut *ffi_array)))?;
+    export.call(pairlist!(schema, address(&mut *ffi_schema)))?;
+
+    let data = unsafe { from_ffi(*ffi_array, &ffi_schema) }.map_err(arrow_error)?;
+    Ok(make_array(data))
+}
+
+/// Import a table-like object as a [`RecordBatch`].
+pub fn import_record_batch(x: &Robj) -> Result<RecordBatch> {
+    let array = import_array(x)?;
+    if !matches!(array.data_type(), DataType::Struct(_)) {
+        return Err(Error::Other(format!(
+            "expected a table, got an Arrow array of type {}",
+            array.data_type()
+        )));
+    }
+    if array.null_count() > 0 {
+        return Err(Error::Other(
+            "cannot import a struct array with null rows as a record batch".into(),
+        ));
+    }
+    Ok(RecordBatch::from(StructArray::from(array.to_data())))
+}
+
+/// Export an Arrow array to R as a `nanoarrow_array`.
+pub fn export_array(array: &dyn Array) -> Result<Robj> {
+    let (ffi_array, ffi_schema) = to_ffi(&array.to_data()).map_err(arrow_error)?;
+
+    // Let {nanoarrow} allocate the structs so that its finalizers release
+    // and free them.
+    let array = nanoarrow("nanoarrow_allocate_array")?.call(pairlist!())?;
+    let schema = nanoarrow("nanoarrow_allocate_schema")?.call(pairlist!())?;
+    unsafe {
+        std::ptr::write(pointer_of(&array)? as *mut FFI_ArrowArray, ffi_array);
+        std::ptr::write(pointer_of(&schema)? as *mut FFI_ArrowSchema, ffi_schema);
+    }
+    nanoarrow("nanoarrow_array_set_schema")?.call(pairlist!(array.clone(), schema))?;
+    Ok(array)
+}
+
+/// Export a [`RecordBatch`] to R as a `nanoarrow_array` of struct type,
+/// which `as.data.frame()` or `arrow::as_record_batch()` accept.
+pub fn export_record_batch(batch: RecordBatch) -> Result<Robj> {
+    export_array(&StructArray::from(batch))
+}
@@ -0,0 +1,313 @@
+//! A typed view of R expressions for analysis and rewriting.
+//!
+//! Parsed R code is a tree of calls, symbols and constants stored as
+//! pairlists. [`Expr`] mirrors that tree with plain Rust types so that tools
+//! can pattern match on it, walk it with a [`Visitor`] and rewrite it with
+//! [`Expr::transform()`], then turn the result back into a language object.
+//!
+//! ```ignore
+//! // Spell out the abbreviation `T`.
+//! let expr = Expr::parse_one("if (T) f(T)")?.transform(&mut |e| match e {
+//!     Expr::Symbol(s) if s == "T" => Expr::Constant(r!(true)),
+//!     e => e,
+//! });
+//! ```
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use std::collections::BTreeSet;
+
+use crate::deparse::{deparse_with, DeparseOptions};
+use crate::lookup::base_function;
+
+/// An R expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    /// A constant such as `1`, `"a"`, `TRUE` or `NULL`.
+    Constant(Robj),
+    /// A name such as `x` or `mean`.
+    Symbol(String),
+    /// The empty argument in `x[, 1]`, or a formal without a default.
+    Missing,
+    /// A call of the function in the first field with the given arguments.
+    Call(Box<Expr>, Vec<Arg>),
+    /// `function(formals) body`. Source references are not kept.
+    Function(Vec<Arg>, Box<Expr>),
+}
+
+/// A possibly named argument of a call, or a formal of a function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Arg {
+    pub name: Option<String>,
+    pub value: Expr,
+}
+
+impl Arg {
+    pub fn positional(value: Expr) -> Self {
+        Self { name: None, value }
+    }
+
+    pub fn named(name: impl Into<String>, value: Expr) -> Self {
+        Self {
+            name: Some(name.into()),
+            value,
+        }
+    }
+}
+
+/// Callbacks for [`Expr::walk()`].
+pub trait Visitor {
+    /// Called before the children of `expr` are visited. Return `false` to
+    /// skip them.
+    fn enter(&mut self, expr: &Expr) -> bool {
+        let _ = expr;
+        true
+    }
+
+    /// Called after the children of `expr` have been visited.
+    fn leave(&mut self, expr: &Expr) {
+        let _ = expr;
+    }
+}
+
+impl Expr {
+    /// Convert a language object, symbol or constant.
+    pub fn from_robj(robj: &Robj) -> Result<Self> {
+        if robj.is_missing_arg() {
+            return Ok(Expr::Missing);
+        }
+        if let Some(sym) = robj.as_symbol() {
+            return Ok(Expr::Symbol(sym.as_str().to_string()));
+        }
+        let call = match robj.as_language() {
+            Some(call) => call,
+            None => return Ok(Expr::Constant(robj.clone())),
+        };
+
+        let mut parts = call.iter();
+        let head = match parts.next() {
+            Some((_, head)) => Expr::from_robj(&head)?,
+            None => return Err(Error::Other("empty call".into())),
+        };
+        if head == Expr::Symbol("function".into()) {
+            let formals = parts.next().map(|(_, f)| f).unwrap_or_else(|| r!(NULL));
+            let body = parts.next().map(|(_, b)| b).unwrap_or_else(|| r!(NULL));
+            let formals = match formals.as_pairlist() {
+                Some(formals) => formals
+                    .iter()
+                    .map(|(name, value)| Ok(Arg::named(name, Expr::from_robj(&value)?)))
+                    .collect::<Result<Vec<_>>>()?,
+                None => Vec::new(),
+            };
+            return Ok(Expr::Function(formals, Box::new(Expr::from_robj(&body)?)));
+        }
+
+        let args = parts
+            .map(|(name, value)| {
+                Ok(Arg {
+                    name: (!name.is_empty()).then(|| name.to_string()),
+                    value: Expr::from_robj(&value)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Expr::Call(Box::new(head), args))
+    }
+
+    /// Parse R code into one `Expr` per top-level expression.
+    pub fn parse(code: &str) -> Result<Vec<Self>> {
+        parse(code)?
+            .values()
+            .map(|expr| Expr::from_robj(&expr))
+            .collect()
+    }
+
+    /// Parse R code that must contain exactly one expression.
+    pub fn parse_one(code: &str) -> Result<Self> {
+        let mut exprs = Self::parse(code)?;
+        if exprs.len() != 1 {
+            return Err(Error::Other(format!(
+                "expected a single R expression, got {}",
+                exprs.len()
+            )));
+        }
+        Ok(exprs.remove(0))
+    }
+
+    /// Build the equivalent R object, ready to be evaluated.
+    pub fn to_robj(&self) -> Result<Robj> {
+        match self {
+            Expr::Constant(value) => Ok(value.clone()),
+            Expr::Symbol(name) => Ok(Symbol::from_string(name).into()),
+            Expr::Missing => Ok(missing_arg().into()),
+            Expr::Call(fun, args) => {
+                let mut pairs = vec![(String::new(), fun.to_robj()?)];
+                for arg in args {
+                    pairs.push((arg.name.clone().unwrap_or_default(), arg.value.to_robj()?));
+                }
+                call_from_pairs(pairs)
+            }
+            Expr::Function(formals, body) => {
+                let formals: Robj = if formals.is_empty() {
+                    r!(NULL)
+                } else {
+                    let pairs = formals
+                        .iter()
+                        .map(|arg| Ok((arg.name.clone().unwrap_or_default(), arg.value.to_robj()?)))
+                        .collect::<Result<Vec<_>>>()?;
+                    Pairlist::from_pairs(pairs).into()
+                };
+                call_from_pairs(vec![
+                    (String::new(), sym!(function)),
+                    (String::new(), formals),
+                    (String::new(), body.to_robj()?),
+                ])
+            }
+        }
+    }
+
+    /// Deparse into R code, for example to show a rewritten expression.
+    pub fn deparse(&self, options: &DeparseOptions) -> Result<String> {
+        deparse_with(&self.to_robj()?, options)
+    }
+
+    pub fn symbol(name: impl Into<String>) -> Self {
+        Expr::Symbol(name.into())
+    }
+
+    /// A call of the function `name` with positional arguments.
+    pub fn call(name: impl Into<String>, args: impl IntoIterator<Item = Expr>) -> Self {
+        Expr::Call(
+            Box::new(Expr::symbol(name)),
+            args.into_iter().map(Arg::positional).collect(),
+        )
+    }
+
+    /// The name of the called function, if this is a call of a named
+    /// function rather than, say, `f()()`.
+    pub fn fun_name(&self) -> Option<&str> {
+        match self {
+            Expr::Call(fun, _) => match fun.as_ref() {
+                Expr::Symbol(name) => Some(name),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn is_call_to(&self, name: &str) -> bool {
+        self.fun_name() == Some(name)
+    }
+
+    /// The direct subexpressions: the function and arguments of a call, or
+    /// the defaults and body of a function.
+    pub fn children(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Call(fun, args) => std::iter::once(fun.as_ref())
+                .chain(args.iter().map(|arg| &arg.value))
+                .collect(),
+            Expr::Function(formals, body) => formals
+                .iter()
+                .map(|arg| &arg.value)
+                .chain(std::iter::once(body.as_ref()))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Visit this expression and its subexpressions depth first.
+    pub fn walk(&self, visitor: &mut impl Visitor) {
+        if visitor.enter(self) {
+            for child in self.children() {
+                child.walk(visitor);
+            }
+        }
+        visitor.leave(self);
+    }
+
+    /// Call `f` on this expression and every subexpression, parents first.
+    pub fn for_each(&self, f: &mut impl FnMut(&Expr)) {
+        f(self);
+        for child in self.children() {
+            child.for_each(f);
+        }
+    }
+
+    /// Rebuild the tree bottom-up, replacing each node by `f(node)` after its
+    /// children have been transformed.
+    pub fn transform(self, f: &mut impl FnMut(Expr) -> Expr) -> Expr {
+        let node = match self {
+            Expr::Call(fun, args) => Expr::Call(
+                Box::new(fun.transform(f)),
+                args.into_iter()
+                    .map(|arg| Arg {
+                        name: arg.name,
+                        value: arg.value.transform(f),
+                    })
+                    .collect(),
+            ),
+            Expr::Function(formals, body) => Expr::Function(
+                formals
+                    .into_iter()
+                    .map(|arg| Arg {
+                        name: arg.name,
+                        value: arg.value.transform(f),
+                    })
+                    .collect(),
+                Box::new(body.transform(f)),
+            ),
+            leaf => leaf,
+        };
+        f(node)
+    }
+
+    /// Names of all functions called anywhere in the expression, including
+    /// `function` for function definitions.
+    pub fn calls(&self) -> BTreeSet<String> {
+        let mut calls = BTreeSet::new();
+        self.for_each(&mut |expr| match expr {
+            Expr::Function(..) => {
+                calls.insert("function".to_string());
+            }
+            _ => {
+                if let Some(name) = expr.fun_name() {
+                    calls.insert(name.to_string());
+                }
+            }
+        });
+        calls
+    }
+
+    /// Names used as values anywhere in the expression, i.e. symbols other
+    /// than those in function position.
+    pub fn symbols(&self) -> BTreeSet<String> {
+        fn collect(expr: &Expr, symbols: &mut BTreeSet<String>) {
+            match expr {
+                Expr::Symbol(name) => {
+                    symbols.insert(name.clone());
+                }
+                Expr::Call(fun, args) => {
+                    if !matches!(fun.as_ref(), Expr::Symbol(_)) {
+                        collect(fun, symbols);
+                    }
+                    for arg in args {
+                        collect(&arg.value, symbols);
+                    }
+                }
+                Expr::Function(..) => {
+                    for child in expr.children() {
+                        collect(child, symbols);
+                    }
+                }
+                Expr::Constant(_) | Expr::Missing => {}
+            }
+        }
+        let mut symbols = BTreeSet::new();
+        collect(self, &mut symbols);
+        symbols
+    }
+}
+
+/// Build a call from (name, value) pairs, the first being the function.
+pub(crate) fn call_from_pairs(pairs: Vec<(String, Robj)>) -> Result<Robj> {
+    base_function("as.call")?.call(pairlist!(Pairlist::from_pairs(pairs)))
+}
@@ -0,0 +1,95 @@
+//! Attribute access by name.
+//!
+//! extendr's [`Attributes`] trait takes attribute names as symbols and
+//! covers `names`, `class`, `dim` and `levels` for reading. [`AttribExt`]
+//! adds access by plain name, and the missing setters for `dim` and
+//! `levels`. Names are interned with `Rf_install()`, which finds a symbol
+//! already in R's symbol table by its hash.
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+/// Attribute accessors by name.
+pub trait AttribExt {
+    /// The attribute `name`, or `None` if it is not set.
+    fn attr(&self, name: &str) -> Option<Robj>;
+
+    /// Set the attribute `name`. Setting it to `NULL` removes it.
+    fn set_attr(&mut self, name: &str, value: impl Into<Robj>) -> Result<&mut Self>;
+
+    fn remove_attr(&mut self, name: &str) -> Result<&mut Self> {
+        self.set_attr(name, r!(NULL))
+    }
+
+    /// The `class` attribute, empty if unset.
+    fn class_vec(&self) -> Vec<String> {
+        self.attr("class")
+            .and_then(|class| class.as_string_vector())
+            .unwrap_or_default()
+    }
+
+    /// The `dim` attribute as Rust sizes, `None` if it is unset. A
+    /// dimension that is `NA`, negative or not a whole number is an error.
+    fn dims(&self) -> Result<Option<Vec<usize>>> {
+        let dim = match self.attr("dim") {
+            Some(dim) => dim,
+            None => return Ok(None),
+        };
+        let dim: Vec<f64> = if let Some(dim) = dim.as_integer_slice() {
+            dim.iter()
+                .map(|&d| if d.is_na() { f64::NAN } else { d.into() })
+                .collect()
+        } else if let Some(dim) = dim.as_real_slice() {
+            dim.to_vec()
+        } else {
+            return Err(Error::Other("the `dim` attribute is not numeric".into()));
+        };
+        let dims = dim
+            .into_iter()
+            .map(|d| match d {
+                d if d.is_nan() => Err(Error::Other("`dim` contains NA".into())),
+                d if d < 0.0 || d.fract() != 0.0 || d > usize::MAX as f64 => {
+                    Err(Error::Other(format!("invalid dimension {d}")))
+                }
+                d => Ok(d as usize),
+            })
+            .collect::<Result<_>>()?;
+        Ok(Some(dims))
+    }
+
+    /// Set the `dim` attribute; the product must equal the length.
+    fn set_dim(&mut self, dim: &[usize]) -> Result<&mut Self> {
+        let dim = dim
+            .iter()
+            .map(|&d| {
+                i32::try_from(d)
+                    .map_err(|_| Error::Other(format!("dimension {d} is too large for R")))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.set_attr("dim", dim)
+    }
+
+    /// Set the `levels` attribute, as for a factor.
+    fn set_levels(&mut self, levels: &[&str]) -> Result<&mut Self> {
+        self.set_attr("levels", levels)
+    }
+}
+
+impl AttribExt for Robj {
+    fn attr(&self, name: &str) -> Option<Robj> {
+        self.get_attrib(Symbol::from_string(name))
+    }
+
+    fn set_attr(&mut self, name: &str, value: impl Into<Robj>) -> Result<&mut Self> {
+        self.set_attrib(Symbol::from_string(name), value)
+    }
+}
+
+/// Set the `row.names` attribute of the data frame `df` to the compact form
+/// for `n` rows, `c(NA_integer_, -n)`.
+pub fn set_compact_row_names(df: &mut Robj, n: usize) -> Result<()> {
+    let n = i32::try_from(n)
+        .map_err(|_| Error::Other(format!("{n} rows are too many for a data frame")))?;
+    df.set_attr("row.names", [i32::MIN, -n])?;
+    Ok(())
+}
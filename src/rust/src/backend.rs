@@ -0,0 +1,55 @@
+//! The libR implementation of [`Backend`].
+//!
+//! Code written against [`Backend`] builds R objects in the running R
+//! session with [`LibR`], and runs on other engines, including the
+//! [`Mock`](helloextendr_core::backend::Mock) of the core crate, unchanged.
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+use crate::attrib::AttribExt;
+use crate::encoding::Utf8Strings;
+use crate::raw_io::IntoRaw;
+
+pub use helloextendr_core::backend::{data_frame, Backend, VectorData};
+
+/// The R session the package is loaded in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LibR;
+
+impl Backend for LibR {
+    type Value = Robj;
+    type Error = Error;
+
+    fn alloc_vector(&self, data: VectorData<Robj>) -> Result<Robj> {
+        Ok(match data {
+            VectorData::Logical(values) => Logicals::from_values(
+                values
+                    .into_iter()
+                    .map(|value| value.map_or(Rbool::na(), Rbool::from)),
+            )
+            .into(),
+            VectorData::Integer(values) => Integers::from_values(values).into(),
+            VectorData::Double(values) => Doubles::from_values(values).into(),
+            VectorData::Character(values) => {
+                Utf8Strings::from_values(values.iter().map(Option::as_deref)).into()
+            }
+            VectorData::Raw(values) => values.into_raw().into(),
+            VectorData::List(values) => List::from_values(values).into(),
+        })
+    }
+
+    fn set_attr(&self, x: &Robj, name: &str, value: Robj) -> Result<()> {
+        // A clone refers to the same object.
+        x.clone().set_attr(name, value)?;
+        Ok(())
+    }
+
+    fn eval(&self, code: &str) -> Result<Robj> {
+        eval_string(code)
+    }
+
+    fn error(&self, message: String) -> Error {
+        Error::Other(message)
+    }
+}
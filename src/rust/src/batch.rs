@@ -0,0 +1,250 @@
+//! Batched invocation of R callbacks.
+//!
+//! Map-style functions that take an R callback usually call it once per
+//! element, paying the cost of crossing into R every time. In
+//! [`CallbackMode::Batched`] mode the callback instead receives slices of the
+//! input and must return one value per element it was given.
+//!
+//! [`ListMapExt::map_r()`] is `lapply()` for Rust code: it applies an R
+//! function to the elements of a list, running batches of calls in a
+//! single evaluation, and turns the R errors of the calls into an
+//! [`Error`] instead of a jump out of the Rust code, either at the first
+//! failing element or after all of them ran.
+
+use std::cell::RefCell;
+use std::sync::Once;
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+use crate::r_module;
+use crate::teardown;
+use crate::xlen::{index_to_robj, robj_to_length};
+
+/// How often [`map_callback_with`] invokes the R callback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallbackMode {
+    /// Call the function once for every element, like `lapply()`.
+    PerElement,
+    /// Call the function once for every run of at most this many elements.
+    Batched(usize),
+}
+
+impl CallbackMode {
+    /// Interpret an R-side `batch_size` argument, where `NULL` means per element.
+    pub fn from_batch_size(batch_size: &Robj) -> Result<Self> {
+        if batch_size.is_null() {
+            return Ok(CallbackMode::PerElement);
+        }
+        match robj_to_length(batch_size) {
+            Ok(n) if n >= 1 => Ok(CallbackMode::Batched(n)),
+            _ => Err(Error::Other(
+                "`batch_size` must be NULL or a positive whole number".into(),
+            )),
+        }
+    }
+
+    fn step(self) -> usize {
+        match self {
+            CallbackMode::PerElement => 1,
+            CallbackMode::Batched(n) => n.max(1),
+        }
+    }
+}
+
+/// Apply the R function `f` to the elements of `x` and combine the results
+/// with `c()`.
+///
+/// `f` must return exactly one value for each element it receives. In
+/// per-element mode list elements are passed unwrapped (`x[[i]]`), otherwise
+/// `f` gets sub-vectors (`x[i:j]`) that keep the attributes of `x`.
+pub fn map_callback_with(x: &Robj, f: &Robj, mode: CallbackMode) -> Result<Robj> {
+    let len = x.len();
+    let step = mode.step();
+    let mut results = Vec::with_capacity(len.div_ceil(step));
+    let mut start = 0;
+    while start < len {
+        let n = step.min(len - start);
+        let chunk = extract(x, start, n, mode)?;
+        let out = Robj::from(Language::from_values([f.clone(), chunk])).eval()?;
+        if out.len() != n {
+            return Err(Error::Other(format!(
+                "callback returned {} values for {} elements",
+                out.len(),
+                n
+            )));
+        }
+        results.push(out);
+        start += n;
+    }
+    combine(results)
+}
+
+/// Subset `n` elements of `x` starting at the zero-based position `start`.
+fn extract(x: &Robj, start: usize, n: usize, mode: CallbackMode) -> Result<Robj> {
+    // Indices are passed as doubles so that long vectors stay addressable.
+    if mode == CallbackMode::PerElement && x.is_list() {
+        return lang!("[[", x.clone(), index_to_robj(start)).eval();
+    }
+    let index = Doubles::from_values((start + 1..start + n + 1).map(|i| i as f64));
+    lang!("[", x.clone(), index).eval()
+}
+
+fn combine(results: Vec<Robj>) -> Result<Robj> {
+    if results.is_empty() {
+        return Ok(r!(NULL));
+    }
+    Robj::from(Language::from_values(
+        std::iter::once(sym!(c)).chain(results),
+    ))
+    .eval()
+}
+
+/// Elements [`ListMapExt::map_r()`] passes to the function in one
+/// evaluation.
+const MAP_BATCH: usize = 256;
+
+/// Errors reported by name beyond which [`ListMapExt::map_r()`] only counts
+/// them.
+const MAX_REPORTED: usize = 10;
+
+/// What [`ListMapExt::map_r()`] does when the function fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Stop at the first error, without calling the function on the
+    /// elements after it.
+    FailFast,
+    /// Call the function on every element, then report all errors.
+    Collect,
+}
+
+/// Call `f` on the elements of `x` in turn, each result in a list of length
+/// one or, if the call failed, the error condition. With `fail_fast`, the
+/// elements after the first error are `NULL`.
+const MAP_BATCH_FN: &str = "function(x, f, fail_fast) {
+    out <- vector(\"list\", length(x))
+    for (i in seq_along(x)) {
+        out[[i]] <- tryCatch(list(f(x[[i]])), error = function(e) e)
+        if (fail_fast && inherits(out[[i]], \"error\")) break
+    }
+    out
+}";
+
+thread_local! {
+    // `MAP_BATCH_FN`, parsed on first use. Only ever touched from the main
+    // thread.
+    static BATCH_FN: RefCell<Option<Function>> = const { RefCell::new(None) };
+}
+
+/// The function of `MAP_BATCH_FN`, parsed once and released when the
+/// package is unloaded.
+fn batch_fn() -> Result<Function> {
+    if let Some(f) = BATCH_FN.with(|f| f.borrow().clone()) {
+        return Ok(f);
+    }
+    let f: Function = lang!("parse", text = MAP_BATCH_FN)
+        .eval()?
+        .as_expressions()
+        .and_then(|exprs| exprs.values().next())
+        .ok_or_else(|| Error::Other("cannot parse the batch function".into()))?
+        .eval_with_env(&base_env())?
+        .try_into()?;
+    static TEARDOWN: Once = Once::new();
+    TEARDOWN.call_once(|| {
+        teardown::on_unload("batch function", || {
+            let _ = BATCH_FN.try_with(|f| f.borrow_mut().take());
+        })
+    });
+    BATCH_FN.with(|cached| *cached.borrow_mut() = Some(f.clone()));
+    Ok(f)
+}
+
+/// Mapping R functions over lists.
+pub trait ListMapExt {
+    /// Apply `fun` to each element, like `lapply()`, and return the results
+    /// under the names of the list. An error of `fun` on any element is an
+    /// error of the whole map, reported with the position of the element.
+    fn map_r(&self, fun: &Function, policy: ErrorPolicy) -> Result<List>;
+}
+
+impl ListMapExt for List {
+    fn map_r(&self, fun: &Function, policy: ErrorPolicy) -> Result<List> {
+        let batch_fn = batch_fn()?;
+        let fail_fast = policy == ErrorPolicy::FailFast;
+        let len = self.len();
+        let mut values = Vec::with_capacity(len);
+        let mut errors = Vec::new();
+        let mut start = 0;
+        while start < len {
+            let n = MAP_BATCH.min(len - start);
+            let chunk = List::from_values(self.values().skip(start).take(n));
+            let out: List = batch_fn
+                .call(pairlist!(chunk, fun.clone(), fail_fast))?
+                .try_into()?;
+            for (i, result) in out.values().enumerate() {
+                if result.inherits("error") {
+                    let message = lang!("conditionMessage", result).eval()?;
+                    errors.push((start + i, message.as_str().unwrap_or("").to_string()));
+                    values.push(r!(NULL));
+                } else if let Some(value) = result.as_list().and_then(|v| v.values().next()) {
+                    values.push(value);
+                }
+            }
+            start += n;
+            if fail_fast && !errors.is_empty() {
+                break;
+            }
+        }
+        if !errors.is_empty() {
+            return Err(Error::Other(map_errors(self, &errors, policy)));
+        }
+        match self.names() {
+            Some(names) => List::from_names_and_values(names, values),
+            None => Ok(List::from_values(values)),
+        }
+    }
+}
+
+fn map_errors(x: &List, errors: &[(usize, String)], policy: ErrorPolicy) -> String {
+    let names: Vec<&str> = x.names().map(|names| names.collect()).unwrap_or_default();
+    let describe = |(i, message): &(usize, String)| match names.get(*i) {
+        Some(name) if !name.is_empty() => format!("element {} (`{name}`): {message}", i + 1),
+        _ => format!("element {}: {message}", i + 1),
+    };
+    if policy == ErrorPolicy::FailFast {
+        return format!("the function failed on {}", describe(&errors[0]));
+    }
+    let mut report = format!(
+        "the function failed on {} of {} elements:",
+        errors.len(),
+        x.len()
+    );
+    for error in errors.iter().take(MAX_REPORTED) {
+        report.push_str("\n  ");
+        report.push_str(&describe(error));
+    }
+    if errors.len() > MAX_REPORTED {
+        report.push_str(&format!("\n  and {} more", errors.len() - MAX_REPORTED));
+    }
+    report
+}
+
+/// Apply an R function over a vector, optionally in batches.
+///
+/// With `batch_size = NULL` the function is called once per element of `x`.
+/// Otherwise it receives `x` in slices of at most `batch_size` elements and
+/// must return one value per element, which greatly reduces call overhead.
+/// @param x A vector or list.
+/// @param f A function returning one value per element of its argument.
+/// @param batch_size `NULL` or a positive whole number of elements per call.
+/// @return The results of all calls combined with `c()`.
+/// @export
+#[extendr]
+fn map_callback(x: Robj, f: Robj, #[extendr(default = "NULL")] batch_size: Robj) -> Result<Robj> {
+    map_callback_with(&x, &f, CallbackMode::from_batch_size(&batch_size)?)
+}
+
+r_module! {
+    mod batch;
+    fn map_callback;
+}
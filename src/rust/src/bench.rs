@@ -0,0 +1,225 @@
+//! Entry points for measuring the cost of calling into Rust.
+//!
+//! `noop()` does nothing, `echo()` returns its argument untouched and the
+//! `roundtrip_*()` functions copy a vector into Rust-owned memory and build
+//! a new R vector from it, which is what most exported functions do with
+//! their arguments and results. Timing them against an empty R closure
+//! separates the cost of `.Call()` and of argument conversion from the cost
+//! of the work a function does. [`measure()`] times repeated evaluation of
+//! a call, and `bench_overhead()` reports it for all of them.
+
+use std::time::Instant;
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+use crate::attrib::{set_compact_row_names, AttribExt};
+use crate::encoding::Utf8Strings;
+use crate::parallel::interrupt_pending;
+use crate::r_module;
+use crate::raw_io::IntoRaw;
+use crate::xlen::robj_to_length;
+
+/// Calls timed together, so that the clock is read rarely compared to the
+/// duration of a call.
+const BATCH: usize = 10;
+
+/// Per-call durations in nanoseconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timing {
+    pub min: f64,
+    pub median: f64,
+    pub mean: f64,
+}
+
+/// Evaluate `call` in `env` about `iterations` times, in batches of ten,
+/// after one untimed call, and summarise the time per call of the batches.
+pub fn measure(call: &Robj, env: &Environment, iterations: usize) -> Result<Timing> {
+    call.eval_with_env(env)?;
+    let batches = iterations.div_ceil(BATCH).max(1);
+    let mut samples = Vec::with_capacity(batches);
+    for _ in 0..batches {
+        let start = Instant::now();
+        for _ in 0..BATCH {
+            call.eval_with_env(env)?;
+        }
+        samples.push(start.elapsed().as_nanos() as f64 / BATCH as f64);
+    }
+    samples.sort_by(f64::total_cmp);
+    let n = samples.len();
+    let median = if n % 2 == 1 {
+        samples[n / 2]
+    } else {
+        (samples[n / 2 - 1] + samples[n / 2]) / 2.0
+    };
+    Ok(Timing {
+        min: samples[0],
+        median,
+        mean: samples.iter().sum::<f64>() / n as f64,
+    })
+}
+
+/// Call overhead benchmarks
+///
+/// `noop()` does nothing and `echo()` returns `x` as it is, so timing them
+/// measures the cost of calling into Rust. The `roundtrip_*()` functions
+/// copy a vector of their type into Rust and return a new vector built from
+/// the copy, without attributes other than list names, which adds the cost
+/// of converting arguments and results. `bench_overhead()` times them all
+/// and an empty R function for comparison.
+/// @param x A value; for the `roundtrip_*()` functions, a vector of the
+///   type in the name.
+/// @return `noop()` returns `NULL`, `echo()` returns `x` and the
+///   `roundtrip_*()` functions a copy of `x`.
+/// @export
+#[extendr]
+fn noop() {}
+
+/// @rdname noop
+/// @export
+#[extendr]
+fn echo(x: Robj) -> Robj {
+    x
+}
+
+/// @rdname noop
+/// @export
+#[extendr]
+fn roundtrip_logical(x: Logicals) -> Logicals {
+    let values: Vec<Rbool> = x.iter().collect();
+    Logicals::from_values(values)
+}
+
+/// @rdname noop
+/// @export
+#[extendr]
+fn roundtrip_integer(x: Integers) -> Integers {
+    let values: Vec<Rint> = x.iter().collect();
+    Integers::from_values(values)
+}
+
+/// @rdname noop
+/// @export
+#[extendr]
+fn roundtrip_double(x: Doubles) -> Doubles {
+    let values: Vec<Rfloat> = x.iter().collect();
+    Doubles::from_values(values)
+}
+
+/// @rdname noop
+/// @export
+#[extendr]
+fn roundtrip_character(x: Robj) -> Result<Robj> {
+    let values = Utf8Strings::try_from(x)?.to_vec()?;
+    Ok(Utf8Strings::from_values(values.iter().map(Option::as_deref)).into())
+}
+
+/// @rdname noop
+/// @export
+#[extendr]
+fn roundtrip_raw(x: Raw) -> Raw {
+    x.as_slice().to_vec().into_raw()
+}
+
+/// @rdname noop
+/// @export
+#[extendr]
+fn roundtrip_list(x: List) -> Result<Robj> {
+    let values: Vec<Robj> = x.values().collect();
+    Ok(match x.names() {
+        Some(names) => List::from_names_and_values(names, values)?.into(),
+        None => List::from_values(values).into(),
+    })
+}
+
+/// @rdname noop
+/// @param iterations The number of calls to time for each function.
+/// @param size The length of the vectors passed to `echo()` and the
+///   `roundtrip_*()` functions.
+/// @return `bench_overhead()` returns a data frame with the `call` timed and
+///   the `min_ns`, `median_ns` and `mean_ns` nanoseconds per call, over
+///   batches of ten calls.
+/// @export
+#[extendr]
+fn bench_overhead(
+    #[extendr(default = "10000L")] iterations: Robj,
+    #[extendr(default = "1000L")] size: Robj,
+) -> Result<Robj> {
+    let iterations = robj_to_length(&iterations)?;
+    let size = robj_to_length(&size)?;
+    let ns: Environment = lang!("asNamespace", "helloextendr").eval()?.try_into()?;
+    let env = Environment::new_with_parent(ns);
+    let r_noop = lang!("parse", text = "function() NULL")
+        .eval()?
+        .as_expressions()
+        .and_then(|exprs| exprs.values().next())
+        .ok_or_else(|| Error::Other("cannot parse the R baseline".into()))?
+        .eval_with_env(&env)?;
+    env.set_local(sym!(r_noop), r_noop);
+
+    let bytes: Vec<u8> = (0..size).map(|i| i as u8).collect();
+    let doubles: Robj = Doubles::from_values((0..size).map(|i| i as f64)).into();
+    let inputs: Vec<(&str, Option<Robj>)> = vec![
+        ("r_noop", None),
+        ("noop", None),
+        ("echo", Some(doubles.clone())),
+        (
+            "roundtrip_logical",
+            Some(Logicals::from_values((0..size).map(|i| i % 2 == 0)).into()),
+        ),
+        (
+            "roundtrip_integer",
+            Some(Integers::from_values((0..size).map(|i| i as i32)).into()),
+        ),
+        ("roundtrip_double", Some(doubles)),
+        (
+            "roundtrip_character",
+            Some(Strings::from_values((0..size).map(|i| format!("s{i}"))).into()),
+        ),
+        ("roundtrip_raw", Some(bytes.into_raw().into())),
+        (
+            "roundtrip_list",
+            Some(List::from_values((0..size).map(|i| r!(i as f64))).into()),
+        ),
+    ];
+
+    let mut calls = Vec::with_capacity(inputs.len());
+    let mut timings = Vec::with_capacity(inputs.len());
+    for (name, x) in inputs {
+        if interrupt_pending() {
+            return Err(Error::Other("interrupted by the user".into()));
+        }
+        let f: Robj = Symbol::from_string(name).into();
+        let (call, label): (Robj, _) = match x {
+            Some(x) => (Language::from_values([f, x]).into(), format!("{name}(x)")),
+            None => (Language::from_values([f]).into(), format!("{name}()")),
+        };
+        timings.push(measure(&call, &env, iterations)?);
+        calls.push(label);
+    }
+
+    let columns: [Robj; 4] = [
+        Strings::from_values(calls.iter().map(String::as_str)).into(),
+        Doubles::from_values(timings.iter().map(|t| t.min)).into(),
+        Doubles::from_values(timings.iter().map(|t| t.median)).into(),
+        Doubles::from_values(timings.iter().map(|t| t.mean)).into(),
+    ];
+    let mut df: Robj =
+        List::from_names_and_values(["call", "min_ns", "median_ns", "mean_ns"], columns)?.into();
+    set_compact_row_names(&mut df, timings.len())?;
+    df.set_attr("class", "data.frame")?;
+    Ok(df)
+}
+
+r_module! {
+    mod bench;
+    fn noop;
+    fn echo;
+    fn roundtrip_logical;
+    fn roundtrip_integer;
+    fn roundtrip_double;
+    fn roundtrip_character;
+    fn roundtrip_raw;
+    fn roundtrip_list;
+    fn bench_overhead;
+}
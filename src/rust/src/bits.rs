@@ -0,0 +1,272 @@
+//! Bit sets and Bloom filters over R vectors.
+//!
+//! A [`BitSet`] stores one bit per position, 64 to a word, so a set of ids
+//! up to a billion takes 125 MB rather than the 4 GB of a logical vector.
+//! A [`BloomFilter`] answers whether a value may have been added in a fixed
+//! number of bits, with false positives at a chosen rate but never false
+//! negatives, which makes it a cheap first test before an exact lookup.
+//!
+//! Values are hashed from their bytes in Rust rather than through R's
+//! string hashing, and whole numbers hash the same whether they come as
+//! integers or doubles, so `1L` and `1` are the same id.
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+use crate::attrib::AttribExt;
+use crate::encoding::RstrEncoding;
+use crate::persist::{self, Persistable};
+use crate::r_module;
+use crate::xlen::{length_to_robj, robj_to_length};
+
+pub use helloextendr_core::bloom::{BloomFilter, Key};
+pub use helloextendr_core::BitSet;
+
+/// The R class of the handles returned by `bitset_new()`.
+const BITSET_CLASS: &str = "helloextendr_bitset";
+
+/// The R class of the handles returned by `bloom_new()`.
+const BLOOM_CLASS: &str = "helloextendr_bloom";
+
+impl Persistable for BitSet {
+    const TAG: &'static str = "helloextendr::BitSet/1";
+
+    fn to_bytes(&self) -> Vec<u8> {
+        BitSet::to_bytes(self)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        BitSet::from_bytes(bytes).map_err(|e| Error::Other(e.to_string()))
+    }
+}
+
+impl Persistable for BloomFilter {
+    const TAG: &'static str = "helloextendr::BloomFilter/1";
+
+    fn to_bytes(&self) -> Vec<u8> {
+        BloomFilter::to_bytes(self)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        BloomFilter::from_bytes(bytes).map_err(|e| Error::Other(e.to_string()))
+    }
+}
+
+/// The elements of an integer, double or character vector as keys, `None`
+/// for `NA`.
+fn keys(x: &Robj) -> Result<Vec<Option<Key>>> {
+    if let Some(x) = x.as_integer_slice() {
+        Ok(x.iter()
+            .map(|&v| Option::<i32>::from(Rint::from(v)).map(|v| Key::Whole(v.into())))
+            .collect())
+    } else if let Some(x) = x.as_real_slice() {
+        Ok(x.iter()
+            .map(|&v| (!v.is_nan()).then(|| Key::from_double(v)))
+            .collect())
+    } else if x.is_string() {
+        Ok(Strings::try_from(x.clone())?
+            .iter()
+            .map(|s| {
+                if s.is_na() {
+                    Ok(None)
+                } else {
+                    Ok(Some(Key::String(s.to_utf8()?.into_owned())))
+                }
+            })
+            .collect::<Result<_>>()?)
+    } else {
+        Err(Error::Other(
+            "expected an integer, double or character vector".into(),
+        ))
+    }
+}
+
+/// One-based positions as zero-based ones, `None` for `NA`, checked against
+/// the size of `bits`.
+fn positions(i: &Robj, bits: &BitSet) -> Result<Vec<Option<usize>>> {
+    let values: Vec<Option<f64>> = if let Some(i) = i.as_integer_slice() {
+        i.iter()
+            .map(|&v| Option::<i32>::from(Rint::from(v)).map(f64::from))
+            .collect()
+    } else if let Some(i) = i.as_real_slice() {
+        i.iter().map(|&v| (!v.is_nan()).then_some(v)).collect()
+    } else {
+        return Err(Error::Other("`i` must be numeric".into()));
+    };
+    values
+        .into_iter()
+        .map(|v| match v {
+            None => Ok(None),
+            Some(v) if v >= 1.0 && v.fract() == 0.0 && v <= bits.len() as f64 => {
+                Ok(Some(v as usize - 1))
+            }
+            Some(v) => Err(Error::Other(format!(
+                "position {v} is outside a bit set of {}",
+                bits.len()
+            ))),
+        })
+        .collect()
+}
+
+fn bitset_mut(b: &mut Robj) -> Result<&mut BitSet> {
+    if !b.inherits(BITSET_CLASS) {
+        return Err(Error::Other(
+            "expected a bit set from `bitset_new()`".into(),
+        ));
+    }
+    persist::get_mut(b)
+}
+
+fn bloom_mut(f: &mut Robj) -> Result<&mut BloomFilter> {
+    if !f.inherits(BLOOM_CLASS) {
+        return Err(Error::Other(
+            "expected a Bloom filter from `bloom_new()`".into(),
+        ));
+    }
+    persist::get_mut(f)
+}
+
+/// A compact set of positions
+///
+/// `bitset_new()` creates `size` bits, all unset, which take an eighth of
+/// a byte each. Positions are one-based, as in R. Bit sets can be saved
+/// with `saveRDS()` and used again after `readRDS()`.
+/// @param size The number of bits.
+/// @param b A bit set created by `bitset_new()`.
+/// @param i A numeric vector of positions.
+/// @param value Whether to set or unset the bits.
+/// @return `bitset_new()` returns a bit set and `bitset_set()` the bit set,
+///   invisibly. `bitset_test()` returns whether each bit is set, `NA` for
+///   `NA` positions, `bitset_count()` the number of bits set,
+///   `bitset_which()` their positions and `bitset_size()` the number of
+///   bits.
+/// @export
+#[extendr]
+fn bitset_new(size: Robj) -> Result<Robj> {
+    let mut handle = persist::new_handle(BitSet::new(robj_to_length(&size)?));
+    handle.set_attr("class", BITSET_CLASS)?;
+    Ok(handle)
+}
+
+/// @rdname bitset_new
+/// @export
+#[extendr(invisible)]
+fn bitset_set(mut b: Robj, i: Robj, #[extendr(default = "TRUE")] value: bool) -> Result<Robj> {
+    let bits = bitset_mut(&mut b)?;
+    let positions = positions(&i, bits)?;
+    if positions.iter().any(Option::is_none) {
+        return Err(Error::Other("positions must not be missing".into()));
+    }
+    for i in positions.into_iter().flatten() {
+        bits.set(i, value);
+    }
+    persist::checkpoint::<BitSet>(&b)?;
+    Ok(b)
+}
+
+/// @rdname bitset_new
+/// @export
+#[extendr]
+fn bitset_test(mut b: Robj, i: Robj) -> Result<Logicals> {
+    let bits = bitset_mut(&mut b)?;
+    let positions = positions(&i, bits)?;
+    Ok(Logicals::from_values(positions.into_iter().map(
+        |i| match i {
+            Some(i) => Rbool::from(bits.get(i)),
+            None => Rbool::na(),
+        },
+    )))
+}
+
+/// @rdname bitset_new
+/// @export
+#[extendr]
+fn bitset_count(mut b: Robj) -> Result<Robj> {
+    Ok(length_to_robj(bitset_mut(&mut b)?.count_ones()))
+}
+
+/// @rdname bitset_new
+/// @export
+#[extendr]
+fn bitset_which(mut b: Robj) -> Result<Robj> {
+    let bits = bitset_mut(&mut b)?;
+    let ones = bits.ones().map(|i| i + 1);
+    Ok(if bits.len() <= i32::MAX as usize {
+        Integers::from_values(ones.map(|i| i as i32).collect::<Vec<_>>()).into()
+    } else {
+        Doubles::from_values(ones.map(|i| i as f64).collect::<Vec<_>>()).into()
+    })
+}
+
+/// @rdname bitset_new
+/// @export
+#[extendr]
+fn bitset_size(mut b: Robj) -> Result<Robj> {
+    Ok(length_to_robj(bitset_mut(&mut b)?.len()))
+}
+
+/// A Bloom filter
+///
+/// `bloom_new()` creates a filter that tells whether a value may have been
+/// added to it, using a fixed amount of memory. Values never added are
+/// reported at the rate `fp_rate` once `capacity` values are added, and
+/// more often after that; values added are always reported. Filters can
+/// be saved with `saveRDS()` and used again after `readRDS()`.
+///
+/// Integers, doubles and strings are hashed by value, and whole numbers
+/// are the same whether integer or double. `NA` is not added, and its
+/// membership is `NA`.
+/// @param capacity The number of values the filter is sized for.
+/// @param fp_rate The false positive rate at `capacity`.
+/// @param f A Bloom filter created by `bloom_new()`.
+/// @param x An integer, double or character vector.
+/// @return `bloom_new()` returns a filter and `bloom_add()` the filter,
+///   invisibly. `bloom_test()` returns a logical vector of whether each
+///   element of `x` may have been added.
+/// @export
+#[extendr]
+fn bloom_new(capacity: Robj, #[extendr(default = "0.01")] fp_rate: f64) -> Result<Robj> {
+    let filter = BloomFilter::new(robj_to_length(&capacity)?, fp_rate)
+        .map_err(|e| Error::Other(e.to_string()))?;
+    let mut handle = persist::new_handle(filter);
+    handle.set_attr("class", BLOOM_CLASS)?;
+    Ok(handle)
+}
+
+/// @rdname bloom_new
+/// @export
+#[extendr(invisible)]
+fn bloom_add(mut f: Robj, x: Robj) -> Result<Robj> {
+    let keys = keys(&x)?;
+    let filter = bloom_mut(&mut f)?;
+    for key in keys.iter().flatten() {
+        filter.insert(key);
+    }
+    persist::checkpoint::<BloomFilter>(&f)?;
+    Ok(f)
+}
+
+/// @rdname bloom_new
+/// @export
+#[extendr]
+fn bloom_test(mut f: Robj, x: Robj) -> Result<Logicals> {
+    let keys = keys(&x)?;
+    let filter = bloom_mut(&mut f)?;
+    Ok(Logicals::from_values(keys.iter().map(|key| match key {
+        Some(key) => Rbool::from(filter.contains(key)),
+        None => Rbool::na(),
+    })))
+}
+
+r_module! {
+    mod bits;
+    fn bitset_new;
+    fn bitset_set;
+    fn bitset_test;
+    fn bitset_count;
+    fn bitset_which;
+    fn bitset_size;
+    fn bloom_new;
+    fn bloom_add;
+    fn bloom_test;
+}
@@ -0,0 +1,433 @@
+//! A persistent cache of R values on disk.
+//!
+//! [`DiskCache`] stores values under a key that is itself an R object, so
+//! the result of an expensive computation can be cached under its inputs and
+//! survive across sessions. Keys are hashed from their serialization: two
+//! keys are the same entry when `identical()` would consider them equal,
+//! except that environments and closures hash by their contents at the time
+//! of the call.
+//!
+//! Each entry is the uncompressed `serialize()` output of its value, stored
+//! as `<dir>/<first two hex digits>/<hash>.rds` so it can also be inspected
+//! with `readRDS()`. Entries are written to a temporary file and renamed
+//! into place, so concurrent sessions sharing a directory never read a
+//! partial value. Expiry and eviction go by the time an entry was written.
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+
+use crate::attrib::AttribExt;
+use crate::r_module;
+use crate::raw_io::IntoRaw;
+
+const EXTENSION: &str = "rds";
+
+/// The R class of the handles returned by `disk_cache()`.
+const CLASS: &str = "helloextendr_disk_cache";
+
+/// A directory of cached R values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiskCache {
+    dir: PathBuf,
+    ttl: Option<Duration>,
+    max_size: Option<u64>,
+}
+
+impl DiskCache {
+    /// Open the cache in `dir`, creating the directory if needed. Entries
+    /// never expire and the size is unbounded until [`ttl()`](Self::ttl)
+    /// and [`max_size()`](Self::max_size) say otherwise.
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).map_err(|e| io_error(&dir, e))?;
+        Ok(Self {
+            dir,
+            ttl: None,
+            max_size: None,
+        })
+    }
+
+    /// Treat entries older than `ttl` as missing.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Keep the total size of the entries below `bytes` by removing the
+    /// oldest ones after each insertion.
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The value stored under `key`, if any and not expired.
+    pub fn get(&self, key: &Robj) -> Result<Option<Robj>> {
+        let path = self.path(&hash_key(key)?);
+        let fresh = match fs::metadata(&path) {
+            Ok(metadata) => !self.is_expired(&metadata),
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(io_error(&path, e)),
+        };
+        if !fresh {
+            remove_file(&path)?;
+            return Ok(None);
+        }
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            // Removed by another session since the metadata was read.
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(io_error(&path, e)),
+        };
+        lang!("unserialize", bytes.into_raw()).eval().map(Some)
+    }
+
+    /// Whether there is an entry for `key` that has not expired.
+    pub fn contains(&self, key: &Robj) -> Result<bool> {
+        let path = self.path(&hash_key(key)?);
+        match fs::metadata(&path) {
+            Ok(metadata) => Ok(!self.is_expired(&metadata)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(io_error(&path, e)),
+        }
+    }
+
+    /// Store `value` under `key`, replacing any previous value.
+    pub fn insert(&self, key: &Robj, value: &Robj) -> Result<()> {
+        let path = self.path(&hash_key(key)?);
+        let bytes = serialize(value)?;
+        write_atomic(&path, &bytes)?;
+        if self.max_size.is_some() {
+            self.prune()?;
+        }
+        Ok(())
+    }
+
+    /// The value stored under `key`, or the result of `compute`, which is
+    /// stored for next time.
+    pub fn get_or_insert_with(
+        &self,
+        key: &Robj,
+        compute: impl FnOnce() -> Result<Robj>,
+    ) -> Result<Robj> {
+        if let Some(value) = self.get(key)? {
+            return Ok(value);
+        }
+        let value = compute()?;
+        self.insert(key, &value)?;
+        Ok(value)
+    }
+
+    /// Remove the entry for `key`. Returns whether there was one.
+    pub fn remove(&self, key: &Robj) -> Result<bool> {
+        remove_file(&self.path(&hash_key(key)?))
+    }
+
+    /// Remove all entries.
+    pub fn clear(&self) -> Result<()> {
+        for entry in self.entries()? {
+            remove_file(&entry.path)?;
+        }
+        Ok(())
+    }
+
+    /// Remove expired entries, then the oldest ones until the cache fits in
+    /// its maximum size.
+    pub fn prune(&self) -> Result<()> {
+        let mut entries = Vec::new();
+        for entry in self.entries()? {
+            if self.ttl.is_some_and(|ttl| entry.age > ttl) {
+                remove_file(&entry.path)?;
+            } else {
+                entries.push(entry);
+            }
+        }
+        let max_size = match self.max_size {
+            Some(max_size) => max_size,
+            None => return Ok(()),
+        };
+        let mut size: u64 = entries.iter().map(|e| e.size).sum();
+        // Oldest first.
+        entries.sort_by_key(|e| std::cmp::Reverse(e.age));
+        for entry in entries {
+            if size <= max_size {
+                break;
+            }
+            remove_file(&entry.path)?;
+            size -= entry.size;
+        }
+        Ok(())
+    }
+
+    /// The total size of the entries in bytes.
+    pub fn size(&self) -> Result<u64> {
+        Ok(self.entries()?.iter().map(|e| e.size).sum())
+    }
+
+    fn path(&self, hash: &str) -> PathBuf {
+        self.dir
+            .join(&hash[..2])
+            .join(format!("{hash}.{EXTENSION}"))
+    }
+
+    fn is_expired(&self, metadata: &fs::Metadata) -> bool {
+        match self.ttl {
+            Some(ttl) => age(metadata) > ttl,
+            None => false,
+        }
+    }
+
+    fn entries(&self) -> Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        for shard in read_dir(&self.dir)? {
+            if !shard.is_dir() {
+                continue;
+            }
+            for path in read_dir(&shard)? {
+                if path.extension().is_none_or(|e| e != EXTENSION) {
+                    continue;
+                }
+                // Entries removed concurrently are skipped.
+                if let Ok(metadata) = fs::metadata(&path) {
+                    entries.push(Entry {
+                        size: metadata.len(),
+                        age: age(&metadata),
+                        path,
+                    });
+                }
+            }
+        }
+        Ok(entries)
+    }
+}
+
+struct Entry {
+    path: PathBuf,
+    size: u64,
+    age: Duration,
+}
+
+fn age(metadata: &fs::Metadata) -> Duration {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .unwrap_or_default()
+}
+
+/// The key a value is stored under: a 128-bit FNV-1a hash of its
+/// serialization, in hex.
+pub fn hash_key(key: &Robj) -> Result<String> {
+    let bytes = serialize(key)?;
+    let mut hash: u128 = 0x6c62_272e_07bb_0142_62b8_2175_6295_c58d;
+    for &byte in skip_header(&bytes) {
+        hash ^= u128::from(byte);
+        hash = hash.wrapping_mul(0x0000_0000_0100_0000_0000_0000_0000_013b);
+    }
+    Ok(format!("{hash:032x}"))
+}
+
+pub(crate) fn serialize(value: &Robj) -> Result<Vec<u8>> {
+    let raw = lang!("serialize", value.clone(), (), version = 3).eval()?;
+    Ok(raw.as_raw_slice().unwrap_or(&[]).to_vec())
+}
+
+/// The serialization without its header, which records the version of R
+/// that wrote it and would make keys differ between R versions.
+fn skip_header(bytes: &[u8]) -> &[u8] {
+    // Format ("X\n"), then the serialization, R and minimal R versions.
+    let rest = match bytes.get(14..) {
+        Some(rest) => rest,
+        None => return bytes,
+    };
+    // Version 3 adds the native encoding as a length-prefixed string.
+    match rest.get(..4) {
+        Some(len) => {
+            let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize;
+            rest.get(4 + len..).unwrap_or(rest)
+        }
+        None => rest,
+    }
+}
+
+/// Write `bytes` to a temporary file next to `path`, then rename it into
+/// place so readers see either the old or the new contents.
+pub(crate) fn write_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let dir = path.parent().unwrap();
+    fs::create_dir_all(dir).map_err(|e| io_error(dir, e))?;
+    let tmp = dir.join(format!(
+        ".tmp-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result = fs::write(&tmp, bytes).and_then(|()| fs::rename(&tmp, path));
+    if let Err(e) = result {
+        let _ = fs::remove_file(&tmp);
+        return Err(io_error(path, e));
+    }
+    Ok(())
+}
+
+fn remove_file(path: &Path) -> Result<bool> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(io_error(path, e)),
+    }
+}
+
+fn read_dir(dir: &Path) -> Result<Vec<PathBuf>> {
+    match fs::read_dir(dir) {
+        Ok(entries) => Ok(entries.filter_map(|e| e.ok()).map(|e| e.path()).collect()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(io_error(dir, e)),
+    }
+}
+
+fn io_error(path: &Path, e: io::Error) -> Error {
+    Error::Other(format!("{}: {e}", path.display()))
+}
+
+impl TryFrom<&Robj> for DiskCache {
+    type Error = Error;
+
+    fn try_from(robj: &Robj) -> Result<Self> {
+        if !robj.inherits(CLASS) {
+            return Err(Error::Other(
+                "expected a cache created by `disk_cache()`".into(),
+            ));
+        }
+        let dir = robj
+            .dollar("dir")?
+            .as_str()
+            .ok_or_else(|| Error::Other("invalid cache directory".into()))?
+            .to_string();
+        let mut cache = DiskCache::new(dir)?;
+        if let Some(ttl) = robj.dollar("ttl")?.as_real() {
+            cache = cache.ttl(Duration::from_secs_f64(ttl.max(0.0)));
+        }
+        if let Some(max_size) = robj.dollar("max_size")?.as_real() {
+            cache = cache.max_size(max_size.max(0.0) as u64);
+        }
+        Ok(cache)
+    }
+}
+
+fn as_seconds(x: &Robj) -> Option<f64> {
+    x.as_real().or_else(|| x.as_integer().map(f64::from))
+}
+
+/// A persistent cache of R values
+///
+/// Values are stored in files under `dir` and keyed by any R object, such
+/// as the arguments of an expensive computation. Several sessions can share
+/// the same directory.
+/// @param dir The cache directory, created if needed.
+/// @param ttl `NULL` or the number of seconds after which entries expire.
+/// @param max_size `NULL` or the maximum total size of the entries in
+///   bytes. The oldest entries are removed to stay below it.
+/// @return A cache to pass to [cache_get()] and the other `cache_*()`
+///   functions.
+/// @export
+#[extendr]
+fn disk_cache(
+    dir: &str,
+    #[extendr(default = "NULL")] ttl: Robj,
+    #[extendr(default = "NULL")] max_size: Robj,
+) -> Result<Robj> {
+    DiskCache::new(dir)?;
+    let dir = fs::canonicalize(dir)
+        .map(|dir| dir.display().to_string())
+        .unwrap_or_else(|_| dir.to_string());
+    let mut cache: Robj = list!(
+        dir = dir,
+        ttl = as_seconds(&ttl),
+        max_size = as_seconds(&max_size)
+    )
+    .into();
+    cache.set_attr("class", CLASS)?;
+    Ok(cache)
+}
+
+/// Use a disk cache
+///
+/// `cache_get_or_set()` returns the cached value if there is one and
+/// otherwise calls `f` and caches its result, which makes it easy to cache
+/// a computation under its inputs.
+/// @param cache A cache created by [disk_cache()].
+/// @param key Any R object identifying the entry.
+/// @param value The value to store.
+/// @param default The value returned when there is no entry.
+/// @param f A function without arguments computing the value.
+/// @return `cache_get()` and `cache_get_or_set()` return the value,
+///   `cache_has()` and `cache_remove()` whether there was an entry, and
+///   `cache_set()`, `cache_clear()` and `cache_prune()` return `NULL`,
+///   invisibly.
+/// @export
+#[extendr]
+fn cache_get(cache: Robj, key: Robj, #[extendr(default = "NULL")] default: Robj) -> Result<Robj> {
+    Ok(DiskCache::try_from(&cache)?.get(&key)?.unwrap_or(default))
+}
+
+/// @rdname cache_get
+/// @export
+#[extendr(invisible)]
+fn cache_set(cache: Robj, key: Robj, value: Robj) -> Result<()> {
+    DiskCache::try_from(&cache)?.insert(&key, &value)
+}
+
+/// @rdname cache_get
+/// @export
+#[extendr]
+fn cache_get_or_set(cache: Robj, key: Robj, f: Function) -> Result<Robj> {
+    DiskCache::try_from(&cache)?.get_or_insert_with(&key, || f.call(pairlist!()))
+}
+
+/// @rdname cache_get
+/// @export
+#[extendr]
+fn cache_has(cache: Robj, key: Robj) -> Result<bool> {
+    DiskCache::try_from(&cache)?.contains(&key)
+}
+
+/// @rdname cache_get
+/// @export
+#[extendr]
+fn cache_remove(cache: Robj, key: Robj) -> Result<bool> {
+    DiskCache::try_from(&cache)?.remove(&key)
+}
+
+/// @rdname cache_get
+/// @export
+#[extendr(invisible)]
+fn cache_clear(cache: Robj) -> Result<()> {
+    DiskCache::try_from(&cache)?.clear()
+}
+
+/// @rdname cache_get
+/// @export
+#[extendr(invisible)]
+fn cache_prune(cache: Robj) -> Result<()> {
+    DiskCache::try_from(&cache)?.prune()
+}
+
+r_module! {
+    mod cache;
+    fn disk_cache;
+    fn cache_get;
+    fn cache_set;
+    fn cache_get_or_set;
+    fn cache_has;
+    fn cache_remove;
+    fn cache_clear;
+    fn cache_prune;
+}
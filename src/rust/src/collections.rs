@@ -0,0 +1,548 @@
+//! Double-ended queues and priority queues of R values.
+//!
+//! R has no mutable collections, so algorithms that need a queue or a heap
+//! grow and shrink lists, copying them on every step. [`Deque`] and
+//! [`Heap`] live behind external pointers and are modified in place, with
+//! pushes and pops in constant and logarithmic time.
+//!
+//! A collection holds elements of one [`ElementType`]. Typed collections
+//! push every element of a vector, converted with `as.vector()`, and pop
+//! vectors of that type; collections of type `"any"` push and pop single R
+//! objects of any kind.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
+use std::str::FromStr;
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+use crate::attrib::AttribExt;
+use crate::r_module;
+use crate::xlen::{length_to_robj, robj_to_length};
+
+/// The R class of the handles returned by `deque_new()`.
+const DEQUE_CLASS: &str = "helloextendr_deque";
+
+/// The R class of the handles returned by `heap_new()`.
+const HEAP_CLASS: &str = "helloextendr_heap";
+
+/// The type of the elements of a collection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElementType {
+    /// Any R object, pushed and popped whole.
+    Any,
+    Logical,
+    Integer,
+    Double,
+    Character,
+}
+
+impl FromStr for ElementType {
+    type Err = Error;
+
+    /// Parse an R-side `mode` argument.
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "any" => Ok(ElementType::Any),
+            "logical" => Ok(ElementType::Logical),
+            "integer" => Ok(ElementType::Integer),
+            "double" => Ok(ElementType::Double),
+            "character" => Ok(ElementType::Character),
+            _ => Err(Error::Other(format!(
+                "`mode` must be \"any\", \"logical\", \"integer\", \"double\" or \"character\", not \"{s}\""
+            ))),
+        }
+    }
+}
+
+/// An element of a collection.
+#[derive(Debug, Clone)]
+pub enum Element {
+    Any(Robj),
+    Logical(Rbool),
+    Integer(Rint),
+    Double(Rfloat),
+    Character(Rstr),
+}
+
+impl ElementType {
+    fn mode(self) -> &'static str {
+        match self {
+            ElementType::Any => "any",
+            ElementType::Logical => "logical",
+            ElementType::Integer => "integer",
+            ElementType::Double => "double",
+            ElementType::Character => "character",
+        }
+    }
+
+    /// The elements to push for `x`: `x` itself for `Any`, and otherwise
+    /// the elements of `x` converted to the type.
+    pub fn elements(self, x: Robj) -> Result<Vec<Element>> {
+        if self == ElementType::Any {
+            return Ok(vec![Element::Any(x)]);
+        }
+        if !x.is_vector_atomic() {
+            return Err(Error::Other(format!(
+                "cannot push a {:?} to a collection of type \"{}\"",
+                x.rtype(),
+                self.mode()
+            )));
+        }
+        let x = lang!("as.vector", x, self.mode()).eval()?;
+        Ok(match self {
+            ElementType::Logical => Logicals::try_from(x)?
+                .iter()
+                .map(Element::Logical)
+                .collect(),
+            ElementType::Integer => Integers::try_from(x)?
+                .iter()
+                .map(Element::Integer)
+                .collect(),
+            ElementType::Double => Doubles::try_from(x)?.iter().map(Element::Double).collect(),
+            _ => Strings::try_from(x)?
+                .iter()
+                .map(|s| Element::Character(s.clone()))
+                .collect(),
+        })
+    }
+
+    /// `elements` as an R object: a vector of the type, or for `Any` the
+    /// element itself if `scalar` is true and a list otherwise.
+    pub fn to_robj(self, elements: Vec<Element>, scalar: bool) -> Robj {
+        fn take<T>(elements: Vec<Element>, f: impl Fn(Element) -> Option<T>) -> Vec<T> {
+            elements.into_iter().filter_map(f).collect()
+        }
+        match self {
+            ElementType::Any => {
+                let mut values = take(elements, |e| match e {
+                    Element::Any(x) => Some(x),
+                    _ => None,
+                });
+                if scalar && values.len() == 1 {
+                    values.remove(0)
+                } else {
+                    List::from_values(values).into()
+                }
+            }
+            ElementType::Logical => Logicals::from_values(take(elements, |e| match e {
+                Element::Logical(x) => Some(x),
+                _ => None,
+            }))
+            .into(),
+            ElementType::Integer => Integers::from_values(take(elements, |e| match e {
+                Element::Integer(x) => Some(x),
+                _ => None,
+            }))
+            .into(),
+            ElementType::Double => Doubles::from_values(take(elements, |e| match e {
+                Element::Double(x) => Some(x),
+                _ => None,
+            }))
+            .into(),
+            ElementType::Character => Strings::from_values(take(elements, |e| match e {
+                Element::Character(x) => Some(x),
+                _ => None,
+            }))
+            .into(),
+        }
+    }
+}
+
+/// A double-ended queue.
+#[derive(Debug, Clone)]
+pub struct Deque {
+    kind: ElementType,
+    items: VecDeque<Element>,
+}
+
+impl Deque {
+    pub fn new(kind: ElementType) -> Self {
+        Self {
+            kind,
+            items: VecDeque::new(),
+        }
+    }
+
+    pub fn kind(&self) -> ElementType {
+        self.kind
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Push the elements of `x` at the back, in order.
+    pub fn push_back(&mut self, x: Robj) -> Result<()> {
+        self.items.extend(self.kind.elements(x)?);
+        Ok(())
+    }
+
+    /// Push the elements of `x` at the front, so that the first element of
+    /// `x` ends up first.
+    pub fn push_front(&mut self, x: Robj) -> Result<()> {
+        for element in self.kind.elements(x)?.into_iter().rev() {
+            self.items.push_front(element);
+        }
+        Ok(())
+    }
+
+    /// Remove `n` elements from the front, in order.
+    pub fn pop_front(&mut self, n: usize) -> Result<Vec<Element>> {
+        self.check_len(n)?;
+        Ok(self.items.drain(..n).collect())
+    }
+
+    /// Remove `n` elements from the back, the last first.
+    pub fn pop_back(&mut self, n: usize) -> Result<Vec<Element>> {
+        self.check_len(n)?;
+        let rest = self.items.len() - n;
+        Ok(self.items.drain(rest..).rev().collect())
+    }
+
+    pub fn front(&self) -> Option<&Element> {
+        self.items.front()
+    }
+
+    pub fn back(&self) -> Option<&Element> {
+        self.items.back()
+    }
+
+    fn check_len(&self, n: usize) -> Result<()> {
+        if n > self.items.len() {
+            return Err(Error::Other(format!(
+                "cannot pop {n} elements from a deque of {}",
+                self.items.len()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// An element of a heap with its priority.
+#[derive(Debug, Clone)]
+struct Entry {
+    /// The priority, negated for a heap that pops the smallest first.
+    key: f64,
+    /// The order of insertion, which breaks ties.
+    seq: u64,
+    element: Element,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Equal priorities pop first in, first out.
+        self.key
+            .total_cmp(&other.key)
+            .then(other.seq.cmp(&self.seq))
+    }
+}
+
+/// A priority queue, which pops the element of the smallest priority first,
+/// or the largest if `decreasing`. Elements of equal priority pop in the
+/// order they were pushed.
+#[derive(Debug, Clone)]
+pub struct Heap {
+    kind: ElementType,
+    decreasing: bool,
+    seq: u64,
+    entries: BinaryHeap<Entry>,
+}
+
+impl Heap {
+    pub fn new(kind: ElementType, decreasing: bool) -> Self {
+        Self {
+            kind,
+            decreasing,
+            seq: 0,
+            entries: BinaryHeap::new(),
+        }
+    }
+
+    pub fn kind(&self) -> ElementType {
+        self.kind
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Push the elements of `x` with one priority each.
+    pub fn push(&mut self, x: Robj, priorities: &[f64]) -> Result<()> {
+        let elements = self.kind.elements(x)?;
+        if elements.len() != priorities.len() {
+            return Err(Error::Other(format!(
+                "{} elements but {} priorities",
+                elements.len(),
+                priorities.len()
+            )));
+        }
+        if priorities.iter().any(|p| p.is_nan()) {
+            return Err(Error::Other("priorities must not be missing".into()));
+        }
+        for (element, &priority) in elements.into_iter().zip(priorities) {
+            self.entries.push(Entry {
+                // `+ 0.0` and `0.0 -` turn -0 into 0, which `total_cmp()`
+                // orders before 0.
+                key: if self.decreasing {
+                    priority + 0.0
+                } else {
+                    0.0 - priority
+                },
+                seq: self.seq,
+                element,
+            });
+            self.seq += 1;
+        }
+        Ok(())
+    }
+
+    /// Remove the first `n` elements in order of priority.
+    pub fn pop(&mut self, n: usize) -> Result<Vec<Element>> {
+        if n > self.entries.len() {
+            return Err(Error::Other(format!(
+                "cannot pop {n} elements from a heap of {}",
+                self.entries.len()
+            )));
+        }
+        Ok((0..n)
+            .filter_map(|_| self.entries.pop())
+            .map(|entry| entry.element)
+            .collect())
+    }
+
+    /// The element that pops next and its priority.
+    pub fn peek(&self) -> Option<(&Element, f64)> {
+        self.entries.peek().map(|entry| {
+            let priority = if self.decreasing {
+                entry.key
+            } else {
+                0.0 - entry.key
+            };
+            (&entry.element, priority)
+        })
+    }
+}
+
+fn deque_mut(d: &mut Robj) -> Result<&mut ExternalPtr<Deque>> {
+    if !d.inherits(DEQUE_CLASS) {
+        return Err(Error::Other("expected a deque from `deque_new()`".into()));
+    }
+    d.try_into()
+}
+
+fn heap_mut(h: &mut Robj) -> Result<&mut ExternalPtr<Heap>> {
+    if !h.inherits(HEAP_CLASS) {
+        return Err(Error::Other("expected a heap from `heap_new()`".into()));
+    }
+    h.try_into()
+}
+
+/// A double-ended queue
+///
+/// `deque_new()` creates a queue that grows and shrinks at both ends in
+/// constant time, modified in place rather than copied.
+///
+/// With a `mode` other than `"any"`, the push functions convert `x` with
+/// `as.vector()` and push each of its elements, and the pop functions
+/// return a vector of that type. Otherwise `x` is pushed as one element,
+/// and popping one element returns it while popping several returns a list.
+/// @param mode The type of the elements: `"any"`, `"logical"`, `"integer"`,
+///   `"double"` or `"character"`.
+/// @param d A deque created by `deque_new()`.
+/// @param x The value to push.
+/// @param n The number of elements to pop.
+/// @return `deque_new()` returns a deque. `deque_pop_front()` returns the
+///   `n` first elements in order and `deque_pop_back()` the `n` last, the
+///   last first. The peek functions return the first or last element
+///   without removing it, or `NULL` if the deque is empty, and
+///   `deque_size()` the number of elements. The push functions return the
+///   deque, invisibly.
+/// @export
+#[extendr]
+fn deque_new(#[extendr(default = "\"any\"")] mode: &str) -> Result<Robj> {
+    let mut handle: Robj = ExternalPtr::new(Deque::new(mode.parse()?)).into();
+    handle.set_attr("class", DEQUE_CLASS)?;
+    Ok(handle)
+}
+
+/// @rdname deque_new
+/// @export
+#[extendr(invisible)]
+fn deque_push_back(mut d: Robj, x: Robj) -> Result<Robj> {
+    deque_mut(&mut d)?.push_back(x)?;
+    Ok(d)
+}
+
+/// @rdname deque_new
+/// @export
+#[extendr(invisible)]
+fn deque_push_front(mut d: Robj, x: Robj) -> Result<Robj> {
+    deque_mut(&mut d)?.push_front(x)?;
+    Ok(d)
+}
+
+/// @rdname deque_new
+/// @export
+#[extendr]
+fn deque_pop_front(mut d: Robj, #[extendr(default = "1L")] n: Robj) -> Result<Robj> {
+    let n = robj_to_length(&n)?;
+    let deque = deque_mut(&mut d)?;
+    let elements = deque.pop_front(n)?;
+    Ok(deque.kind().to_robj(elements, n == 1))
+}
+
+/// @rdname deque_new
+/// @export
+#[extendr]
+fn deque_pop_back(mut d: Robj, #[extendr(default = "1L")] n: Robj) -> Result<Robj> {
+    let n = robj_to_length(&n)?;
+    let deque = deque_mut(&mut d)?;
+    let elements = deque.pop_back(n)?;
+    Ok(deque.kind().to_robj(elements, n == 1))
+}
+
+/// @rdname deque_new
+/// @export
+#[extendr]
+fn deque_peek_front(mut d: Robj) -> Result<Robj> {
+    let deque = deque_mut(&mut d)?;
+    Ok(match deque.front() {
+        Some(element) => deque.kind().to_robj(vec![element.clone()], true),
+        None => r!(NULL),
+    })
+}
+
+/// @rdname deque_new
+/// @export
+#[extendr]
+fn deque_peek_back(mut d: Robj) -> Result<Robj> {
+    let deque = deque_mut(&mut d)?;
+    Ok(match deque.back() {
+        Some(element) => deque.kind().to_robj(vec![element.clone()], true),
+        None => r!(NULL),
+    })
+}
+
+/// @rdname deque_new
+/// @export
+#[extendr]
+fn deque_size(mut d: Robj) -> Result<Robj> {
+    Ok(length_to_robj(deque_mut(&mut d)?.len()))
+}
+
+/// A priority queue
+///
+/// `heap_new()` creates a binary heap, which pops the element of the
+/// smallest priority first, or the largest with `decreasing = TRUE`.
+/// Elements of equal priority pop in the order they were pushed. Elements
+/// are typed as in [deque_new()]: with a `mode` other than `"any"`,
+/// `heap_push()` pushes each element of `x` with the corresponding
+/// priority.
+/// @inheritParams deque_new
+/// @param decreasing Whether to pop the largest priority first.
+/// @param h A heap created by `heap_new()`.
+/// @param priority The priority of `x`, or one per element of a typed `x`.
+/// @return `heap_new()` returns a heap, and `heap_push()` the heap,
+///   invisibly. `heap_pop()` returns the `n` next elements in order of
+///   priority, `heap_peek()` a list of the next element `value` and its
+///   `priority`, or `NULL` if the heap is empty, and `heap_size()` the
+///   number of elements.
+/// @export
+#[extendr]
+fn heap_new(
+    #[extendr(default = "\"any\"")] mode: &str,
+    #[extendr(default = "FALSE")] decreasing: bool,
+) -> Result<Robj> {
+    let mut handle: Robj = ExternalPtr::new(Heap::new(mode.parse()?, decreasing)).into();
+    handle.set_attr("class", HEAP_CLASS)?;
+    Ok(handle)
+}
+
+/// @rdname heap_new
+/// @export
+#[extendr(invisible)]
+fn heap_push(mut h: Robj, x: Robj, priority: Robj) -> Result<Robj> {
+    let priority: Vec<f64> = if let Some(p) = priority.as_real_slice() {
+        p.to_vec()
+    } else if let Some(p) = priority.as_integer_slice() {
+        p.iter()
+            .map(|&v| Option::<i32>::from(Rint::from(v)).map_or(f64::NAN, f64::from))
+            .collect()
+    } else {
+        return Err(Error::Other("`priority` must be numeric".into()));
+    };
+    heap_mut(&mut h)?.push(x, &priority)?;
+    Ok(h)
+}
+
+/// @rdname heap_new
+/// @export
+#[extendr]
+fn heap_pop(mut h: Robj, #[extendr(default = "1L")] n: Robj) -> Result<Robj> {
+    let n = robj_to_length(&n)?;
+    let heap = heap_mut(&mut h)?;
+    let elements = heap.pop(n)?;
+    Ok(heap.kind().to_robj(elements, n == 1))
+}
+
+/// @rdname heap_new
+/// @export
+#[extendr]
+fn heap_peek(mut h: Robj) -> Result<Robj> {
+    let heap = heap_mut(&mut h)?;
+    Ok(match heap.peek() {
+        Some((element, priority)) => {
+            let value = heap.kind().to_robj(vec![element.clone()], true);
+            list!(value = value, priority = priority).into()
+        }
+        None => r!(NULL),
+    })
+}
+
+/// @rdname heap_new
+/// @export
+#[extendr]
+fn heap_size(mut h: Robj) -> Result<Robj> {
+    Ok(length_to_robj(heap_mut(&mut h)?.len()))
+}
+
+r_module! {
+    mod collections;
+    fn deque_new;
+    fn deque_push_back;
+    fn deque_push_front;
+    fn deque_pop_front;
+    fn deque_pop_back;
+    fn deque_peek_front;
+    fn deque_peek_back;
+    fn deque_size;
+    fn heap_new;
+    fn heap_push;
+    fn heap_pop;
+    fn heap_peek;
+    fn heap_size;
+}
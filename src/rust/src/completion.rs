@@ -0,0 +1,129 @@
+//! Console completion for Rust-backed objects.
+//!
+//! Two hooks of R's completion machinery are exposed:
+//!
+//! * [`register_dollar_completer()`] provides the names offered after `x$`
+//!   for objects of a class, through a `.DollarNames()` method. This is what
+//!   both the R console and RStudio use, so Rust-held data such as the
+//!   columns of a dataset behind an external pointer complete like list
+//!   elements.
+//! * [`register_token_completer()`] adds candidates for any token typed at
+//!   the console through `rc.options(custom.completer = )`, on top of R's
+//!   own completions. RStudio ignores this hook.
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use std::sync::{Arc, Mutex};
+
+use crate::attrib::AttribExt;
+use crate::r_module;
+
+/// The R package whose namespace holds the R side of the hooks.
+const PACKAGE: &str = "helloextendr";
+
+// Shared, so a completer can be cloned out of the registry and called with
+// the lock released: it may register completers itself.
+type DollarCompleter = Arc<dyn Fn(&Robj) -> Vec<String> + Send + Sync>;
+type TokenCompleter = Arc<dyn Fn(&CompletionRequest) -> Vec<String> + Send + Sync>;
+
+static DOLLAR_COMPLETERS: Mutex<Vec<(String, DollarCompleter)>> = Mutex::new(Vec::new());
+static TOKEN_COMPLETERS: Mutex<Vec<TokenCompleter>> = Mutex::new(Vec::new());
+
+/// The state of the console line when completion was requested.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletionRequest {
+    /// The token being completed.
+    pub token: String,
+    /// The whole line up to the cursor.
+    pub line: String,
+    /// Zero-based start and end positions of the token in the line.
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Complete `x$` for objects inheriting from `class` with the names returned
+/// by `complete`. Registering a class again replaces its completer.
+pub fn register_dollar_completer(
+    class: &str,
+    complete: impl Fn(&Robj) -> Vec<String> + Send + Sync + 'static,
+) -> Result<()> {
+    {
+        let mut completers = DOLLAR_COMPLETERS.lock().unwrap_or_else(|e| e.into_inner());
+        completers.retain(|(c, _)| c != class);
+        completers.push((class.to_string(), Arc::new(complete)));
+    }
+    package_function("register_dollar_completion")?.call(pairlist!(class))?;
+    Ok(())
+}
+
+/// Offer the candidates returned by `complete` for tokens typed at the
+/// console. Only candidates that start with the token are shown.
+pub fn register_token_completer(
+    complete: impl Fn(&CompletionRequest) -> Vec<String> + Send + Sync + 'static,
+) -> Result<()> {
+    let first = {
+        let mut completers = TOKEN_COMPLETERS.lock().unwrap_or_else(|e| e.into_inner());
+        completers.push(Arc::new(complete));
+        completers.len() == 1
+    };
+    if first {
+        package_function("install_token_completer")?.call(pairlist!())?;
+    }
+    Ok(())
+}
+
+fn package_function(name: &str) -> Result<Function> {
+    let namespace = lang!("asNamespace", PACKAGE).eval()?;
+    let namespace: Environment = namespace.try_into()?;
+    namespace.local(Symbol::from_string(name))?.try_into()
+}
+
+/// Names to complete after `x$`, filtered by the regular expression
+/// `pattern`, as `.DollarNames()` expects.
+/// @noRd
+#[extendr]
+fn complete_dollar(x: Robj, pattern: &str) -> Result<Vec<String>> {
+    let classes = x.class_vec();
+    let complete = {
+        let completers = DOLLAR_COMPLETERS.lock().unwrap_or_else(|e| e.into_inner());
+        classes
+            .iter()
+            .find_map(|class| completers.iter().find(|(c, _)| c == class))
+            .map(|(_, complete)| Arc::clone(complete))
+    };
+    let names = complete.map(|complete| complete(&x)).unwrap_or_default();
+    if pattern.is_empty() || names.is_empty() {
+        return Ok(names);
+    }
+    lang!("grep", pattern, names, value = true)
+        .eval()?
+        .as_string_vector()
+        .ok_or_else(|| Error::Other("`grep()` did not return a character vector".into()))
+}
+
+/// Candidates from the token completers for the current console line.
+/// @noRd
+#[extendr]
+fn complete_token(token: &str, line: &str, start: i32, end: i32) -> Vec<String> {
+    let request = CompletionRequest {
+        token: token.to_string(),
+        line: line.to_string(),
+        start: start.max(0) as usize,
+        end: end.max(0) as usize,
+    };
+    let completers = TOKEN_COMPLETERS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    completers
+        .iter()
+        .flat_map(|complete| complete(&request))
+        .filter(|candidate| candidate.starts_with(token))
+        .collect()
+}
+
+r_module! {
+    mod completion;
+    fn complete_dollar;
+    fn complete_token;
+}
@@ -0,0 +1,258 @@
+//! Classed R conditions.
+//!
+//! `Rf_error()` and extendr's errors only carry a message. [`Condition`]
+//! builds a full condition object, with a class vector callers can
+//! `tryCatch()` on and arbitrary data fields, and [`throw_condition()`] and
+//! [`signal_condition()`] raise it in the R code that called into Rust. In
+//! the other direction, [`catch_conditions()`] evaluates R code and returns
+//! the conditions of the given classes as values instead of failing.
+
+use extendr_api::prelude::*;
+use extendr_api::robj::GetSexp;
+use extendr_api::Result;
+use extendr_ffi::{R_BaseEnv, Rf_protect};
+
+use crate::attrib::AttribExt;
+use crate::rapi::Rf_eval;
+use crate::sexp::Sexp;
+
+/// The base class of a condition, which decides how R reacts to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConditionKind {
+    Error,
+    Warning,
+    Message,
+    /// A bare condition, which does nothing unless handled.
+    Condition,
+}
+
+impl ConditionKind {
+    fn base_classes(self) -> &'static [&'static str] {
+        match self {
+            ConditionKind::Error => &["error", "condition"],
+            ConditionKind::Warning => &["warning", "condition"],
+            ConditionKind::Message => &["message", "condition"],
+            ConditionKind::Condition => &["condition"],
+        }
+    }
+
+    /// The base function that signals conditions of this kind.
+    fn signaller(self) -> &'static str {
+        match self {
+            ConditionKind::Error => "stop",
+            ConditionKind::Warning => "warning",
+            ConditionKind::Message => "message",
+            ConditionKind::Condition => "signalCondition",
+        }
+    }
+}
+
+/// A condition object under construction.
+#[derive(Debug, Clone)]
+pub struct Condition {
+    kind: ConditionKind,
+    message: String,
+    classes: Vec<String>,
+    call: Robj,
+    fields: Vec<(String, Robj)>,
+}
+
+impl Condition {
+    pub fn new(kind: ConditionKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            classes: Vec::new(),
+            call: r!(NULL),
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(ConditionKind::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(ConditionKind::Warning, message)
+    }
+
+    /// A message condition. As with `message()`, end the text with a newline
+    /// to have it printed on a line of its own.
+    pub fn message(message: impl Into<String>) -> Self {
+        Self::new(ConditionKind::Message, message)
+    }
+
+    /// A bare condition, for signalling to handlers only.
+    pub fn plain(message: impl Into<String>) -> Self {
+        Self::new(ConditionKind::Condition, message)
+    }
+
+    /// Add a subclass. Classes are kept in the order they are added, before
+    /// the base classes, so add the most specific one first.
+    pub fn class(mut self, class: impl Into<String>) -> Self {
+        self.classes.push(class.into());
+        self
+    }
+
+    /// The call the condition is reported for, `NULL` by default.
+    pub fn call(mut self, call: impl Into<Robj>) -> Self {
+        self.call = call.into();
+        self
+    }
+
+    /// Add a data field, available to handlers as `cnd$name`.
+    pub fn field(mut self, name: impl Into<String>, value: impl Into<Robj>) -> Self {
+        self.fields.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn kind(&self) -> ConditionKind {
+        self.kind
+    }
+
+    /// The full class vector of the condition.
+    pub fn classes(&self) -> Vec<String> {
+        let base = self.kind.base_classes().iter().map(|c| c.to_string());
+        self.classes.iter().cloned().chain(base).collect()
+    }
+
+    /// Build the R condition object.
+    pub fn to_robj(&self) -> Result<Robj> {
+        let mut names = vec!["message".to_string(), "call".to_string()];
+        let mut values = vec![r!(self.message.as_str()), self.call.clone()];
+        for (name, value) in &self.fields {
+            names.push(name.clone());
+            values.push(value.clone());
+        }
+        let mut cnd: Robj = List::from_names_and_values(names, values)?.into();
+        cnd.set_attr("class", self.classes())?;
+        Ok(cnd)
+    }
+}
+
+/// Raise `cnd` as an error in the calling R code, as `stop(cnd)` does.
+///
+/// This does not return: R unwinds the stack with a long jump, so, as with
+/// `throw_r_error()`, destructors of values still alive in the calling Rust
+/// frames do not run. Call it last, from the function R called.
+pub fn throw_condition(cnd: &Condition) -> ! {
+    let signal = build_signal("stop", cnd);
+    unsafe {
+        Rf_eval(signal, Sexp::from_raw(R_BaseEnv));
+    }
+    unreachable!("`stop()` returned")
+}
+
+/// Signal `cnd` according to its kind: errors as with `stop()`, and warnings,
+/// messages and bare conditions as with `warning()`, `message()` and
+/// `signalCondition()`, which return unless a handler exits.
+///
+/// A handler in the calling R code may exit with a long jump, with the
+/// caveats of [`throw_condition()`].
+pub fn signal_condition(cnd: &Condition) -> Result<()> {
+    let signal = build_signal(cnd.kind.signaller(), cnd);
+    unsafe {
+        Rf_eval(signal, Sexp::from_raw(R_BaseEnv));
+        extendr_ffi::Rf_unprotect(1);
+    }
+    Ok(())
+}
+
+/// The protected call `fun(cnd)`, with no Rust-owned objects left to leak
+/// if evaluating it jumps.
+fn build_signal(fun: &str, cnd: &Condition) -> Sexp {
+    let cnd = match cnd.to_robj() {
+        Ok(cnd) => cnd,
+        Err(e) => throw_r_error(e.to_string()),
+    };
+    let call = Language::from_values([Symbol::from_string(fun).into(), cnd]);
+    Sexp::from_raw(unsafe { Rf_protect(call.get()) })
+}
+
+/// A condition caught by [`catch_conditions()`].
+#[derive(Debug, Clone)]
+pub struct CaughtCondition {
+    cnd: Robj,
+}
+
+impl CaughtCondition {
+    pub fn message(&self) -> String {
+        self.field("message")
+            .and_then(|m| m.as_str().map(str::to_string))
+            .unwrap_or_default()
+    }
+
+    pub fn classes(&self) -> Vec<String> {
+        self.cnd.class_vec()
+    }
+
+    pub fn inherits(&self, class: &str) -> bool {
+        self.classes().iter().any(|c| c == class)
+    }
+
+    /// The data field `name`, including `message` and `call`.
+    pub fn field(&self, name: &str) -> Option<Robj> {
+        self.cnd.dollar(name).ok().filter(|value| !value.is_null())
+    }
+
+    pub fn into_robj(self) -> Robj {
+        self.cnd
+    }
+}
+
+/// Marks a handler result, so it cannot be confused with a value of `expr`.
+const CAUGHT_CLASS: &str = "helloextendr_caught_condition";
+
+const CATCHER: &str = "function(expr, env, classes) {
+  handler <- function(cnd) structure(list(cnd), class = marker)
+  handlers <- rep(list(handler), length(classes))
+  names(handlers) <- classes
+  do.call(tryCatch, c(list(quote(eval(expr, env))), handlers))
+}";
+
+/// Evaluate `expr` in `env`, returning conditions that inherit from any of
+/// `classes` as `Err` values rather than unwinding. Errors of other classes
+/// fail as usual.
+pub fn catch_conditions(
+    expr: &Robj,
+    env: &Environment,
+    classes: &[&str],
+) -> Result<std::result::Result<Robj, CaughtCondition>> {
+    let catcher_env = Environment::new_with_parent(base_env());
+    catcher_env.set_local(sym!(marker), CAUGHT_CLASS);
+    let catcher: Function = lang!("parse", text = CATCHER)
+        .eval()?
+        .as_expressions()
+        .and_then(|exprs| exprs.values().next())
+        .ok_or_else(|| Error::Other("cannot parse the condition catcher".into()))?
+        .eval_with_env(&catcher_env)?
+        .try_into()?;
+
+    let result = catcher.call(pairlist!(
+        crate::deparse::quoted(expr),
+        env.clone(),
+        classes.to_vec()
+    ))?;
+    if result.inherits(CAUGHT_CLASS) {
+        let cnd = result
+            .as_list()
+            .and_then(|l| l.values().next())
+            .ok_or_else(|| Error::Other("malformed caught condition".into()))?;
+        Ok(Err(CaughtCondition { cnd }))
+    } else {
+        Ok(Ok(result))
+    }
+}
+
+/// Call `f` with `args`, catching conditions as [`catch_conditions()`] does.
+pub fn catch_call(
+    f: &Function,
+    args: Pairlist,
+    classes: &[&str],
+) -> Result<std::result::Result<Robj, CaughtCondition>> {
+    let env = Environment::new_with_parent(base_env());
+    env.set_local(sym!(f), f.clone());
+    let args: Robj = args.into();
+    env.set_local(sym!(args), lang!("as.list", args).eval()?);
+    catch_conditions(&lang!("do.call", sym!(f), sym!(args)), &env, classes)
+}
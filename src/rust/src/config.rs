@@ -0,0 +1,504 @@
+//! Layered configuration for the Rust side of packages.
+//!
+//! A [`Config`] looks a setting up in three places, the first that has it
+//! winning:
+//!
+//! 1. the R option `<package>.<key>`, e.g. set in `.Rprofile`;
+//! 2. the environment variable `<PACKAGE>_<KEY>`, upper case with `.` and
+//!    `-` turned into `_`, e.g. set in `.Renviron`;
+//! 3. the config file, if any, of `key = value` lines with `#` comments.
+//!
+//! `.Rprofile` and `.Renviron` are read by R at startup, so their settings
+//! arrive through the first two layers; every lookup reads the current
+//! values, so later `options()` and `Sys.setenv()` calls apply as well.
+//!
+//! [`Config::on_change()`] calls back when the value of a key changes.
+//! Changes are looked for by [`poll()`], which runs after each top-level
+//! command at the console while any callback is registered, and can be
+//! called directly, or as `config_poll()` from R, elsewhere.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str::FromStr;
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use helloextendr_core::na::is_na_integer;
+
+use crate::attrib::AttribExt;
+use crate::r_module;
+
+/// The R class of the handles returned by `config_new()`.
+const CLASS: &str = "helloextendr_config";
+
+/// The name of the task callback that polls for changes.
+const TASK: &str = "helloextendr_config";
+
+/// Where a value came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Option(String),
+    EnvVar(String),
+    File(PathBuf),
+}
+
+impl Source {
+    /// `"option"`, `"envvar"` or `"file"`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Source::Option(_) => "option",
+            Source::EnvVar(_) => "envvar",
+            Source::File(_) => "file",
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Option(name) => write!(f, "option `{name}`"),
+            Source::EnvVar(name) => write!(f, "environment variable `{name}`"),
+            Source::File(path) => write!(f, "config file {}", path.display()),
+        }
+    }
+}
+
+/// The settings of a package.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    package: String,
+    file: Option<PathBuf>,
+}
+
+impl Config {
+    /// The settings of `package`, from options and environment variables
+    /// only.
+    pub fn new(package: impl Into<String>) -> Self {
+        Self {
+            package: package.into(),
+            file: None,
+        }
+    }
+
+    /// Also read the config file at `path`, which need not exist.
+    pub fn file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.file = Some(path.into());
+        self
+    }
+
+    pub fn package(&self) -> &str {
+        &self.package
+    }
+
+    /// The name of the R option for `key`.
+    pub fn option_name(&self, key: &str) -> String {
+        format!("{}.{key}", self.package)
+    }
+
+    /// The name of the environment variable for `key`.
+    pub fn env_var_name(&self, key: &str) -> String {
+        format!("{}_{key}", self.package)
+            .chars()
+            .map(|c| match c {
+                '.' | '-' => '_',
+                c => c.to_ascii_uppercase(),
+            })
+            .collect()
+    }
+
+    /// The value of `key` as text, and where it came from. Must be called
+    /// on the main thread.
+    pub fn lookup(&self, key: &str) -> Result<Option<(String, Source)>> {
+        check_key(key)?;
+        let option = self.option_name(key);
+        if let Some(value) = option_text(&option)? {
+            return Ok(Some((value, Source::Option(option))));
+        }
+        let var = self.env_var_name(key);
+        if let Ok(value) = std::env::var(&var) {
+            return Ok(Some((value, Source::EnvVar(var))));
+        }
+        if let Some(path) = &self.file {
+            if let Some(value) = read_file(path)?.remove(key) {
+                return Ok(Some((value, Source::File(path.clone()))));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The value of `key` parsed as a `T`.
+    pub fn get<T: FromStr>(&self, key: &str) -> Result<Option<T>>
+    where
+        T::Err: fmt::Display,
+    {
+        match self.lookup(key)? {
+            Some((value, source)) => {
+                value.trim().parse().map(Some).map_err(|e| {
+                    Error::Other(format!("invalid value \"{value}\" of {source}: {e}"))
+                })
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_string(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.lookup(key)?.map(|(value, _)| value))
+    }
+
+    /// The value of `key` as a boolean, from `true`, `false`, `yes`, `no`,
+    /// `on`, `off`, `1` or `0` in any case.
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>> {
+        match self.lookup(key)? {
+            Some((value, source)) => match value.trim().to_ascii_lowercase().as_str() {
+                "true" | "yes" | "on" | "1" => Ok(Some(true)),
+                "false" | "no" | "off" | "0" => Ok(Some(false)),
+                _ => Err(Error::Other(format!(
+                    "invalid value \"{value}\" of {source}: expected true or false"
+                ))),
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_int(&self, key: &str) -> Result<Option<i64>> {
+        self.get(key)
+    }
+
+    pub fn get_double(&self, key: &str) -> Result<Option<f64>> {
+        self.get(key)
+    }
+
+    /// Call `callback` with the key and its new value, `None` once unset,
+    /// whenever [`poll()`] sees the value of `key` change. Returns the id to
+    /// pass to [`unwatch()`]. Must be called on the main thread.
+    pub fn on_change(
+        &self,
+        key: &str,
+        callback: impl FnMut(&str, Option<&str>) + 'static,
+    ) -> Result<u32> {
+        let last = self.get_string(key)?;
+        let id = NEXT_ID.with(|next| {
+            let id = next.get();
+            next.set(id + 1);
+            id
+        });
+        if WATCHES.with(|watches| watches.borrow().is_empty()) {
+            add_task_callback()?;
+        }
+        WATCHES.with(|watches| {
+            watches.borrow_mut().insert(
+                id,
+                Watch {
+                    config: self.clone(),
+                    key: key.to_string(),
+                    last,
+                    callback: Rc::new(RefCell::new(callback)),
+                },
+            )
+        });
+        Ok(id)
+    }
+}
+
+fn check_key(key: &str) -> Result<()> {
+    if key.is_empty()
+        || !key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c))
+    {
+        return Err(Error::Other(format!(
+            "invalid config key \"{key}\"; use letters, digits, `.`, `_` and `-`"
+        )));
+    }
+    Ok(())
+}
+
+/// The R option `name` as text, `None` if it is unset or `NA`.
+fn option_text(name: &str) -> Result<Option<String>> {
+    let value = lang!("getOption", name).eval()?;
+    if value.is_null() {
+        return Ok(None);
+    }
+    let invalid = || Error::Other(format!("option `{name}` must be a single value"));
+    if value.len() != 1 {
+        return Err(invalid());
+    }
+    Ok(match value.rtype() {
+        Rtype::Strings => value
+            .as_str()
+            .filter(|_| !value.is_na())
+            .map(str::to_string),
+        Rtype::Logicals => value
+            .as_bool()
+            .map(|b| if b { "true" } else { "false" }.to_string()),
+        Rtype::Integers => value
+            .as_integer()
+            .filter(|&i| !is_na_integer(i))
+            .map(|i| i.to_string()),
+        Rtype::Doubles => value
+            .as_real()
+            .filter(|x| !x.is_nan())
+            .map(|x| x.to_string()),
+        _ => return Err(invalid()),
+    })
+}
+
+/// The entries of a config file, none if it does not exist.
+fn read_file(path: &Path) -> Result<HashMap<String, String>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(Error::Other(format!("{}: {e}", path.display()))),
+    };
+    parse_file(&text)
+        .map_err(|(line, problem)| Error::Other(format!("{}:{line}: {problem}", path.display())))
+}
+
+/// Parse `key = value` lines, skipping blank lines and `#` comments.
+/// Values may be quoted to keep surrounding spaces or a `#`. Errors carry
+/// the line number.
+pub fn parse_file(text: &str) -> std::result::Result<HashMap<String, String>, (usize, String)> {
+    let mut entries = HashMap::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| (i + 1, "expected `key = value`".to_string()))?;
+        let key = key.trim();
+        check_key(key).map_err(|e| (i + 1, e.to_string()))?;
+        let value = value.trim();
+        let value = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..]
+                .split_once(quote)
+                .map(|(value, _)| value)
+                .ok_or_else(|| (i + 1, "unclosed quote".to_string()))?,
+            _ => value
+                .split_once('#')
+                .map_or(value, |(value, _)| value)
+                .trim_end(),
+        };
+        entries.insert(key.to_string(), value.to_string());
+    }
+    Ok(entries)
+}
+
+type Callback = Rc<RefCell<dyn FnMut(&str, Option<&str>)>>;
+
+struct Watch {
+    config: Config,
+    key: String,
+    last: Option<String>,
+    callback: Callback,
+}
+
+thread_local! {
+    // Only ever touched from the main thread.
+    static WATCHES: RefCell<HashMap<u32, Watch>> = RefCell::new(HashMap::new());
+    static NEXT_ID: Cell<u32> = const { Cell::new(1) };
+}
+
+/// Poll after every top-level command, from R's task callbacks.
+fn add_task_callback() -> Result<()> {
+    let poll = lang!(
+        "parse",
+        text = "function(...) { try(helloextendr::config_poll()); TRUE }"
+    )
+    .eval()?
+    .as_expressions()
+    .and_then(|exprs| exprs.values().next())
+    .ok_or_else(|| Error::Other("cannot create the config task callback".into()))?
+    .eval()?;
+    lang!("addTaskCallback", poll, name = TASK).eval()?;
+    Ok(())
+}
+
+/// Stop the callback `id`. Returns whether it existed.
+pub fn unwatch(id: u32) -> Result<bool> {
+    let removed = WATCHES.with(|watches| watches.borrow_mut().remove(&id));
+    if removed.is_some() && WATCHES.with(|watches| watches.borrow().is_empty()) {
+        lang!("removeTaskCallback", TASK).eval()?;
+    }
+    Ok(removed.is_some())
+}
+
+/// Look up the watched keys again and run the callbacks of those whose
+/// value changed. Returns how many ran. Must be called on the main thread.
+pub fn poll() -> Result<usize> {
+    let ids: Vec<u32> = WATCHES.with(|watches| watches.borrow().keys().copied().collect());
+    let mut changed = 0;
+    for id in ids {
+        // Callbacks may remove watches, including those not polled yet.
+        let watch = WATCHES.with(|watches| {
+            watches.borrow().get(&id).map(|w| {
+                (
+                    w.config.clone(),
+                    w.key.clone(),
+                    w.last.clone(),
+                    w.callback.clone(),
+                )
+            })
+        });
+        let (config, key, last, callback) = match watch {
+            Some(watch) => watch,
+            None => continue,
+        };
+        let value = config.get_string(&key)?;
+        if value == last {
+            continue;
+        }
+        WATCHES.with(|watches| {
+            if let Some(watch) = watches.borrow_mut().get_mut(&id) {
+                watch.last = value.clone();
+            }
+        });
+        // A callback that polls from within itself does not run again.
+        let borrowed = callback.try_borrow_mut();
+        if let Ok(mut callback) = borrowed {
+            (*callback)(&key, value.as_deref());
+            changed += 1;
+        }
+    }
+    Ok(changed)
+}
+
+fn config_ref(config: &Robj) -> Result<ExternalPtr<Config>> {
+    if !config.inherits(CLASS) {
+        return Err(Error::Other("expected a config from `config_new()`".into()));
+    }
+    config.clone().try_into()
+}
+
+/// Layered package configuration
+///
+/// `config_new()` describes where the settings of `package` come from. A
+/// setting `key` is read from the option `<package>.<key>`, else from the
+/// environment variable `<PACKAGE>_<KEY>` in upper case with `.` and `-`
+/// replaced by `_`, else from the config file, which has `key = value`
+/// lines and `#` comments. Options set in `.Rprofile` and variables set in
+/// `.Renviron` thus apply, and so do later `options()` and `Sys.setenv()`
+/// calls. The Rust side of the package reads the same settings.
+/// @param package The name of the package.
+/// @param file `NULL`, or the path of a config file, which need not exist.
+/// @return A config to pass to [config_get()] and the other `config_*()`
+///   functions.
+/// @export
+#[extendr]
+fn config_new(package: &str, #[extendr(default = "NULL")] file: Robj) -> Result<Robj> {
+    let mut config = Config::new(package);
+    if let Some(path) = file.as_str() {
+        config = config.file(path);
+    }
+    let mut handle: Robj = ExternalPtr::new(config).into();
+    handle.set_attr("class", CLASS)?;
+    Ok(handle)
+}
+
+/// Read package settings
+///
+/// `config_get()` returns a setting converted to `mode`; logical settings
+/// are written `true`, `false`, `yes`, `no`, `on`, `off`, `1` or `0`.
+/// `config_source()` tells where the setting came from.
+///
+/// `config_on_change()` calls `callback` with the key and its new value,
+/// `NULL` once unset, whenever the setting changes. Changes are looked for
+/// after each top-level command at the console; elsewhere, call
+/// `config_poll()`.
+/// @param config A config created by [config_new()].
+/// @param key The name of the setting.
+/// @param mode `"character"`, `"logical"`, `"integer"` or `"double"`.
+/// @param default The value returned when the setting is not set.
+/// @param callback A function of two arguments.
+/// @param id An id returned by `config_on_change()`.
+/// @return `config_get()` returns the setting or `default`.
+///   `config_source()` returns `NULL` or a list of the `kind` of source,
+///   `"option"`, `"envvar"` or `"file"`, and its `name`.
+///   `config_on_change()` returns the id of the callback, `config_unwatch()`
+///   whether it existed and `config_poll()` the number of callbacks run.
+/// @export
+#[extendr]
+fn config_get(
+    config: Robj,
+    key: &str,
+    #[extendr(default = "\"character\"")] mode: &str,
+    #[extendr(default = "NULL")] default: Robj,
+) -> Result<Robj> {
+    let config = config_ref(&config)?;
+    let value: Option<Robj> = match mode {
+        "character" => config.get_string(key)?.map(|s| r!(s)),
+        "logical" => config.get_bool(key)?.map(|b| r!(b)),
+        "integer" => match config.get_int(key)? {
+            Some(i) => Some(r!(i32::try_from(i)
+                .ok()
+                .filter(|&i| !is_na_integer(i))
+                .ok_or_else(|| Error::Other(format!(
+                    "setting `{key}` is out of the integer range"
+                )))?)),
+            None => None,
+        },
+        "double" => config.get_double(key)?.map(|x| r!(x)),
+        other => return Err(Error::Other(format!(
+            "`mode` must be \"character\", \"logical\", \"integer\" or \"double\", not \"{other}\""
+        ))),
+    };
+    Ok(value.unwrap_or(default))
+}
+
+/// @rdname config_get
+/// @export
+#[extendr]
+fn config_source(config: Robj, key: &str) -> Result<Robj> {
+    Ok(match config_ref(&config)?.lookup(key)? {
+        Some((_, source)) => {
+            let name = match &source {
+                Source::Option(name) | Source::EnvVar(name) => name.clone(),
+                Source::File(path) => path.display().to_string(),
+            };
+            list!(kind = source.kind(), name = name).into()
+        }
+        None => r!(NULL),
+    })
+}
+
+/// @rdname config_get
+/// @export
+#[extendr]
+fn config_on_change(config: Robj, key: &str, callback: Function) -> Result<i32> {
+    let id = config_ref(&config)?.on_change(key, move |key, value| {
+        if let Err(e) = callback.call(pairlist!(key, value.map_or(r!(NULL), |v| r!(v)))) {
+            let _ = lang!("warning", format!("config callback failed: {e}")).eval();
+        }
+    })?;
+    Ok(id as i32)
+}
+
+/// @rdname config_get
+/// @export
+#[extendr]
+fn config_unwatch(id: i32) -> Result<bool> {
+    unwatch(id as u32)
+}
+
+/// @rdname config_get
+/// @export
+#[extendr]
+fn config_poll() -> Result<i32> {
+    Ok(poll()? as i32)
+}
+
+r_module! {
+    mod config;
+    fn config_new;
+    fn config_get;
+    fn config_source;
+    fn config_on_change;
+    fn config_unwatch;
+    fn config_poll;
+}
@@ -0,0 +1,283 @@
+//! Styled console output in the manner of the {cli} package.
+//!
+//! [`Console`] prints rules, headers, alerts and bullet lists through
+//! `Rprintf`, with the same symbols and colours as {cli}. Whether to use
+//! colour and Unicode is decided like {cli} does: explicit options and
+//! environment variables first, then RStudio and Positron, then the terminal.
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+/// Foreground colours, as ANSI SGR codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Red = 31,
+    Green = 32,
+    Yellow = 33,
+    Blue = 34,
+    Cyan = 36,
+    Grey = 90,
+}
+
+/// The marker in front of an item of [`Console::bullets()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bullet {
+    /// `•`
+    Plain,
+    /// `→`
+    Arrow,
+    /// `ℹ`
+    Info,
+    /// `✔`
+    Success,
+    /// `✖`
+    Danger,
+    /// `!`
+    Warning,
+    /// No marker, only indentation.
+    Indent,
+}
+
+/// Output settings of the session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Console {
+    colors: u32,
+    unicode: bool,
+    width: usize,
+}
+
+impl Console {
+    /// Detect colour and Unicode support and the console width.
+    pub fn detect() -> Result<Self> {
+        Ok(Self {
+            colors: num_ansi_colors()?,
+            unicode: is_utf8_output()?,
+            width: console_width()?,
+        })
+    }
+
+    /// Plain ASCII output without colour, e.g. for log files.
+    pub fn plain(width: usize) -> Self {
+        Self {
+            colors: 1,
+            unicode: false,
+            width,
+        }
+    }
+
+    /// Number of colours the console supports; 1 means no colour.
+    pub fn colors(&self) -> u32 {
+        self.colors
+    }
+
+    pub fn unicode(&self) -> bool {
+        self.unicode
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Wrap `text` in ANSI codes if the console supports colour.
+    pub fn paint(&self, text: &str, color: Option<Color>, bold: bool) -> String {
+        if self.colors <= 1 || (color.is_none() && !bold) {
+            return text.to_string();
+        }
+        let mut codes = Vec::new();
+        if bold {
+            codes.push("1".to_string());
+        }
+        if let Some(color) = color {
+            codes.push((color as u8).to_string());
+        }
+        format!("\x1b[{}m{}\x1b[0m", codes.join(";"), text)
+    }
+
+    fn symbol(&self, unicode: &'static str, ascii: &'static str) -> &'static str {
+        if self.unicode {
+            unicode
+        } else {
+            ascii
+        }
+    }
+
+    fn marker(&self, bullet: Bullet) -> String {
+        let (symbol, color) = match bullet {
+            Bullet::Plain => (self.symbol("\u{2022}", "*"), Some(Color::Cyan)),
+            Bullet::Arrow => (self.symbol("\u{2192}", ">"), None),
+            Bullet::Info => (self.symbol("\u{2139}", "i"), Some(Color::Cyan)),
+            Bullet::Success => (self.symbol("\u{2714}", "v"), Some(Color::Green)),
+            Bullet::Danger => (self.symbol("\u{2716}", "x"), Some(Color::Red)),
+            Bullet::Warning => ("!", Some(Color::Yellow)),
+            Bullet::Indent => (" ", None),
+        };
+        self.paint(symbol, color, false)
+    }
+
+    /// A horizontal line across the console with an optional left-aligned
+    /// title, like `cli::rule()`.
+    pub fn format_rule(&self, title: &str) -> String {
+        self.format_titled_rule(title, false)
+    }
+
+    /// A rule with `title` in bold if `bold` is true. The rule is measured
+    /// against the plain title, as the escape codes of the bold one take no
+    /// room on the console.
+    fn format_titled_rule(&self, title: &str, bold: bool) -> String {
+        let line = self.symbol("\u{2500}", "-");
+        if title.is_empty() {
+            return self.paint(&line.repeat(self.width), Some(Color::Grey), false);
+        }
+        let used = 4 + title.chars().count();
+        let rest = self.width.saturating_sub(used);
+        format!(
+            "{} {} {}",
+            self.paint(&line.repeat(2), Some(Color::Grey), false),
+            self.paint(title, None, bold),
+            self.paint(&line.repeat(rest), Some(Color::Grey), false)
+        )
+    }
+
+    /// A single alert line with the marker of `kind`.
+    pub fn format_alert(&self, kind: Bullet, text: &str) -> String {
+        format!("{} {}", self.marker(kind), text)
+    }
+
+    /// Print a rule with a title.
+    pub fn rule(&self, title: &str) {
+        rprintln!("{}", self.format_rule(title));
+    }
+
+    /// Print a top-level header: a rule with a bold title, set off by blank
+    /// lines.
+    pub fn h1(&self, title: &str) {
+        rprintln!();
+        rprintln!("{}", self.format_titled_rule(title, true));
+        rprintln!();
+    }
+
+    /// Print a second-level header: a bold title between short rules.
+    pub fn h2(&self, title: &str) {
+        let line = self.symbol("\u{2500}", "-").repeat(2);
+        rprintln!("{} {} {}", line, self.paint(title, None, true), line);
+    }
+
+    pub fn alert_success(&self, text: &str) {
+        rprintln!("{}", self.format_alert(Bullet::Success, text));
+    }
+
+    pub fn alert_danger(&self, text: &str) {
+        rprintln!("{}", self.format_alert(Bullet::Danger, text));
+    }
+
+    pub fn alert_warning(&self, text: &str) {
+        rprintln!("{}", self.format_alert(Bullet::Warning, text));
+    }
+
+    pub fn alert_info(&self, text: &str) {
+        rprintln!("{}", self.format_alert(Bullet::Info, text));
+    }
+
+    /// Print a list of items, each with its own marker, like
+    /// `cli::cli_bullets()`.
+    pub fn bullets(&self, items: &[(Bullet, &str)]) {
+        for (bullet, text) in items {
+            rprintln!("{}", self.format_alert(*bullet, text));
+        }
+    }
+}
+
+fn option(name: &str) -> Result<Robj> {
+    lang!("getOption", name).eval()
+}
+
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|v| !v.is_empty())
+}
+
+fn as_count(x: &Robj) -> Option<u32> {
+    x.as_real()
+        .or_else(|| x.as_integer().map(f64::from))
+        .filter(|n| *n >= 1.0)
+        .map(|n| n as u32)
+}
+
+/// The number of ANSI colours to use, following `cli::num_ansi_colors()`.
+pub fn num_ansi_colors() -> Result<u32> {
+    if let Some(n) = as_count(&option("cli.num_colors")?) {
+        return Ok(n);
+    }
+    if let Some(n) = env_var("R_CLI_NUM_COLORS").and_then(|v| v.parse().ok()) {
+        return Ok(n);
+    }
+    if env_var("NO_COLOR").is_some() {
+        return Ok(1);
+    }
+    match option("crayon.enabled")?.as_bool() {
+        Some(false) => return Ok(1),
+        Some(true) => return Ok(as_count(&option("crayon.colors")?).unwrap_or(8)),
+        None => {}
+    }
+    if option("knitr.in.progress")?.as_bool() == Some(true) {
+        return Ok(1);
+    }
+    // The RStudio and Positron consoles are not terminals but render colour.
+    if env_var("RSTUDIO").as_deref() == Some("1") && env_var("RSTUDIO_TERM").is_none() {
+        return Ok(env_var("RSTUDIO_CONSOLE_COLOR")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(1));
+    }
+    if env_var("POSITRON").as_deref() == Some("1") {
+        return Ok(256);
+    }
+    let tty = lang!("isatty", lang!("stdout")).eval()?.as_bool();
+    if tty != Some(true) {
+        return Ok(1);
+    }
+    if cfg!(windows) {
+        if env_var("WT_SESSION").is_some() {
+            return Ok(1 << 24);
+        }
+        if env_var("ConEmuANSI").as_deref() == Some("ON") {
+            return Ok(256);
+        }
+    }
+    let term = env_var("TERM").unwrap_or_default();
+    if term.is_empty() || term == "dumb" {
+        return Ok(1);
+    }
+    if matches!(
+        env_var("COLORTERM").as_deref(),
+        Some("truecolor") | Some("24bit")
+    ) {
+        return Ok(1 << 24);
+    }
+    if term.contains("256") {
+        return Ok(256);
+    }
+    Ok(8)
+}
+
+/// Whether Unicode symbols can be printed, following
+/// `cli::is_utf8_output()`.
+pub fn is_utf8_output() -> Result<bool> {
+    if let Some(unicode) = option("cli.unicode")?.as_bool() {
+        return Ok(unicode);
+    }
+    let info: List = lang!("l10n_info").eval()?.try_into()?;
+    let utf8 = info
+        .iter()
+        .find(|(name, _)| *name == "UTF-8")
+        .and_then(|(_, value)| value.as_bool());
+    Ok(utf8 == Some(true))
+}
+
+/// The console width from `cli.width` or `width`, defaulting to 80.
+pub fn console_width() -> Result<usize> {
+    for name in ["cli.width", "width"] {
+        if let Some(n) = as_count(&option(name)?) {
+            return Ok(n as usize);
+        }
+    }
+    Ok(80)
+}
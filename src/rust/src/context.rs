@@ -0,0 +1,137 @@
+//! Isolated evaluation contexts for hosts embedding R.
+//!
+//! A single embedded R session is shared by everything in the process. An
+//! [`EvalContext`] gives each tenant its own environment as a child of the
+//! global environment, its own set of options that are only in force while
+//! its code runs, and undoes any `library()` or `attach()` calls that its code
+//! made on the search path.
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+use crate::lookup::{base_function, exists_local};
+
+/// An evaluation scope with its own variables and options.
+#[derive(Debug, Clone)]
+pub struct EvalContext {
+    name: String,
+    env: Environment,
+    options: Vec<(String, Robj)>,
+    isolate_search_path: bool,
+}
+
+impl EvalContext {
+    /// Create a context whose environment is a fresh child of the global
+    /// environment.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            env: Environment::new_with_parent(global_env()),
+            options: Vec::new(),
+            isolate_search_path: true,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The environment code in this context is evaluated in.
+    pub fn env(&self) -> &Environment {
+        &self.env
+    }
+
+    /// Set an R option that applies only while this context evaluates code.
+    pub fn set_option(&mut self, name: impl Into<String>, value: impl Into<Robj>) -> &mut Self {
+        let name = name.into();
+        let value = value.into();
+        match self.options.iter_mut().find(|(n, _)| *n == name) {
+            Some(entry) => entry.1 = value,
+            None => self.options.push((name, value)),
+        }
+        self
+    }
+
+    /// Whether packages attached during evaluation are detached again
+    /// afterwards. Enabled by default.
+    pub fn isolate_search_path(&mut self, isolate: bool) -> &mut Self {
+        self.isolate_search_path = isolate;
+        self
+    }
+
+    /// Bind `value` to `name` in this context's environment.
+    pub fn assign(&self, name: &str, value: impl Into<Robj>) {
+        self.env.set_local(Symbol::from_string(name), value);
+    }
+
+    /// Look up `name` in this context's environment, but not its parents.
+    pub fn get(&self, name: &str) -> Option<Robj> {
+        if !exists_local(&self.env, name).ok()? {
+            return None;
+        }
+        self.env.local(Symbol::from_string(name)).ok()
+    }
+
+    /// Evaluate a language object or symbol in this context.
+    pub fn eval(&self, expr: &Robj) -> Result<Robj> {
+        self.scoped(|| expr.eval_with_env(&self.env))
+    }
+
+    /// Parse `code` and evaluate each expression in turn, returning the value
+    /// of the last one.
+    pub fn eval_string(&self, code: &str) -> Result<Robj> {
+        let exprs = parse(code)?;
+        self.scoped(|| {
+            let mut result = r!(NULL);
+            for expr in exprs.values() {
+                result = expr.eval_with_env(&self.env)?;
+            }
+            Ok(result)
+        })
+    }
+
+    /// Run `f` with this context's options set and restore the session
+    /// state afterwards, whether or not `f` succeeded.
+    fn scoped<T>(&self, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        let search = if self.isolate_search_path {
+            Some(search_path()?)
+        } else {
+            None
+        };
+        let old_options = if self.options.is_empty() {
+            None
+        } else {
+            let options = Pairlist::from_pairs(self.options.iter().map(|(n, v)| (n.as_str(), v)));
+            Some(base_function("options")?.call(options)?)
+        };
+
+        let result = f();
+
+        if let Some(old) = old_options {
+            base_function("options")?.call(pairlist!(old))?;
+        }
+        if let Some(before) = search {
+            restore_search_path(&before)?;
+        }
+        result
+    }
+}
+
+fn search_path() -> Result<Vec<String>> {
+    lang!("search")
+        .eval()?
+        .as_string_vector()
+        .ok_or_else(|| Error::Other("`search()` did not return a character vector".into()))
+}
+
+/// Detach every search path entry that is not in `before`.
+fn restore_search_path(before: &[String]) -> Result<()> {
+    let now = search_path()?;
+    // Detach from the back so that earlier positions stay valid.
+    for (pos, name) in now.iter().enumerate().rev() {
+        if !before.contains(name) {
+            lang!("detach", pos = (pos + 1) as i32).eval()?;
+        }
+    }
+    Ok(())
+}
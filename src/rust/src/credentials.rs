@@ -0,0 +1,161 @@
+//! Credentials in the operating system's keychain.
+//!
+//! Secrets are stored per service and user name in the macOS keychain, the
+//! Windows credential manager, or the Secret Service (GNOME Keyring,
+//! KWallet) on Linux, instead of in plain environment variables. Secrets
+//! read on the Rust side are held in a [`Secret`], which wipes its memory
+//! when dropped. Secrets returned to R become ordinary R strings, which R
+//! may keep in memory until the session ends.
+//!
+//! The keychains are reached through the native backends of `keyring`,
+//! with the `credentials` feature. Without it, every access fails.
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use std::fmt;
+use zeroize::Zeroizing;
+
+use crate::r_module;
+
+/// A secret read from the keychain, wiped from memory when dropped. Its
+/// `Debug` output does not show it.
+pub struct Secret(Zeroizing<String>);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(..)")
+    }
+}
+
+/// The secret stored for `user` of `service`, if any.
+pub fn get_secret(service: &str, user: &str) -> Result<Option<Secret>> {
+    let password = keychain::get(service, user)?;
+    Ok(password.map(|password| Secret(Zeroizing::new(password))))
+}
+
+/// Store `secret` for `user` of `service`, replacing any previous one.
+pub fn set_secret(service: &str, user: &str, secret: &str) -> Result<()> {
+    keychain::set(service, user, secret)
+}
+
+/// Remove the secret for `user` of `service`. Returns whether there was one.
+pub fn delete_secret(service: &str, user: &str) -> Result<bool> {
+    keychain::delete(service, user)
+}
+
+#[cfg(feature = "credentials")]
+mod keychain {
+    use ::keyring::Entry;
+    use extendr_api::prelude::*;
+    use extendr_api::Result;
+
+    fn entry(service: &str, user: &str) -> Result<Entry> {
+        Entry::new(service, user).map_err(keyring_error)
+    }
+
+    fn keyring_error(e: ::keyring::Error) -> Error {
+        Error::Other(format!("keychain: {e}"))
+    }
+
+    pub(super) fn get(service: &str, user: &str) -> Result<Option<String>> {
+        match entry(service, user)?.get_password() {
+            Ok(password) => Ok(Some(password)),
+            Err(::keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(keyring_error(e)),
+        }
+    }
+
+    pub(super) fn set(service: &str, user: &str, secret: &str) -> Result<()> {
+        entry(service, user)?
+            .set_password(secret)
+            .map_err(keyring_error)
+    }
+
+    pub(super) fn delete(service: &str, user: &str) -> Result<bool> {
+        match entry(service, user)?.delete_credential() {
+            Ok(()) => Ok(true),
+            Err(::keyring::Error::NoEntry) => Ok(false),
+            Err(e) => Err(keyring_error(e)),
+        }
+    }
+}
+
+#[cfg(not(feature = "credentials"))]
+mod keychain {
+    use extendr_api::Result;
+
+    use crate::missing_feature;
+
+    pub(super) fn get(_service: &str, _user: &str) -> Result<Option<String>> {
+        Err(missing_feature("credentials"))
+    }
+
+    pub(super) fn set(_service: &str, _user: &str, _secret: &str) -> Result<()> {
+        Err(missing_feature("credentials"))
+    }
+
+    pub(super) fn delete(_service: &str, _user: &str) -> Result<bool> {
+        Err(missing_feature("credentials"))
+    }
+}
+
+/// The user name of the session, the default for credentials.
+fn default_user(username: &Robj) -> Result<String> {
+    if let Some(user) = username.as_str() {
+        return Ok(user.to_string());
+    }
+    ["USER", "USERNAME", "LOGNAME"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok().filter(|v| !v.is_empty()))
+        .ok_or_else(|| Error::Other("cannot determine the user name; pass `username`".into()))
+}
+
+/// Store credentials in the system keychain
+///
+/// Secrets are kept by the macOS keychain, the Windows credential manager
+/// or the Secret Service on Linux, per service and user name.
+/// `credential_get()` returns the secret as a string, which stays in the
+/// memory of the R session; use it directly rather than assigning it.
+/// @param service The name of the service, such as a host name or API.
+/// @param username `NULL` for the user name of the session, or a user name.
+/// @param password The secret to store.
+/// @return `credential_get()` returns the secret, or `NULL` if there is
+///   none. `credential_set()` returns `NULL` invisibly and
+///   `credential_delete()` whether there was a secret.
+/// @export
+#[extendr]
+fn credential_get(service: &str, #[extendr(default = "NULL")] username: Robj) -> Result<Robj> {
+    let secret = get_secret(service, &default_user(&username)?)?;
+    Ok(secret.map_or_else(|| r!(NULL), |s| s.expose().into()))
+}
+
+/// @rdname credential_get
+/// @export
+#[extendr(invisible)]
+fn credential_set(
+    service: &str,
+    password: &str,
+    #[extendr(default = "NULL")] username: Robj,
+) -> Result<()> {
+    set_secret(service, &default_user(&username)?, password)
+}
+
+/// @rdname credential_get
+/// @export
+#[extendr]
+fn credential_delete(service: &str, #[extendr(default = "NULL")] username: Robj) -> Result<bool> {
+    delete_secret(service, &default_user(&username)?)
+}
+
+r_module! {
+    mod credentials;
+    fn credential_get;
+    fn credential_set;
+    fn credential_delete;
+}
@@ -0,0 +1,368 @@
+//! A compact columnar format for package datasets.
+//!
+//! Packages usually ship datasets as `.rda` files under `data/`, which R
+//! loads whole. Datasets written with [`write_dataset()`] are small binary
+//! files, read back by [`read_dataset()`] without going through R's
+//! serialization: integers, logicals and factor codes are stored as
+//! variable-length integers and strings are dictionary-encoded, so typical
+//! tables take a fraction of their in-memory size.
+//!
+//! `.onLoad()` binds every `inst/rust-data/<name>.hxdf` file of the package
+//! to a promise in its lazy-data environment, so `helloextendr::name` and
+//! the attached `name` read the file on first use only.
+//!
+//! Supported columns are logical, integer, double and character vectors and
+//! factors. Column classes such as `Date` or `ordered` are kept; other
+//! attributes are not.
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::path::Path;
+
+use crate::attrib::{set_compact_row_names, AttribExt};
+use crate::encoding::RstrEncoding;
+use crate::r_module;
+
+const MAGIC: &[u8; 4] = b"HXDF";
+const VERSION: u8 = 1;
+
+const LOGICAL: u8 = 0;
+const INTEGER: u8 = 1;
+const DOUBLE: u8 = 2;
+const CHARACTER: u8 = 3;
+const FACTOR: u8 = 4;
+
+/// Write the data frame `df` to `path`.
+pub fn write_dataset(df: &Robj, path: &Path) -> Result<()> {
+    let columns = df
+        .as_list()
+        .filter(|_| df.inherits("data.frame"))
+        .ok_or_else(|| Error::Other("expected a data frame".into()))?;
+    let nrow = df
+        .attr("row.names")
+        .map_or(0, |row_names| row_names_count(&row_names));
+
+    let mut out = Writer::default();
+    out.bytes(MAGIC);
+    out.bytes(&[VERSION]);
+    out.varint(nrow as u64);
+    out.varint(columns.len() as u64);
+    for (name, column) in columns.iter() {
+        if column.len() != nrow {
+            return Err(Error::Other(format!(
+                "column `{name}` has {} rows instead of {nrow}",
+                column.len()
+            )));
+        }
+        out.string(name);
+        write_column(&mut out, name, &column)?;
+    }
+    std::fs::write(path, out.buf).map_err(|e| Error::Other(format!("{}: {e}", path.display())))
+}
+
+/// The number of rows from a `row.names` attribute, including the compact
+/// `c(NA, -n)` form.
+fn row_names_count(row_names: &Robj) -> usize {
+    match row_names.as_integer_slice() {
+        Some([na, n]) if *na == i32::MIN => n.unsigned_abs() as usize,
+        _ => row_names.len(),
+    }
+}
+
+fn write_column(out: &mut Writer, name: &str, column: &Robj) -> Result<()> {
+    if column.inherits("factor") {
+        out.bytes(&[FACTOR]);
+        write_classes(out, &column.class_vec());
+        let levels = column
+            .attr("levels")
+            .and_then(|l| Strings::try_from(l).ok())
+            .ok_or_else(|| Error::Other(format!("factor `{name}` has no levels")))?;
+        out.varint(levels.len() as u64);
+        for level in levels.iter() {
+            out.string(&level.to_utf8()?);
+        }
+        for &code in column.as_integer_slice().unwrap_or(&[]) {
+            out.zigzag(code);
+        }
+        return Ok(());
+    }
+
+    let class = column.class_vec();
+    match column.rtype() {
+        Rtype::Logicals => {
+            out.bytes(&[LOGICAL]);
+            write_classes(out, &class);
+            let values = column.as_logical_slice().unwrap_or(&[]);
+            let bytes: Vec<u8> = values
+                .iter()
+                .map(|v| match Option::<bool>::from(*v) {
+                    Some(false) => 0,
+                    Some(true) => 1,
+                    None => 2,
+                })
+                .collect();
+            out.bytes(&bytes);
+        }
+        Rtype::Integers => {
+            out.bytes(&[INTEGER]);
+            write_classes(out, &class);
+            for &value in column.as_integer_slice().unwrap_or(&[]) {
+                out.zigzag(value);
+            }
+        }
+        Rtype::Doubles => {
+            out.bytes(&[DOUBLE]);
+            write_classes(out, &class);
+            for value in column.as_real_slice().unwrap_or(&[]) {
+                out.bytes(&value.to_bits().to_le_bytes());
+            }
+        }
+        Rtype::Strings => {
+            out.bytes(&[CHARACTER]);
+            write_classes(out, &class);
+            write_strings(out, &Strings::try_from(column.clone())?)?;
+        }
+        other => {
+            return Err(Error::Other(format!(
+                "column `{name}` of type {other:?} is not supported"
+            )))
+        }
+    }
+    Ok(())
+}
+
+fn write_classes(out: &mut Writer, classes: &[String]) {
+    out.varint(classes.len() as u64);
+    for class in classes {
+        out.string(class);
+    }
+}
+
+/// A dictionary of the distinct strings, then one index per row, with 0 for
+/// `NA`.
+fn write_strings(out: &mut Writer, strings: &Strings) -> Result<()> {
+    let mut dictionary: Vec<String> = Vec::new();
+    let mut ids: HashMap<String, u64> = HashMap::new();
+    let mut indices = Vec::with_capacity(strings.len());
+    for s in strings.iter() {
+        if s.is_na() {
+            indices.push(0);
+            continue;
+        }
+        let s = s.to_utf8()?;
+        let id = match ids.get(s.as_ref()) {
+            Some(&id) => id,
+            None => {
+                dictionary.push(s.to_string());
+                ids.insert(s.into_owned(), dictionary.len() as u64);
+                dictionary.len() as u64
+            }
+        };
+        indices.push(id);
+    }
+    out.varint(dictionary.len() as u64);
+    for s in &dictionary {
+        out.string(s);
+    }
+    for index in indices {
+        out.varint(index);
+    }
+    Ok(())
+}
+
+/// Read a data frame written by [`write_dataset()`].
+pub fn read_dataset(path: &Path) -> Result<Robj> {
+    let bytes =
+        std::fs::read(path).map_err(|e| Error::Other(format!("{}: {e}", path.display())))?;
+    let mut input = Reader { bytes: &bytes };
+    if input.take(4)? != MAGIC {
+        return Err(Error::Other(format!("{} is not a dataset", path.display())));
+    }
+    let version = input.take(1)?[0];
+    if version != VERSION {
+        return Err(Error::Other(format!(
+            "unsupported dataset version {version}"
+        )));
+    }
+    let nrow = input.varint()? as usize;
+    let ncol = input.varint()? as usize;
+
+    let mut names = Vec::with_capacity(ncol);
+    let mut columns = Vec::with_capacity(ncol);
+    for _ in 0..ncol {
+        names.push(input.string()?);
+        columns.push(read_column(&mut input, nrow)?);
+    }
+
+    let mut df: Robj = List::from_names_and_values(names, columns)?.into();
+    set_compact_row_names(&mut df, nrow)?;
+    df.set_attr("class", "data.frame")?;
+    Ok(df)
+}
+
+fn read_column(input: &mut Reader<'_>, nrow: usize) -> Result<Robj> {
+    let tag = input.take(1)?[0];
+    let nclasses = input.varint()? as usize;
+    let classes = (0..nclasses)
+        .map(|_| input.string())
+        .collect::<Result<Vec<_>>>()?;
+    if tag == FACTOR {
+        let nlevels = input.varint()? as usize;
+        let levels = (0..nlevels)
+            .map(|_| input.string())
+            .collect::<Result<Vec<_>>>()?;
+        let codes = (0..nrow)
+            .map(|_| input.zigzag())
+            .collect::<Result<Vec<_>>>()?;
+        let mut column: Robj = codes.into();
+        column.set_attr("levels", levels)?;
+        column.set_attr("class", classes)?;
+        return Ok(column);
+    }
+
+    let mut column: Robj = match tag {
+        LOGICAL => input
+            .take(nrow)?
+            .iter()
+            .map(|&b| match b {
+                0 => Rbool::from(false),
+                1 => Rbool::from(true),
+                _ => Rbool::na_value(),
+            })
+            .collect::<Logicals>()
+            .into(),
+        INTEGER => (0..nrow)
+            .map(|_| input.zigzag())
+            .collect::<Result<Vec<_>>>()?
+            .into(),
+        DOUBLE => (0..nrow)
+            .map(|_| {
+                let bits = input.take(8)?.try_into().unwrap();
+                Ok(f64::from_bits(u64::from_le_bytes(bits)))
+            })
+            .collect::<Result<Vec<_>>>()?
+            .into(),
+        CHARACTER => {
+            let size = input.varint()? as usize;
+            let dictionary = (0..size)
+                .map(|_| input.string())
+                .collect::<Result<Vec<_>>>()?;
+            let strings = (0..nrow)
+                .map(|_| match input.varint()? as usize {
+                    0 => Ok(Rstr::na()),
+                    i => dictionary
+                        .get(i - 1)
+                        .map(|s| Rstr::from(s.as_str()))
+                        .ok_or_else(|| Error::Other("string index out of range".into())),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Strings::from_values(strings).into()
+        }
+        other => return Err(Error::Other(format!("unknown column type {other}"))),
+    };
+    if !classes.is_empty() {
+        column.set_attr("class", classes)?;
+    }
+    Ok(column)
+}
+
+#[derive(Default)]
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// LEB128: seven bits per byte, least significant first.
+    fn varint(&mut self, mut n: u64) {
+        while n >= 0x80 {
+            self.buf.push((n as u8) | 0x80);
+            n >>= 7;
+        }
+        self.buf.push(n as u8);
+    }
+
+    /// Signed values interleaved so that small magnitudes stay short.
+    fn zigzag(&mut self, n: i32) {
+        let n = n as i64;
+        self.varint(((n << 1) ^ (n >> 63)) as u64);
+    }
+
+    fn string(&mut self, s: &str) {
+        self.varint(s.len() as u64);
+        self.bytes(s.as_bytes());
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < n {
+            return Err(Error::Other("dataset file is truncated".into()));
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn varint(&mut self) -> Result<u64> {
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            n |= u64::from(byte & 0x7f) << shift;
+            if byte < 0x80 {
+                return Ok(n);
+            }
+        }
+        Err(Error::Other("malformed integer in dataset file".into()))
+    }
+
+    fn zigzag(&mut self) -> Result<i32> {
+        let n = self.varint()?;
+        Ok(((n >> 1) as i64 ^ -((n & 1) as i64)) as i32)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = self.varint()? as usize;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| Error::Other("invalid UTF-8 in dataset file".into()))
+    }
+}
+
+/// Write a data frame as a compact dataset file
+///
+/// The file can be shipped as `inst/rust-data/<name>.hxdf`, which makes the
+/// data frame available as the lazily loaded dataset `<name>`.
+/// @param df A data frame with logical, integer, double, character or
+///   factor columns.
+/// @param path The file to write.
+/// @return `NULL`, invisibly.
+/// @export
+#[extendr(invisible)]
+fn write_rust_dataset(df: Robj, path: &str) -> Result<()> {
+    write_dataset(&df, Path::new(path))
+}
+
+/// Read a compact dataset file
+///
+/// @param path A file written by [write_rust_dataset()].
+/// @return A data frame.
+/// @export
+#[extendr]
+fn read_rust_dataset(path: &str) -> Result<Robj> {
+    read_dataset(Path::new(path))
+}
+
+r_module! {
+    mod dataset;
+    fn write_rust_dataset;
+    fn read_rust_dataset;
+}
@@ -0,0 +1,231 @@
+//! Deduplicated storage of serialized R values.
+//!
+//! A [`ChunkStore`] saves values under names, as a list of chunks of their
+//! `serialize()` output. Chunk boundaries are set by the content with
+//! FastCDC, so an insertion or change in a value moves only the boundaries
+//! around it: saving a slightly different version of a large value, such
+//! as the next checkpoint of a model or a simulation, writes the few chunks
+//! that changed and reuses all others.
+//!
+//! The store is a directory with every chunk once, as
+//! `chunks/<first two hex digits>/<SHA-256>`, and a manifest per name in
+//! `snapshots/`. Files are written atomically, so a crash leaves at worst
+//! unreferenced chunks, which [`ChunkStore::gc()`] removes.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use sha2::{Digest, Sha256};
+
+use crate::cache::{serialize, write_atomic};
+use crate::raw_io::IntoRaw;
+
+pub use helloextendr_core::Chunker;
+
+/// The first line of manifests, followed by the length of the value.
+const MANIFEST_MAGIC: &str = "HXCDC1";
+
+/// What [`ChunkStore::save()`] wrote.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SaveStats {
+    /// Chunks of the value, and the bytes of its serialization.
+    pub chunks: usize,
+    pub bytes: u64,
+    /// Chunks the store did not have yet, and their bytes.
+    pub new_chunks: usize,
+    pub new_bytes: u64,
+}
+
+/// A directory of deduplicated R values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkStore {
+    dir: PathBuf,
+    chunker: Chunker,
+}
+
+impl ChunkStore {
+    /// Open the store in `dir`, creating the directory if needed.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        for sub in ["chunks", "snapshots"] {
+            let path = dir.join(sub);
+            fs::create_dir_all(&path).map_err(|e| io_error(&path, e))?;
+        }
+        Ok(Self {
+            dir,
+            chunker: Chunker::default(),
+        })
+    }
+
+    /// Split values with `chunker` instead of the default one. Values saved
+    /// with different chunkers share fewer chunks.
+    pub fn chunker(mut self, chunker: Chunker) -> Self {
+        self.chunker = chunker;
+        self
+    }
+
+    fn chunk_path(&self, hash: &str) -> PathBuf {
+        self.dir.join("chunks").join(&hash[..2]).join(hash)
+    }
+
+    fn manifest_path(&self, name: &str) -> Result<PathBuf> {
+        let valid = !name.is_empty()
+            && !name.starts_with('.')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid {
+            return Err(Error::Other(format!(
+                "invalid snapshot name `{name}`: use letters, digits, `-`, `_` and `.`"
+            )));
+        }
+        Ok(self.dir.join("snapshots").join(name))
+    }
+
+    /// Save `value` under `name`, replacing the value saved there before.
+    pub fn save(&self, name: &str, value: &Robj) -> Result<SaveStats> {
+        let manifest_path = self.manifest_path(name)?;
+        let bytes = serialize(value)?;
+        let mut stats = SaveStats {
+            bytes: bytes.len() as u64,
+            ..SaveStats::default()
+        };
+        let mut manifest = format!("{MANIFEST_MAGIC} {}\n", bytes.len());
+        for chunk in self.chunker.chunks(&bytes) {
+            let hash = hex(&Sha256::digest(chunk));
+            let path = self.chunk_path(&hash);
+            if !path.exists() {
+                write_atomic(&path, chunk)?;
+                stats.new_chunks += 1;
+                stats.new_bytes += chunk.len() as u64;
+            }
+            stats.chunks += 1;
+            manifest.push_str(&hash);
+            manifest.push('\n');
+        }
+        write_atomic(&manifest_path, manifest.as_bytes())?;
+        Ok(stats)
+    }
+
+    /// The value saved under `name`, or `None` if there is none.
+    pub fn load(&self, name: &str) -> Result<Option<Robj>> {
+        let (len, hashes) = match self.manifest(name)? {
+            Some(manifest) => manifest,
+            None => return Ok(None),
+        };
+        let mut bytes = Vec::with_capacity(len as usize);
+        for hash in &hashes {
+            let path = self.chunk_path(hash);
+            let chunk = fs::read(&path).map_err(|e| io_error(&path, e))?;
+            if hex(&Sha256::digest(&chunk)) != *hash {
+                return Err(Error::Other(format!(
+                    "{}: chunk is corrupt",
+                    path.display()
+                )));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        if bytes.len() as u64 != len {
+            return Err(Error::Other(format!(
+                "snapshot `{name}` has {} bytes instead of {len}",
+                bytes.len()
+            )));
+        }
+        lang!("unserialize", bytes.into_raw()).eval().map(Some)
+    }
+
+    /// The length and chunk hashes of the value saved under `name`.
+    fn manifest(&self, name: &str) -> Result<Option<(u64, Vec<String>)>> {
+        let path = self.manifest_path(name)?;
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(io_error(&path, e)),
+        };
+        let mut lines = text.lines();
+        let len = lines
+            .next()
+            .and_then(|header| header.strip_prefix(MANIFEST_MAGIC))
+            .and_then(|len| len.trim().parse().ok())
+            .ok_or_else(|| Error::Other(format!("{}: not a snapshot manifest", path.display())))?;
+        let hashes: Vec<String> = lines.map(str::to_string).collect();
+        if let Some(bad) = hashes
+            .iter()
+            .find(|h| h.len() != 64 || !h.bytes().all(|b| b.is_ascii_hexdigit()))
+        {
+            return Err(Error::Other(format!(
+                "{}: invalid chunk hash `{bad}`",
+                path.display()
+            )));
+        }
+        Ok(Some((len, hashes)))
+    }
+
+    /// The names of the saved values, sorted.
+    pub fn names(&self) -> Result<Vec<String>> {
+        let dir = self.dir.join("snapshots");
+        let mut names: Vec<String> = fs::read_dir(&dir)
+            .map_err(|e| io_error(&dir, e))?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|name| !name.starts_with('.'))
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    /// Forget the value saved under `name`. Its chunks stay until
+    /// [`gc()`](Self::gc). Returns whether there was one.
+    pub fn remove(&self, name: &str) -> Result<bool> {
+        let path = self.manifest_path(name)?;
+        match fs::remove_file(&path) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(io_error(&path, e)),
+        }
+    }
+
+    /// Delete the chunks no saved value uses and return how many bytes
+    /// that freed. Must not run while another process saves to the store.
+    pub fn gc(&self) -> Result<u64> {
+        let mut used = HashSet::new();
+        for name in self.names()? {
+            if let Some((_, hashes)) = self.manifest(&name)? {
+                used.extend(hashes);
+            }
+        }
+        let mut freed = 0;
+        let chunks = self.dir.join("chunks");
+        for prefix in fs::read_dir(&chunks).map_err(|e| io_error(&chunks, e))? {
+            let prefix = prefix.map_err(|e| io_error(&chunks, e))?.path();
+            let entries = match fs::read_dir(&prefix) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries.filter_map(|entry| entry.ok()) {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if used.contains(&name) {
+                    continue;
+                }
+                // Also removes temporary files left by interrupted saves.
+                let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+                let path = entry.path();
+                fs::remove_file(&path).map_err(|e| io_error(&path, e))?;
+                freed += size;
+            }
+        }
+        Ok(freed)
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn io_error(path: &Path, e: io::Error) -> Error {
+    Error::Other(format!("{}: {e}", path.display()))
+}
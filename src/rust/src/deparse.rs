@@ -0,0 +1,293 @@
+//! Deparsing and formatting that match base R.
+//!
+//! Messages and generated code read best when they look exactly like what
+//! users see at the R console, so rather than reimplementing R's rules these
+//! helpers call `deparse()` and `format()` with explicit options.
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+use crate::lookup::base_function;
+
+/// Options for [`deparse_with()`], mirroring the arguments of `deparse()`.
+///
+/// The defaults are those of `deparse()`: a width cutoff of 60 bytes and the
+/// `"keepNA"`, `"keepInteger"`, `"niceNames"` and `"showAttributes"` control
+/// flags.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeparseOptions {
+    width_cutoff: i32,
+    backtick: Option<bool>,
+    nlines: Option<i32>,
+    keep_na: bool,
+    keep_integer: bool,
+    nice_names: bool,
+    show_attributes: bool,
+    digits17: bool,
+    hex_numeric: bool,
+    quote_expressions: bool,
+    use_source: bool,
+}
+
+impl Default for DeparseOptions {
+    fn default() -> Self {
+        Self {
+            width_cutoff: 60,
+            backtick: None,
+            nlines: None,
+            keep_na: true,
+            keep_integer: true,
+            nice_names: true,
+            show_attributes: true,
+            digits17: false,
+            hex_numeric: false,
+            quote_expressions: false,
+            use_source: false,
+        }
+    }
+}
+
+impl DeparseOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Target line width in bytes, between 20 and 500 (`width.cutoff`).
+    pub fn width(mut self, width: i32) -> Self {
+        self.width_cutoff = width.clamp(20, 500);
+        self
+    }
+
+    /// Quote non-syntactic names in backticks. By default R does so only for
+    /// calls and functions.
+    pub fn backtick(mut self, backtick: bool) -> Self {
+        self.backtick = Some(backtick);
+        self
+    }
+
+    /// Stop after this many lines.
+    pub fn nlines(mut self, nlines: i32) -> Self {
+        self.nlines = Some(nlines);
+        self
+    }
+
+    /// Write typed missing values such as `NA_integer_` (`"keepNA"`).
+    pub fn keep_na(mut self, on: bool) -> Self {
+        self.keep_na = on;
+        self
+    }
+
+    /// Write integers with an `L` suffix (`"keepInteger"`).
+    pub fn keep_integer(mut self, on: bool) -> Self {
+        self.keep_integer = on;
+        self
+    }
+
+    /// Write names as `c(a = 1)` rather than through `structure()`
+    /// (`"niceNames"`).
+    pub fn nice_names(mut self, on: bool) -> Self {
+        self.nice_names = on;
+        self
+    }
+
+    /// Include attributes (`"showAttributes"`).
+    pub fn show_attributes(mut self, on: bool) -> Self {
+        self.show_attributes = on;
+        self
+    }
+
+    /// Write doubles with 17 significant digits so they round-trip exactly
+    /// (`"digits17"`).
+    pub fn digits17(mut self, on: bool) -> Self {
+        self.digits17 = on;
+        self
+    }
+
+    /// Write doubles in hexadecimal notation (`"hexNumeric"`).
+    pub fn hex_numeric(mut self, on: bool) -> Self {
+        self.hex_numeric = on;
+        self
+    }
+
+    /// Wrap language objects in `quote()` (`"quoteExpressions"`).
+    pub fn quote_expressions(mut self, on: bool) -> Self {
+        self.quote_expressions = on;
+        self
+    }
+
+    /// Use the source text of functions when available (`"useSource"`).
+    pub fn use_source(mut self, on: bool) -> Self {
+        self.use_source = on;
+        self
+    }
+
+    fn control(&self) -> Vec<&'static str> {
+        [
+            (self.keep_na, "keepNA"),
+            (self.keep_integer, "keepInteger"),
+            (self.nice_names, "niceNames"),
+            (self.show_attributes, "showAttributes"),
+            (self.digits17, "digits17"),
+            (self.hex_numeric, "hexNumeric"),
+            (self.quote_expressions, "quoteExpressions"),
+            (self.use_source, "useSource"),
+        ]
+        .iter()
+        .filter(|(on, _)| *on)
+        .map(|(_, flag)| *flag)
+        .collect()
+    }
+}
+
+/// Deparse `x` into lines of R code.
+pub fn deparse_lines(x: &Robj, options: &DeparseOptions) -> Result<Vec<String>> {
+    let mut args = vec![
+        ("", quoted(x)),
+        ("width.cutoff", r!(options.width_cutoff)),
+        ("control", r!(options.control())),
+    ];
+    if let Some(backtick) = options.backtick {
+        args.push(("backtick", r!(backtick)));
+    }
+    if let Some(nlines) = options.nlines {
+        args.push(("nlines", r!(nlines)));
+    }
+    base_function("deparse")?
+        .call(Pairlist::from_pairs(args))?
+        .as_string_vector()
+        .ok_or_else(|| Error::Other("`deparse()` did not return a character vector".into()))
+}
+
+/// Deparse `x` into a single string, joining lines with newlines.
+pub fn deparse_with(x: &Robj, options: &DeparseOptions) -> Result<String> {
+    Ok(deparse_lines(x, options)?.join("\n"))
+}
+
+/// Text alignment for [`FormatOptions::justify()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Justify {
+    Left,
+    Right,
+    Centre,
+    None,
+}
+
+impl Justify {
+    fn as_str(self) -> &'static str {
+        match self {
+            Justify::Left => "left",
+            Justify::Right => "right",
+            Justify::Centre => "centre",
+            Justify::None => "none",
+        }
+    }
+}
+
+/// Options for [`format_vector()`], mirroring the arguments of `format()`.
+/// Unset options take their values from R, including `getOption("digits")`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FormatOptions {
+    digits: Option<i32>,
+    nsmall: Option<i32>,
+    width: Option<i32>,
+    justify: Option<Justify>,
+    big_mark: Option<String>,
+    scientific: Option<bool>,
+    trim: bool,
+    na_encode: Option<bool>,
+}
+
+impl FormatOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Significant digits for numbers.
+    pub fn digits(mut self, digits: i32) -> Self {
+        self.digits = Some(digits);
+        self
+    }
+
+    /// Minimum number of digits after the decimal point.
+    pub fn nsmall(mut self, nsmall: i32) -> Self {
+        self.nsmall = Some(nsmall);
+        self
+    }
+
+    /// Minimum field width.
+    pub fn width(mut self, width: i32) -> Self {
+        self.width = Some(width);
+        self
+    }
+
+    /// Alignment of character vectors.
+    pub fn justify(mut self, justify: Justify) -> Self {
+        self.justify = Some(justify);
+        self
+    }
+
+    /// Separator between groups of three digits, such as `","`.
+    pub fn big_mark(mut self, mark: impl Into<String>) -> Self {
+        self.big_mark = Some(mark.into());
+        self
+    }
+
+    /// Force (`true`) or suppress (`false`) scientific notation.
+    pub fn scientific(mut self, scientific: bool) -> Self {
+        self.scientific = Some(scientific);
+        self
+    }
+
+    /// Do not pad numbers to a common width.
+    pub fn trim(mut self, trim: bool) -> Self {
+        self.trim = trim;
+        self
+    }
+
+    /// Whether missing strings are formatted as `"NA"`.
+    pub fn na_encode(mut self, na_encode: bool) -> Self {
+        self.na_encode = Some(na_encode);
+        self
+    }
+}
+
+/// Format the elements of an atomic vector as `format()` does, so that all
+/// elements share a common width and number of digits.
+pub fn format_vector(x: &Robj, options: &FormatOptions) -> Result<Vec<String>> {
+    let mut args = vec![("", x.clone()), ("trim", r!(options.trim))];
+    if let Some(digits) = options.digits {
+        args.push(("digits", r!(digits)));
+    }
+    if let Some(nsmall) = options.nsmall {
+        args.push(("nsmall", r!(nsmall)));
+    }
+    if let Some(width) = options.width {
+        args.push(("width", r!(width)));
+    }
+    if let Some(justify) = options.justify {
+        args.push(("justify", r!(justify.as_str())));
+    }
+    if let Some(mark) = &options.big_mark {
+        args.push(("big.mark", r!(mark.as_str())));
+    }
+    if let Some(scientific) = options.scientific {
+        args.push(("scientific", r!(scientific)));
+    }
+    if let Some(na_encode) = options.na_encode {
+        args.push(("na.encode", r!(na_encode)));
+    }
+    base_function("format")?
+        .call(Pairlist::from_pairs(args))?
+        .as_string_vector()
+        .ok_or_else(|| Error::Other("`format()` did not return a character vector".into()))
+}
+
+/// Protect language objects and symbols from evaluation when they are
+/// passed as arguments of a call.
+pub(crate) fn quoted(x: &Robj) -> Robj {
+    if x.is_language() || x.is_symbol() || x.is_promise() {
+        Language::from_values([sym!(quote), x.clone()]).into()
+    } else {
+        x.clone()
+    }
+}
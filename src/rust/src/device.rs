@@ -0,0 +1,356 @@
+//! Custom R graphics devices in Rust.
+//!
+//! extendr's [`DeviceDriver`] exposes the graphics engine callbacks almost
+//! as they are in `R_ext/GraphicsDevice.h`: every callback receives the raw
+//! `R_GE_gcontext`, and a panic in any of them aborts the R session. The
+//! [`Device`] trait here is the safe counterpart: drawing callbacks receive a
+//! decoded [`Style`], panics are caught and reported instead of unwinding
+//! into R, and [`install_device()`] registers the device with the graphics
+//! engine and makes it the current device.
+//!
+//! All callbacks have a default implementation that draws nothing, so a
+//! device only implements what it supports.
+
+use extendr_api::graphics::{
+    DevDesc, DeviceDescriptor, DeviceDriver, FontFace, LineEnd, LineJoin, R_GE_gcontext,
+    R_GE_lineend, R_GE_linejoin, Raster, TextMetric,
+};
+use extendr_api::prelude::*;
+use std::ffi::CStr;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+pub use extendr_api::graphics::Device as GraphicsDevice;
+
+/// Points per inch, the unit of device coordinates set up by
+/// [`install_device()`].
+pub const POINTS_PER_INCH: f64 = 72.0;
+
+/// A colour in R's packed representation: red in the lowest byte, then
+/// green, blue and alpha.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgba(pub u32);
+
+impl Rgba {
+    pub fn red(self) -> u8 {
+        self.0 as u8
+    }
+
+    pub fn green(self) -> u8 {
+        (self.0 >> 8) as u8
+    }
+
+    pub fn blue(self) -> u8 {
+        (self.0 >> 16) as u8
+    }
+
+    pub fn alpha(self) -> u8 {
+        (self.0 >> 24) as u8
+    }
+
+    /// The colour as `#RRGGBB`, ignoring alpha, as SVG expects it.
+    pub fn to_hex(self) -> String {
+        format!("#{:02X}{:02X}{:02X}", self.red(), self.green(), self.blue())
+    }
+
+    /// `None` for `NA` and fully transparent colours, which mean "do not draw".
+    fn from_r(col: i32) -> Option<Self> {
+        let rgba = Rgba(col as u32);
+        if col == i32::MIN || rgba.alpha() == 0 {
+            None
+        } else {
+            Some(rgba)
+        }
+    }
+}
+
+/// The graphical parameters of a drawing operation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Style {
+    /// Stroke colour; `None` means no border or line.
+    pub color: Option<Rgba>,
+    /// Fill colour; `None` means no fill.
+    pub fill: Option<Rgba>,
+    /// Line width in R's `lwd` unit, 1/96 inch.
+    pub line_width: f64,
+    /// R's packed line type: 0 is solid, -1 is blank. See
+    /// [`Style::dash_pattern()`].
+    pub line_type: i32,
+    pub line_end: LineEnd,
+    pub line_join: LineJoin,
+    pub line_mitre: f64,
+    /// Font size in points, that is `ps * cex`.
+    pub font_size: f64,
+    pub font_face: FontFace,
+    /// Empty for the device's default family.
+    pub font_family: String,
+    /// Line height as a multiple of the font size.
+    pub line_height: f64,
+}
+
+impl Style {
+    fn from_gc(gc: &R_GE_gcontext) -> Self {
+        let family = unsafe { CStr::from_ptr(gc.fontfamily.as_ptr()) };
+        Self {
+            color: Rgba::from_r(gc.col),
+            fill: Rgba::from_r(gc.fill),
+            line_width: gc.lwd,
+            line_type: gc.lty,
+            line_end: match gc.lend {
+                R_GE_lineend::GE_ROUND_CAP => LineEnd::Round,
+                R_GE_lineend::GE_BUTT_CAP => LineEnd::Butt,
+                R_GE_lineend::GE_SQUARE_CAP => LineEnd::Square,
+            },
+            line_join: match gc.ljoin {
+                R_GE_linejoin::GE_ROUND_JOIN => LineJoin::Round,
+                R_GE_linejoin::GE_MITRE_JOIN => LineJoin::Mitre,
+                R_GE_linejoin::GE_BEVEL_JOIN => LineJoin::Bevel,
+            },
+            line_mitre: gc.lmitre,
+            font_size: gc.ps * gc.cex,
+            font_face: match gc.fontface {
+                2 => FontFace::Bold,
+                3 => FontFace::Italic,
+                4 => FontFace::BoldItalic,
+                5 => FontFace::Symbol,
+                _ => FontFace::Plain,
+            },
+            font_family: family.to_string_lossy().into_owned(),
+            line_height: gc.lineheight,
+        }
+    }
+
+    /// Whether lines should not be drawn at all.
+    pub fn is_blank(&self) -> bool {
+        self.color.is_none() || self.line_type == -1
+    }
+
+    /// Dash and gap lengths, alternating, in multiples of the line width.
+    /// `None` for solid and blank lines.
+    pub fn dash_pattern(&self) -> Option<Vec<u8>> {
+        if self.line_type == 0 || self.line_type == -1 {
+            return None;
+        }
+        let mut lty = self.line_type as u32;
+        let mut dashes = Vec::new();
+        while lty != 0 && dashes.len() < 8 {
+            dashes.push((lty & 0xF) as u8);
+            lty >>= 4;
+        }
+        Some(dashes)
+    }
+}
+
+/// A raster image in row-major order, starting at the top-left pixel.
+#[derive(Debug, Clone, Copy)]
+pub struct Image<'a> {
+    pub pixels: &'a [u32],
+    pub width: usize,
+    pub height: usize,
+}
+
+/// A graphics device. Coordinates are in device units; with
+/// [`install_device()`] these are points, with the origin in the top-left
+/// corner and y growing downwards.
+#[allow(unused_variables)]
+pub trait Device {
+    /// Start a new page, cleared to `fill` if it is given.
+    fn new_page(&mut self, fill: Option<Rgba>) {}
+
+    fn line(&mut self, from: (f64, f64), to: (f64, f64), style: &Style) {}
+
+    fn polyline(&mut self, points: &[(f64, f64)], style: &Style) {}
+
+    fn polygon(&mut self, points: &[(f64, f64)], style: &Style) {}
+
+    /// A rectangle between two opposite corners.
+    fn rect(&mut self, from: (f64, f64), to: (f64, f64), style: &Style) {}
+
+    fn circle(&mut self, center: (f64, f64), radius: f64, style: &Style) {}
+
+    /// Draw `text` anchored at `pos`, rotated anticlockwise by `angle`
+    /// degrees. `hadj` is the horizontal adjustment: 0 for left-aligned, 0.5
+    /// for centred and 1 for right-aligned.
+    fn text(&mut self, pos: (f64, f64), text: &str, angle: f64, hadj: f64, style: &Style) {}
+
+    /// Ascent, descent and width of `c`. Devices that cannot measure text
+    /// must return zeros, as the default does.
+    fn char_metric(&mut self, c: char, style: &Style) -> TextMetric {
+        TextMetric {
+            ascent: 0.0,
+            descent: 0.0,
+            width: 0.0,
+        }
+    }
+
+    /// The width of `text`, by default the sum of the character widths.
+    fn text_width(&mut self, text: &str, style: &Style) -> f64 {
+        text.chars().map(|c| self.char_metric(c, style).width).sum()
+    }
+
+    /// Draw `image` into the rectangle with the bottom-left corner `pos` and
+    /// the given size, rotated anticlockwise by `angle` degrees.
+    fn raster(
+        &mut self,
+        image: Image<'_>,
+        pos: (f64, f64),
+        size: (f64, f64),
+        angle: f64,
+        interpolate: bool,
+    ) {
+    }
+
+    /// Restrict drawing to the rectangle between two opposite corners.
+    fn clip(&mut self, from: (f64, f64), to: (f64, f64)) {}
+
+    /// The device is being closed, e.g. by `dev.off()`. Flush any output here.
+    fn close(&mut self) {}
+}
+
+/// Run a callback, turning a panic into a message since unwinding into the
+/// graphics engine is undefined behaviour.
+fn guard<R>(callback: &str, default: R, f: impl FnOnce() -> R) -> R {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(value) => value,
+        Err(_) => {
+            rprintln!("graphics device panicked in `{callback}()`");
+            default
+        }
+    }
+}
+
+/// Adapts a [`Device`] to extendr's [`DeviceDriver`].
+struct Driver<D>(D);
+
+impl<D: Device> DeviceDriver for Driver<D> {
+    fn new_page(&mut self, gc: R_GE_gcontext, _: DevDesc) {
+        guard("new_page", (), || self.0.new_page(Rgba::from_r(gc.fill)))
+    }
+
+    fn line(&mut self, from: (f64, f64), to: (f64, f64), gc: R_GE_gcontext, _: DevDesc) {
+        guard("line", (), || self.0.line(from, to, &Style::from_gc(&gc)))
+    }
+
+    fn polyline<T: IntoIterator<Item = (f64, f64)>>(
+        &mut self,
+        coords: T,
+        gc: R_GE_gcontext,
+        _: DevDesc,
+    ) {
+        let points: Vec<_> = coords.into_iter().collect();
+        guard("polyline", (), || {
+            self.0.polyline(&points, &Style::from_gc(&gc))
+        })
+    }
+
+    fn polygon<T: IntoIterator<Item = (f64, f64)>>(
+        &mut self,
+        coords: T,
+        gc: R_GE_gcontext,
+        _: DevDesc,
+    ) {
+        let points: Vec<_> = coords.into_iter().collect();
+        guard("polygon", (), || {
+            self.0.polygon(&points, &Style::from_gc(&gc))
+        })
+    }
+
+    fn rect(&mut self, from: (f64, f64), to: (f64, f64), gc: R_GE_gcontext, _: DevDesc) {
+        guard("rect", (), || self.0.rect(from, to, &Style::from_gc(&gc)))
+    }
+
+    fn circle(&mut self, center: (f64, f64), r: f64, gc: R_GE_gcontext, _: DevDesc) {
+        guard("circle", (), || {
+            self.0.circle(center, r, &Style::from_gc(&gc))
+        })
+    }
+
+    fn text(
+        &mut self,
+        pos: (f64, f64),
+        text: &str,
+        angle: f64,
+        hadj: f64,
+        gc: R_GE_gcontext,
+        _: DevDesc,
+    ) {
+        guard("text", (), || {
+            self.0.text(pos, text, angle, hadj, &Style::from_gc(&gc))
+        })
+    }
+
+    fn char_metric(&mut self, c: char, gc: R_GE_gcontext, _: DevDesc) -> TextMetric {
+        let zero = TextMetric {
+            ascent: 0.0,
+            descent: 0.0,
+            width: 0.0,
+        };
+        guard("char_metric", zero, || {
+            self.0.char_metric(c, &Style::from_gc(&gc))
+        })
+    }
+
+    fn text_width(&mut self, text: &str, gc: R_GE_gcontext, _: DevDesc) -> f64 {
+        guard("text_width", 0.0, || {
+            self.0.text_width(text, &Style::from_gc(&gc))
+        })
+    }
+
+    fn raster<T: AsRef<[u32]>>(
+        &mut self,
+        raster: Raster<T>,
+        pos: (f64, f64),
+        size: (f64, f64),
+        angle: f64,
+        interpolate: bool,
+        _: R_GE_gcontext,
+        _: DevDesc,
+    ) {
+        let pixels = raster.pixels.as_ref();
+        let image = Image {
+            pixels,
+            width: raster.width,
+            height: pixels.len().checked_div(raster.width).unwrap_or(0),
+        };
+        guard("raster", (), || {
+            self.0.raster(image, pos, size, angle, interpolate)
+        })
+    }
+
+    fn clip(&mut self, from: (f64, f64), to: (f64, f64), _: DevDesc) {
+        guard("clip", (), || self.0.clip(from, to))
+    }
+
+    fn close(&mut self, _: DevDesc) {
+        guard("close", (), || self.0.close())
+    }
+}
+
+/// Install `device` as a new graphics device of `width` by `height` inches
+/// and make it the current device.
+///
+/// Device coordinates are points, with the origin in the top-left corner as
+/// raster and SVG formats expect.
+pub fn install_device<D: Device + 'static>(
+    device: D,
+    name: &'static str,
+    width: f64,
+    height: f64,
+) -> GraphicsDevice {
+    let descriptor = DeviceDescriptor::new().device_size(
+        0.0,
+        width * POINTS_PER_INCH,
+        height * POINTS_PER_INCH,
+        0.0,
+    );
+    install_device_with(device, name, descriptor)
+}
+
+/// Install `device` with a custom descriptor, for full control over its
+/// size, coordinate system, resolution and initial parameters.
+pub fn install_device_with<D: Device + 'static>(
+    device: D,
+    name: &'static str,
+    descriptor: DeviceDescriptor,
+) -> GraphicsDevice {
+    Driver(device).create_device::<Driver<D>>(descriptor, name)
+}
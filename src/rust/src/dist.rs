@@ -0,0 +1,219 @@
+//! Distance matrices, as `stats::dist()`.
+//!
+//! [`distances()`] computes the distances between all pairs of rows of a
+//! matrix on worker threads, one column of the lower triangle per task,
+//! and [`dist_robj()`] wraps them in an object of class `dist` that
+//! `hclust()`, `cmdscale()` and `as.matrix()` accept.
+//!
+//! Missing values are handled as `dist()` handles them: coordinates
+//! missing in either row are left out and the sum over the others is
+//! scaled up by the proportion left out, and the distance is `NA` if no
+//! coordinate is left.
+
+use std::str::FromStr;
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+use crate::attrib::AttribExt;
+use crate::parallel::{par_map_slice, ParallelOptions};
+use crate::r_module;
+use crate::xlen::robj_to_length;
+
+/// How the distance between two rows is measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    /// The square root of the sum of squared differences.
+    Euclidean,
+    /// The sum of absolute differences.
+    Manhattan,
+    /// One minus the cosine of the angle between the rows.
+    Cosine,
+    /// The number of coordinates that differ.
+    Hamming,
+}
+
+impl Metric {
+    /// The name of the metric, as in the `method` attribute.
+    pub fn name(self) -> &'static str {
+        match self {
+            Metric::Euclidean => "euclidean",
+            Metric::Manhattan => "manhattan",
+            Metric::Cosine => "cosine",
+            Metric::Hamming => "hamming",
+        }
+    }
+
+    /// The distance between the rows `a` and `b`.
+    pub fn distance(self, a: &[f64], b: &[f64]) -> f64 {
+        if self == Metric::Cosine {
+            return cosine(a, b);
+        }
+        let mut sum = 0.0;
+        let mut count = 0;
+        for (&x, &y) in a.iter().zip(b) {
+            if x.is_nan() || y.is_nan() {
+                continue;
+            }
+            let dev = match self {
+                Metric::Euclidean => (x - y) * (x - y),
+                Metric::Manhattan => (x - y).abs(),
+                _ => f64::from(u8::from(x != y)),
+            };
+            // Inf - Inf, as dist() does.
+            if !dev.is_nan() {
+                sum += dev;
+                count += 1;
+            }
+        }
+        if count == 0 {
+            return <f64 as CanBeNA>::na();
+        }
+        if count != a.len() {
+            sum /= count as f64 / a.len() as f64;
+        }
+        if self == Metric::Euclidean {
+            sum.sqrt()
+        } else {
+            sum
+        }
+    }
+}
+
+fn cosine(a: &[f64], b: &[f64]) -> f64 {
+    let (mut dot, mut aa, mut bb) = (0.0, 0.0, 0.0);
+    let mut count = 0;
+    for (&x, &y) in a.iter().zip(b) {
+        if !x.is_nan() && !y.is_nan() {
+            dot += x * y;
+            aa += x * x;
+            bb += y * y;
+            count += 1;
+        }
+    }
+    if count == 0 {
+        <f64 as CanBeNA>::na()
+    } else {
+        1.0 - dot / (aa.sqrt() * bb.sqrt())
+    }
+}
+
+impl FromStr for Metric {
+    type Err = Error;
+
+    /// Parse an R-side `method` argument.
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "euclidean" => Ok(Metric::Euclidean),
+            "manhattan" => Ok(Metric::Manhattan),
+            "cosine" => Ok(Metric::Cosine),
+            "hamming" => Ok(Metric::Hamming),
+            _ => Err(Error::Other(format!(
+                "`method` must be \"euclidean\", \"manhattan\", \"cosine\" or \"hamming\", not \"{s}\""
+            ))),
+        }
+    }
+}
+
+/// The distances between the rows of the column-major `nrow` by `ncol`
+/// matrix `data`, in the order of a `dist` object: the lower triangle by
+/// columns.
+pub fn distances(
+    data: &[f64],
+    nrow: usize,
+    ncol: usize,
+    metric: Metric,
+    options: ParallelOptions,
+) -> Result<Vec<f64>> {
+    if data.len() != nrow * ncol {
+        return Err(Error::Other(format!(
+            "{} values for a {nrow} by {ncol} matrix",
+            data.len()
+        )));
+    }
+    // Rows are read over and over, so they are made contiguous first.
+    let mut rows = vec![0.0; data.len()];
+    for (k, column) in data.chunks(nrow.max(1)).enumerate() {
+        for (i, &x) in column.iter().enumerate() {
+            rows[i * ncol + k] = x;
+        }
+    }
+    let row = |i: usize| &rows[i * ncol..(i + 1) * ncol];
+    let columns: Vec<usize> = (0..nrow.saturating_sub(1)).collect();
+    let triangle = par_map_slice(&columns, options, |&i| {
+        (i + 1..nrow)
+            .map(|j| metric.distance(row(i), row(j)))
+            .collect::<Vec<f64>>()
+    })?;
+    Ok(triangle.into_iter().flatten().collect())
+}
+
+/// An object of class `dist` for `size` observations, named `labels`.
+pub fn dist_robj(
+    values: Vec<f64>,
+    size: usize,
+    labels: Option<Robj>,
+    metric: Metric,
+) -> Result<Robj> {
+    let size = i32::try_from(size)
+        .map_err(|_| Error::Other(format!("{size} observations are too many for dist")))?;
+    let mut dist = Robj::from(Doubles::from_values(values));
+    dist.set_attr("Size", size)?;
+    if let Some(labels) = labels {
+        dist.set_attr("Labels", labels)?;
+    }
+    dist.set_attr("Diag", false)?;
+    dist.set_attr("Upper", false)?;
+    dist.set_attr("method", metric.name())?;
+    dist.set_attr("class", "dist")?;
+    Ok(dist)
+}
+
+/// Distances between the rows of a matrix, in parallel
+///
+/// Computes the same distances as [stats::dist()] on several threads, and
+/// adds the cosine and Hamming distances. Missing values are left out as
+/// `dist()` leaves them out.
+/// @param x A numeric matrix.
+/// @param method One of `"euclidean"`, `"manhattan"`, `"cosine"` and
+///   `"hamming"`.
+/// @param threads `NULL` for one thread per CPU, or a number of threads.
+/// @return An object of class `dist`, labelled with the row names of `x`.
+/// @export
+#[extendr]
+fn parallel_dist(
+    x: Robj,
+    #[extendr(default = "\"euclidean\"")] method: &str,
+    #[extendr(default = "NULL")] threads: Robj,
+) -> Result<Robj> {
+    let metric: Metric = method.parse()?;
+    let (nrow, ncol) = match x.dims()?.as_deref() {
+        Some(&[nrow, ncol]) => (nrow, ncol),
+        _ => return Err(Error::Other("`x` must be a numeric matrix".into())),
+    };
+    let data: Vec<f64> = if let Some(data) = x.as_real_slice() {
+        data.to_vec()
+    } else if let Some(data) = x.as_integer_slice() {
+        data.iter()
+            .map(|&v| Option::<i32>::from(Rint::from(v)).map_or(<f64 as CanBeNA>::na(), f64::from))
+            .collect()
+    } else {
+        return Err(Error::Other("`x` must be a numeric matrix".into()));
+    };
+    let mut options = ParallelOptions::new().chunk_size(16);
+    if !threads.is_null() {
+        options = options.threads(robj_to_length(&threads)?);
+    }
+    let values = distances(&data, nrow, ncol, metric, options)?;
+    let labels = x
+        .attr("dimnames")
+        .and_then(|dimnames| List::try_from(dimnames).ok())
+        .and_then(|dimnames| dimnames.elt(0).ok())
+        .filter(|names| !names.is_null());
+    dist_robj(values, nrow, labels, metric)
+}
+
+r_module! {
+    mod dist;
+    fn parallel_dist;
+}
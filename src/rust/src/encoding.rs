@@ -0,0 +1,161 @@
+//! Encoding-aware access to R strings.
+//!
+//! R strings carry an encoding mark: UTF-8, latin1, "bytes" or unmarked,
+//! meaning the native encoding of the session, which is not UTF-8 on older
+//! Windows versions of R. Reading such strings as if they were UTF-8 produces
+//! garbage at best. [`RstrEncoding`] reports the mark of an [`Rstr`] and
+//! converts it to UTF-8 the way R itself does, and [`Utf8Strings`] wraps a
+//! character vector for NA-aware iteration over the converted strings.
+//!
+//! Strings created from Rust `&str`s are always marked as UTF-8.
+
+use extendr_api::prelude::*;
+use extendr_api::robj::GetSexp;
+use extendr_api::Result;
+use extendr_ffi::cetype_t;
+use std::borrow::Cow;
+use std::ffi::CStr;
+
+use crate::rapi::{Rf_getCharCE, Rf_translateCharUTF8};
+use crate::sexp::SexpRef;
+
+/// The encoding mark of an R string (`cetype_t`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Unmarked: the native encoding of the session, or ASCII.
+    Native,
+    Utf8,
+    Latin1,
+    /// Arbitrary bytes that R does not translate.
+    Bytes,
+}
+
+impl From<cetype_t> for Encoding {
+    fn from(ce: cetype_t) -> Self {
+        match ce {
+            cetype_t::CE_UTF8 => Encoding::Utf8,
+            cetype_t::CE_LATIN1 => Encoding::Latin1,
+            cetype_t::CE_BYTES => Encoding::Bytes,
+            _ => Encoding::Native,
+        }
+    }
+}
+
+/// Encoding information and conversion for [`Rstr`].
+pub trait RstrEncoding {
+    /// The encoding the string is marked with.
+    fn encoding(&self) -> Encoding;
+
+    /// The string as UTF-8, translated from its encoding if needed. Fails
+    /// for `NA` and for strings marked as bytes.
+    fn to_utf8(&self) -> Result<Cow<'_, str>>;
+}
+
+impl RstrEncoding for Rstr {
+    fn encoding(&self) -> Encoding {
+        unsafe { Rf_getCharCE(SexpRef::of(self)) }.into()
+    }
+
+    fn to_utf8(&self) -> Result<Cow<'_, str>> {
+        if self.is_na() {
+            return Err(Error::Other("cannot convert NA to a UTF-8 string".into()));
+        }
+        let bytes = unsafe { CStr::from_ptr(extendr_ffi::R_CHAR(self.get())) }.to_bytes();
+        match self.encoding() {
+            // Also covers unmarked ASCII strings, which need no translation.
+            _ if bytes.is_ascii() => Ok(Cow::Borrowed(std::str::from_utf8(bytes).unwrap())),
+            Encoding::Utf8 => std::str::from_utf8(bytes)
+                .map(Cow::Borrowed)
+                .map_err(|e| Error::Other(format!("string marked as UTF-8 is invalid: {e}"))),
+            Encoding::Latin1 => Ok(Cow::Owned(bytes.iter().map(|&b| b as char).collect())),
+            Encoding::Bytes => Err(Error::Other(
+                "strings marked as \"bytes\" have no encoding to convert from".into(),
+            )),
+            Encoding::Native => {
+                // R allocates the translation on its transient stack, so copy it.
+                let utf8 = unsafe { CStr::from_ptr(Rf_translateCharUTF8(SexpRef::of(self))) };
+                Ok(Cow::Owned(utf8.to_string_lossy().into_owned()))
+            }
+        }
+    }
+}
+
+/// A character vector read as UTF-8, with `None` for `NA`.
+#[derive(Debug, Clone)]
+pub struct Utf8Strings {
+    strings: Strings,
+}
+
+impl Utf8Strings {
+    pub fn new(strings: Strings) -> Self {
+        Self { strings }
+    }
+
+    /// Build a character vector of UTF-8 marked strings.
+    pub fn from_values<'a>(values: impl IntoIterator<Item = Option<&'a str>>) -> Self {
+        let strings = values
+            .into_iter()
+            .map(|v| match v {
+                Some(s) => Rstr::from(s),
+                None => Rstr::na(),
+            })
+            .collect::<Vec<_>>();
+        Self::new(Strings::from_values(strings))
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.len() == 0
+    }
+
+    /// The element at `i` as UTF-8, `Ok(None)` if it is `NA`.
+    pub fn get(&self, i: usize) -> Result<Option<Cow<'_, str>>> {
+        let rstr = self
+            .strings
+            .as_slice()
+            .get(i)
+            .ok_or_else(|| Error::Other(format!("index {i} is out of bounds")))?;
+        utf8_or_na(rstr)
+    }
+
+    /// Iterate over the elements as UTF-8, with `None` for `NA`.
+    pub fn iter(&self) -> impl Iterator<Item = Result<Option<Cow<'_, str>>>> {
+        self.strings.as_slice().iter().map(utf8_or_na)
+    }
+
+    /// Copy all elements into Rust strings.
+    pub fn to_vec(&self) -> Result<Vec<Option<String>>> {
+        self.iter()
+            .map(|s| s.map(|s| s.map(Cow::into_owned)))
+            .collect()
+    }
+
+    pub fn into_inner(self) -> Strings {
+        self.strings
+    }
+}
+
+fn utf8_or_na(rstr: &Rstr) -> Result<Option<Cow<'_, str>>> {
+    if rstr.is_na() {
+        Ok(None)
+    } else {
+        rstr.to_utf8().map(Some)
+    }
+}
+
+impl TryFrom<Robj> for Utf8Strings {
+    type Error = Error;
+
+    fn try_from(robj: Robj) -> Result<Self> {
+        Ok(Self::new(robj.try_into()?))
+    }
+}
+
+impl From<Utf8Strings> for Robj {
+    fn from(strings: Utf8Strings) -> Self {
+        strings.strings.into()
+    }
+}
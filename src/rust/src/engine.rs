@@ -0,0 +1,337 @@
+//! Embedded R runtime for Rust code running outside of an R session.
+//!
+//! When the package is loaded by R, the interpreter is already running. Under
+//! plain `cargo test` there is no R at all and the first call into the R API
+//! crashes the process. [`start_r()`] boots an embedded interpreter once per
+//! process, and [`with_r()`] and [`test_with_r!`] make it easy to use in tests.
+//!
+//! Never call [`start_r()`] from code that runs inside an R session.
+//!
+//! Embedding R needs entry points outside its API, so the module is left out
+//! of builds with the `cran-strict` feature, where [`test_with_r!`] is a
+//! compile error.
+
+use extendr_ffi::{setup_Rmainloop, Rf_initialize_R};
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Mutex;
+
+extern "C" {
+    fn Rf_endEmbeddedR(fatal: c_int);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Stopped,
+    Running,
+    Ended,
+}
+
+static STATE: Mutex<State> = Mutex::new(State::Stopped);
+
+/// Serializes [`with_r()`] bodies so that tests on different threads do not
+/// interleave their R API calls.
+static BODY_LOCK: Mutex<()> = Mutex::new(());
+
+/// Locate the R installation: `R_HOME` if set, otherwise ask `R RHOME`.
+pub fn r_home() -> Option<String> {
+    if let Ok(home) = std::env::var("R_HOME") {
+        if !home.is_empty() {
+            return Some(home);
+        }
+    }
+    let r = if cfg!(windows) { "R.exe" } else { "R" };
+    let out = Command::new(r).arg("RHOME").output().ok()?;
+    let home = String::from_utf8(out.stdout).ok()?.trim().to_string();
+    (!home.is_empty()).then_some(home)
+}
+
+/// Startup options for the embedded interpreter, mirroring the command-line
+/// flags of `R` itself.
+///
+/// The defaults match what tests want: no echo of the console and no saving
+/// of the workspace on exit.
+///
+/// ```ignore
+/// REmbedOptions::new().vanilla(true).max_ppsize(100_000).start();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct REmbedOptions {
+    quiet: bool,
+    echo: bool,
+    save: Option<bool>,
+    restore: bool,
+    vanilla: bool,
+    site_file: bool,
+    init_file: bool,
+    environ: bool,
+    max_ppsize: Option<usize>,
+    max_vsize: Option<String>,
+    site_profile: Option<PathBuf>,
+    user_profile: Option<PathBuf>,
+    args: Vec<String>,
+}
+
+impl Default for REmbedOptions {
+    fn default() -> Self {
+        Self {
+            quiet: false,
+            echo: false,
+            save: Some(false),
+            restore: true,
+            vanilla: false,
+            site_file: true,
+            init_file: true,
+            environ: true,
+            max_ppsize: None,
+            max_vsize: None,
+            site_profile: None,
+            user_profile: None,
+            args: Vec::new(),
+        }
+    }
+}
+
+impl REmbedOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Suppress the startup banner (`--quiet`).
+    pub fn quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// Echo input and print the console as an interactive session would.
+    /// Disabled by default (`--slave`).
+    pub fn echo(mut self, echo: bool) -> Self {
+        self.echo = echo;
+        self
+    }
+
+    /// Save (`--save`) or discard (`--no-save`) the workspace on exit, or
+    /// `None` to pass neither flag.
+    pub fn save(mut self, save: Option<bool>) -> Self {
+        self.save = save;
+        self
+    }
+
+    /// Restore a saved `.RData` on startup; `false` passes `--no-restore`.
+    pub fn restore(mut self, restore: bool) -> Self {
+        self.restore = restore;
+        self
+    }
+
+    /// Skip all startup and workspace files (`--vanilla`).
+    pub fn vanilla(mut self, vanilla: bool) -> Self {
+        self.vanilla = vanilla;
+        self
+    }
+
+    /// Read the site profile; `false` passes `--no-site-file`.
+    pub fn site_file(mut self, site_file: bool) -> Self {
+        self.site_file = site_file;
+        self
+    }
+
+    /// Read the user profile; `false` passes `--no-init-file`.
+    pub fn init_file(mut self, init_file: bool) -> Self {
+        self.init_file = init_file;
+        self
+    }
+
+    /// Read `.Renviron` files; `false` passes `--no-environ`.
+    pub fn environ(mut self, environ: bool) -> Self {
+        self.environ = environ;
+        self
+    }
+
+    /// Size of the pointer protection stack (`--max-ppsize`).
+    pub fn max_ppsize(mut self, size: usize) -> Self {
+        self.max_ppsize = Some(size);
+        self
+    }
+
+    /// Limit on the vector heap, such as `"4Gb"`, passed as `R_MAX_VSIZE`.
+    pub fn max_vsize(mut self, size: impl Into<String>) -> Self {
+        self.max_vsize = Some(size.into());
+        self
+    }
+
+    /// Use this file as the site profile instead of `Rprofile.site`
+    /// (`R_PROFILE`).
+    pub fn site_profile(mut self, path: impl Into<PathBuf>) -> Self {
+        self.site_profile = Some(path.into());
+        self
+    }
+
+    /// Use this file as the user profile instead of `.Rprofile`
+    /// (`R_PROFILE_USER`).
+    pub fn user_profile(mut self, path: impl Into<PathBuf>) -> Self {
+        self.user_profile = Some(path.into());
+        self
+    }
+
+    /// Pass an extra argument through to R verbatim.
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Pass several extra arguments through to R verbatim.
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// The argument vector handed to R, starting with the program name.
+    pub fn argv(&self) -> Vec<String> {
+        let mut argv = vec!["R".to_string()];
+        let mut flag = |on: bool, name: &str| {
+            if on {
+                argv.push(name.to_string());
+            }
+        };
+        flag(cfg!(all(windows, target_arch = "x86")), "--arch=i386");
+        flag(self.vanilla, "--vanilla");
+        flag(self.quiet, "--quiet");
+        flag(!self.echo, "--slave");
+        flag(self.save == Some(true), "--save");
+        flag(self.save == Some(false), "--no-save");
+        flag(!self.restore, "--no-restore");
+        flag(!self.site_file, "--no-site-file");
+        flag(!self.init_file, "--no-init-file");
+        flag(!self.environ, "--no-environ");
+        if let Some(size) = self.max_ppsize {
+            argv.push(format!("--max-ppsize={size}"));
+        }
+        argv.extend(self.args.iter().cloned());
+        argv
+    }
+
+    /// Start the embedded interpreter with these options.
+    ///
+    /// Options only take effect on the call that actually starts R.
+    pub fn start(&self) {
+        start_r_with(self)
+    }
+}
+
+/// Start the embedded R interpreter with default options if it is not
+/// running yet.
+///
+/// This is cheap to call repeatedly; only the first call initializes R.
+/// Panics if R cannot be found or if it was already shut down with
+/// [`end_r()`], since R cannot be restarted within a process.
+pub fn start_r() {
+    start_r_with(&REmbedOptions::default())
+}
+
+/// Like [`start_r()`], but with explicit startup options.
+pub fn start_r_with(options: &REmbedOptions) {
+    let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    match *state {
+        State::Running => return,
+        State::Ended => panic!("the embedded R session has ended and cannot be restarted"),
+        State::Stopped => {}
+    }
+
+    let home = r_home().expect("cannot find R: set `R_HOME` or put `R` on the `PATH`");
+    std::env::set_var("R_HOME", home);
+    if let Some(size) = &options.max_vsize {
+        std::env::set_var("R_MAX_VSIZE", size);
+    }
+    if let Some(path) = &options.site_profile {
+        std::env::set_var("R_PROFILE", path);
+    }
+    if let Some(path) = &options.user_profile {
+        std::env::set_var("R_PROFILE_USER", path);
+    }
+
+    let args: Vec<CString> = options
+        .argv()
+        .into_iter()
+        .map(|a| CString::new(a).expect("R arguments must not contain NUL bytes"))
+        .collect();
+    // R keeps pointers into argv, so the strings must outlive the session.
+    let mut argv: Vec<*mut c_char> = args.into_iter().map(CString::into_raw).collect();
+
+    unsafe {
+        // `Rf_initEmbeddedR()` relies on `__libc_stack_end`, which is not
+        // usable from Rust, so perform the same steps by hand.
+        Rf_initialize_R(argv.len() as c_int, argv.as_mut_ptr());
+        // Tests run on worker threads whose stacks R knows nothing about.
+        if cfg!(not(windows)) {
+            extendr_ffi::R_CStackLimit = usize::MAX;
+        }
+        setup_Rmainloop();
+    }
+    std::mem::forget(argv);
+    *state = State::Running;
+}
+
+/// Shut down the embedded R interpreter started by [`start_r()`].
+///
+/// Runs exit finalizers and removes the session's temporary directory. Does
+/// nothing if R was never started.
+pub fn end_r() {
+    let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    if *state == State::Running {
+        unsafe { Rf_endEmbeddedR(0) };
+        *state = State::Ended;
+    }
+}
+
+/// Run `f` with the embedded R interpreter available.
+///
+/// Calls are serialized across threads, and a panic in one call does not
+/// affect later ones.
+pub fn with_r<F, T>(f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    start_r();
+    let _guard = BODY_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    f()
+}
+
+/// Define `#[test]` functions whose bodies run under [`with_r()`].
+///
+/// The body may use `?` on `extendr_api::Result` values. A test fails if
+/// it leaves [`Preserved`](crate::preserve::Preserved) values alive.
+///
+/// ```ignore
+/// test_with_r! {
+///     fn doubles_round_trip() {
+///         let x: Robj = r!([1.0, 2.0]);
+///         assert_eq!(x.as_real_vector(), Some(vec![1.0, 2.0]));
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! test_with_r {
+    ($($(#[$meta:meta])* fn $name:ident() $body:block)*) => {
+        $(
+            $(#[$meta])*
+            #[test]
+            fn $name() {
+                $crate::engine::with_r(|| -> ::extendr_api::Result<()> {
+                    let since = $crate::preserve::next_id();
+                    (|| -> ::extendr_api::Result<()> {
+                        $body;
+                        Ok(())
+                    })()?;
+                    $crate::preserve::check_released(since)
+                })
+                .unwrap();
+            }
+        )*
+    };
+}
@@ -0,0 +1,141 @@
+//! Running callbacks on R's main thread for events from other threads.
+//!
+//! Background threads (file watchers, subprocess readers) queue their
+//! events themselves and call [`wake()`]. The main thread then runs every
+//! registered dispatcher, each of which drains its own queue, either from
+//! [`run_pending()`] or, on Unix, from R's event loop: a socket registered as
+//! an R input handler becomes readable on `wake()`, so dispatchers run while
+//! the console waits for input. Windows has no such hook and scripts that
+//! never return to the console do not run the event loop; both have to call
+//! `run_pending()`. Neither do builds with the `cran-strict` feature, as
+//! input handlers are not part of R's API.
+
+use extendr_api::Result;
+use std::sync::Mutex;
+
+/// Drains one queue on the main thread and returns how many events it
+/// handled.
+pub type Dispatcher = fn() -> usize;
+
+static DISPATCHERS: Mutex<Vec<Dispatcher>> = Mutex::new(Vec::new());
+
+/// Run `dispatch` whenever the event loop is woken. Registering the same
+/// function again has no effect.
+pub fn register(dispatch: Dispatcher) -> Result<()> {
+    platform::install()?;
+    let mut dispatchers = DISPATCHERS.lock().unwrap_or_else(|e| e.into_inner());
+    if !dispatchers
+        .iter()
+        .any(|d| std::ptr::fn_addr_eq(*d, dispatch))
+    {
+        dispatchers.push(dispatch);
+    }
+    Ok(())
+}
+
+/// Ask the main thread to run the dispatchers. Can be called from any
+/// thread.
+pub fn wake() {
+    platform::wake();
+}
+
+/// Run every dispatcher and return how many events they handled. Must be
+/// called from the main thread.
+pub fn run_pending() -> usize {
+    // Copied out so that dispatchers may register others.
+    let dispatchers = DISPATCHERS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    dispatchers.iter().map(|dispatch| dispatch()).sum()
+}
+
+#[cfg(all(unix, not(feature = "cran-strict")))]
+mod platform {
+    use extendr_api::Result;
+    use std::io::{Read, Write};
+    use std::os::raw::{c_int, c_void};
+    use std::os::unix::io::AsRawFd;
+    use std::os::unix::net::UnixStream;
+    use std::sync::OnceLock;
+
+    // Not exported by `extendr_ffi`; see `R_ext/eventloop.h`. Handlers are
+    // only used through pointers.
+    #[repr(C)]
+    struct InputHandler {
+        _private: [u8; 0],
+    }
+
+    extern "C" {
+        static mut R_InputHandlers: *mut InputHandler;
+        fn addInputHandler(
+            handlers: *mut InputHandler,
+            fd: c_int,
+            handler: extern "C" fn(*mut c_void),
+            activity: c_int,
+        ) -> *mut InputHandler;
+        fn getInputHandler(handlers: *mut InputHandler, fd: c_int) -> *mut InputHandler;
+        fn removeInputHandler(handlers: *mut *mut InputHandler, it: *mut InputHandler) -> c_int;
+    }
+
+    /// Identifies the handler among R's; any value other than R's own
+    /// activities will do.
+    const ACTIVITY: c_int = 21;
+
+    /// The write end wakes R's event loop, the read end is registered with
+    /// it.
+    static SOCKETS: OnceLock<(UnixStream, UnixStream)> = OnceLock::new();
+
+    pub(super) fn install() -> Result<()> {
+        if SOCKETS.get().is_some() {
+            return Ok(());
+        }
+        let (reader, writer) = UnixStream::pair()
+            .and_then(|(reader, writer)| {
+                reader.set_nonblocking(true)?;
+                writer.set_nonblocking(true)?;
+                Ok((reader, writer))
+            })
+            .map_err(|e| extendr_api::Error::Other(format!("cannot create a socket: {e}")))?;
+        let fd = reader.as_raw_fd();
+        let _ = SOCKETS.set((reader, writer));
+        unsafe {
+            addInputHandler(R_InputHandlers, fd, on_input, ACTIVITY);
+        }
+        // R must not call `on_input()` once the library is unloaded.
+        crate::teardown::on_unload("event loop handler", move || unsafe {
+            let handler = getInputHandler(R_InputHandlers, fd);
+            if !handler.is_null() {
+                removeInputHandler(std::ptr::addr_of_mut!(R_InputHandlers), handler);
+            }
+        });
+        Ok(())
+    }
+
+    pub(super) fn wake() {
+        if let Some((_, writer)) = SOCKETS.get() {
+            // A full socket already wakes the loop.
+            let _ = (&*writer).write(&[1]);
+        }
+    }
+
+    extern "C" fn on_input(_: *mut c_void) {
+        if let Some((reader, _)) = SOCKETS.get() {
+            let mut buf = [0u8; 256];
+            while matches!((&*reader).read(&mut buf), Ok(n) if n > 0) {}
+        }
+        // Unwinding into R's event loop would abort.
+        let _ = std::panic::catch_unwind(super::run_pending);
+    }
+}
+
+#[cfg(any(not(unix), feature = "cran-strict"))]
+mod platform {
+    use extendr_api::Result;
+
+    pub(super) fn install() -> Result<()> {
+        Ok(())
+    }
+
+    pub(super) fn wake() {}
+}
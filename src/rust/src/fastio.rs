@@ -0,0 +1,222 @@
+//! Fast saving and reading of R objects.
+//!
+//! `fast_save()` and `fast_read()` do what `saveRDS()` and `readRDS()` do,
+//! several times faster on large objects: the serialization is in the
+//! native byte order, without the conversion to XDR, and it is cut into
+//! blocks that are compressed and decompressed with zstd on all CPUs.
+//!
+//! A file is `HXFST001`, the length of the serialization and the number of
+//! blocks as little-endian `u64` and `u32`, then the compressed and
+//! uncompressed length of each block as `u32` pairs, then the compressed
+//! blocks. Reading can memory-map the file instead of reading it, which
+//! saves a copy of the compressed data and lets the page cache serve it.
+//!
+//! zstd needs the `zstd` feature; without it, saving and reading fail.
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+use crate::parallel::{par_map_slice, ParallelOptions};
+use crate::r_module;
+use crate::raw_io::IntoRaw;
+use crate::xlen::robj_to_length;
+
+use helloextendr_core::fastio::MAGIC;
+
+pub use helloextendr_core::fastio::Blocks;
+
+/// Bytes of serialization per block: large enough for zstd to find
+/// repetitions, small enough to spread over all CPUs.
+const BLOCK_SIZE: usize = 4 << 20;
+
+/// How [`write_object()`] compresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SaveOptions {
+    pub level: i32,
+    pub parallel: ParallelOptions,
+}
+
+impl Default for SaveOptions {
+    /// zstd level 3 on one thread per CPU.
+    fn default() -> Self {
+        Self {
+            level: 3,
+            parallel: ParallelOptions::default(),
+        }
+    }
+}
+
+/// The native `serialize()` output of `value`.
+fn serialize_native(value: &Robj) -> Result<Vec<u8>> {
+    let raw = lang!("serialize", value.clone(), (), xdr = false, version = 3).eval()?;
+    Ok(raw.as_raw_slice().unwrap_or(&[]).to_vec())
+}
+
+/// Serialize `value` and write it to `writer` in the format of
+/// `fast_save()`.
+pub fn write_object(value: &Robj, writer: &mut impl Write, options: SaveOptions) -> Result<()> {
+    let bytes = serialize_native(value)?;
+    let blocks: Vec<&[u8]> = bytes.chunks(BLOCK_SIZE).collect();
+    let level = options.level;
+    let compressed = par_map_slice(&blocks, options.parallel.chunk_size(1), |block| {
+        compress(block, level)
+    })?
+    .into_iter()
+    .collect::<io::Result<Vec<_>>>()
+    .map_err(|e| Error::Other(format!("cannot compress: {e}")))?;
+
+    let mut header = Vec::with_capacity(20 + 8 * blocks.len());
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+    header.extend_from_slice(&(blocks.len() as u32).to_le_bytes());
+    for (block, packed) in blocks.iter().zip(&compressed) {
+        header.extend_from_slice(&(packed.len() as u32).to_le_bytes());
+        header.extend_from_slice(&(block.len() as u32).to_le_bytes());
+    }
+    let mut write = || -> io::Result<()> {
+        writer.write_all(&header)?;
+        for packed in &compressed {
+            writer.write_all(packed)?;
+        }
+        writer.flush()
+    };
+    write().map_err(|e| Error::Other(format!("cannot write: {e}")))
+}
+
+#[cfg(feature = "zstd")]
+fn compress(block: &[u8], level: i32) -> io::Result<Vec<u8>> {
+    zstd::bulk::compress(block, level)
+}
+
+#[cfg(feature = "zstd")]
+fn decompress(block: &[u8], len: usize) -> io::Result<Vec<u8>> {
+    zstd::bulk::decompress(block, len)
+}
+
+#[cfg(not(feature = "zstd"))]
+fn compress(_block: &[u8], _level: i32) -> io::Result<Vec<u8>> {
+    Err(io::Error::other(crate::missing_feature("zstd").to_string()))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn decompress(_block: &[u8], _len: usize) -> io::Result<Vec<u8>> {
+    Err(io::Error::other(crate::missing_feature("zstd").to_string()))
+}
+
+/// The length of the serialization in `data`, the contents of a file
+/// written by [`write_object()`], and its compressed blocks with their
+/// uncompressed lengths.
+pub fn parse_blocks(data: &[u8]) -> Result<(usize, Blocks<'_>)> {
+    helloextendr_core::fastio::parse_blocks(data).map_err(|e| Error::Other(e.to_string()))
+}
+
+fn corrupt() -> Error {
+    Error::Other("not a fast_save() file, or a truncated one".into())
+}
+
+/// Decompress the contents of a file written by [`write_object()`] and
+/// unserialize the object.
+pub fn decode_object(data: &[u8], parallel: ParallelOptions) -> Result<Robj> {
+    let (len, blocks) = parse_blocks(data)?;
+    let unpacked = par_map_slice(&blocks, parallel.chunk_size(1), |&(block, raw)| {
+        decompress(block, raw)
+    })?;
+    let mut bytes = Vec::with_capacity(len);
+    for block in unpacked {
+        let block = block.map_err(|e| Error::Other(format!("cannot decompress: {e}")))?;
+        bytes.extend_from_slice(&block);
+    }
+    if bytes.len() != len {
+        return Err(corrupt());
+    }
+    lang!("unserialize", bytes.into_raw()).eval()
+}
+
+/// Read the object saved in the file at `path`, memory-mapping the file if
+/// `mmap` is true.
+pub fn read_object(path: &Path, mmap: bool, parallel: ParallelOptions) -> Result<Robj> {
+    let io_error = |e: io::Error| Error::Other(format!("{}: {e}", path.display()));
+    let mut file = File::open(path).map_err(io_error)?;
+    if mmap {
+        // Safety: the map is only read, and dropped before returning. A
+        // file truncated meanwhile by another process fails to decompress
+        // or raises SIGBUS, as with any memory-mapped reader.
+        let map = unsafe { memmap2::Mmap::map(&file) }.map_err(io_error)?;
+        decode_object(&map, parallel)
+    } else {
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).map_err(io_error)?;
+        decode_object(&data, parallel)
+    }
+}
+
+fn parallel_options(threads: &Robj) -> Result<ParallelOptions> {
+    let options = ParallelOptions::default();
+    if threads.is_null() {
+        return Ok(options);
+    }
+    Ok(options.threads(robj_to_length(threads)?))
+}
+
+/// Fast object files
+///
+/// `fast_save()` saves an R object to a file and `fast_read()` reads it
+/// back, like `saveRDS()` and `readRDS()` but much faster for large
+/// objects: the serialization is compressed with zstd in blocks, on several
+/// threads. Files are only meant to be read by `fast_read()`, on a machine
+/// with the same byte order.
+/// @param x An R object.
+/// @param path The path of the file.
+/// @param level The zstd compression level, from 1 (fastest) to 22
+///   (smallest). Negative levels compress even faster.
+/// @param threads `NULL` for one thread per CPU, or a number of threads.
+/// @return `fast_save()` returns `path`, invisibly. `fast_read()` returns
+///   the saved object.
+/// @export
+#[extendr(invisible)]
+fn fast_save(
+    x: Robj,
+    path: &str,
+    #[extendr(default = "3L")] level: i32,
+    #[extendr(default = "NULL")] threads: Robj,
+) -> Result<String> {
+    let options = SaveOptions {
+        level,
+        parallel: parallel_options(&threads)?,
+    };
+    let partial = format!("{path}.partial");
+    let io_error = |e: io::Error| Error::Other(format!("{path}: {e}"));
+    let mut file = io::BufWriter::new(File::create(&partial).map_err(io_error)?);
+    let written = write_object(&x, &mut file, options);
+    drop(file);
+    match written.and_then(|()| std::fs::rename(&partial, path).map_err(io_error)) {
+        Ok(()) => Ok(path.to_string()),
+        Err(e) => {
+            let _ = std::fs::remove_file(&partial);
+            Err(e)
+        }
+    }
+}
+
+/// @rdname fast_save
+/// @param mmap Whether to memory-map the file instead of reading it into
+///   memory.
+/// @export
+#[extendr]
+fn fast_read(
+    path: &str,
+    #[extendr(default = "FALSE")] mmap: bool,
+    #[extendr(default = "NULL")] threads: Robj,
+) -> Result<Robj> {
+    read_object(Path::new(path), mmap, parallel_options(&threads)?)
+}
+
+r_module! {
+    mod fastio;
+    fn fast_save;
+    fn fast_read;
+}
@@ -0,0 +1,147 @@
+//! String interpolation in the manner of the {glue} package.
+//!
+//! [`r_glue()`] fills the `{}` fields of a template with the values of the
+//! R code inside them, evaluated in an environment:
+//!
+//! ```ignore
+//! let env = Environment::new_with_parent(global_env());
+//! env.set_local(sym!(x), r!([1.0, 2.0, 4.0]));
+//! let text = r_glue("mean is {mean(x)} of {length(x)} values", &env)?;
+//! assert_eq!(text, ["mean is 2.333333 of 3 values"]);
+//! ```
+//!
+//! Values are formatted with `format()`, so numbers follow
+//! `getOption("digits")` and classes such as `Date` have their usual
+//! representation. As in {glue}, the result is vectorised over the fields:
+//! fields of length one are recycled and any of length zero make the result
+//! empty. `{{` and `}}` stand for literal braces, and braces inside strings
+//! and comments of the code do not end a field.
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use helloextendr_core::recycle::strict_length;
+
+use crate::deparse::{format_vector, FormatOptions};
+
+/// A part of a template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Literal(String),
+    /// The R code of a `{}` field.
+    Code(String),
+}
+
+/// Split a template into literal text and the code of its fields.
+pub fn parse_template(template: &str) -> Result<Vec<Segment>> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '{' if chars.peek().map(|&(_, c)| c) == Some('{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek().map(|&(_, c)| c) == Some('}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '}' => {
+                return Err(Error::Other(format!(
+                    "unmatched `}}` at position {} of the template",
+                    start + 1
+                )))
+            }
+            '{' => {
+                let end = field_end(template, start + 1).ok_or_else(|| {
+                    Error::Other(format!(
+                        "unclosed `{{` at position {} of the template",
+                        start + 1
+                    ))
+                })?;
+                if !literal.is_empty() {
+                    segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                }
+                segments.push(Segment::Code(template[start + 1..end].to_string()));
+                while chars.peek().is_some_and(|&(i, _)| i <= end) {
+                    chars.next();
+                }
+            }
+            c => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(literal));
+    }
+    Ok(segments)
+}
+
+/// The byte offset of the `}` closing the field whose code starts at
+/// `start`, skipping nested braces, strings and comments.
+fn field_end(template: &str, start: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut comment = false;
+    let mut escaped = false;
+    for (i, c) in template[start..].char_indices() {
+        if comment {
+            comment = c != '\n';
+        } else if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+        } else {
+            match c {
+                '"' | '\'' | '`' => quote = Some(c),
+                '#' => comment = true,
+                '{' => depth += 1,
+                '}' if depth == 0 => return Some(start + i),
+                '}' => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+    None
+}
+
+/// The values of a field, formatted.
+fn field(code: &str, env: &Environment) -> Result<Vec<String>> {
+    let exprs = parse(code).map_err(|_| Error::Other(format!("cannot parse `{{{code}}}`")))?;
+    // Each expression in turn, as the code of a field may have several.
+    let mut value = None;
+    for expr in exprs.values() {
+        value = Some(expr.eval_with_env(env)?);
+    }
+    let value = value.ok_or_else(|| Error::Other("empty `{}` in the template".into()))?;
+    if value.is_null() {
+        return Ok(Vec::new());
+    }
+    format_vector(&value, &FormatOptions::new().trim(true))
+}
+
+/// Interpolate the `{}` fields of `template` with the values of their code
+/// evaluated in `env`, one string per element of the longest field.
+pub fn r_glue(template: &str, env: &Environment) -> Result<Vec<String>> {
+    let segments = parse_template(template)?;
+    let values = segments
+        .iter()
+        .map(|segment| match segment {
+            Segment::Literal(text) => Ok(vec![text.clone()]),
+            Segment::Code(code) => field(code, env),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let lengths: Vec<usize> = values.iter().map(Vec::len).collect();
+    let len = strict_length(&lengths).map_err(|e| Error::Other(format!("fields of {e}")))?;
+    Ok((0..len)
+        .map(|i| {
+            values
+                .iter()
+                .map(|v| v[if v.len() == 1 { 0 } else { i }].as_str())
+                .collect()
+        })
+        .collect())
+}
@@ -0,0 +1,199 @@
+//! Regular expressions with the `regex` crate, as `grepl()`, `sub()` and
+//! `gsub()`.
+//!
+//! The functions take the arguments of their base R counterparts and follow
+//! their conventions where the engines allow it, so that code spending its
+//! time in `grepl()` and `gsub()` can switch to them:
+//!
+//! * `fixed = TRUE` matches the pattern literally and inserts the
+//!   replacement literally.
+//! * Replacements refer to groups as `\\1` to `\\9` and to the whole match
+//!   as `\\0`, and any other escaped character is itself.
+//! * POSIX classes such as `[[:alpha:]]` and Perl classes such as `\\d`,
+//!   `\\w` and `\\s` are available.
+//! * `grepl()` is `FALSE` for `NA` and the substitutions keep it `NA`; a
+//!   missing pattern gives `NA` for every element.
+//! * Factors and other vectors are matched by `as.character()`.
+//!
+//! Matching runs in time linear in the input, which excludes some features
+//! of R's engines: back-references in patterns and look-around are an error,
+//! `.` does not match a newline (as with `perl = TRUE`), `\\U` and `\\L` in
+//! replacements are not supported, and `ignore_case` also applies to
+//! `fixed` patterns instead of being ignored. An empty pattern matches at
+//! the end of the string too, so `re_gsub("", "-", "ab")` is `"-a-b-"`
+//! where `gsub()` gives `"-a-b"`.
+
+use std::borrow::Cow;
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use regex::{NoExpand, Regex, RegexBuilder};
+
+use crate::encoding::Utf8Strings;
+use crate::parallel::{par_map_slice, ParallelOptions};
+use crate::r_module;
+
+/// Compile `pattern` with R's `fixed` and `ignore.case` options.
+pub fn compile(pattern: &str, fixed: bool, ignore_case: bool) -> Result<Regex> {
+    let pattern = if fixed {
+        Cow::Owned(regex::escape(pattern))
+    } else {
+        Cow::Borrowed(pattern)
+    };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|e| Error::Other(format!("invalid regular expression: {e}")))
+}
+
+/// Translate an R replacement string, with `\\1` for groups, to the syntax
+/// of [`Regex::replace()`].
+pub fn replacement(r: &str) -> String {
+    let mut out = String::with_capacity(r.len());
+    let mut chars = r.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(d) if d.is_ascii_digit() => {
+                    out.push_str("${");
+                    out.push(d);
+                    out.push('}');
+                }
+                Some('$') => out.push_str("$$"),
+                Some(c) => out.push(c),
+                None => out.push('\\'),
+            },
+            '$' => out.push_str("$$"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// A scalar string argument, `None` for `NA`.
+fn scalar(x: Robj, what: &str) -> Result<Option<String>> {
+    let strings = Utf8Strings::try_from(x)
+        .ok()
+        .filter(|s| s.len() == 1)
+        .ok_or_else(|| Error::Other(format!("`{what}` must be a single string")))?;
+    Ok(strings.get(0)?.map(Cow::into_owned))
+}
+
+/// The elements of `x` as strings, converting other vectors with
+/// `as.character()`.
+fn subject(x: Robj) -> Result<Vec<Option<String>>> {
+    let x = if x.is_string() {
+        x
+    } else {
+        lang!("as.character", x).eval()?
+    };
+    Utf8Strings::try_from(x)?.to_vec()
+}
+
+/// Regular expressions without ICU or PCRE
+///
+/// `re_grepl()`, `re_sub()` and `re_gsub()` are faster versions of
+/// [grepl()], [sub()] and [gsub()] with the `regex` Rust crate, which
+/// matches in time linear in the length of the input. They follow the
+/// conventions of base R where the engines allow: `fixed = TRUE` matches
+/// and replaces literally, replacements refer to groups as `\\1` and to the
+/// whole match as `\\0`, POSIX and Perl character classes such as
+/// `[[:alpha:]]` and `\\d` are available, `NA` does not match and is not
+/// replaced, and factors are matched by their labels.
+///
+/// Back-references and look-around in patterns are not supported, `.` does
+/// not match a newline, as with `perl = TRUE`, replacements cannot change
+/// case with `\\U` and `\\L`, `ignore_case` also applies to fixed patterns,
+/// and an empty pattern also matches at the end of a string.
+/// @param pattern A regular expression, or a string with `fixed = TRUE`.
+/// @param x A character vector, or a vector to convert with
+///   `as.character()`.
+/// @param ignore_case Whether to match regardless of case.
+/// @param fixed Whether `pattern` is a string to match as it is.
+/// @return `re_grepl()` returns a logical vector as long as `x`, and
+///   `re_sub()` and `re_gsub()` a character vector as long as `x`, in UTF-8.
+/// @export
+#[extendr]
+fn re_grepl(
+    pattern: Robj,
+    x: Robj,
+    #[extendr(default = "FALSE")] ignore_case: bool,
+    #[extendr(default = "FALSE")] fixed: bool,
+) -> Result<Logicals> {
+    let pattern = scalar(pattern, "pattern")?;
+    let x = subject(x)?;
+    let Some(pattern) = pattern else {
+        return Ok(Logicals::from_values(x.iter().map(|_| Rbool::na())));
+    };
+    let re = compile(&pattern, fixed, ignore_case)?;
+    let found = par_map_slice(&x, ParallelOptions::new(), |x| {
+        x.as_deref().is_some_and(|x| re.is_match(x))
+    })?;
+    Ok(Logicals::from_values(found.into_iter().map(Rbool::from)))
+}
+
+fn replace(
+    pattern: Robj,
+    with: Robj,
+    x: Robj,
+    ignore_case: bool,
+    fixed: bool,
+    all: bool,
+) -> Result<Strings> {
+    let pattern = scalar(pattern, "pattern")?;
+    let with = scalar(with, "replacement")?;
+    let x = subject(x)?;
+    let (Some(pattern), Some(with)) = (pattern, with) else {
+        return Ok(Utf8Strings::from_values(x.iter().map(|_| None)).into_inner());
+    };
+    let re = compile(&pattern, fixed, ignore_case)?;
+    let limit = if all { 0 } else { 1 };
+    let replaced = if fixed {
+        par_map_slice(&x, ParallelOptions::new(), |x| {
+            x.as_deref()
+                .map(|x| re.replacen(x, limit, NoExpand(&with)).into_owned())
+        })?
+    } else {
+        let with = replacement(&with);
+        par_map_slice(&x, ParallelOptions::new(), |x| {
+            x.as_deref()
+                .map(|x| re.replacen(x, limit, with.as_str()).into_owned())
+        })?
+    };
+    Ok(Utf8Strings::from_values(replaced.iter().map(Option::as_deref)).into_inner())
+}
+
+/// @rdname re_grepl
+/// @param replacement The replacement of the first match with `re_sub()`
+///   and of every match with `re_gsub()`.
+/// @export
+#[extendr]
+fn re_sub(
+    pattern: Robj,
+    replacement: Robj,
+    x: Robj,
+    #[extendr(default = "FALSE")] ignore_case: bool,
+    #[extendr(default = "FALSE")] fixed: bool,
+) -> Result<Strings> {
+    replace(pattern, replacement, x, ignore_case, fixed, false)
+}
+
+/// @rdname re_grepl
+/// @export
+#[extendr]
+fn re_gsub(
+    pattern: Robj,
+    replacement: Robj,
+    x: Robj,
+    #[extendr(default = "FALSE")] ignore_case: bool,
+    #[extendr(default = "FALSE")] fixed: bool,
+) -> Result<Strings> {
+    replace(pattern, replacement, x, ignore_case, fixed, true)
+}
+
+r_module! {
+    mod grep;
+    fn re_grepl;
+    fn re_sub;
+    fn re_gsub;
+}
@@ -0,0 +1,87 @@
+//! Integration with the RStudio and Positron IDEs.
+//!
+//! Both IDEs replace `View()` with their own data viewer and set the
+//! `viewer` option to a function that shows local web content in their
+//! Viewer pane. The helpers here go through those hooks, so objects held in
+//! Rust can be inspected like data frames, and fall back to what plain R
+//! offers outside an IDE.
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+/// The IDE the session runs in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ide {
+    RStudio,
+    Positron,
+    /// A terminal, R GUI or any other front end.
+    Other,
+}
+
+impl Ide {
+    /// Detect the IDE from the environment variables it sets.
+    pub fn detect() -> Self {
+        let is_set = |name: &str| std::env::var(name).is_ok_and(|v| v == "1");
+        if is_set("POSITRON") {
+            Ide::Positron
+        } else if is_set("RSTUDIO") && std::env::var_os("RSTUDIO_TERM").is_none() {
+            // `RSTUDIO_TERM` marks R started in the RStudio terminal, which
+            // has no access to the IDE.
+            Ide::RStudio
+        } else {
+            Ide::Other
+        }
+    }
+
+    pub fn is_ide(self) -> bool {
+        self != Ide::Other
+    }
+}
+
+/// Objects that can be shown in a data viewer as a table.
+pub trait Viewable {
+    /// The default title of the viewer tab.
+    fn title(&self) -> String;
+
+    /// The contents as a data frame. Large tables may return a preview.
+    fn to_data_frame(&self) -> Result<Robj>;
+}
+
+/// Show `x` in the data viewer of the IDE, or in R's data editor otherwise.
+pub fn view(x: &impl Viewable) -> Result<()> {
+    view_data_frame(&x.to_data_frame()?, &x.title())
+}
+
+/// Show a data frame in the data viewer under `title`.
+pub fn view_data_frame(df: &Robj, title: &str) -> Result<()> {
+    // Look `View` up from the global environment so that the IDE's version,
+    // attached to the search path, takes precedence over `utils::View()`.
+    let view: Function = global_env().find_var(sym!(View))?.try_into()?;
+    view.call(pairlist!(df.clone(), title = title))?;
+    Ok(())
+}
+
+/// Show a local file or URL in the Viewer pane of the IDE, or in the web
+/// browser otherwise. IDEs only show files in the session's temporary
+/// directory in the Viewer.
+pub fn show_in_viewer(url: &str) -> Result<()> {
+    let viewer = lang!("getOption", "viewer").eval()?;
+    if viewer.is_function() {
+        viewer.as_function().unwrap().call(pairlist!(url))?;
+    } else {
+        lang!("browseURL", url).eval()?;
+    }
+    Ok(())
+}
+
+/// Write `html` to a page in the temporary directory and show it with
+/// [`show_in_viewer()`]. Returns the path of the page.
+pub fn view_html(html: &str, name: &str) -> Result<String> {
+    let dir = lang!("tempfile", "viewer").eval()?;
+    let dir = dir.as_str().unwrap_or_default().to_string();
+    std::fs::create_dir_all(&dir).map_err(|e| Error::Other(format!("{dir}: {e}")))?;
+    let path = format!("{dir}/{name}.html");
+    std::fs::write(&path, html).map_err(|e| Error::Other(format!("{path}: {e}")))?;
+    show_in_viewer(&path)?;
+    Ok(path)
+}
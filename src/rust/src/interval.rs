@@ -0,0 +1,63 @@
+//! Binning of numbers, as R's `findInterval()` and `cut()`.
+//!
+//! Histogram and discretization code must put values on a boundary in
+//! the same bin as R does, and label the bins the same way, or results
+//! computed in Rust and R disagree at the edges. The binning is done by
+//! [`helloextendr_core::interval`]; [`BinnedExt`] turns the result into an R
+//! factor. [`find_interval()`]
+//! follows `findInterval()` including its `rightmost.closed`,
+//! `all.inside` and `left.open` options, and [`cut()`] follows
+//! `cut.default()`: breaks given as a number of intervals are spread over
+//! the range of the values and widened by a thousandth of it, bins are
+//! `(a,b]` or `[a,b)`, and labels use as few significant digits as keep
+//! them distinct. Missing values are in no interval.
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+use crate::attrib::AttribExt;
+
+pub use helloextendr_core::interval::{Binned, Breaks, Cut, FindInterval};
+
+/// For each of `x`, the number of the interval of `breaks` it is in: `0`
+/// below the first break, `i` from `breaks[i - 1]` up to `breaks[i]` and
+/// `breaks.len()` above the last, or `None` for `NA` and `NaN`. `breaks`
+/// must be sorted and free of missing values.
+pub fn find_interval(
+    x: &[f64],
+    breaks: &[f64],
+    options: FindInterval,
+) -> Result<Vec<Option<usize>>> {
+    helloextendr_core::interval::find_interval(x, breaks, options)
+        .map_err(|e| Error::Other(e.to_string()))
+}
+
+/// Divide `x` into intervals as `cut(x, breaks)` does.
+pub fn cut(x: &[f64], breaks: Breaks<'_>, options: &Cut) -> Result<Binned> {
+    helloextendr_core::interval::cut(x, breaks, options).map_err(|e| Error::Other(e.to_string()))
+}
+
+/// [`Binned`] as an R factor.
+pub trait BinnedExt {
+    /// The codes as a factor with the labels as levels, ordered if
+    /// `ordered` is true.
+    fn to_factor(&self, ordered: bool) -> Result<Robj>;
+}
+
+impl BinnedExt for Binned {
+    fn to_factor(&self, ordered: bool) -> Result<Robj> {
+        let codes = Integers::from_values(self.codes.iter().map(|code| match code {
+            Some(code) => Rint::from(*code as i32 + 1),
+            None => Rint::na(),
+        }));
+        let mut factor = Robj::from(codes);
+        let levels: Vec<&str> = self.labels.iter().map(String::as_str).collect();
+        factor.set_levels(&levels)?;
+        if ordered {
+            factor.set_attr("class", ["ordered", "factor"])?;
+        } else {
+            factor.set_attr("class", "factor")?;
+        }
+        Ok(factor)
+    }
+}
@@ -0,0 +1,343 @@
+//! A k-d tree over the rows of a matrix, for nearest-neighbour queries.
+//!
+//! The tree is implicit: the rows are stored in an order where the middle
+//! row of every range splits the rest of the range on one coordinate, so
+//! the index is nothing but the reordered rows and their original numbers,
+//! and saving it writes those as they are. Queries of several points run
+//! on worker threads.
+//!
+//! In R the tree is a `#[r_class]` object: `new_KdTree(x)` builds it and
+//! binds the methods below to it.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+use crate::attrib::AttribExt;
+use crate::parallel::{par_map_slice, ParallelOptions};
+use crate::r_class;
+use crate::r_module;
+use crate::xlen::{length_to_robj, robj_to_length};
+
+/// The start of files written by [`KdTree::write()`], with the format
+/// version.
+const MAGIC: &[u8; 8] = b"HXKDTR01";
+
+#[extendr]
+#[derive(Debug, Clone, PartialEq)]
+pub struct KdTree {
+    dim: usize,
+    /// The rows in tree order, each `dim` coordinates.
+    points: Vec<f64>,
+    /// The zero-based original row of each point.
+    rows: Vec<usize>,
+}
+
+/// A point found by a query, ordered by distance.
+#[derive(Debug, Clone, Copy)]
+struct Candidate {
+    distance2: f64,
+    slot: usize,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance2
+            .total_cmp(&other.distance2)
+            .then(self.slot.cmp(&other.slot))
+    }
+}
+
+impl KdTree {
+    /// Index the rows of the column-major `nrow` by `ncol` matrix `data`,
+    /// which must not contain missing values.
+    pub fn build(data: &[f64], nrow: usize, ncol: usize) -> Result<Self> {
+        if ncol == 0 || data.len() != nrow * ncol {
+            return Err(Error::Other(format!(
+                "cannot index {} values as a {nrow} by {ncol} matrix",
+                data.len()
+            )));
+        }
+        if data.iter().any(|x| x.is_nan()) {
+            return Err(Error::Other("`x` must not contain missing values".into()));
+        }
+        let value = |row: usize, d: usize| data[row + d * nrow];
+        let mut rows: Vec<usize> = (0..nrow).collect();
+        split(&mut rows, 0, ncol, &value);
+        let points = rows
+            .iter()
+            .flat_map(|&row| (0..ncol).map(move |d| value(row, d)))
+            .collect();
+        Ok(KdTree {
+            dim: ncol,
+            points,
+            rows,
+        })
+    }
+
+    /// The number of points.
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    fn point(&self, slot: usize) -> &[f64] {
+        &self.points[slot * self.dim..(slot + 1) * self.dim]
+    }
+
+    /// The zero-based rows of the `k` points nearest to `query` and their
+    /// Euclidean distances, nearest first.
+    pub fn nearest(&self, query: &[f64], k: usize) -> Vec<(usize, f64)> {
+        let mut found = BinaryHeap::with_capacity(k + 1);
+        if k > 0 {
+            self.search(0, self.len(), 0, query, k, &mut found);
+        }
+        found
+            .into_sorted_vec()
+            .into_iter()
+            .map(|c| (self.rows[c.slot], c.distance2.sqrt()))
+            .collect()
+    }
+
+    fn search(
+        &self,
+        lo: usize,
+        hi: usize,
+        depth: usize,
+        query: &[f64],
+        k: usize,
+        found: &mut BinaryHeap<Candidate>,
+    ) {
+        if lo >= hi {
+            return;
+        }
+        let mid = lo + (hi - lo) / 2;
+        let point = self.point(mid);
+        let distance2 = point
+            .iter()
+            .zip(query)
+            .map(|(p, q)| (p - q) * (p - q))
+            .sum();
+        let candidate = Candidate {
+            distance2,
+            slot: mid,
+        };
+        if found.len() < k {
+            found.push(candidate);
+        } else if found.peek().is_some_and(|worst| candidate < *worst) {
+            found.pop();
+            found.push(candidate);
+        }
+
+        let d = depth % self.dim;
+        let diff = query[d] - point[d];
+        let (near, far) = if diff < 0.0 {
+            ((lo, mid), (mid + 1, hi))
+        } else {
+            ((mid + 1, hi), (lo, mid))
+        };
+        self.search(near.0, near.1, depth + 1, query, k, found);
+        let worst = found.peek().map_or(f64::INFINITY, |c| c.distance2);
+        if found.len() < k || diff * diff < worst {
+            self.search(far.0, far.1, depth + 1, query, k, found);
+        }
+    }
+
+    /// Save the tree to `path`, to be read back without rebuilding it.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let io = |e: std::io::Error| Error::Other(format!("{}: {e}", path.display()));
+        let mut out = BufWriter::new(fs::File::create(path).map_err(io)?);
+        out.write_all(MAGIC).map_err(io)?;
+        for n in [self.len(), self.dim] {
+            out.write_all(&(n as u64).to_le_bytes()).map_err(io)?;
+        }
+        for &row in &self.rows {
+            out.write_all(&(row as u64).to_le_bytes()).map_err(io)?;
+        }
+        for &x in &self.points {
+            out.write_all(&x.to_le_bytes()).map_err(io)?;
+        }
+        out.flush().map_err(io)
+    }
+
+    /// Read a tree saved by [`KdTree::write()`].
+    pub fn read(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path).map_err(|e| Error::Other(format!("{}: {e}", path.display())))?;
+        let invalid = || Error::Other(format!("{} is not a saved k-d tree", path.display()));
+        let rest = bytes.strip_prefix(MAGIC.as_slice()).ok_or_else(invalid)?;
+        let mut words = rest.chunks_exact(8).map(|w| {
+            let mut word = [0; 8];
+            word.copy_from_slice(w);
+            word
+        });
+        let mut next = || words.next().ok_or_else(invalid);
+        let len = u64::from_le_bytes(next()?) as usize;
+        let dim = u64::from_le_bytes(next()?) as usize;
+        let expected = len
+            .checked_mul(dim)
+            .and_then(|n| n.checked_add(len + 2))
+            .and_then(|n| n.checked_mul(8));
+        if dim == 0 || expected != Some(rest.len()) {
+            return Err(invalid());
+        }
+        let rows = (0..len)
+            .map(|_| Ok(u64::from_le_bytes(next()?) as usize))
+            .collect::<Result<Vec<_>>>()?;
+        if rows.iter().any(|&row| row >= len) {
+            return Err(invalid());
+        }
+        let points = (0..len * dim)
+            .map(|_| Ok(f64::from_le_bytes(next()?)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(KdTree { dim, points, rows })
+    }
+}
+
+/// Order `rows` so that the middle one splits the others on coordinate
+/// `depth % ncol`, and recursively each half on the next coordinate.
+fn split(rows: &mut [usize], depth: usize, ncol: usize, value: &impl Fn(usize, usize) -> f64) {
+    if rows.len() <= 1 {
+        return;
+    }
+    let d = depth % ncol;
+    let mid = rows.len() / 2;
+    rows.select_nth_unstable_by(mid, |&a, &b| value(a, d).total_cmp(&value(b, d)));
+    let (left, right) = rows.split_at_mut(mid);
+    split(left, depth + 1, ncol, value);
+    split(&mut right[1..], depth + 1, ncol, value);
+}
+
+/// The values and dimensions of a numeric matrix, with integer `NA` as
+/// `NaN`.
+fn numeric_matrix(x: &Robj, what: &str) -> Result<(Vec<f64>, usize, usize)> {
+    let not_matrix = || Error::Other(format!("`{what}` must be a numeric matrix"));
+    let (nrow, ncol) = match x.dims()?.as_deref() {
+        Some(&[nrow, ncol]) => (nrow, ncol),
+        _ => return Err(not_matrix()),
+    };
+    let data = if let Some(data) = x.as_real_slice() {
+        data.to_vec()
+    } else if let Some(data) = x.as_integer_slice() {
+        data.iter()
+            .map(|&v| Option::<i32>::from(Rint::from(v)).map_or(f64::NAN, f64::from))
+            .collect()
+    } else {
+        return Err(not_matrix());
+    };
+    Ok((data, nrow, ncol))
+}
+
+/// A k-d tree for nearest-neighbour queries
+///
+/// `new_KdTree(x)` indexes the rows of the numeric matrix `x`, or reads an
+/// index written by `$save()` if `x` is the path of one. The object has the
+/// methods:
+///
+/// * `$query(points, k = 1L)`: the `k` rows of `x` nearest to each row of
+///   the matrix `points`, as a list of an integer matrix `index` of their
+///   row numbers and a matrix `distance` of their Euclidean distances, with
+///   one row per point, nearest first.
+/// * `$save(path)`: write the index to a file.
+/// * `$size()` and `$dim()`: the number of rows and columns of `x`.
+/// @name new_KdTree
+/// @usage new_KdTree(x)
+/// @param x A numeric matrix without missing values, or a path.
+/// @return A `KdTree` object.
+/// @rawNamespace export(new_KdTree)
+#[r_class]
+#[extendr]
+impl KdTree {
+    /// @noRd
+    fn new(x: Robj) -> Result<Self> {
+        if let Some(path) = x.as_str() {
+            return KdTree::read(Path::new(path));
+        }
+        let (data, nrow, ncol) = numeric_matrix(&x, "x")?;
+        KdTree::build(&data, nrow, ncol)
+    }
+
+    /// @noRd
+    fn query(&self, points: Robj, #[extendr(default = "1L")] k: Robj) -> Result<Robj> {
+        let (data, nrow, ncol) = numeric_matrix(&points, "points")?;
+        if ncol != self.dim {
+            return Err(Error::Other(format!(
+                "`points` has {ncol} columns but the tree {}",
+                self.dim
+            )));
+        }
+        if data.iter().any(|x| x.is_nan()) {
+            return Err(Error::Other(
+                "`points` must not contain missing values".into(),
+            ));
+        }
+        let k = robj_to_length(&k)?;
+        if k > self.len() {
+            return Err(Error::Other(format!(
+                "cannot find {k} neighbours among {} points",
+                self.len()
+            )));
+        }
+        let queries: Vec<usize> = (0..nrow).collect();
+        let found = par_map_slice(&queries, ParallelOptions::new().chunk_size(256), |&i| {
+            let query: Vec<f64> = (0..ncol).map(|d| data[i + d * nrow]).collect();
+            self.nearest(&query, k)
+        })?;
+
+        // Column-major, one row per query.
+        let mut index = vec![0; nrow * k];
+        let mut distance = vec![0.0; nrow * k];
+        for (i, neighbours) in found.iter().enumerate() {
+            for (j, &(row, d)) in neighbours.iter().enumerate() {
+                index[i + j * nrow] = row as i32 + 1;
+                distance[i + j * nrow] = d;
+            }
+        }
+        let mut index = Robj::from(Integers::from_values(index));
+        index.set_dim(&[nrow, k])?;
+        let mut distance = Robj::from(Doubles::from_values(distance));
+        distance.set_dim(&[nrow, k])?;
+        Ok(list!(index = index, distance = distance).into())
+    }
+
+    /// @noRd
+    fn save(&self, path: &str) -> Result<()> {
+        self.write(Path::new(path))
+    }
+
+    /// @noRd
+    fn size(&self) -> Robj {
+        length_to_robj(self.len())
+    }
+
+    /// @noRd
+    fn dim(&self) -> i32 {
+        self.dim as i32
+    }
+}
+
+r_module! {
+    mod kdtree;
+    impl KdTree;
+}
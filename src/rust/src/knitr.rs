@@ -0,0 +1,212 @@
+//! A knitr engine for Rust chunks.
+//!
+//! Each `rust` chunk is compiled with cargo into its own cdylib under the R
+//! session's temporary directory and loaded with `dyn.load()`. The chunk
+//! defines `fn main()` as a program would. The generated crate shadows
+//! `print!`, `println!`, `eprint!` and `eprintln!` with versions that write
+//! to a buffer, and exports an entry point that runs `main()` and returns
+//! the buffer, which becomes the chunk output.
+//!
+//! Chunks are independent crates: they share a cargo target directory, so
+//! dependencies are only built once, but not variables or items. Extra
+//! dependencies are given as Cargo.toml lines in the `rust.deps` chunk
+//! option, e.g. `rust.deps = 'itoa = "1"'`.
+//!
+//! The R side is `register_knitr_engine()` in `R/knitr.R`.
+
+use extendr_api::prelude::*;
+use extendr_api::robj::GetSexp;
+use extendr_api::Result;
+use std::collections::hash_map::DefaultHasher;
+use std::ffi::CStr;
+use std::hash::{Hash, Hasher};
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::r_module;
+
+/// The symbol the generated crate exports to run the chunk.
+const ENTRY_POINT: &str = "knitr_chunk_main";
+/// The symbol that frees the output returned by [`ENTRY_POINT`].
+const FREE_OUTPUT: &str = "knitr_chunk_free";
+
+const PRELUDE: &str = r#"#![allow(dead_code, unused_macros)]
+
+thread_local! {
+    static KNITR_OUTPUT: std::cell::RefCell<String> = std::cell::RefCell::new(String::new());
+}
+
+macro_rules! print {
+    ($($arg:tt)*) => {
+        KNITR_OUTPUT.with(|out| out.borrow_mut().push_str(&format!($($arg)*)))
+    };
+}
+
+macro_rules! println {
+    () => { print!("\n") };
+    ($($arg:tt)*) => {{ print!($($arg)*); print!("\n") }};
+}
+
+macro_rules! eprint {
+    ($($arg:tt)*) => { print!($($arg)*) };
+}
+
+macro_rules! eprintln {
+    ($($arg:tt)*) => { println!($($arg)*) };
+}
+
+"#;
+
+const EPILOGUE: &str = r#"
+
+#[no_mangle]
+pub extern "C" fn knitr_chunk_main() -> *mut std::os::raw::c_char {
+    KNITR_OUTPUT.with(|out| out.borrow_mut().clear());
+    let result = std::panic::catch_unwind(main);
+    let mut output = KNITR_OUTPUT.with(|out| out.borrow_mut().split_off(0));
+    if let Err(panic) = result {
+        let message = panic
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        output.push_str(&format!("panicked: {message}\n"));
+    }
+    std::ffi::CString::new(output.replace('\0', ""))
+        .unwrap()
+        .into_raw()
+}
+
+#[no_mangle]
+pub extern "C" fn knitr_chunk_free(output: *mut std::os::raw::c_char) {
+    if !output.is_null() {
+        drop(unsafe { std::ffi::CString::from_raw(output) });
+    }
+}
+"#;
+
+/// A Rust chunk of a knitr document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RustChunk {
+    code: String,
+    dependencies: Vec<String>,
+}
+
+impl RustChunk {
+    /// A chunk that defines `fn main()`.
+    pub fn new(code: impl Into<String>) -> Self {
+        Self {
+            code: code.into(),
+            dependencies: Vec::new(),
+        }
+    }
+
+    /// Add a line to the `[dependencies]` section of the chunk's Cargo.toml.
+    pub fn dependency(mut self, line: impl Into<String>) -> Self {
+        self.dependencies.push(line.into());
+        self
+    }
+
+    /// The crate name, unique per code and dependencies.
+    fn name(&self) -> String {
+        let mut hasher = DefaultHasher::new();
+        self.code.hash(&mut hasher);
+        self.dependencies.hash(&mut hasher);
+        format!("knitr_chunk_{:016x}", hasher.finish())
+    }
+
+    /// The `src/lib.rs` of the chunk crate.
+    pub fn source(&self) -> String {
+        format!("{PRELUDE}{}{EPILOGUE}", self.code)
+    }
+
+    fn manifest(&self) -> String {
+        format!(
+            "[package]\nname = \"{}\"\nversion = \"0.0.0\"\nedition = \"2021\"\n\n\
+             [lib]\ncrate-type = [\"cdylib\"]\npath = \"lib.rs\"\n\n\
+             [dependencies]\n{}\n",
+            self.name(),
+            self.dependencies.join("\n")
+        )
+    }
+
+    /// Compile the chunk in `root` and return the path of the library.
+    pub fn build(&self, root: &Path) -> Result<PathBuf> {
+        let name = self.name();
+        let dir = root.join(&name);
+        let io = |e: std::io::Error| Error::Other(format!("{}: {e}", dir.display()));
+        std::fs::create_dir_all(&dir).map_err(io)?;
+        std::fs::write(dir.join("Cargo.toml"), self.manifest()).map_err(io)?;
+        std::fs::write(dir.join("lib.rs"), self.source()).map_err(io)?;
+
+        let target = root.join("target");
+        let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".into());
+        let output = Command::new(cargo)
+            .args(["build", "--release", "--quiet", "--manifest-path"])
+            .arg(dir.join("Cargo.toml"))
+            .env("CARGO_TARGET_DIR", &target)
+            .output()
+            .map_err(|e| Error::Other(format!("cannot run cargo: {e}")))?;
+        if !output.status.success() {
+            return Err(Error::Other(format!(
+                "the Rust chunk failed to compile:\n{}",
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+        Ok(target.join("release").join(format!(
+            "{}{name}{}",
+            std::env::consts::DLL_PREFIX,
+            std::env::consts::DLL_SUFFIX
+        )))
+    }
+
+    /// Compile and run the chunk and return what it printed.
+    pub fn run(&self, root: &Path) -> Result<String> {
+        let library = self.build(root)?;
+        let path = library.to_string_lossy().into_owned();
+        let dll = lang!("dyn.load", path.as_str(), local = true, now = true).eval()?;
+        let output = call_entry_point(&dll);
+        lang!("dyn.unload", path.as_str()).eval()?;
+        output
+    }
+}
+
+fn native_symbol(dll: &Robj, name: &str) -> Result<*mut std::os::raw::c_void> {
+    let info = lang!("getNativeSymbolInfo", name, dll.clone()).eval()?;
+    let address = info.dollar("address")?;
+    let address = unsafe { extendr_ffi::R_ExternalPtrAddr(address.get()) };
+    if address.is_null() {
+        return Err(Error::Other(format!("symbol `{name}` not found")));
+    }
+    Ok(address)
+}
+
+fn call_entry_point(dll: &Robj) -> Result<String> {
+    let main = native_symbol(dll, ENTRY_POINT)?;
+    let free = native_symbol(dll, FREE_OUTPUT)?;
+    unsafe {
+        let main: extern "C" fn() -> *mut c_char = std::mem::transmute(main);
+        let free: extern "C" fn(*mut c_char) = std::mem::transmute(free);
+        let raw = main();
+        let output = CStr::from_ptr(raw).to_string_lossy().into_owned();
+        free(raw);
+        Ok(output)
+    }
+}
+
+/// Compile and run a Rust chunk for the knitr engine.
+/// @noRd
+#[extendr]
+fn knitr_rust_chunk(code: &str, deps: Vec<String>) -> Result<String> {
+    let root = lang!("tempdir").eval()?;
+    let root = Path::new(root.as_str().unwrap_or(".")).join("knitr-rust");
+    deps.into_iter()
+        .fold(RustChunk::new(code), RustChunk::dependency)
+        .run(&root)
+}
+
+r_module! {
+    mod knitr;
+    fn knitr_rust_chunk;
+}
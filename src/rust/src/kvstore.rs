@@ -0,0 +1,312 @@
+//! A concurrent in-memory key-value store shared by R and Rust.
+//!
+//! [`KvStore`] is a cheaply cloned handle to a [`DashMap`] from string keys
+//! to the `serialize()` output of R values. R code uses it through the
+//! `kv_*()` functions, and Rust code gets a handle with
+//! [`KvStore::try_from()`] and can read and write the bytes of entries from
+//! any thread, for example from the workers of a parallel map, without
+//! going through R or taking a global lock.
+//!
+//! A store opened with a path loads the entries saved there, and
+//! [`KvStore::save()`] writes them back atomically, so a store can outlive
+//! the session.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+use crate::attrib::AttribExt;
+use crate::cache::{serialize, write_atomic};
+use crate::encoding::Utf8Strings;
+use crate::r_module;
+use crate::raw_io::IntoRaw;
+use crate::xlen::length_to_robj;
+
+/// The start of files written by [`KvStore::save()`], with the format
+/// version.
+const MAGIC: &[u8; 8] = b"HXKVST01";
+
+/// The R class of the handles returned by `kv_store()`.
+const CLASS: &str = "helloextendr_kv_store";
+
+/// A thread-safe map from strings to serialized R values.
+#[derive(Debug, Clone, Default)]
+pub struct KvStore {
+    map: Arc<DashMap<String, Vec<u8>>>,
+    path: Option<PathBuf>,
+}
+
+impl KvStore {
+    /// An empty store that is not saved anywhere.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The store saved at `path`, or an empty one if there is no such file.
+    /// [`save()`](Self::save) writes to `path` by default.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let map = match fs::read(&path) {
+            Ok(bytes) => decode(&bytes)
+                .ok_or_else(|| Error::Other(format!("{} is not a saved store", path.display())))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => DashMap::new(),
+            Err(e) => return Err(Error::Other(format!("{}: {e}", path.display()))),
+        };
+        Ok(Self {
+            map: Arc::new(map),
+            path: Some(path),
+        })
+    }
+
+    /// The path the store was opened from, if any.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// A copy of the bytes stored under `key`.
+    pub fn get_bytes(&self, key: &str) -> Option<Vec<u8>> {
+        self.map.get(key).map(|value| value.clone())
+    }
+
+    /// Store `bytes` under `key`, returning the previous bytes.
+    pub fn insert_bytes(&self, key: impl Into<String>, bytes: Vec<u8>) -> Option<Vec<u8>> {
+        self.map.insert(key.into(), bytes)
+    }
+
+    /// The R value stored under `key`. Must be called on the main thread.
+    pub fn get(&self, key: &str) -> Result<Option<Robj>> {
+        match self.get_bytes(key) {
+            Some(bytes) => lang!("unserialize", bytes.into_raw()).eval().map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Store the R value `value` under `key`. Must be called on the main
+    /// thread.
+    pub fn insert(&self, key: impl Into<String>, value: &Robj) -> Result<()> {
+        self.insert_bytes(key, serialize(value)?);
+        Ok(())
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.map.contains_key(key)
+    }
+
+    /// Remove the entry for `key`. Returns whether there was one.
+    pub fn remove(&self, key: &str) -> bool {
+        self.map.remove(key).is_some()
+    }
+
+    /// The keys, sorted.
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.map.iter().map(|entry| entry.key().clone()).collect();
+        keys.sort();
+        keys
+    }
+
+    /// The number of entries.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn clear(&self) {
+        self.map.clear();
+    }
+
+    /// Write the entries to `path`, or to the path the store was opened
+    /// from, replacing the file at once.
+    pub fn save(&self, path: Option<&Path>) -> Result<()> {
+        let path = path
+            .or(self.path.as_deref())
+            .ok_or_else(|| Error::Other("the store has no path to save to".into()))?;
+        // Copied out first: writing while holding the shards would block
+        // other threads for the whole write.
+        let mut entries: Vec<(String, Vec<u8>)> = self
+            .map
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+        entries.sort();
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&(entries.len() as u64).to_le_bytes());
+        for (key, value) in &entries {
+            for field in [key.as_bytes(), value.as_slice()] {
+                bytes.extend_from_slice(&(field.len() as u64).to_le_bytes());
+                bytes.extend_from_slice(field);
+            }
+        }
+        write_atomic(path, &bytes)
+    }
+}
+
+/// The entries of a file written by [`KvStore::save()`], or `None` if it is
+/// not one.
+fn decode(bytes: &[u8]) -> Option<DashMap<String, Vec<u8>>> {
+    let mut rest = bytes.strip_prefix(MAGIC.as_slice())?;
+    let count = take_len(&mut rest)?;
+    let map = DashMap::new();
+    for _ in 0..count {
+        let n = take_len(&mut rest)?;
+        let key = String::from_utf8(take(&mut rest, n)?.to_vec()).ok()?;
+        let n = take_len(&mut rest)?;
+        map.insert(key, take(&mut rest, n)?.to_vec());
+    }
+    rest.is_empty().then_some(map)
+}
+
+fn take<'a>(rest: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+    if n > rest.len() {
+        return None;
+    }
+    let (head, tail) = rest.split_at(n);
+    *rest = tail;
+    Some(head)
+}
+
+fn take_len(rest: &mut &[u8]) -> Option<usize> {
+    let mut word = [0; 8];
+    word.copy_from_slice(take(rest, 8)?);
+    usize::try_from(u64::from_le_bytes(word)).ok()
+}
+
+impl TryFrom<&Robj> for KvStore {
+    type Error = Error;
+
+    /// The store behind a handle returned by `kv_store()`, sharing its
+    /// entries.
+    fn try_from(robj: &Robj) -> Result<Self> {
+        if !robj.inherits(CLASS) {
+            return Err(Error::Other(
+                "expected a store created by `kv_store()`".into(),
+            ));
+        }
+        let store: ExternalPtr<KvStore> = robj.clone().try_into()?;
+        Ok(KvStore::clone(&store))
+    }
+}
+
+/// A key argument, which must be a single string.
+fn key(key: &Robj) -> Result<String> {
+    let invalid = || Error::Other("`key` must be a single string".into());
+    let key = Utf8Strings::try_from(key.clone())
+        .ok()
+        .filter(|key| key.len() == 1)
+        .ok_or_else(invalid)?;
+    let key = key.get(0)?.ok_or_else(invalid)?;
+    Ok(key.into_owned())
+}
+
+/// A concurrent key-value store
+///
+/// `kv_store()` creates a store of R values in memory under string keys,
+/// which Rust code in the package can also read and write from several
+/// threads at once. Values are stored serialized, so a value read back is a
+/// copy. With a `path`, the store starts with the entries saved there by
+/// `kv_save()`.
+/// @param path `NULL`, or a file to load the store from and save it to.
+/// @return A store to pass to [kv_get()] and the other `kv_*()` functions.
+/// @export
+#[extendr]
+fn kv_store(#[extendr(default = "NULL")] path: Robj) -> Result<Robj> {
+    let store = match path.as_str() {
+        Some(path) => KvStore::open(path)?,
+        None => KvStore::new(),
+    };
+    let mut handle: Robj = ExternalPtr::new(store).into();
+    handle.set_attr("class", CLASS)?;
+    Ok(handle)
+}
+
+/// Use a key-value store
+///
+/// `kv_save()` writes all entries to a file that [kv_store()] can load
+/// them from in a later session.
+/// @param store A store created by [kv_store()].
+/// @param key A string.
+/// @param value Any R object.
+/// @param default The value returned when there is no entry.
+/// @param path `NULL` to save to the path the store was created with, or
+///   another file.
+/// @return `kv_get()` returns the value, `kv_has()` and `kv_remove()`
+///   whether there was an entry, `kv_keys()` the sorted keys and `kv_size()`
+///   the number of entries. `kv_set()`, `kv_clear()` and `kv_save()` return
+///   `NULL`, invisibly.
+/// @export
+#[extendr]
+fn kv_get(store: Robj, key: Robj, #[extendr(default = "NULL")] default: Robj) -> Result<Robj> {
+    Ok(KvStore::try_from(&store)?
+        .get(&self::key(&key)?)?
+        .unwrap_or(default))
+}
+
+/// @rdname kv_get
+/// @export
+#[extendr(invisible)]
+fn kv_set(store: Robj, key: Robj, value: Robj) -> Result<()> {
+    KvStore::try_from(&store)?.insert(self::key(&key)?, &value)
+}
+
+/// @rdname kv_get
+/// @export
+#[extendr]
+fn kv_has(store: Robj, key: Robj) -> Result<bool> {
+    Ok(KvStore::try_from(&store)?.contains(&self::key(&key)?))
+}
+
+/// @rdname kv_get
+/// @export
+#[extendr]
+fn kv_remove(store: Robj, key: Robj) -> Result<bool> {
+    Ok(KvStore::try_from(&store)?.remove(&self::key(&key)?))
+}
+
+/// @rdname kv_get
+/// @export
+#[extendr]
+fn kv_keys(store: Robj) -> Result<Vec<String>> {
+    Ok(KvStore::try_from(&store)?.keys())
+}
+
+/// @rdname kv_get
+/// @export
+#[extendr]
+fn kv_size(store: Robj) -> Result<Robj> {
+    Ok(length_to_robj(KvStore::try_from(&store)?.len()))
+}
+
+/// @rdname kv_get
+/// @export
+#[extendr(invisible)]
+fn kv_clear(store: Robj) -> Result<()> {
+    KvStore::try_from(&store)?.clear();
+    Ok(())
+}
+
+/// @rdname kv_get
+/// @export
+#[extendr(invisible)]
+fn kv_save(store: Robj, #[extendr(default = "NULL")] path: Robj) -> Result<()> {
+    KvStore::try_from(&store)?.save(path.as_str().map(Path::new))
+}
+
+r_module! {
+    mod kvstore;
+    fn kv_store;
+    fn kv_get;
+    fn kv_set;
+    fn kv_has;
+    fn kv_remove;
+    fn kv_keys;
+    fn kv_size;
+    fn kv_clear;
+    fn kv_save;
+}
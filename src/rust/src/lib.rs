@@ -1,5 +1,95 @@
 use extendr_api::prelude::*;
 
+pub use helloextendr_macros::{list, pairlist, r_class, r_module, strings, sym};
+
+pub mod abi;
+pub mod archive;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod ast;
+pub mod attrib;
+pub mod backend;
+pub mod batch;
+pub mod bench;
+pub mod bits;
+pub mod cache;
+pub mod collections;
+pub mod completion;
+pub mod condition;
+pub mod config;
+pub mod console;
+pub mod context;
+pub mod credentials;
+pub mod dataset;
+pub mod dedup;
+pub mod deparse;
+pub mod dist;
+#[cfg(feature = "graphics")]
+pub mod device;
+pub mod encoding;
+#[cfg(not(feature = "cran-strict"))]
+pub mod engine;
+pub mod event_loop;
+pub mod fastio;
+pub mod glue;
+pub mod grep;
+pub mod ide;
+pub mod interval;
+pub mod kdtree;
+pub mod knitr;
+pub mod kvstore;
+mod lookup;
+pub mod mask;
+pub mod moments;
+pub mod ndjson;
+pub mod owned;
+pub mod parallel;
+pub mod patch;
+pub mod persist;
+pub mod preserve;
+pub mod process;
+#[cfg(feature = "promises")]
+pub mod promise;
+pub mod quantile;
+pub mod quote;
+pub mod rapi;
+pub mod raw_io;
+pub mod registry;
+pub mod resources;
+pub mod rle;
+pub mod roll;
+pub mod s4;
+pub mod sandbox;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod session;
+pub mod sexp;
+pub mod srcref;
+pub mod strdist;
+pub mod table;
+pub mod teardown;
+pub mod view;
+pub mod watch;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+pub mod xlen;
+pub mod xlsx;
+#[cfg(feature = "xml")]
+pub mod xml;
+
+/// `test_with_r!` needs the embedded R of [`engine`], which is not part of
+/// builds with the `cran-strict` feature.
+#[cfg(feature = "cran-strict")]
+#[macro_export]
+macro_rules! test_with_r {
+    ($($tt:tt)*) => {
+        compile_error!(
+            "`test_with_r!` embeds R through entry points outside its API, which the \
+             `cran-strict` feature leaves out; run these tests without `--features cran-strict`"
+        );
+    };
+}
+
 /// Return string `"Hello world!"` to R.
 /// @export
 #[extendr]
@@ -7,10 +97,69 @@ fn hello_world() -> &'static str {
     "Hello world!"
 }
 
+/// The error of a function that needs the cargo feature `feature`, which
+/// the library was built without. The function stays registered, so that
+/// the R wrappers match every build.
+#[allow(dead_code)] // In builds with all the features.
+pub(crate) fn missing_feature(feature: &str) -> Error {
+    Error::Other(format!(
+        "helloextendr was built without the `{feature}` feature; reinstall it \
+         with HELLOEXTENDR_FEATURES=\"{feature}\""
+    ))
+}
+
+/// The optional features that R functions of the package need, of those
+/// the library was built with.
+/// @noRd
+#[extendr]
+fn rust_features() -> Vec<String> {
+    [
+        ("credentials", cfg!(feature = "credentials")),
+        ("watch", cfg!(feature = "watch")),
+        ("xlsx", cfg!(feature = "xlsx")),
+        ("zip", cfg!(feature = "zip")),
+        ("zstd", cfg!(feature = "zstd")),
+    ]
+    .iter()
+    .filter(|(_, enabled)| *enabled)
+    .map(|(feature, _)| feature.to_string())
+    .collect()
+}
+
 // Macro to generate exports.
 // This ensures exported functions are registered with R.
 // See corresponding C code in `entrypoint.c`.
-extendr_module! {
+// `r_module!` also rejects names registered twice, across all modules.
+r_module! {
     mod helloextendr;
     fn hello_world;
+    fn rust_features;
+    use abi;
+    use archive;
+    use batch;
+    use bench;
+    use bits;
+    use cache;
+    use collections;
+    use completion;
+    use config;
+    use credentials;
+    use dataset;
+    use dist;
+    use fastio;
+    use grep;
+    use kdtree;
+    use knitr;
+    use kvstore;
+    use ndjson;
+    use persist;
+    use preserve;
+    use process;
+    use resources;
+    use sandbox;
+    use session;
+    use strdist;
+    use teardown;
+    use watch;
+    use xlsx;
 }
@@ -0,0 +1,26 @@
+//! Lookups of R variables and functions shared by the modules.
+//!
+//! R 4.5 took `R_UnboundValue` out of the API, and with it the way to tell
+//! an unbound variable from a bound one after `Environment::local()`, so
+//! [`exists_local()`] asks `exists()` instead.
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+/// Whether `name` is bound in `env` itself, not in its parents.
+pub(crate) fn exists_local(env: &Environment, name: &str) -> Result<bool> {
+    let exists = lang!("exists", name, envir = env.clone(), inherits = false).eval()?;
+    Ok(exists.as_bool() == Some(true))
+}
+
+/// The function `name` of the base package.
+pub(crate) fn base_function(name: &str) -> Result<Function> {
+    base_env().local(Symbol::from_string(name))?.try_into()
+}
+
+/// The function `name` of the namespace of `package`, which is loaded if
+/// it is not yet.
+pub(crate) fn namespace_function(package: &str, name: &str) -> Result<Function> {
+    let ns: Environment = lang!("asNamespace", package).eval()?.try_into()?;
+    ns.local(Symbol::from_string(name))?.try_into()
+}
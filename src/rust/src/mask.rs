@@ -0,0 +1,56 @@
+//! Evaluation with the columns of a data frame as variables.
+//!
+//! [`eval_with_data()`] is `with(data, expr)`: the expression sees the
+//! columns of `data` first and the variables of `enclos` after them, so
+//! interfaces taking formulas or column expressions from users can evaluate
+//! them against a table:
+//!
+//! ```ignore
+//! let expr = R!("quote(price * quantity)")?;
+//! let total = eval_with_data(&expr, &sales, &global_env())?;
+//! ```
+//!
+//! As in `base::eval()` with a list, the columns are bound in a new
+//! environment whose parent is `enclos`, so assignments made by the
+//! expression stay in that environment and neither `data` nor `enclos`
+//! change. Unnamed columns are not visible and, of columns with the same
+//! name, the first one is.
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+use crate::lookup::exists_local;
+
+/// The environment `with(data, )` evaluates in: the columns of `data`,
+/// bound in a new child of `enclos`.
+pub fn data_mask(data: &List, enclos: &Environment) -> Environment {
+    let mask = Environment::new_with_parent(enclos.clone());
+    for (name, column) in data.iter() {
+        if name.is_empty() {
+            continue;
+        }
+        let symbol = Symbol::from_string(name);
+        if !exists_local(&mask, name).unwrap_or(false) {
+            mask.set_local(symbol, column);
+        }
+    }
+    mask
+}
+
+/// Evaluate `expr`, a call, symbol or expression vector, with the columns
+/// of `data`, a data frame or named list, as variables in front of those of
+/// `enclos`, as `with(data, expr)` does. An expression vector evaluates to
+/// the value of its last element.
+pub fn eval_with_data(expr: &Robj, data: &List, enclos: &Environment) -> Result<Robj> {
+    let mask = data_mask(data, enclos);
+    match expr.as_expressions() {
+        Some(exprs) => {
+            let mut value = r!(NULL);
+            for expr in exprs.values() {
+                value = expr.eval_with_env(&mask)?;
+            }
+            Ok(value)
+        }
+        None => expr.eval_with_env(&mask),
+    }
+}
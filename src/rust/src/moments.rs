@@ -0,0 +1,100 @@
+//! Single-pass summary statistics of R vectors with bounded memory.
+//!
+//! [`Moments`] comes from [`helloextendr_core::moments`], which updates the
+//! mean and central moments with the numerically stable formulas of Welford
+//! and Pébay. [`MomentsExt`] pushes R vectors into it, reading them with
+//! `get_region()` a few thousand elements at a time, so ALTREP vectors,
+//! such as `1:1e10` or vectors backed by files, are summarised without
+//! being materialised.
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+pub use helloextendr_core::moments::Moments;
+
+/// The number of elements read from R at a time.
+const CHUNK: usize = 4096;
+
+/// Pushing the elements of R vectors into [`Moments`].
+pub trait MomentsExt {
+    /// Push the elements of `x`.
+    fn push_doubles(&mut self, x: &Doubles);
+
+    /// Push the elements of `x`, whose `NA` is a missing value.
+    fn push_integers(&mut self, x: &Integers);
+
+    /// Push the elements of `x` with the weights `w`, which must be as many
+    /// and not negative.
+    fn push_weighted_doubles(&mut self, x: &Doubles, w: &Doubles) -> Result<()>;
+}
+
+impl MomentsExt for Moments {
+    fn push_doubles(&mut self, x: &Doubles) {
+        for_regions(
+            x.len(),
+            Rfloat::from(0.0),
+            |start, buffer| x.get_region(start, buffer),
+            |x| self.push(x.0),
+        );
+    }
+
+    fn push_integers(&mut self, x: &Integers) {
+        for_regions(
+            x.len(),
+            Rint::from(0),
+            |start, buffer| x.get_region(start, buffer),
+            |x| self.push(Option::<i32>::from(x).map_or(<f64 as CanBeNA>::na(), f64::from)),
+        );
+    }
+
+    fn push_weighted_doubles(&mut self, x: &Doubles, w: &Doubles) -> Result<()> {
+        if x.len() != w.len() {
+            return Err(Error::Other(format!(
+                "{} values but {} weights",
+                x.len(),
+                w.len()
+            )));
+        }
+        let len = CHUNK.min(x.len());
+        let (mut values, mut weights) =
+            (vec![Rfloat::from(0.0); len], vec![Rfloat::from(0.0); len]);
+        let mut start = 0;
+        while start < x.len() {
+            let n = x
+                .get_region(start, &mut values)
+                .min(w.get_region(start, &mut weights));
+            if n == 0 {
+                break;
+            }
+            for (x, w) in values[..n].iter().zip(&weights[..n]) {
+                if w.0 < 0.0 {
+                    return Err(Error::Other("weights must not be negative".into()));
+                }
+                self.push_weighted(x.0, w.0);
+            }
+            start += n;
+        }
+        Ok(())
+    }
+}
+
+/// Read `len` elements with `read(start, buffer)`, which fills the buffer
+/// from `start` on and returns how many it read, and call `each` with every
+/// one of them in turn.
+fn for_regions<T, R, E>(len: usize, fill: T, mut read: R, mut each: E)
+where
+    T: Copy,
+    R: FnMut(usize, &mut [T]) -> usize,
+    E: FnMut(T),
+{
+    let mut buffer = vec![fill; CHUNK.min(len)];
+    let mut start = 0;
+    while start < len {
+        let n = read(start, &mut buffer);
+        if n == 0 {
+            break;
+        }
+        buffer[..n].iter().copied().for_each(&mut each);
+        start += n;
+    }
+}
@@ -0,0 +1,262 @@
+//! Newline-delimited JSON, read in batches.
+//!
+//! [`NdjsonReader`] reads a file of one JSON value per line a batch of lines
+//! at a time, parses the lines of a batch on worker threads with
+//! `serde_json`, and hands the values back for conversion on the main
+//! thread, so files much larger than memory can be streamed into R.
+//!
+//! Values are converted as `jsonlite` simplifies them: `null` is `NULL`,
+//! scalars are vectors of length one, arrays of scalars of one type are
+//! vectors, other arrays are lists and objects are named lists. A batch of
+//! objects becomes a data frame with a column per key, in the order keys
+//! first appear, `NA` where a record lacks a key and a list column where
+//! the values of a key are not scalars of one type.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use serde_json::{Map, Value};
+
+use crate::attrib::{set_compact_row_names, AttribExt};
+use crate::parallel::{par_map_slice, ParallelOptions};
+use crate::r_module;
+use crate::xlen::{length_to_robj, robj_to_length};
+
+/// Reads the values of an NDJSON file in batches.
+#[derive(Debug)]
+pub struct NdjsonReader {
+    path: PathBuf,
+    lines: std::io::Lines<BufReader<File>>,
+    /// The number of lines read so far.
+    line: usize,
+}
+
+impl NdjsonReader {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let file = File::open(&path).map_err(|e| io_error(&path, e))?;
+        Ok(Self {
+            lines: BufReader::new(file).lines(),
+            path,
+            line: 0,
+        })
+    }
+
+    /// The values of the next `size` non-empty lines, fewer at the end of
+    /// the file and none after it.
+    pub fn next_batch(&mut self, size: usize) -> Result<Vec<Value>> {
+        // Each line with its number, for errors.
+        let mut lines = Vec::with_capacity(size.min(4096));
+        while lines.len() < size {
+            let line = match self.lines.next() {
+                Some(line) => line.map_err(|e| io_error(&self.path, e))?,
+                None => break,
+            };
+            self.line += 1;
+            if !line.trim().is_empty() {
+                lines.push((self.line, line));
+            }
+        }
+        let parsed = par_map_slice(
+            &lines,
+            ParallelOptions::new().chunk_size(256),
+            |(n, line)| serde_json::from_str::<Value>(line).map_err(|e| (*n, e.to_string())),
+        )?;
+        parsed
+            .into_iter()
+            .map(|value| {
+                value.map_err(|(n, e)| {
+                    Error::Other(format!("{}:{n}: invalid JSON: {e}", self.path.display()))
+                })
+            })
+            .collect()
+    }
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> Error {
+    Error::Other(format!("{}: {e}", path.display()))
+}
+
+/// A JSON value as an R object.
+pub fn value_to_robj(value: &Value) -> Result<Robj> {
+    Ok(match value {
+        Value::Null => r!(NULL),
+        Value::Bool(b) => r!(*b),
+        Value::Number(_) | Value::String(_) => simplify(&[Some(value)])?,
+        Value::Array(values) if values.is_empty() => List::new(0).into(),
+        Value::Array(values) => {
+            let values: Vec<Option<&Value>> = values.iter().map(Some).collect();
+            simplify(&values)?
+        }
+        Value::Object(map) => object_to_robj(map)?,
+    })
+}
+
+fn object_to_robj(map: &Map<String, Value>) -> Result<Robj> {
+    let values = map
+        .values()
+        .map(value_to_robj)
+        .collect::<Result<Vec<_>>>()?;
+    Ok(List::from_names_and_values(map.keys(), values)?.into())
+}
+
+/// The kind of vector a sequence of JSON values fits in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    Logical,
+    Integer,
+    Double,
+    Character,
+    List,
+}
+
+fn kind(value: &Value) -> Option<Kind> {
+    match value {
+        Value::Null => None,
+        Value::Bool(_) => Some(Kind::Logical),
+        Value::Number(n) => match n.as_i64().map(i32::try_from) {
+            // i32::MIN is NA_integer_.
+            Some(Ok(i)) if i != i32::MIN => Some(Kind::Integer),
+            _ => Some(Kind::Double),
+        },
+        Value::String(_) => Some(Kind::Character),
+        Value::Array(_) | Value::Object(_) => Some(Kind::List),
+    }
+}
+
+/// `values` as one vector, `None` and `null` being `NA`: an atomic vector if
+/// all are scalars of one type, where integers widen to doubles, and a list
+/// otherwise.
+fn simplify(values: &[Option<&Value>]) -> Result<Robj> {
+    let kinds: Vec<Kind> = values.iter().flatten().filter_map(|v| kind(v)).collect();
+    let kind = match (kinds.iter().min(), kinds.iter().max()) {
+        (None, _) => Kind::Logical,
+        (Some(&lo), Some(&hi)) if lo == hi => lo,
+        (Some(&Kind::Integer), Some(&Kind::Double)) => Kind::Double,
+        _ => Kind::List,
+    };
+    let values = values.iter().map(|v| v.filter(|v| !v.is_null()));
+    Ok(match kind {
+        Kind::Logical => Logicals::from_values(values.map(|v| match v {
+            Some(Value::Bool(b)) => Rbool::from(*b),
+            _ => Rbool::na(),
+        }))
+        .into(),
+        Kind::Integer => Integers::from_values(values.map(|v| {
+            v.and_then(Value::as_i64)
+                .map_or(Rint::na(), |i| Rint::from(i as i32))
+        }))
+        .into(),
+        Kind::Double => Doubles::from_values(
+            values.map(|v| v.and_then(Value::as_f64).map_or(Rfloat::na(), Rfloat::from)),
+        )
+        .into(),
+        Kind::Character => Strings::from_values(values.map(|v| match v {
+            Some(Value::String(s)) => Rstr::from(s.as_str()),
+            _ => Rstr::na(),
+        }))
+        .into(),
+        Kind::List => List::from_values(
+            values
+                .map(|v| v.map_or(Ok(r!(NULL)), value_to_robj))
+                .collect::<Result<Vec<_>>>()?,
+        )
+        .into(),
+    })
+}
+
+/// A batch of values as a data frame if they are all objects, with a column
+/// per key, and as a list otherwise.
+pub fn batch_to_robj(values: &[Value], simplify_objects: bool) -> Result<Robj> {
+    let objects: Option<Vec<&Map<String, Value>>> = values.iter().map(Value::as_object).collect();
+    match objects {
+        Some(objects) if simplify_objects && !objects.is_empty() => data_frame(&objects),
+        _ => Ok(List::from_values(
+            values
+                .iter()
+                .map(value_to_robj)
+                .collect::<Result<Vec<_>>>()?,
+        )
+        .into()),
+    }
+}
+
+fn data_frame(objects: &[&Map<String, Value>]) -> Result<Robj> {
+    let mut keys: Vec<&str> = Vec::new();
+    let mut seen = HashSet::new();
+    for object in objects {
+        for key in object.keys() {
+            if seen.insert(key.as_str()) {
+                keys.push(key);
+            }
+        }
+    }
+    let columns = keys
+        .iter()
+        .map(|&key| {
+            let values: Vec<Option<&Value>> = objects.iter().map(|o| o.get(key)).collect();
+            simplify(&values)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut df: Robj = List::from_names_and_values(keys, columns)?.into();
+    set_compact_row_names(&mut df, objects.len())?;
+    df.set_attr("class", "data.frame")?;
+    Ok(df)
+}
+
+/// Read newline-delimited JSON
+///
+/// `ndjson_stream()` reads the file `path` of one JSON value per line in
+/// batches of `batch_size` lines, parses each batch on several threads and
+/// calls `callback` with it, so that files larger than memory can be
+/// processed. `ndjson_read()` reads the whole file into one batch.
+///
+/// A batch of objects is a data frame with a column per key, `NA` where a
+/// record lacks the key; with `simplify = FALSE`, or if some value is not an
+/// object, the batch is a list of values. Values are converted as
+/// `jsonlite::fromJSON()` converts them. Empty lines are skipped.
+/// @param path The path of the file.
+/// @param callback A function called with each batch.
+/// @param batch_size The number of lines per batch.
+/// @param simplify Whether to turn batches of objects into data frames.
+/// @return `ndjson_stream()` returns the number of values read, invisibly,
+///   and `ndjson_read()` the values.
+/// @export
+#[extendr(invisible)]
+fn ndjson_stream(
+    path: &str,
+    callback: Function,
+    #[extendr(default = "10000L")] batch_size: Robj,
+    #[extendr(default = "TRUE")] simplify: bool,
+) -> Result<Robj> {
+    let batch_size = robj_to_length(&batch_size)?.max(1);
+    let mut reader = NdjsonReader::open(path)?;
+    let mut count = 0;
+    loop {
+        let batch = reader.next_batch(batch_size)?;
+        if batch.is_empty() {
+            break;
+        }
+        count += batch.len();
+        callback.call(pairlist!(batch_to_robj(&batch, simplify)?))?;
+    }
+    Ok(length_to_robj(count))
+}
+
+/// @rdname ndjson_stream
+/// @export
+#[extendr]
+fn ndjson_read(path: &str, #[extendr(default = "TRUE")] simplify: bool) -> Result<Robj> {
+    let batch = NdjsonReader::open(path)?.next_batch(usize::MAX)?;
+    batch_to_robj(&batch, simplify)
+}
+
+r_module! {
+    mod ndjson;
+    fn ndjson_stream;
+    fn ndjson_read;
+}
@@ -0,0 +1,92 @@
+//! R values held by Rust objects that R holds.
+//!
+//! An `Robj` keeps its value alive until it is dropped. A Rust object behind
+//! an external pointer with `Robj` fields therefore leaks when one of them
+//! refers back to the pointer, as a callback closing over the handle of its
+//! object does: the field keeps the handle reachable, so the handle is never
+//! finalized and the Rust object never drops the field.
+//!
+//! An [`Owned`] field is kept alive by its owner instead, through an R weak
+//! reference with the handle as key: R keeps the value as long as the handle
+//! is reachable by other means than the value, and collects both together
+//! once it is not. The handle must exist before the fields it owns, so an
+//! object is wrapped first and given its R values after:
+//!
+//! ```ignore
+//! let mut handle: Robj = ExternalPtr::new(Watcher::default()).into();
+//! let callback = Owned::new(&handle, callback)?;
+//! watcher_mut(&mut handle)?.callback = Some(callback);
+//! ```
+//!
+//! R keeps a weak reference until its key is collected, even when the
+//! [`Owned`] is dropped earlier, so this suits the few long-lived fields of
+//! an object rather than the elements of a container.
+
+use std::fmt;
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use extendr_ffi::Rboolean;
+
+use crate::preserve::Preserved;
+use crate::rapi::{R_MakeWeakRef, R_WeakRefKey, R_WeakRefValue};
+use crate::sexp::{Sexp, SexpRef};
+
+/// An R value kept alive by the external pointer or environment owning it.
+#[derive(Clone)]
+pub struct Owned {
+    weakref: Preserved,
+}
+
+impl Owned {
+    /// `value`, owned by `owner`, an external pointer or an environment.
+    pub fn new(owner: &Robj, value: impl Into<Robj>) -> Result<Self> {
+        if !matches!(owner.rtype(), Rtype::ExternalPtr | Rtype::Environment) {
+            return Err(Error::Other(format!(
+                "an owner must be an external pointer or an environment, not {:?}",
+                owner.rtype()
+            )));
+        }
+        let value = value.into();
+        let weakref = unsafe {
+            let weakref = R_MakeWeakRef(
+                Sexp::of(owner),
+                Sexp::of(&value),
+                Sexp::of(&r!(NULL)),
+                Rboolean::FALSE,
+            );
+            Preserved::new(Robj::from_sexp(weakref.as_raw()))
+        };
+        Ok(Self { weakref })
+    }
+
+    /// The value, or `None` once the owner was collected, which cannot
+    /// happen while the object behind the owner holds this.
+    pub fn get(&self) -> Option<Robj> {
+        let key = unsafe { Robj::from_sexp(R_WeakRefKey(SexpRef::of(&*self.weakref)).as_raw()) };
+        if key.is_null() {
+            return None;
+        }
+        Some(unsafe { Robj::from_sexp(R_WeakRefValue(SexpRef::of(&*self.weakref)).as_raw()) })
+    }
+
+    /// The value as a `T`, failing once the owner was collected.
+    pub fn get_as<T>(&self) -> Result<T>
+    where
+        T: TryFrom<Robj, Error = Error>,
+    {
+        let value = self
+            .get()
+            .ok_or_else(|| Error::Other("the owner of the value was collected".into()))?;
+        T::try_from(value)
+    }
+}
+
+impl fmt::Debug for Owned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.get() {
+            Some(value) => f.debug_tuple("Owned").field(&value.rtype()).finish(),
+            None => f.write_str("Owned(<collected>)"),
+        }
+    }
+}
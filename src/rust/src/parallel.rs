@@ -0,0 +1,259 @@
+//! Parallel maps over R vectors.
+//!
+//! The R API must only ever be used from the main thread. These helpers
+//! borrow the input as a plain slice, run the closure on a pool of worker
+//! threads that never see an R object, and allocate the result vector back
+//! on the main thread. While the workers run, the main thread checks for
+//! user interrupts between chunks, so Ctrl-C stops the computation instead
+//! of waiting for it.
+//!
+//! The workers are a rayon thread pool of the requested size, and the
+//! closures run in a scope, so they may borrow from the caller. Each pool
+//! is started on first use and kept for later maps with as many threads,
+//! until the package is unloaded.
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+#[cfg(not(feature = "cran-strict"))]
+use extendr_ffi::Rboolean;
+use rayon::prelude::*;
+use rayon::ThreadPool;
+use std::collections::HashMap;
+#[cfg(not(feature = "cran-strict"))]
+use std::os::raw::c_void;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex, OnceLock};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::teardown;
+
+// Not exported by `extendr_ffi`. `R_ToplevelExec()` is not part of R's API.
+#[cfg(not(feature = "cran-strict"))]
+extern "C" {
+    fn R_ToplevelExec(fun: extern "C" fn(*mut c_void), data: *mut c_void) -> Rboolean;
+}
+
+/// How often the main thread looks for interrupts while waiting on workers.
+const INTERRUPT_POLL: Duration = Duration::from_millis(50);
+
+/// The pools started so far, by number of threads.
+static POOLS: OnceLock<Mutex<HashMap<usize, Arc<ThreadPool>>>> = OnceLock::new();
+
+/// The threads of all pools, joined once the pools are dropped.
+static WORKERS: Mutex<Vec<JoinHandle<()>>> = Mutex::new(Vec::new());
+
+/// The pool of `threads` worker threads, started on first use.
+fn pool(threads: usize) -> Result<Arc<ThreadPool>> {
+    let pools = POOLS.get_or_init(|| {
+        teardown::on_unload("thread pools", stop_pools);
+        Mutex::new(HashMap::new())
+    });
+    let mut pools = pools.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(pool) = pools.get(&threads) {
+        return Ok(pool.clone());
+    }
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .spawn_handler(|thread| {
+            let worker = std::thread::Builder::new().spawn(|| thread.run())?;
+            WORKERS
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(worker);
+            Ok(())
+        })
+        .build()
+        .map_err(|e| Error::Other(format!("cannot start the worker threads: {e}")))?;
+    let pool = Arc::new(pool);
+    pools.insert(threads, pool.clone());
+    Ok(pool)
+}
+
+/// Drop the pools, which ends their threads, and join the threads.
+fn stop_pools() {
+    if let Some(pools) = POOLS.get() {
+        pools.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+    let workers = std::mem::take(&mut *WORKERS.lock().unwrap_or_else(|e| e.into_inner()));
+    for worker in workers {
+        let _ = worker.join();
+    }
+}
+
+/// How a parallel map splits its work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParallelOptions {
+    threads: usize,
+    chunk_size: usize,
+}
+
+impl Default for ParallelOptions {
+    fn default() -> Self {
+        Self {
+            threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            chunk_size: 4096,
+        }
+    }
+}
+
+impl ParallelOptions {
+    /// One worker per available CPU and chunks of 4096 elements.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of worker threads, at least one.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Elements per chunk, at least one. Interrupts are checked whenever a
+    /// chunk completes.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+}
+
+/// Apply `f` to every element of `input` in parallel. `NA` is passed as R's
+/// `NA_real_` NaN, which arithmetic propagates.
+pub fn par_map_doubles(input: &Doubles, f: impl Fn(f64) -> f64 + Sync) -> Result<Doubles> {
+    par_map_doubles_with(input, ParallelOptions::default(), f)
+}
+
+pub fn par_map_doubles_with(
+    input: &Doubles,
+    options: ParallelOptions,
+    f: impl Fn(f64) -> f64 + Sync,
+) -> Result<Doubles> {
+    let output = par_map_slice(input, options, |x| f(x.0))?;
+    Ok(Doubles::from_values(output))
+}
+
+/// Apply `f` to every element of `input` in parallel, with `None` for `NA`.
+pub fn par_map_integers(
+    input: &Integers,
+    f: impl Fn(Option<i32>) -> Option<i32> + Sync,
+) -> Result<Integers> {
+    par_map_integers_with(input, ParallelOptions::default(), f)
+}
+
+pub fn par_map_integers_with(
+    input: &Integers,
+    options: ParallelOptions,
+    f: impl Fn(Option<i32>) -> Option<i32> + Sync,
+) -> Result<Integers> {
+    let output = par_map_slice(input, options, |x| {
+        f(Option::<i32>::from(*x)).map_or(Rint::na(), Rint::from)
+    })?;
+    Ok(Integers::from_values(output))
+}
+
+/// Map `f` over a slice on worker threads and return the results in order.
+///
+/// This is the building block of the typed helpers: copy or borrow the data
+/// out of R, call this, and convert the result on the main thread. Fails if
+/// the user interrupts or if `f` panics.
+pub fn par_map_slice<T, U, F>(input: &[T], options: ParallelOptions, f: F) -> Result<Vec<U>>
+where
+    T: Sync,
+    U: Send,
+    F: Fn(&T) -> U + Sync,
+{
+    let chunks: Vec<&[T]> = input.chunks(options.chunk_size).collect();
+    let threads = options.threads.min(chunks.len());
+    if threads <= 1 {
+        return serial_map(&chunks, &f);
+    }
+
+    let pool = pool(threads)?;
+    let cancelled = AtomicBool::new(false);
+    let panicked = AtomicBool::new(false);
+    let mut results: Vec<Option<Vec<U>>> = (0..chunks.len()).map(|_| None).collect();
+
+    // The main thread stays in the scope to collect results and check for
+    // interrupts while the pool maps the chunks.
+    let outcome = pool.in_place_scope(|scope| {
+        let (tx, rx) = mpsc::channel();
+        let (chunks, cancelled, panicked, f) = (&chunks, &cancelled, &panicked, &f);
+        scope.spawn(move |_| {
+            let mapped = catch_unwind(AssertUnwindSafe(|| {
+                chunks
+                    .par_iter()
+                    .enumerate()
+                    .for_each_with(tx, |tx, (i, chunk)| {
+                        if cancelled.load(Ordering::Relaxed) {
+                            return;
+                        }
+                        let mapped: Vec<U> = chunk.iter().map(f).collect();
+                        // The receiver is gone once the map was cancelled.
+                        let _ = tx.send((i, mapped));
+                    })
+            }));
+            panicked.store(mapped.is_err(), Ordering::Relaxed);
+        });
+
+        let mut outcome = Ok(());
+        let mut done = 0;
+        while done < chunks.len() {
+            match rx.recv_timeout(INTERRUPT_POLL) {
+                Ok((i, mapped)) => {
+                    results[i] = Some(mapped);
+                    done += 1;
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                // The map ended early, which only happens on a panic.
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+            if interrupt_pending() {
+                outcome = Err(Error::Other("interrupted by the user".into()));
+                break;
+            }
+        }
+        cancelled.store(true, Ordering::Relaxed);
+        outcome
+    });
+    outcome?;
+    // Read after the scope, which waits for the map to finish.
+    if panicked.load(Ordering::Relaxed) {
+        return Err(Error::Other("a parallel worker panicked".into()));
+    }
+
+    Ok(results.into_iter().flatten().flatten().collect())
+}
+
+fn serial_map<T, U>(chunks: &[&[T]], f: &impl Fn(&T) -> U) -> Result<Vec<U>> {
+    let mut output = Vec::with_capacity(chunks.iter().map(|c| c.len()).sum());
+    for chunk in chunks {
+        output.extend(chunk.iter().map(f));
+        if interrupt_pending() {
+            return Err(Error::Other("interrupted by the user".into()));
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(not(feature = "cran-strict"))]
+extern "C" fn check_interrupt(_: *mut c_void) {
+    unsafe { crate::rapi::R_CheckUserInterrupt() }
+}
+
+/// Whether the user pressed Ctrl-C. `R_CheckUserInterrupt()` jumps out on an
+/// interrupt, so it runs under `R_ToplevelExec()`, which catches the jump.
+#[cfg(not(feature = "cran-strict"))]
+pub(crate) fn interrupt_pending() -> bool {
+    let completed = unsafe { R_ToplevelExec(check_interrupt, std::ptr::null_mut()) };
+    completed == Rboolean::FALSE
+}
+
+/// Whether the user pressed Ctrl-C, through R's API only: `Sys.sleep(0)`
+/// checks for interrupts, and evaluating R code catches the jump. It also
+/// runs the input handlers of the event loop, as `Sys.sleep()` does in R
+/// code.
+#[cfg(feature = "cran-strict")]
+pub(crate) fn interrupt_pending() -> bool {
+    lang!("Sys.sleep", 0.0).eval().is_err()
+}
@@ -0,0 +1,111 @@
+//! Minimal updates between versions of a numeric vector.
+//!
+//! A reactive backend holding a vector on both sides of a connection can
+//! send the elements that changed instead of the whole vector: [`diff()`]
+//! computes the [`Patch`] from the old version to the new one, and
+//! [`apply_patch()`] turns the old version into the new one on the other
+//! side. Elements are compared by their bits, so `NA` and `NaN` differ from
+//! each other and equal themselves.
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+use crate::xlen::length_to_robj;
+
+/// The changes from a vector of `old_len` elements to one of `new_len`:
+/// the elements at zero-based `indices` take `values`. The elements past
+/// `old_len` are always among them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Patch {
+    pub old_len: usize,
+    pub new_len: usize,
+    pub indices: Vec<usize>,
+    pub values: Vec<f64>,
+}
+
+impl Patch {
+    /// Whether the patch changes nothing.
+    pub fn is_empty(&self) -> bool {
+        self.old_len == self.new_len && self.indices.is_empty()
+    }
+
+    /// The patch as an R list with `old_length`, `new_length`, the
+    /// one-based `index` of the changed elements and their `value`.
+    pub fn to_robj(&self) -> Result<Robj> {
+        // Doubles, so that indices into long vectors fit.
+        let index = Doubles::from_values(self.indices.iter().map(|&i| (i + 1) as f64));
+        Ok(List::from_names_and_values(
+            ["old_length", "new_length", "index", "value"],
+            [
+                length_to_robj(self.old_len),
+                length_to_robj(self.new_len),
+                index.into(),
+                Doubles::from_values(self.values.iter().copied()).into(),
+            ],
+        )?
+        .into())
+    }
+}
+
+fn same(a: Rfloat, b: Rfloat) -> bool {
+    a.0.to_bits() == b.0.to_bits()
+}
+
+/// The patch turning `old` into `new`.
+pub fn diff(old: &Doubles, new: &Doubles) -> Patch {
+    let mut indices = Vec::new();
+    let mut values = Vec::new();
+    for (i, value) in new.iter().enumerate() {
+        let changed = i >= old.len() || !same(old.elt(i), value);
+        if changed {
+            indices.push(i);
+            values.push(value.0);
+        }
+    }
+    Patch {
+        old_len: old.len(),
+        new_len: new.len(),
+        indices,
+        values,
+    }
+}
+
+/// Apply `patch` to `old`, the vector it was computed from.
+pub fn apply_patch(old: &Doubles, patch: &Patch) -> Result<Doubles> {
+    if old.len() != patch.old_len {
+        return Err(Error::Other(format!(
+            "the patch applies to {} elements, not {}",
+            patch.old_len,
+            old.len()
+        )));
+    }
+    if patch.indices.len() != patch.values.len() {
+        return Err(Error::Other(format!(
+            "the patch has {} indices but {} values",
+            patch.indices.len(),
+            patch.values.len()
+        )));
+    }
+    let mut new: Vec<f64> = old.iter().take(patch.new_len).map(|x| x.0).collect();
+    new.resize(patch.new_len, f64::NAN);
+    let mut set = vec![false; patch.new_len.saturating_sub(patch.old_len)];
+    for (&i, &value) in patch.indices.iter().zip(&patch.values) {
+        if i >= patch.new_len {
+            return Err(Error::Other(format!(
+                "patch index {} is out of bounds for {} elements",
+                i + 1,
+                patch.new_len
+            )));
+        }
+        new[i] = value;
+        if i >= patch.old_len {
+            set[i - patch.old_len] = true;
+        }
+    }
+    if set.iter().any(|&set| !set) {
+        return Err(Error::Other(
+            "the patch does not set every element it adds".into(),
+        ));
+    }
+    Ok(Doubles::from_values(new))
+}
@@ -0,0 +1,238 @@
+//! External pointers whose Rust state survives serialization.
+//!
+//! R saves an external pointer without its address, so an object holding
+//! Rust state through an `ExternalPtr` comes back from `saveRDS()`,
+//! `save.image()` or a parallel worker as a null pointer, and the next call
+//! using it fails or crashes. A handle made by [`new_handle()`] for a
+//! [`Persistable`] type also keeps a snapshot of the state as a raw vector
+//! in the protected slot of the pointer, which R does save, and
+//! [`get_mut()`] rebuilds the state from it the first time a restored
+//! handle is used.
+//!
+//! The snapshot is only as recent as the last [`checkpoint()`], so
+//! functions that change the state call it before returning:
+//!
+//! ```ignore
+//! let counter = persist::get_mut::<Counter>(&mut handle)?;
+//! counter.add(1);
+//! persist::checkpoint::<Counter>(&handle)?;
+//! ```
+//!
+//! Every checkpoint encodes the whole state, so this suits state that is
+//! small or changed in batches rather than element by element.
+//!
+//! A workspace saved with such handles is often loaded before the package
+//! is. When the package loads, `rehydrate()` scans the global environment
+//! and rebuilds the state of every handle whose type was registered with
+//! [`register_persistable()`], so that a broken snapshot is reported then
+//! rather than in the middle of later work. Handles restored afterwards,
+//! e.g. by `readRDS()`, are rebuilt on first use.
+
+use std::sync::{Mutex, Once};
+
+use extendr_api::prelude::*;
+use extendr_api::robj::GetSexp;
+use extendr_api::Result;
+use extendr_ffi::{
+    R_ClearExternalPtr, R_ExternalPtrAddr, R_ExternalPtrProtected, R_ExternalPtrTag,
+    R_MakeExternalPtr, R_RegisterCFinalizerEx, R_SetExternalPtrAddr, R_SetExternalPtrProtected,
+    Rboolean, SEXP,
+};
+
+use crate::bits::{BitSet, BloomFilter};
+use crate::r_module;
+use crate::raw_io::IntoRaw;
+use crate::xlen::length_to_robj;
+
+/// Rebuilds the state of a handle restored from a serialization.
+type Rehydrate = fn(&mut Robj) -> Result<()>;
+
+/// The persistable types by tag.
+static REHYDRATORS: Mutex<Vec<(&'static str, Rehydrate)>> = Mutex::new(Vec::new());
+
+/// Rust state that can be encoded in bytes and rebuilt from them.
+pub trait Persistable: Sized + 'static {
+    /// Identifies the type and the version of its encoding. Restoring a
+    /// handle with another tag fails instead of decoding the wrong bytes,
+    /// so change it when the encoding changes.
+    const TAG: &'static str;
+
+    fn to_bytes(&self) -> Vec<u8>;
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self>;
+}
+
+unsafe extern "C" fn finalize<T: Persistable>(handle: SEXP) {
+    let address = R_ExternalPtrAddr(handle) as *mut T;
+    if address.is_null() {
+        return;
+    }
+    R_ClearExternalPtr(handle);
+    // A panic must not unwind into R.
+    let drop_value = std::panic::AssertUnwindSafe(|| drop(Box::from_raw(address)));
+    let _ = std::panic::catch_unwind(drop_value);
+}
+
+/// Box `value` behind the address of `handle` and free it when R collects
+/// the handle.
+unsafe fn attach<T: Persistable>(handle: SEXP, value: T) {
+    R_SetExternalPtrAddr(handle, Box::into_raw(Box::new(value)).cast());
+    R_RegisterCFinalizerEx(handle, Some(finalize::<T>), Rboolean::TRUE);
+}
+
+/// A new external pointer owning `value`, with a snapshot of it.
+pub fn new_handle<T: Persistable>(value: T) -> Robj {
+    let tag = r!(T::TAG);
+    let snapshot = value.to_bytes().into_raw();
+    unsafe {
+        let handle = Robj::from_sexp(R_MakeExternalPtr(
+            std::ptr::null_mut(),
+            tag.get(),
+            snapshot.get(),
+        ));
+        attach(handle.get(), value);
+        handle
+    }
+}
+
+fn check_tag<T: Persistable>(handle: &Robj) -> Result<()> {
+    if handle.rtype() == Rtype::ExternalPtr {
+        let tag = unsafe { Robj::from_sexp(R_ExternalPtrTag(handle.get())) };
+        if tag.as_str() == Some(T::TAG) {
+            return Ok(());
+        }
+    }
+    Err(Error::Other(format!(
+        "expected an external pointer to `{}`",
+        T::TAG
+    )))
+}
+
+/// The state owned by `handle`, rebuilt from its snapshot if the handle
+/// was restored from a serialization.
+pub fn get_mut<T: Persistable>(handle: &mut Robj) -> Result<&mut T> {
+    check_tag::<T>(handle)?;
+    let sexp = unsafe { handle.get() };
+    unsafe {
+        if R_ExternalPtrAddr(sexp).is_null() {
+            let snapshot = Robj::from_sexp(R_ExternalPtrProtected(sexp));
+            let bytes = snapshot
+                .as_raw_slice()
+                .ok_or_else(|| Error::Other(format!("the `{}` pointer has no snapshot", T::TAG)))?;
+            let value = T::from_bytes(bytes).map_err(|e| {
+                Error::Other(format!("cannot restore the `{}` pointer: {e}", T::TAG))
+            })?;
+            attach(sexp, value);
+        }
+        // Safety: the address is a `Box<T>`, as the tag says, and lives as
+        // long as the handle.
+        Ok(&mut *(R_ExternalPtrAddr(sexp) as *mut T))
+    }
+}
+
+/// Replace the snapshot of `handle` with its current state, so that a
+/// serialization of the handle restores that state.
+pub fn checkpoint<T: Persistable>(handle: &Robj) -> Result<()> {
+    check_tag::<T>(handle)?;
+    let sexp = unsafe { handle.get() };
+    let address = unsafe { R_ExternalPtrAddr(sexp) } as *const T;
+    // A handle never used since it was restored still has a valid snapshot.
+    if address.is_null() {
+        return Ok(());
+    }
+    let snapshot = unsafe { &*address }.to_bytes().into_raw();
+    unsafe { R_SetExternalPtrProtected(sexp, snapshot.get()) };
+    Ok(())
+}
+
+fn rehydrate_handle<T: Persistable>(handle: &mut Robj) -> Result<()> {
+    get_mut::<T>(handle).map(|_| ())
+}
+
+/// Rebuild the handles to `T` found by `rehydrate()`. Registering a type
+/// again has no effect.
+pub fn register_persistable<T: Persistable>() {
+    let mut rehydrators = REHYDRATORS.lock().unwrap_or_else(|e| e.into_inner());
+    if rehydrators.iter().all(|&(tag, _)| tag != T::TAG) {
+        rehydrators.push((T::TAG, rehydrate_handle::<T>));
+    }
+}
+
+/// The rehydrator of `handle` if it is a registered handle without state.
+fn pending_rehydrator(handle: &Robj) -> Option<Rehydrate> {
+    static BUILTIN: Once = Once::new();
+    BUILTIN.call_once(|| {
+        register_persistable::<BitSet>();
+        register_persistable::<BloomFilter>();
+    });
+    if handle.rtype() != Rtype::ExternalPtr || !unsafe { R_ExternalPtrAddr(handle.get()) }.is_null()
+    {
+        return None;
+    }
+    let tag = unsafe { Robj::from_sexp(R_ExternalPtrTag(handle.get())) };
+    let tag = tag.as_str()?;
+    let rehydrators = REHYDRATORS.lock().unwrap_or_else(|e| e.into_inner());
+    rehydrators
+        .iter()
+        .find(|&&(t, _)| t == tag)
+        .map(|&(_, rehydrate)| rehydrate)
+}
+
+/// Rebuild the registered handles in `x` and the lists it contains, and
+/// return how many there were. `path` names `x` in errors.
+fn rehydrate_value(x: &Robj, path: &str) -> Result<usize> {
+    if let Some(rehydrate) = pending_rehydrator(x) {
+        // A clone is the same pointer, whose address is set in place.
+        rehydrate(&mut x.clone()).map_err(|e| Error::Other(format!("`{path}`: {e}")))?;
+        return Ok(1);
+    }
+    let mut count = 0;
+    if let Some(list) = x.as_list() {
+        for (i, (name, value)) in list.iter().enumerate() {
+            let path = if name.is_empty() || name == "NA" {
+                format!("{path}[[{}]]", i + 1)
+            } else {
+                format!("{path}${name}")
+            };
+            count += rehydrate_value(&value, &path)?;
+        }
+    }
+    Ok(count)
+}
+
+/// Rebuild saved objects
+///
+/// Objects of this package that hold native state, such as bit sets, can be
+/// saved and read back, and their state is rebuilt the first time they are
+/// used. `rehydrate()` rebuilds it at once for the objects in `x`, so that
+/// a damaged object is reported now. It runs on the global environment when
+/// the package is loaded, for workspaces restored before that.
+/// @param x An object, a list of objects or an environment. Lists are
+///   searched recursively, and environments for their variables but not
+///   further environments.
+/// @return The number of objects rebuilt, invisibly.
+/// @export
+#[extendr(invisible)]
+fn rehydrate(x: Robj) -> Result<Robj> {
+    let count = match x.as_environment() {
+        Some(env) => {
+            let mut count = 0;
+            let names = R!("ls({{ env.clone() }}, all.names = TRUE)")?;
+            for name in names.as_str_vector().unwrap_or_default() {
+                let value = env.local(Symbol::from_string(name))?;
+                // Not forced: a promise holds no handle until it is.
+                if value.rtype() != Rtype::Promise {
+                    count += rehydrate_value(&value, name)?;
+                }
+            }
+            count
+        }
+        None => rehydrate_value(&x, "x")?,
+    };
+    Ok(length_to_robj(count))
+}
+
+r_module! {
+    mod persist;
+    fn rehydrate;
+}
@@ -0,0 +1,194 @@
+//! Accounting of the R values that Rust code keeps alive.
+//!
+//! Every `Robj` protects its value from R's garbage collector until it is
+//! dropped, so one kept in a static, in a leaked box or in a cycle through
+//! an external pointer (see [`crate::owned`]) holds its value, and all
+//! that value refers to, for the rest of the session. Such leaks are hard to
+//! find from R, which only sees memory that is never freed.
+//!
+//! Rust code that keeps an R value beyond the call that got it wraps it in a
+//! [`Preserved`], which records where it was created until it is dropped.
+//! [`preserved_objects()`] and `preserved_object_report()` list the values
+//! alive, and [`test_with_r!`](crate::test_with_r) fails a test that leaves
+//! some behind. With the `preserve-backtraces` feature, each record also
+//! has the backtrace of its creation, which costs a stack walk per value.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::ops::Deref;
+use std::panic::Location;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+use crate::backend::{data_frame, Backend, LibR, VectorData};
+use crate::r_module;
+use crate::rapi::TYPEOF;
+use crate::sexp::{SexpRef, SexpType};
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// The records of the live [`Preserved`] values, by id.
+static LIVE: Mutex<BTreeMap<u64, PreservedObject>> = Mutex::new(BTreeMap::new());
+
+/// Where a live [`Preserved`] value was created.
+#[derive(Debug, Clone)]
+pub struct PreservedObject {
+    /// Increases with the time of creation.
+    pub id: u64,
+    /// The `SEXPTYPE` of the value, see [`SexpType`].
+    pub sexptype: u32,
+    pub location: &'static Location<'static>,
+    /// With the `preserve-backtraces` feature only.
+    pub backtrace: Option<String>,
+}
+
+impl PreservedObject {
+    /// The name of the type of the value.
+    pub fn type_name(&self) -> String {
+        match SexpType::try_from(self.sexptype) {
+            Ok(sexptype) => format!("{sexptype:?}"),
+            Err(e) => e.to_string(),
+        }
+    }
+}
+
+/// An R value kept alive by Rust code, and accounted for until dropped.
+#[derive(Debug)]
+pub struct Preserved {
+    robj: Robj,
+    id: u64,
+}
+
+impl Preserved {
+    #[track_caller]
+    pub fn new(robj: impl Into<Robj>) -> Self {
+        let robj = robj.into();
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let record = PreservedObject {
+            id,
+            sexptype: unsafe { TYPEOF(SexpRef::of(&robj)) } as u32,
+            location: Location::caller(),
+            backtrace: backtrace(),
+        };
+        LIVE.lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(id, record);
+        Self { robj, id }
+    }
+
+    /// The value, which stays preserved until the last clone of it is
+    /// dropped but is no longer accounted for.
+    pub fn into_inner(self) -> Robj {
+        self.robj.clone()
+    }
+}
+
+impl Clone for Preserved {
+    #[track_caller]
+    fn clone(&self) -> Self {
+        Self::new(self.robj.clone())
+    }
+}
+
+impl Deref for Preserved {
+    type Target = Robj;
+
+    fn deref(&self) -> &Robj {
+        &self.robj
+    }
+}
+
+impl Drop for Preserved {
+    fn drop(&mut self) {
+        LIVE.lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.id);
+    }
+}
+
+#[cfg(feature = "preserve-backtraces")]
+fn backtrace() -> Option<String> {
+    Some(std::backtrace::Backtrace::force_capture().to_string())
+}
+
+#[cfg(not(feature = "preserve-backtraces"))]
+fn backtrace() -> Option<String> {
+    None
+}
+
+/// The live [`Preserved`] values, oldest first.
+pub fn preserved_objects() -> Vec<PreservedObject> {
+    let live = LIVE.lock().unwrap_or_else(|e| e.into_inner());
+    live.values().cloned().collect()
+}
+
+/// The id the next [`Preserved`] value will have, which separates the
+/// values created before and after a point.
+pub fn next_id() -> u64 {
+    NEXT_ID.load(Ordering::Relaxed)
+}
+
+/// Fail if values created since `since`, an earlier [`next_id()`], are
+/// still preserved, after a garbage collection has finalized the external
+/// pointers that are no longer used.
+pub fn check_released(since: u64) -> Result<()> {
+    lang!("gc", verbose = false).eval()?;
+    let leaked: Vec<String> = preserved_objects()
+        .into_iter()
+        .filter(|object| object.id >= since)
+        .map(|object| {
+            let mut line = format!("{} preserved at {}", object.type_name(), object.location);
+            if let Some(backtrace) = &object.backtrace {
+                line.push('\n');
+                line.push_str(backtrace);
+            }
+            line
+        })
+        .collect();
+    if leaked.is_empty() {
+        return Ok(());
+    }
+    Err(Error::Other(format!(
+        "{} R value(s) still preserved:\n{}",
+        leaked.len(),
+        leaked.join("\n")
+    )))
+}
+
+/// R values kept alive by Rust code
+///
+/// Lists the R values that the native code of the package keeps alive, with
+/// the place in the Rust sources where each was kept. A value listed after
+/// the objects using it are gone, and `gc()` has run, is a leak. The
+/// backtraces are only recorded when the package is built with the
+/// `preserve-backtraces` feature.
+/// @return A data frame with the `type` of each value, the `location` it was
+///   preserved at and the `backtrace` of that moment, or `NA`.
+/// @export
+#[extendr]
+fn preserved_object_report() -> Result<Robj> {
+    let objects = preserved_objects();
+    let column = |f: fn(&PreservedObject) -> Option<String>| {
+        LibR.alloc_vector(VectorData::Character(objects.iter().map(f).collect()))
+    };
+    let types = column(|object| Some(object.type_name()))?;
+    let locations = column(|object| Some(object.location.to_string()))?;
+    let backtraces = column(|object| object.backtrace.clone())?;
+    data_frame(
+        &LibR,
+        vec![
+            ("type", types),
+            ("location", locations),
+            ("backtrace", backtraces),
+        ],
+        objects.len(),
+    )
+}
+
+r_module! {
+    mod preserve;
+    fn preserved_object_report;
+}
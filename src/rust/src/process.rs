@@ -0,0 +1,454 @@
+//! Subprocesses with output streamed to the main thread.
+//!
+//! [`Process::spawn()`] starts a command with its standard output and error
+//! read line by line by background threads. Each stream goes to a
+//! [`Sink`]: lines are either collected, to be read with
+//! [`Process::take_output()`], or passed to a callback. Either way they are
+//! handled on the main thread only, by the [`event_loop`](crate::event_loop)
+//! and while [`Process::wait()`] blocks, so callbacks may use the R API.
+//!
+//! A process still running when its [`Process`] is dropped, such as when
+//! the R handle is garbage collected, is killed, as are all processes when
+//! the package is unloaded, by [`teardown`](crate::teardown).
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Child, Command, Stdio};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, Once};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::attrib::AttribExt;
+use crate::event_loop;
+use crate::parallel::interrupt_pending;
+use crate::r_module;
+use crate::teardown;
+
+/// How often `wait()` checks the process, its output and interrupts.
+const WAIT_POLL: Duration = Duration::from_millis(20);
+
+/// How long `wait()` lets reader threads finish after the process exited.
+/// Children of the process that inherited its output keep the pipes open.
+const DRAIN_TIMEOUT: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Where the lines of an output stream go.
+pub enum Sink {
+    /// Keep them for [`Process::take_output()`].
+    Collect,
+    /// Pass each line, without its terminator, to a callback.
+    Callback(Box<dyn FnMut(&str)>),
+    /// Do not capture the stream at all.
+    Discard,
+}
+
+type LineCallback = Rc<RefCell<dyn FnMut(&str)>>;
+
+enum Handler {
+    Collect(Vec<String>),
+    Callback(LineCallback),
+}
+
+/// Identifies processes in the queue; process ids may be reused.
+static NEXT_KEY: AtomicU32 = AtomicU32::new(1);
+
+/// Lines waiting for the main thread, by process key and stream.
+static PENDING: Mutex<Vec<(u32, Stream, String)>> = Mutex::new(Vec::new());
+
+/// A process not dropped yet and the threads reading its output.
+struct Running {
+    key: u32,
+    child: Arc<Mutex<Child>>,
+    readers: Vec<JoinHandle<()>>,
+}
+
+static RUNNING: Mutex<Vec<Running>> = Mutex::new(Vec::new());
+
+thread_local! {
+    // Only ever touched from the main thread.
+    static HANDLERS: RefCell<HashMap<(u32, Stream), Handler>> = RefCell::new(HashMap::new());
+}
+
+/// A running or finished subprocess.
+pub struct Process {
+    key: u32,
+    /// Shared with [`RUNNING`], to be killed on unload.
+    child: Arc<Mutex<Child>>,
+    status: Option<Option<i32>>,
+}
+
+impl Process {
+    /// Start `command` with its output going to `stdout` and `stderr`. Its
+    /// standard input is closed.
+    pub fn spawn(command: &mut Command, stdout: Sink, stderr: Sink) -> Result<Self> {
+        let pipe = |sink: &Sink| match sink {
+            Sink::Discard => Stdio::null(),
+            _ => Stdio::piped(),
+        };
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(pipe(&stdout))
+            .stderr(pipe(&stderr))
+            .spawn()
+            .map_err(|e| Error::Other(format!("cannot start {:?}: {e}", command.get_program())))?;
+        let key = NEXT_KEY.fetch_add(1, Ordering::Relaxed);
+        event_loop::register(dispatch_pending)?;
+
+        let mut readers = Vec::new();
+        for (stream, sink) in [(Stream::Stdout, stdout), (Stream::Stderr, stderr)] {
+            let handler = match sink {
+                Sink::Discard => continue,
+                Sink::Collect => Handler::Collect(Vec::new()),
+                Sink::Callback(callback) => Handler::Callback(Rc::new(RefCell::new(callback))),
+            };
+            HANDLERS.with(|handlers| handlers.borrow_mut().insert((key, stream), handler));
+            let pipe: Box<dyn Read + Send> = match stream {
+                Stream::Stdout => Box::new(child.stdout.take().unwrap()),
+                Stream::Stderr => Box::new(child.stderr.take().unwrap()),
+            };
+            readers.push(std::thread::spawn(move || read_lines(key, stream, pipe)));
+        }
+        static TEARDOWN: Once = Once::new();
+        TEARDOWN.call_once(|| teardown::on_unload("subprocesses", kill_all));
+        let child = Arc::new(Mutex::new(child));
+        RUNNING
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(Running {
+                key,
+                child: child.clone(),
+                readers,
+            });
+        Ok(Self {
+            key,
+            child,
+            status: None,
+        })
+    }
+
+    fn child(&self) -> std::sync::MutexGuard<'_, Child> {
+        self.child.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.child().id()
+    }
+
+    pub fn is_alive(&mut self) -> bool {
+        self.try_status().is_none()
+    }
+
+    /// Wait for the process to exit, for at most `timeout` if given, and
+    /// return its exit code, or `None` if it was killed by a signal. Output
+    /// is dispatched while waiting and all of it has been by the time the
+    /// process is reported as finished. Returns `Ok(None)` on timeout.
+    pub fn wait(&mut self, timeout: Option<Duration>) -> Result<Option<Option<i32>>> {
+        let start = Instant::now();
+        loop {
+            dispatch_pending();
+            if let Some(status) = self.try_status() {
+                self.drain();
+                return Ok(Some(status));
+            }
+            if timeout.is_some_and(|timeout| start.elapsed() >= timeout) {
+                return Ok(None);
+            }
+            if interrupt_pending() {
+                return Err(Error::Other("interrupted by the user".into()));
+            }
+            std::thread::sleep(WAIT_POLL);
+        }
+    }
+
+    /// Kill the process. Returns whether it was still running.
+    pub fn kill(&mut self) -> bool {
+        if !self.is_alive() {
+            return false;
+        }
+        let mut child = self.child();
+        let _ = child.kill();
+        let status = child.wait().ok().and_then(|s| s.code());
+        drop(child);
+        self.status = Some(status);
+        true
+    }
+
+    /// The lines of `stream` collected and not taken yet. Empty unless the
+    /// stream was spawned with [`Sink::Collect`].
+    pub fn take_output(&mut self, stream: Stream) -> Vec<String> {
+        dispatch_pending();
+        HANDLERS.with(
+            |handlers| match handlers.borrow_mut().get_mut(&(self.key, stream)) {
+                Some(Handler::Collect(lines)) => std::mem::take(lines),
+                _ => Vec::new(),
+            },
+        )
+    }
+
+    fn try_status(&mut self) -> Option<Option<i32>> {
+        if self.status.is_none() {
+            let status = self.child().try_wait();
+            if let Ok(Some(status)) = status {
+                self.status = Some(status.code());
+            }
+        }
+        self.status
+    }
+
+    /// Give the readers a moment to reach the end of the output, then
+    /// dispatch what they read.
+    fn drain(&mut self) {
+        let start = Instant::now();
+        while readers_running(|key| key == self.key) && start.elapsed() < DRAIN_TIMEOUT {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        dispatch_pending();
+    }
+}
+
+/// Whether a reader thread of a process whose key matches `keys` is still
+/// running.
+fn readers_running(keys: impl Fn(u32) -> bool) -> bool {
+    RUNNING
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .filter(|running| keys(running.key))
+        .any(|running| running.readers.iter().any(|r| !r.is_finished()))
+}
+
+/// Kill all processes and join the threads reading their output.
+fn kill_all() {
+    for running in RUNNING.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+        let mut child = running.child.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+    // Children of the processes that inherited their output keep the pipes
+    // open, and the threads reading them with it; those are left running.
+    let start = Instant::now();
+    while readers_running(|_| true) && start.elapsed() < DRAIN_TIMEOUT {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    let running = std::mem::take(&mut *RUNNING.lock().unwrap_or_else(|e| e.into_inner()));
+    for reader in running.into_iter().flat_map(|running| running.readers) {
+        if reader.is_finished() {
+            let _ = reader.join();
+        }
+    }
+    let _ = HANDLERS.try_with(|handlers| handlers.borrow_mut().clear());
+    PENDING.lock().unwrap_or_else(|e| e.into_inner()).clear();
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        self.kill();
+        let key = self.key;
+        RUNNING
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|running| running.key != key);
+        // The handlers are gone already if R is finalizing at exit.
+        let _ = HANDLERS.try_with(|handlers| handlers.borrow_mut().retain(|(k, _), _| *k != key));
+        PENDING
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|(k, _, _)| *k != key);
+    }
+}
+
+fn read_lines(key: u32, stream: Stream, pipe: Box<dyn Read + Send>) {
+    let mut reader = BufReader::new(pipe);
+    let mut buf = Vec::new();
+    while matches!(reader.read_until(b'\n', &mut buf), Ok(n) if n > 0) {
+        while matches!(buf.last(), Some(b'\n' | b'\r')) {
+            buf.pop();
+        }
+        let line = String::from_utf8_lossy(&buf).into_owned();
+        buf.clear();
+        PENDING
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((key, stream, line));
+        event_loop::wake();
+    }
+}
+
+/// Hand the lines read so far to their sinks and return how many there
+/// were. Must be called from the main thread.
+pub fn dispatch_pending() -> usize {
+    let lines = std::mem::take(&mut *PENDING.lock().unwrap_or_else(|e| e.into_inner()));
+    let count = lines.len();
+    for (key, stream, line) in lines {
+        let callback =
+            HANDLERS.with(
+                |handlers| match handlers.borrow_mut().get_mut(&(key, stream)) {
+                    Some(Handler::Collect(lines)) => {
+                        lines.push(line.clone());
+                        None
+                    }
+                    Some(Handler::Callback(callback)) => Some(callback.clone()),
+                    None => None,
+                },
+            );
+        // The callback runs without the handlers borrowed, and is skipped if
+        // it dispatches from within itself.
+        let callback = match callback {
+            Some(callback) => callback,
+            None => continue,
+        };
+        let mut callback = match callback.try_borrow_mut() {
+            Ok(callback) => callback,
+            Err(_) => continue,
+        };
+        (*callback)(&line);
+    }
+    count
+}
+
+/// The R class of process handles.
+const CLASS: &str = "helloextendr_process";
+
+fn sink(callback: Robj) -> Result<Sink> {
+    if callback.is_null() {
+        return Ok(Sink::Collect);
+    }
+    if callback.as_bool() == Some(false) {
+        return Ok(Sink::Discard);
+    }
+    let callback: Function = callback.try_into()?;
+    Ok(Sink::Callback(Box::new(move |line| {
+        if let Err(e) = callback.call(pairlist!(line)) {
+            let _ = lang!("warning", format!("output callback failed: {e}")).eval();
+        }
+    })))
+}
+
+fn process_mut(p: &mut Robj) -> Result<&mut ExternalPtr<Process>> {
+    if !p.inherits(CLASS) {
+        return Err(Error::Other(
+            "expected a process from `process_spawn()`".into(),
+        ));
+    }
+    p.try_into()
+}
+
+/// Run a subprocess
+///
+/// `process_spawn()` starts `command` without a shell and returns at once.
+/// Each line the process writes is collected for `process_read_output()`,
+/// or passed to a callback. Callbacks run while R waits for console input
+/// on Linux and macOS, and during `process_wait()` everywhere.
+///
+/// The process is killed when its handle is garbage collected.
+/// @param command The program to run.
+/// @param args Its arguments.
+/// @param stdout,stderr `NULL` to collect the lines of the stream, `FALSE`
+///   to discard it, or a function called with each line.
+/// @param wd `NULL` or the working directory of the process.
+/// @param env `NULL` or a named character vector of environment variables
+///   to set in addition to those of the R session.
+/// @param p A process handle returned by `process_spawn()`.
+/// @param timeout `NULL` to wait until the process exits, or a number of
+///   seconds.
+/// @return `process_spawn()` returns a process handle. `process_wait()`
+///   returns the exit status, `NA` if the process was killed by a signal,
+///   or `NULL` on timeout. `process_kill()` returns whether the process was
+///   running, `process_is_alive()` whether it is and `process_pid()` its
+///   process id. `process_read_output()` returns a list of the `stdout` and
+///   `stderr` lines collected since the last call.
+/// @export
+#[extendr]
+fn process_spawn(
+    command: &str,
+    #[extendr(default = "character()")] args: Vec<String>,
+    #[extendr(default = "NULL")] stdout: Robj,
+    #[extendr(default = "NULL")] stderr: Robj,
+    #[extendr(default = "NULL")] wd: Robj,
+    #[extendr(default = "NULL")] env: Robj,
+) -> Result<Robj> {
+    let mut cmd = Command::new(command);
+    cmd.args(&args);
+    if let Some(wd) = wd.as_str() {
+        cmd.current_dir(wd);
+    }
+    if !env.is_null() {
+        let values = Strings::try_from(env.clone())?;
+        let names = env
+            .names()
+            .ok_or_else(|| Error::Other("`env` must be named".into()))?;
+        for (name, value) in names.zip(values.iter()) {
+            let value: &str = value;
+            cmd.env(name, value);
+        }
+    }
+    let process = Process::spawn(&mut cmd, sink(stdout)?, sink(stderr)?)?;
+    let mut handle: Robj = ExternalPtr::new(process).into();
+    handle.set_attr("class", CLASS)?;
+    Ok(handle)
+}
+
+/// @rdname process_spawn
+/// @export
+#[extendr]
+fn process_wait(mut p: Robj, #[extendr(default = "NULL")] timeout: Robj) -> Result<Robj> {
+    let timeout = timeout
+        .as_real()
+        .or_else(|| timeout.as_integer().map(f64::from))
+        .map(|secs| Duration::from_secs_f64(secs.max(0.0)));
+    Ok(match process_mut(&mut p)?.wait(timeout)? {
+        Some(status) => status.into(),
+        None => r!(NULL),
+    })
+}
+
+/// @rdname process_spawn
+/// @export
+#[extendr]
+fn process_kill(mut p: Robj) -> Result<bool> {
+    Ok(process_mut(&mut p)?.kill())
+}
+
+/// @rdname process_spawn
+/// @export
+#[extendr]
+fn process_is_alive(mut p: Robj) -> Result<bool> {
+    Ok(process_mut(&mut p)?.is_alive())
+}
+
+/// @rdname process_spawn
+/// @export
+#[extendr]
+fn process_pid(mut p: Robj) -> Result<i32> {
+    Ok(process_mut(&mut p)?.pid() as i32)
+}
+
+/// @rdname process_spawn
+/// @export
+#[extendr]
+fn process_read_output(mut p: Robj) -> Result<Robj> {
+    let process = process_mut(&mut p)?;
+    let stdout = process.take_output(Stream::Stdout);
+    let stderr = process.take_output(Stream::Stderr);
+    Ok(list!(stdout = stdout, stderr = stderr).into())
+}
+
+r_module! {
+    mod process;
+    fn process_spawn;
+    fn process_wait;
+    fn process_kill;
+    fn process_is_alive;
+    fn process_pid;
+    fn process_read_output;
+}
@@ -0,0 +1,224 @@
+//! Rust futures as promises of the {promises} package.
+//!
+//! Available with the `promises` feature. [`promise_from_future()`] runs a
+//! future on a tokio runtime of its own thread and returns a promise that
+//! is resolved with the `Ok` value, or rejected with a classed error
+//! condition built from the `Err` value, so async failures reach
+//! `promises::catch()` and `then(onRejected = )` handlers:
+//!
+//! ```ignore
+//! #[extendr]
+//! fn fetch_size(url: String) -> Result<Robj> {
+//!     promise_from_future(async move {
+//!         let body = download(&url).await?;
+//!         Ok::<_, Rejection>(body.len() as f64)
+//!     })
+//! }
+//! ```
+//!
+//! Values are converted to R objects, and promises settled, on the main
+//! thread, when the [`event_loop`](crate::event_loop) runs the pending
+//! settlements while R waits for input. Scripts that never return to the
+//! console call `event_loop::run_pending()`, then `later::run_now()` to run
+//! the handlers of the settled promises.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{mpsc, Mutex, OnceLock};
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use tokio::runtime::Handle;
+
+use crate::condition::Condition;
+use crate::event_loop;
+use crate::teardown;
+
+/// The class every rejection condition has, before `error`.
+pub const REJECTION_CLASS: &str = "helloextendr_async_error";
+
+/// Why a future failed: the message and subclasses of the error condition
+/// its promise is rejected with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejection {
+    message: String,
+    classes: Vec<String>,
+}
+
+impl Rejection {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            classes: Vec::new(),
+        }
+    }
+
+    /// Add a subclass, most specific first, to handle the rejection by.
+    pub fn class(mut self, class: impl Into<String>) -> Self {
+        self.classes.push(class.into());
+        self
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The condition the promise is rejected with: classes
+    /// `c(<classes>, "helloextendr_async_error", "error", "condition")`.
+    pub fn to_condition(&self) -> Condition {
+        self.classes
+            .iter()
+            .fold(Condition::error(self.message.as_str()), |cnd, class| {
+                cnd.class(class.as_str())
+            })
+            .class(REJECTION_CLASS)
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl<E: std::error::Error> From<E> for Rejection {
+    fn from(e: E) -> Self {
+        Rejection::new(e.to_string())
+    }
+}
+
+/// The value of a future, converted to an R object on the main thread.
+type Value = Box<dyn FnOnce() -> Robj + Send>;
+
+/// The outcome of a future, waiting for the main thread.
+type Settlement = std::result::Result<Value, Rejection>;
+
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+/// Settled futures waiting for the main thread, by promise.
+static PENDING: Mutex<Vec<(u32, Settlement)>> = Mutex::new(Vec::new());
+
+thread_local! {
+    // The `resolve` and `reject` functions of the open promises. Only ever
+    // touched from the main thread.
+    static PROMISES: RefCell<HashMap<u32, (Function, Function)>> = RefCell::new(HashMap::new());
+}
+
+/// The handle of the runtime futures run on, started on first use on a
+/// thread of its own.
+fn runtime() -> Result<&'static Handle> {
+    static RUNTIME: OnceLock<std::result::Result<Handle, String>> = OnceLock::new();
+    RUNTIME
+        .get_or_init(|| {
+            let (tx, rx) = mpsc::channel();
+            let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+            let thread = std::thread::spawn(move || {
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build();
+                match runtime {
+                    Ok(runtime) => {
+                        let _ = tx.send(Ok(runtime.handle().clone()));
+                        // Drives the tasks spawned through the handle until
+                        // the package is unloaded, which cancels them.
+                        let _ = runtime.block_on(stopped);
+                    }
+                    Err(e) => {
+                        let _ = tx.send(Err(e.to_string()));
+                    }
+                }
+            });
+            teardown::on_unload("async runtime", move || {
+                let _ = stop.send(());
+                let _ = thread.join();
+            });
+            rx.recv()
+                .unwrap_or_else(|_| Err("the runtime thread panicked".into()))
+        })
+        .as_ref()
+        .map_err(|e| Error::Other(format!("cannot start the async runtime: {e}")))
+}
+
+/// Capture the `resolve` and `reject` functions of a new promise.
+const EXECUTOR: &str = "function(resolve, reject) {
+    target$resolve <- resolve
+    target$reject <- reject
+}";
+
+/// Run `future` and return a promise of its outcome. Must be called on the
+/// main thread.
+pub fn promise_from_future<F, T, E>(future: F) -> Result<Robj>
+where
+    F: Future<Output = std::result::Result<T, E>> + Send + 'static,
+    T: Into<Robj> + Send + 'static,
+    E: Into<Rejection> + Send + 'static,
+{
+    let runtime = runtime()?;
+    event_loop::register(dispatch_pending)?;
+
+    let env = Environment::new_with_parent(base_env());
+    env.set_local(sym!(target), env.clone());
+    let executor = lang!("parse", text = EXECUTOR)
+        .eval()?
+        .as_expressions()
+        .and_then(|exprs| exprs.values().next())
+        .ok_or_else(|| Error::Other("cannot parse the promise executor".into()))?
+        .eval_with_env(&env)?;
+    let promise: Function = lang!("getExportedValue", "promises", "promise")
+        .eval()?
+        .try_into()?;
+    let promise = promise.call(pairlist!(executor))?;
+    let resolve: Function = env.local(sym!(resolve))?.try_into()?;
+    let reject: Function = env.local(sym!(reject))?.try_into()?;
+
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    PROMISES.with(|promises| promises.borrow_mut().insert(id, (resolve, reject)));
+    let task = runtime.spawn(future);
+    runtime.spawn(async move {
+        let settlement: Settlement =
+            match task.await {
+                Ok(Ok(value)) => Ok(Box::new(move || value.into()) as Value),
+                Ok(Err(e)) => Err(e.into()),
+                Err(e) if e.is_panic() => {
+                    Err(Rejection::new("the future panicked").class("helloextendr_async_panic"))
+                }
+                Err(_) => Err(Rejection::new("the future was cancelled")
+                    .class("helloextendr_async_cancelled")),
+            };
+        PENDING
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((id, settlement));
+        event_loop::wake();
+    });
+    Ok(promise)
+}
+
+/// Settle the promises of the futures finished so far and return how many
+/// there were. Must be called from the main thread. Handlers attached with
+/// `then()` run later, from the {later} event loop.
+pub fn dispatch_pending() -> usize {
+    let settled = std::mem::take(&mut *PENDING.lock().unwrap_or_else(|e| e.into_inner()));
+    let count = settled.len();
+    for (id, settlement) in settled {
+        let functions = PROMISES.with(|promises| promises.borrow_mut().remove(&id));
+        let (resolve, reject) = match functions {
+            Some(functions) => functions,
+            None => continue,
+        };
+        let outcome = match settlement {
+            Ok(value) => resolve.call(pairlist!(value())),
+            Err(rejection) => rejection
+                .to_condition()
+                .to_robj()
+                .and_then(|cnd| reject.call(pairlist!(cnd))),
+        };
+        if let Err(e) = outcome {
+            let _ = lang!("warning", format!("cannot settle a promise: {e}")).eval();
+        }
+    }
+    count
+}
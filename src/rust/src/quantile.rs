@@ -0,0 +1,19 @@
+//! Sample quantiles, as R's `quantile()`.
+//!
+//! The nine types of Hyndman and Fan (1996) are computed by
+//! [`helloextendr_core::quantile`], which agrees with `quantile.default()`
+//! to the last bit. Type 7 is R's default.
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+pub use helloextendr_core::quantile::quantile_names;
+
+/// The quantiles of type `kind` (1 to 9) of `x` at `probs`.
+///
+/// Missing values in `x` are an error unless `na_rm` is true, in which case
+/// they are dropped. Missing probabilities and an empty `x` give `NA`.
+pub fn quantile(x: &[f64], probs: &[f64], kind: u8, na_rm: bool) -> Result<Vec<f64>> {
+    helloextendr_core::quantile::quantile(x, probs, kind, na_rm)
+        .map_err(|e| Error::Other(e.to_string()))
+}
@@ -0,0 +1,193 @@
+//! Quasi-quotation of R code.
+//!
+//! [`r_expr!`] writes an R expression inline with R syntax and returns it
+//! unevaluated. Rust values are inserted with `!!name`, and the elements of a
+//! list are spliced into the arguments of a call with `!!!name`, following
+//! the conventions of {rlang}:
+//!
+//! ```ignore
+//! let formula = R!("y ~ x")?;
+//! let args = list!(weights = w, na.action = "na.omit");
+//! let call = r_expr!{ lm(!!formula, data = !!df, !!!args) }?;
+//! ```
+//!
+//! Only plain identifiers can be unquoted; bind anything more complex to a
+//! local first. Long expressions may need a higher `#![recursion_limit]`.
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+use crate::ast::call_from_pairs;
+use crate::lookup::base_function;
+
+/// A Rust value to be inserted into quoted code, produced by [`r_expr!`].
+#[derive(Debug, Clone)]
+pub enum Unquoted {
+    /// `!!name`: the value replaces the name.
+    Value(&'static str, Robj),
+    /// `!!!name`: the elements of the value become call arguments.
+    Splice(&'static str, Robj),
+}
+
+impl Unquoted {
+    pub fn value(name: &'static str, value: impl Into<Robj>) -> Self {
+        Unquoted::Value(name, value.into())
+    }
+
+    pub fn splice(name: &'static str, value: impl Into<Robj>) -> Self {
+        Unquoted::Splice(name, value.into())
+    }
+}
+
+/// Build an unevaluated call from R syntax, inserting Rust values marked
+/// with `!!` and splicing lists marked with `!!!`.
+///
+/// Returns `extendr_api::Result<Robj>`; the result is usually a call, but can
+/// be a symbol or constant for trivial expressions.
+#[macro_export]
+macro_rules! r_expr {
+    (@values [$($acc:tt)*]) => {
+        [$($acc)*]
+    };
+    (@values [$($acc:tt)*] ! ! ! $name:ident $($rest:tt)*) => {
+        $crate::r_expr!(@values [
+            $($acc)* $crate::quote::Unquoted::splice(stringify!($name), $name.clone()),
+        ] $($rest)*)
+    };
+    (@values [$($acc:tt)*] ! ! $name:ident $($rest:tt)*) => {
+        $crate::r_expr!(@values [
+            $($acc)* $crate::quote::Unquoted::value(stringify!($name), $name.clone()),
+        ] $($rest)*)
+    };
+    // Unquoted names may appear at any depth, so look inside groups too.
+    (@values [$($acc:tt)*] ($($inner:tt)*) $($rest:tt)*) => {
+        $crate::r_expr!(@values [$($acc)*] $($inner)* $($rest)*)
+    };
+    (@values [$($acc:tt)*] [$($inner:tt)*] $($rest:tt)*) => {
+        $crate::r_expr!(@values [$($acc)*] $($inner)* $($rest)*)
+    };
+    (@values [$($acc:tt)*] {$($inner:tt)*} $($rest:tt)*) => {
+        $crate::r_expr!(@values [$($acc)*] $($inner)* $($rest)*)
+    };
+    (@values [$($acc:tt)*] $other:tt $($rest:tt)*) => {
+        $crate::r_expr!(@values [$($acc)*] $($rest)*)
+    };
+    ($($code:tt)*) => {
+        $crate::quote::unquote(stringify!($($code)*), &$crate::r_expr!(@values [] $($code)*))
+    };
+}
+
+/// Parse `code` as a single R expression and substitute the unquoted
+/// values. This is the runtime half of [`r_expr!`].
+pub fn unquote(code: &str, values: &[Unquoted]) -> Result<Robj> {
+    let exprs = parse(code)?;
+    if exprs.len() != 1 {
+        return Err(Error::Other(format!(
+            "expected a single R expression, got {}",
+            exprs.len()
+        )));
+    }
+    let expr = exprs.values().next().unwrap();
+    rewrite(&expr, values)
+}
+
+/// If `expr` is `!!name` or `!!!name`, return the number of `!` and the name.
+fn unquoted_name(expr: &Robj) -> Option<(usize, String)> {
+    let mut bangs = 0;
+    let mut cur = expr.clone();
+    loop {
+        let next = match cur.as_language() {
+            Some(call) if call.len() == 2 => {
+                let mut parts = call.values();
+                let head = parts.next()?;
+                match head.as_symbol() {
+                    Some(sym) if sym.as_str() == "!" => {}
+                    _ => break,
+                }
+                parts.next()?
+            }
+            _ => break,
+        };
+        bangs += 1;
+        cur = next;
+    }
+    match bangs {
+        2 | 3 => cur.as_symbol().map(|sym| (bangs, sym.as_str().to_string())),
+        _ => None,
+    }
+}
+
+fn find_value<'a>(values: &'a [Unquoted], name: &str) -> Option<&'a Robj> {
+    values.iter().find_map(|v| match v {
+        Unquoted::Value(n, value) if *n == name => Some(value),
+        _ => None,
+    })
+}
+
+fn find_splice<'a>(values: &'a [Unquoted], name: &str) -> Option<&'a Robj> {
+    values.iter().find_map(|v| match v {
+        Unquoted::Splice(n, value) if *n == name => Some(value),
+        _ => None,
+    })
+}
+
+fn rewrite(expr: &Robj, values: &[Unquoted]) -> Result<Robj> {
+    match unquoted_name(expr) {
+        Some((2, name)) => {
+            if let Some(value) = find_value(values, &name) {
+                return Ok(value.clone());
+            }
+        }
+        Some((3, name)) if find_splice(values, &name).is_some() => {
+            return Err(Error::Other(format!(
+                "`!!!{name}` can only be used as a function argument"
+            )));
+        }
+        _ => {}
+    }
+
+    if let Some(call) = expr.as_language() {
+        let mut pairs = Vec::with_capacity(call.len());
+        for (i, (tag, arg)) in call.iter().enumerate() {
+            if i > 0 {
+                if let Some((3, name)) = unquoted_name(&arg) {
+                    if let Some(value) = find_splice(values, &name) {
+                        pairs.extend(spliced(value)?);
+                        continue;
+                    }
+                }
+            }
+            pairs.push((tag.to_string(), rewrite(&arg, values)?));
+        }
+        call_from_pairs(pairs)
+    } else if let Some(args) = expr.as_pairlist() {
+        // The formals of `function(...)`, whose defaults may be unquoted.
+        let pairs = args
+            .iter()
+            .map(|(tag, arg)| Ok((tag, rewrite(&arg, values)?)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Pairlist::from_pairs(pairs).into())
+    } else {
+        Ok(expr.clone())
+    }
+}
+
+/// The elements of `value` as (name, value) pairs for splicing into a call.
+fn spliced(value: &Robj) -> Result<Vec<(String, Robj)>> {
+    let list: List = base_function("as.list")?
+        .call(pairlist!(value.clone()))?
+        .try_into()?;
+    let names: Vec<String> = match list.names() {
+        Some(names) => names
+            .map(|n| {
+                if n == "NA" {
+                    String::new()
+                } else {
+                    n.to_string()
+                }
+            })
+            .collect(),
+        None => vec![String::new(); list.len()],
+    };
+    Ok(names.into_iter().zip(list.values()).collect())
+}
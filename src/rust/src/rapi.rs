@@ -0,0 +1,135 @@
+//! The entry points of R's C API this crate declares itself, with what the
+//! safe wrappers need to know about each of them.
+//!
+//! R's headers say neither which functions leave their arguments alone nor
+//! which can run the garbage collector or leave the caller with a
+//! `longjmp()`, and bindgen turns every `SEXP` into the same `*mut`. Each
+//! entry point is declared here with [`SexpRef`] for the arguments it only
+//! reads and [`Sexp`] for those it may modify, and annotated with whether it
+//! allocates, so unprotected objects must be protected across the call, and
+//! whether it can jump, so no Rust frame with a destructor may be between
+//! the call and R. [`ENTRY_POINTS`] holds those facts for every declaration,
+//! with `read_only` derived from the signature rather than written by hand,
+//! and [`entry_point()`] looks one up by name.
+//!
+//! The table is checked when the crate compiles: an argument type that does
+//! not say whether it can be written through is an error, and so is an
+//! entry point that allocates without being marked as jumping, since R
+//! raises an error when an allocation fails.
+
+use std::os::raw::{c_char, c_int};
+
+use extendr_ffi::{cetype_t, Rboolean};
+
+use crate::sexp::{Sexp, SexpRef};
+
+/// What an entry point may do besides reading its arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fact {
+    /// It allocates R objects and so may run the garbage collector.
+    Allocates,
+    /// It may leave through a `longjmp()`, on an error or an interrupt.
+    Longjmps,
+}
+
+/// The facts of one entry point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntryPoint {
+    pub name: &'static str,
+    /// Every object argument is a [`SexpRef`].
+    pub read_only: bool,
+    pub allocates: bool,
+    pub longjmps: bool,
+}
+
+impl EntryPoint {
+    const fn with(mut self, fact: Fact) -> Self {
+        match fact {
+            Fact::Allocates => self.allocates = true,
+            Fact::Longjmps => self.longjmps = true,
+        }
+        self
+    }
+}
+
+/// The argument types of entry points, and whether R may write through them.
+trait Argument {
+    const WRITABLE: bool;
+}
+
+impl Argument for Sexp {
+    const WRITABLE: bool = true;
+}
+
+impl Argument for SexpRef<'_> {
+    const WRITABLE: bool = false;
+}
+
+impl Argument for Rboolean {
+    const WRITABLE: bool = false;
+}
+
+/// Declares the entry points and builds [`ENTRY_POINTS`] from the same list.
+macro_rules! entry_points {
+    ($(
+        $(#[doc = $doc:literal])*
+        [$($fact:ident),*] fn $name:ident($($arg:ident: $ty:ty),*) $(-> $ret:ty)?;
+    )*) => {
+        // Not exported by `extendr_ffi`. `SEXPREC` is opaque, as in its own bindings.
+        #[allow(improper_ctypes)]
+        extern "C" {
+            $($(#[doc = $doc])* pub fn $name($($arg: $ty),*) $(-> $ret)?;)*
+        }
+
+        /// The facts of every entry point declared here.
+        pub const ENTRY_POINTS: &[EntryPoint] = &[$(
+            EntryPoint {
+                name: stringify!($name),
+                read_only: true $(&& !<$ty as Argument>::WRITABLE)*,
+                allocates: false,
+                longjmps: false,
+            }
+            $(.with(Fact::$fact))*
+        ),*];
+    };
+}
+
+entry_points! {
+    [] fn TYPEOF(x: SexpRef<'_>) -> c_int;
+    [] fn Rf_getCharCE(x: SexpRef<'_>) -> cetype_t;
+    /// The result is allocated with `R_alloc()` and freed when the `.Call()`
+    /// returns.
+    [Allocates, Longjmps] fn Rf_translateCharUTF8(x: SexpRef<'_>) -> *const c_char;
+    /// Evaluation runs arbitrary R code, which can modify `expr` as well.
+    [Allocates, Longjmps] fn Rf_eval(expr: Sexp, env: Sexp) -> Sexp;
+    /// The call does not modify `key` and `val`, but they are [`Sexp`]: the
+    /// weak reference keeps them and hands them back writable through
+    /// `R_WeakRefKey()` and `R_WeakRefValue()`.
+    [Allocates, Longjmps] fn R_MakeWeakRef(
+        key: Sexp,
+        val: Sexp,
+        fin: Sexp,
+        onexit: Rboolean
+    ) -> Sexp;
+    [] fn R_WeakRefKey(w: SexpRef<'_>) -> Sexp;
+    [] fn R_WeakRefValue(w: SexpRef<'_>) -> Sexp;
+    /// Runs the event loop and jumps out on an interrupt.
+    [Allocates, Longjmps] fn R_CheckUserInterrupt();
+}
+
+const _: () = {
+    let mut i = 0;
+    while i < ENTRY_POINTS.len() {
+        let entry = &ENTRY_POINTS[i];
+        assert!(
+            !entry.allocates || entry.longjmps,
+            "an entry point that allocates can jump"
+        );
+        i += 1;
+    }
+};
+
+/// The facts of the entry point called `name`, if it is declared here.
+pub fn entry_point(name: &str) -> Option<&'static EntryPoint> {
+    ENTRY_POINTS.iter().find(|entry| entry.name == name)
+}
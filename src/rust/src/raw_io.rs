@@ -0,0 +1,229 @@
+//! `std::io` adapters for R raw vectors and R connections.
+//!
+//! Rust decoders usually consume `impl Read` and encoders produce
+//! `impl Write`. [`RawReader`] and [`RawWriter`] bridge those traits to
+//! `RAWSXP` vectors, while [`ConnectionReader`] and [`ConnectionWriter`] do the
+//! same for any R connection by going through `readBin()` and `writeBin()`.
+
+use extendr_api::prelude::*;
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+
+/// Additional accessors for [`Raw`] vectors.
+pub trait RawExt {
+    /// Mutable view of the bytes, e.g. for filling a freshly allocated vector.
+    fn as_mut_slice(&mut self) -> &mut [u8];
+
+    /// Copy the bytes into a Rust vector.
+    fn to_vec(&self) -> Vec<u8>;
+}
+
+impl RawExt for Raw {
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.as_robj_mut().as_raw_slice_mut().unwrap()
+    }
+
+    fn to_vec(&self) -> Vec<u8> {
+        self.as_slice().to_vec()
+    }
+}
+
+/// Conversion of byte buffers into R raw vectors.
+pub trait IntoRaw {
+    fn into_raw(self) -> Raw;
+}
+
+impl IntoRaw for &[u8] {
+    fn into_raw(self) -> Raw {
+        Raw::from_bytes(self)
+    }
+}
+
+impl IntoRaw for Vec<u8> {
+    fn into_raw(self) -> Raw {
+        Raw::from_bytes(&self)
+    }
+}
+
+impl IntoRaw for &Vec<u8> {
+    fn into_raw(self) -> Raw {
+        Raw::from_bytes(self)
+    }
+}
+
+fn to_io_error(e: extendr_api::Error) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+/// Reads from an R raw vector without copying it.
+#[derive(Debug, Clone)]
+pub struct RawReader {
+    raw: Raw,
+    /// Like the position of a `Cursor`, it can be past the end.
+    pos: u64,
+}
+
+impl RawReader {
+    pub fn new(raw: Raw) -> Self {
+        Self { raw, pos: 0 }
+    }
+
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    pub fn into_inner(self) -> Raw {
+        self.raw
+    }
+
+    fn remaining(&self) -> &[u8] {
+        let data = self.raw.as_slice();
+        let start = usize::try_from(self.pos).map_or(data.len(), |pos| pos.min(data.len()));
+        &data[start..]
+    }
+}
+
+impl Read for RawReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.remaining().read(buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl BufRead for RawReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Ok(self.remaining())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt as u64;
+    }
+}
+
+impl Seek for RawReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let len = self.raw.len() as i128;
+        let target = match pos {
+            SeekFrom::Start(n) => n as i128,
+            SeekFrom::End(n) => len + n as i128,
+            SeekFrom::Current(n) => self.pos as i128 + n as i128,
+        };
+        if target < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek before the start of the raw vector",
+            ));
+        }
+        // Like `Cursor`, seeking past the end is allowed; reads then return 0.
+        self.pos = u64::try_from(target).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek past the largest position",
+            )
+        })?;
+        Ok(self.pos)
+    }
+}
+
+/// Collects written bytes and turns them into an R raw vector at the end.
+///
+/// R vectors cannot grow in place, so the data is buffered in Rust and
+/// copied into R exactly once by [`RawWriter::into_raw()`].
+#[derive(Debug, Default, Clone)]
+pub struct RawWriter {
+    buf: Vec<u8>,
+}
+
+impl RawWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            buf: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn into_raw(self) -> Raw {
+        self.buf.into_raw()
+    }
+}
+
+impl Write for RawWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn check_connection(con: &Robj) -> extendr_api::Result<()> {
+    if con.inherits("connection") {
+        Ok(())
+    } else {
+        Err(Error::Other("expected an R connection".into()))
+    }
+}
+
+/// Reads binary data from an open R connection.
+///
+/// Each `read()` is one `readBin()` call, so wrap this in a
+/// [`std::io::BufReader`] when reading many small pieces.
+#[derive(Debug, Clone)]
+pub struct ConnectionReader {
+    con: Robj,
+}
+
+impl ConnectionReader {
+    pub fn new(con: Robj) -> extendr_api::Result<Self> {
+        check_connection(&con)?;
+        Ok(Self { con })
+    }
+}
+
+impl Read for ConnectionReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let chunk = lang!("readBin", self.con.clone(), "raw", n = buf.len() as f64)
+            .eval()
+            .map_err(to_io_error)?;
+        let bytes = chunk.as_raw_slice().unwrap_or(&[]);
+        buf[..bytes.len()].copy_from_slice(bytes);
+        Ok(bytes.len())
+    }
+}
+
+/// Writes binary data to an open R connection.
+#[derive(Debug, Clone)]
+pub struct ConnectionWriter {
+    con: Robj,
+}
+
+impl ConnectionWriter {
+    pub fn new(con: Robj) -> extendr_api::Result<Self> {
+        check_connection(&con)?;
+        Ok(Self { con })
+    }
+}
+
+impl Write for ConnectionWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        lang!("writeBin", buf.into_raw(), self.con.clone())
+            .eval()
+            .map_err(to_io_error)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        lang!("flush", self.con.clone())
+            .eval()
+            .map_err(to_io_error)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,169 @@
+//! Compile-time checks of the routines modules register with R.
+//!
+//! [`r_module!`](crate::r_module) takes the same input as `extendr_module!`
+//! and also defines a [`Registry`] of the names the module and the modules
+//! it `use`s register. Names given twice within one invocation are spanned
+//! errors of the macro; [`check()`] runs in a constant of the same
+//! expansion and fails the build when two modules register the same R
+//! function or class, or when there are more routines than
+//! `R_registerRoutines()` can count. Without it, such a package builds and
+//! then fails to link, or to load, with an error that does not name the
+//! clash.
+//!
+//! The expansion replaces the one of `extendr_module!`, whose wrapper
+//! generator cannot write anything but the wrappers: [`make_wrappers()`]
+//! follows them with the R definitions of the module's `#[r_class]`
+//! classes, so that they are part of `R/extendr-wrappers.R`.
+
+use std::convert::TryFrom;
+
+use extendr_api::metadata::Metadata;
+use extendr_api::{GetSexp, Robj, SEXP};
+
+/// Routines registered for each module besides its functions: the module
+/// metadata and the wrapper generator.
+const ROUTINES_PER_MODULE: usize = 2;
+
+/// `R_registerRoutines()` counts routines in a C `int`.
+pub const MAX_ROUTINES: usize = i32::MAX as usize;
+
+/// An R name a module registers, with the error reported if another module
+/// registers it too.
+#[derive(Debug, Clone, Copy)]
+pub struct Registration {
+    pub name: &'static str,
+    pub clash: &'static str,
+}
+
+/// The names registered by a module and by the modules it `use`s.
+#[derive(Debug, Clone, Copy)]
+pub struct Registry {
+    pub module: &'static str,
+    pub entries: &'static [Registration],
+    pub uses: &'static [&'static Registry],
+}
+
+impl Registry {
+    /// The number of routines registered by the module and the modules it
+    /// uses.
+    pub const fn routines(&self) -> usize {
+        let mut count = self.entries.len() + ROUTINES_PER_MODULE;
+        let mut i = 0;
+        while i < self.uses.len() {
+            count += self.uses[i].routines();
+            i += 1;
+        }
+        count
+    }
+
+    /// The number of registered names, including those of used modules.
+    const fn len(&self) -> usize {
+        let mut count = self.entries.len();
+        let mut i = 0;
+        while i < self.uses.len() {
+            count += self.uses[i].len();
+            i += 1;
+        }
+        count
+    }
+
+    /// The `index`th registered name, in depth-first order.
+    const fn get(&self, mut index: usize) -> &'static Registration {
+        if index < self.entries.len() {
+            return &self.entries[index];
+        }
+        index -= self.entries.len();
+        let mut i = 0;
+        while i < self.uses.len() {
+            let len = self.uses[i].len();
+            if index < len {
+                return self.uses[i].get(index);
+            }
+            index -= len;
+            i += 1;
+        }
+        panic!("registration index out of bounds")
+    }
+}
+
+const fn same(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// Fail constant evaluation if `registry` registers a name twice or has
+/// too many routines.
+pub const fn check(registry: &Registry) {
+    if registry.routines() > MAX_ROUTINES {
+        panic!("too many routines for R_registerRoutines()");
+    }
+    let len = registry.len();
+    let mut i = 0;
+    while i < len {
+        let a = registry.get(i);
+        let mut j = i + 1;
+        while j < len {
+            if same(a.name, registry.get(j).name) {
+                panic!("{}", a.clash);
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+}
+
+/// The R code defining the class of a type. `#[r_class]` gives its type an
+/// inherent constant of the same name, which takes precedence over this
+/// one, so the constant of any other type is `None`.
+pub trait RClassDefinition {
+    const R_CLASS_DEFINITION: Option<&'static str> = None;
+}
+
+impl<T: ?Sized> RClassDefinition for T {}
+
+/// The wrappers of `metadata`, followed by the class definitions pushed by
+/// `classes`.
+pub fn r_wrappers(
+    metadata: &Metadata,
+    classes: fn(&mut Vec<&'static str>),
+    use_symbols: bool,
+    package_name: &str,
+) -> std::io::Result<String> {
+    let mut wrappers = metadata.make_r_wrappers(use_symbols, package_name)?;
+    let mut definitions = Vec::new();
+    classes(&mut definitions);
+    for definition in definitions {
+        wrappers.push('\n');
+        wrappers.push_str(definition);
+    }
+    Ok(wrappers)
+}
+
+/// The body of the `make_<module>_wrappers()` routine of a module.
+///
+/// # Safety
+///
+/// The arguments must be the SEXPs the routine was called with.
+pub unsafe fn make_wrappers(
+    metadata: Metadata,
+    classes: fn(&mut Vec<&'static str>),
+    use_symbols: SEXP,
+    package_name: SEXP,
+) -> SEXP {
+    let use_symbols = Robj::from_sexp(use_symbols);
+    let package_name = Robj::from_sexp(package_name);
+    let use_symbols = bool::try_from(&use_symbols).unwrap();
+    let package_name = <&str>::try_from(&package_name).unwrap();
+    let wrappers = r_wrappers(&metadata, classes, use_symbols, package_name).unwrap();
+    Robj::from(wrappers).get()
+}
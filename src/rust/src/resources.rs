@@ -0,0 +1,280 @@
+//! Large native assets downloaded at first use.
+//!
+//! Models, datasets or prebuilt libraries too large to ship in a package
+//! are described by a [`Resource`]: a URL and the SHA-256 checksum of the
+//! file behind it. [`fetch()`] downloads the file once, verifies it and
+//! keeps it, unpacked if it is an archive, in the package's cache directory
+//! from `tools::R_user_dir(package, "cache")`, the location CRAN policy
+//! allows packages to write to. Later calls, also in later sessions, return
+//! the cached copy.
+//!
+//! Downloads go through `utils::download.file()`, so the proxy and download
+//! method settings of the session apply and the console shows R's usual
+//! progress. Archives are unpacked with [`crate::archive`], except tar
+//! formats it does not read, such as `.tar.bz2` and `.tar.xz`, which are
+//! left to `utils::untar()`.
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use crate::archive::{self, Format, Progress};
+use crate::console::Console;
+use crate::lookup::namespace_function;
+use crate::r_module;
+
+/// Written into a resource's directory once it is complete.
+const MARKER: &str = ".complete";
+
+/// How a downloaded file is stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unpack {
+    /// Keep the file as it is.
+    No,
+    /// Extract a `.tar`, `.tar.gz`, `.tar.bz2` or `.tar.xz` archive.
+    Tar,
+    /// Extract a `.zip` archive.
+    Zip,
+}
+
+/// A file to download.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resource {
+    name: String,
+    url: String,
+    sha256: String,
+    unpack: Unpack,
+}
+
+impl Resource {
+    /// The file at `url`, with the given SHA-256 checksum in hex. `name`
+    /// identifies it in the cache and in messages.
+    pub fn new(name: impl Into<String>, url: impl Into<String>, sha256: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            url: url.into(),
+            sha256: sha256.into().to_ascii_lowercase(),
+            unpack: Unpack::No,
+        }
+    }
+
+    pub fn unpack(mut self, unpack: Unpack) -> Self {
+        self.unpack = unpack;
+        self
+    }
+
+    /// The directory of this resource in the cache. Another checksum, such
+    /// as for a new version of the file, gets another directory.
+    fn dir(&self, cache: &Path) -> PathBuf {
+        let short = self.sha256.get(..12).unwrap_or(&self.sha256);
+        cache.join(format!("{}-{short}", self.name))
+    }
+
+    fn file_name(&self) -> String {
+        let path = self.url.split(['?', '#']).next().unwrap_or("");
+        match path.rsplit('/').next() {
+            Some(name) if !name.is_empty() => name.to_string(),
+            _ => self.name.clone(),
+        }
+    }
+}
+
+/// The cache directory for resources of `package`.
+pub fn cache_dir(package: &str) -> Result<PathBuf> {
+    let dir =
+        namespace_function("tools", "R_user_dir")?.call(pairlist!(package, which = "cache"))?;
+    let dir = dir
+        .as_str()
+        .ok_or_else(|| Error::Other("invalid cache directory".into()))?;
+    Ok(Path::new(dir).join("resources"))
+}
+
+/// The path of `resource` for `package`, downloading it first if it is not
+/// in the cache. For archives, this is the directory they were extracted
+/// into, otherwise the file itself.
+pub fn fetch(package: &str, resource: &Resource) -> Result<PathBuf> {
+    let cache = cache_dir(package)?;
+    let dir = resource.dir(&cache);
+    let path = match resource.unpack {
+        Unpack::No => dir.join(resource.file_name()),
+        Unpack::Tar | Unpack::Zip => dir.clone(),
+    };
+    if dir.join(MARKER).is_file() {
+        return Ok(path);
+    }
+
+    let console = Console::detect()?;
+    fs::create_dir_all(&cache).map_err(|e| io_error(&cache, e))?;
+    // Assembled next to its final place and renamed once complete, so an
+    // interrupted download never looks complete.
+    let staging = cache.join(format!(".{}-{}", resource.name, std::process::id()));
+    let _ = fs::remove_dir_all(&staging);
+    fs::create_dir_all(&staging).map_err(|e| io_error(&staging, e))?;
+    let result = download_into(&console, resource, &staging);
+    if let Err(e) = result {
+        let _ = fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+    fs::write(staging.join(MARKER), "").map_err(|e| io_error(&staging, e))?;
+    // Another session may have finished the same resource meanwhile.
+    let _ = fs::remove_dir_all(&dir);
+    if let Err(e) = fs::rename(&staging, &dir) {
+        let _ = fs::remove_dir_all(&staging);
+        if !dir.join(MARKER).is_file() {
+            return Err(io_error(&dir, e));
+        }
+    }
+    console.alert_success(&format!("{} is ready", resource.name));
+    Ok(path)
+}
+
+fn download_into(console: &Console, resource: &Resource, staging: &Path) -> Result<()> {
+    let file = staging.join(resource.file_name());
+    console.alert_info(&format!(
+        "Downloading {} from {}",
+        resource.name, resource.url
+    ));
+    let file_arg = file.display().to_string();
+    let status = namespace_function("utils", "download.file")?.call(pairlist!(
+        resource.url.as_str(),
+        file_arg.as_str(),
+        mode = "wb"
+    ))?;
+    if status.as_integer() != Some(0) && status.as_real() != Some(0.0) {
+        return Err(Error::Other(format!("cannot download {}", resource.url)));
+    }
+
+    let actual = sha256_file(&file)?;
+    if actual != resource.sha256 {
+        return Err(Error::Other(format!(
+            "checksum mismatch for {}: expected {}, got {actual}",
+            resource.name, resource.sha256
+        )));
+    }
+
+    let format = match resource.unpack {
+        Unpack::No => return Ok(()),
+        Unpack::Tar => Format::from_path(&file).filter(|&format| format != Format::Zip),
+        Unpack::Zip => Some(Format::Zip),
+    };
+    console.alert_info(&format!("Unpacking {}", resource.name));
+    match format {
+        Some(format) => {
+            let reader = BufReader::new(fs::File::open(&file).map_err(|e| io_error(&file, e))?);
+            archive::extract(
+                reader,
+                format,
+                staging,
+                None,
+                &mut Progress::new("Unpacked", false),
+            )?;
+        }
+        None => {
+            let staging_arg = staging.display().to_string();
+            namespace_function("utils", "untar")?
+                .call(pairlist!(file_arg.as_str(), exdir = staging_arg.as_str()))?;
+        }
+    }
+    fs::remove_file(&file).map_err(|e| io_error(&file, e))
+}
+
+/// The SHA-256 checksum of the file at `path`, in hex.
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = fs::File::open(path).map_err(|e| io_error(path, e))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 1 << 16];
+    loop {
+        let n = file.read(&mut buf).map_err(|e| io_error(path, e))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect())
+}
+
+/// Remove the cached copies of the resources of `package` named `name`, or
+/// of all of them.
+pub fn clear(package: &str, name: Option<&str>) -> Result<()> {
+    let cache = cache_dir(package)?;
+    let entries = match fs::read_dir(&cache) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(()),
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        let matches = match name {
+            Some(name) => file_name
+                .strip_prefix(name)
+                .is_some_and(|rest| rest.starts_with('-')),
+            None => true,
+        };
+        if matches {
+            let path = entry.path();
+            fs::remove_dir_all(&path).map_err(|e| io_error(&path, e))?;
+        }
+    }
+    Ok(())
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> Error {
+    Error::Other(format!("{}: {e}", path.display()))
+}
+
+/// Download a resource at first use
+///
+/// Downloads the file at `url` once, checks its SHA-256 checksum and keeps
+/// it in the package's cache directory, `tools::R_user_dir(package,
+/// "cache")`. Later calls return the cached copy without downloading.
+/// @param package The package the resource belongs to.
+/// @param name A name for the resource, used in the cache and in messages.
+/// @param url Where to download it from.
+/// @param sha256 Its SHA-256 checksum in hex.
+/// @param unpack `"no"` to keep the file, or `"tar"` or `"zip"` to extract
+///   the archive.
+/// @return `resource_fetch()` returns the path of the file, or of the
+///   directory the archive was extracted into. `resource_clear()` returns
+///   `NULL` invisibly.
+/// @export
+#[extendr]
+fn resource_fetch(
+    package: &str,
+    name: &str,
+    url: &str,
+    sha256: &str,
+    #[extendr(default = "\"no\"")] unpack: &str,
+) -> Result<String> {
+    let unpack = match unpack {
+        "no" => Unpack::No,
+        "tar" => Unpack::Tar,
+        "zip" => Unpack::Zip,
+        other => {
+            return Err(Error::Other(format!(
+                "`unpack` must be \"no\", \"tar\" or \"zip\", not \"{other}\""
+            )))
+        }
+    };
+    let resource = Resource::new(name, url, sha256).unpack(unpack);
+    Ok(fetch(package, &resource)?.display().to_string())
+}
+
+/// @rdname resource_fetch
+/// @param name `NULL` to remove all cached resources of the package.
+/// @export
+#[extendr(invisible)]
+fn resource_clear(package: &str, #[extendr(default = "NULL")] name: Robj) -> Result<()> {
+    clear(package, name.as_str())
+}
+
+r_module! {
+    mod resources;
+    fn resource_fetch;
+    fn resource_clear;
+}
@@ -0,0 +1,102 @@
+//! Run-length encoding of R vectors, as R's `rle()` and `inverse.rle()`.
+//!
+//! The runs themselves are found by [`helloextendr_core::rle`], re-exported
+//! here; this module reads R vectors into it and builds the `rle` objects R
+//! code expects. Strings are read as `Option<&str>`, with `None` for `NA`,
+//! and logicals as their integer codes.
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+use crate::attrib::AttribExt;
+
+pub use helloextendr_core::rle::{
+    inverse_rle, rle, run_ends, run_ids, run_lengths, run_starts, Rle, RunValue,
+};
+
+/// `rle(x)`: a list of class `rle` with the integer `lengths` of the runs of
+/// `x` and their `values`, which keep the names of `x`.
+pub fn rle_robj(x: &Robj) -> Result<Robj> {
+    let atomic = || Error::Other("'x' must be a vector of an atomic type".into());
+    if lang!("is.vector", x.clone()).eval()?.as_bool() != Some(true) {
+        return Err(atomic());
+    }
+    let ends = match x.rtype() {
+        Rtype::Logicals => {
+            let codes: Vec<i32> = x
+                .as_logical_slice()
+                .unwrap_or(&[])
+                .iter()
+                .map(|b| b.0)
+                .collect();
+            run_ends(&codes)
+        }
+        Rtype::Integers => run_ends(x.as_integer_slice().unwrap_or(&[])),
+        Rtype::Doubles => run_ends(x.as_real_slice().unwrap_or(&[])),
+        Rtype::Raw => run_ends(x.as_raw_slice().unwrap_or(&[])),
+        Rtype::Strings => {
+            let strings = Strings::try_from(x.clone())?;
+            let values: Vec<Option<&str>> = strings
+                .iter()
+                .map(|s| if s.is_na() { None } else { Some(s.as_ref()) })
+                .collect();
+            run_ends(&values)
+        }
+        _ => return Err(atomic()),
+    };
+    let lengths = run_lengths(&ends)
+        .into_iter()
+        .map(|n| {
+            i32::try_from(n).map_err(|_| Error::Other(format!("a run of {n} values is too long")))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let index = Doubles::from_values(ends.iter().map(|&i| (i + 1) as f64));
+    let values = lang!("[", x.clone(), index).eval()?;
+    let mut out = Robj::from(crate::list!(
+        lengths = Integers::from_values(lengths),
+        values = values
+    ));
+    out.set_attr("class", "rle")?;
+    Ok(out)
+}
+
+/// `inverse.rle(x)`: the `values` of an `rle` list, each repeated as often
+/// as its `lengths` say, without names.
+pub fn inverse_rle_robj(x: &Robj) -> Result<Robj> {
+    let invalid = || Error::Other("invalid 'rle' structure".into());
+    let list = List::try_from(x.clone()).map_err(|_| invalid())?;
+    let field = |name: &str| {
+        list.iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, value)| value)
+            .filter(|value| !value.is_null())
+    };
+    let (lengths, values) = match (field("lengths"), field("values")) {
+        (Some(lengths), Some(values)) if lengths.len() == values.len() => (lengths, values),
+        _ => return Err(invalid()),
+    };
+    let times: Vec<usize> = if let Some(lengths) = lengths.as_integer_slice() {
+        lengths
+            .iter()
+            .map(|&n| usize::try_from(n).ok())
+            .collect::<Option<_>>()
+    } else if let Some(lengths) = lengths.as_real_slice() {
+        lengths
+            .iter()
+            .map(|&n| (n >= 0.0 && n.is_finite()).then_some(n as usize))
+            .collect::<Option<_>>()
+    } else {
+        None
+    }
+    .ok_or_else(|| Error::Other("invalid 'times' value".into()))?;
+
+    let index: Vec<f64> = times
+        .iter()
+        .enumerate()
+        .flat_map(|(i, &n)| std::iter::repeat_n((i + 1) as f64, n))
+        .collect();
+    let index = Doubles::from_values(index);
+    let mut out = lang!("[", values, index).eval()?;
+    out.remove_attr("names")?;
+    Ok(out)
+}
@@ -0,0 +1,319 @@
+//! Rolling-window statistics.
+//!
+//! [`roll_apply()`] calls a closure with a [`View`] of every window, and
+//! [`roll_sum()`], [`roll_mean()`], [`roll_max()`] and [`roll_min()`]
+//! update a running state as the window moves instead, which keeps them
+//! linear in the length of the vector whatever the width:
+//!
+//! ```ignore
+//! let x: Doubles = R!("c(1, 2, NA, 4, 5)")?.try_into()?;
+//! let means = roll_mean(x.view(0, x.len(), 1)?, 2, Align::Right, true)?;
+//! // NA, 1.5, 2, 4, 4.5
+//! ```
+//!
+//! Results have one element per element of the input, like those of
+//! `zoo::rollapply(fill = NA)` and `RcppRoll::roll_mean(fill = NA)`: the
+//! result for a window goes where [`Align`] puts it, and the positions no
+//! complete window maps to are `NA`. Missing values propagate as in R's
+//! `sum()`, `mean()` and `max()`, or are skipped with `na_rm`.
+
+use std::collections::VecDeque;
+use std::str::FromStr;
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+use crate::view::View;
+
+/// Where the result for a window is placed relative to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    /// At the first element of the window.
+    Left,
+    /// At its middle element, or the one before the middle for even
+    /// widths, as in zoo.
+    Center,
+    /// At its last element, so that each result only depends on the
+    /// elements up to its position.
+    Right,
+}
+
+impl Align {
+    /// The number of elements of a window before the one it is placed at.
+    fn before(self, width: usize) -> usize {
+        match self {
+            Align::Left => 0,
+            Align::Center => (width - 1) - width / 2,
+            Align::Right => width - 1,
+        }
+    }
+}
+
+impl FromStr for Align {
+    type Err = Error;
+
+    /// Parse an R-side `align` argument.
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "left" => Ok(Align::Left),
+            "center" => Ok(Align::Center),
+            "right" => Ok(Align::Right),
+            _ => Err(Error::Other(format!(
+                "`align` must be \"left\", \"center\" or \"right\", not \"{s}\""
+            ))),
+        }
+    }
+}
+
+fn check_width(width: usize) -> Result<()> {
+    if width == 0 {
+        Err(Error::Other(
+            "the width of a window must be positive".into(),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// `f` applied to every window of `width` elements of `values`, placed as
+/// `align` says. Positions without a complete window are `None`.
+pub fn roll_apply<'a, T, R, F>(
+    values: View<'a, T>,
+    width: usize,
+    align: Align,
+    mut f: F,
+) -> Result<Vec<Option<R>>>
+where
+    T: Copy,
+    F: FnMut(View<'a, T>) -> R,
+{
+    check_width(width)?;
+    let before = align.before(width);
+    let mut out: Vec<Option<R>> = (0..values.len()).map(|_| None).collect();
+    for (start, window) in values.windows(width).enumerate() {
+        out[start + before] = Some(f(window));
+    }
+    Ok(out)
+}
+
+/// Calls `step` with each index of `values` in turn and places what it
+/// returns for each complete window, `NA` elsewhere.
+fn roll_with<F>(values: View<'_, f64>, width: usize, align: Align, mut step: F) -> Result<Vec<f64>>
+where
+    F: FnMut(usize) -> Option<f64>,
+{
+    check_width(width)?;
+    let before = align.before(width);
+    let mut out = vec![<f64 as CanBeNA>::na(); values.len()];
+    for i in 0..values.len() {
+        if let Some(value) = step(i) {
+            out[i + 1 - width + before] = value;
+        }
+    }
+    Ok(out)
+}
+
+/// The kinds of values that R's summaries treat specially.
+#[derive(Debug, Default, Clone, Copy)]
+struct Specials {
+    na: usize,
+    nan: usize,
+    pos_inf: usize,
+    neg_inf: usize,
+}
+
+impl Specials {
+    /// Count `x` in or out of the window, returning whether it is finite.
+    fn update(&mut self, x: f64, add: bool) -> bool {
+        let count = if Rfloat::from(x).is_na() {
+            &mut self.na
+        } else if x.is_nan() {
+            &mut self.nan
+        } else if x == f64::INFINITY {
+            &mut self.pos_inf
+        } else if x == f64::NEG_INFINITY {
+            &mut self.neg_inf
+        } else {
+            return true;
+        };
+        if add {
+            *count += 1;
+        } else {
+            *count -= 1;
+        }
+        false
+    }
+
+    /// The result forced by missing values, if any, where `NA` wins over
+    /// `NaN`.
+    fn missing(&self, na_rm: bool) -> Option<f64> {
+        if na_rm {
+            None
+        } else if self.na > 0 {
+            Some(<f64 as CanBeNA>::na())
+        } else if self.nan > 0 {
+            Some(f64::NAN)
+        } else {
+            None
+        }
+    }
+}
+
+/// A compensated (Neumaier) running sum of the finite values of a window,
+/// so that rounding errors do not build up as values enter and leave it.
+#[derive(Debug, Default)]
+struct RunningSum {
+    sum: f64,
+    compensation: f64,
+    /// Values that are neither `NA` nor `NaN`.
+    count: usize,
+    specials: Specials,
+}
+
+impl RunningSum {
+    fn update(&mut self, x: f64, add: bool) {
+        let finite = self.specials.update(x, add);
+        if finite {
+            let y = if add { x } else { -x };
+            let t = self.sum + y;
+            if self.sum.abs() >= y.abs() {
+                self.compensation += (self.sum - t) + y;
+            } else {
+                self.compensation += (y - t) + self.sum;
+            }
+            self.sum = t;
+        }
+        if finite || x.is_infinite() {
+            if add {
+                self.count += 1;
+            } else {
+                self.count -= 1;
+            }
+        }
+    }
+
+    fn sum(&self, na_rm: bool) -> f64 {
+        let specials = &self.specials;
+        if let Some(missing) = specials.missing(na_rm) {
+            return missing;
+        }
+        match (specials.pos_inf > 0, specials.neg_inf > 0) {
+            (true, true) => f64::NAN,
+            (true, false) => f64::INFINITY,
+            (false, true) => f64::NEG_INFINITY,
+            (false, false) => self.sum + self.compensation,
+        }
+    }
+}
+
+fn roll_running<F>(values: View<'_, f64>, width: usize, align: Align, result: F) -> Result<Vec<f64>>
+where
+    F: Fn(&RunningSum) -> f64,
+{
+    let mut state = RunningSum::default();
+    roll_with(values, width, align, |i| {
+        state.update(values[i], true);
+        if i >= width {
+            state.update(values[i - width], false);
+        }
+        (i + 1 >= width).then(|| result(&state))
+    })
+}
+
+/// The sums of the windows of `width` elements of `values`.
+pub fn roll_sum(
+    values: View<'_, f64>,
+    width: usize,
+    align: Align,
+    na_rm: bool,
+) -> Result<Vec<f64>> {
+    roll_running(values, width, align, |state| state.sum(na_rm))
+}
+
+/// The means of the windows of `width` elements of `values`. With `na_rm`,
+/// windows without any values have a mean of `NaN`, as in R.
+pub fn roll_mean(
+    values: View<'_, f64>,
+    width: usize,
+    align: Align,
+    na_rm: bool,
+) -> Result<Vec<f64>> {
+    roll_running(values, width, align, |state| match state.count {
+        _ if state.specials.missing(na_rm).is_some() => state.sum(na_rm),
+        0 => f64::NAN,
+        n => state.sum(na_rm) / n as f64,
+    })
+}
+
+/// The maxima of the windows of `width` elements of `values`. With
+/// `na_rm`, windows without any values have a maximum of `-Inf`, as in R.
+pub fn roll_max(
+    values: View<'_, f64>,
+    width: usize,
+    align: Align,
+    na_rm: bool,
+) -> Result<Vec<f64>> {
+    roll_extreme(
+        values,
+        width,
+        align,
+        na_rm,
+        |a, b| a >= b,
+        f64::NEG_INFINITY,
+    )
+}
+
+/// The minima of the windows of `width` elements of `values`. With
+/// `na_rm`, windows without any values have a minimum of `Inf`, as in R.
+pub fn roll_min(
+    values: View<'_, f64>,
+    width: usize,
+    align: Align,
+    na_rm: bool,
+) -> Result<Vec<f64>> {
+    roll_extreme(values, width, align, na_rm, |a, b| a <= b, f64::INFINITY)
+}
+
+/// Running extrema with a monotonic deque: it holds the indices of the
+/// window's values that may still become its extreme, most extreme first,
+/// so every index is pushed and popped once.
+fn roll_extreme<F>(
+    values: View<'_, f64>,
+    width: usize,
+    align: Align,
+    na_rm: bool,
+    dominates: F,
+    empty: f64,
+) -> Result<Vec<f64>>
+where
+    F: Fn(f64, f64) -> bool,
+{
+    let mut candidates: VecDeque<usize> = VecDeque::with_capacity(width.min(values.len()));
+    let mut specials = Specials::default();
+    roll_with(values, width, align, |i| {
+        let x = values[i];
+        specials.update(x, true);
+        if !x.is_nan() {
+            while candidates.back().is_some_and(|&j| dominates(x, values[j])) {
+                candidates.pop_back();
+            }
+            candidates.push_back(i);
+        }
+        if i >= width {
+            specials.update(values[i - width], false);
+        }
+        if i + 1 < width {
+            return None;
+        }
+        let start = i + 1 - width;
+        while candidates.front().is_some_and(|&j| j < start) {
+            candidates.pop_front();
+        }
+        Some(
+            specials
+                .missing(na_rm)
+                .or_else(|| candidates.front().map(|&j| values[j]))
+                .unwrap_or(empty),
+        )
+    })
+}
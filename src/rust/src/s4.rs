@@ -0,0 +1,128 @@
+//! Typed views of R objects of known classes.
+//!
+//! [`import_s4_class!`] declares a Rust type for an R class and the fields
+//! Rust code reads from it, with their types:
+//!
+//! ```ignore
+//! import_s4_class! {
+//!     /// A linear model fitted by `lm()`.
+//!     pub struct Lm("lm") {
+//!         coefficients: Doubles,
+//!         residuals: Doubles,
+//!         rank: Integers,
+//!     }
+//! }
+//!
+//! let fit = Lm::try_from(R!("lm(mpg ~ wt, mtcars)")?)?;
+//! let slope = fit.coefficients().elt(1);
+//! ```
+//!
+//! Converting an object checks that it inherits from the class and that
+//! every field is there with the declared type, so a wrong object fails
+//! once, with an error naming the field, and the accessors return the
+//! fields without further checks. Fields are slots of S4 objects, read
+//! with `@`, and components of other objects, read with `[[`, as for the
+//! lists of S3 classes such as `lm`. A field type can be anything that
+//! converts from an `Robj`, including `Robj` itself for fields of any type.
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+/// Fail unless `x` inherits from `class`.
+pub fn check_class(x: &Robj, class: &str) -> Result<()> {
+    if x.inherits(class) {
+        return Ok(());
+    }
+    let actual = x
+        .class()
+        .map(|classes| classes.collect::<Vec<_>>().join("/"))
+        .unwrap_or_else(|| format!("{:?}", x.rtype()));
+    Err(Error::Other(format!(
+        "expected an object of class `{class}`, got `{actual}`"
+    )))
+}
+
+/// The slot `name` of an S4 object, or the component `name` of any other
+/// object, `NULL` if it has none.
+pub fn field(x: &Robj, name: &str) -> Result<Robj> {
+    let is_s4 = lang!("isS4", x.clone()).eval()?.as_bool() == Some(true);
+    if is_s4 {
+        Robj::from(Language::from_values([
+            Symbol::from_string("@").into(),
+            x.clone(),
+            Symbol::from_string(name).into(),
+        ]))
+        .eval()
+    } else {
+        lang!("[[", x.clone(), name).eval()
+    }
+}
+
+/// The field `name` of `x`, an object of class `class`, as a `T`.
+pub fn typed_field<T>(x: &Robj, class: &str, name: &str) -> Result<T>
+where
+    T: TryFrom<Robj>,
+    T::Error: std::fmt::Display,
+{
+    let value = field(x, name)?;
+    let rtype = value.rtype();
+    T::try_from(value).map_err(|e| {
+        Error::Other(format!(
+            "field `{name}` of the `{class}` object has the wrong type ({rtype:?}): {e}"
+        ))
+    })
+}
+
+/// Declare a Rust type for objects of an R class, with typed accessors for
+/// the fields listed. See the [module documentation](crate::s4).
+#[macro_export]
+macro_rules! import_s4_class {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident($class:literal) {
+            $($(#[$field_meta:meta])* $field:ident: $ty:ty),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone)]
+        $vis struct $name {
+            robj: ::extendr_api::Robj,
+            $($field: $ty,)*
+        }
+
+        impl $name {
+            /// The R class objects of this type inherit from.
+            pub const CLASS: &'static str = $class;
+
+            /// The R object.
+            pub fn robj(&self) -> &::extendr_api::Robj {
+                &self.robj
+            }
+
+            $(
+                $(#[$field_meta])*
+                pub fn $field(&self) -> &$ty {
+                    &self.$field
+                }
+            )*
+        }
+
+        impl ::std::convert::TryFrom<::extendr_api::Robj> for $name {
+            type Error = ::extendr_api::Error;
+
+            fn try_from(robj: ::extendr_api::Robj) -> ::extendr_api::Result<Self> {
+                $crate::s4::check_class(&robj, $class)?;
+                Ok($name {
+                    $($field: $crate::s4::typed_field(&robj, $class, stringify!($field))?,)*
+                    robj,
+                })
+            }
+        }
+
+        impl From<$name> for ::extendr_api::Robj {
+            fn from(value: $name) -> Self {
+                value.robj
+            }
+        }
+    };
+}
@@ -0,0 +1,364 @@
+//! Restricted evaluation of untrusted R snippets.
+//!
+//! [`SandboxedEval`] evaluates code in an environment whose parent is the
+//! empty environment and which only binds an allowlist of functions, so names
+//! such as `file()`, `system()` or `eval()` simply do not resolve. Code is
+//! also checked before it runs, and evaluation can be bounded in time and in
+//! nesting depth.
+//!
+//! This is defence in depth for hosts running snippets from their users; it
+//! is not a substitute for process-level isolation.
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use crate::ast::Expr;
+use crate::lookup::{base_function, exists_local};
+use crate::r_module;
+
+/// Functions available in a sandbox unless configured otherwise.
+pub const DEFAULT_ALLOWLIST: &[&str] = &[
+    "{",
+    "(",
+    "if",
+    "for",
+    "while",
+    "repeat",
+    "break",
+    "next",
+    "function",
+    "return",
+    "<-",
+    "=",
+    "+",
+    "-",
+    "*",
+    "/",
+    "^",
+    "%%",
+    "%/%",
+    "==",
+    "!=",
+    "<",
+    ">",
+    "<=",
+    ">=",
+    "!",
+    "&",
+    "|",
+    "&&",
+    "||",
+    ":",
+    "[",
+    "[[",
+    "$",
+    "[<-",
+    "[[<-",
+    "$<-",
+    "names<-",
+    "c",
+    "list",
+    "length",
+    "names",
+    "seq",
+    "seq_len",
+    "seq_along",
+    "rep",
+    "rev",
+    "sort",
+    "order",
+    "unique",
+    "sum",
+    "prod",
+    "mean",
+    "min",
+    "max",
+    "range",
+    "abs",
+    "sqrt",
+    "exp",
+    "log",
+    "round",
+    "floor",
+    "ceiling",
+    "cumsum",
+    "which",
+    "any",
+    "all",
+    "is.na",
+    "is.null",
+    "ifelse",
+    "identical",
+    "paste",
+    "paste0",
+    "nchar",
+    "substr",
+    "toupper",
+    "tolower",
+    "sprintf",
+    "format",
+    "as.numeric",
+    "as.integer",
+    "as.character",
+    "as.logical",
+    "numeric",
+    "integer",
+    "character",
+    "logical",
+    "matrix",
+    "nrow",
+    "ncol",
+    "t",
+    "lapply",
+    "sapply",
+    "vapply",
+    "Map",
+    "Filter",
+    "Reduce",
+    "stop",
+    "warning",
+];
+
+/// Functions that reach the file system, the network, other processes or the
+/// evaluator itself. These can never be allowed.
+pub const DENYLIST: &[&str] = &[
+    "::",
+    ":::",
+    "<<-",
+    "->>",
+    ".Internal",
+    ".Primitive",
+    ".Call",
+    ".External",
+    ".C",
+    ".Fortran",
+    "eval",
+    "evalq",
+    "local",
+    "get",
+    "get0",
+    "mget",
+    "assign",
+    "exists",
+    "environment",
+    "environment<-",
+    "as.environment",
+    "sys.call",
+    "sys.calls",
+    "sys.frame",
+    "sys.frames",
+    "sys.function",
+    "parent.frame",
+    "parent.env",
+    "parent.env<-",
+    "topenv",
+    "list2env",
+    "globalenv",
+    "emptyenv",
+    "baseenv",
+    "do.call",
+    "match.fun",
+    "body",
+    "body<-",
+    "formals<-",
+    "library",
+    "require",
+    "attach",
+    "loadNamespace",
+    "requireNamespace",
+    "getNamespace",
+    "asNamespace",
+    "system",
+    "system2",
+    "shell",
+    "file",
+    "url",
+    "gzfile",
+    "bzfile",
+    "xzfile",
+    "unz",
+    "pipe",
+    "fifo",
+    "socketConnection",
+    "serverSocket",
+    "download.file",
+    "readLines",
+    "writeLines",
+    "readRDS",
+    "saveRDS",
+    "load",
+    "save",
+    "source",
+    "sink",
+    "scan",
+    "file.remove",
+    "unlink",
+    "setwd",
+    "Sys.setenv",
+    "Sys.getenv",
+    "Sys.chmod",
+    "options",
+    "q",
+    "quit",
+    "reg.finalizer",
+    "dyn.load",
+];
+
+/// Evaluates R code with a restricted set of functions and resource limits.
+#[derive(Debug, Clone)]
+pub struct SandboxedEval {
+    env: Environment,
+    allowed: BTreeSet<String>,
+    timeout: Option<Duration>,
+    max_depth: Option<i32>,
+}
+
+impl SandboxedEval {
+    /// A sandbox exposing [`DEFAULT_ALLOWLIST`].
+    pub fn new() -> Result<Self> {
+        let mut sandbox = Self::empty();
+        for name in DEFAULT_ALLOWLIST {
+            sandbox.allow(name)?;
+        }
+        Ok(sandbox)
+    }
+
+    /// A sandbox with no functions at all, not even `{` or `<-`.
+    pub fn empty() -> Self {
+        Self {
+            env: Environment::new_with_parent(empty_env()),
+            allowed: BTreeSet::new(),
+            timeout: None,
+            max_depth: None,
+        }
+    }
+
+    /// Make a function callable inside the sandbox.
+    ///
+    /// Plain names are looked up in base R; `"pkg::name"` binds `name` from
+    /// the namespace of `pkg`. Names on the [`DENYLIST`] are rejected.
+    pub fn allow(&mut self, name: &str) -> Result<&mut Self> {
+        let (namespace, fun) = match name.split_once("::") {
+            Some((pkg, fun)) => (find_namespace(pkg)?, fun),
+            None => (base_namespace(), name),
+        };
+        if DENYLIST.contains(&fun) {
+            return Err(Error::Other(format!(
+                "`{fun}` cannot be allowed in a sandbox"
+            )));
+        }
+        let value = if exists_local(&namespace, fun)? {
+            namespace.local(Symbol::from_string(fun))?
+        } else {
+            Robj::from(())
+        };
+        if !value.is_function() {
+            return Err(Error::Other(format!("`{name}` is not a function")));
+        }
+        self.env.set_local(Symbol::from_string(fun), value);
+        self.allowed.insert(fun.to_string());
+        Ok(self)
+    }
+
+    /// Abort evaluation that takes longer than `timeout` of elapsed time.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Limit the nesting depth of evaluation (the `expressions` option, at
+    /// least 25).
+    pub fn max_depth(&mut self, depth: i32) -> &mut Self {
+        self.max_depth = Some(depth.max(25));
+        self
+    }
+
+    /// Make `value` available to sandboxed code as `name`.
+    pub fn assign(&self, name: &str, value: impl Into<Robj>) {
+        self.env.set_local(Symbol::from_string(name), value);
+    }
+
+    /// Parse `code` and reject it if it calls anything outside the allowlist.
+    pub fn check(&self, code: &str) -> Result<Expressions> {
+        let exprs = parse(code)?;
+        for expr in exprs.values() {
+            let calls = Expr::from_robj(&expr)?.calls();
+            if let Some(name) = calls.iter().find(|name| !self.allowed.contains(*name)) {
+                return Err(Error::Other(format!(
+                    "function `{name}` is not allowed in the sandbox"
+                )));
+            }
+        }
+        Ok(exprs)
+    }
+
+    /// Check and evaluate `code`, returning the value of the last expression.
+    pub fn eval(&self, code: &str) -> Result<Robj> {
+        let exprs = self.check(code)?;
+        let old_options = match self.max_depth {
+            Some(depth) => Some(base_function("options")?.call(pairlist!(expressions = depth))?),
+            None => None,
+        };
+        if let Some(timeout) = self.timeout {
+            lang!(
+                "setTimeLimit",
+                elapsed = timeout.as_secs_f64(),
+                transient = true
+            )
+            .eval()?;
+        }
+
+        let mut result = Ok(r!(NULL));
+        for expr in exprs.values() {
+            result = expr.eval_with_env(&self.env);
+            if result.is_err() {
+                break;
+            }
+        }
+
+        if self.timeout.is_some() {
+            lang!("setTimeLimit", elapsed = f64::INFINITY).eval()?;
+        }
+        if let Some(old) = old_options {
+            base_function("options")?.call(pairlist!(old))?;
+        }
+        result
+    }
+}
+
+/// Evaluate untrusted R code in a sandbox.
+///
+/// The code runs in an environment that only contains a fixed set of basic
+/// functions. Functions that touch files, connections, the network, other
+/// processes or the evaluator itself are not available and cannot be added.
+/// @param code A string of R code.
+/// @param allow Names of additional functions to make available, either as
+///   `"name"` for base functions or as `"pkg::name"`.
+/// @param timeout `NULL` or the maximum elapsed time in seconds.
+/// @return The value of the last expression in `code`.
+/// @export
+#[extendr]
+fn sandbox_eval(
+    code: &str,
+    #[extendr(default = "character()")] allow: Vec<String>,
+    #[extendr(default = "NULL")] timeout: Robj,
+) -> Result<Robj> {
+    let mut sandbox = SandboxedEval::new()?;
+    for name in &allow {
+        sandbox.allow(name)?;
+    }
+    if let Some(secs) = timeout
+        .as_real()
+        .or_else(|| timeout.as_integer().map(f64::from))
+    {
+        sandbox.timeout(Duration::from_secs_f64(secs.max(0.0)));
+    }
+    sandbox.eval(code)
+}
+
+r_module! {
+    mod sandbox;
+    fn sandbox_eval;
+}
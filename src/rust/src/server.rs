@@ -0,0 +1,346 @@
+//! A line-based socket server answering requests on R's main thread.
+//!
+//! [`Server`] listens on a TCP address or, on Unix, a socket file. Each
+//! connection is served by its own thread, which reads requests, one per
+//! line, and writes back one line per response. The requests themselves are
+//! queued for the main thread and answered by the server's handler there,
+//! through the [`event_loop`](crate::event_loop), so the handler may call
+//! into R: an R session serves requests while it waits at the console.
+//! Scripts, which never wait at the console, serve with
+//! [`Server::serve_for()`].
+//!
+//! Requests are handled one at a time, in order of arrival across all
+//! connections. A connection waits for the answer to each request before
+//! reading the next one.
+//!
+//! Servers still running when the package is unloaded are stopped, and
+//! their threads joined, by [`teardown`](crate::teardown).
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{mpsc, Arc, Mutex, Once};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::event_loop;
+use crate::parallel::interrupt_pending;
+use crate::teardown;
+
+/// How often the listener and idle connections check for shutdown.
+const SHUTDOWN_POLL: Duration = Duration::from_millis(50);
+
+type RequestHandler = Rc<RefCell<dyn FnMut(&str) -> String>>;
+
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+/// Requests waiting for the main thread: the server, the request line and
+/// where to send the response.
+static PENDING: Mutex<Vec<(u32, String, mpsc::Sender<String>)>> = Mutex::new(Vec::new());
+
+/// The listener thread of a running server and its shutdown flag.
+struct Listening {
+    id: u32,
+    shutdown: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+static LISTENERS: Mutex<Vec<Listening>> = Mutex::new(Vec::new());
+
+thread_local! {
+    // Only ever touched from the main thread.
+    static HANDLERS: RefCell<HashMap<u32, RequestHandler>> = RefCell::new(HashMap::new());
+}
+
+/// A running server. Dropping it stops listening and closes its
+/// connections.
+pub struct Server {
+    id: u32,
+    address: String,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl Server {
+    /// Listen on `address`, such as `127.0.0.1:8080`, or port 0 for any free
+    /// port, and answer each request line with `handler`.
+    pub fn bind_tcp(address: &str, handler: impl FnMut(&str) -> String + 'static) -> Result<Self> {
+        let listener = TcpListener::bind(address).map_err(|e| bind_error(address, e))?;
+        let local = listener
+            .local_addr()
+            .map_err(|e| bind_error(address, e))?
+            .to_string();
+        Self::start(listener, local, handler)
+    }
+
+    /// Listen on the Unix socket file `path`, which must not exist yet.
+    #[cfg(unix)]
+    pub fn bind_unix(
+        path: &std::path::Path,
+        handler: impl FnMut(&str) -> String + 'static,
+    ) -> Result<Self> {
+        let address = path.display().to_string();
+        let listener =
+            std::os::unix::net::UnixListener::bind(path).map_err(|e| bind_error(&address, e))?;
+        Self::start(listener, address, handler)
+    }
+
+    fn start<L: Listener>(
+        listener: L,
+        address: String,
+        handler: impl FnMut(&str) -> String + 'static,
+    ) -> Result<Self> {
+        listener
+            .set_nonblocking(true)
+            .map_err(|e| bind_error(&address, e))?;
+        event_loop::register(dispatch_pending)?;
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        HANDLERS.with(|handlers| {
+            handlers
+                .borrow_mut()
+                .insert(id, Rc::new(RefCell::new(handler)))
+        });
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let flag = shutdown.clone();
+        let listener = std::thread::spawn(move || accept_loop(id, listener, flag));
+        static TEARDOWN: Once = Once::new();
+        TEARDOWN.call_once(|| teardown::on_unload("socket servers", stop_all));
+        LISTENERS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(Listening {
+                id,
+                shutdown: shutdown.clone(),
+                thread: listener,
+            });
+        Ok(Self {
+            id,
+            address,
+            shutdown,
+        })
+    }
+
+    /// The address the server listens on, with the actual port for TCP.
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Answer requests for `duration`, or until the user interrupts, for
+    /// sessions that are not waiting at the console.
+    pub fn serve_for(&self, duration: Duration) -> Result<()> {
+        let start = Instant::now();
+        while start.elapsed() < duration {
+            event_loop::run_pending();
+            if interrupt_pending() {
+                return Err(Error::Other("interrupted by the user".into()));
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        let id = self.id;
+        let _ = HANDLERS.try_with(|handlers| handlers.borrow_mut().remove(&id));
+        // Dropping the senders ends the connections waiting for a response.
+        PENDING
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|(server, _, _)| *server != id);
+        // The listener and connection threads are not joined: one may be
+        // about to queue a request, which only the main thread can drop.
+        // They exit within `SHUTDOWN_POLL`.
+        LISTENERS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|listening| listening.id != id);
+    }
+}
+
+/// Stop all servers and join their threads, dropping the requests they
+/// queue meanwhile so that no connection waits for an answer.
+fn stop_all() {
+    let listeners = std::mem::take(&mut *LISTENERS.lock().unwrap_or_else(|e| e.into_inner()));
+    for listening in &listeners {
+        listening.shutdown.store(true, Ordering::Relaxed);
+    }
+    let _ = HANDLERS.try_with(|handlers| handlers.borrow_mut().clear());
+    loop {
+        PENDING.lock().unwrap_or_else(|e| e.into_inner()).clear();
+        if listeners
+            .iter()
+            .all(|listening| listening.thread.is_finished())
+        {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    for listening in listeners {
+        let _ = listening.thread.join();
+    }
+}
+
+fn bind_error(address: &str, e: io::Error) -> Error {
+    Error::Other(format!("cannot listen on {address}: {e}"))
+}
+
+/// What the server needs of TCP and Unix listeners and their streams.
+trait Listener: Send + 'static {
+    type Stream: Read + Write + Send + 'static;
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()>;
+    fn accept_stream(&self) -> io::Result<Self::Stream>;
+    fn prepare(stream: &Self::Stream) -> io::Result<Self::Stream>;
+}
+
+impl Listener for TcpListener {
+    type Stream = TcpStream;
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        TcpListener::set_nonblocking(self, nonblocking)
+    }
+
+    fn accept_stream(&self) -> io::Result<TcpStream> {
+        self.accept().map(|(stream, _)| stream)
+    }
+
+    fn prepare(stream: &TcpStream) -> io::Result<TcpStream> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(SHUTDOWN_POLL))?;
+        stream.try_clone()
+    }
+}
+
+#[cfg(unix)]
+impl Listener for std::os::unix::net::UnixListener {
+    type Stream = std::os::unix::net::UnixStream;
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        std::os::unix::net::UnixListener::set_nonblocking(self, nonblocking)
+    }
+
+    fn accept_stream(&self) -> io::Result<Self::Stream> {
+        self.accept().map(|(stream, _)| stream)
+    }
+
+    fn prepare(stream: &Self::Stream) -> io::Result<Self::Stream> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(SHUTDOWN_POLL))?;
+        stream.try_clone()
+    }
+}
+
+fn accept_loop<L: Listener>(id: u32, listener: L, shutdown: Arc<AtomicBool>) {
+    let mut connections = Vec::new();
+    while !shutdown.load(Ordering::Relaxed) {
+        match listener.accept_stream() {
+            Ok(stream) => {
+                let shutdown = shutdown.clone();
+                connections.push(std::thread::spawn(move || {
+                    // A connection that fails is simply closed.
+                    if let Ok(writer) = L::prepare(&stream) {
+                        let _ = serve_connection(id, stream, writer, &shutdown);
+                    }
+                }));
+            }
+            // Nothing to accept, or a connection that failed before it was
+            // accepted.
+            Err(_) => std::thread::sleep(SHUTDOWN_POLL),
+        }
+        connections.retain(|c: &JoinHandle<()>| !c.is_finished());
+    }
+    for connection in connections {
+        let _ = connection.join();
+    }
+}
+
+fn serve_connection(
+    id: u32,
+    reader: impl Read,
+    mut writer: impl Write,
+    shutdown: &AtomicBool,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(reader);
+    let mut line = Vec::new();
+    loop {
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) => return Ok(()),
+            Ok(_) if line.last() != Some(&b'\n') => continue,
+            Ok(_) => {}
+            // The read timed out: check for shutdown and keep the partial
+            // line.
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                if shutdown.load(Ordering::Relaxed) {
+                    return Ok(());
+                }
+                continue;
+            }
+            Err(e) => return Err(e),
+        }
+        while matches!(line.last(), Some(b'\n' | b'\r')) {
+            line.pop();
+        }
+        let request = String::from_utf8_lossy(&line).into_owned();
+        line.clear();
+
+        let (tx, rx) = mpsc::channel();
+        PENDING
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((id, request, tx));
+        event_loop::wake();
+        let response = match rx.recv() {
+            Ok(response) => response,
+            // The server was stopped.
+            Err(_) => return Ok(()),
+        };
+        writer.write_all(response.as_bytes())?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+    }
+}
+
+/// Answer the requests received so far and return how many there were.
+/// Must be called from the main thread.
+pub fn dispatch_pending() -> usize {
+    let requests = std::mem::take(&mut *PENDING.lock().unwrap_or_else(|e| e.into_inner()));
+    let count = requests.len();
+    for (id, request, tx) in requests {
+        let handler = HANDLERS.with(|handlers| handlers.borrow().get(&id).cloned());
+        let handler = match handler {
+            Some(handler) => handler,
+            None => continue,
+        };
+        // A handler that dispatches from within itself leaves its own
+        // requests unanswered, which closes their connections.
+        let mut handler = match handler.try_borrow_mut() {
+            Ok(handler) => handler,
+            Err(_) => continue,
+        };
+        let response = (*handler)(&request);
+        let _ = tx.send(response);
+    }
+    count
+}
+
+/// A handler calling the R function `f` with each request and answering
+/// with the string it returns, which must not contain newlines. Errors are
+/// answered with `error: <message>`.
+pub fn r_handler(f: Function) -> impl FnMut(&str) -> String {
+    move |request| {
+        let response = f.call(pairlist!(request)).and_then(|value| {
+            value
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| Error::Other("the handler must return a string".into()))
+        });
+        response.unwrap_or_else(|e| format!("error: {e}").replace('\n', " "))
+    }
+}
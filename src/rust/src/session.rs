@@ -0,0 +1,275 @@
+//! Snapshots of the state of the R session, to find side effects.
+//!
+//! A function that assigns into the global environment, sets an option or
+//! loads a package changes the session for all the code that runs after it,
+//! and the change often only shows much later. A [`SessionSnapshot`] records
+//! the bindings of the global environment, the options and the loaded
+//! namespaces, and [`SessionSnapshot::diff()`] lists what differs between
+//! two of them. A test can check that a function leaves no trace:
+//!
+//! ```ignore
+//! let before = SessionSnapshot::take()?;
+//! f()?;
+//! assert_eq!(before.changes_since()?, []);
+//! ```
+//!
+//! Values are compared by a hash of their serialization, and environments
+//! and external pointers by identity, so a change inside an environment
+//! bound in the global one goes unnoticed. Promises are not forced and only
+//! show as added or removed.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use extendr_api::prelude::*;
+use extendr_api::robj::GetSexp;
+use extendr_api::Result;
+
+use crate::attrib::{set_compact_row_names, AttribExt};
+use crate::cache::hash_key;
+use crate::r_module;
+
+/// Where a [`Change`] happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+    Global,
+    Option,
+    Namespace,
+}
+
+/// What a [`Change`] did to its binding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Scope::Global => "global",
+            Scope::Option => "option",
+            Scope::Namespace => "namespace",
+        })
+    }
+}
+
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Changed => "changed",
+        })
+    }
+}
+
+/// A difference between two snapshots.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Change {
+    pub scope: Scope,
+    pub name: String,
+    pub kind: ChangeKind,
+}
+
+/// The state of the session at one point, as fingerprints of its values.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SessionSnapshot {
+    pub globals: BTreeMap<String, String>,
+    pub options: BTreeMap<String, String>,
+    pub namespaces: BTreeSet<String>,
+}
+
+/// A string that changes when `value` does.
+fn fingerprint(value: &Robj) -> Result<String> {
+    match value.rtype() {
+        Rtype::Environment | Rtype::ExternalPtr => {
+            let address = unsafe { value.get() };
+            Ok(format!("{:?} at {:p}", value.rtype(), address))
+        }
+        Rtype::Promise => Ok("promise".into()),
+        _ => hash_key(value),
+    }
+}
+
+fn strings(x: &Robj) -> Vec<String> {
+    x.as_str_vector()
+        .map(|x| x.into_iter().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+impl SessionSnapshot {
+    /// The current state.
+    pub fn take() -> Result<Self> {
+        let env = global_env();
+        let mut globals = BTreeMap::new();
+        for name in strings(&R!("ls({{ env.clone() }}, all.names = TRUE)")?) {
+            let value = env.local(Symbol::from_string(&name))?;
+            globals.insert(name, fingerprint(&value)?);
+        }
+        let mut options = BTreeMap::new();
+        for (name, value) in List::try_from(lang!("options").eval()?)?.iter() {
+            options.insert(name.to_string(), fingerprint(&value)?);
+        }
+        let namespaces = strings(&lang!("loadedNamespaces").eval()?);
+        Ok(Self {
+            globals,
+            options,
+            namespaces: namespaces.into_iter().collect(),
+        })
+    }
+
+    /// What changed from `self` to `after`, by scope and name.
+    pub fn diff(&self, after: &SessionSnapshot) -> Vec<Change> {
+        let mut changes = Vec::new();
+        diff_maps(Scope::Global, &self.globals, &after.globals, &mut changes);
+        diff_maps(Scope::Option, &self.options, &after.options, &mut changes);
+        for name in after.namespaces.difference(&self.namespaces) {
+            changes.push(Change {
+                scope: Scope::Namespace,
+                name: name.clone(),
+                kind: ChangeKind::Added,
+            });
+        }
+        for name in self.namespaces.difference(&after.namespaces) {
+            changes.push(Change {
+                scope: Scope::Namespace,
+                name: name.clone(),
+                kind: ChangeKind::Removed,
+            });
+        }
+        changes.sort();
+        changes
+    }
+
+    /// What changed since `self` was taken.
+    pub fn changes_since(&self) -> Result<Vec<Change>> {
+        Ok(self.diff(&Self::take()?))
+    }
+
+    /// The snapshot as a list of class `session_snapshot`.
+    pub fn to_robj(&self) -> Result<Robj> {
+        let named = |map: &BTreeMap<String, String>| -> Result<Robj> {
+            let mut values: Robj = Strings::from_values(map.values().map(String::as_str)).into();
+            values.set_attr(
+                "names",
+                Strings::from_values(map.keys().map(String::as_str)),
+            )?;
+            Ok(values)
+        };
+        let mut x: Robj = List::from_names_and_values(
+            ["globals", "options", "namespaces"],
+            [
+                named(&self.globals)?,
+                named(&self.options)?,
+                Strings::from_values(self.namespaces.iter().map(String::as_str)).into(),
+            ],
+        )?
+        .into();
+        x.set_attr("class", "session_snapshot")?;
+        Ok(x)
+    }
+
+    /// A snapshot made by [`to_robj()`](Self::to_robj).
+    pub fn from_robj(x: &Robj) -> Result<Self> {
+        if !x.inherits("session_snapshot") {
+            return Err(Error::Other(
+                "expected a snapshot made by `session_snapshot()`".into(),
+            ));
+        }
+        let field = |name: &str| x.dollar(name).unwrap_or_else(|_| r!(NULL));
+        let named = |name: &str| -> BTreeMap<String, String> {
+            let values = field(name);
+            let names = values
+                .names()
+                .map(|names| names.map(str::to_string).collect::<Vec<_>>())
+                .unwrap_or_default();
+            names.into_iter().zip(strings(&values)).collect()
+        };
+        Ok(Self {
+            globals: named("globals"),
+            options: named("options"),
+            namespaces: strings(&field("namespaces")).into_iter().collect(),
+        })
+    }
+}
+
+fn diff_maps(
+    scope: Scope,
+    before: &BTreeMap<String, String>,
+    after: &BTreeMap<String, String>,
+    changes: &mut Vec<Change>,
+) {
+    let mut push = |name: &str, kind| {
+        changes.push(Change {
+            scope,
+            name: name.to_string(),
+            kind,
+        })
+    };
+    for (name, value) in after {
+        match before.get(name) {
+            None => push(name, ChangeKind::Added),
+            Some(old) if old != value => push(name, ChangeKind::Changed),
+            Some(_) => {}
+        }
+    }
+    for name in before.keys().filter(|name| !after.contains_key(*name)) {
+        push(name, ChangeKind::Removed);
+    }
+}
+
+/// Find the side effects of code on the session
+///
+/// `session_snapshot()` records the variables of the global environment,
+/// the options and the loaded namespaces, and `what_changed()` lists the
+/// differences between two snapshots, to find out what a function left
+/// behind. Values are compared by the hash of their serialization, and
+/// environments by identity, so changes inside an environment are not
+/// reported.
+/// @return `session_snapshot()` returns an object of class
+///   `session_snapshot`.
+/// @export
+#[extendr]
+fn session_snapshot() -> Result<Robj> {
+    SessionSnapshot::take()?.to_robj()
+}
+
+/// @rdname session_snapshot
+/// @param before A snapshot taken before the code ran.
+/// @param after A later snapshot, or `NULL` for the current state.
+/// @return `what_changed()` returns a data frame with the `scope` of each
+///   change (`"global"`, `"option"` or `"namespace"`), the `name` changed
+///   and the `change` (`"added"`, `"removed"` or `"changed"`).
+/// @export
+#[extendr]
+fn what_changed(before: Robj, #[extendr(default = "NULL")] after: Robj) -> Result<Robj> {
+    let before = SessionSnapshot::from_robj(&before)?;
+    let after = if after.is_null() {
+        SessionSnapshot::take()?
+    } else {
+        SessionSnapshot::from_robj(&after)?
+    };
+    let changes = before.diff(&after);
+    let column =
+        |f: fn(&Change) -> String| -> Robj { Strings::from_values(changes.iter().map(f)).into() };
+    let mut df: Robj = List::from_names_and_values(
+        ["scope", "name", "change"],
+        [
+            column(|c| c.scope.to_string()),
+            column(|c| c.name.clone()),
+            column(|c| c.kind.to_string()),
+        ],
+    )?
+    .into();
+    set_compact_row_names(&mut df, changes.len())?;
+    df.set_attr("class", "data.frame")?;
+    Ok(df)
+}
+
+r_module! {
+    mod session;
+    fn session_snapshot;
+    fn what_changed;
+}
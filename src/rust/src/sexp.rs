@@ -0,0 +1,175 @@
+//! Typed handles for calls into R's C API.
+//!
+//! The bindings of `extendr_ffi` are generated by bindgen from R's headers,
+//! so every R object is a bare `*mut SEXPREC` and every type code a bare
+//! `u32`: a `SEXP` is accepted where a `*mut` to anything else converts to
+//! it, and a type code compares equal to any integer. [`Sexp`] wraps the
+//! pointer in a `#[repr(transparent)]` newtype, which has the same ABI and
+//! so can be used in `extern` declarations directly, and [`SexpType`] is
+//! `SEXPTYPE` as an enum, converted from the raw code with `TryFrom<u32>`.
+//!
+//! The entry points this crate declares itself, the ones `extendr_ffi` does
+//! not export, take and return [`Sexp`], or [`SexpRef`] for arguments they
+//! only read; see [`crate::rapi`]. Values from `extendr_ffi` are wrapped with
+//! [`Sexp::from_raw()`] and [`Sexp::of()`].
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::marker::PhantomData;
+
+use extendr_api::robj::GetSexp;
+use extendr_ffi::SEXP;
+
+use crate::rapi::TYPEOF;
+
+/// A pointer to an R object, unprotected: it is only valid while something
+/// else keeps the object alive.
+#[repr(transparent)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Sexp(SEXP);
+
+impl Sexp {
+    pub fn from_raw(sexp: SEXP) -> Self {
+        Self(sexp)
+    }
+
+    /// The object of `x`, such as an `Robj`, which keeps it alive.
+    pub fn of(x: &impl GetSexp) -> Self {
+        // Safety: reading the pointer is safe, using it is what needs care.
+        Self(unsafe { x.get() })
+    }
+
+    pub fn as_raw(self) -> SEXP {
+        self.0
+    }
+
+    /// The type of the object, which fails for types newer than this crate.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must be to a live R object.
+    pub unsafe fn sexptype(self) -> std::result::Result<SexpType, UnknownSexpType> {
+        SexpType::try_from(TYPEOF(SexpRef::new(self)) as u32)
+    }
+}
+
+/// A [`Sexp`] passed to an entry point that only reads it, the `const SEXP`
+/// R's headers cannot express. It borrows the owner it was taken from, so
+/// the object outlives the call.
+#[repr(transparent)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct SexpRef<'a>(Sexp, PhantomData<&'a ()>);
+
+impl<'a> SexpRef<'a> {
+    /// The object of `x`, borrowed for as long as `x` is.
+    pub fn of(x: &'a impl GetSexp) -> Self {
+        Self(Sexp::of(x), PhantomData)
+    }
+
+    /// # Safety
+    ///
+    /// `sexp` must stay alive for `'a`.
+    pub unsafe fn new(sexp: Sexp) -> Self {
+        Self(sexp, PhantomData)
+    }
+
+    pub fn sexp(self) -> Sexp {
+        self.0
+    }
+}
+
+impl fmt::Debug for SexpRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SexpRef({:p})", (self.0).0)
+    }
+}
+
+impl fmt::Debug for Sexp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Sexp({:p})", self.0)
+    }
+}
+
+/// The type of an R object, `SEXPTYPE` in R's headers.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SexpType {
+    Nil = 0,
+    Symbol = 1,
+    Pairlist = 2,
+    Closure = 3,
+    Environment = 4,
+    Promise = 5,
+    Language = 6,
+    Special = 7,
+    Builtin = 8,
+    Char = 9,
+    Logical = 10,
+    Integer = 13,
+    Real = 14,
+    Complex = 15,
+    String = 16,
+    Dot = 17,
+    Any = 18,
+    List = 19,
+    Expression = 20,
+    Bytecode = 21,
+    ExternalPtr = 22,
+    WeakRef = 23,
+    Raw = 24,
+    /// `OBJSXP`, formerly `S4SXP`.
+    Object = 25,
+}
+
+/// A type code that is not a [`SexpType`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownSexpType(pub u32);
+
+impl fmt::Display for UnknownSexpType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown SEXPTYPE {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownSexpType {}
+
+impl TryFrom<u32> for SexpType {
+    type Error = UnknownSexpType;
+
+    fn try_from(code: u32) -> std::result::Result<Self, UnknownSexpType> {
+        use SexpType::*;
+        Ok(match code {
+            0 => Nil,
+            1 => Symbol,
+            2 => Pairlist,
+            3 => Closure,
+            4 => Environment,
+            5 => Promise,
+            6 => Language,
+            7 => Special,
+            8 => Builtin,
+            9 => Char,
+            10 => Logical,
+            13 => Integer,
+            14 => Real,
+            15 => Complex,
+            16 => String,
+            17 => Dot,
+            18 => Any,
+            19 => List,
+            20 => Expression,
+            21 => Bytecode,
+            22 => ExternalPtr,
+            23 => WeakRef,
+            24 => Raw,
+            25 => Object,
+            _ => return Err(UnknownSexpType(code)),
+        })
+    }
+}
+
+impl From<SexpType> for u32 {
+    fn from(sexptype: SexpType) -> u32 {
+        sexptype as u32
+    }
+}
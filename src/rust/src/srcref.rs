@@ -0,0 +1,161 @@
+//! Parsing with source references.
+//!
+//! [`parse_with_srcrefs()`] parses R code with `keep.source = TRUE` and pairs
+//! each top-level expression, and each statement of the `{` blocks within
+//! it, with its location in the original text. Linters, formatters and error
+//! reporters can then point at the exact code an [`Expr`] came from.
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+use crate::ast::Expr;
+use crate::attrib::AttribExt;
+
+/// The location of an expression in its source file.
+///
+/// Lines and columns are one-based and inclusive, as in R's `srcref`
+/// objects; columns count characters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SrcRef {
+    pub file: String,
+    pub first_line: usize,
+    pub first_column: usize,
+    pub last_line: usize,
+    pub last_column: usize,
+    /// The source text of the expression.
+    pub text: String,
+}
+
+impl std::fmt::Display for SrcRef {
+    /// Formats as `file:line:column`, the form editors recognize.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.first_line, self.first_column)
+    }
+}
+
+/// An expression together with where it was parsed from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceExpr {
+    pub expr: Expr,
+    pub srcref: SrcRef,
+    /// The statements of the `{` blocks nested in this expression, such as a
+    /// function body or the branches of an `if`, with their own locations.
+    pub statements: Vec<SourceExpr>,
+}
+
+/// Parse `code`, as if read from `file`, keeping source references.
+pub fn parse_with_srcrefs(code: &str, file: &str) -> Result<Vec<SourceExpr>> {
+    // Passing a `srcfile` object makes `parse()` keep source references
+    // regardless of the `keep.source` option.
+    let srcfile = lang!("srcfilecopy", file, code).eval()?;
+    let exprs = lang!("parse", text = code, srcfile = srcfile).eval()?;
+    let source = Source { file, code };
+
+    let srcrefs = srcref_list(&exprs);
+    exprs
+        .as_expressions()
+        .ok_or_else(|| Error::Other("`parse()` did not return an expression vector".into()))?
+        .values()
+        .zip(srcrefs)
+        .map(|(expr, srcref)| source.locate(&expr, &srcref))
+        .collect()
+}
+
+struct Source<'a> {
+    file: &'a str,
+    code: &'a str,
+}
+
+impl Source<'_> {
+    fn locate(&self, expr: &Robj, srcref: &Robj) -> Result<SourceExpr> {
+        let mut statements = Vec::new();
+        self.collect_statements(expr, &mut statements)?;
+        Ok(SourceExpr {
+            expr: Expr::from_robj(expr)?,
+            srcref: self.srcref(srcref)?,
+            statements,
+        })
+    }
+
+    /// Find the `{` blocks in `expr`, stopping at each block since its
+    /// statements collect their own nested blocks.
+    fn collect_statements(&self, expr: &Robj, out: &mut Vec<SourceExpr>) -> Result<()> {
+        let call = match expr.as_language() {
+            Some(call) => call,
+            None => return Ok(()),
+        };
+        let is_block = call
+            .values()
+            .next()
+            .and_then(|head| head.as_symbol())
+            .is_some_and(|sym| sym.as_str() == "{");
+        if is_block {
+            // The first srcref of a block belongs to the `{` itself.
+            for (stmt, srcref) in call.values().zip(srcref_list(expr)).skip(1) {
+                out.push(self.locate(&stmt, &srcref)?);
+            }
+            return Ok(());
+        }
+        for part in call.values() {
+            self.collect_statements(&part, out)?;
+        }
+        Ok(())
+    }
+
+    fn srcref(&self, srcref: &Robj) -> Result<SrcRef> {
+        let pos = srcref
+            .as_integer_slice()
+            .filter(|pos| pos.len() >= 6)
+            .ok_or_else(|| Error::Other("malformed srcref".into()))?;
+        let at = |i: usize| pos[i].max(1) as usize;
+        Ok(SrcRef {
+            file: self.file.to_string(),
+            first_line: at(0),
+            first_column: at(4),
+            last_line: at(2),
+            last_column: at(5),
+            text: self.text(at(0), at(1), at(2), at(3)),
+        })
+    }
+
+    /// The code between two one-based, inclusive (line, byte) positions.
+    fn text(
+        &self,
+        first_line: usize,
+        first_byte: usize,
+        last_line: usize,
+        last_byte: usize,
+    ) -> String {
+        let lines: Vec<&str> = self.code.lines().collect();
+        let line = |n: usize| lines.get(n - 1).copied().unwrap_or("");
+        let clamp = |s: &str, i: usize| i.min(s.len());
+        if first_line == last_line {
+            let l = line(first_line);
+            return l
+                .get(clamp(l, first_byte - 1)..clamp(l, last_byte))
+                .unwrap_or("")
+                .to_string();
+        }
+        let first = line(first_line);
+        let mut text = first
+            .get(clamp(first, first_byte - 1)..)
+            .unwrap_or("")
+            .to_string();
+        for n in first_line + 1..last_line {
+            text.push('\n');
+            text.push_str(line(n));
+        }
+        let last = line(last_line);
+        text.push('\n');
+        text.push_str(last.get(..clamp(last, last_byte)).unwrap_or(""));
+        text
+    }
+}
+
+/// The `srcref` attribute of an expression vector or block as a list.
+fn srcref_list(robj: &Robj) -> Vec<Robj> {
+    robj.attr("srcref")
+        .and_then(|refs| refs.as_list())
+        .map(|refs| refs.values().collect())
+        .unwrap_or_default()
+}
@@ -0,0 +1,258 @@
+//! Edit distances between strings, and fuzzy matching with them.
+//!
+//! [`levenshtein()`], [`osa()`] and [`jaro_winkler()`] compare strings by
+//! Unicode characters rather than bytes, so an accented letter is one edit
+//! whatever its encoding, and agree with the `"lv"`, `"osa"` and `"jw"`
+//! methods of the stringdist package. The R functions convert all strings
+//! to UTF-8 on the main thread first and compare them on worker threads.
+//!
+//! `NA` is at an unknown distance from everything: the distance is `NA`
+//! and, unless `match_na` is true, an `NA` is matched to nothing.
+
+use std::str::FromStr;
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use helloextendr_core::recycle::{common_length, recycled_index};
+
+use crate::encoding::Utf8Strings;
+use crate::parallel::{par_map_slice, ParallelOptions};
+use crate::r_module;
+use crate::xlen::robj_to_length;
+
+/// How the distance between two strings is measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StringMetric {
+    /// The number of insertions, deletions and substitutions.
+    Levenshtein,
+    /// The Levenshtein distance, counting a swap of adjacent characters as
+    /// one edit, with no substring edited twice.
+    Osa,
+    /// One minus the Jaro-Winkler similarity.
+    JaroWinkler,
+}
+
+impl StringMetric {
+    /// The distance between `a` and `b`, where `p` is the Jaro-Winkler
+    /// prefix scale and ignored by the other metrics.
+    pub fn distance(self, a: &[char], b: &[char], p: f64) -> f64 {
+        match self {
+            StringMetric::Levenshtein => levenshtein(a, b) as f64,
+            StringMetric::Osa => osa(a, b) as f64,
+            StringMetric::JaroWinkler => jaro_winkler(a, b, p),
+        }
+    }
+}
+
+impl FromStr for StringMetric {
+    type Err = Error;
+
+    /// Parse an R-side `method` argument, with stringdist's names.
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "lv" => Ok(StringMetric::Levenshtein),
+            "osa" => Ok(StringMetric::Osa),
+            "jw" => Ok(StringMetric::JaroWinkler),
+            _ => Err(Error::Other(format!(
+                "`method` must be \"lv\", \"osa\" or \"jw\", not \"{s}\""
+            ))),
+        }
+    }
+}
+
+/// The Levenshtein distance between `a` and `b`.
+pub fn levenshtein(a: &[char], b: &[char]) -> usize {
+    // One row of the edit matrix at a time.
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, &ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitute = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitute.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// The optimal string alignment distance between `a` and `b`.
+pub fn osa(a: &[char], b: &[char]) -> usize {
+    // Transpositions look two rows back.
+    let width = b.len() + 1;
+    let mut previous2 = vec![0; width];
+    let mut previous: Vec<usize> = (0..width).collect();
+    let mut current = vec![0; width];
+    for i in 1..=a.len() {
+        current[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut d = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d = d.min(previous2[j - 2] + 1);
+            }
+            current[j] = d;
+        }
+        std::mem::swap(&mut previous2, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// One minus the Jaro-Winkler similarity of `a` and `b`, which rewards a
+/// common prefix of up to four characters by the scale `p`, from 0 for the
+/// plain Jaro distance to 0.25.
+pub fn jaro_winkler(a: &[char], b: &[char], p: f64) -> f64 {
+    if a.is_empty() && b.is_empty() {
+        return 0.0;
+    }
+    if a.is_empty() || b.is_empty() {
+        return 1.0;
+    }
+    let window = (a.len().max(b.len()) / 2).saturating_sub(1);
+    let mut matched_b = vec![false; b.len()];
+    let mut matches_a = Vec::with_capacity(a.len().min(b.len()));
+    for (i, &ca) in a.iter().enumerate() {
+        let lo = i.saturating_sub(window);
+        let hi = (i + window + 1).min(b.len());
+        if let Some(j) = (lo..hi).find(|&j| !matched_b[j] && b[j] == ca) {
+            matched_b[j] = true;
+            matches_a.push(ca);
+        }
+    }
+    let m = matches_a.len();
+    if m == 0 {
+        return 1.0;
+    }
+    let matches_b = b.iter().zip(&matched_b).filter(|(_, &m)| m).map(|(c, _)| c);
+    let half_transpositions = matches_a
+        .iter()
+        .zip(matches_b)
+        .filter(|(x, y)| x != y)
+        .count();
+    let m = m as f64;
+    let t = (half_transpositions / 2) as f64;
+    let jaro = (m / a.len() as f64 + m / b.len() as f64 + (m - t) / m) / 3.0;
+    let prefix = a.iter().zip(b).take(4).take_while(|(x, y)| x == y).count();
+    1.0 - (jaro + prefix as f64 * p * (1.0 - jaro))
+}
+
+/// The characters of each string, `None` for `NA`.
+fn chars(x: Robj, what: &str) -> Result<Vec<Option<Vec<char>>>> {
+    let strings = Utf8Strings::try_from(x)
+        .map_err(|_| Error::Other(format!("`{what}` must be a character vector")))?;
+    strings
+        .iter()
+        .map(|s| s.map(|s| s.map(|s| s.chars().collect())))
+        .collect()
+}
+
+fn metric_options(method: &str, p: f64, threads: &Robj) -> Result<(StringMetric, ParallelOptions)> {
+    let metric: StringMetric = method.parse()?;
+    if !(0.0..=0.25).contains(&p) {
+        return Err(Error::Other("`p` must be between 0 and 0.25".into()));
+    }
+    let mut options = ParallelOptions::new().chunk_size(256);
+    if !threads.is_null() {
+        options = options.threads(robj_to_length(threads)?);
+    }
+    Ok((metric, options))
+}
+
+/// Distances between strings
+///
+/// `string_dist()` computes the distance between each element of `a` and
+/// the corresponding element of `b`, recycling the shorter. Strings are
+/// compared by characters, and the distance is `NA` if either is `NA`.
+///
+/// `string_amatch()` finds, for each element of `x`, the closest element of
+/// `table` at a distance of at most `max_dist`, as `match()` does for exact
+/// matches. Of equally close elements, the first is found.
+/// @param a,b,x,table Character vectors.
+/// @param method `"osa"` for the optimal string alignment distance, `"lv"`
+///   for the Levenshtein distance or `"jw"` for the Jaro-Winkler distance.
+/// @param p The Jaro-Winkler prefix scale, from 0 to 0.25. The default of 0
+///   gives the Jaro distance.
+/// @param threads `NULL` for one thread per CPU, or a number of threads.
+/// @return `string_dist()` returns a numeric vector of distances.
+///   `string_amatch()` returns an integer vector of positions in `table`,
+///   `NA` where no element is close enough.
+/// @export
+#[extendr]
+fn string_dist(
+    a: Robj,
+    b: Robj,
+    #[extendr(default = "\"osa\"")] method: &str,
+    #[extendr(default = "0")] p: f64,
+    #[extendr(default = "NULL")] threads: Robj,
+) -> Result<Doubles> {
+    let (metric, options) = metric_options(method, p, &threads)?;
+    let (a, b) = (chars(a, "a")?, chars(b, "b")?);
+    let len = common_length(&[a.len(), b.len()]).unwrap_or(0);
+    let indices: Vec<usize> = (0..len).collect();
+    let distances = par_map_slice(&indices, options, |&i| {
+        match (
+            &a[recycled_index(i, a.len())],
+            &b[recycled_index(i, b.len())],
+        ) {
+            (Some(a), Some(b)) => Some(metric.distance(a, b, p)),
+            _ => None,
+        }
+    })?;
+    Ok(Doubles::from_values(
+        distances
+            .into_iter()
+            .map(|d| d.map_or(Rfloat::na(), Rfloat::from)),
+    ))
+}
+
+/// @rdname string_dist
+/// @param max_dist The largest distance at which elements match.
+/// @param match_na Whether `NA` matches `NA` in `table`.
+/// @export
+#[extendr]
+fn string_amatch(
+    x: Robj,
+    table: Robj,
+    #[extendr(default = "0.1")] max_dist: f64,
+    #[extendr(default = "\"osa\"")] method: &str,
+    #[extendr(default = "0")] p: f64,
+    #[extendr(default = "TRUE")] match_na: bool,
+    #[extendr(default = "NULL")] threads: Robj,
+) -> Result<Integers> {
+    let (metric, options) = metric_options(method, p, &threads)?;
+    if max_dist.is_nan() {
+        return Err(Error::Other("`max_dist` must not be missing".into()));
+    }
+    let (x, table) = (chars(x, "x")?, chars(table, "table")?);
+    let first_na = table.iter().position(Option::is_none);
+    let found = par_map_slice(&x, options, |x| match x {
+        None => first_na.filter(|_| match_na),
+        Some(x) => {
+            let mut best: Option<(usize, f64)> = None;
+            for (j, candidate) in table.iter().enumerate() {
+                let Some(candidate) = candidate else { continue };
+                let d = metric.distance(x, candidate, p);
+                if d <= max_dist && best.is_none_or(|(_, best)| d < best) {
+                    best = Some((j, d));
+                    if d == 0.0 {
+                        break;
+                    }
+                }
+            }
+            best.map(|(j, _)| j)
+        }
+    })?;
+    Ok(Integers::from_values(found.into_iter().map(|j| match j {
+        Some(j) => Rint::from(j as i32 + 1),
+        None => Rint::na(),
+    })))
+}
+
+r_module! {
+    mod strdist;
+    fn string_dist;
+    fn string_amatch;
+}
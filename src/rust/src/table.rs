@@ -0,0 +1,153 @@
+//! Contingency tables, as R's `table()`.
+//!
+//! [`cross_count()`] counts the combinations of zero-based codes, such as
+//! those of [`cut()`](crate::interval::cut), into a column-major array, and
+//! [`table_robj()`] turns R vectors into factors the way `table()` does and
+//! returns an integer array of class `table` with named dimnames, one
+//! dimension per vector.
+
+use std::str::FromStr;
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+use crate::attrib::AttribExt;
+
+/// Whether tables count missing values, as `table(useNA = )`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum UseNa {
+    /// Leave them out.
+    #[default]
+    No,
+    /// Count them in an `NA` level if there are any.
+    IfAny,
+    /// Always have an `NA` level.
+    Always,
+}
+
+impl FromStr for UseNa {
+    type Err = Error;
+
+    /// Parse an R-side `useNA` argument.
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "no" => Ok(UseNa::No),
+            "ifany" => Ok(UseNa::IfAny),
+            "always" => Ok(UseNa::Always),
+            _ => Err(Error::Other(format!(
+                "`useNA` must be \"no\", \"ifany\" or \"always\", not \"{s}\""
+            ))),
+        }
+    }
+}
+
+/// The number of times each combination of codes occurs, in column-major
+/// order over `dims`: `codes[k][i]` is the level of the `i`-th observation
+/// in dimension `k`. Observations with a `None` code are not counted.
+pub fn cross_count(codes: &[Vec<Option<usize>>], dims: &[usize]) -> Result<Vec<usize>> {
+    if codes.len() != dims.len() {
+        return Err(Error::Other(format!(
+            "{} code vectors for {} dimensions",
+            codes.len(),
+            dims.len()
+        )));
+    }
+    let n = codes.first().map_or(0, Vec::len);
+    if codes.iter().any(|c| c.len() != n) {
+        return Err(Error::Other(
+            "all arguments must have the same length".into(),
+        ));
+    }
+    let size = dims
+        .iter()
+        .try_fold(1usize, |size, &d| size.checked_mul(d))
+        .ok_or_else(|| Error::Other("attempt to make a table with too many cells".into()))?;
+    let mut counts = vec![0; size];
+    'observations: for i in 0..n {
+        let mut cell = 0;
+        let mut stride = 1;
+        for (codes, &dim) in codes.iter().zip(dims) {
+            match codes[i] {
+                Some(code) if code < dim => cell += code * stride,
+                _ => continue 'observations,
+            }
+            stride *= dim;
+        }
+        counts[cell] += 1;
+    }
+    Ok(counts)
+}
+
+/// The levels and zero-based codes of one argument of `table()`.
+fn classify(x: &Robj, use_na: UseNa) -> Result<(Strings, Vec<Option<usize>>)> {
+    let factor = if x.inherits("factor") {
+        x.clone()
+    } else if use_na == UseNa::No {
+        // `table()` also excludes NaN, and with it the string "NaN".
+        let exclude = Doubles::from_values([Rfloat::na(), Rfloat::from(f64::NAN)]);
+        lang!("factor", x.clone(), exclude = exclude).eval()?
+    } else {
+        // With `exclude = NULL`, NA and NaN become levels if they occur.
+        lang!("factor", x.clone(), exclude = ()).eval()?
+    };
+    let mut levels: Vec<Rstr> = match factor.attr("levels") {
+        Some(levels) => Strings::try_from(levels)?.iter().cloned().collect(),
+        None => Vec::new(),
+    };
+    let raw = factor.as_integer_slice().unwrap_or(&[]);
+    let has_na = raw.iter().any(|&code| Rint::from(code).is_na());
+    let mut na_level = levels.iter().position(|level| level.is_na());
+    let add_na = match use_na {
+        UseNa::No => false,
+        UseNa::IfAny => has_na,
+        UseNa::Always => true,
+    };
+    if add_na && na_level.is_none() {
+        na_level = Some(levels.len());
+        levels.push(Rstr::na());
+    }
+    let codes = raw
+        .iter()
+        .map(|&code| {
+            if Rint::from(code).is_na() {
+                if use_na == UseNa::No {
+                    None
+                } else {
+                    na_level
+                }
+            } else {
+                usize::try_from(code).ok()?.checked_sub(1)
+            }
+        })
+        .collect();
+    Ok((Strings::from_values(levels), codes))
+}
+
+/// `table(...)` over the elements of `args`, whose names become the names
+/// of the dimnames.
+pub fn table_robj(args: &List, use_na: UseNa) -> Result<Robj> {
+    if args.is_empty() {
+        return Err(Error::Other("nothing to tabulate".into()));
+    }
+    let mut names = Vec::with_capacity(args.len());
+    let mut levels = Vec::with_capacity(args.len());
+    let mut codes = Vec::with_capacity(args.len());
+    for (name, arg) in args.iter() {
+        let (arg_levels, arg_codes) = classify(&arg, use_na)?;
+        names.push(name.to_string());
+        levels.push(arg_levels);
+        codes.push(arg_codes);
+    }
+    let dims: Vec<usize> = levels.iter().map(|l| l.len()).collect();
+    let counts = cross_count(&codes, &dims)?
+        .into_iter()
+        .map(|n| i32::try_from(n).map_err(|_| Error::Other(format!("a count of {n} is too large"))))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut out = Robj::from(Integers::from_values(counts));
+    out.set_dim(&dims)?;
+    let dimnames = List::from_names_and_values(names, levels)?;
+    out.set_attr("dimnames", dimnames)?;
+    out.set_attr("class", "table")?;
+    Ok(out)
+}
@@ -0,0 +1,72 @@
+//! Teardown of global Rust state when the package is unloaded.
+//!
+//! Threads, event loop handlers and temporary files started by the library
+//! outlive it unless they are stopped before R unloads it: a thread left
+//! running executes code that is no longer mapped, and a handler left
+//! registered makes R call into it. Each reload during development would
+//! also start them again.
+//!
+//! Code that starts such global state registers how to stop it with
+//! [`on_unload()`]. [`run()`] stops everything registered, newest first, so
+//! state torn down never sees the state it was built on go first: a file
+//! watcher stops before the event loop handler it wakes is removed. It runs
+//! from `.onUnload()` and, for a library unloaded without it, from
+//! `R_unload_helloextendr()`. A teardown that panics is reported, and the
+//! others still run.
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Mutex;
+
+use extendr_api::prelude::*;
+
+use crate::r_module;
+
+type Teardown = Box<dyn FnOnce() + Send>;
+
+/// The registered teardowns with their names, oldest first.
+static TEARDOWNS: Mutex<Vec<(&'static str, Teardown)>> = Mutex::new(Vec::new());
+
+/// Run `teardown` when the package is unloaded. `name` identifies it in
+/// warnings.
+pub fn on_unload(name: &'static str, teardown: impl FnOnce() + Send + 'static) {
+    TEARDOWNS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push((name, Box::new(teardown)));
+}
+
+/// Run the registered teardowns, newest first, and return the names of
+/// those that panicked. Each runs once.
+pub fn run() -> Vec<&'static str> {
+    let mut failed = Vec::new();
+    loop {
+        // Not held while a teardown runs, which may register another.
+        let next = TEARDOWNS.lock().unwrap_or_else(|e| e.into_inner()).pop();
+        let (name, teardown) = match next {
+            Some(next) => next,
+            None => return failed,
+        };
+        if catch_unwind(AssertUnwindSafe(teardown)).is_err() {
+            failed.push(name);
+        }
+    }
+}
+
+/// Called by `R_unload_helloextendr()` in `entrypoint.c`.
+#[no_mangle]
+pub extern "C" fn helloextendr_teardown() {
+    let _ = catch_unwind(run);
+}
+
+/// Stop the global state of the library, newest first, and return the
+/// names of the teardowns that failed.
+/// @noRd
+#[extendr]
+fn run_teardown() -> Vec<String> {
+    run().into_iter().map(str::to_string).collect()
+}
+
+r_module! {
+    mod teardown;
+    fn run_teardown;
+}
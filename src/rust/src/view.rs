@@ -0,0 +1,172 @@
+//! Strided views of R vectors.
+//!
+//! Rolling-window and signal-processing kernels look at many overlapping
+//! parts of the same vector. Subsetting them in R copies each part into a
+//! new vector; a [`View`] instead reads every `stride`-th element starting
+//! at `offset` in place, and costs no more than a slice to create:
+//!
+//! ```ignore
+//! let x: Doubles = R!("as.double(1:10)")?.try_into()?;
+//! let odd = x.view(0, 5, 2)?; // 1, 3, 5, 7, 9
+//! let means: Vec<f64> = odd.windows(3).map(|w| w.iter().sum::<f64>() / 3.0).collect();
+//! ```
+//!
+//! Views are read-only and borrow the vector, which therefore stays
+//! protected for as long as they are used.
+
+use std::ops::Index;
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+/// Every `stride`-th of `len` elements of a slice, starting at `offset`.
+#[derive(Debug, Clone, Copy)]
+pub struct View<'a, T> {
+    data: &'a [T],
+    offset: usize,
+    len: usize,
+    stride: usize,
+}
+
+impl<'a, T: Copy> View<'a, T> {
+    /// A view of `data`, failing unless the `len` elements are all within
+    /// it and `stride` is at least one.
+    pub fn new(data: &'a [T], offset: usize, len: usize, stride: usize) -> Result<Self> {
+        check_fits(data.len(), offset, len, stride)?;
+        Ok(Self {
+            data,
+            offset,
+            len,
+            stride,
+        })
+    }
+
+    /// A view of all of `data`.
+    pub fn of(data: &'a [T]) -> Self {
+        Self {
+            data,
+            offset: 0,
+            len: data.len(),
+            stride: 1,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// The `i`-th element of the view.
+    pub fn get(&self, i: usize) -> Option<T> {
+        (i < self.len).then(|| self.data[self.offset + i * self.stride])
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = T> + ExactSizeIterator + 'a {
+        let (data, offset, stride) = (self.data, self.offset, self.stride);
+        (0..self.len).map(move |i| data[offset + i * stride])
+    }
+
+    /// A view of the elements of this view, with `offset`, `len` and
+    /// `stride` counted in them rather than in the underlying vector.
+    pub fn view(&self, offset: usize, len: usize, stride: usize) -> Result<Self> {
+        check_fits(self.len, offset, len, stride)?;
+        Ok(Self {
+            data: self.data,
+            offset: self.offset + offset * self.stride,
+            len,
+            stride: self.stride * stride,
+        })
+    }
+
+    /// The views of `width` consecutive elements of this view, starting at
+    /// each element in turn, like [`slice::windows()`].
+    pub fn windows(&self, width: usize) -> impl ExactSizeIterator<Item = View<'a, T>> + 'a {
+        let view = *self;
+        let count = if width == 0 {
+            0
+        } else {
+            (self.len + 1).saturating_sub(width)
+        };
+        (0..count).map(move |start| View {
+            data: view.data,
+            offset: view.offset + start * view.stride,
+            len: width,
+            stride: view.stride,
+        })
+    }
+
+    pub fn to_vec(self) -> Vec<T> {
+        self.iter().collect()
+    }
+}
+
+impl<T> Index<usize> for View<'_, T> {
+    type Output = T;
+
+    fn index(&self, i: usize) -> &T {
+        assert!(
+            i < self.len,
+            "index {i} out of bounds for a view of {}",
+            self.len
+        );
+        &self.data[self.offset + i * self.stride]
+    }
+}
+
+fn check_fits(available: usize, offset: usize, len: usize, stride: usize) -> Result<()> {
+    if stride == 0 {
+        return Err(Error::Other("the stride of a view must be positive".into()));
+    }
+    let end = match len {
+        0 => Some(offset),
+        _ => (len - 1)
+            .checked_mul(stride)
+            .and_then(|span| span.checked_add(offset))
+            .and_then(|last| last.checked_add(1)),
+    };
+    match end {
+        Some(end) if end <= available => Ok(()),
+        _ => Err(Error::Other(format!(
+            "a view of {len} elements from offset {offset} with stride {stride} \
+             does not fit in {available} elements"
+        ))),
+    }
+}
+
+/// Views of the elements of R vectors.
+pub trait ViewExt {
+    type Elt: Copy;
+
+    /// Every `stride`-th of `len` elements starting at the zero-based
+    /// `offset`, failing unless they are all within the vector.
+    fn view(&self, offset: usize, len: usize, stride: usize) -> Result<View<'_, Self::Elt>>;
+}
+
+impl ViewExt for Doubles {
+    type Elt = f64;
+
+    fn view(&self, offset: usize, len: usize, stride: usize) -> Result<View<'_, f64>> {
+        let data = self.as_robj().as_real_slice().unwrap_or(&[]);
+        View::new(data, offset, len, stride)
+    }
+}
+
+impl ViewExt for Integers {
+    type Elt = i32;
+
+    fn view(&self, offset: usize, len: usize, stride: usize) -> Result<View<'_, i32>> {
+        let data = self.as_robj().as_integer_slice().unwrap_or(&[]);
+        View::new(data, offset, len, stride)
+    }
+}
@@ -0,0 +1,255 @@
+//! File watching with callbacks on R's main thread.
+//!
+//! [`watch()`] registers a native watcher (inotify, FSEvents or
+//! `ReadDirectoryChangesW`, through the `notify` crate) whose events are
+//! queued by its background thread. The callbacks run on the main thread
+//! only, when the queue is drained by [`dispatch_pending()`], which the
+//! [`event_loop`](crate::event_loop) does while R waits for console input
+//! on Unix. Elsewhere, call `dispatch_pending()`, or `watch_poll()` from R.
+//!
+//! The native watchers need the `watch` feature. Without it, [`watch()`]
+//! fails.
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+#[cfg(feature = "watch")]
+use notify::event::{EventKind, ModifyKind};
+#[cfg(feature = "watch")]
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::{Mutex, Once};
+
+use crate::event_loop;
+use crate::r_module;
+use crate::teardown;
+
+/// What happened to the paths of an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchEventKind {
+    Create,
+    Modify,
+    Remove,
+    /// The paths are the old then the new name, when both are known.
+    Rename,
+    Other,
+}
+
+impl WatchEventKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WatchEventKind::Create => "create",
+            WatchEventKind::Modify => "modify",
+            WatchEventKind::Remove => "remove",
+            WatchEventKind::Rename => "rename",
+            WatchEventKind::Other => "other",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchEvent {
+    pub kind: WatchEventKind,
+    pub paths: Vec<PathBuf>,
+}
+
+impl WatchEvent {
+    /// Convert an event of the watcher. Accesses are not reported.
+    #[cfg(feature = "watch")]
+    fn from_notify(event: notify::Event) -> Option<Self> {
+        let kind = match event.kind {
+            EventKind::Access(_) => return None,
+            EventKind::Create(_) => WatchEventKind::Create,
+            EventKind::Modify(ModifyKind::Name(_)) => WatchEventKind::Rename,
+            EventKind::Modify(_) => WatchEventKind::Modify,
+            EventKind::Remove(_) => WatchEventKind::Remove,
+            EventKind::Any | EventKind::Other => WatchEventKind::Other,
+        };
+        Some(Self {
+            kind,
+            paths: event.paths,
+        })
+    }
+
+    /// The event as `list(kind = , paths = )`.
+    pub fn to_robj(&self) -> Robj {
+        let paths: Vec<String> = self.paths.iter().map(|p| p.display().to_string()).collect();
+        list!(kind = self.kind.as_str(), paths = paths).into()
+    }
+}
+
+type Callback = Rc<RefCell<dyn FnMut(&WatchEvent)>>;
+
+#[cfg(feature = "watch")]
+type NativeWatcher = RecommendedWatcher;
+
+/// No watcher can be created without the `watch` feature.
+#[cfg(not(feature = "watch"))]
+type NativeWatcher = std::convert::Infallible;
+
+struct Watch {
+    // Dropping the watcher stops it.
+    _watcher: NativeWatcher,
+    callback: Callback,
+}
+
+/// Events waiting for the main thread, by watch id. Filled by the watcher
+/// threads.
+static PENDING: Mutex<Vec<(u32, WatchEvent)>> = Mutex::new(Vec::new());
+
+thread_local! {
+    // Only ever touched from the main thread.
+    static WATCHES: RefCell<HashMap<u32, Watch>> = RefCell::new(HashMap::new());
+    static NEXT_ID: std::cell::Cell<u32> = const { std::cell::Cell::new(1) };
+}
+
+/// Call `callback` on the main thread for changes to `path`, and to the
+/// files below it if it is a directory and `recursive` is set. Returns the
+/// id to pass to [`unwatch()`].
+pub fn watch(
+    path: &Path,
+    recursive: bool,
+    callback: impl FnMut(&WatchEvent) + 'static,
+) -> Result<u32> {
+    let id = NEXT_ID.with(|next| {
+        let id = next.get();
+        next.set(id + 1);
+        id
+    });
+    let watcher = start_watcher(id, path, recursive)?;
+    event_loop::register(dispatch_pending)?;
+    static TEARDOWN: Once = Once::new();
+    TEARDOWN.call_once(|| {
+        // Dropping the watchers joins their threads.
+        teardown::on_unload("file watchers", || {
+            WATCHES.with(|watches| watches.borrow_mut().clear());
+        });
+    });
+    WATCHES.with(|watches| {
+        watches.borrow_mut().insert(
+            id,
+            Watch {
+                _watcher: watcher,
+                callback: Rc::new(RefCell::new(callback)),
+            },
+        )
+    });
+    Ok(id)
+}
+
+/// A native watcher of `path` queueing its events for the watch `id`.
+#[cfg(feature = "watch")]
+fn start_watcher(id: u32, path: &Path, recursive: bool) -> Result<NativeWatcher> {
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        // Errors of the watcher thread have nowhere to go but the queue
+        // would only report them out of context, so they are dropped.
+        if let Some(event) = event.ok().and_then(WatchEvent::from_notify) {
+            PENDING
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push((id, event));
+            event_loop::wake();
+        }
+    })
+    .map_err(|e| Error::Other(format!("cannot create a file watcher: {e}")))?;
+    let mode = if recursive {
+        RecursiveMode::Recursive
+    } else {
+        RecursiveMode::NonRecursive
+    };
+    watcher
+        .watch(path, mode)
+        .map_err(|e| Error::Other(format!("cannot watch {}: {e}", path.display())))?;
+    Ok(watcher)
+}
+
+#[cfg(not(feature = "watch"))]
+fn start_watcher(_id: u32, _path: &Path, _recursive: bool) -> Result<NativeWatcher> {
+    Err(crate::missing_feature("watch"))
+}
+
+/// Stop the watch `id` and drop its callback and pending events. Returns
+/// whether it existed.
+pub fn unwatch(id: u32) -> bool {
+    let removed = WATCHES.with(|watches| watches.borrow_mut().remove(&id));
+    PENDING
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .retain(|(watch, _)| *watch != id);
+    removed.is_some()
+}
+
+/// Run the callbacks of the events queued so far and return how many ran.
+/// Must be called from the main thread.
+pub fn dispatch_pending() -> usize {
+    let events = std::mem::take(&mut *PENDING.lock().unwrap_or_else(|e| e.into_inner()));
+    let mut dispatched = 0;
+    for (id, event) in events {
+        let callback =
+            WATCHES.with(|watches| watches.borrow().get(&id).map(|w| w.callback.clone()));
+        // A callback that dispatches from within itself does not see its
+        // own events again.
+        let callback = match callback {
+            Some(callback) => callback,
+            None => continue,
+        };
+        let mut callback = match callback.try_borrow_mut() {
+            Ok(callback) => callback,
+            Err(_) => continue,
+        };
+        (*callback)(&event);
+        dispatched += 1;
+    }
+    dispatched
+}
+
+/// Watch files for changes
+///
+/// `callback` is called with a list of the event `kind` (`"create"`,
+/// `"modify"`, `"remove"`, `"rename"` or `"other"`) and the affected
+/// `paths`. On Linux and macOS the callbacks run while R waits for console
+/// input. On Windows, and in scripts that do not return to the console,
+/// call `watch_poll()` to run them.
+/// @param path A file or directory.
+/// @param callback A function of one argument.
+/// @param recursive Whether to also watch the files below a directory.
+/// @param id An id returned by `watch_path()`.
+/// @return `watch_path()` returns the id of the watch, `unwatch_path()`
+///   whether it existed and `watch_poll()` the number of callbacks run.
+/// @export
+#[extendr]
+fn watch_path(
+    path: &str,
+    callback: Function,
+    #[extendr(default = "TRUE")] recursive: bool,
+) -> Result<i32> {
+    let id = watch(Path::new(path), recursive, move |event| {
+        if let Err(e) = callback.call(pairlist!(event.to_robj())) {
+            let _ = lang!("warning", format!("file watch callback failed: {e}")).eval();
+        }
+    })?;
+    Ok(id as i32)
+}
+
+/// @rdname watch_path
+/// @export
+#[extendr]
+fn unwatch_path(id: i32) -> bool {
+    unwatch(id as u32)
+}
+
+/// @rdname watch_path
+/// @export
+#[extendr]
+fn watch_poll() -> i32 {
+    dispatch_pending() as i32
+}
+
+r_module! {
+    mod watch;
+    fn watch_path;
+    fn unwatch_path;
+    fn watch_poll;
+}
@@ -0,0 +1,296 @@
+//! A WebSocket client delivering messages on R's main thread.
+//!
+//! [`WebSocket::connect()`] opens a `ws://` or `wss://` connection served by
+//! its own thread, which runs a single-threaded tokio runtime. Incoming
+//! messages are queued and passed to the connection's handler on the main
+//! thread by the [`event_loop`](crate::event_loop), so the handler may call
+//! into R and a dashboard can update from a stream while the console waits
+//! for input. Sending never blocks: messages are queued for the
+//! connection's thread. Connections still open when the package is
+//! unloaded are closed, and their threads joined, by
+//! [`teardown`](crate::teardown).
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use futures_util::{SinkExt, StreamExt};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{mpsc, Mutex, Once};
+use std::thread::JoinHandle;
+use tokio::sync::mpsc as async_mpsc;
+use tokio_tungstenite::tungstenite;
+
+use crate::event_loop;
+use crate::raw_io::IntoRaw;
+use crate::teardown;
+
+/// What the handler of a connection receives.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    /// The connection was closed, by the server with its reason if any.
+    Closed(Option<String>),
+    /// The connection failed and is closed.
+    Error(String),
+}
+
+impl Message {
+    /// The kind of message: `"text"`, `"binary"`, `"close"` or `"error"`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Message::Text(_) => "text",
+            Message::Binary(_) => "binary",
+            Message::Closed(_) => "close",
+            Message::Error(_) => "error",
+        }
+    }
+
+    /// The payload as a string, a raw vector, or the close reason or error
+    /// message.
+    pub fn to_robj(&self) -> Robj {
+        match self {
+            Message::Text(text) => text.into(),
+            Message::Binary(bytes) => bytes.into_raw().into(),
+            Message::Closed(reason) => reason.as_deref().into(),
+            Message::Error(message) => message.into(),
+        }
+    }
+}
+
+type MessageHandler = Rc<RefCell<dyn FnMut(&Message)>>;
+
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+/// Messages waiting for the main thread, by connection.
+static PENDING: Mutex<Vec<(u32, Message)>> = Mutex::new(Vec::new());
+
+/// The threads of the open connections, with the senders that close them.
+static CONNECTIONS: Mutex<Vec<(u32, Sender, JoinHandle<()>)>> = Mutex::new(Vec::new());
+
+thread_local! {
+    // Only ever touched from the main thread.
+    static HANDLERS: RefCell<HashMap<u32, MessageHandler>> = RefCell::new(HashMap::new());
+}
+
+enum Outgoing {
+    Message(tungstenite::Message),
+    Close,
+}
+
+type Sender = async_mpsc::UnboundedSender<Outgoing>;
+
+/// An open connection. Dropping it closes the connection.
+pub struct WebSocket {
+    id: u32,
+    outgoing: Sender,
+}
+
+impl WebSocket {
+    /// Connect to `url` and pass every message received to `handler`.
+    /// Returns once the handshake succeeded.
+    pub fn connect(url: &str, handler: impl FnMut(&Message) + 'static) -> Result<Self> {
+        event_loop::register(dispatch_pending)?;
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let (outgoing, rx) = async_mpsc::unbounded_channel();
+        let (connected_tx, connected) = mpsc::channel();
+        let url = url.to_string();
+        let thread = std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build();
+            match runtime {
+                Ok(runtime) => runtime.block_on(run(id, url, rx, connected_tx)),
+                Err(e) => {
+                    let _ = connected_tx.send(Err(e.to_string()));
+                }
+            }
+        });
+        match connected.recv() {
+            Ok(Ok(())) => {}
+            Ok(Err(message)) => {
+                let _ = thread.join();
+                return Err(Error::Other(message));
+            }
+            Err(_) => return Err(Error::Other("the WebSocket thread panicked".into())),
+        }
+        HANDLERS.with(|handlers| {
+            handlers
+                .borrow_mut()
+                .insert(id, Rc::new(RefCell::new(handler)))
+        });
+        static TEARDOWN: Once = Once::new();
+        TEARDOWN.call_once(|| teardown::on_unload("WebSockets", close_all));
+        CONNECTIONS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((id, outgoing.clone(), thread));
+        Ok(Self { id, outgoing })
+    }
+
+    pub fn send_text(&self, text: &str) -> Result<()> {
+        self.send(tungstenite::Message::text(text))
+    }
+
+    pub fn send_binary(&self, bytes: &[u8]) -> Result<()> {
+        self.send(tungstenite::Message::binary(bytes.to_vec()))
+    }
+
+    fn send(&self, message: tungstenite::Message) -> Result<()> {
+        self.outgoing
+            .send(Outgoing::Message(message))
+            .map_err(|_| Error::Other("the WebSocket is closed".into()))
+    }
+
+    /// Whether the connection is still open.
+    pub fn is_open(&self) -> bool {
+        CONNECTIONS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .any(|(id, _, thread)| *id == self.id && !thread.is_finished())
+    }
+
+    /// Close the connection and wait for its thread to finish. The handler
+    /// receives no further messages.
+    pub fn close(&mut self) {
+        let id = self.id;
+        let connection = {
+            let mut connections = CONNECTIONS.lock().unwrap_or_else(|e| e.into_inner());
+            let index = connections.iter().position(|(c, _, _)| *c == id);
+            index.map(|index| connections.remove(index))
+        };
+        if let Some((_, outgoing, thread)) = connection {
+            let _ = outgoing.send(Outgoing::Close);
+            let _ = thread.join();
+        }
+        let _ = HANDLERS.try_with(|handlers| handlers.borrow_mut().remove(&id));
+        PENDING
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|(connection, _)| *connection != id);
+    }
+}
+
+/// Close all connections and join their threads.
+fn close_all() {
+    let connections = std::mem::take(&mut *CONNECTIONS.lock().unwrap_or_else(|e| e.into_inner()));
+    for (_, outgoing, _) in &connections {
+        let _ = outgoing.send(Outgoing::Close);
+    }
+    for (_, _, thread) in connections {
+        let _ = thread.join();
+    }
+    let _ = HANDLERS.try_with(|handlers| handlers.borrow_mut().clear());
+    PENDING.lock().unwrap_or_else(|e| e.into_inner()).clear();
+}
+
+impl Drop for WebSocket {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+fn queue(id: u32, message: Message) {
+    PENDING
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push((id, message));
+    event_loop::wake();
+}
+
+async fn run(
+    id: u32,
+    url: String,
+    mut outgoing: async_mpsc::UnboundedReceiver<Outgoing>,
+    connected: mpsc::Sender<std::result::Result<(), String>>,
+) {
+    let stream = match tokio_tungstenite::connect_async(url.as_str()).await {
+        Ok((stream, _)) => {
+            let _ = connected.send(Ok(()));
+            stream
+        }
+        Err(e) => {
+            let _ = connected.send(Err(format!("cannot connect to {url}: {e}")));
+            return;
+        }
+    };
+    let (mut sink, mut incoming) = stream.split();
+    loop {
+        tokio::select! {
+            message = incoming.next() => match message {
+                Some(Ok(tungstenite::Message::Text(text))) => {
+                    queue(id, Message::Text(text.to_string()));
+                }
+                Some(Ok(tungstenite::Message::Binary(bytes))) => {
+                    queue(id, Message::Binary(bytes.to_vec()));
+                }
+                Some(Ok(tungstenite::Message::Close(frame))) => {
+                    let reason = frame
+                        .map(|f| f.reason.to_string())
+                        .filter(|r| !r.is_empty());
+                    queue(id, Message::Closed(reason));
+                    return;
+                }
+                // Pings are answered by tungstenite itself.
+                Some(Ok(_)) => {}
+                Some(Err(e)) => {
+                    queue(id, Message::Error(e.to_string()));
+                    return;
+                }
+                None => {
+                    queue(id, Message::Closed(None));
+                    return;
+                }
+            },
+            message = outgoing.recv() => match message {
+                Some(Outgoing::Message(message)) => {
+                    if let Err(e) = sink.send(message).await {
+                        queue(id, Message::Error(e.to_string()));
+                        return;
+                    }
+                }
+                // Closed by the user, or the `WebSocket` was dropped.
+                Some(Outgoing::Close) | None => {
+                    let _ = sink.send(tungstenite::Message::Close(None)).await;
+                    return;
+                }
+            },
+        }
+    }
+}
+
+/// Pass the messages received so far to their handlers and return how many
+/// there were. Must be called from the main thread.
+pub fn dispatch_pending() -> usize {
+    let messages = std::mem::take(&mut *PENDING.lock().unwrap_or_else(|e| e.into_inner()));
+    let count = messages.len();
+    for (id, message) in messages {
+        let handler = HANDLERS.with(|handlers| handlers.borrow().get(&id).cloned());
+        let handler = match handler {
+            Some(handler) => handler,
+            None => continue,
+        };
+        // A handler that dispatches from within itself skips its own
+        // messages.
+        let mut handler = match handler.try_borrow_mut() {
+            Ok(handler) => handler,
+            Err(_) => continue,
+        };
+        (*handler)(&message);
+    }
+    count
+}
+
+/// A handler calling the R function `f` with the payload of each message,
+/// from [`Message::to_robj()`], and its [kind](Message::kind). Errors of
+/// `f` become R warnings.
+pub fn r_handler(f: Function) -> impl FnMut(&Message) {
+    move |message| {
+        if let Err(e) = f.call(pairlist!(message.to_robj(), message.kind())) {
+            let _ = lang!("warning", format!("WebSocket handler failed: {e}")).eval();
+        }
+    }
+}
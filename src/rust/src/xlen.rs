@@ -0,0 +1,77 @@
+//! Lengths and indices of long vectors.
+//!
+//! Since R 3.0 vectors can have more than `2^31 - 1` elements, with lengths
+//! of type `R_xlen_t`. Such lengths no longer fit in an R integer, so R
+//! reports them as doubles, and Rust code must keep them in `usize` rather
+//! than `i32` all the way through. The helpers here convert between the two
+//! sides without truncation.
+
+use extendr_api::prelude::*;
+use extendr_api::robj::GetSexp;
+use extendr_api::Result;
+use helloextendr_core::na::is_na_integer;
+
+/// The largest length a vector can have without being a long vector.
+pub const R_SHORT_LEN_MAX: usize = i32::MAX as usize;
+
+/// The largest whole number a double represents exactly, and therefore the
+/// largest length or index that R can pass around.
+pub const R_XLEN_T_MAX: usize = (1 << 52) - 1;
+
+/// `XLENGTH`-based length accessors.
+pub trait XLength {
+    /// The number of elements of a vector, or `None` if this is not a vector.
+    fn xlength(&self) -> Option<usize>;
+
+    /// Whether this is a vector with more than [`R_SHORT_LEN_MAX`] elements.
+    fn is_long_vector(&self) -> bool {
+        self.xlength().is_some_and(|len| len > R_SHORT_LEN_MAX)
+    }
+}
+
+impl XLength for Robj {
+    fn xlength(&self) -> Option<usize> {
+        match self.rtype() {
+            Rtype::Logicals
+            | Rtype::Integers
+            | Rtype::Doubles
+            | Rtype::Complexes
+            | Rtype::Strings
+            | Rtype::List
+            | Rtype::Expressions
+            | Rtype::Raw => Some(unsafe { extendr_ffi::XLENGTH(self.get()) } as usize),
+            _ => None,
+        }
+    }
+}
+
+/// Convert a length or count for R the way `length()` does: an integer if it
+/// fits, a double otherwise.
+pub fn length_to_robj(len: usize) -> Robj {
+    if len <= R_SHORT_LEN_MAX {
+        r!(len as i32)
+    } else {
+        r!(len as f64)
+    }
+}
+
+/// Convert a zero-based offset into a one-based R index, using a double for
+/// positions a long vector can only be subset with.
+pub fn index_to_robj(offset: usize) -> Robj {
+    length_to_robj(offset + 1)
+}
+
+/// Read a length from an R integer or double scalar without truncation.
+pub fn robj_to_length(x: &Robj) -> Result<usize> {
+    let value = x
+        .as_integer()
+        .filter(|&i| !is_na_integer(i))
+        .map(f64::from)
+        .or_else(|| x.as_real());
+    match value {
+        Some(v) if v >= 0.0 && v.fract() == 0.0 && v <= R_XLEN_T_MAX as f64 => Ok(v as usize),
+        _ => Err(Error::Other(format!(
+            "expected a non-negative whole number of at most {R_XLEN_T_MAX}, got {x:?}"
+        ))),
+    }
+}
@@ -0,0 +1,324 @@
+//! Excel workbooks read into data frames with `calamine`.
+//!
+//! [`read_sheet()`] reads a rectangle of a worksheet, by default the cells
+//! in use, and turns each column into the vector its cells fit: logical,
+//! double, `Date`, `POSIXct` or character. Blank cells, empty strings and
+//! error values are `NA`. A column mixing types is character, with numbers,
+//! booleans and dates as text. Cells formatted as dates become `Date` when
+//! all of a column falls on midnight and `POSIXct` in UTC otherwise; date
+//! serials count from 1899-12-30, so workbooks on the 1904 date system are
+//! read four years early.
+//!
+//! Reading needs the `xlsx` feature; without it, [`read_sheet()`] fails.
+
+#[cfg(feature = "xlsx")]
+use std::collections::BTreeSet;
+use std::path::Path;
+
+#[cfg(feature = "xlsx")]
+use calamine::{open_workbook_auto, Data, Range, Reader};
+use extendr_api::prelude::*;
+use extendr_api::Result;
+
+#[cfg(feature = "xlsx")]
+use crate::attrib::{set_compact_row_names, AttribExt};
+use crate::r_module;
+use crate::xlen::robj_to_length;
+
+/// The worksheet to read, by position from 1 or by name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sheet {
+    Index(usize),
+    Name(String),
+}
+
+/// A rectangle of cells, as zero-based `(row, column)` corners, both
+/// included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellRange {
+    pub start: (u32, u32),
+    pub end: (u32, u32),
+}
+
+impl std::str::FromStr for CellRange {
+    type Err = Error;
+
+    /// Parse a range in A1 notation, like `"B2:D10"`.
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::Other(format!("invalid cell range \"{s}\""));
+        let (start, end) = s.split_once(':').ok_or_else(invalid)?;
+        let start = parse_cell(start).ok_or_else(invalid)?;
+        let end = parse_cell(end).ok_or_else(invalid)?;
+        if start.0 > end.0 || start.1 > end.1 {
+            return Err(invalid());
+        }
+        Ok(Self { start, end })
+    }
+}
+
+/// The zero-based position of a cell like `"AB12"`, with optional `$`.
+fn parse_cell(cell: &str) -> Option<(u32, u32)> {
+    let cell = cell.trim().to_ascii_uppercase().replace('$', "");
+    let split = cell.find(|c: char| c.is_ascii_digit())?;
+    let (letters, digits) = cell.split_at(split);
+    if letters.is_empty() || !letters.bytes().all(|b| b.is_ascii_uppercase()) {
+        return None;
+    }
+    let column = letters.bytes().try_fold(0u32, |n, b| {
+        n.checked_mul(26)?.checked_add(u32::from(b - b'A') + 1)
+    })?;
+    let row: u32 = digits.parse().ok()?;
+    Some((row.checked_sub(1)?, column - 1))
+}
+
+/// The type of a column.
+#[cfg(feature = "xlsx")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    Logical,
+    Double,
+    Date,
+    DateTime,
+    Character,
+}
+
+/// The kind of a cell, `None` for `NA`.
+#[cfg(feature = "xlsx")]
+fn kind(cell: &Data) -> Option<Kind> {
+    match cell {
+        Data::Empty | Data::Error(_) => None,
+        Data::String(s) if s.is_empty() => None,
+        Data::Bool(_) => Some(Kind::Logical),
+        Data::Int(_) | Data::Float(_) => Some(Kind::Double),
+        Data::DateTime(dt) if dt.is_datetime() => {
+            if dt.as_f64().fract() == 0.0 {
+                Some(Kind::Date)
+            } else {
+                Some(Kind::DateTime)
+            }
+        }
+        Data::DateTime(_) => Some(Kind::Double),
+        Data::String(_) | Data::DateTimeIso(_) | Data::DurationIso(_) => Some(Kind::Character),
+    }
+}
+
+/// The kind of a column of cells: the kind of all of them, `POSIXct` for
+/// dates with and without times, and character for any other mix.
+#[cfg(feature = "xlsx")]
+fn column_kind(cells: &[&Data]) -> Kind {
+    let kinds: BTreeSet<Kind> = cells.iter().filter_map(|cell| kind(cell)).collect();
+    let kinds: Vec<Kind> = kinds.into_iter().collect();
+    match kinds.as_slice() {
+        [] => Kind::Logical,
+        [kind] => *kind,
+        [Kind::Date, Kind::DateTime] => Kind::DateTime,
+        _ => Kind::Character,
+    }
+}
+
+/// Days between the Excel epoch and the Unix epoch.
+#[cfg(feature = "xlsx")]
+const EXCEL_EPOCH_OFFSET: f64 = 25569.0;
+
+/// The Excel serial of a number or date cell.
+#[cfg(feature = "xlsx")]
+fn serial(cell: &Data) -> Option<f64> {
+    match cell {
+        Data::Int(i) => Some(*i as f64),
+        Data::Float(f) => Some(*f),
+        Data::DateTime(dt) => Some(dt.as_f64()),
+        _ => None,
+    }
+}
+
+/// Seconds since the Unix epoch, rounded to milliseconds, which is as
+/// precise as Excel stores times.
+#[cfg(feature = "xlsx")]
+fn posix_seconds(serial: f64) -> f64 {
+    ((serial - EXCEL_EPOCH_OFFSET) * 86_400_000.0).round() / 1000.0
+}
+
+/// A cell as text, for columns of mixed types.
+#[cfg(feature = "xlsx")]
+fn text(cell: &Data) -> Option<String> {
+    match (cell, kind(cell)?) {
+        (Data::Bool(b), _) => Some(if *b { "TRUE" } else { "FALSE" }.to_string()),
+        (Data::DateTime(dt), Kind::Date) => Some(format_date(dt.as_f64())),
+        (Data::DateTime(dt), Kind::DateTime) => {
+            let seconds = posix_seconds(dt.as_f64());
+            let days = (seconds / 86_400.0).floor();
+            let time = (seconds - days * 86_400.0) as u32;
+            Some(format!(
+                "{} {:02}:{:02}:{:02}",
+                format_date(days + EXCEL_EPOCH_OFFSET),
+                time / 3600,
+                time / 60 % 60,
+                time % 60
+            ))
+        }
+        (cell, Kind::Double) => serial(cell).map(|x| x.to_string()),
+        (Data::String(s) | Data::DateTimeIso(s) | Data::DurationIso(s), _) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+/// The ISO 8601 date of a whole Excel serial.
+#[cfg(feature = "xlsx")]
+fn format_date(serial: f64) -> String {
+    // Days since 1970-01-01 to a civil date, after Howard Hinnant.
+    let z = (serial - EXCEL_EPOCH_OFFSET) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// A column of cells as an R vector of its kind.
+#[cfg(feature = "xlsx")]
+fn column(cells: &[&Data]) -> Result<Robj> {
+    let target = column_kind(cells);
+    Ok(match target {
+        Kind::Logical => Logicals::from_values(cells.iter().map(|cell| match cell {
+            Data::Bool(b) => Rbool::from(*b),
+            _ => Rbool::na(),
+        }))
+        .into(),
+        Kind::Double => Doubles::from_values(cells.iter().map(|cell| {
+            serial(cell)
+                .filter(|_| kind(cell).is_some())
+                .map_or(Rfloat::na(), Rfloat::from)
+        }))
+        .into(),
+        Kind::Date | Kind::DateTime => {
+            let mut column: Robj =
+                Doubles::from_values(cells.iter().map(|cell| match (kind(cell), serial(cell)) {
+                    (Some(_), Some(x)) if target == Kind::Date => {
+                        Rfloat::from(x - EXCEL_EPOCH_OFFSET)
+                    }
+                    (Some(_), Some(x)) => Rfloat::from(posix_seconds(x)),
+                    _ => Rfloat::na(),
+                }))
+                .into();
+            if target == Kind::Date {
+                column.set_attr("class", "Date")?;
+            } else {
+                column.set_attr("class", ["POSIXct", "POSIXt"])?;
+                column.set_attr("tzone", "UTC")?;
+            }
+            column
+        }
+        Kind::Character => Strings::from_values(cells.iter().map(|cell| match text(cell) {
+            Some(s) => Rstr::from(s),
+            None => Rstr::na(),
+        }))
+        .into(),
+    })
+}
+
+/// The cells of `range` of a worksheet of the workbook at `path`, or all
+/// the cells in use.
+#[cfg(feature = "xlsx")]
+pub fn read_range(path: &Path, sheet: &Sheet, range: Option<CellRange>) -> Result<Range<Data>> {
+    let xlsx_error = |e: calamine::Error| Error::Other(format!("{}: {e}", path.display()));
+    let mut workbook = open_workbook_auto(path).map_err(xlsx_error)?;
+    let names = workbook.sheet_names();
+    let name = match sheet {
+        Sheet::Name(name) => name.clone(),
+        Sheet::Index(i) => names.get(i.wrapping_sub(1)).cloned().ok_or_else(|| {
+            Error::Other(format!(
+                "sheet {i} does not exist; the workbook has {} sheets",
+                names.len()
+            ))
+        })?,
+    };
+    let cells = workbook.worksheet_range(&name).map_err(xlsx_error)?;
+    Ok(match range {
+        Some(range) => cells.range(range.start, range.end),
+        None => cells,
+    })
+}
+
+/// A worksheet as a data frame, the first row of the cells giving the
+/// column names if `col_names` is true. Unnamed columns are called `...1`,
+/// `...2` and so on by position.
+#[cfg(feature = "xlsx")]
+pub fn read_sheet(
+    path: &Path,
+    sheet: &Sheet,
+    range: Option<CellRange>,
+    col_names: bool,
+) -> Result<Robj> {
+    let cells = read_range(path, sheet, range)?;
+    let rows: Vec<&[Data]> = cells.rows().collect();
+    let (header, body) = match rows.split_first() {
+        Some((header, body)) if col_names => (Some(*header), body),
+        _ => (None, rows.as_slice()),
+    };
+    let width = cells.width();
+    let names: Vec<String> = (0..width)
+        .map(|j| {
+            header
+                .and_then(|header| text(&header[j]))
+                .unwrap_or_else(|| format!("...{}", j + 1))
+        })
+        .collect();
+    let columns = (0..width)
+        .map(|j| column(&body.iter().map(|row| &row[j]).collect::<Vec<_>>()))
+        .collect::<Result<Vec<_>>>()?;
+    let mut df: Robj = List::from_names_and_values(names, columns)?.into();
+    set_compact_row_names(&mut df, body.len())?;
+    df.set_attr("class", "data.frame")?;
+    Ok(df)
+}
+
+#[cfg(not(feature = "xlsx"))]
+pub fn read_sheet(
+    _path: &Path,
+    _sheet: &Sheet,
+    _range: Option<CellRange>,
+    _col_names: bool,
+) -> Result<Robj> {
+    Err(crate::missing_feature("xlsx"))
+}
+
+/// Read an Excel worksheet
+///
+/// `read_xlsx()` reads a worksheet of an `.xlsx`, `.xlsm`, `.xls` or `.ods`
+/// file into a data frame. Each column gets the type all its cells share:
+/// logical, double, `Date` for dates, `POSIXct` in UTC for dates with
+/// times, or character when the types are mixed. Blank cells, empty
+/// strings and errors such as `#N/A` are `NA`.
+/// @param path The path of the workbook.
+/// @param sheet The position or name of the worksheet.
+/// @param range `NULL` for the cells in use, or a range like `"B2:D10"`.
+/// @param col_names Whether the first row holds the column names.
+/// @return A data frame.
+/// @export
+#[extendr]
+fn read_xlsx(
+    path: &str,
+    #[extendr(default = "1L")] sheet: Robj,
+    #[extendr(default = "NULL")] range: Robj,
+    #[extendr(default = "TRUE")] col_names: bool,
+) -> Result<Robj> {
+    let sheet = match sheet.as_str() {
+        Some(name) => Sheet::Name(name.to_string()),
+        None => Sheet::Index(robj_to_length(&sheet)?),
+    };
+    let range = match range.as_str() {
+        Some(range) => Some(range.parse()?),
+        None if range.is_null() => None,
+        None => return Err(Error::Other("`range` must be NULL or a string".into())),
+    };
+    read_sheet(Path::new(path), &sheet, range, col_names)
+}
+
+r_module! {
+    mod xlsx;
+    fn read_xlsx;
+}
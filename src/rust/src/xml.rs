@@ -0,0 +1,467 @@
+//! XML and HTML documents as R list trees, with XPath and CSS queries.
+//!
+//! Available with the `xml` feature. [`parse_xml()`] reads XML with
+//! `quick-xml` and [`parse_html()`] reads HTML, however malformed, with the
+//! HTML5 parser of `scraper`; both produce the same [`Node`] tree, which
+//! [`Node::to_robj()`] turns into the nested lists of `xml2::as_list()`:
+//! an element is a list of its children, named by element name, with its
+//! attributes as R attributes, and a text node is a string.
+//!
+//! [`Node::xpath()`] evaluates a subset of XPath 1.0 that covers most
+//! scraping: absolute location paths of child (`/`) and descendant (`//`)
+//! steps, name tests, `*`, `text()` and `@name` or `@*` as the last step,
+//! and the predicates `[n]`, `[@name]` and `[@name='value']`. Functions,
+//! other axes and operators are not supported. [`css_select()`] runs CSS
+//! selectors on HTML. Both return strings: the text of the elements found,
+//! or the values of the attributes.
+
+use std::collections::HashSet;
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use scraper::{ElementRef, Html, Selector};
+
+use crate::attrib::AttribExt;
+
+/// An element or a text node. A parsed document is an element without a
+/// name whose children are the top-level nodes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Node {
+    Element {
+        name: String,
+        attributes: Vec<(String, String)>,
+        children: Vec<Node>,
+    },
+    Text(String),
+}
+
+fn xml_error(e: impl std::fmt::Display) -> Error {
+    Error::Other(format!("invalid XML: {e}"))
+}
+
+/// Parse an XML document. Comments, processing instructions and text that
+/// is only whitespace are left out, and CDATA sections are text.
+pub fn parse_xml(text: &str) -> Result<Node> {
+    let mut reader = Reader::from_str(text);
+    reader.config_mut().trim_text(true);
+    // The elements being read, the document first.
+    let mut open = vec![Node::document(Vec::new())];
+    loop {
+        match reader.read_event().map_err(xml_error)? {
+            Event::Start(start) => {
+                open.push(element(&start)?);
+            }
+            Event::Empty(start) => {
+                let node = element(&start)?;
+                push_child(&mut open, node);
+            }
+            Event::End(_) => {
+                let node = open.pop().filter(|_| !open.is_empty());
+                let node = node.ok_or_else(|| xml_error("unexpected end tag"))?;
+                push_child(&mut open, node);
+            }
+            Event::Text(text) => {
+                let text = text.unescape().map_err(xml_error)?.into_owned();
+                push_child(&mut open, Node::Text(text));
+            }
+            Event::CData(data) => {
+                let text = String::from_utf8(data.into_inner().into_owned()).map_err(xml_error)?;
+                push_child(&mut open, Node::Text(text));
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    match (open.pop(), open.is_empty()) {
+        (Some(document), true) => Ok(document),
+        _ => Err(xml_error("unclosed element at the end of the document")),
+    }
+}
+
+fn element(start: &quick_xml::events::BytesStart<'_>) -> Result<Node> {
+    let name = String::from_utf8_lossy(start.name().as_ref()).into_owned();
+    let attributes = start
+        .attributes()
+        .map(|attribute| {
+            let attribute = attribute.map_err(xml_error)?;
+            let key = String::from_utf8_lossy(attribute.key.as_ref()).into_owned();
+            let value = attribute.unescape_value().map_err(xml_error)?.into_owned();
+            Ok((key, value))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Node::Element {
+        name,
+        attributes,
+        children: Vec::new(),
+    })
+}
+
+fn push_child(open: &mut [Node], node: Node) {
+    if let Some(Node::Element { children, .. }) = open.last_mut() {
+        children.push(node);
+    }
+}
+
+/// Parse an HTML document as a browser would, adding the `html`, `head`
+/// and `body` elements it omits.
+pub fn parse_html(text: &str) -> Node {
+    let html = Html::parse_document(text);
+    Node::document(vec![from_scraper(html.root_element())])
+}
+
+fn from_scraper(element: ElementRef<'_>) -> Node {
+    let children = element
+        .children()
+        .filter_map(|child| match child.value() {
+            scraper::Node::Element(_) => ElementRef::wrap(child).map(from_scraper),
+            scraper::Node::Text(text) if !text.trim().is_empty() => {
+                Some(Node::Text(text.to_string()))
+            }
+            _ => None,
+        })
+        .collect();
+    Node::Element {
+        name: element.value().name().to_string(),
+        attributes: element
+            .value()
+            .attrs()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+        children,
+    }
+}
+
+/// The text of the elements of `html` that match the CSS `selector`, or
+/// the values of their attribute `attribute`, skipping elements without it.
+pub fn css_select(html: &str, selector: &str, attribute: Option<&str>) -> Result<Vec<String>> {
+    let selector = Selector::parse(selector)
+        .map_err(|e| Error::Other(format!("invalid CSS selector: {e:?}")))?;
+    let html = Html::parse_document(html);
+    let found = html.select(&selector);
+    Ok(match attribute {
+        Some(attribute) => found
+            .filter_map(|element| element.value().attr(attribute))
+            .map(str::to_string)
+            .collect(),
+        None => found.map(|element| element.text().collect()).collect(),
+    })
+}
+
+/// R attributes an XML attribute must not overwrite; they are prefixed
+/// with a dot.
+const RESERVED: &[&str] = &[
+    "names",
+    "class",
+    "dim",
+    "dimnames",
+    "row.names",
+    "tsp",
+    "levels",
+];
+
+impl Node {
+    pub fn document(children: Vec<Node>) -> Self {
+        Node::Element {
+            name: String::new(),
+            attributes: Vec::new(),
+            children,
+        }
+    }
+
+    /// The concatenated text of the node and its descendants.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        self.push_text(&mut text);
+        text
+    }
+
+    fn push_text(&self, text: &mut String) {
+        match self {
+            Node::Text(s) => text.push_str(s),
+            Node::Element { children, .. } => {
+                for child in children {
+                    child.push_text(text);
+                }
+            }
+        }
+    }
+
+    /// The node as `xml2::as_list()` converts it.
+    pub fn to_robj(&self) -> Result<Robj> {
+        match self {
+            Node::Text(s) => Ok(r!(s.as_str())),
+            Node::Element {
+                attributes,
+                children,
+                ..
+            } => {
+                let names = children.iter().map(|child| match child {
+                    Node::Element { name, .. } => name.as_str(),
+                    Node::Text(_) => "",
+                });
+                let values = children
+                    .iter()
+                    .map(Node::to_robj)
+                    .collect::<Result<Vec<_>>>()?;
+                let mut list: Robj = List::from_names_and_values(names, values)?.into();
+                for (key, value) in attributes {
+                    if RESERVED.contains(&key.as_str()) {
+                        list.set_attr(&format!(".{key}"), value.as_str())?;
+                    } else {
+                        list.set_attr(key, value.as_str())?;
+                    }
+                }
+                Ok(list)
+            }
+        }
+    }
+
+    fn children(&self) -> &[Node] {
+        match self {
+            Node::Element { children, .. } => children,
+            Node::Text(_) => &[],
+        }
+    }
+
+    fn attribute(&self, key: &str) -> Option<&str> {
+        match self {
+            Node::Element { attributes, .. } => attributes
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str()),
+            Node::Text(_) => None,
+        }
+    }
+
+    /// The strings selected by the XPath `path`, evaluated from this node
+    /// as the document: the text of elements, or text nodes and attribute
+    /// values.
+    pub fn xpath(&self, path: &str) -> Result<Vec<String>> {
+        let steps = parse_xpath(path)?;
+        let mut context: Vec<&Node> = vec![self];
+        for (i, step) in steps.iter().enumerate() {
+            let last = i == steps.len() - 1;
+            let from = if step.descendant {
+                with_descendants(&context)
+            } else {
+                context.clone()
+            };
+            match &step.test {
+                Test::Text | Test::Attribute(_) if !last => {
+                    return Err(xpath_error(path, "text() and @ must be the last step"));
+                }
+                Test::Text => {
+                    return Ok(from
+                        .iter()
+                        .flat_map(|node| node.children())
+                        .filter_map(|child| match child {
+                            Node::Text(s) => Some(s.clone()),
+                            Node::Element { .. } => None,
+                        })
+                        .collect());
+                }
+                Test::Attribute(key) => {
+                    return Ok(from
+                        .iter()
+                        .flat_map(|node| match node {
+                            Node::Element { attributes, .. } => attributes.as_slice(),
+                            Node::Text(_) => &[],
+                        })
+                        .filter(|(k, _)| key == "*" || k == key)
+                        .map(|(_, v)| v.clone())
+                        .collect());
+                }
+                Test::Name(_) | Test::Any => {}
+            }
+            let mut seen = HashSet::new();
+            let mut next = Vec::new();
+            for node in from {
+                let mut matched: Vec<&Node> = node
+                    .children()
+                    .iter()
+                    .filter(|child| step.test.matches(child))
+                    .collect();
+                for predicate in &step.predicates {
+                    matched = predicate.filter(matched);
+                }
+                for node in matched {
+                    if seen.insert(node as *const Node) {
+                        next.push(node);
+                    }
+                }
+            }
+            context = next;
+        }
+        Ok(context.iter().map(|node| node.text()).collect())
+    }
+}
+
+/// The nodes and all their descendant elements, in document order.
+fn with_descendants<'a>(nodes: &[&'a Node]) -> Vec<&'a Node> {
+    fn walk<'a>(node: &'a Node, out: &mut Vec<&'a Node>) {
+        out.push(node);
+        for child in node.children() {
+            if let Node::Element { .. } = child {
+                walk(child, out);
+            }
+        }
+    }
+    let mut out = Vec::new();
+    for node in nodes {
+        walk(node, &mut out);
+    }
+    out
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Step {
+    /// `//` rather than `/`.
+    descendant: bool,
+    test: Test,
+    predicates: Vec<Predicate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Test {
+    Name(String),
+    Any,
+    Text,
+    Attribute(String),
+}
+
+impl Test {
+    fn matches(&self, node: &Node) -> bool {
+        match (self, node) {
+            (Test::Any, Node::Element { .. }) => true,
+            (Test::Name(test), Node::Element { name, .. }) => test == name,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Predicate {
+    /// The one-based position among the matches of the step.
+    Position(usize),
+    HasAttribute(String),
+    AttributeEquals(String, String),
+}
+
+impl Predicate {
+    fn filter<'a>(&self, nodes: Vec<&'a Node>) -> Vec<&'a Node> {
+        match self {
+            Predicate::Position(n) => nodes.get(n - 1).copied().into_iter().collect(),
+            Predicate::HasAttribute(key) => nodes
+                .into_iter()
+                .filter(|node| node.attribute(key).is_some())
+                .collect(),
+            Predicate::AttributeEquals(key, value) => nodes
+                .into_iter()
+                .filter(|node| node.attribute(key) == Some(value))
+                .collect(),
+        }
+    }
+}
+
+fn xpath_error(path: &str, problem: &str) -> Error {
+    Error::Other(format!("unsupported XPath \"{path}\": {problem}"))
+}
+
+fn parse_xpath(path: &str) -> Result<Vec<Step>> {
+    if !path.starts_with('/') {
+        return Err(xpath_error(path, "only absolute paths are supported"));
+    }
+    let mut steps = Vec::new();
+    let mut rest = path;
+    while !rest.is_empty() {
+        let descendant = rest.starts_with("//");
+        rest = rest.trim_start_matches('/');
+        // The step ends at the next `/` outside brackets and quotes.
+        let mut depth = 0;
+        let mut quote = None;
+        let end = rest
+            .char_indices()
+            .find(|&(_, c)| {
+                match (quote, c) {
+                    (Some(q), c) if c == q => quote = None,
+                    (Some(_), _) => {}
+                    (None, '\'' | '"') => quote = Some(c),
+                    (None, '[') => depth += 1,
+                    (None, ']') => depth -= 1,
+                    (None, '/') if depth == 0 => return true,
+                    _ => {}
+                }
+                false
+            })
+            .map_or(rest.len(), |(i, _)| i);
+        steps.push(parse_step(path, &rest[..end], descendant)?);
+        rest = &rest[end..];
+    }
+    if steps.is_empty() {
+        return Err(xpath_error(path, "no steps"));
+    }
+    Ok(steps)
+}
+
+fn parse_step(path: &str, step: &str, descendant: bool) -> Result<Step> {
+    let (test, mut predicates) = step.split_at(step.find('[').unwrap_or(step.len()));
+    let test = match test.trim() {
+        "" => return Err(xpath_error(path, "empty step")),
+        "*" => Test::Any,
+        "text()" => Test::Text,
+        t if t.starts_with('@') => Test::Attribute(t[1..].to_string()),
+        t if t.chars().all(|c| c.is_alphanumeric() || "_-.:".contains(c)) => {
+            Test::Name(t.to_string())
+        }
+        t => return Err(xpath_error(path, &format!("cannot parse step \"{t}\""))),
+    };
+    let mut parsed = Vec::new();
+    while let Some(inner) = predicates.strip_prefix('[') {
+        let end = inner
+            .find(']')
+            .ok_or_else(|| xpath_error(path, "unclosed predicate"))?;
+        parsed.push(parse_predicate(path, inner[..end].trim())?);
+        predicates = &inner[end + 1..];
+    }
+    if !predicates.trim().is_empty() {
+        return Err(xpath_error(path, "unexpected text after a predicate"));
+    }
+    if !parsed.is_empty() && matches!(test, Test::Text | Test::Attribute(_)) {
+        return Err(xpath_error(path, "predicates on text() and @ steps"));
+    }
+    Ok(Step {
+        descendant,
+        test,
+        predicates: parsed,
+    })
+}
+
+fn parse_predicate(path: &str, predicate: &str) -> Result<Predicate> {
+    if let Ok(n) = predicate.parse::<usize>() {
+        return if n >= 1 {
+            Ok(Predicate::Position(n))
+        } else {
+            Err(xpath_error(path, "positions start at 1"))
+        };
+    }
+    let attribute = predicate
+        .strip_prefix('@')
+        .ok_or_else(|| xpath_error(path, &format!("cannot parse predicate [{predicate}]")))?;
+    match attribute.split_once('=') {
+        None => Ok(Predicate::HasAttribute(attribute.trim().to_string())),
+        Some((key, value)) => {
+            let value = value.trim();
+            let unquoted = value
+                .strip_prefix('\'')
+                .and_then(|v| v.strip_suffix('\''))
+                .or_else(|| value.strip_prefix('"').and_then(|v| v.strip_suffix('"')))
+                .ok_or_else(|| xpath_error(path, "attribute values must be quoted"))?;
+            Ok(Predicate::AttributeEquals(
+                key.trim().to_string(),
+                unquoted.to_string(),
+            ))
+        }
+    }
+}
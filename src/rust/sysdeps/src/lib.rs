@@ -0,0 +1,453 @@
+//! Find the native libraries a package's Rust code links, from build scripts.
+//!
+//! Hybrid packages, whose Rust code links libraries such as zstd or GDAL,
+//! describe each library with a [`Library`] and probe for it in
+//! `build.rs`:
+//!
+//! ```no_run
+//! use helloextendr_sysdeps::Library;
+//!
+//! let zstd = Library::new("zstd")
+//!     .pkg_config("libzstd")
+//!     .atleast_version("1.4")
+//!     .system_package("deb", "libzstd-dev")
+//!     .system_package("rpm", "libzstd-devel")
+//!     .system_package("brew", "zstd")
+//!     .probe_or_exit();
+//! zstd.emit();
+//! helloextendr_sysdeps::write_link_flags(&[zstd]).unwrap();
+//! ```
+//!
+//! A library is looked up, in order, in the directories given by the
+//! environment variables `<NAME>_LIB_DIR` and `<NAME>_INCLUDE_DIR`, through
+//! pkg-config, through vcpkg when building for Windows and in the active
+//! conda environment. When all of them fail, the error lists what each one
+//! reported and how to install the library, so that the problem shows up at
+//! configure time rather than as an undefined symbol when the package is
+//! loaded.
+//!
+//! Cargo passes the libraries found to rustc, but an R package links its
+//! Rust static library itself, from `Makevars`. [`write_link_flags()`]
+//! therefore also writes the linker flags to `native-libs.txt` next to the
+//! static library, for `Makevars` to add to `PKG_LIBS` once cargo is done:
+//!
+//! ```make
+//! PKG_LIBS = -L$(LIBDIR) -lmypackage $(shell cat $(LIBDIR)/native-libs.txt)
+//! ```
+
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+type Probe = fn(&Library) -> Result<Found, String>;
+
+/// A native library to find.
+#[derive(Debug, Clone)]
+pub struct Library {
+    name: String,
+    pkg_config: Option<String>,
+    vcpkg: Option<String>,
+    libs: Vec<String>,
+    min_version: Option<String>,
+    statik: Option<bool>,
+    system_packages: Vec<(String, String)>,
+}
+
+/// Where a library was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// The `<NAME>_LIB_DIR` and `<NAME>_INCLUDE_DIR` environment variables.
+    Env,
+    PkgConfig,
+    Vcpkg,
+    Conda,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Source::Env => "environment",
+            Source::PkgConfig => "pkg-config",
+            Source::Vcpkg => "vcpkg",
+            Source::Conda => "conda",
+        })
+    }
+}
+
+/// A library that was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Found {
+    pub name: String,
+    pub source: Source,
+    /// The version, when the source knows it.
+    pub version: Option<String>,
+    pub include_paths: Vec<PathBuf>,
+    pub link_paths: Vec<PathBuf>,
+    /// The libraries to link, without `lib` prefix or extension.
+    pub libs: Vec<String>,
+    pub statik: bool,
+}
+
+/// A library that could not be found, with what each source reported.
+#[derive(Debug, Clone)]
+pub struct ProbeError {
+    name: String,
+    system_packages: Vec<(String, String)>,
+    attempts: Vec<(Source, String)>,
+}
+
+impl Library {
+    /// The library `name`, which is also its name for pkg-config and vcpkg
+    /// and the library linked unless set otherwise.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            pkg_config: None,
+            vcpkg: None,
+            libs: Vec::new(),
+            min_version: None,
+            statik: None,
+            system_packages: Vec::new(),
+        }
+    }
+
+    /// The name of its pkg-config module, such as `libzstd`.
+    pub fn pkg_config(mut self, module: &str) -> Self {
+        self.pkg_config = Some(module.to_string());
+        self
+    }
+
+    /// The name of its vcpkg port.
+    pub fn vcpkg(mut self, port: &str) -> Self {
+        self.vcpkg = Some(port.to_string());
+        self
+    }
+
+    /// A library to link when it is found through the environment variables
+    /// or conda, which do not list them. May be given several times.
+    pub fn lib(mut self, lib: &str) -> Self {
+        self.libs.push(lib.to_string());
+        self
+    }
+
+    /// The oldest acceptable version. Only pkg-config checks it.
+    pub fn atleast_version(mut self, version: &str) -> Self {
+        self.min_version = Some(version.to_string());
+        self
+    }
+
+    /// Link it statically. By default it is linked statically if
+    /// `<NAME>_STATIC` is set, and dynamically otherwise.
+    pub fn statik(mut self, statik: bool) -> Self {
+        self.statik = Some(statik);
+        self
+    }
+
+    /// The package providing it for a package `manager` such as `deb`,
+    /// `rpm`, `brew` or `pacman`, named in the error if it is not found.
+    pub fn system_package(mut self, manager: &str, package: &str) -> Self {
+        self.system_packages
+            .push((manager.to_string(), package.to_string()));
+        self
+    }
+
+    fn env_prefix(&self) -> String {
+        env_prefix(&self.name)
+    }
+
+    fn env_var(&self, suffix: &str) -> Option<String> {
+        let name = format!("{}_{suffix}", self.env_prefix());
+        println!("cargo:rerun-if-env-changed={name}");
+        env::var(name).ok().filter(|value| !value.is_empty())
+    }
+
+    fn is_static(&self) -> bool {
+        self.statik
+            .unwrap_or_else(|| self.env_var("STATIC").is_some_and(|v| v != "0"))
+    }
+
+    fn link_names(&self) -> Vec<String> {
+        if self.libs.is_empty() {
+            vec![self.name.clone()]
+        } else {
+            self.libs.clone()
+        }
+    }
+
+    /// Look for the library in each source in turn.
+    pub fn probe(&self) -> Result<Found, ProbeError> {
+        let mut attempts = Vec::new();
+        let probes: [(Source, Probe); 4] = [
+            (Source::Env, Self::probe_env),
+            (Source::PkgConfig, Self::probe_pkg_config),
+            (Source::Vcpkg, Self::probe_vcpkg),
+            (Source::Conda, Self::probe_conda),
+        ];
+        for (source, probe) in probes.iter() {
+            match probe(self) {
+                Ok(found) => return Ok(found),
+                Err(message) => attempts.push((*source, message)),
+            }
+        }
+        Err(ProbeError {
+            name: self.name.clone(),
+            system_packages: self.system_packages.clone(),
+            attempts,
+        })
+    }
+
+    /// [`probe()`](Self::probe), exiting the build script with the error if
+    /// the library is not found.
+    pub fn probe_or_exit(&self) -> Found {
+        self.probe().unwrap_or_else(|e| {
+            eprintln!("error: {e}");
+            std::process::exit(1)
+        })
+    }
+
+    fn probe_env(&self) -> Result<Found, String> {
+        let prefix = self.env_prefix();
+        let lib_dir = self
+            .env_var("LIB_DIR")
+            .ok_or_else(|| format!("{prefix}_LIB_DIR is not set"))?;
+        let lib_dir = PathBuf::from(lib_dir);
+        if !lib_dir.is_dir() {
+            return Err(format!(
+                "{prefix}_LIB_DIR is not a directory: {}",
+                lib_dir.display()
+            ));
+        }
+        Ok(Found {
+            name: self.name.clone(),
+            source: Source::Env,
+            version: None,
+            include_paths: self
+                .env_var("INCLUDE_DIR")
+                .map(PathBuf::from)
+                .into_iter()
+                .collect(),
+            link_paths: vec![lib_dir],
+            libs: self.link_names(),
+            statik: self.is_static(),
+        })
+    }
+
+    fn probe_pkg_config(&self) -> Result<Found, String> {
+        let module = self.pkg_config.as_deref().unwrap_or(&self.name);
+        let statik = self.is_static();
+        let mut config = pkg_config::Config::new();
+        config
+            .cargo_metadata(false)
+            .env_metadata(true)
+            .statik(statik);
+        if let Some(version) = &self.min_version {
+            config.atleast_version(version);
+        }
+        let library = config
+            .probe(module)
+            .map_err(|e| first_line(&e.to_string()))?;
+        Ok(Found {
+            name: self.name.clone(),
+            source: Source::PkgConfig,
+            version: Some(library.version),
+            include_paths: library.include_paths,
+            link_paths: library.link_paths,
+            libs: library.libs,
+            statik,
+        })
+    }
+
+    fn probe_vcpkg(&self) -> Result<Found, String> {
+        if target_os() != "windows" {
+            return Err("only used when building for Windows".into());
+        }
+        let port = self.vcpkg.as_deref().unwrap_or(&self.name);
+        let library = vcpkg::Config::new()
+            .cargo_metadata(false)
+            .find_package(port)
+            .map_err(|e| first_line(&e.to_string()))?;
+        let libs = library
+            .found_libs
+            .iter()
+            .filter_map(|path| path.file_stem())
+            .map(|stem| {
+                let stem = stem.to_string_lossy();
+                stem.strip_prefix("lib").unwrap_or(&stem).to_string()
+            })
+            .collect();
+        Ok(Found {
+            name: self.name.clone(),
+            source: Source::Vcpkg,
+            version: None,
+            include_paths: library.include_paths,
+            link_paths: library.link_paths,
+            libs,
+            statik: library.is_static,
+        })
+    }
+
+    fn probe_conda(&self) -> Result<Found, String> {
+        println!("cargo:rerun-if-env-changed=CONDA_PREFIX");
+        let prefix = env::var_os("CONDA_PREFIX")
+            .filter(|prefix| !prefix.is_empty())
+            .ok_or("no conda environment is active")?;
+        // Conda keeps native libraries under `Library` on Windows.
+        let root = if target_os() == "windows" {
+            Path::new(&prefix).join("Library")
+        } else {
+            PathBuf::from(prefix)
+        };
+        let lib_dir = root.join("lib");
+        let statik = self.is_static();
+        let libs = self.link_names();
+        if let Some(missing) = libs.iter().find(|lib| !has_library(&lib_dir, lib, statik)) {
+            return Err(format!("{missing} is not in {}", lib_dir.display()));
+        }
+        Ok(Found {
+            name: self.name.clone(),
+            source: Source::Conda,
+            version: None,
+            include_paths: vec![root.join("include")],
+            link_paths: vec![lib_dir],
+            libs,
+            statik,
+        })
+    }
+}
+
+/// The prefix of the environment variables for library `name`: the name in
+/// upper case with `-` replaced by `_`.
+fn env_prefix(name: &str) -> String {
+    name.to_ascii_uppercase().replace('-', "_")
+}
+
+fn target_os() -> String {
+    env::var("CARGO_CFG_TARGET_OS").unwrap_or_else(|_| env::consts::OS.to_string())
+}
+
+fn first_line(message: &str) -> String {
+    message.lines().next().unwrap_or("").trim().to_string()
+}
+
+/// Whether `dir` has a file for linking `lib`, statically or otherwise.
+fn has_library(dir: &Path, lib: &str, statik: bool) -> bool {
+    let mut names = vec![format!("lib{lib}.a"), format!("{lib}.lib")];
+    if !statik {
+        names.push(format!("lib{lib}.so"));
+        names.push(format!("lib{lib}.dylib"));
+        names.push(format!("lib{lib}.dll.a"));
+    }
+    names.iter().any(|name| dir.join(name).is_file())
+}
+
+impl Found {
+    /// Tell cargo where the library is and to link it, and make its include
+    /// directories available to the build scripts of dependent crates as
+    /// `DEP_<LINKS>_INCLUDE`, for crates with a `links` key.
+    pub fn emit(&self) {
+        for path in &self.link_paths {
+            println!("cargo:rustc-link-search=native={}", path.display());
+        }
+        let kind = if self.statik { "static" } else { "dylib" };
+        for lib in &self.libs {
+            println!("cargo:rustc-link-lib={kind}={lib}");
+        }
+        let include = env::join_paths(&self.include_paths)
+            .map(|paths| paths.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        println!("cargo:include={include}");
+    }
+
+    /// The linker flags for the library, in the form of `PKG_LIBS`.
+    pub fn link_flags(&self) -> String {
+        let search = self
+            .link_paths
+            .iter()
+            .map(|path| format!("-L{}", path.display()));
+        let libs = self.libs.iter().map(|lib| format!("-l{lib}"));
+        search.chain(libs).collect::<Vec<_>>().join(" ")
+    }
+}
+
+/// Write the linker flags of `libraries` to `native-libs.txt` in the
+/// directory cargo puts the package's static library in, and return its
+/// path. Must be called from a build script.
+pub fn write_link_flags(libraries: &[Found]) -> io::Result<PathBuf> {
+    let out_dir = env::var_os("OUT_DIR")
+        .ok_or_else(|| io::Error::other("OUT_DIR is not set; call this from a build script"))?;
+    // OUT_DIR is `<target-dir>/<profile>/build/<package>-<hash>/out`.
+    let profile_dir = Path::new(&out_dir)
+        .ancestors()
+        .nth(3)
+        .ok_or_else(|| io::Error::other("unexpected OUT_DIR layout"))?;
+    let flags = libraries
+        .iter()
+        .map(Found::link_flags)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let path = profile_dir.join("native-libs.txt");
+    fs::write(&path, flags + "\n")?;
+    Ok(path)
+}
+
+/// The libraries the static `libR.a` of the R installation at `home` needs,
+/// from the `LIBS`, `BLAS_LIBS`, `LAPACK_LIBS` and `FLIBS` variables of its
+/// `Makeconf`.
+pub fn r_static_dependencies(home: &Path) -> Found {
+    let makeconf = fs::read_to_string(home.join("etc").join("Makeconf")).unwrap_or_default();
+    let mut libs: Vec<String> = Vec::new();
+    let mut link_paths = Vec::new();
+    for line in makeconf.lines() {
+        let value = ["LIBS", "BLAS_LIBS", "LAPACK_LIBS", "FLIBS"]
+            .iter()
+            .find_map(|name| line.strip_prefix(name)?.trim_start().strip_prefix('='));
+        // Skip values that refer to other variables, such as the R
+        // library directory, which is already searched.
+        for flag in value.unwrap_or("").split_whitespace() {
+            if flag.contains("$(") {
+                continue;
+            }
+            if let Some(lib) = flag.strip_prefix("-l") {
+                if lib != "R" && !libs.iter().any(|l| l == lib) {
+                    libs.push(lib.to_string());
+                }
+            } else if let Some(dir) = flag.strip_prefix("-L") {
+                link_paths.push(PathBuf::from(dir.trim_matches('"')));
+            }
+        }
+    }
+    Found {
+        name: "R-dependencies".into(),
+        source: Source::Env,
+        version: None,
+        include_paths: Vec::new(),
+        link_paths,
+        libs,
+        statik: false,
+    }
+}
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "cannot find the system library `{}`", self.name)?;
+        for (source, message) in &self.attempts {
+            writeln!(f, "  {source}: {message}")?;
+        }
+        if !self.system_packages.is_empty() {
+            writeln!(f, "Install it with your package manager:")?;
+            for (manager, package) in &self.system_packages {
+                writeln!(f, "  {manager}: {package}")?;
+            }
+        }
+        let prefix = env_prefix(&self.name);
+        write!(
+            f,
+            "or set {prefix}_LIB_DIR and {prefix}_INCLUDE_DIR to where it is installed."
+        )
+    }
+}
+
+impl Error for ProbeError {}
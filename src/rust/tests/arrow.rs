@@ -0,0 +1,55 @@
+//! Arrow data through {nanoarrow}. These need the package installed and
+//! only run with `cargo test --features arrow`.
+#![cfg(not(feature = "cran-strict"))]
+
+use arrow_array::{Array, Float64Array, Int32Array, RecordBatch, StringArray};
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use helloextendr::arrow::{export_array, export_record_batch, import_array, import_record_batch};
+use helloextendr::test_with_r;
+use std::sync::Arc;
+
+fn has_nanoarrow() -> Result<bool> {
+    Ok(R!("requireNamespace('nanoarrow', quietly = TRUE)")?.as_bool() == Some(true))
+}
+
+test_with_r! {
+    fn imports_data_frames() {
+        if !has_nanoarrow()? {
+            return Ok(());
+        }
+        let df = R!("data.frame(x = c(1L, NA, 3L), y = c(0.5, 1.5, NaN), z = c('a', NA, 'c'))")?;
+        let batch = import_record_batch(&df)?;
+        assert_eq!((batch.num_rows(), batch.num_columns()), (3, 3));
+        let x = batch.column(0).as_any().downcast_ref::<Int32Array>().unwrap();
+        assert_eq!(x.iter().collect::<Vec<_>>(), [Some(1), None, Some(3)]);
+        let y = batch.column(1).as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(y.value(1), 1.5);
+        assert!(y.value(2).is_nan());
+        let z = batch.column(2).as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(z.iter().collect::<Vec<_>>(), [Some("a"), None, Some("c")]);
+
+        assert!(import_record_batch(&R!("1:3")?).is_err());
+    }
+
+    fn round_trips_through_r() {
+        if !has_nanoarrow()? {
+            return Ok(());
+        }
+        let array = Int32Array::from(vec![Some(4), None, Some(6)]);
+        let exported = export_array(&array)?;
+        assert!(exported.inherits("nanoarrow_array"));
+        let back = lang!("as.vector", exported.clone()).eval()?;
+        assert_eq!(back, R!("c(4L, NA, 6L)")?);
+        assert_eq!(import_array(&exported)?.to_data(), array.to_data());
+
+        let batch = RecordBatch::try_from_iter([
+            ("n", Arc::new(Int32Array::from(vec![1, 2])) as _),
+            ("s", Arc::new(StringArray::from(vec!["p", "q"])) as _),
+        ])
+        .unwrap();
+        let df = lang!("as.data.frame", export_record_batch(batch)?).eval()?;
+        let expected = R!("data.frame(n = 1:2, s = c('p', 'q'))")?;
+        assert_eq!(lang!("identical", df, expected).eval()?, r!(true));
+    }
+}
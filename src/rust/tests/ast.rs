@@ -0,0 +1,130 @@
+//! The typed view of R expressions.
+#![cfg(not(feature = "cran-strict"))]
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use helloextendr::ast::{Arg, Expr, Visitor};
+use helloextendr::test_with_r;
+
+/// Whether `expr` builds the same language object as R parses `code` into.
+fn same_as_parsed(expr: &Expr, code: &str) -> Result<bool> {
+    let parsed = R!("str2lang({{code}})")?;
+    let same = lang!("identical", expr.to_robj()?, parsed).eval()?;
+    Ok(same.as_bool() == Some(true))
+}
+
+const CODE: &[&str] = &[
+    "x",
+    "1L",
+    "\"text\"",
+    "f()",
+    "f(x, y = 2, ...)",
+    "x[, 1]",
+    "a$b$c",
+    "f(x)(y)",
+    "if (a > 1) b else c",
+    "function(x, y = 2, ...) x + y",
+    "function() NULL",
+    "{ x <- 1; x * 2 }",
+    "`my var` + 1",
+];
+
+test_with_r! {
+    fn round_trips_r_code() {
+        for code in CODE {
+            let expr = Expr::parse_one(code)?;
+            if !matches!(expr, Expr::Function(..)) {
+                assert!(same_as_parsed(&expr, code)?, "{}", code);
+            }
+            let back = Expr::from_robj(&expr.to_robj()?)?;
+            assert_eq!(back, expr, "{}", code);
+        }
+        // Functions are rebuilt without their source reference.
+        let f = Expr::parse_one("function(x, y = 2) x + y")?.to_robj()?;
+        assert_eq!(lang!("eval", f).eval()?.call(pairlist!(1))?, r!(3.0));
+    }
+
+    fn mirrors_the_tree() {
+        let expr = Expr::parse_one("f(x, n = 2, m[, 1])")?;
+        assert_eq!(
+            expr,
+            Expr::Call(
+                Box::new(Expr::symbol("f")),
+                vec![
+                    Arg::positional(Expr::symbol("x")),
+                    Arg::named("n", Expr::Constant(r!(2.0))),
+                    Arg::positional(Expr::Call(
+                        Box::new(Expr::symbol("[")),
+                        vec![
+                            Arg::positional(Expr::symbol("m")),
+                            Arg::positional(Expr::Missing),
+                            Arg::positional(Expr::Constant(r!(1.0))),
+                        ]
+                    )),
+                ]
+            )
+        );
+        let function = Expr::parse_one("function(a, b = 1) a")?;
+        assert_eq!(
+            function,
+            Expr::Function(
+                vec![
+                    Arg::named("a", Expr::Missing),
+                    Arg::named("b", Expr::Constant(r!(1.0)))
+                ],
+                Box::new(Expr::symbol("a"))
+            )
+        );
+        assert!(Expr::parse_one("1; 2").is_err());
+        assert_eq!(Expr::parse("1; 2")?.len(), 2);
+    }
+
+    fn rewrites_expressions() {
+        let expr = Expr::parse_one("if (T) f(T, F)")?.transform(&mut |e| match e {
+            Expr::Symbol(s) if s == "T" => Expr::Constant(r!(true)),
+            Expr::Symbol(s) if s == "F" => Expr::Constant(r!(false)),
+            e => e,
+        });
+        assert!(same_as_parsed(&expr, "if (TRUE) f(TRUE, FALSE)")?);
+
+        let call = Expr::call("sum", [Expr::symbol("x"), Expr::Constant(r!(1))]);
+        assert!(same_as_parsed(&call, "sum(x, 1L)")?);
+        assert!(call.is_call_to("sum"));
+        assert_eq!(Expr::parse_one("f(x)(y)")?.fun_name(), None);
+    }
+
+    fn collects_calls_and_symbols() {
+        let expr = Expr::parse_one("function(x, n = length(y)) mean(x[seq_len(n)]) + z")?;
+        let calls: Vec<_> = expr.calls().into_iter().collect();
+        assert_eq!(calls, ["+", "[", "function", "length", "mean", "seq_len"]);
+        let symbols: Vec<_> = expr.symbols().into_iter().collect();
+        assert_eq!(symbols, ["n", "x", "y", "z"]);
+    }
+
+    fn walks_depth_first() {
+        struct Depths {
+            depth: usize,
+            max: usize,
+            entered: Vec<String>,
+        }
+        impl Visitor for Depths {
+            fn enter(&mut self, expr: &Expr) -> bool {
+                self.depth += 1;
+                self.max = self.max.max(self.depth);
+                if let Some(name) = expr.fun_name() {
+                    self.entered.push(name.to_string());
+                }
+                // Do not look inside `quote()`.
+                !expr.is_call_to("quote")
+            }
+            fn leave(&mut self, _expr: &Expr) {
+                self.depth -= 1;
+            }
+        }
+        let mut visitor = Depths { depth: 0, max: 0, entered: Vec::new() };
+        Expr::parse_one("f(g(h(1)), quote(k(l(m))))")?.walk(&mut visitor);
+        assert_eq!(visitor.entered, ["f", "g", "h", "quote"]);
+        assert_eq!(visitor.depth, 0);
+        assert_eq!(visitor.max, 4);
+    }
+}
@@ -0,0 +1,54 @@
+//! Attribute access by name.
+#![cfg(not(feature = "cran-strict"))]
+
+use extendr_api::prelude::*;
+use helloextendr::attrib::{set_compact_row_names, AttribExt};
+use helloextendr::test_with_r;
+
+test_with_r! {
+    fn reads_and_writes_attributes() {
+        let mut x = R!("1:4")?;
+        assert_eq!(x.attr("units"), None);
+        x.set_attr("units", "cm")?.set_attr("note", 1.5)?;
+        assert_eq!(x.attr("units"), Some(r!("cm")));
+        assert_eq!(lang!("attr", x.clone(), "note").eval()?, r!(1.5));
+        x.remove_attr("units")?;
+        assert_eq!(lang!("names", lang!("attributes", x.clone())).eval()?, r!("note"));
+        x.set_attr("note", r!(NULL))?;
+        assert!(lang!("attributes", x).eval()?.is_null());
+    }
+
+    fn reads_classes() {
+        assert_eq!(R!("factor('a')")?.class_vec(), ["factor"]);
+        assert_eq!(R!("as.POSIXct('2024-01-01', tz = 'UTC')")?.class_vec(), ["POSIXct", "POSIXt"]);
+        // The implicit class of `class()` is not an attribute.
+        assert!(R!("matrix(1:4, 2)")?.class_vec().is_empty());
+    }
+
+    fn reads_and_sets_dimensions() {
+        assert_eq!(R!("array(0, c(2, 3, 4))")?.dims()?, Some(vec![2, 3, 4]));
+        assert_eq!(R!("matrix(numeric(), 0, 5)")?.dims()?, Some(vec![0, 5]));
+        assert_eq!(R!("1:6")?.dims()?, None);
+
+        let mut x = R!("1:6")?;
+        x.set_dim(&[3, 2])?;
+        assert_eq!(x, R!("matrix(1:6, 3, 2)")?);
+        assert!(x.set_dim(&[4, 2]).is_err());
+        assert!(x.set_dim(&[1 << 40, 0]).is_err());
+    }
+
+    fn sets_levels() {
+        let mut f = R!("c(2L, 1L, 2L)")?;
+        f.set_levels(&["lo", "hi"])?.set_attr("class", "factor")?;
+        assert_eq!(f, R!("factor(c('hi', 'lo', 'hi'), levels = c('lo', 'hi'))")?);
+    }
+
+    fn sets_compact_row_names() {
+        let mut df: Robj = List::from_names_and_values(["x"], [R!("1:3")?])?.into();
+        df.set_attr("class", "data.frame")?;
+        set_compact_row_names(&mut df, 3)?;
+        assert_eq!(lang!(".row_names_info", df.clone()).eval()?, r!(-3));
+        assert_eq!(df, R!("data.frame(x = 1:3)")?);
+        assert!(set_compact_row_names(&mut df, 1 << 40).is_err());
+    }
+}
@@ -0,0 +1,68 @@
+//! Mapping R functions over lists with `ListMapExt::map_r()`.
+#![cfg(not(feature = "cran-strict"))]
+
+use extendr_api::prelude::*;
+use helloextendr::batch::{ErrorPolicy, ListMapExt};
+use helloextendr::test_with_r;
+
+/// A function failing on negative numbers that counts its calls in
+/// `environment(f)$calls`.
+const COUNTING: &str = "local({
+    calls <- 0
+    function(x) {
+        calls <<- calls + 1
+        if (x < 0) stop(\"negative: \", x)
+        x * 2
+    }
+})";
+
+fn calls(f: &Function) -> f64 {
+    lang!("get", "calls", envir = lang!("environment", f.clone()))
+        .eval()
+        .unwrap()
+        .as_real()
+        .unwrap()
+}
+
+test_with_r! {
+    fn maps_every_element_under_its_name() {
+        let x: List = eval_string("list(a = 1, b = 2, c = 3)")?.try_into()?;
+        let f: Function = eval_string(COUNTING)?.try_into()?;
+        for policy in [ErrorPolicy::FailFast, ErrorPolicy::Collect] {
+            let out = x.map_r(&f, policy)?;
+            let expected = eval_string("list(a = 2, b = 4, c = 6)")?;
+            assert_eq!(Robj::from(out), expected);
+        }
+        assert_eq!(calls(&f), 6.0);
+    }
+
+    fn fail_fast_stops_at_the_first_error() {
+        let x: List = eval_string("list(a = 1, b = -1, c = 2, d = -2)")?.try_into()?;
+        let f: Function = eval_string(COUNTING)?.try_into()?;
+        let error = x.map_r(&f, ErrorPolicy::FailFast).unwrap_err().to_string();
+        assert_eq!(error, "the function failed on element 2 (`b`): negative: -1");
+        assert_eq!(calls(&f), 2.0);
+    }
+
+    fn collect_reports_the_condition_of_every_failing_element() {
+        let x: List = eval_string("list(a = 1, b = -1, c = 2, -2)")?.try_into()?;
+        let f: Function = eval_string(COUNTING)?.try_into()?;
+        let error = x.map_r(&f, ErrorPolicy::Collect).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "the function failed on 2 of 4 elements:\n  \
+             element 2 (`b`): negative: -1\n  \
+             element 4: negative: -2"
+        );
+        assert_eq!(calls(&f), 4.0);
+    }
+
+    fn collect_counts_errors_beyond_the_first_ten() {
+        let x: List = eval_string("as.list(-(1:12))")?.try_into()?;
+        let f: Function = eval_string(COUNTING)?.try_into()?;
+        let error = x.map_r(&f, ErrorPolicy::Collect).unwrap_err().to_string();
+        assert!(error.starts_with("the function failed on 12 of 12 elements:"));
+        assert!(error.ends_with("\n  and 2 more"));
+        assert_eq!(calls(&f), 12.0);
+    }
+}
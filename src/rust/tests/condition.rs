@@ -0,0 +1,84 @@
+//! Building, signalling and catching classed R conditions.
+#![cfg(not(feature = "cran-strict"))]
+
+use extendr_api::prelude::*;
+use helloextendr::condition::{
+    catch_call, catch_conditions, signal_condition, Condition, ConditionKind,
+};
+use helloextendr::test_with_r;
+
+#[test]
+fn orders_the_classes() {
+    let cnd = Condition::warning("careful")
+        .class("deprecated")
+        .class("lifecycle");
+    assert_eq!(cnd.kind(), ConditionKind::Warning);
+    assert_eq!(
+        cnd.classes(),
+        ["deprecated", "lifecycle", "warning", "condition"]
+    );
+    assert_eq!(Condition::plain("x").classes(), ["condition"]);
+    assert_eq!(
+        Condition::message("x\n").classes(),
+        ["message", "condition"]
+    );
+}
+
+test_with_r! {
+    fn builds_condition_objects_like_r() {
+        let cnd = Condition::error("boom")
+            .class("my_error")
+            .call(R!("quote(f(1))")?)
+            .field("code", 42);
+        assert_eq!(
+            cnd.to_robj()?,
+            R!("errorCondition('boom', class = 'my_error', call = quote(f(1)), code = 42L)")?
+        );
+        assert_eq!(
+            Condition::warning("careful").to_robj()?,
+            R!("warningCondition('careful')")?
+        );
+        assert_eq!(
+            Condition::message("note\n").to_robj()?,
+            R!("structure(class = c('message', 'condition'), list(message = 'note\n', call = NULL))")?
+        );
+    }
+
+    fn catches_conditions_of_the_given_classes() {
+        let env = Environment::new_with_parent(base_env());
+        env.set_local(sym!(n), 2);
+        let ok = catch_conditions(&R!("quote(n + 1L)")?, &env, &["error"])?;
+        assert_eq!(ok.unwrap(), r!(3));
+
+        let code = R!("quote(stop(errorCondition('failed', class = 'custom', data = n)))")?;
+        let caught = catch_conditions(&code, &env, &["custom"])?.unwrap_err();
+        assert_eq!(caught.message(), "failed");
+        assert!(caught.inherits("custom") && caught.inherits("error"));
+        assert_eq!(caught.field("data"), Some(r!(2)));
+        assert_eq!(caught.field("call"), None);
+        assert_eq!(caught.classes(), ["custom", "error", "condition"]);
+
+        // Other classes are not caught.
+        assert!(catch_conditions(&code, &env, &["other"]).is_err());
+        let warned = catch_conditions(&R!("quote(warning('w'))")?, &env, &["warning"])?;
+        assert_eq!(warned.unwrap_err().message(), "w");
+        // A value of the expression is never mistaken for a condition.
+        let list = catch_conditions(&R!("quote(list(1))")?, &env, &["error"])?;
+        assert_eq!(list.unwrap(), R!("list(1)")?);
+    }
+
+    fn round_trips_conditions_through_stop() {
+        let cnd = Condition::error("boom").class("my_error").field("code", 42);
+        let stop: Function = R!("stop")?.try_into()?;
+        let caught = catch_call(&stop, pairlist!(cnd.to_robj()?), &["my_error"])?.unwrap_err();
+        assert_eq!(caught.into_robj(), cnd.to_robj()?);
+
+        let sum: Function = R!("sum")?.try_into()?;
+        assert_eq!(catch_call(&sum, pairlist!(1, 2), &["error"])?.unwrap(), r!(3));
+    }
+
+    fn signals_without_handlers() {
+        // With no handler established, a bare condition is ignored.
+        signal_condition(&Condition::plain("unheard").class("custom"))?;
+    }
+}
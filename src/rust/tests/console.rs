@@ -0,0 +1,69 @@
+//! Styled console output and the detection of what the console supports.
+#![cfg(not(feature = "cran-strict"))]
+
+use extendr_api::prelude::*;
+use helloextendr::console::{
+    console_width, is_utf8_output, num_ansi_colors, Bullet, Color, Console,
+};
+use helloextendr::test_with_r;
+
+#[test]
+fn formats_plain_output() {
+    let console = Console::plain(20);
+    assert_eq!(console.format_rule(""), "-".repeat(20));
+    assert_eq!(console.format_rule("Title"), "-- Title -----------");
+    assert_eq!(console.format_rule("Title").len(), 20);
+    // A title wider than the console gets no trailing line.
+    assert_eq!(
+        Console::plain(8).format_rule("Long title"),
+        "-- Long title "
+    );
+    assert_eq!(console.format_alert(Bullet::Success, "done"), "v done");
+    assert_eq!(console.format_alert(Bullet::Danger, "failed"), "x failed");
+    assert_eq!(console.format_alert(Bullet::Indent, "more"), "  more");
+    assert_eq!(console.paint("text", Some(Color::Red), true), "text");
+}
+
+test_with_r! {
+    fn follows_the_cli_options() {
+        R!("old <- options(cli.num_colors = 256, cli.unicode = TRUE, cli.width = 30)")?;
+        let console = Console::detect()?;
+        R!("options(old)")?;
+        assert_eq!((console.colors(), console.unicode(), console.width()), (256, true, 30));
+
+        assert_eq!(console.paint("text", Some(Color::Red), true), "\x1b[1;31mtext\x1b[0m");
+        assert_eq!(console.paint("text", None, false), "text");
+        assert_eq!(console.format_alert(Bullet::Warning, "careful"), "\x1b[33m!\x1b[0m careful");
+        assert_eq!(console.format_alert(Bullet::Arrow, "next"), "\u{2192} next");
+        let rule = console.format_rule("Title");
+        assert!(rule.contains(&"\u{2500}".repeat(21)), "{}", rule);
+    }
+
+    fn falls_back_to_the_width_option() {
+        R!("old <- options(cli.width = NULL, width = 55)")?;
+        let width = console_width();
+        R!("options(old)")?;
+        assert_eq!(width?, 55);
+    }
+
+    fn detects_like_cli() {
+        if !R!("requireNamespace('cli', quietly = TRUE)")?.as_bool().unwrap() {
+            return Ok(());
+        }
+        for options in [
+            "cli.num_colors = NULL, crayon.enabled = FALSE",
+            "cli.num_colors = NULL, crayon.enabled = TRUE, crayon.colors = 16",
+            "cli.num_colors = 8",
+        ] {
+            eval_string(&format!("old <- options({})", options))?;
+            let ours = num_ansi_colors();
+            let theirs = R!("as.numeric(cli::num_ansi_colors())");
+            R!("options(old)")?;
+            assert_eq!(theirs?.as_real(), Some(f64::from(ours?)), "{}", options);
+        }
+        R!("old <- options(cli.unicode = FALSE)")?;
+        let unicode = is_utf8_output();
+        R!("options(old)")?;
+        assert!(!unicode?);
+    }
+}
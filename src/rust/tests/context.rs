@@ -0,0 +1,57 @@
+//! Evaluation contexts: their variables, options and search path.
+#![cfg(not(feature = "cran-strict"))]
+
+use extendr_api::prelude::*;
+use helloextendr::context::EvalContext;
+use helloextendr::test_with_r;
+
+test_with_r! {
+    fn keeps_variables_apart() {
+        let a = EvalContext::new("a");
+        let b = EvalContext::new("b");
+        a.eval_string("x <- 1; y <- x + 1")?;
+        b.assign("x", 10);
+        assert_eq!(a.get("y"), Some(r!(2.0)));
+        assert_eq!(b.eval_string("x * 2")?, r!(20.0));
+        assert_eq!(b.get("y"), None);
+        // Bindings of the parents are not the context's own.
+        assert_eq!(a.get("pi"), None);
+        assert_eq!(a.eval_string("pi")?, r!(std::f64::consts::PI));
+        assert!(!R!("exists('y', envir = globalenv(), inherits = FALSE)")?.as_bool().unwrap());
+    }
+
+    fn evaluates_language_objects() {
+        let context = EvalContext::new("lang");
+        context.assign("n", 4);
+        assert_eq!(context.name(), "lang");
+        assert_eq!(context.eval(&lang!("seq_len", sym!(n)))?, R!("1:4")?);
+    }
+
+    fn sets_options_only_while_evaluating() {
+        let mut context = EvalContext::new("options");
+        context.set_option("helloextendr.test", "inside");
+        context.set_option("helloextendr.test", "replaced");
+        assert_eq!(
+            context.eval_string("getOption('helloextendr.test')")?,
+            r!("replaced")
+        );
+        assert!(R!("getOption('helloextendr.test')")?.is_null());
+
+        assert!(context.eval_string("stop('failed')").is_err());
+        assert!(R!("getOption('helloextendr.test')")?.is_null());
+    }
+
+    fn detaches_what_the_code_attached() {
+        let context = EvalContext::new("search");
+        let attached = "attach(list(ctx_value = 1), name = 'helloextendr_ctx'); search()";
+        let during = context.eval_string(attached)?;
+        assert!(during.as_string_vector().unwrap().contains(&"helloextendr_ctx".to_string()));
+        assert!(!R!("'helloextendr_ctx' %in% search()")?.as_bool().unwrap());
+
+        let mut kept = EvalContext::new("kept");
+        kept.isolate_search_path(false);
+        kept.eval_string("attach(list(ctx_value = 1), name = 'helloextendr_ctx')")?;
+        assert!(R!("'helloextendr_ctx' %in% search()")?.as_bool().unwrap());
+        R!("detach('helloextendr_ctx')")?;
+    }
+}
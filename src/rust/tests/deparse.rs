@@ -0,0 +1,99 @@
+//! Deparsing and formatting against `deparse()` and `format()`.
+#![cfg(not(feature = "cran-strict"))]
+
+use extendr_api::prelude::*;
+use helloextendr::deparse::{
+    deparse_lines, deparse_with, format_vector, DeparseOptions, FormatOptions, Justify,
+};
+use helloextendr::test_with_r;
+
+fn strings(code: &str) -> Vec<String> {
+    eval_string(code).unwrap().as_string_vector().unwrap()
+}
+
+test_with_r! {
+    fn deparses_like_r() {
+        let options = DeparseOptions::new();
+        for code in [
+            "1:3",
+            "c(a = 1.5, b = NA)",
+            "NA_integer_",
+            "list(x = 'a', y = list(TRUE, NULL))",
+            "factor(c('u', 'v'))",
+            "seq(0, 1, length.out = 40)",
+        ] {
+            let x = eval_string(code)?;
+            assert_eq!(
+                deparse_lines(&x, &options)?,
+                strings(&format!("deparse({code})")),
+                "{}",
+                code
+            );
+        }
+    }
+
+    fn passes_the_options() {
+        let x = R!("c(a = 1L, b = NA)")?;
+        let bare = DeparseOptions::new()
+            .keep_na(false)
+            .keep_integer(false)
+            .nice_names(false)
+            .show_attributes(false);
+        assert_eq!(deparse_with(&x, &bare)?, "c(1, NA)");
+        assert_eq!(
+            deparse_with(&r!(0.1), &DeparseOptions::new().digits17(true))?,
+            "0.10000000000000001"
+        );
+        assert_eq!(
+            deparse_with(&r!(1.0), &DeparseOptions::new().hex_numeric(true))?,
+            "0x1p+0"
+        );
+
+        let long = R!("seq_len(100) + 0.5")?;
+        let narrow = deparse_lines(&long, &DeparseOptions::new().width(20))?;
+        assert_eq!(narrow, strings("deparse(seq_len(100) + 0.5, width.cutoff = 20)"));
+        // Widths are clamped to what `deparse()` accepts.
+        assert_eq!(deparse_lines(&long, &DeparseOptions::new().width(1))?, narrow);
+        assert_eq!(
+            deparse_lines(&long, &DeparseOptions::new().width(20).nlines(2))?.len(),
+            2
+        );
+    }
+
+    fn leaves_language_unevaluated() {
+        let call = R!("quote(stop('not evaluated'))")?;
+        assert_eq!(deparse_with(&call, &DeparseOptions::new())?, "stop(\"not evaluated\")");
+        assert_eq!(
+            deparse_with(&call, &DeparseOptions::new().quote_expressions(true))?,
+            "quote(stop(\"not evaluated\"))"
+        );
+        let symbol = R!("as.name('my var')")?;
+        assert_eq!(deparse_with(&symbol, &DeparseOptions::new())?, "my var");
+        assert_eq!(
+            deparse_with(&symbol, &DeparseOptions::new().backtick(true))?,
+            "`my var`"
+        );
+    }
+
+    fn formats_like_r() {
+        let x = R!("c(1, 10.5, 1234567, NA)")?;
+        assert_eq!(format_vector(&x, &FormatOptions::new())?, strings("format(c(1, 10.5, 1234567, NA))"));
+        assert_eq!(
+            format_vector(&x, &FormatOptions::new().big_mark(",").nsmall(2).trim(true))?,
+            strings("format(c(1, 10.5, 1234567, NA), big.mark = ',', nsmall = 2, trim = TRUE)")
+        );
+        assert_eq!(
+            format_vector(&r!(123456.0), &FormatOptions::new().scientific(true).digits(2))?,
+            ["1.2e+05"]
+        );
+        let s = R!("c('a', 'bcd', NA)")?;
+        assert_eq!(
+            format_vector(&s, &FormatOptions::new().justify(Justify::Right).na_encode(false))?,
+            strings("format(c('a', 'bcd', NA), justify = 'right', na.encode = FALSE)")
+        );
+        assert_eq!(
+            format_vector(&s, &FormatOptions::new().width(5).justify(Justify::Centre))?,
+            strings("format(c('a', 'bcd', NA), width = 5, justify = 'centre')")
+        );
+    }
+}
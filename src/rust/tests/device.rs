@@ -0,0 +1,133 @@
+//! Graphics devices implemented in Rust.
+#![cfg(not(feature = "cran-strict"))]
+
+use extendr_api::graphics::{FontFace, LineEnd, LineJoin};
+use extendr_api::prelude::*;
+use helloextendr::device::{install_device, Device, Rgba, Style};
+use helloextendr::test_with_r;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Op {
+    NewPage,
+    Rect((f64, f64), (f64, f64), Style),
+    Text(String, Style),
+    Close,
+}
+
+/// Records what is drawn, and panics on circles.
+struct Recorder(Rc<RefCell<Vec<Op>>>);
+
+impl Device for Recorder {
+    fn new_page(&mut self, _fill: Option<Rgba>) {
+        self.0.borrow_mut().push(Op::NewPage);
+    }
+
+    fn rect(&mut self, from: (f64, f64), to: (f64, f64), style: &Style) {
+        self.0.borrow_mut().push(Op::Rect(from, to, style.clone()));
+    }
+
+    fn circle(&mut self, _center: (f64, f64), _radius: f64, _style: &Style) {
+        panic!("circles are not supported");
+    }
+
+    fn text(&mut self, _pos: (f64, f64), text: &str, _angle: f64, _hadj: f64, style: &Style) {
+        self.0
+            .borrow_mut()
+            .push(Op::Text(text.to_string(), style.clone()));
+    }
+
+    fn close(&mut self) {
+        self.0.borrow_mut().push(Op::Close);
+    }
+}
+
+fn style(line_type: i32) -> Style {
+    Style {
+        color: Some(Rgba(0xFF00_00FF)),
+        fill: None,
+        line_width: 1.0,
+        line_type,
+        line_end: LineEnd::Round,
+        line_join: LineJoin::Round,
+        line_mitre: 10.0,
+        font_size: 12.0,
+        font_face: FontFace::Plain,
+        font_family: String::new(),
+        line_height: 1.2,
+    }
+}
+
+#[test]
+fn unpacks_colours() {
+    let col = Rgba(0x8033_66CC);
+    assert_eq!(
+        (col.red(), col.green(), col.blue(), col.alpha()),
+        (0xCC, 0x66, 0x33, 0x80)
+    );
+    assert_eq!(col.to_hex(), "#CC6633");
+}
+
+#[test]
+fn decodes_line_types() {
+    assert_eq!(style(0).dash_pattern(), None);
+    assert_eq!(style(-1).dash_pattern(), None);
+    assert!(style(-1).is_blank());
+    assert!(!style(0).is_blank());
+    // "dotdash" is 0x3431: a dash of 1, a gap of 3, a dash of 4, a gap of 3.
+    assert_eq!(style(0x3431).dash_pattern(), Some(vec![1, 3, 4, 3]));
+    let mut none = style(0);
+    none.color = None;
+    assert!(none.is_blank());
+}
+
+test_with_r! {
+    fn draws_through_the_device() {
+        let ops = Rc::new(RefCell::new(Vec::new()));
+        install_device(Recorder(ops.clone()), "recorder", 4.0, 3.0);
+        R!("grid::grid.newpage()
+            grid::grid.rect(gp = grid::gpar(col = 'red', fill = NA, lty = 'dashed', lwd = 2))
+            grid::grid.text('hi', gp = grid::gpar(fontsize = 20, fontface = 'bold'))")?;
+
+        let ops = ops.borrow().clone();
+        assert_eq!(ops.first(), Some(&Op::NewPage));
+        let (from, to, rect) = ops
+            .iter()
+            .find_map(|op| match op {
+                Op::Rect(from, to, style) => Some((*from, *to, style.clone())),
+                _ => None,
+            })
+            .unwrap();
+        // The whole device, in points.
+        assert_eq!((from.0.min(to.0), from.0.max(to.0)), (0.0, 288.0));
+        assert_eq!((from.1.min(to.1), from.1.max(to.1)), (0.0, 216.0));
+        assert_eq!(rect.color.map(Rgba::to_hex).as_deref(), Some("#FF0000"));
+        assert_eq!(rect.fill, None);
+        assert_eq!(rect.line_width, 2.0);
+        assert_eq!(rect.dash_pattern(), Some(vec![4, 4]));
+
+        let text = ops
+            .iter()
+            .find_map(|op| match op {
+                Op::Text(text, style) => Some((text.clone(), style.clone())),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(text.0, "hi");
+        assert_eq!(text.1.font_size, 20.0);
+        assert_eq!(text.1.font_face, FontFace::Bold);
+        R!("dev.off()")?;
+    }
+
+    fn survives_a_panicking_callback() {
+        let ops = Rc::new(RefCell::new(Vec::new()));
+        install_device(Recorder(ops.clone()), "recorder", 2.0, 2.0);
+        R!("grid::grid.newpage()
+            grid::grid.circle()
+            grid::grid.rect()")?;
+        assert!(ops.borrow().iter().any(|op| matches!(op, Op::Rect(..))));
+        R!("dev.off()")?;
+        assert_eq!(ops.borrow().last(), Some(&Op::Close));
+    }
+}
@@ -0,0 +1,56 @@
+//! Encoding marks and UTF-8 conversion of R strings.
+#![cfg(not(feature = "cran-strict"))]
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use helloextendr::encoding::{Encoding, RstrEncoding, Utf8Strings};
+use helloextendr::test_with_r;
+
+fn first(code: &str) -> Result<Rstr> {
+    let strings: Strings = eval_string(code)?.try_into()?;
+    Ok(strings.elt(0))
+}
+
+test_with_r! {
+    fn reports_encoding_marks() {
+        assert_eq!(first("'plain'")?.encoding(), Encoding::Native);
+        assert_eq!(first("enc2utf8('caf\\u00e9')")?.encoding(), Encoding::Utf8);
+        assert_eq!(first("iconv('caf\\u00e9', 'UTF-8', 'latin1')")?.encoding(), Encoding::Latin1);
+        let bytes = "x <- 'caf\\xe9'; Encoding(x) <- 'bytes'; x";
+        assert_eq!(first(bytes)?.encoding(), Encoding::Bytes);
+    }
+
+    fn converts_to_utf8() {
+        assert_eq!(first("'plain'")?.to_utf8()?, "plain");
+        assert_eq!(first("enc2utf8('caf\\u00e9')")?.to_utf8()?, "café");
+        assert_eq!(first("iconv('caf\\u00e9', 'UTF-8', 'latin1')")?.to_utf8()?, "café");
+        assert_eq!(first("iconv('\\u00fe\\u00ff', 'UTF-8', 'latin1')")?.to_utf8()?, "þÿ");
+        assert!(first("x <- 'caf\\xe9'; Encoding(x) <- 'bytes'; x")?.to_utf8().is_err());
+        assert!(first("NA_character_")?.to_utf8().is_err());
+    }
+
+    fn reads_character_vectors() {
+        let x: Utf8Strings =
+            R!("c('a', NA, iconv('\\u00e9t\\u00e9', 'UTF-8', 'latin1'))")?.try_into()?;
+        assert_eq!(x.len(), 3);
+        assert_eq!(x.get(1)?, None);
+        assert_eq!(x.get(2)?.as_deref(), Some("été"));
+        assert!(x.get(3).is_err());
+        assert_eq!(
+            x.to_vec()?,
+            [Some("a".to_string()), None, Some("été".to_string())]
+        );
+        assert!(Utf8Strings::try_from(r!(1)).is_err());
+    }
+
+    fn marks_strings_from_rust_as_utf8() {
+        let x = Utf8Strings::from_values([Some("naïve"), None, Some("ascii")]);
+        assert!(!x.is_empty());
+        let x = Robj::from(x);
+        assert_eq!(
+            lang!("Encoding", x.clone()).eval()?,
+            R!("c('UTF-8', 'unknown', 'unknown')")?
+        );
+        assert_eq!(x, R!("c('na\\u00efve', NA, 'ascii')")?);
+    }
+}
@@ -0,0 +1,54 @@
+//! Tests of the embedded R that `test_with_r!` runs its bodies in. They
+//! need R, found through `R_HOME` or `R` on the `PATH`.
+#![cfg(not(feature = "cran-strict"))]
+
+use extendr_api::prelude::*;
+use helloextendr::engine::{with_r, REmbedOptions};
+use helloextendr::test_with_r;
+
+test_with_r! {
+    fn evaluates_r_code() {
+        assert_eq!(R!("sum(1:10)")?, r!(55));
+        let x = R!("paste0('x', 1:3)")?;
+        assert_eq!(x.as_str_vector(), Some(vec!["x1", "x2", "x3"]));
+    }
+
+    fn keeps_bindings_between_calls() {
+        R!("engine_test_value <- 42L")?;
+        assert_eq!(R!("engine_test_value")?, r!(42));
+        R!("rm(engine_test_value)")?;
+    }
+
+    fn returns_r_errors() {
+        let error = R!("stop('from R')").unwrap_err();
+        assert!(error.to_string().contains("from R"), "{}", error);
+        // The session is still usable after the error.
+        assert_eq!(R!("1 + 1")?, r!(2.0));
+    }
+
+    fn starts_non_interactive() {
+        assert_eq!(R!("interactive()")?, r!(false));
+    }
+}
+
+#[test]
+fn survives_a_panicking_body() {
+    let panicked = std::panic::catch_unwind(|| with_r(|| panic!("in the body")));
+    assert!(panicked.is_err());
+    let value = with_r(|| R!("2L * 21L").map(|x| x.as_integer()));
+    assert_eq!(value.unwrap(), Some(42));
+}
+
+#[test]
+fn builds_the_command_line() {
+    let argv = REmbedOptions::new()
+        .vanilla(true)
+        .max_ppsize(100_000)
+        .arg("--no-readline")
+        .argv();
+    assert_eq!(argv[0], "R");
+    for flag in ["--vanilla", "--slave", "--no-save", "--max-ppsize=100000"] {
+        assert!(argv.iter().any(|a| a == flag), "{} not in {:?}", flag, argv);
+    }
+    assert_eq!(argv.last().map(String::as_str), Some("--no-readline"));
+}
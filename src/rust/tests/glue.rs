@@ -0,0 +1,75 @@
+//! String interpolation of R values.
+#![cfg(not(feature = "cran-strict"))]
+
+use extendr_api::prelude::*;
+use helloextendr::glue::{parse_template, r_glue, Segment};
+use helloextendr::test_with_r;
+
+fn lit(text: &str) -> Segment {
+    Segment::Literal(text.to_string())
+}
+
+fn code(text: &str) -> Segment {
+    Segment::Code(text.to_string())
+}
+
+#[test]
+fn splits_templates() {
+    assert_eq!(
+        parse_template("a {x} b {{c}} {f(y)}").unwrap(),
+        [lit("a "), code("x"), lit(" b {c} "), code("f(y)")]
+    );
+    // Braces inside strings, comments and nested code do not end a field.
+    assert_eq!(
+        parse_template("{paste('}', \"{\")}").unwrap(),
+        [code("paste('}', \"{\")")]
+    );
+    assert_eq!(
+        parse_template("{function(x) { x } # }\n}!").unwrap(),
+        [code("function(x) { x } # }\n"), lit("!")]
+    );
+    assert_eq!(parse_template("{'a\\'}'}").unwrap(), [code("'a\\'}'")]);
+    assert_eq!(parse_template("").unwrap(), []);
+    assert!(parse_template("a } b").is_err());
+    assert!(parse_template("a {b").is_err());
+    assert!(parse_template("{'}").is_err());
+}
+
+test_with_r! {
+    fn interpolates_formatted_values() {
+        let env = Environment::new_with_parent(global_env());
+        env.set_local(sym!(x), r!([1.0, 2.0, 4.0]));
+        assert_eq!(
+            r_glue("mean is {mean(x)} of {length(x)} values", &env)?,
+            ["mean is 2.333333 of 3 values"]
+        );
+        let date = r_glue("{as.Date('2024-03-01')}", &env)?;
+        assert_eq!(date, ["2024-03-01"]);
+        assert_eq!(r_glue("{y <- 2; y * 3}", &env)?, ["6"]);
+        assert_eq!(r_glue("no fields", &env)?, ["no fields"]);
+    }
+
+    fn recycles_like_glue() {
+        let env = Environment::new_with_parent(global_env());
+        env.set_local(sym!(who), r!(["a", "b"]));
+        assert_eq!(r_glue("hi {who}{'!'}", &env)?, ["hi a!", "hi b!"]);
+        assert!(r_glue("{1:2}{1:3}", &env).is_err());
+        assert!(r_glue("x{NULL}", &env)?.is_empty());
+        assert!(r_glue("x{character()}{who}", &env)?.is_empty());
+        assert!(r_glue("{}", &env).is_err());
+        assert!(r_glue("{1 +}", &env).is_err());
+        assert!(r_glue("{stop('failed')}", &env).is_err());
+
+        if R!("requireNamespace('glue', quietly = TRUE)")?.as_bool().unwrap() {
+            for template in ["hi {who}{'!'}", "{who} and {toupper(who)}", "{{{who}}}"] {
+                let theirs = R!("as.character(glue::glue({{template}}, .envir = {{env.clone()}}))")?;
+                assert_eq!(
+                    Some(r_glue(template, &env)?),
+                    theirs.as_string_vector(),
+                    "{}",
+                    template
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! The data viewer and Viewer pane hooks of the IDEs.
+#![cfg(not(feature = "cran-strict"))]
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use helloextendr::ide::{show_in_viewer, view, view_html, Ide, Viewable};
+use helloextendr::test_with_r;
+
+struct Squares(usize);
+
+impl Viewable for Squares {
+    fn title(&self) -> String {
+        format!("{} squares", self.0)
+    }
+
+    fn to_data_frame(&self) -> Result<Robj> {
+        let n: Vec<i32> = (1..=self.0 as i32).collect();
+        let squares: Vec<i32> = n.iter().map(|i| i * i).collect();
+        lang!("data.frame", n = n, square = squares).eval()
+    }
+}
+
+#[test]
+fn detects_the_ide() {
+    let vars = ["POSITRON", "RSTUDIO", "RSTUDIO_TERM"];
+    let saved: Vec<_> = vars.iter().map(std::env::var_os).collect();
+    let set = |values: [Option<&str>; 3]| {
+        for (name, value) in vars.iter().zip(values) {
+            match value {
+                Some(value) => std::env::set_var(name, value),
+                None => std::env::remove_var(name),
+            }
+        }
+        Ide::detect()
+    };
+    assert_eq!(set([None, None, None]), Ide::Other);
+    assert_eq!(set([Some("1"), None, None]), Ide::Positron);
+    assert_eq!(set([None, Some("1"), None]), Ide::RStudio);
+    assert_eq!(set([None, Some("1"), Some("xterm")]), Ide::Other);
+    assert_eq!(set([None, Some("0"), None]), Ide::Other);
+    assert!(Ide::RStudio.is_ide() && !Ide::Other.is_ide());
+    for (name, value) in vars.iter().zip(saved) {
+        match value {
+            Some(value) => std::env::set_var(name, value),
+            None => std::env::remove_var(name),
+        }
+    }
+}
+
+test_with_r! {
+    fn views_through_the_view_function() {
+        // An IDE replaces `View()` on the search path; mask it the same way.
+        R!("View <- function(x, title) .viewed <<- list(x, title)")?;
+        view(&Squares(3))?;
+        let viewed = R!(".viewed")?;
+        R!("rm(View, .viewed)")?;
+        assert_eq!(
+            viewed,
+            R!("list(data.frame(n = 1:3, square = c(1L, 4L, 9L)), '3 squares')")?
+        );
+    }
+
+    fn shows_pages_in_the_viewer() {
+        R!("old <- options(viewer = function(url) .shown <<- url)")?;
+        let path = view_html("<p>hello</p>", "page");
+        let shown = R!(".shown");
+        R!("options(old); rm(.shown)")?;
+        let path = path?;
+        assert_eq!(shown?, r!(path.as_str()));
+        assert!(path.ends_with("/page.html"));
+        assert!(R!("startsWith({{path.as_str()}}, tempdir())")?.as_bool().unwrap());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "<p>hello</p>");
+    }
+
+    fn falls_back_to_the_browser() {
+        R!("old <- options(viewer = NULL, browser = function(url) .browsed <<- url)")?;
+        let shown = show_in_viewer("https://cran.r-project.org");
+        let browsed = R!(".browsed");
+        R!("options(old); rm(.browsed)")?;
+        shown?;
+        assert_eq!(browsed?, r!("https://cran.r-project.org"));
+    }
+}
@@ -0,0 +1,134 @@
+//! Binning against R's `findInterval()` and `cut()`.
+#![cfg(not(feature = "cran-strict"))]
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use helloextendr::interval::{cut, find_interval, BinnedExt, Breaks, Cut, FindInterval};
+use helloextendr::test_with_r;
+
+fn r_logical(x: bool) -> &'static str {
+    if x {
+        "TRUE"
+    } else {
+        "FALSE"
+    }
+}
+
+/// Whether `ours` is `identical()` to the value of the R code `theirs`.
+fn identical(ours: Robj, theirs: &str) -> Result<bool> {
+    let theirs = eval_string(theirs)?;
+    Ok(lang!("identical", ours, theirs).eval()?.as_bool() == Some(true))
+}
+
+const X: &str = "c(-Inf, -1, 0, 0.5, 1, 1.5, 2, 2.5, 3, 3.5, 4, Inf, NA, NaN)";
+
+test_with_r! {
+    fn finds_intervals_like_r() {
+        let x = eval_string(X)?.as_real_vector().unwrap_or_default();
+        for breaks in ["c(0, 1, 2, 3)", "c(1, 1, 2, 2, 3)", "2", "numeric()"] {
+            let vec = eval_string(breaks)?.as_real_vector().unwrap_or_default();
+            for bits in 0..8 {
+                let options = FindInterval {
+                    rightmost_closed: bits & 1 != 0,
+                    all_inside: bits & 2 != 0,
+                    left_open: bits & 4 != 0,
+                };
+                let ours = find_interval(&x, &vec, options)?;
+                let ours = Integers::from_values(ours.iter().map(|i| match i {
+                    Some(i) => Rint::from(*i as i32),
+                    None => Rint::na(),
+                }));
+                let call = format!(
+                    "findInterval({X}, {breaks}, rightmost.closed = {}, all.inside = {}, \
+                     left.open = {})",
+                    r_logical(options.rightmost_closed),
+                    r_logical(options.all_inside),
+                    r_logical(options.left_open)
+                );
+                assert!(identical(ours.into(), &call)?, "{}", call);
+            }
+        }
+    }
+
+    fn rejects_unsorted_breaks() {
+        let options = FindInterval::default();
+        assert!(find_interval(&[1.0], &[2.0, 1.0], options).is_err());
+        assert!(R!("findInterval(1, c(2, 1))").is_err());
+        assert!(find_interval(&[1.0], &[1.0, f64::NAN], options).is_err());
+    }
+
+    fn cuts_like_r() {
+        let samples = [
+            X,
+            "c(0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10)",
+            "c(0.001, 0.0011, 0.00111, 0.00112)",
+            "c(123456, 123457, 123458, 1e6)",
+            "c(5, 5, 5)",
+            "c(-0.5, 0, 0.5)",
+        ];
+        let breaks = [
+            "c(0, 1, 2, 3, 4)",
+            "c(4, 0, 2)",
+            "c(-Inf, 0.5, 2.5, Inf)",
+            "c(0, 0.001, 0.0011, 0.00112, 1)",
+            "3",
+            "5",
+        ];
+        for x in samples {
+            let values = eval_string(x)?.as_real_vector().unwrap_or_default();
+            for b in breaks {
+                let at = eval_string(b)?.as_real_vector().unwrap_or_default();
+                let breaks = if at.len() == 1 {
+                    Breaks::Count(at[0] as usize)
+                } else {
+                    Breaks::At(&at)
+                };
+                for (right, include_lowest) in [(true, false), (true, true), (false, false), (false, true)] {
+                    for dig_lab in [1, 3, 6] {
+                        let options = Cut {
+                            right,
+                            include_lowest,
+                            dig_lab,
+                        };
+                        let call = format!(
+                            "cut({x}, {b}, right = {}, include.lowest = {}, dig.lab = {dig_lab})",
+                            r_logical(right),
+                            r_logical(include_lowest)
+                        );
+                        let theirs = eval_string(&call);
+                        match cut(&values, breaks, &options) {
+                            Ok(binned) => {
+                                let ours = binned.to_factor(false)?;
+                                assert!(identical(ours, &call)?, "{}", call);
+                            }
+                            Err(_) => assert!(theirs.is_err(), "{}", call),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn rejects_what_cut_rejects() {
+        let options = Cut::default();
+        assert!(cut(&[1.0, 2.0], Breaks::At(&[1.0, 1.0, 2.0]), &options).is_err());
+        assert!(R!("cut(c(1, 2), c(1, 1, 2))").is_err());
+        assert!(cut(&[1.0, 2.0], Breaks::Count(1), &options).is_err());
+        assert!(R!("cut(c(1, 2), 1)").is_err());
+        assert!(cut(&[f64::NAN], Breaks::Count(3), &options).is_err());
+        assert!(R!("cut(NA_real_, 3)").is_err());
+    }
+
+    fn labels_ordered_factors() {
+        let binned = cut(&[1.0, 5.0, 9.0], Breaks::At(&[0.0, 3.0, 6.0, 9.0]), &Cut::default())?;
+        assert!(identical(
+            binned.to_factor(true)?,
+            "cut(c(1, 5, 9), c(0, 3, 6, 9), ordered_result = TRUE)"
+        )?);
+        let labels = vec!["low".to_string(), "mid".to_string(), "high".to_string()];
+        assert!(identical(
+            binned.with_labels(labels).unwrap().to_factor(false)?,
+            "cut(c(1, 5, 9), c(0, 3, 6, 9), labels = c('low', 'mid', 'high'))"
+        )?);
+    }
+}
@@ -0,0 +1,38 @@
+//! Long vector tests. These allocate more than 2 GB and only run with
+//! `cargo test --features long-vector-tests`.
+
+use extendr_api::prelude::*;
+use helloextendr::raw_io::RawReader;
+use helloextendr::test_with_r;
+use helloextendr::xlen::{length_to_robj, robj_to_length, XLength, R_SHORT_LEN_MAX};
+use std::io::{Read, Seek, SeekFrom};
+
+const LONG_LEN: usize = R_SHORT_LEN_MAX + 10;
+
+test_with_r! {
+    fn long_raw_vector_reports_full_length() {
+        let x = lang!("raw", LONG_LEN as f64).eval()?;
+        assert_eq!(x.xlength(), Some(LONG_LEN));
+        assert_eq!(x.len(), LONG_LEN);
+        assert!(x.is_long_vector());
+
+        let len = lang!("length", x).eval()?;
+        assert_eq!(len, length_to_robj(LONG_LEN));
+        assert_eq!(robj_to_length(&len)?, LONG_LEN);
+    }
+
+    fn raw_reader_reads_past_short_length() {
+        let x = R!("x <- raw({{LONG_LEN as f64}}); x[length(x)] <- as.raw(42); x")?;
+        let mut reader = RawReader::new(x.try_into()?);
+        let pos = reader.seek(SeekFrom::End(-1)).unwrap();
+        assert_eq!(pos as usize, LONG_LEN - 1);
+        let mut byte = [0u8];
+        reader.read_exact(&mut byte).unwrap();
+        assert_eq!(byte, [42]);
+    }
+
+    fn short_lengths_stay_integer() {
+        assert_eq!(length_to_robj(R_SHORT_LEN_MAX), r!(i32::MAX));
+        assert_eq!(length_to_robj(R_SHORT_LEN_MAX + 1), r!(R_SHORT_LEN_MAX as f64 + 1.0));
+    }
+}
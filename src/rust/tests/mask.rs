@@ -0,0 +1,53 @@
+//! Evaluation against the columns of a data frame.
+#![cfg(not(feature = "cran-strict"))]
+
+use extendr_api::prelude::*;
+use helloextendr::mask::{data_mask, eval_with_data};
+use helloextendr::test_with_r;
+
+test_with_r! {
+    fn evaluates_like_with() {
+        let sales: List = R!("data.frame(price = c(2, 3), quantity = c(10L, 4L))")?.try_into()?;
+        let env = Environment::new_with_parent(global_env());
+        env.set_local(sym!(price), 100);
+        env.set_local(sym!(discount), 0.5);
+        for code in [
+            "quote(price * quantity)",
+            "quote(price * discount)",
+            "quote(quantity)",
+            "expression(x <- price + 1, x * 2)",
+        ] {
+            let expr = eval_string(code)?;
+            let ours = eval_with_data(&expr, &sales, &env)?;
+            let theirs = R!("eval({{expr.clone()}}, {{sales.clone()}}, {{env.clone()}})")?;
+            assert_eq!(ours, theirs, "{}", code);
+        }
+        // A bare symbol is evaluated, not returned.
+        assert_eq!(eval_with_data(&sym!(price), &sales, &env)?, R!("c(2, 3)")?);
+        assert_eq!(eval_with_data(&R!("expression()")?, &sales, &env)?, r!(NULL));
+        assert!(eval_with_data(&R!("quote(missing_column)")?, &sales, &env).is_err());
+    }
+
+    fn leaves_data_and_enclos_alone() {
+        let data: List = R!("list(a = 1)")?.try_into()?;
+        let env = Environment::new_with_parent(global_env());
+        eval_with_data(&R!("quote({ a <- 2; b <- 3 })")?, &data, &env)?;
+        assert_eq!(data, R!("list(a = 1)")?.try_into()?);
+        assert!(env.local(sym!(b)).is_err());
+
+        let mask = data_mask(&data, &env);
+        assert_eq!(mask.parent(), Some(env));
+        assert_eq!(mask.local(sym!(a))?, r!(1.0));
+    }
+
+    fn binds_named_columns_only() {
+        let data: List = R!("list(x = 1, 2, x = 3, y = 4)")?.try_into()?;
+        let env = Environment::new_with_parent(base_env());
+        let mask = data_mask(&data, &env);
+        let names = R!("sort(ls({{mask.clone()}}))")?;
+        assert_eq!(names, r!(["x", "y"]));
+        // The first of duplicated names wins, as with `eval()` on a list.
+        assert_eq!(eval_with_data(&sym!(x), &data, &env)?, r!(1.0));
+        assert_eq!(R!("eval(quote(x), {{data.clone()}})")?, r!(1.0));
+    }
+}
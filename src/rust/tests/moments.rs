@@ -0,0 +1,151 @@
+//! Moment accumulators against R's `mean()`, `var()`, `sd()` and
+//! `weighted.mean()`.
+#![cfg(not(feature = "cran-strict"))]
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use helloextendr::moments::{Moments, MomentsExt};
+use helloextendr::test_with_r;
+
+/// Whether `ours` is R's `theirs` up to rounding: both missing (`NA` and
+/// `NaN` alike, as `is.na()` has it), equal, or within a relative 1e-10.
+fn near(ours: f64, theirs: f64) -> bool {
+    (ours.is_nan() && theirs.is_nan())
+        || ours == theirs
+        || (ours - theirs).abs() <= 1e-10 * theirs.abs().max(1.0)
+}
+
+fn r_value(code: &str) -> Result<f64> {
+    Ok(eval_string(code)?.as_real().unwrap_or(f64::NAN))
+}
+
+fn check(x: &str, na_rm: bool) -> Result<()> {
+    let values: Doubles = eval_string(&format!("as.double({x})"))?.try_into()?;
+    let mut moments = Moments::new(na_rm);
+    moments.push_doubles(&values);
+    let na_rm = if na_rm { "TRUE" } else { "FALSE" };
+    let expected = [
+        (
+            "mean",
+            moments.mean(),
+            format!("mean({x}, na.rm = {na_rm})"),
+        ),
+        (
+            "var",
+            moments.variance(),
+            format!("var({x}, na.rm = {na_rm})"),
+        ),
+        ("sd", moments.sd(), format!("sd({x}, na.rm = {na_rm})")),
+        (
+            "skewness",
+            moments.skewness(),
+            format!(
+                "local({{ x <- {x}; if ({na_rm}) x <- x[!is.na(x)]; d <- x - mean(x); \
+                 mean(d^3) / mean(d^2)^1.5 }})"
+            ),
+        ),
+    ];
+    for (what, ours, code) in expected {
+        let theirs = r_value(&code)?;
+        assert!(
+            near(ours, theirs),
+            "{} of {}: {} but R gives {}",
+            what,
+            x,
+            ours,
+            theirs
+        );
+    }
+    Ok(())
+}
+
+const SAMPLES: &[&str] = &[
+    "numeric()",
+    "5",
+    "c(1, 2)",
+    "c(2, 4, 4, 4, 5, 5, 7, 9)",
+    "1e5 + c(1, 2, 3, 4)",
+    "c(-3.5, 0, 1e-8, 12, 7.25, -1)",
+    "rep(3, 10)",
+    "c(1, NA, 3)",
+    "c(1, NaN, 3)",
+    "c(NA, NA)",
+    "c(1, Inf, 3)",
+    "c(-Inf, Inf)",
+    "c(Inf)",
+    "c(NA, Inf)",
+];
+
+test_with_r! {
+    fn matches_r() {
+        for x in SAMPLES {
+            check(x, false)?;
+            check(x, true)?;
+        }
+    }
+
+    fn reads_integers_and_altrep_sequences() {
+        let x: Integers = R!("c(4L, NA, 1L, 7L)")?.try_into()?;
+        let mut moments = Moments::new(true);
+        moments.push_integers(&x);
+        assert_eq!(moments.count(), 3);
+        assert!(near(moments.mean(), 4.0));
+        assert!(near(moments.variance(), r_value("var(c(4, 1, 7))")?));
+
+        let mut na = Moments::new(false);
+        na.push_integers(&x);
+        assert!(na.mean().is_nan());
+
+        // Compact, so only read region by region.
+        let x: Integers = R!("seq_len(100000L)")?.try_into()?;
+        let mut moments = Moments::new(false);
+        moments.push_integers(&x);
+        assert!(near(moments.mean(), 50000.5));
+        assert!(near(moments.variance(), r_value("var(seq_len(100000L))")?));
+    }
+
+    fn weights_count_as_frequencies() {
+        let x: Doubles = R!("c(1.5, 2, 10, -4, 3)")?.try_into()?;
+        let w: Doubles = R!("c(2, 1, 0, 3, 1)")?.try_into()?;
+        let mut moments = Moments::new(false);
+        moments.push_weighted_doubles(&x, &w)?;
+        assert_eq!(moments.count(), 4);
+        assert_eq!(moments.weight(), 7.0);
+        let mean = r_value("weighted.mean(c(1.5, 2, 10, -4, 3), c(2, 1, 0, 3, 1))")?;
+        assert!(near(moments.mean(), mean));
+        let rep = "rep(c(1.5, 2, 10, -4, 3), c(2, 1, 0, 3, 1))";
+        assert!(near(moments.variance(), r_value(&format!("var({rep})"))?));
+        assert!(near(moments.sd(), r_value(&format!("sd({rep})"))?));
+
+        let negative: Doubles = R!("c(1, -1, 1, 1, 1)")?.try_into()?;
+        assert!(moments.push_weighted_doubles(&x, &negative).is_err());
+        let short: Doubles = R!("c(1, 2)")?.try_into()?;
+        assert!(moments.push_weighted_doubles(&x, &short).is_err());
+    }
+}
+
+#[test]
+fn merging_equals_pushing_everything() {
+    let x = [3.0, -1.0, 4.0, 1.0, -5.0, 9.0, 2.0, 6.0, 5.0, -3.0, 5.0];
+    let mut all = Moments::new(false);
+    x.iter().for_each(|&v| all.push(v));
+    for split in 0..=x.len() {
+        let (mut left, mut right) = (Moments::new(false), Moments::new(false));
+        x[..split].iter().for_each(|&v| left.push(v));
+        x[split..].iter().for_each(|&v| right.push(v));
+        left.merge(&right);
+        assert_eq!(left.count(), all.count());
+        assert!(near(left.mean(), all.mean()));
+        assert!(near(left.variance(), all.variance()));
+        assert!(near(left.skewness(), all.skewness()));
+    }
+}
+
+#[test]
+fn stays_precise_with_a_large_mean() {
+    let mut moments = Moments::new(false);
+    for v in [1e12 + 4.0, 1e12 + 7.0, 1e12 + 13.0, 1e12 + 16.0] {
+        moments.push(v);
+    }
+    assert_eq!(moments.variance(), 30.0);
+}
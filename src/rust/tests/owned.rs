@@ -0,0 +1,46 @@
+//! R values kept alive by their owner.
+#![cfg(not(feature = "cran-strict"))]
+
+use extendr_api::prelude::*;
+use helloextendr::owned::Owned;
+use helloextendr::test_with_r;
+
+fn gc() {
+    R!("invisible(gc())").unwrap();
+}
+
+test_with_r! {
+    fn keeps_the_value_while_the_owner_lives() {
+        let owner: Robj = Environment::new_with_parent(global_env()).into();
+        let owned = Owned::new(&owner, R!("c(1, 2, 3)")?)?;
+        gc();
+        assert_eq!(owned.get(), Some(R!("c(1, 2, 3)")?));
+        let values: Doubles = owned.get_as()?;
+        assert_eq!(values.len(), 3);
+        assert_eq!(format!("{:?}", owned), "Owned(Doubles)");
+
+        let pointer: Robj = ExternalPtr::new(5_i32).into();
+        let owned = Owned::new(&pointer, "text")?;
+        assert_eq!(owned.clone().get_as::<String>()?, "text");
+    }
+
+    fn collects_values_that_refer_back_to_their_owner() {
+        let owner = Environment::new_with_parent(global_env());
+        // A closure over its owner, as a callback over a handle would be.
+        let callback = R!("local(function() owner, list(owner = {{owner.clone()}}))")?;
+        let owned = Owned::new(&owner.clone().into(), callback)?;
+        gc();
+        assert!(owned.get().is_some());
+
+        drop(owner);
+        gc();
+        assert_eq!(owned.get(), None);
+        assert!(owned.get_as::<Function>().is_err());
+        assert_eq!(format!("{:?}", owned), "Owned(<collected>)");
+    }
+
+    fn needs_an_owner_with_identity() {
+        assert!(Owned::new(&r!(1), 2).is_err());
+        assert!(Owned::new(&R!("list()")?, 2).is_err());
+    }
+}
@@ -0,0 +1,47 @@
+//! Parallel maps over R vectors.
+#![cfg(not(feature = "cran-strict"))]
+
+use extendr_api::prelude::*;
+use helloextendr::parallel::{
+    par_map_doubles, par_map_doubles_with, par_map_integers_with, par_map_slice, ParallelOptions,
+};
+use helloextendr::test_with_r;
+
+test_with_r! {
+    fn keeps_the_order_of_the_input() {
+        let input: Vec<u64> = (0..10_000).collect();
+        let expected: Vec<u64> = input.iter().map(|x| x * x).collect();
+        for (threads, chunk_size) in [(1, 4096), (2, 1), (4, 7), (8, 10_000), (3, 100_000)] {
+            let options = ParallelOptions::new().threads(threads).chunk_size(chunk_size);
+            let output = par_map_slice(&input, options, |x| x * x)?;
+            assert_eq!(output, expected, "{} threads, chunks of {}", threads, chunk_size);
+        }
+        let empty: Vec<u64> = par_map_slice(&[], ParallelOptions::new(), |x: &u64| *x)?;
+        assert!(empty.is_empty());
+    }
+
+    fn maps_r_vectors() {
+        let x: Doubles = R!("c(1, 4, NA, 9, NaN)")?.try_into()?;
+        let roots = par_map_doubles(&x, f64::sqrt)?;
+        assert_eq!(Robj::from(roots), R!("sqrt(c(1, 4, NA, 9, NaN))")?);
+
+        let options = ParallelOptions::new().threads(2).chunk_size(1);
+        let x: Doubles = R!("seq(-1, 1, length.out = 101)")?.try_into()?;
+        let doubled = par_map_doubles_with(&x, options, |x| 2.0 * x)?;
+        assert_eq!(Robj::from(doubled), R!("2 * seq(-1, 1, length.out = 101)")?);
+
+        let x: Integers = R!("c(1L, NA, 3L)")?.try_into()?;
+        let plus_one = par_map_integers_with(&x, options, |x| x.map(|x| x + 1))?;
+        assert_eq!(Robj::from(plus_one), R!("c(2L, NA, 4L)")?);
+    }
+
+    fn reports_panics() {
+        let input: Vec<i32> = (0..100).collect();
+        let options = ParallelOptions::new().threads(4).chunk_size(3);
+        let result = par_map_slice(&input, options, |&x| {
+            assert!(x != 50, "failed on 50");
+            x
+        });
+        assert!(result.is_err());
+    }
+}
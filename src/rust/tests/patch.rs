@@ -0,0 +1,67 @@
+//! Patches between versions of a numeric vector.
+#![cfg(not(feature = "cran-strict"))]
+
+use extendr_api::prelude::*;
+use helloextendr::patch::{apply_patch, diff, Patch};
+use helloextendr::test_with_r;
+
+fn doubles(code: &str) -> Doubles {
+    eval_string(code).unwrap().try_into().unwrap()
+}
+
+test_with_r! {
+    fn round_trips_between_versions() {
+        let versions = [
+            "numeric()",
+            "c(1, 2, 3)",
+            "c(1, 5, 3)",
+            "c(1, 5, 3, NA, NaN)",
+            "c(1, 5, 3, NaN, NA)",
+            "c(-0, 5)",
+            "c(0, 5, Inf)",
+            "seq(0, 1, length.out = 50)",
+        ];
+        for old in versions {
+            for new in versions {
+                let (a, b) = (doubles(old), doubles(new));
+                let patch = diff(&a, &b);
+                let patched: Robj = apply_patch(&a, &patch)?.into();
+                let same = lang!("identical", patched, Robj::from(b.clone())).eval()?;
+                assert_eq!(same, r!(true), "{} -> {}", old, new);
+                assert_eq!(patch.is_empty(), old == new, "{} -> {}", old, new);
+            }
+        }
+    }
+
+    fn sends_only_what_changed() {
+        let patch = diff(&doubles("c(1, 2, NA, 0)"), &doubles("c(1, 3, NaN, -0, 7)"));
+        assert_eq!(patch.indices, [1, 2, 3, 4]);
+        assert_eq!(patch.new_len, 5);
+        assert_eq!(
+            patch.to_robj()?,
+            R!("list(old_length = 4L, new_length = 5L, index = c(2, 3, 4, 5),
+                     value = c(3, NaN, -0, 7))")?
+        );
+        let same = diff(&doubles("c(NA, NaN)"), &doubles("c(NA, NaN)"));
+        assert!(same.is_empty() && same.indices.is_empty());
+        // Shrinking changes no element.
+        let shrink = diff(&doubles("1:5 + 0"), &doubles("1:2 + 0"));
+        assert!(!shrink.is_empty() && shrink.indices.is_empty());
+    }
+
+    fn rejects_patches_that_do_not_fit() {
+        let old = doubles("c(1, 2)");
+        let patch = |old_len, new_len, indices: &[usize], values: &[f64]| Patch {
+            old_len,
+            new_len,
+            indices: indices.to_vec(),
+            values: values.to_vec(),
+        };
+        assert!(apply_patch(&old, &patch(3, 3, &[], &[])).is_err());
+        assert!(apply_patch(&old, &patch(2, 2, &[0, 1], &[1.0])).is_err());
+        assert!(apply_patch(&old, &patch(2, 2, &[2], &[1.0])).is_err());
+        assert!(apply_patch(&old, &patch(2, 4, &[2], &[1.0])).is_err());
+        let grown = apply_patch(&old, &patch(2, 3, &[0, 2], &[9.0, 3.0]))?;
+        assert_eq!(Robj::from(grown), R!("c(9, 2, 3)")?);
+    }
+}
@@ -0,0 +1,98 @@
+//! `quantile()` against R's `stats::quantile()`, which it must match to the
+//! last bit.
+#![cfg(not(feature = "cran-strict"))]
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use helloextendr::quantile::{quantile, quantile_names};
+use helloextendr::test_with_r;
+
+/// `stats::quantile()` of `x` at `probs`.
+fn r_quantile(x: &[f64], probs: &[f64], kind: u8, na_rm: bool) -> Result<Vec<f64>> {
+    let q = R!(
+        "stats::quantile({{x.to_vec()}}, {{probs.to_vec()}}, type = {{kind as i32}},
+                         na.rm = {{na_rm}}, names = FALSE)"
+    )?;
+    Ok(q.as_real_vector().unwrap_or_default())
+}
+
+fn assert_same(x: &[f64], probs: &[f64], kind: u8, na_rm: bool) -> Result<()> {
+    let ours = quantile(x, probs, kind, na_rm)?;
+    let theirs = r_quantile(x, probs, kind, na_rm)?;
+    assert_eq!(ours.len(), theirs.len());
+    for (i, (a, b)) in ours.iter().zip(&theirs).enumerate() {
+        assert!(
+            a == b || (a.is_nan() && b.is_nan()),
+            "type {} of {:?} at {}: {} but R gives {}",
+            kind,
+            x,
+            probs[i],
+            a,
+            b
+        );
+    }
+    Ok(())
+}
+
+const PROBS: &[f64] = &[0.0, 1e-17, 0.1, 0.25, 1.0 / 3.0, 0.5, 0.9, 0.99, 1.0];
+
+test_with_r! {
+    fn matches_r_for_every_type() {
+        let samples: &[&[f64]] = &[
+            &[3.0],
+            &[2.0, 1.0],
+            &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0],
+            &[0.1, -2.5, 7.25, 1e10, -1e-10, 3.0, 3.5],
+            &[f64::NEG_INFINITY, 0.0, 1.0, f64::INFINITY],
+        ];
+        for kind in 1..=9 {
+            for x in samples {
+                assert_same(x, PROBS, kind, false)?;
+            }
+        }
+    }
+
+    fn matches_r_with_ties() {
+        let x = [1.0, 1.0, 1.0, 2.0, 2.0, 5.0, 5.0, 5.0, 5.0, 9.0, 9.0];
+        let probs: Vec<f64> = (0..=20).map(|i| f64::from(i) / 20.0).collect();
+        for kind in 1..=9 {
+            assert_same(&x, &probs, kind, false)?;
+            assert_same(&[4.0; 6], &probs, kind, false)?;
+        }
+    }
+
+    fn drops_missing_values_with_na_rm() {
+        let na = <f64 as CanBeNA>::na();
+        let x = [4.0, na, 1.0, f64::NAN, 3.0, 2.0];
+        for kind in 1..=9 {
+            assert_same(&x, PROBS, kind, true)?;
+        }
+        let error = quantile(&x, PROBS, 7, false).unwrap_err();
+        assert!(R!("stats::quantile(c(1, NA), 0.5)").is_err());
+        assert!(error.to_string().contains("'na.rm' is FALSE"), "{}", error);
+    }
+
+    fn gives_na_for_missing_probabilities_and_empty_samples() {
+        let na = <f64 as CanBeNA>::na();
+        for kind in 1..=9 {
+            assert_same(&[1.0, 2.0, 3.0], &[0.5, na], kind, false)?;
+            assert_same(&[], &[0.0, 0.5, 1.0], kind, false)?;
+            assert_same(&[na, na], &[0.5], kind, true)?;
+        }
+    }
+
+    fn rejects_probabilities_outside_the_unit_interval() {
+        for p in [-0.1, 1.1] {
+            assert!(quantile(&[1.0, 2.0], &[p], 7, false).is_err());
+            assert!(R!("stats::quantile(c(1, 2), {{p}})").is_err());
+        }
+        assert!(quantile(&[1.0, 2.0], &[0.5], 10, false).is_err());
+    }
+
+    fn names_like_r() {
+        let na = <f64 as CanBeNA>::na();
+        let probs = [0.0, 1e-17, 0.001, 0.025, 0.1, 1.0 / 3.0, 0.5, 2.0 / 3.0, 0.999, 1.0, na];
+        let names = R!("names(stats::quantile(1:10, {{probs.to_vec()}}))")?;
+        assert_eq!(Some(quantile_names(&probs)), names.as_string_vector());
+    }
+}
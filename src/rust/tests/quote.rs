@@ -0,0 +1,53 @@
+//! Quasi-quotation with `r_expr!`.
+#![cfg(not(feature = "cran-strict"))]
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use helloextendr::quote::unquote;
+use helloextendr::{r_expr, test_with_r};
+
+fn deparse(expr: &Robj) -> Result<String> {
+    let lines = lang!("deparse", expr.clone()).eval()?;
+    Ok(lines.as_string_vector().unwrap_or_default().concat())
+}
+
+test_with_r! {
+    fn inserts_values() {
+        let n = r!(10);
+        let expr = r_expr! { seq_len(!!n) }?;
+        assert_eq!(deparse(&expr)?, "seq_len(10L)");
+        assert_eq!(expr.eval()?, R!("1:10")?);
+
+        // At any depth, and in the defaults of functions.
+        let x = r!("a");
+        let expr = r_expr! { list(paste(!!x, "b"), c(!!n, !!n)) }?;
+        assert_eq!(deparse(&expr)?, "list(paste(\"a\", \"b\"), c(10L, 10L))");
+        let f = r_expr! { function(k = !!n) k * 2L }?.eval()?;
+        assert_eq!(f.call(pairlist!())?, r!(20));
+    }
+
+    fn splices_lists_into_calls() {
+        let args = R!("list(1, b = 2)")?;
+        let expr = r_expr! { c(0, !!!args, z = 3) }?;
+        assert_eq!(deparse(&expr)?, "c(0, 1, b = 2, z = 3)");
+        assert_eq!(expr.eval()?, R!("c(0, 1, b = 2, z = 3)")?);
+
+        // Atomic vectors are spliced element by element.
+        let values = R!("c(x = 1L, y = 2L)")?;
+        assert_eq!(deparse(&r_expr! { sum(!!!values) }?)?, "sum(x = 1L, y = 2L)");
+    }
+
+    fn rejects_splices_outside_calls() {
+        let args = R!("list(1, 2)")?;
+        let error = r_expr! { !!!args }.unwrap_err();
+        assert!(error.to_string().contains("function argument"), "{}", error);
+        assert!(unquote("1; 2", &[]).is_err());
+    }
+
+    fn leaves_unknown_names_alone() {
+        let expr = unquote("f(!!y)", &[])?;
+        assert_eq!(deparse(&expr)?, "f(!!y)");
+        let plain = r_expr! { x + 1 }?;
+        assert_eq!(plain, R!("quote(x + 1)")?);
+    }
+}
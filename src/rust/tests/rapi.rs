@@ -0,0 +1,77 @@
+//! The entry points of R's C API declared by the crate.
+#![cfg(not(feature = "cran-strict"))]
+
+use extendr_api::prelude::*;
+use extendr_ffi::{cetype_t, R_GlobalEnv, Rboolean, STRING_ELT};
+use helloextendr::rapi::{self, entry_point, ENTRY_POINTS};
+use helloextendr::sexp::{Sexp, SexpRef};
+use helloextendr::test_with_r;
+use std::ffi::CStr;
+
+#[test]
+fn records_the_facts_of_each_entry_point() {
+    let typeof_ = entry_point("TYPEOF").unwrap();
+    assert!(typeof_.read_only && !typeof_.allocates && !typeof_.longjmps);
+    let eval = entry_point("Rf_eval").unwrap();
+    assert!(!eval.read_only && eval.allocates && eval.longjmps);
+    // Derived from the signature: one writable argument is enough.
+    assert!(!entry_point("R_MakeWeakRef").unwrap().read_only);
+    assert!(entry_point("R_WeakRefValue").unwrap().read_only);
+    assert_eq!(entry_point("Rf_allocVector"), None);
+
+    for (i, entry) in ENTRY_POINTS.iter().enumerate() {
+        assert!(!entry.allocates || entry.longjmps, "{}", entry.name);
+        assert!(
+            ENTRY_POINTS[..i]
+                .iter()
+                .all(|other| other.name != entry.name),
+            "{}",
+            entry.name
+        );
+    }
+}
+
+test_with_r! {
+    fn calls_into_r() {
+        let x = R!("c(a = 1.5)")?;
+        assert_eq!(unsafe { rapi::TYPEOF(SexpRef::of(&x)) }, 14);
+
+        let latin1 = R!("iconv('caf\\u00e9', 'UTF-8', 'latin1')")?;
+        let text = unsafe {
+            let chars = SexpRef::new(Sexp::from_raw(STRING_ELT(latin1.get(), 0)));
+            assert_eq!(rapi::Rf_getCharCE(chars), cetype_t::CE_LATIN1);
+            CStr::from_ptr(rapi::Rf_translateCharUTF8(chars)).to_str().unwrap().to_string()
+        };
+        assert_eq!(text, "café");
+
+        let call = R!("quote(1 + 2)")?;
+        let sum = unsafe {
+            Robj::from_sexp(rapi::Rf_eval(Sexp::of(&call), Sexp::from_raw(R_GlobalEnv)).as_raw())
+        };
+        assert_eq!(sum, r!(3.0));
+    }
+
+    fn makes_weak_references() {
+        let key: Robj = Environment::new_with_parent(global_env()).into();
+        let value = r!("value");
+        let weakref = unsafe {
+            Robj::from_sexp(
+                rapi::R_MakeWeakRef(
+                    Sexp::of(&key),
+                    Sexp::of(&value),
+                    Sexp::of(&r!(NULL)),
+                    Rboolean::FALSE,
+                )
+                .as_raw(),
+            )
+        };
+        let (k, v) = unsafe {
+            (
+                rapi::R_WeakRefKey(SexpRef::of(&weakref)),
+                rapi::R_WeakRefValue(SexpRef::of(&weakref)),
+            )
+        };
+        assert_eq!(k, Sexp::of(&key));
+        assert_eq!(v, Sexp::of(&value));
+    }
+}
@@ -0,0 +1,83 @@
+//! `std::io` over raw vectors and connections.
+#![cfg(not(feature = "cran-strict"))]
+
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+
+use extendr_api::prelude::*;
+use helloextendr::raw_io::{
+    ConnectionReader, ConnectionWriter, IntoRaw, RawExt, RawReader, RawWriter,
+};
+use helloextendr::test_with_r;
+
+test_with_r! {
+    fn reads_raw_vectors() {
+        let raw: Raw = R!("charToRaw('hello\nworld')")?.try_into()?;
+        let mut reader = RawReader::new(raw);
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "hello\n");
+        assert_eq!(reader.position(), 6);
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"world");
+        assert_eq!(reader.read(&mut [0; 4]).unwrap(), 0);
+    }
+
+    fn seeks_like_a_cursor() {
+        let mut reader = RawReader::new(b"0123456789".into_raw());
+        assert_eq!(reader.seek(SeekFrom::End(-3)).unwrap(), 7);
+        let mut byte = [0];
+        reader.read_exact(&mut byte).unwrap();
+        assert_eq!(&byte, b"7");
+        assert_eq!(reader.seek(SeekFrom::Current(-5)).unwrap(), 3);
+        assert!(reader.seek(SeekFrom::Current(-4)).is_err());
+        assert_eq!(reader.position(), 3);
+        // Past the end is allowed, and reads nothing.
+        assert_eq!(reader.seek(SeekFrom::Start(100)).unwrap(), 100);
+        assert_eq!(reader.read(&mut byte).unwrap(), 0);
+        assert_eq!(reader.fill_buf().unwrap(), b"");
+        assert_eq!(reader.seek(SeekFrom::Start(u64::MAX)).unwrap(), u64::MAX);
+        assert!(reader.seek(SeekFrom::Current(1)).is_err());
+    }
+
+    fn writes_raw_vectors() {
+        let mut writer = RawWriter::with_capacity(4);
+        write!(writer, "{}-ab", 12).unwrap();
+        writer.write_all(&[0, 255]).unwrap();
+        let raw = writer.into_raw();
+        assert_eq!(raw.to_vec(), b"12-ab\x00\xff");
+        assert_eq!(Robj::from(raw), R!("as.raw(c(0x31, 0x32, 0x2d, 0x61, 0x62, 0, 255))")?);
+
+        let mut raw = vec![0u8; 3].into_raw();
+        raw.as_mut_slice().copy_from_slice(b"xyz");
+        assert_eq!(Robj::from(raw), R!("charToRaw('xyz')")?);
+    }
+
+    fn goes_through_connections() {
+        let path = R!("tempfile()")?;
+        let path = path.as_str().unwrap().to_string();
+        let con = R!("file({{path.as_str()}}, 'wb')")?;
+        let mut writer = ConnectionWriter::new(con.clone())?;
+        writer.write_all(b"line one\nline two\n").unwrap();
+        writer.flush().unwrap();
+        R!("close({{con}})")?;
+
+        let con = R!("file({{path.as_str()}}, 'rb')")?;
+        let mut lines = BufReader::new(ConnectionReader::new(con.clone())?).lines();
+        assert_eq!(lines.next().unwrap().unwrap(), "line one");
+        assert_eq!(lines.next().unwrap().unwrap(), "line two");
+        assert!(lines.next().is_none());
+        R!("close({{con}}); unlink({{path.as_str()}})")?;
+
+        assert!(ConnectionReader::new(r!(1)).is_err());
+        assert!(ConnectionWriter::new(r!("a path")).is_err());
+    }
+
+    fn reports_r_errors_as_io_errors() {
+        let con = R!("file(tempfile(), 'wb')")?;
+        let mut reader = ConnectionReader::new(con.clone())?;
+        // Reading a connection opened for writing fails in R.
+        assert!(reader.read(&mut [0; 8]).is_err());
+        R!("close({{con}})")?;
+    }
+}
@@ -0,0 +1,31 @@
+//! Wrappers generated by `r_module!`, with the `#[r_class]` definitions.
+#![cfg(not(feature = "cran-strict"))]
+
+use helloextendr::registry::r_wrappers;
+use helloextendr::{__r_class_definitions, get_helloextendr_metadata, test_with_r};
+
+test_with_r! {
+    fn the_wrappers_end_with_the_class_definitions() {
+        let wrappers = r_wrappers(
+            &get_helloextendr_metadata(),
+            __r_class_definitions,
+            true,
+            "helloextendr",
+        )
+        .unwrap();
+        let class = wrappers.find("KdTree <- new.env(parent = emptyenv())").unwrap();
+        let constructor = wrappers.find("new_KdTree <- function(...) {").unwrap();
+        assert!(class < constructor);
+        assert!(wrappers[constructor..].contains(r#"setdiff(ls(KdTree), "new")"#));
+        assert!(!wrappers.contains("r_class_definition"));
+    }
+
+    fn classes_are_defined_once_by_their_own_module() {
+        let mut all = Vec::new();
+        __r_class_definitions(&mut all);
+        let mut kdtree = Vec::new();
+        helloextendr::kdtree::__r_class_definitions(&mut kdtree);
+        assert_eq!(kdtree.len(), 1);
+        assert_eq!(all.iter().filter(|d| d.contains("new_KdTree")).count(), 1);
+    }
+}
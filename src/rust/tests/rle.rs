@@ -0,0 +1,104 @@
+//! Run-length encoding against R's `rle()` and `inverse.rle()`.
+#![cfg(not(feature = "cran-strict"))]
+
+use extendr_api::prelude::*;
+use helloextendr::rle::{inverse_rle, inverse_rle_robj, rle, rle_robj, run_ids, run_starts, Rle};
+use helloextendr::test_with_r;
+
+/// Whether `ours` is `identical()` to the value of the R code `theirs`.
+fn identical(ours: Robj, theirs: &str) -> bool {
+    let theirs = eval_string(theirs).unwrap();
+    lang!("identical", ours, theirs).eval().unwrap().as_bool() == Some(true)
+}
+
+const VECTORS: &[&str] = &[
+    "c(1, 1, 2, 2, 2, NA, NA, NaN, NaN, 3, 0, -0)",
+    "c(a = 1L, b = 1L, c = NA, d = NA, e = 2L)",
+    "c(TRUE, TRUE, NA, NA, FALSE, TRUE)",
+    "c('a', 'a', NA, NA, 'b', 'é', 'é', '')",
+    "as.raw(c(1, 1, 0, 255, 255))",
+    "integer()",
+    "character()",
+    "NA",
+];
+
+test_with_r! {
+    fn encodes_like_r() {
+        for code in VECTORS {
+            let x = eval_string(code)?;
+            let encoded = rle_robj(&x)?;
+            assert!(identical(encoded.clone(), &format!("rle({code})")), "rle({})", code);
+            let decoded = inverse_rle_robj(&encoded)?;
+            assert!(
+                identical(decoded, &format!("inverse.rle(rle({code}))")),
+                "inverse.rle(rle({}))",
+                code
+            );
+        }
+    }
+
+    fn decodes_lists_r_built() {
+        let x = R!("list(lengths = c(2, 0, 3), values = c(x = 'a', y = 'b', z = NA))")?;
+        assert!(identical(
+            inverse_rle_robj(&x)?,
+            "inverse.rle(list(lengths = c(2, 0, 3), values = c(x = 'a', y = 'b', z = NA)))"
+        ));
+        let invalid = R!("list(lengths = 1:2, values = 1)")?;
+        assert!(inverse_rle_robj(&invalid).is_err());
+        assert!(inverse_rle_robj(&R!("list(lengths = -1, values = 1)")?).is_err());
+    }
+
+    fn rejects_what_r_rejects() {
+        for code in ["factor(c('a', 'a'))", "matrix(1:4, 2)"] {
+            assert!(rle_robj(&eval_string(code)?).is_err(), "rle({})", code);
+            assert!(eval_string(&format!("rle({code})")).is_err(), "rle({})", code);
+        }
+    }
+
+    fn finds_runs_of_strings() {
+        let x: Strings = R!("c('a', 'a', NA, NA, 'b')")?.try_into()?;
+        let values: Vec<Option<&str>> =
+            x.iter().map(|s| if s.is_na() { None } else { Some(s.as_ref()) }).collect();
+        assert_eq!(run_starts(&values), [0, 2, 3, 4]);
+        assert_eq!(run_ids(&values), [0, 0, 1, 2, 3]);
+        let runs = rle(&values);
+        assert_eq!(runs.lengths, [2, 1, 1, 1]);
+        assert_eq!(inverse_rle(&runs).len(), values.len());
+    }
+}
+
+#[test]
+fn missing_values_are_runs_of_their_own() {
+    let na = <f64 as CanBeNA>::na();
+    let x = [1.0, 1.0, na, na, f64::NAN, 2.0];
+    assert_eq!(run_starts(&x), [0, 2, 3, 4, 5]);
+    assert_eq!(run_ids(&x), [0, 0, 1, 2, 3, 4]);
+    assert_eq!(rle(&x).lengths, [2, 1, 1, 1, 1]);
+
+    let na = <i32 as CanBeNA>::na();
+    assert_eq!(rle(&[na, na, 3, 3]).lengths, [1, 1, 2]);
+}
+
+#[test]
+fn runs_take_their_last_value() {
+    let runs = rle(&[0.0, -0.0, -0.0]);
+    assert_eq!(runs.lengths, [3]);
+    assert!(runs.values[0].is_sign_negative());
+}
+
+#[test]
+fn inverse_rle_repeats_values() {
+    let runs = Rle {
+        lengths: vec![2, 0, 1],
+        values: vec![true, false, true],
+    };
+    assert_eq!(inverse_rle(&runs), [true, true, true]);
+    assert_eq!(rle(&inverse_rle(&rle(&[1u8, 1, 2]))), rle(&[1u8, 1, 2]));
+    assert_eq!(
+        rle::<u8>(&[]),
+        Rle {
+            lengths: vec![],
+            values: vec![]
+        }
+    );
+}
@@ -0,0 +1,162 @@
+//! Rolling statistics against R's `sum()`, `mean()`, `max()` and `min()`
+//! applied to every window in turn.
+#![cfg(not(feature = "cran-strict"))]
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use helloextendr::roll::{roll_apply, roll_max, roll_mean, roll_min, roll_sum, Align};
+use helloextendr::test_with_r;
+use helloextendr::view::{View, ViewExt};
+
+type Roll = fn(View<'_, f64>, usize, Align, bool) -> Result<Vec<f64>>;
+
+const ROLLS: &[(&str, Roll)] = &[
+    ("sum", roll_sum),
+    ("mean", roll_mean),
+    ("max", roll_max),
+    ("min", roll_min),
+];
+
+const ALIGNS: &[Align] = &[Align::Left, Align::Center, Align::Right];
+
+/// Where `align` places the result of a window of `width`, counted from
+/// its first element: what zoo does.
+fn before(align: Align, width: usize) -> usize {
+    match align {
+        Align::Left => 0,
+        Align::Center => (width - 1) / 2,
+        Align::Right => width - 1,
+    }
+}
+
+/// `f` of each window of `x`, the R code of a double vector, computed by R
+/// one window at a time.
+fn naive(x: &str, f: &str, width: usize, align: Align, na_rm: bool) -> Result<Vec<f64>> {
+    let code = format!(
+        "local({{
+            x <- {x}
+            n <- length(x)
+            out <- rep(NA_real_, n)
+            for (s in seq_len(max(0, n - {width} + 1))) {{
+                w <- x[s:(s + {width} - 1)]
+                out[s + {before}] <- suppressWarnings({f}(w, na.rm = {na_rm}))
+            }}
+            out
+        }})",
+        before = before(align, width),
+        na_rm = if na_rm { "TRUE" } else { "FALSE" },
+    );
+    Ok(eval_string(&code)?.as_real_vector().unwrap_or_default())
+}
+
+/// Equal, both missing, or equal but for rounding, since R sums in long
+/// double and the rolling sums with compensation.
+fn near(ours: f64, theirs: f64) -> bool {
+    (ours.is_nan() && theirs.is_nan())
+        || ours == theirs
+        || (ours - theirs).abs() <= 1e-12 * theirs.abs().max(1.0)
+}
+
+/// Checks `roll` against R's `name` on every window of `x`.
+fn check(x: &str, name: &str, roll: Roll) -> Result<()> {
+    let values: Doubles = eval_string(x)?.try_into()?;
+    let view = values.view(0, values.len(), 1)?;
+    for width in 1..=5 {
+        for &align in ALIGNS {
+            for na_rm in [false, true] {
+                let ours = roll(view, width, align, na_rm)?;
+                let theirs = naive(x, name, width, align, na_rm)?;
+                assert_eq!(ours.len(), theirs.len());
+                for (i, (a, b)) in ours.iter().zip(&theirs).enumerate() {
+                    assert!(
+                        near(*a, *b),
+                        "{} of {} at {}, width {}, {:?}, na_rm = {}: {} but R gives {}",
+                        name,
+                        x,
+                        i,
+                        width,
+                        align,
+                        na_rm,
+                        a,
+                        b
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+const SAMPLES: &[&str] = &[
+    "c(1, 2, 3, 4, 5, 6, 7)",
+    "c(1, Inf, 2, 3, -Inf, 4, 5, Inf, -Inf, 6)",
+    "c(1, NaN, 2, 3, NA, 4, NA, NaN, 5, 6, 7)",
+    "c(NA, Inf, NaN, -Inf, 1, NA, 2)",
+    "c(1e16, 1, -1e16, 3, 0.1, 0.2, 0.3, -0.6)",
+    "c(5, 3, 5, 1, 1, 2, 8, 8, 0, -1)",
+    "c(NA_real_, NA, NA)",
+    "numeric()",
+    "42",
+];
+
+test_with_r! {
+    fn matches_r_window_by_window() {
+        for x in SAMPLES {
+            for &(name, roll) in ROLLS {
+                check(x, name, roll)?;
+            }
+        }
+    }
+
+    fn rolls_over_strided_views() {
+        let values: Doubles = R!("as.double(1:12)")?.try_into()?;
+        let odd = values.view(0, 6, 2)?;
+        let sums = roll_sum(odd, 3, Align::Right, false)?;
+        let theirs = naive("as.double(seq(1, 11, by = 2))", "sum", 3, Align::Right, false)?;
+        assert_eq!(sums.len(), theirs.len());
+        assert!(sums.iter().zip(&theirs).all(|(a, b)| near(*a, *b)));
+    }
+}
+
+#[test]
+fn keeps_the_documented_example() {
+    let na = <f64 as CanBeNA>::na();
+    let x = [1.0, 2.0, na, 4.0, 5.0];
+    let means = roll_mean(View::of(&x), 2, Align::Right, true).unwrap();
+    assert!(means[0].is_nan());
+    assert_eq!(means[1..], [1.5, 2.0, 4.0, 4.5]);
+}
+
+#[test]
+fn leaves_no_trace_of_values_that_left_the_window() {
+    let x = [f64::INFINITY, f64::NAN, 1.0, 2.0, 3.0];
+    for roll in [roll_sum, roll_mean, roll_max, roll_min] {
+        let out = roll(View::of(&x), 2, Align::Left, false).unwrap();
+        assert!(out[0].is_nan() && out[1].is_nan());
+        assert!(out[2..4].iter().all(|v| v.is_finite()));
+    }
+}
+
+#[test]
+fn applies_functions_to_windows() {
+    let x = [3, 1, 4, 1, 5, 9, 2];
+    let spans = roll_apply(View::of(&x), 3, Align::Center, |w| {
+        w.iter().max().unwrap() - w.iter().min().unwrap()
+    })
+    .unwrap();
+    assert_eq!(
+        spans,
+        [None, Some(3), Some(3), Some(4), Some(8), Some(7), None]
+    );
+    let none = roll_apply(View::of(&x), 8, Align::Left, |w| w.len()).unwrap();
+    assert!(none.iter().all(Option::is_none));
+}
+
+#[test]
+fn rejects_empty_windows_and_unknown_alignments() {
+    let x = [1.0, 2.0];
+    assert!(roll_sum(View::of(&x), 0, Align::Left, false).is_err());
+    assert!(roll_apply(View::of(&x), 0, Align::Left, |w| w.len()).is_err());
+    assert_eq!("center".parse::<Align>().unwrap(), Align::Center);
+    assert!("middle".parse::<Align>().is_err());
+}
@@ -0,0 +1,76 @@
+//! Typed views of S3 and S4 objects.
+#![cfg(not(feature = "cran-strict"))]
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use helloextendr::s4::{check_class, field};
+use helloextendr::{import_s4_class, test_with_r};
+
+import_s4_class! {
+    /// A linear model fitted by `lm()`.
+    struct Lm("lm") {
+        coefficients: Doubles,
+        rank: Integers,
+        call: Robj,
+    }
+}
+
+import_s4_class! {
+    struct Person("Person") {
+        name: Strings,
+        age: Doubles,
+    }
+}
+
+fn error_of<T: std::fmt::Debug>(result: Result<T>) -> String {
+    result.unwrap_err().to_string()
+}
+
+test_with_r! {
+    fn reads_the_components_of_s3_objects() {
+        let fit = Lm::try_from(R!("lm(mpg ~ wt, mtcars)")?)?;
+        assert_eq!(fit.coefficients().len(), 2);
+        assert_eq!(
+            Robj::from(fit.coefficients().clone()),
+            R!("coef(lm(mpg ~ wt, mtcars))")?
+        );
+        assert_eq!(fit.rank().elt(0), 2);
+        assert!(fit.call().is_language());
+        assert!(fit.robj().inherits(Lm::CLASS));
+        assert_eq!(Robj::from(fit), R!("lm(mpg ~ wt, mtcars)")?);
+    }
+
+    fn reads_the_slots_of_s4_objects() {
+        R!("setClass('Person', representation(name = 'character', age = 'numeric'))
+            setClass('Student', contains = 'Person', representation(school = 'character'))")?;
+        let ada = Person::try_from(R!("new('Person', name = 'Ada', age = 36)")?)?;
+        assert_eq!(ada.name().elt(0), "Ada");
+        assert_eq!(ada.age().elt(0), 36.0);
+        // Subclasses have the fields too.
+        let student = R!("new('Student', name = 'Bo', age = 20, school = 'X')")?;
+        assert_eq!(Person::try_from(student.clone())?.name().elt(0), "Bo");
+        assert_eq!(field(&student, "school")?, r!("X"));
+        R!("removeClass('Student'); removeClass('Person')")?;
+    }
+
+    fn names_what_is_wrong() {
+        assert_eq!(
+            error_of(Lm::try_from(R!("data.frame(x = 1)")?)),
+            "expected an object of class `lm`, got `data.frame`"
+        );
+        assert_eq!(
+            error_of(check_class(&r!(1.5), "lm")),
+            "expected an object of class `lm`, got `Doubles`"
+        );
+        let fake = R!("structure(list(coefficients = 'a', rank = 1L, call = NULL), class = 'lm')")?;
+        let message = error_of(Lm::try_from(fake));
+        assert!(
+            message.starts_with("field `coefficients` of the `lm` object has the wrong type"),
+            "{}",
+            message
+        );
+        let missing = R!("structure(list(coefficients = 1), class = 'lm')")?;
+        assert!(error_of(Lm::try_from(missing)).starts_with("field `rank`"));
+        assert!(field(&r!(1.5), "x").is_err());
+    }
+}
@@ -0,0 +1,80 @@
+//! Typed handles and type codes of R objects.
+#![cfg(not(feature = "cran-strict"))]
+
+use extendr_api::prelude::*;
+use extendr_api::robj::GetSexp;
+use helloextendr::sexp::{Sexp, SexpRef, SexpType, UnknownSexpType};
+use helloextendr::test_with_r;
+use std::convert::TryFrom;
+
+#[test]
+fn converts_type_codes() {
+    for code in 0..64 {
+        match SexpType::try_from(code) {
+            Ok(sexptype) => assert_eq!(u32::from(sexptype), code),
+            Err(e) => {
+                assert_eq!(e, UnknownSexpType(code));
+                assert!(matches!(code, 11 | 12) || code > 25, "{}", code);
+            }
+        }
+    }
+    assert_eq!(UnknownSexpType(99).to_string(), "unknown SEXPTYPE 99");
+}
+
+#[test]
+fn matches_the_codes_of_the_headers() {
+    let codes = [
+        (SexpType::Nil, extendr_ffi::SEXPTYPE::NILSXP),
+        (SexpType::Closure, extendr_ffi::SEXPTYPE::CLOSXP),
+        (SexpType::Integer, extendr_ffi::SEXPTYPE::INTSXP),
+        (SexpType::Real, extendr_ffi::SEXPTYPE::REALSXP),
+        (SexpType::String, extendr_ffi::SEXPTYPE::STRSXP),
+        (SexpType::List, extendr_ffi::SEXPTYPE::VECSXP),
+        (SexpType::ExternalPtr, extendr_ffi::SEXPTYPE::EXTPTRSXP),
+        (SexpType::Raw, extendr_ffi::SEXPTYPE::RAWSXP),
+    ];
+    for (ours, theirs) in codes {
+        assert_eq!(u32::from(ours), theirs as u32, "{:?}", ours);
+    }
+}
+
+test_with_r! {
+    fn reads_the_type_of_objects() {
+        for (code, sexptype) in [
+            ("NULL", SexpType::Nil),
+            ("quote(x)", SexpType::Symbol),
+            ("pairlist(1)", SexpType::Pairlist),
+            ("function() 1", SexpType::Closure),
+            ("globalenv()", SexpType::Environment),
+            ("quote(f(x))", SexpType::Language),
+            ("`if`", SexpType::Special),
+            ("sum", SexpType::Builtin),
+            ("TRUE", SexpType::Logical),
+            ("1:2", SexpType::Integer),
+            ("1.5", SexpType::Real),
+            ("1i", SexpType::Complex),
+            ("'a'", SexpType::String),
+            ("list()", SexpType::List),
+            ("expression(1)", SexpType::Expression),
+            ("as.raw(1)", SexpType::Raw),
+            ("getClass('numeric')", SexpType::Object),
+        ] {
+            let x = eval_string(code)?;
+            assert_eq!(unsafe { Sexp::of(&x).sexptype() }, Ok(sexptype), "{}", code);
+        }
+    }
+
+    fn wraps_the_same_pointer() {
+        let x = r!([1, 2]);
+        let sexp = Sexp::of(&x);
+        assert_eq!(sexp, Sexp::from_raw(unsafe { x.get() }));
+        assert_eq!(sexp.as_raw(), unsafe { x.get() });
+        assert_eq!(SexpRef::of(&x).sexp(), sexp);
+        assert_ne!(Sexp::of(&r!([1, 2])), sexp);
+        assert_eq!(format!("{:?}", sexp), format!("Sexp({:p})", sexp.as_raw()));
+        assert_eq!(
+            format!("{:?}", SexpRef::of(&x)),
+            format!("SexpRef({:p})", sexp.as_raw())
+        );
+    }
+}
@@ -0,0 +1,65 @@
+//! Parsing with source references.
+#![cfg(not(feature = "cran-strict"))]
+
+use extendr_api::prelude::*;
+use helloextendr::ast::Expr;
+use helloextendr::srcref::parse_with_srcrefs;
+use helloextendr::test_with_r;
+
+const CODE: &str = "x <- 1
+f <- function(a) {
+  b <- a + 1
+  if (b > 2) {
+    b * 2
+  } else b
+}
+y <- \"é\"; z <- 3
+";
+
+test_with_r! {
+    fn locates_top_level_expressions() {
+        let exprs = parse_with_srcrefs(CODE, "script.R")?;
+        assert_eq!(exprs.len(), 4);
+        let texts: Vec<&str> = exprs.iter().map(|e| e.srcref.text.as_str()).collect();
+        let theirs = R!("vapply(
+            attr(parse(text = {{CODE}}, keep.source = TRUE), 'srcref'),
+            function(s) paste(as.character(s), collapse = '\n'),
+            ''
+        )")?;
+        assert_eq!(Some(texts.iter().map(|t| t.to_string()).collect()), theirs.as_string_vector());
+
+        let f = &exprs[1].srcref;
+        assert_eq!((f.first_line, f.first_column, f.last_line, f.last_column), (2, 1, 7, 1));
+        assert_eq!(exprs[0].expr, Expr::parse_one("x <- 1")?);
+    }
+
+    fn counts_columns_in_characters() {
+        let exprs = parse_with_srcrefs(CODE, "script.R")?;
+        let (y, z) = (&exprs[2].srcref, &exprs[3].srcref);
+        assert_eq!(y.text, "y <- \"é\"");
+        assert_eq!((y.first_column, y.last_column), (1, 8));
+        assert_eq!(z.text, "z <- 3");
+        assert_eq!((z.first_line, z.first_column, z.last_column), (8, 11, 16));
+        assert_eq!(z.to_string(), "script.R:8:11");
+    }
+
+    fn locates_the_statements_of_blocks() {
+        let exprs = parse_with_srcrefs(CODE, "script.R")?;
+        let body = &exprs[1].statements;
+        let texts: Vec<&str> = body.iter().map(|s| s.srcref.text.as_str()).collect();
+        assert_eq!(texts, ["b <- a + 1", "if (b > 2) {\n    b * 2\n  } else b"]);
+        assert_eq!(body[0].srcref.first_line, 3);
+        assert_eq!(body[0].srcref.first_column, 3);
+        // Nested blocks belong to the statement they are in.
+        let nested = &body[1].statements;
+        assert_eq!(nested.len(), 1);
+        assert_eq!(nested[0].srcref.text, "b * 2");
+        assert_eq!(nested[0].srcref.first_line, 5);
+        assert!(exprs[0].statements.is_empty());
+    }
+
+    fn reports_syntax_errors() {
+        assert!(parse_with_srcrefs("f(", "broken.R").is_err());
+        assert!(parse_with_srcrefs("", "empty.R")?.is_empty());
+    }
+}
@@ -0,0 +1,65 @@
+//! Contingency tables against R's `table()`.
+#![cfg(not(feature = "cran-strict"))]
+
+use extendr_api::prelude::*;
+use extendr_api::Result;
+use helloextendr::table::{cross_count, table_robj, UseNa};
+use helloextendr::test_with_r;
+
+/// Whether `table_robj()` of the list the R code `args` builds is
+/// `identical()` to `table()` of it.
+fn same_as_r(args: &str, use_na: &str) -> Result<bool> {
+    let list: List = eval_string(args)?.try_into()?;
+    let ours = table_robj(&list, use_na.parse()?)?;
+    let theirs = eval_string(&format!("do.call(table, c({args}, useNA = '{use_na}'))"))?;
+    Ok(lang!("identical", ours, theirs).eval()?.as_bool() == Some(true))
+}
+
+const ARGS: &[&str] = &[
+    "list(x = c('b', 'a', 'c', 'a', 'b', 'a'))",
+    "list(x = c(3, 1, 2, 1, 10, 2.5))",
+    "list(x = c(TRUE, FALSE, TRUE))",
+    "list(x = c('b', NA, 'a', NA))",
+    "list(x = c(1, NA, NaN, 2, 1))",
+    "list(x = c('x', 'NaN', 'y'))",
+    "list(x = integer())",
+    "list(x = factor(c('lo', 'hi', 'lo'), levels = c('lo', 'mid', 'hi')))",
+    "list(x = factor(c('a', NA, 'b'), levels = c('b', 'a', 'z')))",
+    "list(x = factor(c('a', NA), exclude = NULL))",
+    "list(x = c(1, 2, 2, NA), y = c('u', 'v', NA, 'v'))",
+    "list(a = 1:3, b = factor(c('p', 'q', 'p'), levels = c('q', 'p', 'r')), c = c(TRUE, TRUE, NA))",
+];
+
+test_with_r! {
+    fn tabulates_like_r() {
+        for args in ARGS {
+            for use_na in ["no", "ifany", "always"] {
+                assert!(same_as_r(args, use_na)?, "table({}, useNA = '{}')", args, use_na);
+            }
+        }
+    }
+
+    fn rejects_bad_arguments() {
+        assert!(table_robj(&List::new(0), UseNa::No).is_err());
+        let unequal: List = R!("list(x = 1:2, y = 1:3)")?.try_into()?;
+        assert!(table_robj(&unequal, UseNa::No).is_err());
+        assert!("sometimes".parse::<UseNa>().is_err());
+    }
+}
+
+#[test]
+fn counts_in_column_major_order() {
+    let codes = vec![
+        vec![Some(0), Some(1), Some(1), None, Some(0)],
+        vec![Some(2), Some(0), Some(0), Some(1), Some(2)],
+    ];
+    assert_eq!(cross_count(&codes, &[2, 3]).unwrap(), [0, 2, 0, 0, 2, 0]);
+    assert_eq!(cross_count(&[], &[]).unwrap(), [0]);
+    assert_eq!(
+        cross_count(&[vec![Some(0), Some(5)]], &[2]).unwrap(),
+        [1, 0]
+    );
+    assert!(cross_count(&codes, &[2]).is_err());
+    assert!(cross_count(&[vec![], vec![None]], &[1, 1]).is_err());
+    assert!(cross_count(&[vec![], vec![]], &[usize::MAX, 2]).is_err());
+}
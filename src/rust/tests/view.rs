@@ -0,0 +1,84 @@
+//! Strided views, over slices and over R vectors.
+#![cfg(not(feature = "cran-strict"))]
+
+use extendr_api::prelude::*;
+use helloextendr::test_with_r;
+use helloextendr::view::{View, ViewExt};
+
+const DATA: [i32; 10] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+
+#[test]
+fn reads_every_stride_th_element() {
+    let view = View::new(&DATA, 1, 4, 2).unwrap();
+    assert_eq!(view.to_vec(), [1, 3, 5, 7]);
+    assert_eq!((view.len(), view.offset(), view.stride()), (4, 1, 2));
+    assert_eq!(view[3], 7);
+    assert_eq!(view.get(3), Some(7));
+    assert_eq!(view.get(4), None);
+    assert_eq!(view.iter().rev().collect::<Vec<_>>(), [7, 5, 3, 1]);
+    assert_eq!(View::of(&DATA).to_vec(), DATA);
+}
+
+#[test]
+fn fits_exactly_at_the_end() {
+    assert_eq!(View::new(&DATA, 9, 1, 5).unwrap().to_vec(), [9]);
+    assert_eq!(View::new(&DATA, 0, 4, 3).unwrap().to_vec(), [0, 3, 6, 9]);
+    assert!(View::new(&DATA, 10, 0, 1).unwrap().is_empty());
+    assert!(View::new(&[] as &[i32], 0, 0, 1).unwrap().is_empty());
+}
+
+#[test]
+fn rejects_views_out_of_range() {
+    assert!(View::new(&DATA, 0, 11, 1).is_err());
+    assert!(View::new(&DATA, 10, 1, 1).is_err());
+    assert!(View::new(&DATA, 11, 0, 1).is_err());
+    assert!(View::new(&DATA, 0, 5, 3).is_err());
+    assert!(View::new(&DATA, 0, 2, usize::MAX).is_err());
+    assert!(View::new(&DATA, usize::MAX, 1, 1).is_err());
+    let error = View::new(&DATA, 0, 1, 0).unwrap_err();
+    assert!(error.to_string().contains("stride"), "{}", error);
+}
+
+#[test]
+#[should_panic(expected = "out of bounds")]
+fn panics_on_indices_out_of_range() {
+    let view = View::new(&DATA, 0, 3, 3).unwrap();
+    let _ = view[3];
+}
+
+#[test]
+fn nests_views_in_view_coordinates() {
+    let odd = View::new(&DATA, 1, 5, 2).unwrap();
+    let nested = odd.view(1, 2, 2).unwrap();
+    assert_eq!(nested.to_vec(), [3, 7]);
+    assert_eq!((nested.offset(), nested.stride()), (3, 4));
+    assert!(odd.view(0, 6, 1).is_err());
+    assert!(odd.view(2, 2, 3).is_err());
+}
+
+#[test]
+fn makes_windows_like_slices() {
+    let view = View::new(&DATA, 0, 5, 2).unwrap();
+    let windows: Vec<Vec<i32>> = view.windows(3).map(View::to_vec).collect();
+    let expected: Vec<Vec<i32>> = [0, 2, 4, 6, 8].windows(3).map(<[i32]>::to_vec).collect();
+    assert_eq!(windows, expected);
+    assert_eq!(view.windows(5).len(), 1);
+    assert_eq!(view.windows(6).len(), 0);
+    assert_eq!(view.windows(0).len(), 0);
+}
+
+test_with_r! {
+    fn views_r_vectors_in_place() {
+        let x: Doubles = R!("as.double(1:10)")?.try_into()?;
+        let odd = x.view(0, 5, 2)?;
+        assert_eq!(odd.to_vec(), [1.0, 3.0, 5.0, 7.0, 9.0]);
+        assert!(x.view(1, 5, 3).is_err());
+
+        let m: Integers = R!("matrix(1:12, nrow = 3)")?.try_into()?;
+        // The second row, one element per column.
+        let row = m.view(1, 4, 3)?;
+        let theirs = R!("matrix(1:12, nrow = 3)[2, ]")?;
+        assert_eq!(Some(row.to_vec()), theirs.as_integer_vector());
+        assert!(m.view(12, 1, 1).is_err());
+    }
+}
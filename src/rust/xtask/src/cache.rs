@@ -0,0 +1,89 @@
+//! What discovery learns about installations, cached across builds.
+//!
+//! Finding R runs `R RHOME` for the `R` on the `PATH` and reads each
+//! installation's version and the header of its `libR`, which together
+//! take longer than many incremental builds. The results are kept in
+//! `probes.tsv` in the user cache directory, each with the modification
+//! times of the files it was computed from, and are recomputed when any of
+//! them changes, as it does when R is upgraded or reinstalled.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// The platform's user cache directory for this tool.
+pub fn user_cache_dir() -> Option<PathBuf> {
+    let base = if let Some(dir) = env::var_os("XDG_CACHE_HOME").filter(|d| !d.is_empty()) {
+        PathBuf::from(dir)
+    } else if cfg!(windows) {
+        PathBuf::from(env::var_os("LOCALAPPDATA")?)
+    } else if cfg!(target_os = "macos") {
+        Path::new(&env::var_os("HOME")?)
+            .join("Library")
+            .join("Caches")
+    } else {
+        Path::new(&env::var_os("HOME")?).join(".cache")
+    };
+    Some(base.join("librsys"))
+}
+
+/// The modification times of `paths`, which identify the state they were
+/// in. Missing files count too, as their appearing changes the result.
+fn fingerprint(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| {
+            fs::metadata(path)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| format!("{}.{:09}", d.as_secs(), d.subsec_nanos()))
+                .unwrap_or_else(|| "-".to_string())
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// The values cached for `kind` and `key` if `sources` have not changed
+/// since, or else those `compute` returns, which are cached for next time.
+/// Values must not contain tabs or newlines.
+pub fn cached<F>(kind: &str, key: &Path, sources: &[PathBuf], compute: F) -> Vec<String>
+where
+    F: FnOnce() -> Vec<String>,
+{
+    let path = match user_cache_dir() {
+        Some(dir) => dir.join("probes.tsv"),
+        None => return compute(),
+    };
+    let key = key.display().to_string();
+    let stamp = fingerprint(sources);
+    let text = fs::read_to_string(&path).unwrap_or_default();
+    let mut lines: Vec<&str> = Vec::new();
+    for line in text.lines() {
+        let mut fields = line.split('\t');
+        if fields.next() == Some(kind) && fields.next() == Some(key.as_str()) {
+            if fields.next() == Some(stamp.as_str()) {
+                return fields.map(str::to_string).collect();
+            }
+            // Stale: replaced below.
+            continue;
+        }
+        lines.push(line);
+    }
+
+    let values = compute();
+    let entry = [kind, &key, &stamp]
+        .iter()
+        .map(|s| s.to_string())
+        .chain(values.iter().cloned())
+        .collect::<Vec<_>>()
+        .join("\t");
+    lines.push(&entry);
+    // A cache that cannot be written only costs time.
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    let _ = fs::write(&path, lines.join("\n") + "\n");
+    values
+}
@@ -0,0 +1,296 @@
+//! `doctor`: a report of the build environment, to paste into bug reports.
+//!
+//! It lists the R installations found and the one selected, the versions
+//! and paths of the Rust toolchain, bindgen and libclang, the environment
+//! variables that change how the crate is built and the features of the
+//! crate, and what the last build of the crate recorded in its probe
+//! manifest, and then checks that a program linking `libR` builds and loads.
+//! Every probe reports its failure in the report instead of stopping it.
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::{fetch, link, rconfig, rhome};
+
+/// The environment variables that affect discovery, linking and bindings.
+const VARIABLES: &[&str] = &[
+    "R_HOME",
+    rhome::VERSION_VAR,
+    link::LINK_VAR,
+    fetch::URL_VAR,
+    rhome::WEBR_ROOT_VAR,
+    "LIBCLANG_PATH",
+    "BINDGEN_EXTRA_CLANG_ARGS",
+    "CARGO_BUILD_TARGET",
+    "RUSTFLAGS",
+];
+
+/// The report for the crate of `manifest`.
+pub fn report(manifest: &Path) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "```");
+    let _ = writeln!(out, "platform: {} {}", env::consts::OS, env::consts::ARCH);
+
+    let _ = writeln!(out, "\n# R");
+    let installations = rhome::find_installations();
+    let requested = env::var(rhome::VERSION_VAR).ok().filter(|v| !v.is_empty());
+    let selected = rhome::select(&installations, requested.as_deref());
+    for installation in &installations {
+        let marker = match &selected {
+            Ok(s) if *s == installation => '*',
+            _ => ' ',
+        };
+        let _ = writeln!(out, "{marker} {installation}");
+    }
+    if let Err(e) = &selected {
+        let _ = writeln!(out, "error: {e}");
+    }
+
+    if let Ok(installation) = &selected {
+        let _ = writeln!(out, "\n# R CMD config");
+        match rconfig::config(installation) {
+            Some(config) => {
+                for (name, value) in &config {
+                    let _ = writeln!(out, "{name}: {value}");
+                }
+                let args = rconfig::join(&rconfig::clang_args(&config));
+                let _ = writeln!(out, "clang args for bindgen: {args}");
+            }
+            None => {
+                let _ = writeln!(out, "not available for this installation");
+            }
+        }
+    }
+
+    let _ = writeln!(out, "\n# toolchain");
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".into());
+    for (name, program, args) in [
+        ("rustc", rustc.as_str(), &["-vV"][..]),
+        ("cargo", cargo.as_str(), &["-V"][..]),
+        ("rustup", "rustup", &["show", "active-toolchain"][..]),
+        ("bindgen", "bindgen", &["--version"][..]),
+        ("clang", "clang", &["--version"][..]),
+        ("llvm-config", "llvm-config", &["--version"][..]),
+    ] {
+        let path = which(program)
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|| "not on PATH".into());
+        let _ = writeln!(out, "{name}: {path}");
+        for line in version(program, args).lines() {
+            let _ = writeln!(out, "  {line}");
+        }
+    }
+    let _ = writeln!(out, "libclang: {}", libclang());
+
+    let _ = writeln!(out, "\n# environment");
+    for name in VARIABLES {
+        let value = env::var(name).unwrap_or_else(|_| "(unset)".into());
+        let _ = writeln!(out, "{name}={value}");
+    }
+
+    let _ = writeln!(out, "\n# features of {}", manifest.display());
+    match fs::read_to_string(manifest) {
+        Ok(text) => {
+            for feature in features(&text) {
+                let _ = writeln!(out, "{feature}");
+            }
+        }
+        Err(e) => {
+            let _ = writeln!(out, "error: {e}");
+        }
+    }
+
+    let _ = writeln!(out, "\n# probe manifest of the last build");
+    match last_probe_manifest(manifest) {
+        Some(path) => {
+            let _ = writeln!(out, "{}", path.display());
+            match fs::read_to_string(&path) {
+                Ok(text) => {
+                    let _ = write!(out, "{text}");
+                }
+                Err(e) => {
+                    let _ = writeln!(out, "error: {e}");
+                }
+            }
+        }
+        None => {
+            let _ = writeln!(out, "none: the crate was not built yet");
+        }
+    }
+
+    let _ = writeln!(out, "\n# link smoke test");
+    let result = match &selected {
+        Ok(installation) => smoke_test(installation),
+        Err(_) => Err("skipped: no R installation".into()),
+    };
+    let _ = writeln!(
+        out,
+        "{}",
+        match result {
+            Ok(()) => "ok: a program linking libR builds and loads".to_string(),
+            Err(e) => format!("failed: {e}"),
+        }
+    );
+    let _ = writeln!(out, "```");
+    out
+}
+
+fn which(program: &str) -> Option<PathBuf> {
+    let program = Path::new(program);
+    if program.components().count() > 1 {
+        return Some(program.to_path_buf());
+    }
+    let exe = if cfg!(windows) {
+        program.with_extension("exe")
+    } else {
+        program.to_path_buf()
+    };
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(&exe))
+        .find(|path| path.is_file())
+}
+
+/// The output of `program args`, or why there is none.
+fn version(program: &str, args: &[&str]) -> String {
+    match Command::new(program).args(args).output() {
+        Ok(output) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout).trim().to_string()
+        }
+        Ok(output) => format!("exited with {}", output.status),
+        Err(e) => format!("cannot run: {e}"),
+    }
+}
+
+/// Where bindgen would load libclang from: `LIBCLANG_PATH`, or the library
+/// directory `llvm-config` reports.
+fn libclang() -> String {
+    if let Ok(path) = env::var("LIBCLANG_PATH") {
+        return format!("{path} (LIBCLANG_PATH)");
+    }
+    match Command::new("llvm-config").arg("--libdir").output() {
+        Ok(output) if output.status.success() => format!(
+            "{} (llvm-config)",
+            String::from_utf8_lossy(&output.stdout).trim()
+        ),
+        _ => "not found; set LIBCLANG_PATH if bindings are generated".into(),
+    }
+}
+
+/// The `[features]` of a manifest, as `name = [ ... ]` lines.
+fn features(manifest: &str) -> Vec<String> {
+    let mut in_features = false;
+    let mut features = Vec::new();
+    for line in manifest.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_features = line == "[features]";
+        } else if in_features && !line.is_empty() && !line.starts_with('#') {
+            features.push(line.to_string());
+        }
+    }
+    features
+}
+
+/// The newest `probe-manifest.json` written by the build script of the
+/// crate of `manifest`, in `CARGO_TARGET_DIR` or the `target` directory
+/// next to it, for any target and profile.
+fn last_probe_manifest(manifest: &Path) -> Option<PathBuf> {
+    let target_dir = env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| manifest.parent().unwrap_or(Path::new(".")).join("target"));
+    let mut found = Vec::new();
+    find_probe_manifests(&target_dir, 0, &mut found);
+    found
+        .into_iter()
+        .filter_map(|path| Some((fs::metadata(&path).ok()?.modified().ok()?, path)))
+        .max()
+        .map(|(_, path)| path)
+}
+
+/// The manifests in `<dir>/[<target>/]<profile>/build/<package>/out`.
+fn find_probe_manifests(dir: &Path, depth: usize, found: &mut Vec<PathBuf>) {
+    let path = dir.join("probe-manifest.json");
+    if dir.ends_with("out") && path.is_file() {
+        found.push(path);
+        return;
+    }
+    if depth == 5 {
+        return;
+    }
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        // Dependencies are built in `deps`, which holds no build outputs.
+        if path.is_dir() && !path.ends_with("deps") && !path.ends_with("incremental") {
+            find_probe_manifests(&path, depth + 1, found);
+        }
+    }
+}
+
+/// Build and run a program that calls into `libR` without starting R,
+/// which fails if `libR` cannot be linked or loaded.
+fn smoke_test(installation: &rhome::Installation) -> Result<(), String> {
+    let dir = env::temp_dir().join(format!("xtask-doctor-{}", std::process::id()));
+    fs::create_dir_all(&dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+    let result = build_and_run(installation, &dir);
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+fn build_and_run(installation: &rhome::Installation, dir: &Path) -> Result<(), String> {
+    let source = dir.join("smoke.rs");
+    fs::write(
+        &source,
+        "extern \"C\" {\n    fn R_IsNA(x: f64) -> i32;\n}\n\n\
+         fn main() {\n    assert_eq!(unsafe { R_IsNA(1.0) }, 0);\n}\n",
+    )
+    .map_err(|e| format!("{}: {e}", source.display()))?;
+    let lib_dir = fetch::library_dir(&installation.home);
+    let exe = dir.join(if cfg!(windows) { "smoke.exe" } else { "smoke" });
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+    let output = Command::new(rustc)
+        .arg(&source)
+        .arg("-o")
+        .arg(&exe)
+        .arg("-L")
+        .arg(&lib_dir)
+        .args(["-l", "dylib=R"])
+        .output()
+        .map_err(|e| format!("cannot run rustc: {e}"))?;
+    if !output.status.success() {
+        // The linker's full command line is noise in a report.
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let errors: Vec<&str> = stderr
+            .lines()
+            .map(str::trim)
+            .filter(|line| line.contains("error"))
+            .collect();
+        return Err(format!(
+            "cannot link libR from {}:\n{}",
+            lib_dir.display(),
+            errors.join("\n")
+        ));
+    }
+
+    let (var, path) = fetch::loader_path(&lib_dir)?;
+    let output = Command::new(&exe)
+        .env(var, path)
+        .env("R_HOME", &installation.home)
+        .output()
+        .map_err(|e| format!("cannot run the linked program: {e}"))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "the linked program exited with {}:\n{}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
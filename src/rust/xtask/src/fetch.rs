@@ -0,0 +1,148 @@
+//! A minimal R downloaded for CI machines that have none installed.
+//!
+//! Installing R is slow or impossible on some CI runners, and building and
+//! testing the crate only needs R's headers and its shared library. When
+//! no installation is found and `LIBRSYS_DOWNLOAD_R` gives the URL of an
+//! archive with those, [`fetch()`] downloads it with `curl`, checks it
+//! against `LIBRSYS_DOWNLOAD_R_SHA256` and unpacks it with `tar` into the
+//! user cache directory, where later builds find it without downloading.
+//! Nothing is downloaded unless both variables are set.
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::cache::user_cache_dir;
+use crate::matrix::sha256_file;
+
+/// The URL of the archive, a `.tar.gz`, `.tar.xz` or `.zip` that `tar`
+/// can unpack, holding `include` and `lib` (or `bin` on Windows) either at
+/// its top level or below one directory or `lib/R`.
+pub const URL_VAR: &str = "LIBRSYS_DOWNLOAD_R";
+
+/// The SHA-256 of the archive, in hex.
+pub const SHA256_VAR: &str = "LIBRSYS_DOWNLOAD_R_SHA256";
+
+/// The R home of the archive, downloaded if it is not cached yet, or
+/// `None` if the download is not configured.
+pub fn fetch() -> Option<Result<PathBuf, String>> {
+    let url = env::var(URL_VAR).ok().filter(|url| !url.is_empty())?;
+    let sha256 = match env::var(SHA256_VAR) {
+        Ok(sha256) if !sha256.is_empty() => sha256.to_ascii_lowercase(),
+        _ => {
+            return Some(Err(format!(
+                "{URL_VAR} is set but {SHA256_VAR} is not; downloads must be pinned"
+            )))
+        }
+    };
+    Some(fetch_pinned(&url, &sha256))
+}
+
+fn fetch_pinned(url: &str, sha256: &str) -> Result<PathBuf, String> {
+    let cache = user_cache_dir()
+        .ok_or("cannot find the user cache directory")?
+        .join("r")
+        .join(sha256);
+    if let Some(home) = find_home(&cache) {
+        return Ok(home);
+    }
+
+    // Unpacked next to the cache entry and renamed once complete, so that an
+    // interrupted download is never mistaken for a cached one.
+    let parent = cache.parent().unwrap_or(&cache);
+    let staging = parent.join(format!(".{sha256}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&staging);
+    fs::create_dir_all(&staging).map_err(|e| format!("{}: {e}", staging.display()))?;
+    let result = download_into(url, sha256, &staging);
+    if let Err(e) = result {
+        let _ = fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+    let _ = fs::remove_dir_all(&cache);
+    fs::rename(&staging, &cache).map_err(|e| format!("{}: {e}", cache.display()))?;
+    find_home(&cache).ok_or_else(|| format!("{url} does not contain R's headers"))
+}
+
+fn download_into(url: &str, sha256: &str, staging: &Path) -> Result<(), String> {
+    let name = url.rsplit('/').next().unwrap_or("R.tar.gz");
+    let archive = staging.join(name);
+    eprintln!("downloading R from {url}");
+    run(Command::new("curl")
+        .args([
+            "--fail",
+            "--silent",
+            "--show-error",
+            "--location",
+            "--output",
+        ])
+        .arg(&archive)
+        .arg(url))?;
+    let actual = sha256_file(&archive)?;
+    if actual != sha256 {
+        return Err(format!(
+            "checksum mismatch for {url}: expected {sha256}, got {actual}"
+        ));
+    }
+    run(Command::new("tar")
+        .arg("-xf")
+        .arg(&archive)
+        .arg("-C")
+        .arg(staging))?;
+    fs::remove_file(&archive).map_err(|e| format!("{}: {e}", archive.display()))
+}
+
+fn run(command: &mut Command) -> Result<(), String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let status = command
+        .status()
+        .map_err(|e| format!("cannot run {program}: {e}"))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{program} exited with {status}"))
+    }
+}
+
+/// The directory below `dir` that holds R's headers.
+fn find_home(dir: &Path) -> Option<PathBuf> {
+    let mut candidates = vec![dir.to_path_buf(), dir.join("lib").join("R")];
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.filter_map(|e| e.ok()) {
+            candidates.push(entry.path());
+            candidates.push(entry.path().join("lib").join("R"));
+        }
+    }
+    candidates
+        .into_iter()
+        .find(|home| home.join("include").join("Rinternals.h").is_file())
+}
+
+/// The directory with R's shared library, for the dynamic loader to find
+/// it when tests run.
+pub fn library_dir(home: &Path) -> PathBuf {
+    if cfg!(windows) {
+        home.join("bin").join("x64")
+    } else {
+        home.join("lib")
+    }
+}
+
+/// The variable the dynamic loader searches for shared libraries and its
+/// value with `dir` prepended.
+pub fn loader_path(dir: &Path) -> Result<(&'static str, OsString), String> {
+    let var = if cfg!(windows) {
+        "PATH"
+    } else if cfg!(target_os = "macos") {
+        "DYLD_LIBRARY_PATH"
+    } else {
+        "LD_LIBRARY_PATH"
+    };
+    let mut paths = vec![dir.to_path_buf()];
+    if let Some(existing) = env::var_os(var) {
+        paths.extend(env::split_paths(&existing));
+    }
+    let joined = env::join_paths(paths).map_err(|e| e.to_string())?;
+    Ok((var, joined))
+}
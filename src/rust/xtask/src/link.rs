@@ -0,0 +1,228 @@
+//! How the crate links against R.
+//!
+//! extendr's build script always links `libR` dynamically. For the other
+//! modes, `with-r` overrides that build script through Cargo's `links`
+//! overrides (`target.<triple>.R` in the configuration), passing what the
+//! script would have emitted with different linker instructions:
+//!
+//! * [`LinkMode::Static`] links `libR.a` and the libraries it depends on,
+//!   for R builds configured with `--enable-R-static-lib`, as in Alpine
+//!   containers and some conda builds.
+//! * [`LinkMode::Host`] does not link R at all and leaves its symbols to be
+//!   resolved by the R process that loads the library. Only libraries can
+//!   be built this way, not test or other executables.
+//! * [`LinkMode::Webr`] builds for `wasm32-unknown-emscripten` against the
+//!   R of a webR build tree, linking its `libR.a` when the tree has one.
+//!   That R cannot run on the build machine, so it is found and its version
+//!   read from its files alone.
+//!
+//! A minimal R downloaded by [`fetch`](crate::fetch) has no `R` for the
+//! build script to run, so even [`LinkMode::Dylib`] goes through the
+//! overrides for it.
+
+use std::fmt;
+use std::process::Command;
+use std::str::FromStr;
+
+use helloextendr_sysdeps::r_static_dependencies;
+
+use crate::fetch;
+use crate::rhome::{Installation, Source};
+
+/// The environment variable selecting the link mode.
+pub const LINK_VAR: &str = "LIBRSYS_LINK";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkMode {
+    /// Link the shared `libR`, as extendr's build script does.
+    Dylib,
+    /// Link `libR.a`.
+    Static,
+    /// Resolve R's symbols from the hosting process when loaded.
+    Host,
+    /// Build for webR, R compiled to WebAssembly with Emscripten.
+    Webr,
+}
+
+/// The target webR packages are built for.
+pub const WEBR_TARGET: &str = "wasm32-unknown-emscripten";
+
+impl FromStr for LinkMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.trim() {
+            "" | "dylib" => Ok(LinkMode::Dylib),
+            "static" => Ok(LinkMode::Static),
+            "host" => Ok(LinkMode::Host),
+            "webr" => Ok(LinkMode::Webr),
+            other => Err(format!(
+                "unknown link mode `{other}`: use `dylib`, `static`, `host` or `webr`"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for LinkMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            LinkMode::Dylib => "dylib",
+            LinkMode::Static => "static",
+            LinkMode::Host => "host",
+            LinkMode::Webr => "webr",
+        })
+    }
+}
+
+/// The `--config` arguments making cargo link `installation` in `mode`
+/// when building for `target`, or none for [`LinkMode::Dylib`], which
+/// extendr's build script handles itself unless R was downloaded.
+pub fn cargo_config_args(
+    installation: &Installation,
+    mode: LinkMode,
+    target: &str,
+) -> Result<Vec<String>, String> {
+    let mut libs: Vec<String> = Vec::new();
+    let mut search: Vec<String> = Vec::new();
+    let mut cdylib_args: Vec<String> = Vec::new();
+    match mode {
+        LinkMode::Dylib if installation.source != Source::Download => return Ok(Vec::new()),
+        LinkMode::Dylib => {
+            search.push(fetch::library_dir(&installation.home).display().to_string());
+            libs.push("dylib=R".into());
+        }
+        LinkMode::Static => {
+            let lib = installation.home.join("lib");
+            if !lib.join("libR.a").is_file() {
+                return Err(format!(
+                    "static linking needs {}, which R only installs when \
+                     configured with --enable-R-static-lib",
+                    lib.join("libR.a").display()
+                ));
+            }
+            search.push(lib.display().to_string());
+            libs.push("static=R".into());
+            let dependencies = r_static_dependencies(&installation.home);
+            libs.extend(dependencies.libs);
+            search.extend(
+                dependencies
+                    .link_paths
+                    .iter()
+                    .map(|dir| dir.display().to_string()),
+            );
+        }
+        LinkMode::Host => {
+            if target.contains("windows") {
+                return Err(
+                    "the host link mode is not available on Windows, where DLLs \
+                     must link against R.dll"
+                        .into(),
+                );
+            }
+            // Linux allows undefined symbols in shared libraries, macOS has
+            // to be told to look them up at load time.
+            if target.contains("apple") {
+                cdylib_args.push("-Wl,-undefined,dynamic_lookup".into());
+            }
+        }
+        LinkMode::Webr => {
+            if target != WEBR_TARGET {
+                return Err(format!(
+                    "webR packages are built for {WEBR_TARGET}, not {target}"
+                ));
+            }
+            // Packages are side modules whose R symbols the webR main
+            // module provides; executables such as tests need libR.a.
+            let lib = installation.home.join("lib");
+            if lib.join("libR.a").is_file() {
+                search.push(lib.display().to_string());
+                libs.push("static=R".into());
+            }
+        }
+    }
+
+    let version = installation
+        .version
+        .as_deref()
+        .ok_or_else(|| format!("cannot read the version of {}", installation.home.display()))?;
+    let parts: Vec<u32> = version.split('.').filter_map(|p| p.parse().ok()).collect();
+    let (major, minor, patch) = match parts[..] {
+        [major, minor, patch, ..] => (major, minor, patch),
+        _ => return Err(format!("cannot parse R version {version}")),
+    };
+
+    // The configuration flags that extendr's build script sets by version.
+    let mut cfgs = Vec::new();
+    for (cfg, since) in [
+        ("r_4_4", (4, 4)),
+        ("r_4_5", (4, 5)),
+        ("use_r_ge_version_15", (4, 2)),
+        ("use_r_ge_version_16", (4, 3)),
+        ("use_r_ge_version_17", (4, 6)),
+    ] {
+        if (major, minor) >= since {
+            cfgs.push(cfg.to_string());
+        }
+    }
+
+    let home = toml_string(&installation.home.display().to_string());
+    let settings = vec![
+        ("rustc-link-lib", toml_array(&libs)),
+        ("rustc-link-search", toml_array(&search)),
+        ("rustc-cdylib-link-arg", toml_array(&cdylib_args)),
+        ("rustc-cfg", toml_array(&cfgs)),
+        ("rustc-env.R_HOME", home.clone()),
+        // The metadata that extendr-api reads as `DEP_R_*`.
+        ("r_home", home),
+        ("r_version_major", toml_string(&major.to_string())),
+        ("r_version_minor", toml_string(&minor.to_string())),
+        ("r_version_patch", toml_string(&patch.to_string())),
+    ];
+    Ok(settings
+        .into_iter()
+        .flat_map(|(name, value)| {
+            vec![
+                "--config".to_string(),
+                format!("target.{target}.R.{name}={value}"),
+            ]
+        })
+        .collect())
+}
+
+/// The target triple from `--target` in `cargo_args`, `CARGO_BUILD_TARGET`
+/// or the host triple of rustc.
+pub fn target_triple(cargo_args: &[String]) -> Result<String, String> {
+    let mut args = cargo_args.iter();
+    while let Some(arg) = args.next() {
+        if let Some(target) = arg.strip_prefix("--target=") {
+            return Ok(target.to_string());
+        }
+        if arg == "--target" {
+            if let Some(target) = args.next() {
+                return Ok(target.clone());
+            }
+        }
+    }
+    if let Ok(target) = std::env::var("CARGO_BUILD_TARGET") {
+        return Ok(target);
+    }
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+    let output = Command::new(rustc)
+        .arg("-vV")
+        .output()
+        .map_err(|e| format!("cannot run rustc: {e}"))?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .map(str::to_string)
+        .ok_or_else(|| "cannot determine the host target of rustc".into())
+}
+
+fn toml_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn toml_array(values: &[String]) -> String {
+    let values: Vec<String> = values.iter().map(|v| toml_string(v)).collect();
+    format!("[{}]", values.join(", "))
+}
@@ -0,0 +1,260 @@
+//! Development tasks for the helloextendr crate, run as `cargo xtask <task>`.
+
+mod cache;
+mod doctor;
+mod fetch;
+mod link;
+mod matrix;
+mod new;
+mod rconfig;
+mod rhome;
+
+use std::path::PathBuf;
+use std::process::{Command, ExitCode};
+
+const USAGE: &str = "\
+usage: cargo xtask <task> [args]
+
+tasks:
+  r-home [--diagnostics]  print the R_HOME to build against; with
+                          --diagnostics, list every R installation found
+  with-r [--link <mode>] <cargo args>
+                          run cargo with R_HOME set to that installation,
+                          linking R as `dylib` (the default), `static`
+                          (libR.a), `host` (symbols resolved by the R
+                          process loading the library) or `webr` (for
+                          wasm32-unknown-emscripten, against the R of
+                          the webR tree in WEBR_ROOT)
+  r-new <name> [--path <dir>]
+                          create the R package <name> with a Rust crate
+                          in <dir>/<name>, by default in the current
+                          directory
+  build-all [--manifest-path <path>] [--out <dir>] [--base-url <url>]
+            [--only <platform>]...
+                          build the static library for every platform
+                          of build-matrix.txt into <dir>/<platform>,
+                          by default in target/dist, with checksums in
+                          SHA256SUMS and, given the URL the libraries
+                          are published under, a prebuilt.txt
+  doctor [--manifest-path <path>]
+                          print a report of the R installations,
+                          toolchain, environment and crate features,
+                          with a libR link test, for bug reports
+  help                    show this message
+
+Set LIBRSYS_R_VERSION (e.g. 4.3 or 4.3.2) to pick the newest matching
+installation instead of R_HOME or the R on the PATH, and LIBRSYS_LINK
+to choose the link mode without --link. On CI machines without R, set
+LIBRSYS_DOWNLOAD_R to the URL of an archive of R's headers and shared
+library and LIBRSYS_DOWNLOAD_R_SHA256 to its checksum to download it
+when no installation is found.";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("r-home") => r_home(args.iter().any(|a| a == "--diagnostics")),
+        Some("with-r") => with_r(&args[1..]),
+        Some("r-new") => r_new(&args[1..]),
+        Some("build-all") => build_all(&args[1..]),
+        Some("doctor") => doctor(&args[1..]),
+        Some("help") | None => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        Some(other) => Err(format!("unknown task `{other}`\n\n{USAGE}")),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("error: {message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn requested_version() -> Option<String> {
+    std::env::var(rhome::VERSION_VAR)
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+}
+
+fn r_home(diagnostics: bool) -> Result<(), String> {
+    let installations = rhome::find_installations();
+    let requested = requested_version();
+    let selected = rhome::select(&installations, requested.as_deref());
+    if diagnostics {
+        match &requested {
+            Some(version) => println!("{}={version}", rhome::VERSION_VAR),
+            None => println!("{} is not set", rhome::VERSION_VAR),
+        }
+        println!("  {:<8} {:<8} {:<9} R_HOME", "version", "arch", "source");
+        for installation in &installations {
+            let marker = match &selected {
+                Ok(s) if *s == installation => '*',
+                _ => ' ',
+            };
+            println!("{marker} {installation}");
+        }
+    }
+    let selected = selected?;
+    if !diagnostics {
+        println!("{}", selected.home.display());
+    }
+    Ok(())
+}
+
+fn with_r(args: &[String]) -> Result<(), String> {
+    let (mode, cargo_args) = match args.first().map(String::as_str) {
+        Some("--link") => (
+            args.get(1).ok_or("--link needs a mode")?.clone(),
+            &args[2..],
+        ),
+        Some(arg) if arg.starts_with("--link=") => (arg["--link=".len()..].to_string(), &args[1..]),
+        _ => (std::env::var(link::LINK_VAR).unwrap_or_default(), args),
+    };
+    let mode: link::LinkMode = mode.parse()?;
+    if mode == link::LinkMode::Webr {
+        return with_webr(cargo_args);
+    }
+    let installations = rhome::find_installations();
+    let selected = rhome::select(&installations, requested_version().as_deref())?;
+    let config_args = match mode {
+        link::LinkMode::Dylib if selected.source != rhome::Source::Download => Vec::new(),
+        _ => link::cargo_config_args(selected, mode, &link::target_triple(cargo_args)?)?,
+    };
+    run_cargo(
+        &config_args,
+        cargo_args,
+        selected,
+        &clang_env(selected, cargo_args),
+    )
+}
+
+/// `BINDGEN_EXTRA_CLANG_ARGS` from the flags of `R CMD config`, unless it
+/// is set already or the build is for another target than the one R runs
+/// on, whose flags would be wrong for it.
+fn clang_env(selected: &rhome::Installation, cargo_args: &[String]) -> Vec<(&'static str, String)> {
+    let cross = cargo_args
+        .iter()
+        .any(|arg| arg == "--target" || arg.starts_with("--target="))
+        || std::env::var_os("CARGO_BUILD_TARGET").is_some();
+    if cross || std::env::var_os("BINDGEN_EXTRA_CLANG_ARGS").is_some() {
+        return Vec::new();
+    }
+    match rconfig::config(selected) {
+        Some(config) => vec![(
+            "BINDGEN_EXTRA_CLANG_ARGS",
+            rconfig::join(&rconfig::clang_args(&config)),
+        )],
+        None => Vec::new(),
+    }
+}
+
+/// `with-r --link webr`, which neither runs R nor asks rustc for its host:
+/// the target is `wasm32-unknown-emscripten` unless given.
+fn with_webr(cargo_args: &[String]) -> Result<(), String> {
+    let selected = rhome::webr_installation(requested_version().as_deref())?;
+    let has_target = cargo_args
+        .iter()
+        .any(|arg| arg == "--target" || arg.starts_with("--target="));
+    let mut config_args = Vec::new();
+    let target = if has_target || std::env::var_os("CARGO_BUILD_TARGET").is_some() {
+        link::target_triple(cargo_args)?
+    } else {
+        config_args.push(format!("--config=build.target=\"{}\"", link::WEBR_TARGET));
+        link::WEBR_TARGET.to_string()
+    };
+    config_args.extend(link::cargo_config_args(
+        &selected,
+        link::LinkMode::Webr,
+        &target,
+    )?);
+    run_cargo(&config_args, cargo_args, &selected, &[])
+}
+
+fn run_cargo(
+    config_args: &[String],
+    cargo_args: &[String],
+    selected: &rhome::Installation,
+    env: &[(&str, String)],
+) -> Result<(), String> {
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".into());
+    let mut command = Command::new(cargo);
+    command
+        .args(config_args)
+        .args(cargo_args)
+        .env("R_HOME", &selected.home)
+        .envs(env.iter().map(|(name, value)| (name, value)));
+    // Nothing else tells the loader where a downloaded libR is when the
+    // tests run.
+    if selected.source == rhome::Source::Download {
+        let (var, path) = fetch::loader_path(&fetch::library_dir(&selected.home))?;
+        command.env(var, path);
+    }
+    let status = command
+        .status()
+        .map_err(|e| format!("cannot run cargo: {e}"))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("cargo exited with {status}"))
+    }
+}
+
+fn r_new(args: &[String]) -> Result<(), String> {
+    let mut name = None;
+    let mut dir = PathBuf::from(".");
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--path" => {
+                dir = args.next().ok_or("--path needs a directory")?.into();
+            }
+            _ if name.is_none() => name = Some(arg.as_str()),
+            other => return Err(format!("unexpected argument `{other}`\n\n{USAGE}")),
+        }
+    }
+    let name = name.ok_or_else(|| format!("r-new needs a package name\n\n{USAGE}"))?;
+    let root = new::scaffold(name, &dir)?;
+    println!("created {}", root.display());
+    println!("build it with `R CMD INSTALL {}`", root.display());
+    Ok(())
+}
+
+fn build_all(args: &[String]) -> Result<(), String> {
+    let mut options = matrix::Options {
+        manifest: PathBuf::from("Cargo.toml"),
+        out: PathBuf::from("target").join("dist"),
+        base_url: None,
+        only: Vec::new(),
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("{arg} needs a value"))
+        };
+        match arg.as_str() {
+            "--manifest-path" => options.manifest = value()?.into(),
+            "--out" => options.out = value()?.into(),
+            "--base-url" => options.base_url = Some(value()?),
+            "--only" => options.only.push(value()?),
+            other => return Err(format!("unexpected argument `{other}`\n\n{USAGE}")),
+        }
+    }
+    for library in matrix::build_all(&options)? {
+        println!("built {}", library.display());
+    }
+    Ok(())
+}
+
+fn doctor(args: &[String]) -> Result<(), String> {
+    let manifest = match args {
+        [] => PathBuf::from("Cargo.toml"),
+        [flag, path] if flag == "--manifest-path" => PathBuf::from(path),
+        _ => return Err(format!("unexpected arguments\n\n{USAGE}")),
+    };
+    print!("{}", doctor::report(&manifest));
+    Ok(())
+}
@@ -0,0 +1,264 @@
+//! `build-all`: build the package's static library for every platform it
+//! is distributed for.
+//!
+//! The platforms are listed in `build-matrix.txt` next to the crate's
+//! `Cargo.toml`, or are [`DEFAULT_MATRIX`] when there is no such file. Each
+//! line names a platform, the target triples built for it and, optionally,
+//! the R installation whose headers and version to build against, which
+//! is how cross builds get the R of their target rather than that of the
+//! host:
+//!
+//! ```text
+//! linux-aarch64    aarch64-unknown-linux-gnu  R_HOME=/opt/sysroots/arm64/usr/lib/R
+//! darwin-universal aarch64-apple-darwin x86_64-apple-darwin
+//! ```
+//!
+//! A platform with several targets is a macOS universal binary, whose
+//! slices are combined with `lipo` (or `$LIPO`). Each library is written to
+//! `<out>/<platform>/lib<crate>.a` and its checksum to `<out>/SHA256SUMS`;
+//! with a base URL, `<out>/prebuilt.txt` lists them in the format of the
+//! `tools/prebuilt.txt` of packages made by `r-new`.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use sha2::{Digest, Sha256};
+
+use crate::rhome;
+
+/// The platforms built when the crate has no `build-matrix.txt`.
+pub const DEFAULT_MATRIX: &str = "\
+linux-x86_64     x86_64-unknown-linux-gnu
+linux-aarch64    aarch64-unknown-linux-gnu
+darwin-universal aarch64-apple-darwin x86_64-apple-darwin
+windows-x86_64   x86_64-pc-windows-gnu
+";
+
+/// A platform of the matrix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Platform {
+    pub name: String,
+    pub targets: Vec<String>,
+    /// The R installation to build against, instead of the one `R_HOME`
+    /// or the `PATH` gives.
+    pub r_home: Option<PathBuf>,
+}
+
+/// Parse a matrix, skipping blank lines and `#` comments.
+pub fn parse(text: &str) -> Result<Vec<Platform>, String> {
+    let mut platforms = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut fields = line.split_whitespace();
+        let name = match fields.next() {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        let mut targets = Vec::new();
+        let mut r_home = None;
+        for field in fields {
+            match field.strip_prefix("R_HOME=") {
+                Some(home) => r_home = Some(PathBuf::from(home)),
+                None => targets.push(field.to_string()),
+            }
+        }
+        if targets.is_empty() {
+            return Err(format!("line {}: `{name}` has no target", n + 1));
+        }
+        platforms.push(Platform {
+            name,
+            targets,
+            r_home,
+        });
+    }
+    Ok(platforms)
+}
+
+/// What to build and where to put it.
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub manifest: PathBuf,
+    pub out: PathBuf,
+    /// The URL the libraries will be published under, for `prebuilt.txt`.
+    pub base_url: Option<String>,
+    /// Only build these platforms, or all of them if empty.
+    pub only: Vec<String>,
+}
+
+/// Build every platform of the matrix of the crate and return the
+/// libraries written.
+pub fn build_all(options: &Options) -> Result<Vec<PathBuf>, String> {
+    let crate_dir = options
+        .manifest
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let matrix = match fs::read_to_string(crate_dir.join("build-matrix.txt")) {
+        Ok(text) => text,
+        Err(_) => DEFAULT_MATRIX.to_string(),
+    };
+    let mut platforms = parse(&matrix)?;
+    if !options.only.is_empty() {
+        if let Some(unknown) = options
+            .only
+            .iter()
+            .find(|name| !platforms.iter().any(|p| &p.name == *name))
+        {
+            return Err(format!("`{unknown}` is not in the build matrix"));
+        }
+        platforms.retain(|p| options.only.contains(&p.name));
+    }
+    let lib_name = format!("lib{}.a", crate_name(&options.manifest)?.replace('-', "_"));
+    let target_dir = crate_dir.join("target");
+
+    let mut written = Vec::new();
+    let mut sums = String::new();
+    let mut prebuilt = String::new();
+    for platform in &platforms {
+        let mut slices = Vec::new();
+        for target in &platform.targets {
+            cargo_build(
+                &options.manifest,
+                &target_dir,
+                target,
+                platform.r_home.as_deref(),
+            )?;
+            slices.push(target_dir.join(target).join("release").join(&lib_name));
+        }
+        let dir = options.out.join(&platform.name);
+        fs::create_dir_all(&dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+        let dest = dir.join(&lib_name);
+        if let [slice] = &slices[..] {
+            fs::copy(slice, &dest).map_err(|e| format!("{}: {e}", slice.display()))?;
+        } else {
+            lipo(&slices, &dest)?;
+        }
+
+        let sum = sha256_file(&dest)?;
+        let relative = format!("{}/{lib_name}", platform.name);
+        let _ = writeln!(sums, "{sum}  {relative}");
+        if let Some(base_url) = &options.base_url {
+            let version = platform
+                .r_home
+                .as_deref()
+                .and_then(rhome::read_version)
+                .map(|v| v.splitn(3, '.').take(2).collect::<Vec<_>>().join("."))
+                .unwrap_or_else(|| "*".to_string());
+            let url = format!("{}/{relative}", base_url.trim_end_matches('/'));
+            for name in prebuilt_names(platform) {
+                let _ = writeln!(prebuilt, "{name} {version} {sum} {url}");
+            }
+        }
+        written.push(dest);
+    }
+
+    let path = options.out.join("SHA256SUMS");
+    fs::write(&path, sums).map_err(|e| format!("{}: {e}", path.display()))?;
+    if options.base_url.is_some() {
+        let path = options.out.join("prebuilt.txt");
+        fs::write(&path, prebuilt).map_err(|e| format!("{}: {e}", path.display()))?;
+    }
+    Ok(written)
+}
+
+/// The `[package]` name in the manifest.
+fn crate_name(manifest: &Path) -> Result<String, String> {
+    let text = fs::read_to_string(manifest).map_err(|e| format!("{}: {e}", manifest.display()))?;
+    let mut in_package = false;
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_package = line == "[package]";
+        } else if in_package {
+            let value = line
+                .strip_prefix("name")
+                .and_then(|rest| rest.trim_start().strip_prefix('='));
+            if let Some(value) = value {
+                return Ok(value
+                    .trim()
+                    .trim_matches(|c| c == '\'' || c == '"')
+                    .to_string());
+            }
+        }
+    }
+    Err(format!("{} has no package name", manifest.display()))
+}
+
+fn cargo_build(
+    manifest: &Path,
+    target_dir: &Path,
+    target: &str,
+    r_home: Option<&Path>,
+) -> Result<(), String> {
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".into());
+    let mut command = Command::new(cargo);
+    command
+        .args(["build", "--lib", "--release", "--target", target])
+        .arg("--manifest-path")
+        .arg(manifest)
+        .arg("--target-dir")
+        .arg(target_dir);
+    if let Some(home) = r_home {
+        command.env("R_HOME", home);
+    }
+    let status = command
+        .status()
+        .map_err(|e| format!("cannot run cargo: {e}"))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("building for {target}: cargo exited with {status}"))
+    }
+}
+
+fn lipo(slices: &[PathBuf], dest: &Path) -> Result<(), String> {
+    let lipo = std::env::var("LIPO").unwrap_or_else(|_| "lipo".into());
+    let status = Command::new(&lipo)
+        .arg("-create")
+        .args(slices)
+        .arg("-output")
+        .arg(dest)
+        .status()
+        .map_err(|e| format!("cannot run {lipo}, which universal binaries need: {e}"))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{lipo} exited with {status}"))
+    }
+}
+
+/// The `<os>-<arch>` names a platform's library serves in `prebuilt.txt`:
+/// its own, or one per slice of a universal binary.
+fn prebuilt_names(platform: &Platform) -> Vec<String> {
+    if platform.targets.len() == 1 {
+        return vec![platform.name.clone()];
+    }
+    let os = platform.name.split('-').next().unwrap_or(&platform.name);
+    platform
+        .targets
+        .iter()
+        .map(|target| format!("{os}-{}", target.split('-').next().unwrap_or(target)))
+        .collect()
+}
+
+pub fn sha256_file(path: &Path) -> Result<String, String> {
+    let mut file = fs::File::open(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 1 << 16];
+    loop {
+        let n = file
+            .read(&mut buf)
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect())
+}
@@ -0,0 +1,421 @@
+//! `r-new`: scaffold an R package with a Rust crate, laid out like this one.
+//!
+//! The package builds its crate from `src/rust` with the platform Makevars,
+//! registers routines from `entrypoint.c`, and ships with a sample exported
+//! function, its wrappers and a testthat test. Dependencies can be vendored
+//! for offline builds, as CRAN requires: `src/rust/vendor.tar.xz` is used
+//! instead of crates.io when it exists.
+//!
+//! `configure` generates `src/Makevars` from `src/Makevars.in`; on macOS,
+//! with `EXTENDR_MACOS_UNIVERSAL=true`, it has the Makevars build both the
+//! arm64 and x86_64 slices and combine them with `lipo`, so one binary
+//! package serves both architectures. When R's compiler is Emscripten's
+//! `emcc`, as for webR, the crate is built for `wasm32-unknown-emscripten`.
+//!
+//! Users without a Rust toolchain can be spared the source build: when
+//! cargo is missing, the Makevars run `tools/prebuilt.R`, which downloads
+//! the static library listed in `tools/prebuilt.txt` for their platform and
+//! R version and checks it against its SHA-256. The list is empty until the
+//! maintainer fills it in, and anything that does not match falls back to
+//! building from source.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Files of the new package, relative to its root, with `{{package}}` and
+/// `{{crate}}` placeholders.
+const TEMPLATES: &[(&str, &str)] = &[
+    (
+        "DESCRIPTION",
+        "\
+Package: {{package}}
+Title: What the Package Does (One Line, Title Case)
+Version: 0.0.0.9000
+Authors@R: person(\"First\", \"Last\", , \"first.last@example.com\", c(\"aut\", \"cre\"))
+Description: What the package does (one paragraph).
+License: What license it uses
+SystemRequirements: Cargo (Rust's package manager), rustc
+Encoding: UTF-8
+Roxygen: list(markdown = TRUE)
+Suggests:
+    testthat (>= 3.0.0)
+Config/testthat/edition: 3
+",
+    ),
+    (
+        "NAMESPACE",
+        "\
+# Generated by roxygen2: do not edit by hand
+
+export(hello_world)
+useDynLib({{package}}, .registration = TRUE)
+",
+    ),
+    (
+        ".Rbuildignore",
+        "\
+^.*\\.Rproj$
+^\\.Rproj\\.user$
+^src/rust/target$
+^src/rust/vendor$
+^src/Makevars$
+",
+    ),
+    (
+        ".gitignore",
+        "\
+.Rproj.user
+.Rhistory
+",
+    ),
+    (
+        "R/extendr-wrappers.R",
+        "\
+# Generated by extendr: Do not edit by hand
+#
+# Regenerate with `rextendr::document()` after changing exported Rust
+# functions.
+
+#' @docType package
+#' @usage NULL
+#' @useDynLib {{package}}, .registration = TRUE
+NULL
+
+#' Return string `\"Hello world!\"` to R.
+#' @export
+hello_world <- function() .Call(wrap__hello_world)
+",
+    ),
+    (
+        "src/entrypoint.c",
+        "\
+// We need to forward routine registration from C to Rust
+// to avoid the linker removing the static library.
+
+void R_init_{{crate}}_extendr(void *dll);
+
+void R_init_{{crate}}(void *dll) {
+    R_init_{{crate}}_extendr(dll);
+}
+",
+    ),
+    (
+        "configure",
+        "\
+#!/bin/sh
+# Generate src/Makevars from src/Makevars.in. On macOS, set
+# EXTENDR_MACOS_UNIVERSAL=true to build for both arm64 and x86_64 and
+# combine them into a universal binary, which needs the Rust targets
+# aarch64-apple-darwin and x86_64-apple-darwin. For webR, whose R is
+# compiled with Emscripten, the crate is built for wasm32-unknown-emscripten.
+
+ARCH_FLAGS=\"\"
+UNIVERSAL=false
+CARGO_FLAGS=\"\"
+LIBDIR='$(TARGET_DIR)/release'
+case \"$(\"${R_HOME}/bin/R\" CMD config CC)\" in
+  *emcc*)
+    CARGO_FLAGS=\"--target=wasm32-unknown-emscripten\"
+    LIBDIR='$(TARGET_DIR)/wasm32-unknown-emscripten/release'
+    echo \"building for webR\"
+    ;;
+esac
+if [ \"$(uname -s)\" = Darwin ] && [ \"${EXTENDR_MACOS_UNIVERSAL}\" = true ]; then
+  ARCH_FLAGS=\"-arch arm64 -arch x86_64\"
+  UNIVERSAL=true
+  echo \"building a universal binary for arm64 and x86_64\"
+fi
+
+sed -e \"s|@ARCH_FLAGS@|${ARCH_FLAGS}|g\" -e \"s|@UNIVERSAL@|${UNIVERSAL}|g\" \\
+  -e \"s|@CARGO_FLAGS@|${CARGO_FLAGS}|g\" -e \"s|@LIBDIR@|${LIBDIR}|g\" \\
+  src/Makevars.in > src/Makevars
+",
+    ),
+    (
+        "src/Makevars.in",
+        "\
+TARGET_DIR = ./rust/target
+LIBDIR = @LIBDIR@
+STATLIB = $(LIBDIR)/lib{{crate}}.a
+UNIVERSAL = @UNIVERSAL@
+CARGO_FLAGS = @CARGO_FLAGS@
+PKG_CFLAGS = @ARCH_FLAGS@
+PKG_LIBS = -L$(LIBDIR) -l{{crate}} @ARCH_FLAGS@
+
+all: C_clean
+
+$(SHLIB): $(STATLIB)
+
+$(STATLIB):
+\tif command -v cargo >/dev/null 2>&1 || \\
+\t\t! \"$(R_HOME)/bin$(R_ARCH_BIN)/Rscript\" ../tools/prebuilt.R $(STATLIB); then \\
+\t\tif [ -f ./rust/vendor.tar.xz ]; then \\
+\t\t\ttar xf ./rust/vendor.tar.xz -C ./rust && \\
+\t\t\tmkdir -p ./rust/.cargo && \\
+\t\t\tcp ./rust/vendor-config.toml ./rust/.cargo/config.toml; \\
+\t\tfi && \\
+\t\tif [ \"$(UNIVERSAL)\" = true ]; then \\
+\t\t\tfor target in aarch64-apple-darwin x86_64-apple-darwin; do \\
+\t\t\t\tcargo build --target=$$target --lib --release --manifest-path=./rust/Cargo.toml --target-dir $(TARGET_DIR) || exit 1; \\
+\t\t\tdone && \\
+\t\t\tmkdir -p $(LIBDIR) && \\
+\t\t\tlipo -create $(TARGET_DIR)/aarch64-apple-darwin/release/lib{{crate}}.a \\
+\t\t\t\t$(TARGET_DIR)/x86_64-apple-darwin/release/lib{{crate}}.a -output $(STATLIB); \\
+\t\telse \\
+\t\t\tcargo build $(CARGO_FLAGS) --lib --release --manifest-path=./rust/Cargo.toml --target-dir $(TARGET_DIR); \\
+\t\tfi; \\
+\tfi
+
+C_clean:
+\trm -Rf $(SHLIB) $(STATLIB) $(OBJECTS)
+
+clean:
+\trm -Rf $(SHLIB) $(STATLIB) $(OBJECTS) rust/target rust/vendor rust/.cargo
+",
+    ),
+    (
+        "src/Makevars.win",
+        "\
+TARGET = $(subst 64,x86_64,$(subst 32,i686,$(WIN)))-pc-windows-gnu
+TARGET_DIR = ./rust/target
+LIBDIR = $(TARGET_DIR)/$(TARGET)/release
+STATLIB = $(LIBDIR)/lib{{crate}}.a
+PKG_LIBS = -L$(LIBDIR) -l{{crate}} -lws2_32 -ladvapi32 -luserenv -lbcrypt -lntdll
+
+all: C_clean
+
+$(SHLIB): $(STATLIB)
+
+$(STATLIB):
+\tif command -v cargo >/dev/null 2>&1 || \\
+\t\t! \"$(R_HOME)/bin$(R_ARCH_BIN)/Rscript.exe\" ../tools/prebuilt.R $(STATLIB); then \\
+\t\tif [ -f ./rust/vendor.tar.xz ]; then \\
+\t\t\ttar xf ./rust/vendor.tar.xz -C ./rust && \\
+\t\t\tmkdir -p ./rust/.cargo && \\
+\t\t\tcp ./rust/vendor-config.toml ./rust/.cargo/config.toml; \\
+\t\tfi && \\
+\t\tcargo build --target=$(TARGET) --lib --release --manifest-path=./rust/Cargo.toml --target-dir $(TARGET_DIR); \\
+\tfi
+
+C_clean:
+\trm -Rf $(SHLIB) $(STATLIB) $(OBJECTS)
+
+clean:
+\trm -Rf $(SHLIB) $(STATLIB) $(OBJECTS) rust/target rust/vendor rust/.cargo
+",
+    ),
+    (
+        "src/.gitignore",
+        "\
+*.o
+*.so
+*.dll
+Makevars
+target
+rust/vendor
+rust/.cargo
+",
+    ),
+    (
+        "src/rust/Cargo.toml",
+        "\
+[package]
+name = '{{crate}}'
+version = '0.1.0'
+edition = '2021'
+publish = false
+
+[lib]
+crate-type = [ 'staticlib' ]
+
+[dependencies]
+extendr-api = '*'
+",
+    ),
+    (
+        "src/rust/vendor-config.toml",
+        "\
+# Copied to .cargo/config.toml by the Makevars when vendor.tar.xz exists.
+# Create the archive from src/rust with:
+#   cargo vendor && tar cJf vendor.tar.xz vendor
+[source.crates-io]
+replace-with = 'vendored-sources'
+
+[source.vendored-sources]
+directory = 'vendor'
+",
+    ),
+    (
+        "tools/prebuilt.txt",
+        "\
+# Prebuilt static libraries, used by the Makevars when cargo is not
+# installed. One per line:
+#   <platform> <R version> <sha256> <url>
+# <platform> is `<os>-<arch>`, such as linux-x86_64, darwin-aarch64 or
+# windows-x86_64, and <R version> is `major.minor` or `*` for any. Build
+# each library from the sources of the release that lists it, and get its
+# checksum with `sha256sum lib{{crate}}.a`.
+",
+    ),
+    (
+        "tools/prebuilt.R",
+        "\
+# Download the prebuilt static library listed in tools/prebuilt.txt for
+# this platform and R version to the path given as argument. Exits with an
+# error, for the Makevars to build from source instead, when none is listed
+# or the download does not match its checksum.
+
+fail <- function(...) {
+  message(\"prebuilt: \", ..., \"; building from source\")
+  quit(save = \"no\", status = 1)
+}
+
+dest <- commandArgs(trailingOnly = TRUE)[[1]]
+platform <- paste0(tolower(Sys.info()[[\"sysname\"]]), \"-\", R.version$arch)
+minor <- strsplit(R.version$minor, \".\", fixed = TRUE)[[1]][[1]]
+r_version <- paste0(R.version$major, \".\", minor)
+
+manifest <- file.path(\"..\", \"tools\", \"prebuilt.txt\")
+lines <- if (file.exists(manifest)) readLines(manifest) else character()
+lines <- trimws(sub(\"#.*\", \"\", lines))
+entries <- strsplit(lines[nzchar(lines)], \"[[:space:]]+\")
+entries <- Filter(function(e) {
+  length(e) == 4 && e[[1]] == platform && e[[2]] %in% c(r_version, \"*\")
+}, entries)
+if (length(entries) == 0) {
+  fail(\"no library listed for \", platform, \" and R \", r_version)
+}
+entry <- entries[[1]]
+
+sha256 <- function(path) {
+  if (exists(\"sha256sum\", envir = asNamespace(\"tools\"))) {
+    return(unname(tools::sha256sum(path)))
+  }
+  for (tool in c(\"sha256sum\", \"shasum -a 256\")) {
+    out <- tryCatch(
+      suppressWarnings(system(paste(tool, shQuote(path)), intern = TRUE)),
+      error = function(e) character()
+    )
+    if (length(out) > 0) {
+      return(strsplit(out[[1]], \" \", fixed = TRUE)[[1]][[1]])
+    }
+  }
+  fail(\"cannot compute checksums without sha256sum or shasum\")
+}
+
+tmp <- tempfile(fileext = \".a\")
+ok <- tryCatch(
+  download.file(entry[[4]], tmp, mode = \"wb\", quiet = TRUE) == 0,
+  error = function(e) FALSE,
+  warning = function(w) FALSE
+)
+if (!ok) {
+  fail(\"cannot download \", entry[[4]])
+}
+if (!identical(tolower(sha256(tmp)), tolower(entry[[3]]))) {
+  fail(\"checksum mismatch for \", entry[[4]])
+}
+dir.create(dirname(dest), recursive = TRUE, showWarnings = FALSE)
+if (!file.copy(tmp, dest, overwrite = TRUE)) {
+  fail(\"cannot write \", dest)
+}
+message(\"prebuilt: using \", entry[[4]])
+",
+    ),
+    (
+        "src/rust/src/lib.rs",
+        "\
+use extendr_api::prelude::*;
+
+/// Return string `\"Hello world!\"` to R.
+/// @export
+#[extendr]
+fn hello_world() -> &'static str {
+    \"Hello world!\"
+}
+
+// Macro to generate exports.
+// This ensures exported functions are registered with R.
+// See corresponding C code in `entrypoint.c`.
+extendr_module! {
+    mod {{crate}};
+    fn hello_world;
+}
+",
+    ),
+    (
+        "tests/testthat.R",
+        "\
+library(testthat)
+library({{package}})
+
+test_check(\"{{package}}\")
+",
+    ),
+    (
+        "tests/testthat/test-hello.R",
+        "\
+test_that(\"Call to Rust function `hello_world()` works\", {
+  expect_equal(hello_world(), \"Hello world!\")
+})
+",
+    ),
+];
+
+/// Check `name` against R's rules for package names: letters, digits and
+/// dots, starting with a letter and not ending with a dot.
+pub fn validate_name(name: &str) -> Result<(), String> {
+    let valid = name.len() >= 2
+        && name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && !name.ends_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '.');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "`{name}` is not a valid R package name: use at least two letters, \
+             digits and dots, starting with a letter and not ending with a dot"
+        ))
+    }
+}
+
+/// The Rust crate name for a package. R also replaces dots with underscores
+/// in the name of the package's init routine.
+fn crate_name(package: &str) -> String {
+    package.replace('.', "_")
+}
+
+/// Create the package `name` in `dir/name` and return its path.
+pub fn scaffold(name: &str, dir: &Path) -> Result<PathBuf, String> {
+    validate_name(name)?;
+    let root = dir.join(name);
+    if root.exists() {
+        return Err(format!("{} already exists", root.display()));
+    }
+    let crate_name = crate_name(name);
+    for (file, template) in TEMPLATES {
+        let path = root.join(file);
+        let contents = template
+            .replace("{{package}}", name)
+            .replace("{{crate}}", &crate_name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("{}: {e}", parent.display()))?;
+        }
+        fs::write(&path, contents).map_err(|e| format!("{}: {e}", path.display()))?;
+    }
+    make_executable(&root.join("configure"))?;
+    Ok(root)
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+        .map_err(|e| format!("{}: {e}", path.display()))
+}
+
+/// R runs `configure` with `sh` on Windows, where there is no mode to set.
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> Result<(), String> {
+    Ok(())
+}
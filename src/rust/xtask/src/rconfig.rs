@@ -0,0 +1,159 @@
+//! The compiler flags of an installation, from `R CMD config`.
+//!
+//! bindgen parses R's headers with libclang, which knows only the include
+//! directory extendr's build script gives it. R itself may have been built
+//! against a macOS SDK, for another architecture than the default one, with
+//! a conda toolchain's sysroot or with `-std=gnu2x`, and headers parsed
+//! without those flags fail to parse or describe another ABI. `R CMD config`
+//! reports the flags R compiles packages with, and [`clang_args()`] keeps
+//! those that change how headers are parsed, for `with-r` to pass to
+//! bindgen as `BINDGEN_EXTRA_CLANG_ARGS`.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::cache;
+use crate::rhome::{Installation, Source};
+
+/// The variables of `R CMD config` that hold compiler flags, in the order
+/// the compiler sees them.
+const VARIABLES: &[&str] = &["--cppflags", "CC", "CPPFLAGS", "CFLAGS"];
+
+/// Flags that take their value as the next argument when not attached.
+const WITH_VALUE: &[&str] = &[
+    "-I",
+    "-isystem",
+    "-iquote",
+    "-idirafter",
+    "-isysroot",
+    "--sysroot",
+    "-arch",
+    "-target",
+    "-include",
+    "-D",
+    "-U",
+];
+
+/// Flags kept when attached to their value, or given as a prefix.
+const KEPT_PREFIXES: &[&str] = &[
+    "-I",
+    "-isystem",
+    "-iquote",
+    "-idirafter",
+    "-isysroot",
+    "--sysroot=",
+    "--target=",
+    "-D",
+    "-U",
+    "-std=",
+    "-m",
+];
+
+/// The values of [`VARIABLES`] and of `--ldflags` for `installation`, or
+/// `None` when it has no `R` to run, as downloaded and webR installations
+/// do not.
+pub fn config(installation: &Installation) -> Option<Vec<(&'static str, String)>> {
+    if matches!(installation.source, Source::Download | Source::Webr) {
+        return None;
+    }
+    let home = &installation.home;
+    let r = home
+        .join("bin")
+        .join(if cfg!(windows) { "R.exe" } else { "R" });
+    // `R CMD config` reads them from Makeconf.
+    let makeconf: Vec<PathBuf> = vec![
+        home.join("etc").join("Makeconf"),
+        home.join("etc").join("x64").join("Makeconf"),
+    ];
+    let names: Vec<&'static str> = VARIABLES.iter().copied().chain(["--ldflags"]).collect();
+    let values = cache::cached("config", home, &makeconf, || {
+        names
+            .iter()
+            .map(|name| {
+                Command::new(&r)
+                    .args(["CMD", "config", name])
+                    .env("R_HOME", home)
+                    .output()
+                    .ok()
+                    .filter(|output| output.status.success())
+                    .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+                    .unwrap_or_default()
+            })
+            .collect()
+    });
+    if values.len() != names.len() || values.iter().all(String::is_empty) {
+        return None;
+    }
+    Some(names.into_iter().zip(values).collect())
+}
+
+/// The flags of `config` that change how headers are parsed: include
+/// directories, macros, the language standard, the sysroot and the target
+/// architecture. Optimization, warning and code generation flags are left
+/// out, as is the compiler named by `CC`.
+pub fn clang_args(config: &[(&str, String)]) -> Vec<String> {
+    let mut args = Vec::new();
+    for (name, value) in config {
+        let mut words = split(value).into_iter();
+        if *name == "CC" {
+            // The compiler, then flags such as `-std=gnu2x` or `-arch arm64`.
+            words.next();
+        } else if !VARIABLES.contains(name) {
+            continue;
+        }
+        while let Some(word) = words.next() {
+            if WITH_VALUE.contains(&word.as_str()) {
+                if let Some(value) = words.next() {
+                    args.push(word);
+                    args.push(value);
+                }
+            } else if KEPT_PREFIXES.iter().any(|prefix| word.starts_with(prefix)) {
+                args.push(word);
+            }
+        }
+    }
+    args
+}
+
+/// `value` split into words as a shell would, honouring quotes and
+/// backslashes.
+fn split(value: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = value.chars();
+    let mut quote = None;
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, '"') | (None, '\'') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (Some(q), c) if c == q => quote = None,
+            (Some('\''), c) => word.get_or_insert_with(String::new).push(c),
+            (_, '\\') => {
+                let word = word.get_or_insert_with(String::new);
+                word.extend(chars.next());
+            }
+            (_, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+/// `args` as one string for `BINDGEN_EXTRA_CLANG_ARGS`, which bindgen
+/// splits as a shell would.
+pub fn join(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| {
+            if !arg.is_empty() && !arg.contains(|c: char| c.is_whitespace() || "\"'\\".contains(c))
+            {
+                arg.clone()
+            } else {
+                format!("'{}'", arg.replace('\'', "'\\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
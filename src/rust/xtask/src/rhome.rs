@@ -0,0 +1,400 @@
+//! Discovery of R installations.
+//!
+//! extendr's build script uses `R_HOME` if it is set and otherwise whatever
+//! `R` is first on the `PATH`, which on machines with several versions
+//! (rig, conda, Homebrew, the Windows installer) is often not the intended
+//! one. [`find_installations()`] enumerates the usual install locations and
+//! [`select()`] picks one, honouring `LIBRSYS_R_VERSION`.
+
+use std::cmp::Reverse;
+use std::convert::TryInto;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::cache;
+use crate::fetch;
+
+/// The environment variable that requests an R version, such as `4.3` or
+/// `4.3.2`. The newest installation whose version starts with it is used.
+pub const VERSION_VAR: &str = "LIBRSYS_R_VERSION";
+
+/// Where an installation was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// The `R_HOME` environment variable.
+    Env,
+    /// `R RHOME` of the `R` on the `PATH`.
+    Path,
+    /// The rig and CRAN installer locations: `/opt/R/*` on Linux, the R
+    /// framework on macOS and `Program Files` on Windows.
+    Rig,
+    /// The active conda environment.
+    Conda,
+    /// The Windows registry.
+    Registry,
+    /// Distribution and Homebrew packages.
+    System,
+    /// A webR build, under `WEBR_ROOT`.
+    Webr,
+    /// A minimal R downloaded because no other was found, see [`fetch`].
+    ///
+    /// [`fetch`]: crate::fetch
+    Download,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Source::Env => "R_HOME",
+            Source::Path => "PATH",
+            Source::Rig => "rig",
+            Source::Conda => "conda",
+            Source::Registry => "registry",
+            Source::System => "system",
+            Source::Webr => "webR",
+            Source::Download => "download",
+        })
+    }
+}
+
+/// An R installation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Installation {
+    pub home: PathBuf,
+    /// `major.minor.patch`, or `None` if it could not be determined.
+    pub version: Option<String>,
+    /// The CPU architecture of `libR`, such as `x86_64` or `aarch64`.
+    pub arch: Option<String>,
+    pub source: Source,
+}
+
+impl Installation {
+    /// The installation at `home`, with its version and architecture from
+    /// the [`cache`] when its files have not changed.
+    fn new(home: PathBuf, source: Source) -> Self {
+        let mut sources = version_headers(&home);
+        sources.extend(library_candidates(&home));
+        let values = cache::cached("installation", &home, &sources, || {
+            vec![
+                read_version(&home).unwrap_or_default(),
+                library_arch(&home).unwrap_or_default(),
+            ]
+        });
+        let value = |n: usize| values.get(n).filter(|v| !v.is_empty()).cloned();
+        let version = value(0);
+        let arch = value(1);
+        Self {
+            home,
+            version,
+            arch,
+            source,
+        }
+    }
+
+    fn version_parts(&self) -> Vec<u32> {
+        self.version
+            .as_deref()
+            .unwrap_or("")
+            .split('.')
+            .filter_map(|part| part.parse().ok())
+            .collect()
+    }
+
+    /// Whether the version starts with the requested one, component-wise.
+    pub fn matches(&self, requested: &str) -> bool {
+        let requested: Vec<&str> = requested.trim().split('.').collect();
+        let version = match &self.version {
+            Some(version) => version,
+            None => return false,
+        };
+        let version: Vec<&str> = version.split('.').collect();
+        requested.len() <= version.len() && requested.iter().zip(&version).all(|(r, v)| r == v)
+    }
+}
+
+impl fmt::Display for Installation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<8} {:<8} {:<9} {}",
+            self.version.as_deref().unwrap_or("?"),
+            self.arch.as_deref().unwrap_or("?"),
+            self.source,
+            self.home.display()
+        )
+    }
+}
+
+/// All installations found, without duplicates, in order of precedence.
+/// When there are none, this is the minimal R that [`fetch`] downloads,
+/// if it is configured.
+///
+/// [`fetch`]: crate::fetch
+pub fn find_installations() -> Vec<Installation> {
+    let mut candidates: Vec<(PathBuf, Source)> = Vec::new();
+    if let Some(home) = std::env::var_os("R_HOME") {
+        candidates.push((home.into(), Source::Env));
+    }
+    if let Some(home) = r_on_path() {
+        candidates.push((home, Source::Path));
+    }
+    if let Some(prefix) = std::env::var_os("CONDA_PREFIX") {
+        let prefix = PathBuf::from(prefix);
+        candidates.push((prefix.join("lib").join("R"), Source::Conda));
+        candidates.push((prefix.join("Lib").join("R"), Source::Conda));
+    }
+    candidates.extend(rig_homes().into_iter().map(|home| (home, Source::Rig)));
+    candidates.extend(
+        registry_homes()
+            .into_iter()
+            .map(|home| (home, Source::Registry)),
+    );
+    for home in [
+        "/usr/lib/R",
+        "/usr/lib64/R",
+        "/usr/local/lib/R",
+        "/opt/homebrew/lib/R",
+        "/opt/homebrew/opt/r/lib/R",
+    ] {
+        candidates.push((home.into(), Source::System));
+    }
+
+    let mut found: Vec<Installation> = Vec::new();
+    for (home, source) in candidates {
+        if !home.join("bin").is_dir() {
+            continue;
+        }
+        let canonical = fs::canonicalize(&home).unwrap_or_else(|_| home.clone());
+        let duplicate = found
+            .iter()
+            .any(|i| fs::canonicalize(&i.home).unwrap_or_else(|_| i.home.clone()) == canonical);
+        if !duplicate {
+            found.push(Installation::new(home, source));
+        }
+    }
+    if found.is_empty() {
+        match fetch::fetch() {
+            Some(Ok(home)) => found.push(Installation::new(home, Source::Download)),
+            Some(Err(e)) => eprintln!("warning: cannot download R: {e}"),
+            None => {}
+        }
+    }
+    found
+}
+
+/// The environment variable pointing at a webR build tree, such as the
+/// `/opt/webr` of webR's development container.
+pub const WEBR_ROOT_VAR: &str = "WEBR_ROOT";
+
+/// The R built for WebAssembly in the webR tree at `WEBR_ROOT`, the newest
+/// matching `requested` if given. Only its files are read: the R it
+/// contains cannot run on the build machine.
+pub fn webr_installation(requested: Option<&str>) -> Result<Installation, String> {
+    let root = std::env::var_os(WEBR_ROOT_VAR)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/opt/webr"));
+    let installations: Vec<Installation> = subdirectories(&root.join("wasm"))
+        .into_iter()
+        .filter(|dir| {
+            dir.file_name()
+                .is_some_and(|n| n.to_string_lossy().starts_with("R-"))
+        })
+        .map(|dir| dir.join("lib").join("R"))
+        .filter(|home| home.join("include").join("Rinternals.h").is_file())
+        .map(|home| Installation {
+            version: read_version(&home),
+            arch: Some("wasm32".to_string()),
+            home,
+            source: Source::Webr,
+        })
+        .collect();
+    if requested.is_some() {
+        return select(&installations, requested).cloned();
+    }
+    installations
+        .into_iter()
+        .max_by_key(Installation::version_parts)
+        .ok_or_else(|| {
+            format!(
+                "no webR build of R in {}; set {WEBR_ROOT_VAR} to the webR source tree",
+                root.join("wasm").display()
+            )
+        })
+}
+
+/// Pick the installation to build against: the newest one matching
+/// `requested` if given, otherwise the first found, which is `R_HOME` or the
+/// `R` on the `PATH` when available.
+pub fn select<'a>(
+    installations: &'a [Installation],
+    requested: Option<&str>,
+) -> Result<&'a Installation, String> {
+    match requested {
+        Some(requested) => installations
+            .iter()
+            .enumerate()
+            .filter(|(_, i)| i.matches(requested))
+            // Among equal versions, prefer the earlier, higher-precedence entry.
+            .max_by_key(|(n, i)| (i.version_parts(), Reverse(*n)))
+            .map(|(_, i)| i)
+            .ok_or_else(|| format!("no R installation matches {VERSION_VAR}={requested}")),
+        None => installations
+            .first()
+            .ok_or_else(|| "no R installation found".to_string()),
+    }
+}
+
+/// `R RHOME` of the `R` on the `PATH`, which is only run again when that
+/// executable changes.
+fn r_on_path() -> Option<PathBuf> {
+    let r = if cfg!(windows) { "R.exe" } else { "R" };
+    let exe = std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(r))
+        .find(|path| path.is_file())?;
+    let values = cache::cached("rhome", &exe, std::slice::from_ref(&exe), || {
+        let home = Command::new(&exe)
+            .args(["RHOME"])
+            .output()
+            .ok()
+            .filter(|o| o.status.success())
+            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+            .unwrap_or_default();
+        vec![home]
+    });
+    let home = values.into_iter().next().unwrap_or_default();
+    (!home.is_empty()).then(|| PathBuf::from(home))
+}
+
+fn subdirectories(dir: &Path) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.is_dir())
+                .collect()
+        })
+        .unwrap_or_default();
+    dirs.sort();
+    dirs
+}
+
+/// Installation directories managed by rig and the CRAN installers.
+fn rig_homes() -> Vec<PathBuf> {
+    if cfg!(target_os = "macos") {
+        subdirectories(Path::new("/Library/Frameworks/R.framework/Versions"))
+            .into_iter()
+            .filter(|p| p.file_name().is_some_and(|n| n != "Current"))
+            .map(|p| p.join("Resources"))
+            .collect()
+    } else if cfg!(windows) {
+        let program_files =
+            std::env::var_os("ProgramFiles").unwrap_or_else(|| r"C:\Program Files".into());
+        subdirectories(&Path::new(&program_files).join("R"))
+    } else {
+        subdirectories(Path::new("/opt/R"))
+            .into_iter()
+            .map(|p| p.join("lib").join("R"))
+            .collect()
+    }
+}
+
+/// `InstallPath` values below `HKLM\Software\R-core\R`, read with `reg`.
+fn registry_homes() -> Vec<PathBuf> {
+    if !cfg!(windows) {
+        return Vec::new();
+    }
+    let output = match Command::new("reg")
+        .args([
+            "query",
+            r"HKLM\Software\R-core\R",
+            "/s",
+            "/v",
+            "InstallPath",
+        ])
+        .output()
+    {
+        Ok(output) if output.status.success() => output,
+        _ => return Vec::new(),
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("InstallPath"))
+        .filter_map(|rest| rest.trim().strip_prefix("REG_SZ"))
+        .map(|path| PathBuf::from(path.trim()))
+        .collect()
+}
+
+/// The version from `Rversion.h`, which Debian keeps outside of `R_HOME`.
+pub fn read_version(home: &Path) -> Option<String> {
+    let header = version_headers(home)
+        .iter()
+        .find_map(|h| fs::read_to_string(h).ok())?;
+    let define = |name: &str| {
+        header.lines().find_map(|line| {
+            let rest = line.strip_prefix("#define ")?.strip_prefix(name)?;
+            Some(rest.trim().trim_matches('"').to_string())
+        })
+    };
+    Some(format!("{}.{}", define("R_MAJOR")?, define("R_MINOR")?))
+}
+
+fn version_headers(home: &Path) -> Vec<PathBuf> {
+    let mut headers = vec![home.join("include").join("Rversion.h")];
+    if home == Path::new("/usr/lib/R") {
+        headers.push(PathBuf::from("/usr/share/R/include/Rversion.h"));
+    }
+    headers
+}
+
+fn library_candidates(home: &Path) -> Vec<PathBuf> {
+    vec![
+        home.join("lib").join("libR.so"),
+        home.join("lib").join("libR.dylib"),
+        home.join("bin").join("x64").join("R.dll"),
+        home.join("bin").join("R.dll"),
+        home.join("bin").join("exec").join("R"),
+    ]
+}
+
+/// The architecture of the R library, read from its executable header.
+fn library_arch(home: &Path) -> Option<String> {
+    let bytes = library_candidates(home)
+        .iter()
+        .find_map(|p| fs::read(p).ok())?;
+    binary_arch(&bytes).map(str::to_string)
+}
+
+fn binary_arch(bytes: &[u8]) -> Option<&'static str> {
+    let u16_le = |at: usize| Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?));
+    let u32_le = |at: usize| Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?));
+    match bytes.get(..4)? {
+        [0x7f, b'E', b'L', b'F'] => match u16_le(18)? {
+            0x03 => Some("i386"),
+            0x3e => Some("x86_64"),
+            0xb7 => Some("aarch64"),
+            0x28 => Some("arm"),
+            0x15 => Some("ppc64"),
+            0xf3 => Some("riscv64"),
+            _ => None,
+        },
+        [0xcf, 0xfa, 0xed, 0xfe] => match u32_le(4)? {
+            0x0100_0007 => Some("x86_64"),
+            0x0100_000c => Some("aarch64"),
+            _ => None,
+        },
+        [0xca, 0xfe, 0xba, 0xbe] => Some("universal"),
+        [b'M', b'Z', ..] => {
+            let pe = u32_le(0x3c)? as usize;
+            match u16_le(pe + 4)? {
+                0x014c => Some("i386"),
+                0x8664 => Some("x86_64"),
+                0xaa64 => Some("aarch64"),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}